@@ -0,0 +1,973 @@
+/* ratbagctl export/import: reads every profile, resolution, button and LED of a device over DBus
+ * into one serializable document, and applies such a document again. Field names follow
+ * `ratbagctl info` and `led get`; raw values sit next to their readable names. An import first
+ * plans the writes by comparing the document with what the device reports, so counts are
+ * checked and values parsed before anything is written. */
+//! Device configuration documents for `ratbagctl export` and `import`.
+
+use std::fmt;
+
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+
+use crate::dbus_client::{ButtonMapping, RatbagClient};
+use crate::names;
+
+/// Everything configurable on one device.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeviceConfig {
+    pub name: String,
+    pub model: String,
+    pub firmware: String,
+    pub profiles: Vec<ProfileConfig>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProfileConfig {
+    pub index: u32,
+    pub name: String,
+    pub report_rate: u32,
+    pub is_active: bool,
+    pub is_disabled: bool,
+    /// Absent when the device has no angle snapping.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub angle_snapping: Option<bool>,
+    /// Debounce time in ms; absent when the device has none.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub debounce: Option<u32>,
+    pub resolutions: Vec<ResolutionConfig>,
+    pub buttons: Vec<ButtonConfig>,
+    pub leds: Vec<LedConfig>,
+}
+
+/// DPI as a single number, or `[x, y]` when the axes differ.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum DpiConfig {
+    Unified(u32),
+    Separate([u32; 2]),
+}
+
+impl DpiConfig {
+    pub fn from_xy((x, y): (u32, u32)) -> Self {
+        if x == y {
+            Self::Unified(x)
+        } else {
+            Self::Separate([x, y])
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResolutionConfig {
+    pub index: u32,
+    pub dpi: DpiConfig,
+    pub is_active: bool,
+    pub is_default: bool,
+    pub is_disabled: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ButtonConfig {
+    pub index: u32,
+    /// `button`, `special`, `key`, `macro` or `none`.
+    pub action_type: String,
+    /// Readable form of the mapping, as `button get` prints it.
+    pub mapping: String,
+    /// Button number, special action code or keycode; absent for macros.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value: Option<u32>,
+    #[serde(default, rename = "macro", skip_serializing_if = "Option::is_none")]
+    pub macro_events: Option<Vec<MacroEvent>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MacroEvent {
+    pub keycode: u32,
+    /// `KEY_` name of the keycode.
+    pub key: String,
+    /// `true` for a key press, `false` for a release.
+    pub press: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LedConfig {
+    pub index: u32,
+    pub mode: String,
+    /// `#rrggbb`.
+    pub color: String,
+    pub secondary_color: String,
+    pub tertiary_color: String,
+    pub brightness: u32,
+    /// Effect duration in milliseconds.
+    pub duration: u32,
+    /// `clockwise` or `counterclockwise`; absent on daemons without the property.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub direction: Option<String>,
+}
+
+impl ButtonConfig {
+    pub fn new(index: u32, action_type: u32, mapping: &ButtonMapping) -> Self {
+        let (value, macro_events) = match mapping {
+            ButtonMapping::Value(v) => (Some(*v), None),
+            ButtonMapping::Macro(events) => (
+                None,
+                Some(
+                    events
+                        .iter()
+                        .map(|&(keycode, dir)| MacroEvent {
+                            keycode,
+                            key: names::key_name(keycode),
+                            press: dir == 1,
+                        })
+                        .collect(),
+                ),
+            ),
+        };
+        Self {
+            index,
+            action_type: crate::action_type_name(action_type).to_string(),
+            mapping: names::resolve_mapping(action_type, mapping),
+            value,
+            macro_events,
+        }
+    }
+}
+
+fn hex_color((r, g, b): (u32, u32, u32)) -> String {
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
+
+/// Read the full configuration of `dev_path` (a device object path).
+pub async fn read_device(client: &RatbagClient, dev_path: &str) -> Result<DeviceConfig> {
+    let mut profiles = Vec::new();
+    for profile_path in client.get_device_profiles(dev_path).await? {
+        profiles.push(read_profile(client, &profile_path).await?);
+    }
+
+    Ok(DeviceConfig {
+        name: client.get_device_name(dev_path).await?,
+        model: client.get_device_model(dev_path).await?,
+        firmware: client.get_device_firmware(dev_path).await?,
+        profiles,
+    })
+}
+
+/// Read one profile with its resolutions, buttons and LEDs.
+pub async fn read_profile(client: &RatbagClient, profile_path: &str) -> Result<ProfileConfig> {
+    let mut resolutions = Vec::new();
+    for path in client.get_profile_resolutions(profile_path).await? {
+        resolutions.push(ResolutionConfig {
+            index: client.get_resolution_index(&path).await?,
+            dpi: DpiConfig::from_xy(client.get_resolution_dpi_xy(&path).await?),
+            is_active: client.get_resolution_is_active(&path).await?,
+            is_default: client.get_resolution_is_default(&path).await?,
+            is_disabled: client.get_resolution_is_disabled(&path).await?,
+        });
+    }
+
+    let mut buttons = Vec::new();
+    for path in client.get_profile_buttons(profile_path).await? {
+        buttons.push(read_button(client, &path).await?);
+    }
+
+    let mut leds = Vec::new();
+    for path in client.get_profile_leds(profile_path).await? {
+        leds.push(read_led(client, &path).await?);
+    }
+
+    /* Both report -1 when unsupported. */
+    let angle = client
+        .get_profile_angle_snapping(profile_path)
+        .await
+        .unwrap_or(-1);
+    let debounce = client
+        .get_profile_debounce(profile_path)
+        .await
+        .unwrap_or(-1);
+    Ok(ProfileConfig {
+        index: client.get_profile_index(profile_path).await?,
+        name: client
+            .get_profile_name(profile_path)
+            .await
+            .unwrap_or_default(),
+        report_rate: client.get_profile_report_rate(profile_path).await?,
+        is_active: client.get_profile_is_active(profile_path).await?,
+        is_disabled: client.get_profile_disabled(profile_path).await?,
+        angle_snapping: (angle >= 0).then_some(angle != 0),
+        debounce: u32::try_from(debounce).ok(),
+        resolutions,
+        buttons,
+        leds,
+    })
+}
+
+/// Read one button's mapping.
+pub async fn read_button(client: &RatbagClient, path: &str) -> Result<ButtonConfig> {
+    let index = client.get_button_index(path).await?;
+    let (action_type, mapping) = client.get_button_mapping_value(path).await?;
+    Ok(ButtonConfig::new(index, action_type, &mapping))
+}
+
+/// Read one LED's mode, colors and effect settings.
+pub async fn read_led(client: &RatbagClient, path: &str) -> Result<LedConfig> {
+    let direction = client.get_led_effect_direction(path).await.ok();
+    Ok(LedConfig {
+        index: client.get_led_index(path).await?,
+        mode: crate::led_mode_name(client.get_led_mode(path).await?).to_string(),
+        color: hex_color(client.get_led_color(path).await?),
+        secondary_color: hex_color(client.get_led_secondary_color(path).await?),
+        tertiary_color: hex_color(client.get_led_tertiary_color(path).await?),
+        brightness: client.get_led_brightness(path).await?,
+        duration: client.get_led_effect_duration(path).await?,
+        direction: direction.map(|d| {
+            crate::LedDirection::from_dbus(d)
+                .map_or("unknown", crate::LedDirection::name)
+                .to_string()
+        }),
+    })
+}
+
+/// One write made by `ratbagctl import`, addressed by profile and item index.
+#[derive(Debug, Clone)]
+pub enum Step {
+    ProfileEnabled {
+        profile: u32,
+        enabled: bool,
+    },
+    ProfileName {
+        profile: u32,
+        name: String,
+    },
+    ReportRate {
+        profile: u32,
+        rate: u32,
+    },
+    AngleSnapping {
+        profile: u32,
+        on: bool,
+    },
+    Debounce {
+        profile: u32,
+        ms: u32,
+    },
+    ResolutionEnabled {
+        profile: u32,
+        resolution: u32,
+        enabled: bool,
+    },
+    Dpi {
+        profile: u32,
+        resolution: u32,
+        dpi: DpiConfig,
+    },
+    ResolutionDefault {
+        profile: u32,
+        resolution: u32,
+    },
+    ResolutionActive {
+        profile: u32,
+        resolution: u32,
+    },
+    Button {
+        profile: u32,
+        button: u32,
+        action_type: u32,
+        mapping: ButtonMapping,
+    },
+    LedMode {
+        profile: u32,
+        led: u32,
+        mode: u32,
+    },
+    /// `property` is `Color`, `SecondaryColor` or `TertiaryColor`.
+    LedColor {
+        profile: u32,
+        led: u32,
+        property: &'static str,
+        rgb: (u32, u32, u32),
+    },
+    LedBrightness {
+        profile: u32,
+        led: u32,
+        value: u32,
+    },
+    LedDuration {
+        profile: u32,
+        led: u32,
+        ms: u32,
+    },
+    LedDirection {
+        profile: u32,
+        led: u32,
+        direction: u32,
+    },
+    ProfileActive {
+        profile: u32,
+    },
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let on_off = |on: bool| if on { "on" } else { "off" };
+        match self {
+            Step::ProfileEnabled { profile, enabled } => {
+                write!(
+                    f,
+                    "profile {profile}: {}",
+                    if *enabled { "enable" } else { "disable" }
+                )
+            }
+            Step::ProfileName { profile, name } => write!(f, "profile {profile}: name \"{name}\""),
+            Step::ReportRate { profile, rate } => {
+                write!(f, "profile {profile}: report rate {rate} Hz")
+            }
+            Step::AngleSnapping { profile, on } => {
+                write!(f, "profile {profile}: angle snapping {}", on_off(*on))
+            }
+            Step::Debounce { profile, ms } => write!(f, "profile {profile}: debounce {ms} ms"),
+            Step::ResolutionEnabled {
+                profile,
+                resolution,
+                enabled,
+            } => write!(
+                f,
+                "profile {profile} resolution {resolution}: {}",
+                if *enabled { "enable" } else { "disable" }
+            ),
+            Step::Dpi {
+                profile,
+                resolution,
+                dpi,
+            } => {
+                let (x, y) = match *dpi {
+                    DpiConfig::Unified(v) => (v, v),
+                    DpiConfig::Separate([x, y]) => (x, y),
+                };
+                write!(
+                    f,
+                    "profile {profile} resolution {resolution}: {}",
+                    crate::dbus_client::format_dpi((x, y))
+                )
+            }
+            Step::ResolutionDefault {
+                profile,
+                resolution,
+            } => {
+                write!(f, "profile {profile} resolution {resolution}: make default")
+            }
+            Step::ResolutionActive {
+                profile,
+                resolution,
+            } => {
+                write!(f, "profile {profile} resolution {resolution}: make active")
+            }
+            Step::Button {
+                profile,
+                button,
+                action_type,
+                mapping,
+            } => write!(
+                f,
+                "profile {profile} button {button}: {} {}",
+                crate::action_type_name(*action_type),
+                names::resolve_mapping(*action_type, mapping)
+            ),
+            Step::LedMode { profile, led, mode } => {
+                write!(
+                    f,
+                    "profile {profile} LED {led}: mode {}",
+                    crate::led_mode_name(*mode)
+                )
+            }
+            Step::LedColor {
+                profile,
+                led,
+                property,
+                rgb,
+            } => write!(
+                f,
+                "profile {profile} LED {led}: {} {}",
+                match *property {
+                    "SecondaryColor" => "secondary color",
+                    "TertiaryColor" => "tertiary color",
+                    _ => "color",
+                },
+                hex_color(*rgb)
+            ),
+            Step::LedBrightness {
+                profile,
+                led,
+                value,
+            } => {
+                write!(f, "profile {profile} LED {led}: brightness {value}")
+            }
+            Step::LedDuration { profile, led, ms } => {
+                write!(f, "profile {profile} LED {led}: duration {ms} ms")
+            }
+            Step::LedDirection {
+                profile,
+                led,
+                direction,
+            } => write!(
+                f,
+                "profile {profile} LED {led}: direction {}",
+                crate::LedDirection::from_dbus(*direction)
+                    .map_or("unknown", crate::LedDirection::name)
+            ),
+            Step::ProfileActive { profile } => write!(f, "profile {profile}: make active"),
+        }
+    }
+}
+
+/// The writes that turn `current` into `wanted`, plus notes on settings
+/// the device cannot take.
+#[derive(Debug, Default)]
+pub struct Plan {
+    pub steps: Vec<Step>,
+    pub skipped: Vec<String>,
+}
+
+fn action_type_from_name(name: &str) -> Option<u32> {
+    (0..=4).find(|&t| crate::action_type_name(t) == name)
+}
+
+fn direction_from_name(name: &str) -> Option<u32> {
+    [crate::LedDirection::Cw, crate::LedDirection::Ccw]
+        .into_iter()
+        .find(|d| d.name() == name)
+        .map(crate::LedDirection::to_dbus)
+}
+
+fn ensure_same_count(what: &str, wanted: usize, current: usize) -> Result<()> {
+    if wanted != current {
+        bail!("The file has {wanted} {what} but the device has {current}; nothing was written");
+    }
+    Ok(())
+}
+
+/// Compare `wanted` (a parsed export) with `current` (what the device
+/// reports) and list the writes needed.  Fails without writing when the
+/// layouts differ or a value cannot be parsed.
+pub fn plan(current: &DeviceConfig, wanted: &DeviceConfig) -> Result<Plan> {
+    ensure_same_count("profiles", wanted.profiles.len(), current.profiles.len())?;
+    for (want, have) in wanted.profiles.iter().zip(&current.profiles) {
+        let p = have.index;
+        ensure_same_count(
+            &format!("resolutions in profile {p}"),
+            want.resolutions.len(),
+            have.resolutions.len(),
+        )?;
+        ensure_same_count(
+            &format!("buttons in profile {p}"),
+            want.buttons.len(),
+            have.buttons.len(),
+        )?;
+        ensure_same_count(
+            &format!("LEDs in profile {p}"),
+            want.leds.len(),
+            have.leds.len(),
+        )?;
+    }
+
+    let mut plan = Plan::default();
+    let mut activate = None;
+    let mut disable = Vec::new();
+    for (want, have) in wanted.profiles.iter().zip(&current.profiles) {
+        let profile = have.index;
+        /* Enable first so the profile takes the writes below; disable last,
+         * after another profile may have become active. */
+        if want.is_disabled != have.is_disabled {
+            if want.is_disabled {
+                disable.push(Step::ProfileEnabled {
+                    profile,
+                    enabled: false,
+                });
+            } else {
+                plan.steps.push(Step::ProfileEnabled {
+                    profile,
+                    enabled: true,
+                });
+            }
+        }
+        if want.name != have.name {
+            plan.steps.push(Step::ProfileName {
+                profile,
+                name: want.name.clone(),
+            });
+        }
+        if want.report_rate != have.report_rate {
+            plan.steps.push(Step::ReportRate {
+                profile,
+                rate: want.report_rate,
+            });
+        }
+        match (want.angle_snapping, have.angle_snapping) {
+            (Some(on), Some(current)) if on != current => {
+                plan.steps.push(Step::AngleSnapping { profile, on });
+            }
+            (Some(_), None) => plan.skipped.push(format!(
+                "profile {profile}: angle snapping is not supported by this device"
+            )),
+            _ => {}
+        }
+        match (want.debounce, have.debounce) {
+            (Some(ms), Some(current)) if ms != current => {
+                plan.steps.push(Step::Debounce { profile, ms })
+            }
+            (Some(_), None) => plan.skipped.push(format!(
+                "profile {profile}: debounce is not supported by this device"
+            )),
+            _ => {}
+        }
+
+        plan_items(&mut plan, profile, want, have)?;
+
+        if want.is_active && !have.is_active {
+            activate = Some(Step::ProfileActive { profile });
+        }
+    }
+    plan.steps.extend(activate);
+    plan.steps.extend(disable);
+    Ok(plan)
+}
+
+/// Add the writes that make the resolutions, buttons and LEDs of `have`
+/// (profile `profile`) match `want`.  Items past the shorter of the two
+/// lists are left alone.
+fn plan_items(
+    plan: &mut Plan,
+    profile: u32,
+    want: &ProfileConfig,
+    have: &ProfileConfig,
+) -> Result<()> {
+    let mut res_disable = Vec::new();
+    for (w, h) in want.resolutions.iter().zip(&have.resolutions) {
+        let resolution = h.index;
+        if w.is_disabled != h.is_disabled {
+            let step = Step::ResolutionEnabled {
+                profile,
+                resolution,
+                enabled: !w.is_disabled,
+            };
+            if w.is_disabled {
+                res_disable.push(step);
+            } else {
+                plan.steps.push(step);
+            }
+        }
+        if w.dpi != h.dpi {
+            plan.steps.push(Step::Dpi {
+                profile,
+                resolution,
+                dpi: w.dpi,
+            });
+        }
+    }
+    for (w, h) in want.resolutions.iter().zip(&have.resolutions) {
+        if w.is_default && !h.is_default {
+            plan.steps.push(Step::ResolutionDefault {
+                profile,
+                resolution: h.index,
+            });
+        }
+        if w.is_active && !h.is_active {
+            plan.steps.push(Step::ResolutionActive {
+                profile,
+                resolution: h.index,
+            });
+        }
+    }
+    plan.steps.extend(res_disable);
+
+    for (w, h) in want.buttons.iter().zip(&have.buttons) {
+        if (&w.action_type, w.value, &w.macro_events)
+            == (&h.action_type, h.value, &h.macro_events)
+        {
+            continue;
+        }
+        let button = h.index;
+        let action_type = action_type_from_name(&w.action_type).with_context(|| {
+            format!(
+                "profile {profile} button {button}: unknown action type '{}'",
+                w.action_type
+            )
+        })?;
+        let mapping = match (&w.macro_events, w.value) {
+            (Some(events), _) => ButtonMapping::Macro(
+                events
+                    .iter()
+                    .map(|e| (e.keycode, u32::from(e.press)))
+                    .collect(),
+            ),
+            (None, Some(value)) => ButtonMapping::Value(value),
+            (None, None) => {
+                bail!("profile {profile} button {button}: neither value nor macro given")
+            }
+        };
+        plan.steps.push(Step::Button {
+            profile,
+            button,
+            action_type,
+            mapping,
+        });
+    }
+
+    for (w, h) in want.leds.iter().zip(&have.leds) {
+        let led = h.index;
+        if w.mode != h.mode {
+            let mode = crate::parse_led_mode(&w.mode)
+                .with_context(|| format!("profile {profile} LED {led}"))?;
+            plan.steps.push(Step::LedMode { profile, led, mode });
+        }
+        for (property, want_color, have_color) in [
+            ("Color", &w.color, &h.color),
+            ("SecondaryColor", &w.secondary_color, &h.secondary_color),
+            ("TertiaryColor", &w.tertiary_color, &h.tertiary_color),
+        ] {
+            if want_color != have_color {
+                let rgb = crate::parse_hex_color(want_color)
+                    .with_context(|| format!("profile {profile} LED {led}"))?;
+                plan.steps.push(Step::LedColor {
+                    profile,
+                    led,
+                    property,
+                    rgb,
+                });
+            }
+        }
+        if w.brightness != h.brightness {
+            plan.steps.push(Step::LedBrightness {
+                profile,
+                led,
+                value: w.brightness,
+            });
+        }
+        if w.duration != h.duration {
+            plan.steps.push(Step::LedDuration {
+                profile,
+                led,
+                ms: w.duration,
+            });
+        }
+        match (&w.direction, &h.direction) {
+            (Some(want_dir), Some(have_dir)) if want_dir != have_dir => {
+                let direction = direction_from_name(want_dir).with_context(|| {
+                    format!("profile {profile} LED {led}: unknown direction '{want_dir}'")
+                })?;
+                plan.steps.push(Step::LedDirection {
+                    profile,
+                    led,
+                    direction,
+                });
+            }
+            (Some(_), None) => plan.skipped.push(format!(
+                "profile {profile} LED {led}: effect direction is not supported by this device"
+            )),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// The writes that copy the resolutions, buttons and LEDs of `src` onto
+/// `dst`.  The destination keeps its own active and enabled state, and
+/// its active resolution.  When the two profiles have different numbers
+/// of items only the common ones are copied, with a note in `skipped`.
+pub fn plan_copy(src: &ProfileConfig, dst: &ProfileConfig) -> Result<Plan> {
+    let mut plan = Plan::default();
+    for (what, from, to) in [
+        ("resolutions", src.resolutions.len(), dst.resolutions.len()),
+        ("buttons", src.buttons.len(), dst.buttons.len()),
+        ("LEDs", src.leds.len(), dst.leds.len()),
+    ] {
+        if from != to {
+            plan.skipped.push(format!(
+                "profile {} has {from} {what} but profile {} has {to}; copying the first {}",
+                src.index,
+                dst.index,
+                from.min(to)
+            ));
+        }
+    }
+
+    let mut want = src.clone();
+    for (w, h) in want.resolutions.iter_mut().zip(&dst.resolutions) {
+        w.is_active = h.is_active;
+    }
+    plan_items(&mut plan, dst.index, &want, dst)?;
+    Ok(plan)
+}
+
+/// Make the write described by `step` on the device at `dev_path`.
+pub async fn apply(client: &RatbagClient, dev_path: &str, step: &Step) -> Result<()> {
+    let profile_path = |p: u32| format!("{dev_path}/p{p}");
+    match step {
+        Step::ProfileEnabled { profile, enabled } => {
+            client
+                .set_profile_disabled(&profile_path(*profile), !enabled)
+                .await
+        }
+        Step::ProfileName { profile, name } => {
+            client.set_profile_name(&profile_path(*profile), name).await
+        }
+        Step::ReportRate { profile, rate } => {
+            client
+                .set_profile_report_rate(&profile_path(*profile), *rate)
+                .await
+        }
+        Step::AngleSnapping { profile, on } => {
+            client
+                .set_profile_angle_snapping(&profile_path(*profile), i32::from(*on))
+                .await
+        }
+        Step::Debounce { profile, ms } => {
+            let ms = i32::try_from(*ms).context("Debounce out of range")?;
+            client
+                .set_profile_debounce(&profile_path(*profile), ms)
+                .await
+        }
+        Step::ResolutionEnabled {
+            profile,
+            resolution,
+            enabled,
+        } => {
+            let path = format!("{}/r{resolution}", profile_path(*profile));
+            client.set_resolution_is_disabled(&path, !enabled).await
+        }
+        Step::Dpi {
+            profile,
+            resolution,
+            dpi,
+        } => {
+            let path = format!("{}/r{resolution}", profile_path(*profile));
+            match *dpi {
+                DpiConfig::Unified(v) => client.set_resolution_dpi(&path, v).await,
+                DpiConfig::Separate([x, y]) => client.set_resolution_dpi_xy(&path, x, y).await,
+            }
+        }
+        Step::ResolutionDefault {
+            profile,
+            resolution,
+        } => {
+            let path = format!("{}/r{resolution}", profile_path(*profile));
+            client.call_resolution_set_default(&path).await
+        }
+        Step::ResolutionActive {
+            profile,
+            resolution,
+        } => {
+            let path = format!("{}/r{resolution}", profile_path(*profile));
+            client.call_resolution_set_active(&path).await
+        }
+        Step::Button {
+            profile,
+            button,
+            action_type,
+            mapping,
+        } => {
+            let path = format!("{}/b{button}", profile_path(*profile));
+            match mapping {
+                ButtonMapping::Value(v) => client.set_button_mapping(&path, *action_type, *v).await,
+                ButtonMapping::Macro(events) => {
+                    client.set_button_macro_mapping(&path, events).await
+                }
+            }
+        }
+        Step::LedMode { profile, led, mode } => {
+            client
+                .set_led_mode(&format!("{}/l{led}", profile_path(*profile)), *mode)
+                .await
+        }
+        Step::LedColor {
+            profile,
+            led,
+            property,
+            rgb: (r, g, b),
+        } => {
+            let path = format!("{}/l{led}", profile_path(*profile));
+            match *property {
+                "SecondaryColor" => client.set_led_secondary_color(&path, *r, *g, *b).await,
+                "TertiaryColor" => client.set_led_tertiary_color(&path, *r, *g, *b).await,
+                _ => client.set_led_color(&path, *r, *g, *b).await,
+            }
+        }
+        Step::LedBrightness {
+            profile,
+            led,
+            value,
+        } => {
+            client
+                .set_led_brightness(&format!("{}/l{led}", profile_path(*profile)), *value)
+                .await
+        }
+        Step::LedDuration { profile, led, ms } => {
+            client
+                .set_led_effect_duration(&format!("{}/l{led}", profile_path(*profile)), *ms)
+                .await
+        }
+        Step::LedDirection {
+            profile,
+            led,
+            direction,
+        } => {
+            client
+                .set_led_effect_direction(&format!("{}/l{led}", profile_path(*profile)), *direction)
+                .await
+        }
+        Step::ProfileActive { profile } => {
+            client
+                .call_profile_set_active(&profile_path(*profile))
+                .await
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_config() -> DeviceConfig {
+        let profile = ProfileConfig {
+            index: 0,
+            name: "Work".to_string(),
+            report_rate: 1000,
+            is_active: true,
+            is_disabled: false,
+            angle_snapping: None,
+            debounce: Some(8),
+            resolutions: vec![ResolutionConfig {
+                index: 0,
+                dpi: DpiConfig::from_xy((800, 1200)),
+                is_active: true,
+                is_default: true,
+                is_disabled: false,
+            }],
+            buttons: vec![
+                ButtonConfig::new(0, 1, &ButtonMapping::Value(1)),
+                ButtonConfig::new(1, 4, &ButtonMapping::Macro(vec![(30, 1), (30, 0)])),
+            ],
+            leds: vec![LedConfig {
+                index: 0,
+                mode: "solid".to_string(),
+                color: hex_color((255, 0, 16)),
+                secondary_color: hex_color((0, 0, 0)),
+                tertiary_color: hex_color((0, 0, 0)),
+                brightness: 255,
+                duration: 0,
+                direction: None,
+            }],
+        };
+        DeviceConfig {
+            name: "G502".to_string(),
+            model: "usb:046d:c08b:0".to_string(),
+            firmware: String::new(),
+            profiles: vec![profile],
+        }
+    }
+
+    #[test]
+    fn test_export_document_shape() {
+        let config = sample_config();
+        let json = serde_json::to_value(&config).unwrap();
+        let profile = &json["profiles"][0];
+        assert_eq!(profile["report_rate"], 1000);
+        assert_eq!(
+            profile["resolutions"][0]["dpi"],
+            serde_json::json!([800, 1200])
+        );
+        assert_eq!(profile["buttons"][0]["mapping"], "left");
+        assert_eq!(profile["buttons"][0]["value"], 1);
+        assert!(profile["buttons"][1].get("value").is_none());
+        assert_eq!(
+            profile["buttons"][1]["macro"],
+            serde_json::json!([
+                { "keycode": 30, "key": "KEY_A", "press": true },
+                { "keycode": 30, "key": "KEY_A", "press": false },
+            ])
+        );
+        assert_eq!(profile["leds"][0]["color"], "#ff0010");
+        assert!(profile["leds"][0].get("direction").is_none());
+        assert!(profile.get("angle_snapping").is_none());
+        assert_eq!(profile["debounce"], 8);
+
+        /* The document reads back unchanged. */
+        let back: DeviceConfig = serde_json::from_value(json).unwrap();
+        assert_eq!(back, config);
+        assert_eq!(DpiConfig::from_xy((800, 800)), DpiConfig::Unified(800));
+    }
+
+    #[test]
+    fn test_import_plan_writes_only_differences() {
+        let current = sample_config();
+        assert!(plan(&current, &current).unwrap().steps.is_empty());
+
+        let mut wanted = current.clone();
+        let p = &mut wanted.profiles[0];
+        p.report_rate = 500;
+        p.angle_snapping = Some(true);
+        p.resolutions[0].dpi = DpiConfig::Unified(1600);
+        p.buttons[0] = ButtonConfig::new(0, 3, &ButtonMapping::Value(30));
+        p.leds[0].mode = "breathing".to_string();
+        p.leds[0].color = "#00ff00".to_string();
+        let plan = plan(&current, &wanted).unwrap();
+        let lines: Vec<String> = plan.steps.iter().map(Step::to_string).collect();
+        assert_eq!(
+            lines,
+            [
+                "profile 0: report rate 500 Hz",
+                "profile 0 resolution 0: 1600 DPI",
+                "profile 0 button 0: key KEY_A",
+                "profile 0 LED 0: mode breathing",
+                "profile 0 LED 0: color #00ff00",
+            ]
+        );
+        assert!(matches!(plan.steps[3], Step::LedMode { mode: 3, .. }));
+        /* The device has no angle snapping: noted, not written. */
+        assert_eq!(plan.skipped.len(), 1);
+        assert!(plan.skipped[0].contains("angle snapping"));
+
+        /* A different layout is refused before anything is planned. */
+        let mut wanted = current.clone();
+        wanted.profiles[0].buttons.pop();
+        let err = super::plan(&current, &wanted).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("1 buttons in profile 0 but the device has 2")
+        );
+    }
+
+    #[test]
+    fn test_copy_plan_keeps_destination_state() {
+        let src = sample_config().profiles.remove(0);
+        let mut dst = src.clone();
+        dst.index = 1;
+        dst.name = "Game".to_string();
+        dst.is_active = false;
+        dst.resolutions[0].dpi = DpiConfig::Unified(400);
+        dst.resolutions[0].is_active = false;
+        dst.resolutions.push(ResolutionConfig {
+            index: 1,
+            dpi: DpiConfig::Unified(3200),
+            is_active: true,
+            is_default: false,
+            is_disabled: false,
+        });
+        dst.buttons[1] = ButtonConfig::new(1, 1, &ButtonMapping::Value(2));
+
+        let plan = plan_copy(&src, &dst).unwrap();
+        let lines: Vec<String> = plan.steps.iter().map(Step::to_string).collect();
+        /* No name, profile activation or resolution switch. */
+        assert_eq!(
+            lines,
+            [
+                "profile 1 resolution 0: 800x1200 DPI",
+                "profile 1 button 1: macro KEY_A↓ KEY_A↑",
+            ]
+        );
+        assert_eq!(
+            plan.skipped,
+            ["profile 0 has 1 resolutions but profile 1 has 2; copying the first 1"]
+        );
+    }
+}
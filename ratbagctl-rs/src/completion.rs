@@ -0,0 +1,182 @@
+/* ratbagctl completions: the bash completion script and the hidden `__complete` helper it calls
+ * on every <Tab>. The helper walks the clap command tree to find what the current word is, and
+ * completes device specifiers and profile indices from the running daemon. */
+//! Shell completion for `ratbagctl`.
+
+use anyhow::Result;
+use clap::Command;
+
+use crate::dbus_client::RatbagClient;
+
+/// Bash completion script, printed by `ratbagctl completions bash`.
+///
+/// Everything but the current word is handed to `ratbagctl __complete`,
+/// which prints one candidate per line.
+pub const BASH_SCRIPT: &str = r#"# bash completion for ratbagctl
+_ratbagctl() {
+    local cur="${COMP_WORDS[COMP_CWORD]}"
+    local -a bus=()
+    local i
+    for ((i = 1; i < COMP_CWORD; i++)); do
+        case "${COMP_WORDS[i]}" in
+            --bus) bus=(--bus "${COMP_WORDS[i+1]}") ;;
+            --bus=*) bus=("${COMP_WORDS[i]}") ;;
+        esac
+    done
+    local candidates
+    candidates="$(ratbagctl "${bus[@]}" __complete -- "${COMP_WORDS[@]:1:COMP_CWORD-1}" 2>/dev/null)"
+    COMPREPLY=($(compgen -W "$candidates" -- "$cur"))
+}
+complete -F _ratbagctl ratbagctl
+"#;
+
+/// What the word being completed is.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Slot {
+    /// One of these literal words (subcommand names).
+    Words(Vec<String>),
+    /// A `<device>` argument.
+    Device,
+    /// A profile index of `device`.
+    Profile { device: String },
+    /// Nothing to offer (free-form values, unknown words).
+    Nothing,
+}
+
+/// Work out what follows `words`, the command line after `ratbagctl`
+/// up to (not including) the word being completed.
+pub fn classify(mut cmd: Command, words: &[String]) -> Slot {
+    cmd.build();
+    let mut cmd = &cmd;
+    let mut positionals: Vec<&str> = Vec::new();
+
+    let mut i = 0;
+    while i < words.len() {
+        let word = words[i].as_str();
+        if let Some(long) = word.strip_prefix("--") {
+            let takes_value = !long.contains('=')
+                && cmd
+                    .get_arguments()
+                    .find(|a| a.get_long() == Some(long))
+                    .is_some_and(|a| a.get_action().takes_values());
+            if takes_value {
+                if i + 1 == words.len() {
+                    /* Completing this option's value. */
+                    return match (long, positional_value(cmd, &positionals, "device")) {
+                        ("profile", Some(device)) => Slot::Profile { device },
+                        _ => Slot::Nothing,
+                    };
+                }
+                i += 1;
+            }
+        } else if cmd.has_subcommands() {
+            match cmd.find_subcommand(word) {
+                Some(sub) => cmd = sub,
+                None => return Slot::Nothing,
+            }
+        } else {
+            positionals.push(word);
+        }
+        i += 1;
+    }
+
+    if cmd.has_subcommands() {
+        return Slot::Words(
+            cmd.get_subcommands()
+                .filter(|s| !s.is_hide_set())
+                .map(|s| s.get_name().to_string())
+                .collect(),
+        );
+    }
+    match cmd.get_positionals().nth(positionals.len()).map(|a| a.get_id().as_str()) {
+        Some("device") => Slot::Device,
+        Some("profile") => match positional_value(cmd, &positionals, "device") {
+            Some(device) => Slot::Profile { device },
+            None => Slot::Nothing,
+        },
+        _ => Slot::Nothing,
+    }
+}
+
+/* Value given for the positional argument `id`, if it was reached. */
+fn positional_value(cmd: &Command, positionals: &[&str], id: &str) -> Option<String> {
+    cmd.get_positionals()
+        .position(|a| a.get_id().as_str() == id)
+        .and_then(|i| positionals.get(i))
+        .map(|v| v.to_string())
+}
+
+/// Device specifiers for completion: every index, then every sysname.
+pub fn device_candidates(paths: &[String]) -> Vec<String> {
+    let indices = (0..paths.len()).map(|i| i.to_string());
+    let sysnames = paths
+        .iter()
+        .filter_map(|p| p.rsplit('/').next())
+        .map(str::to_string);
+    indices.chain(sysnames).collect()
+}
+
+/// Candidates for `slot`, asking the daemon where needed.
+pub async fn candidates(client: &RatbagClient, slot: Slot) -> Result<Vec<String>> {
+    Ok(match slot {
+        Slot::Words(words) => words,
+        Slot::Device => device_candidates(&client.list_devices().await?),
+        Slot::Profile { device } => {
+            let path = client.resolve_device(&device).await?;
+            let count = client.get_device_profiles(&path).await?.len();
+            (0..count).map(|i| i.to_string()).collect()
+        }
+        Slot::Nothing => Vec::new(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    fn slot(line: &str) -> Slot {
+        let words: Vec<String> = line.split_whitespace().map(str::to_string).collect();
+        classify(crate::Cli::command(), &words)
+    }
+
+    #[test]
+    fn test_device_candidates_for_known_devices() {
+        let paths = [
+            "/org/freedesktop/ratbag1/device/hidraw3".to_string(),
+            "/org/freedesktop/ratbag1/device/hidraw7".to_string(),
+        ];
+        assert_eq!(device_candidates(&paths), ["0", "1", "hidraw3", "hidraw7"]);
+        assert!(device_candidates(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_classify_finds_device_and_profile_slots() {
+        assert_eq!(slot("info"), Slot::Device);
+        assert_eq!(slot("--bus session profile info"), Slot::Device);
+        assert_eq!(
+            slot("resolution dpi hidraw3"),
+            Slot::Profile {
+                device: "hidraw3".into()
+            }
+        );
+        assert_eq!(
+            slot("commit 1 --profile"),
+            Slot::Profile { device: "1".into() }
+        );
+        /* Resolution index and DPI value are free-form. */
+        assert_eq!(slot("resolution dpi 0 0"), Slot::Nothing);
+        assert_eq!(slot("list"), Slot::Nothing);
+        assert_eq!(slot("bogus"), Slot::Nothing);
+
+        let Slot::Words(words) = slot("profile") else {
+            panic!("expected profile subcommands");
+        };
+        assert!(words.iter().any(|w| w == "list"));
+        let Slot::Words(words) = slot("") else {
+            panic!("expected top-level subcommands");
+        };
+        assert!(words.iter().any(|w| w == "info"));
+        assert!(!words.iter().any(|w| w == "__complete"));
+    }
+}
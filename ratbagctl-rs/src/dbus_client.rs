@@ -4,12 +4,15 @@
 //!
 //! All communication with the daemon goes through this module.
 
+use std::time::Duration;
+
 use anyhow::{anyhow, Context, Result};
-use zbus::zvariant::{OwnedValue, Value};
+use tokio_stream::StreamExt;
+use zbus::zvariant::{ObjectPath, OwnedObjectPath, OwnedValue, Value};
 use zbus::Connection;
 
 const BUS_NAME: &str = "org.freedesktop.ratbag1";
-const MANAGER_PATH: &str = "/org/freedesktop/ratbag1";
+pub(crate) const MANAGER_PATH: &str = "/org/freedesktop/ratbag1";
 const MANAGER_IFACE: &str = "org.freedesktop.ratbag1.Manager";
 const DEVICE_IFACE: &str = "org.freedesktop.ratbag1.Device";
 const PROFILE_IFACE: &str = "org.freedesktop.ratbag1.Profile";
@@ -17,17 +20,84 @@ const RESOLUTION_IFACE: &str = "org.freedesktop.ratbag1.Resolution";
 const BUTTON_IFACE: &str = "org.freedesktop.ratbag1.Button";
 const LED_IFACE: &str = "org.freedesktop.ratbag1.Led";
 
+/// Payload of a button's `Mapping` property.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ButtonMapping {
+    /// Button number, special action code or keycode, depending on the action type.
+    Value(u32),
+    /// Macro events as `(keycode, direction)`; direction 1 is press, 0 release.
+    Macro(Vec<(u32, u32)>),
+}
+
+impl std::fmt::Display for ButtonMapping {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ButtonMapping::Value(v) => write!(f, "{}", v),
+            ButtonMapping::Macro(events) => {
+                let entries: Vec<String> = events
+                    .iter()
+                    .map(|&(keycode, dir)| format!("{}:{}", keycode, if dir == 1 { "↓" } else { "↑" }))
+                    .collect();
+                write!(f, "{}", entries.join(" "))
+            }
+        }
+    }
+}
+
+/// Reply of a device's or profile's `Commit`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CommitReply {
+    /// 0 when everything was written.
+    pub status: u32,
+    /// `(object path, error)` for each write that failed.
+    pub failures: Vec<(String, String)>,
+}
+
+impl CommitReply {
+    /// `Ok` when everything was written, otherwise an error listing what
+    /// was not, with paths relative to `device_path`, e.g. `p1/l0: busy`.
+    pub fn check(self, device_path: &str) -> Result<()> {
+        if self.status == 0 {
+            return Ok(());
+        }
+        if self.failures.is_empty() {
+            anyhow::bail!("Commit returned error code {}", self.status);
+        }
+        let lines: Vec<String> = self
+            .failures
+            .iter()
+            .map(|(path, error)| {
+                let object = path
+                    .strip_prefix(device_path)
+                    .map(|rest| rest.trim_start_matches('/'))
+                    .filter(|rest| !rest.is_empty())
+                    .unwrap_or("device");
+                format!("  {}: {}", object, error)
+            })
+            .collect();
+        anyhow::bail!("Commit failed; not written:\n{}", lines.join("\n"))
+    }
+}
+
 /// A client that talks to the `ratbagd` daemon over the system DBus.
+#[derive(Clone)]
 pub struct RatbagClient {
     conn: Connection,
 }
 
 impl RatbagClient {
-    /// Connect to the system bus.
-    pub async fn connect() -> Result<Self> {
-        let conn = Connection::system()
-            .await
-            .context("Cannot connect to the system DBus")?;
+    /// Connect to the system bus, or to the session bus when `session` is
+    /// set (useful for running a development daemon without root).
+    pub async fn connect(session: bool) -> Result<Self> {
+        let conn = if session {
+            Connection::session()
+                .await
+                .context("Cannot connect to the session DBus")?
+        } else {
+            Connection::system()
+                .await
+                .context("Cannot connect to the system DBus")?
+        };
         Ok(Self { conn })
     }
 
@@ -40,12 +110,129 @@ impl RatbagClient {
         self.get_i32_property(MANAGER_PATH, MANAGER_IFACE, "APIVersion").await
     }
 
+    /// Get the daemon's version string.
+    pub async fn get_daemon_version(&self) -> Result<String> {
+        self.get_string_property(MANAGER_PATH, MANAGER_IFACE, "DaemonVersion").await
+    }
+
+    /// Get the `Driver=` names the daemon was built with.
+    pub async fn get_drivers(&self) -> Result<Vec<String>> {
+        let val = self.get_property(MANAGER_PATH, MANAGER_IFACE, "Drivers").await?;
+        Vec::<String>::try_from(val).context("Failed to parse Drivers property")
+    }
+
+    /// Unique bus name of the process owning `org.freedesktop.ratbag1`,
+    /// `None` when no daemon holds it.
+    pub async fn get_name_owner(&self) -> Result<Option<String>> {
+        let proxy = zbus::fdo::DBusProxy::new(&self.conn)
+            .await
+            .context("Cannot create DBus proxy")?;
+        let name = zbus::names::BusName::try_from(BUS_NAME)?;
+        match proxy.get_name_owner(name).await {
+            Ok(owner) => Ok(Some(owner.to_string())),
+            Err(zbus::fdo::Error::NameHasNoOwner(_)) => Ok(None),
+            Err(e) => Err(e).context("GetNameOwner call failed"),
+        }
+    }
+
+    /// Process ID behind the bus connection `unique_name`.
+    pub async fn get_connection_pid(&self, unique_name: &str) -> Result<u32> {
+        let proxy = zbus::fdo::DBusProxy::new(&self.conn)
+            .await
+            .context("Cannot create DBus proxy")?;
+        let name = zbus::names::BusName::try_from(unique_name)?;
+        proxy
+            .get_connection_unix_process_id(name)
+            .await
+            .context("GetConnectionUnixProcessID call failed")
+    }
+
     /// Get the list of device object paths from the Manager.
     pub async fn list_devices(&self) -> Result<Vec<String>> {
         let val = self.get_property(MANAGER_PATH, MANAGER_IFACE, "Devices").await?;
         extract_object_path_array(val).context("Failed to parse Devices property")
     }
 
+    /// Wait until a device is ready and return its object path.
+    ///
+    /// Subscribes to the Manager's `DeviceReady` signal *before* checking
+    /// `Devices`, so a device that finishes loading in between is not missed.
+    /// Returns the first listed device whose `IsReady` is set immediately;
+    /// listed devices still asleep are waited for like new ones.
+    pub async fn wait_for_device(&self, timeout: Duration) -> Result<String> {
+        let proxy = zbus::Proxy::new(&self.conn, BUS_NAME, MANAGER_PATH, MANAGER_IFACE)
+            .await
+            .context("Cannot create Manager proxy")?;
+        let mut ready = proxy
+            .receive_signal("DeviceReady")
+            .await
+            .context("Cannot subscribe to DeviceReady")?;
+
+        for path in self.list_devices().await? {
+            if self.get_device_is_ready(&path).await? {
+                return Ok(path);
+            }
+        }
+
+        let msg = tokio::time::timeout(timeout, ready.next())
+            .await
+            .map_err(|_| anyhow!("No device became ready within {}s", timeout.as_secs()))?
+            .context("DeviceReady signal stream closed")?;
+        let body = msg.body();
+        let path: ObjectPath<'_> = body.deserialize()?;
+        Ok(path.to_string())
+    }
+
+    /// Subscribe to `PropertiesChanged` for a device and all of its child
+    /// objects (profiles, resolutions, buttons, LEDs).
+    pub async fn receive_property_changes(&self, device_path: &str) -> Result<zbus::MessageStream> {
+        let rule = zbus::MatchRule::builder()
+            .msg_type(zbus::message::Type::Signal)
+            .sender(BUS_NAME)?
+            .interface("org.freedesktop.DBus.Properties")?
+            .member("PropertiesChanged")?
+            .path_namespace(device_path.to_string())?
+            .build();
+        zbus::MessageStream::for_match_rule(rule, &self.conn, None)
+            .await
+            .context("Cannot subscribe to PropertiesChanged")
+    }
+
+    /// Subscribe to every signal the daemon emits: `PropertiesChanged` of
+    /// the manager and of all device objects, and the daemon's own signals.
+    pub async fn receive_signals(&self) -> Result<zbus::MessageStream> {
+        let rule = zbus::MatchRule::builder()
+            .msg_type(zbus::message::Type::Signal)
+            .sender(BUS_NAME)?
+            .path_namespace(MANAGER_PATH)?
+            .build();
+        zbus::MessageStream::for_match_rule(rule, &self.conn, None)
+            .await
+            .context("Cannot subscribe to daemon signals")
+    }
+
+    /// Features the named driver can write to hardware, by feature name.
+    pub async fn driver_capabilities(
+        &self,
+        driver: &str,
+    ) -> Result<std::collections::BTreeMap<String, bool>> {
+        let reply = self
+            .conn
+            .call_method(Some(BUS_NAME), MANAGER_PATH, Some(MANAGER_IFACE), "DriverCapabilities", &(driver,))
+            .await
+            .context("DriverCapabilities call failed")?;
+        Ok(reply.body().deserialize()?)
+    }
+
+    /// Ask the daemon to shut down (root or the daemon's own user only).
+    pub async fn quit(&self) -> Result<()> {
+        self.conn
+            .call_method(Some(BUS_NAME), MANAGER_PATH, Some(MANAGER_IFACE), "Quit", &())
+            .await
+            .context("Quit call failed")?;
+        Ok(())
+    }
+
     /// Load a synthetic test device (dev-hooks only).
     pub async fn load_test_device(&self, json: &str) -> Result<String> {
         let reply = self
@@ -66,8 +253,26 @@ impl RatbagClient {
         Ok(())
     }
 
-    /// Resolve a device specifier (numeric index or sysname substring) to a
-    /// full object path.
+    /// Send a raw HID report to a device and return its reply (dev-hooks
+    /// only).  Empty when an output report got no answer.
+    pub async fn send_raw_report(&self, path: &str, feature: bool, data: &[u8]) -> Result<Vec<u8>> {
+        let path = ObjectPath::try_from(path)?;
+        let reply = self
+            .conn
+            .call_method(
+                Some(BUS_NAME),
+                MANAGER_PATH,
+                Some(MANAGER_IFACE),
+                "SendRawReport",
+                &(path, feature, data),
+            )
+            .await
+            .context("SendRawReport call failed (is ratbagd built with dev-hooks?)")?;
+        Ok(reply.body().deserialize()?)
+    }
+
+    /// Resolve a device specifier (numeric index, stable key or sysname
+    /// substring) to a full object path.
     pub async fn resolve_device(&self, spec: &str) -> Result<String> {
         let devices = self.list_devices().await?;
         anyhow::ensure!(!devices.is_empty(), "No devices found");
@@ -80,14 +285,54 @@ impl RatbagClient {
                 .with_context(|| format!("Device index {} out of range (0..{})", idx, devices.len()));
         }
 
-        // Otherwise match against the path suffix (sysname).
-        for path in &devices {
-            if path.ends_with(spec) || path.contains(spec) {
-                return Ok(path.clone());
-            }
+        // Stable keys always contain ':' and sysnames never do, so only
+        // fetch the keys when they can match.
+        let mut candidates = Vec::with_capacity(devices.len());
+        for path in devices {
+            let key = if spec.contains(':') {
+                self.get_device_stable_key(&path).await.unwrap_or_default()
+            } else {
+                String::new()
+            };
+            candidates.push((path, key));
         }
 
-        anyhow::bail!("No device matching '{}' found", spec)
+        match_device(spec, &candidates).map(str::to_string)
+    }
+
+    /// Path of profile `profile` on `dev_path`, checked against the
+    /// device's profile count.
+    pub async fn profile_path(&self, dev_path: &str, profile: u32) -> Result<String> {
+        let count = self.get_device_profiles(dev_path).await?.len();
+        check_index("Profile", profile, count)?;
+        Ok(format!("{}/p{}", dev_path, profile))
+    }
+
+    /// Path of resolution `index` of a profile, checked against the
+    /// profile's resolution count.
+    pub async fn resolution_path(&self, dev_path: &str, profile: u32, index: u32) -> Result<String> {
+        let profile_path = self.profile_path(dev_path, profile).await?;
+        let count = self.get_profile_resolutions(&profile_path).await?.len();
+        check_index("Resolution", index, count)?;
+        Ok(format!("{}/r{}", profile_path, index))
+    }
+
+    /// Path of button `index` of a profile, checked against the
+    /// profile's button count.
+    pub async fn button_path(&self, dev_path: &str, profile: u32, index: u32) -> Result<String> {
+        let profile_path = self.profile_path(dev_path, profile).await?;
+        let count = self.get_profile_buttons(&profile_path).await?.len();
+        check_index("Button", index, count)?;
+        Ok(format!("{}/b{}", profile_path, index))
+    }
+
+    /// Path of LED `index` of a profile, checked against the profile's
+    /// LED count.
+    pub async fn led_path(&self, dev_path: &str, profile: u32, index: u32) -> Result<String> {
+        let profile_path = self.profile_path(dev_path, profile).await?;
+        let count = self.get_profile_leds(&profile_path).await?.len();
+        check_index("LED", index, count)?;
+        Ok(format!("{}/l{}", profile_path, index))
     }
 
     // -----------------------------------------------------------------------
@@ -102,23 +347,165 @@ impl RatbagClient {
         self.get_string_property(path, DEVICE_IFACE, "Model").await
     }
 
+    /// Stable `bustype:vid:pid[:serial]` key; empty on daemons without it.
+    pub async fn get_device_stable_key(&self, path: &str) -> Result<String> {
+        self.get_string_property(path, DEVICE_IFACE, "StableKey").await
+    }
+
+    /// Device type: 0=unspecified, 1=other, 2=mouse, 3=keyboard.
+    pub async fn get_device_type(&self, path: &str) -> Result<u32> {
+        self.get_u32_property(path, DEVICE_IFACE, "DeviceType").await
+    }
+
+    /// Bus type, e.g. "usb"; missing on older daemons.
+    pub async fn get_device_bus_type(&self, path: &str) -> Result<String> {
+        self.get_string_property(path, DEVICE_IFACE, "BusType").await
+    }
+
+    /// Vendor and product ID; missing on older daemons.
+    pub async fn get_device_ids(&self, path: &str) -> Result<(u32, u32)> {
+        Ok((
+            self.get_u32_property(path, DEVICE_IFACE, "VendorId").await?,
+            self.get_u32_property(path, DEVICE_IFACE, "ProductId").await?,
+        ))
+    }
+
+    pub async fn get_device_serial(&self, path: &str) -> Result<String> {
+        self.get_string_property(path, DEVICE_IFACE, "Serial").await
+    }
+
     pub async fn get_device_firmware(&self, path: &str) -> Result<String> {
         self.get_string_property(path, DEVICE_IFACE, "FirmwareVersion").await
     }
 
+    /// SmartShift ratchet threshold (1-255); 0 when the device has none.
+    pub async fn get_device_smart_shift_threshold(&self, path: &str) -> Result<u32> {
+        self.get_u32_property(path, DEVICE_IFACE, "SmartShiftThreshold").await
+    }
+
+    pub async fn set_device_smart_shift_threshold(&self, path: &str, value: u32) -> Result<()> {
+        self.set_property(path, DEVICE_IFACE, "SmartShiftThreshold", Value::from(value))
+            .await
+    }
+
+    /// Whether a device-wide setting (SmartShift, sleep timeout) has
+    /// uncommitted changes.  False on daemons that predate the property.
+    pub async fn get_device_is_dirty(&self, path: &str) -> Result<bool> {
+        self.get_bool_property(path, DEVICE_IFACE, "IsDirty").await
+    }
+
+    /// Auto-sleep timeout in minutes; 0 when the device has none.
+    pub async fn get_device_sleep_timeout(&self, path: &str) -> Result<u32> {
+        self.get_u32_property(path, DEVICE_IFACE, "SleepTimeout").await
+    }
+
+    pub async fn set_device_sleep_timeout(&self, path: &str, minutes: u32) -> Result<()> {
+        self.set_property(path, DEVICE_IFACE, "SleepTimeout", Value::from(minutes))
+            .await
+    }
+
+    /// Lowest and highest sleep timeout in minutes the device accepts.
+    pub async fn get_device_sleep_timeout_range(&self, path: &str) -> Result<(u32, u32)> {
+        let val = self.get_property(path, DEVICE_IFACE, "SleepTimeoutRange").await?;
+        <(u32, u32)>::try_from(val).context("Failed to parse SleepTimeoutRange property")
+    }
+
+    /// Battery level in percent and whether it is charging; `None` when
+    /// the device reports no battery.
+    pub async fn get_device_battery(&self, path: &str) -> Result<Option<(u32, bool)>> {
+        let level = self.get_i32_property(path, DEVICE_IFACE, "BatteryLevel").await?;
+        let Ok(level) = u32::try_from(level) else {
+            return Ok(None);
+        };
+        let charging = self.get_bool_property(path, DEVICE_IFACE, "BatteryCharging").await?;
+        Ok(Some((level, charging)))
+    }
+
     pub async fn get_device_profiles(&self, path: &str) -> Result<Vec<String>> {
         let val = self.get_property(path, DEVICE_IFACE, "Profiles").await?;
         extract_object_path_array(val).context("Failed to parse Profiles property")
     }
 
-    pub async fn commit_device(&self, path: &str) -> Result<u32> {
+    /// Whether the device's objects reflect the hardware; false while a
+    /// device that was asleep at plug-in has not answered yet.
+    pub async fn get_device_is_ready(&self, path: &str) -> Result<bool> {
+        self.get_bool_property(path, DEVICE_IFACE, "IsReady").await
+    }
+
+    /// Whether the profiles stored on the device failed their checksums.
+    pub async fn get_device_needs_repair(&self, path: &str) -> Result<bool> {
+        self.get_bool_property(path, DEVICE_IFACE, "NeedsRepair").await
+    }
+
+    /// Rewrite every profile stored on the device; fails with NotSupported
+    /// when the device keeps no checksummed profiles.
+    pub async fn repair_device(&self, path: &str) -> Result<CommitReply> {
         let reply = self
             .conn
-            .call_method(Some(BUS_NAME), path, Some(DEVICE_IFACE), "Commit", &())
+            .call_method(Some(BUS_NAME), path, Some(DEVICE_IFACE), "Repair", &())
+            .await
+            .context("Repair call failed")?;
+        parse_commit_reply(&reply)
+    }
+
+    /// Commit a device's pending changes.  Returns the status code (0 on
+    /// success) and the `(object path, error)` pairs the daemon could not
+    /// write.
+    pub async fn commit_device(&self, path: &str) -> Result<CommitReply> {
+        let call = |method| {
+            self.conn
+                .call_method(Some(BUS_NAME), path, Some(DEVICE_IFACE), method, &())
+        };
+        /* Daemons from before `CommitWithFailures` only have `Commit`. */
+        let reply = match call("CommitWithFailures").await {
+            Err(zbus::Error::MethodError(name, _, _))
+                if name.as_str() == "org.freedesktop.DBus.Error.UnknownMethod" =>
+            {
+                call("Commit").await
+            }
+            reply => reply,
+        }
+        .context("Commit call failed")?;
+        parse_commit_reply(&reply)
+    }
+
+    /// Raw HID report descriptor of a device (dev-hooks daemons only).
+    pub async fn get_hid_descriptor(&self, path: &str) -> Result<Vec<u8>> {
+        let reply = self
+            .conn
+            .call_method(Some(BUS_NAME), path, Some(DEVICE_IFACE), "GetHidDescriptor", &())
+            .await
+            .context("GetHidDescriptor call failed (is ratbagd built with dev-hooks?)")?;
+        Ok(reply.body().deserialize()?)
+    }
+
+    /// Commit one profile's pending changes; other profiles stay dirty.
+    pub async fn commit_profile(&self, path: &str) -> Result<CommitReply> {
+        let reply = self
+            .conn
+            .call_method(Some(BUS_NAME), path, Some(PROFILE_IFACE), "Commit", &())
             .await
             .context("Commit call failed")?;
-        let result: u32 = reply.body().deserialize()?;
-        Ok(result)
+        parse_commit_reply(&reply)
+    }
+
+    /// Show a solid LED color right away, without a commit.  Returns
+    /// `false` when the daemon dropped the update to rate-limit the device.
+    pub async fn set_led_color_live(&self, path: &str, led: u32, r: u32, g: u32, b: u32) -> Result<bool> {
+        validate_rgb(r, g, b)?;
+        let reply = self
+            .conn
+            .call_method(
+                Some(BUS_NAME),
+                path,
+                Some(DEVICE_IFACE),
+                "SetLedColorLive",
+                &(led, r, g, b),
+            )
+            .await
+            .context("SetLedColorLive call failed")?;
+        let applied: bool = reply.body().deserialize()?;
+        Ok(applied)
     }
 
     // -----------------------------------------------------------------------
@@ -163,6 +550,10 @@ impl RatbagClient {
         self.get_vec_u32_property(path, PROFILE_IFACE, "ReportRates").await
     }
 
+    pub async fn get_profile_capabilities(&self, path: &str) -> Result<Vec<u32>> {
+        self.get_vec_u32_property(path, PROFILE_IFACE, "Capabilities").await
+    }
+
     pub async fn get_profile_angle_snapping(&self, path: &str) -> Result<i32> {
         self.get_i32_property(path, PROFILE_IFACE, "AngleSnapping").await
     }
@@ -185,6 +576,20 @@ impl RatbagClient {
         self.get_vec_u32_property(path, PROFILE_IFACE, "Debounces").await
     }
 
+    /// Lift-off distance level; 0 when the device cannot set it.
+    pub async fn get_profile_lift_off_distance(&self, path: &str) -> Result<u32> {
+        self.get_u32_property(path, PROFILE_IFACE, "LiftOffDistance").await
+    }
+
+    pub async fn set_profile_lift_off_distance(&self, path: &str, level: u32) -> Result<()> {
+        self.set_property(path, PROFILE_IFACE, "LiftOffDistance", Value::from(level))
+            .await
+    }
+
+    pub async fn get_profile_lift_off_distances(&self, path: &str) -> Result<Vec<u32>> {
+        self.get_vec_u32_property(path, PROFILE_IFACE, "LiftOffDistances").await
+    }
+
     pub async fn set_profile_report_rate(&self, path: &str, rate: u32) -> Result<()> {
         self.set_property(path, PROFILE_IFACE, "ReportRate", Value::from(rate))
             .await
@@ -198,6 +603,17 @@ impl RatbagClient {
         Ok(())
     }
 
+    /// Switch to the profile on the device right away where the driver
+    /// can.  Returns false when the switch is only staged until a commit.
+    pub async fn call_profile_switch(&self, path: &str) -> Result<bool> {
+        let reply = self
+            .conn
+            .call_method(Some(BUS_NAME), path, Some(PROFILE_IFACE), "Switch", &())
+            .await
+            .context("Switch call failed")?;
+        Ok(reply.body().deserialize()?)
+    }
+
     pub async fn get_profile_resolutions(&self, path: &str) -> Result<Vec<String>> {
         let val = self.get_property(path, PROFILE_IFACE, "Resolutions").await?;
         extract_object_path_array(val).context("Failed to parse Resolutions property")
@@ -251,17 +667,18 @@ impl RatbagClient {
     ///
     /// The DBus property is a variant: either `u32` or `(u32, u32)`.
     pub async fn get_resolution_dpi(&self, path: &str) -> Result<String> {
+        Ok(format_dpi(self.get_resolution_dpi_xy(path).await?))
+    }
+
+    /// Read the `Resolution` property as `(x, y)`; a unified DPI yields `x == y`.
+    pub async fn get_resolution_dpi_xy(&self, path: &str) -> Result<(u32, u32)> {
         let val = self.get_property(path, RESOLUTION_IFACE, "Resolution").await?;
         let inner: Value<'_> = val.into();
         match &inner {
-            Value::U32(v) => Ok(format!("{} DPI", v)),
+            Value::U32(v) => Ok((*v, *v)),
             Value::Structure(s) => {
                 if let [Value::U32(x), Value::U32(y)] = s.fields() {
-                    if x == y {
-                        Ok(format!("{} DPI", x))
-                    } else {
-                        Ok(format!("{}x{} DPI", x, y))
-                    }
+                    Ok((*x, *y))
                 } else {
                     Err(anyhow!("Malformed Resolution property at {}", path))
                 }
@@ -271,7 +688,14 @@ impl RatbagClient {
     }
 
     pub async fn set_resolution_dpi(&self, path: &str, dpi: u32) -> Result<()> {
-        let owned = OwnedValue::try_from(Value::from((dpi, dpi)))
+        self.set_resolution_dpi_xy(path, dpi, dpi).await
+    }
+
+    /// Write the `Resolution` property; equal axes are sent as a plain `u32`
+    /// so devices without separate X/Y support accept the value.
+    pub async fn set_resolution_dpi_xy(&self, path: &str, x: u32, y: u32) -> Result<()> {
+        let value = if x == y { Value::from(x) } else { Value::from((x, y)) };
+        let owned = OwnedValue::try_from(value)
             .map_err(|e| anyhow!("Failed to encode D-Bus value: {e}"))?;
         let wrapped = Value::Value(Box::new(owned.into()));
         self.set_property(path, RESOLUTION_IFACE, "Resolution", wrapped)
@@ -310,6 +734,15 @@ impl RatbagClient {
     // Button
     // -----------------------------------------------------------------------
 
+    /// Restore the button's factory mapping (pending until commit).
+    pub async fn call_button_reset(&self, path: &str) -> Result<()> {
+        self.conn
+            .call_method(Some(BUS_NAME), path, Some(BUTTON_IFACE), "Reset", &())
+            .await
+            .context("Reset call failed")?;
+        Ok(())
+    }
+
     pub async fn get_button_index(&self, path: &str) -> Result<u32> {
         self.get_u32_property(path, BUTTON_IFACE, "Index").await
     }
@@ -318,30 +751,46 @@ impl RatbagClient {
     ///
     /// For macro mappings (type 4) the display string shows decoded key events.
     pub async fn get_button_mapping(&self, path: &str) -> Result<(u32, String)> {
+        let (action_type, mapping) = self.get_button_mapping_value(path).await?;
+        Ok((action_type, mapping.to_string()))
+    }
+
+    /// Physical position hint from the device file (`ButtonLayout`);
+    /// empty when unknown.
+    pub async fn get_button_position(&self, path: &str) -> Result<String> {
+        self.get_string_property(path, BUTTON_IFACE, "Position").await
+    }
+
+    /// Returns `(action_type, mapping)` with the payload left undecoded.
+    pub async fn get_button_mapping_value(&self, path: &str) -> Result<(u32, ButtonMapping)> {
         let val = self.get_property(path, BUTTON_IFACE, "Mapping").await?;
         let inner: Value<'_> = val.into();
         if let Value::Structure(s) = &inner {
             if let [Value::U32(action_type), variant] = s.fields() {
-                let display = match variant {
-                    Value::U32(v) => v.to_string(),
+                /* The payload is a `v`, which arrives boxed. */
+                let variant = match variant {
+                    Value::Value(inner) => inner.as_ref(),
+                    other => other,
+                };
+                let mapping = match variant {
+                    Value::U32(v) => ButtonMapping::Value(*v),
                     Value::Array(arr) => {
                         // Decode macro entries: Vec<(u32, u32)> = (keycode, direction)
-                        let mut entries: Vec<String> = Vec::with_capacity(arr.len());
+                        let mut events = Vec::with_capacity(arr.len());
                         for item in arr.iter() {
                             if let Value::Structure(t) = item {
                                 if let [Value::U32(keycode), Value::U32(dir)] = t.fields() {
-                                    let arrow = if *dir == 1 { "↓" } else { "↑" };
-                                    entries.push(format!("{}:{}", keycode, arrow));
+                                    events.push((*keycode, *dir));
                                     continue;
                                 }
                             }
                             return Err(anyhow!("Malformed macro mapping entry at {}", path));
                         }
-                        entries.join(" ")
+                        ButtonMapping::Macro(events)
                     }
                     _ => return Err(anyhow!("Unsupported Mapping payload type at {}", path)),
                 };
-                return Ok((*action_type, display));
+                return Ok((*action_type, mapping));
             }
         }
         Err(anyhow!("Malformed Mapping property at {}", path))
@@ -378,6 +827,12 @@ impl RatbagClient {
             .await
     }
 
+    /// Set the macro repeat mode: 0 repeats while held, N >= 1 plays N times.
+    pub async fn set_button_macro_repeat(&self, path: &str, repeat: u32) -> Result<()> {
+        self.set_property(path, BUTTON_IFACE, "MacroRepeat", Value::from(repeat))
+            .await
+    }
+
     // -----------------------------------------------------------------------
     // LED
     // -----------------------------------------------------------------------
@@ -418,6 +873,15 @@ impl RatbagClient {
             .await
     }
 
+    pub async fn get_led_effect_direction(&self, path: &str) -> Result<u32> {
+        self.get_u32_property(path, LED_IFACE, "EffectDirection").await
+    }
+
+    pub async fn set_led_effect_direction(&self, path: &str, direction: u32) -> Result<()> {
+        self.set_property(path, LED_IFACE, "EffectDirection", Value::from(direction))
+            .await
+    }
+
     pub async fn get_led_secondary_color(&self, path: &str) -> Result<(u32, u32, u32)> {
         let val = self.get_property(path, LED_IFACE, "SecondaryColor").await?;
         let inner: Value<'_> = val.into();
@@ -536,12 +1000,47 @@ impl RatbagClient {
     }
 }
 
+/* `Device.Commit`, and the commits of daemons from before per-object
+ * failures, reply with the status alone. */
+fn parse_commit_reply(reply: &zbus::Message) -> Result<CommitReply> {
+    let body = reply.body();
+    if body.signature().to_string() == "u" {
+        let status: u32 = body.deserialize()?;
+        return Ok(CommitReply { status, failures: Vec::new() });
+    }
+    let (status, failures): (u32, Vec<(OwnedObjectPath, String)>) =
+        body.deserialize().context("Malformed Commit reply")?;
+    Ok(CommitReply {
+        status,
+        failures: failures
+            .into_iter()
+            .map(|(path, error)| (path.to_string(), error))
+            .collect(),
+    })
+}
+
 // ---------------------------------------------------------------------------
 // Free-standing helpers for extracting arrays from OwnedValue
 // ---------------------------------------------------------------------------
 
 /// Extract a `Vec<String>` of object-path strings from an `OwnedValue`
 /// that wraps an array of object-paths.
+/* Reject an index the daemon has no object for, instead of letting the
+ * call fail later with an unknown-object error. */
+fn check_index(kind: &str, index: u32, count: usize) -> Result<()> {
+    match count {
+        0 => anyhow::bail!("{} {} out of range: there are none", kind, index),
+        _ => anyhow::ensure!(
+            (index as usize) < count,
+            "{} {} out of range (0..{})",
+            kind,
+            index,
+            count
+        ),
+    }
+    Ok(())
+}
+
 fn extract_object_path_array(val: OwnedValue) -> Result<Vec<String>> {
     let inner: Value<'_> = val.into();
     match inner {
@@ -584,3 +1083,159 @@ fn validate_rgb(r: u32, g: u32, b: u32) -> Result<()> {
     anyhow::ensure!(b <= 255, "Blue component out of range: {}", b);
     Ok(())
 }
+
+/// Pick the device for `spec` from `(object path, stable key)` pairs: an
+/// exact stable key match wins, then the first path containing `spec`.
+/// Identical devices without a serial share a key; a key matching more
+/// than one device is an error listing them.
+pub fn match_device<'a>(spec: &str, devices: &'a [(String, String)]) -> Result<&'a str> {
+    let by_key: Vec<&str> = devices
+        .iter()
+        .filter(|(_, key)| !key.is_empty() && key == spec)
+        .map(|(path, _)| path.as_str())
+        .collect();
+    match by_key[..] {
+        [path] => return Ok(path),
+        [] => {}
+        _ => anyhow::bail!(
+            "Stable key '{}' is ambiguous; it matches {}. Use the device name instead",
+            spec,
+            by_key.join(", ")
+        ),
+    }
+    devices
+        .iter()
+        .find(|(path, _)| path.contains(spec))
+        .map(|(path, _)| path.as_str())
+        .with_context(|| format!("No device matching '{}' found", spec))
+}
+
+/// Whether `err` is the daemon rejecting a value as unsupported
+/// (`InvalidArgs` or `NotSupported`) rather than a bus failure.
+pub fn is_unsupported(err: &anyhow::Error) -> bool {
+    let rejected = |name: &str| name.ends_with(".InvalidArgs") || name.ends_with(".NotSupported");
+    match err.downcast_ref::<zbus::Error>() {
+        /* Setters returning zbus::Result reply with the generic
+         * org.freedesktop.zbus.Error and put the fdo name in the message. */
+        Some(zbus::Error::MethodError(name, detail, _)) => {
+            rejected(name)
+                || detail
+                    .as_deref()
+                    .and_then(|d| d.split(':').next())
+                    .is_some_and(rejected)
+        }
+        _ => false,
+    }
+}
+
+/// Display string for a DPI read as `(x, y)`: `800 DPI`, or `800x1200 DPI`
+/// for a resolution with separate X/Y axes.
+pub fn format_dpi((x, y): (u32, u32)) -> String {
+    if x == y {
+        format!("{} DPI", x)
+    } else {
+        format!("{}x{} DPI", x, y)
+    }
+}
+
+/// DPI as a percentage of `max` (the device's highest DPI), e.g.
+/// `"50% of 16000 DPI"` or `"25%x50% of 16000 DPI"`.
+pub fn format_dpi_percent((x, y): (u32, u32), max: u32) -> String {
+    let percent = |dpi: u32| (u64::from(dpi) * 100 + u64::from(max) / 2) / u64::from(max.max(1));
+    if x == y {
+        format!("{}% of {} DPI", percent(x), max)
+    } else {
+        format!("{}%x{}% of {} DPI", percent(x), percent(y), max)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_out_of_range_indices_are_rejected() {
+        assert!(check_index("Button", 7, 8).is_ok());
+        let err = |kind, index, count| check_index(kind, index, count).unwrap_err().to_string();
+        assert_eq!(err("Button", 8, 8), "Button 8 out of range (0..8)");
+        assert_eq!(err("Resolution", 5, 5), "Resolution 5 out of range (0..5)");
+        assert_eq!(err("LED", 0, 0), "LED 0 out of range: there are none");
+    }
+
+    #[test]
+    fn test_format_dpi_shows_separate_axes() {
+        assert_eq!(format_dpi((800, 800)), "800 DPI");
+        assert_eq!(format_dpi((800, 1200)), "800x1200 DPI");
+        assert_eq!(format_dpi((1200, 800)), "1200x800 DPI");
+    }
+
+    #[test]
+    fn test_format_dpi_percent_of_max() {
+        assert_eq!(format_dpi_percent((8000, 8000), 16000), "50% of 16000 DPI");
+        assert_eq!(format_dpi_percent((4000, 8000), 16000), "25%x50% of 16000 DPI");
+        assert_eq!(format_dpi_percent((100, 100), 16000), "1% of 16000 DPI");
+    }
+
+    #[test]
+    fn test_stable_key_resolves_back_to_device() {
+        let devices = [
+            (
+                "/org/freedesktop/ratbag1/device/hidraw3".to_string(),
+                "usb:046d:c539:1111aaaa".to_string(),
+            ),
+            (
+                "/org/freedesktop/ratbag1/device/hidraw5".to_string(),
+                "usb:046d:c539:2222bbbb".to_string(),
+            ),
+        ];
+        assert_eq!(
+            match_device("usb:046d:c539:2222bbbb", &devices).unwrap(),
+            "/org/freedesktop/ratbag1/device/hidraw5"
+        );
+        assert_eq!(
+            match_device("hidraw3", &devices).unwrap(),
+            "/org/freedesktop/ratbag1/device/hidraw3"
+        );
+        assert!(match_device("usb:046d:c539:3333cccc", &devices).is_err());
+    }
+
+    #[test]
+    fn test_shared_stable_key_is_ambiguous() {
+        /* Two identical mice without a serial number. */
+        let devices = [
+            ("/org/freedesktop/ratbag1/device/hidraw3".to_string(), "usb:1e7d:2e22".to_string()),
+            ("/org/freedesktop/ratbag1/device/hidraw5".to_string(), "usb:1e7d:2e22".to_string()),
+        ];
+        let err = match_device("usb:1e7d:2e22", &devices).unwrap_err().to_string();
+        assert!(err.contains("ambiguous"), "{err}");
+        assert!(err.contains("device/hidraw3, /org/freedesktop/ratbag1/device/hidraw5"), "{err}");
+        assert_eq!(
+            match_device("hidraw5", &devices).unwrap(),
+            "/org/freedesktop/ratbag1/device/hidraw5"
+        );
+    }
+
+    #[test]
+    fn test_commit_failures_are_listed_per_object() {
+        const DEVICE: &str = "/org/freedesktop/ratbag1/device/hidraw3";
+        let failures = vec![
+            (ObjectPath::try_from(format!("{DEVICE}/p1/l0")).unwrap(), "zone busy"),
+            (ObjectPath::try_from(DEVICE).unwrap(), "SmartShift timed out"),
+        ];
+        let reply = zbus::Message::method_call(DEVICE, "Commit")
+            .unwrap()
+            .build(&(1u32, failures))
+            .unwrap();
+        let err = parse_commit_reply(&reply).unwrap().check(DEVICE).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Commit failed; not written:\n  p1/l0: zone busy\n  device: SmartShift timed out"
+        );
+
+        /* An older daemon replies with the status code only. */
+        let reply = zbus::Message::method_call(DEVICE, "Commit").unwrap().build(&(0u32,)).unwrap();
+        let reply = parse_commit_reply(&reply).unwrap();
+        assert_eq!(reply, CommitReply::default());
+        assert!(reply.check(DEVICE).is_ok());
+    }
+}
@@ -0,0 +1,155 @@
+/* ratbagctl doctor: finds out which process owns org.freedesktop.ratbag1 and whether it is this
+ * Rust ratbagd or libratbag's C ratbagd. Only the Rust daemon has a DaemonVersion property, so
+ * its presence tells the two apart; the owner's process name is shown for confirmation. */
+//! Diagnosis of the daemon behind the bus name, used by `ratbagctl doctor`.
+
+use anyhow::Result;
+use serde_json::json;
+
+use crate::dbus_client::RatbagClient;
+
+/// Which daemon answers on `org.freedesktop.ratbag1`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Implementation {
+    /// This project's daemon (ratbagd-rs).
+    Rust,
+    /// libratbag's C ratbagd, as shipped with Piper.
+    C,
+    /// Something owns the name but does not look like either.
+    Unknown,
+}
+
+/// What could be learned about the owner of the bus name.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Owner {
+    pub unique_name: String,
+    pub pid: Option<u32>,
+    /// Process name from `/proc/<pid>/comm`.
+    pub process: Option<String>,
+    pub daemon_version: Option<String>,
+    pub api_version: Option<i32>,
+}
+
+impl Owner {
+    pub fn implementation(&self) -> Implementation {
+        if self.daemon_version.is_some() {
+            Implementation::Rust
+        } else if self.api_version.is_some() {
+            Implementation::C
+        } else {
+            Implementation::Unknown
+        }
+    }
+}
+
+/// Ask the bus who owns the name and what it reports; `None` when nobody does.
+pub async fn inspect(client: &RatbagClient) -> Result<Option<Owner>> {
+    let Some(unique_name) = client.get_name_owner().await? else {
+        return Ok(None);
+    };
+    let pid = client.get_connection_pid(&unique_name).await.ok();
+    let process = pid.and_then(|pid| {
+        std::fs::read_to_string(format!("/proc/{pid}/comm"))
+            .ok()
+            .map(|comm| comm.trim().to_string())
+    });
+    Ok(Some(Owner {
+        unique_name,
+        pid,
+        process,
+        daemon_version: client.get_daemon_version().await.ok(),
+        api_version: client.get_api_version().await.ok(),
+    }))
+}
+
+/// Human-readable report for `owner`.
+pub fn report_lines(owner: Option<&Owner>) -> Vec<String> {
+    let Some(owner) = owner else {
+        return vec![
+            "org.freedesktop.ratbag1: not owned; no ratbagd is running on this bus.".to_string(),
+        ];
+    };
+    let process = match (&owner.process, owner.pid) {
+        (Some(name), Some(pid)) => format!("{name} (pid {pid})"),
+        (None, Some(pid)) => format!("pid {pid}"),
+        _ => "unknown process".to_string(),
+    };
+    let mut lines = vec![format!(
+        "org.freedesktop.ratbag1: owned by {} ({})",
+        owner.unique_name, process
+    )];
+    let api = owner
+        .api_version
+        .map_or_else(|| "unknown".to_string(), |v| v.to_string());
+    match owner.implementation() {
+        Implementation::Rust => {
+            let version = owner.daemon_version.as_deref().unwrap_or_default();
+            lines.push(format!("Implementation: ratbagd-rs {version}, API version {api}"));
+        }
+        Implementation::C => {
+            lines.push(format!("Implementation: libratbag C ratbagd, API version {api}"));
+            lines.push(
+                "The C daemon holds the name, so ratbagd-rs cannot start. Stop it first, \
+                 e.g. `systemctl stop ratbagd`, or uninstall libratbag's ratbagd."
+                    .to_string(),
+            );
+        }
+        Implementation::Unknown => {
+            lines.push("Implementation: unknown; the owner does not answer the Manager interface.".to_string());
+        }
+    }
+    lines
+}
+
+/// JSON form of the report.
+pub fn report_json(owner: Option<&Owner>) -> serde_json::Value {
+    match owner {
+        None => json!({ "owner": null }),
+        Some(owner) => json!({
+            "owner": owner.unique_name,
+            "pid": owner.pid,
+            "process": owner.process,
+            "implementation": match owner.implementation() {
+                Implementation::Rust => "ratbagd-rs",
+                Implementation::C => "libratbag",
+                Implementation::Unknown => "unknown",
+            },
+            "daemon_version": owner.daemon_version,
+            "api_version": owner.api_version,
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn owner(daemon_version: Option<&str>, api_version: Option<i32>) -> Owner {
+        Owner {
+            unique_name: ":1.42".to_string(),
+            pid: Some(812),
+            process: Some("ratbagd".to_string()),
+            daemon_version: daemon_version.map(str::to_string),
+            api_version,
+        }
+    }
+
+    #[test]
+    fn test_doctor_tells_implementations_apart() {
+        assert_eq!(owner(Some("1.0.0"), Some(2)).implementation(), Implementation::Rust);
+        assert_eq!(owner(None, Some(2)).implementation(), Implementation::C);
+        assert_eq!(owner(None, None).implementation(), Implementation::Unknown);
+
+        let lines = report_lines(Some(&owner(None, Some(2))));
+        assert_eq!(lines[0], "org.freedesktop.ratbag1: owned by :1.42 (ratbagd (pid 812))");
+        assert_eq!(lines[1], "Implementation: libratbag C ratbagd, API version 2");
+        assert!(lines[2].contains("systemctl stop ratbagd"));
+
+        let lines = report_lines(Some(&owner(Some("1.0.0"), Some(2))));
+        assert_eq!(lines[1], "Implementation: ratbagd-rs 1.0.0, API version 2");
+        assert_eq!(lines.len(), 2);
+
+        assert!(report_lines(None)[0].contains("not owned"));
+        assert_eq!(report_json(Some(&owner(None, Some(2))))["implementation"], "libratbag");
+    }
+}
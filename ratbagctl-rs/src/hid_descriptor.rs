@@ -0,0 +1,142 @@
+/* ratbagctl test hid-descriptor: decodes a raw HID report descriptor into one line per item, with
+ * the item's bytes, its name and its value, indented by collection depth. Only names defined by
+ * the HID specification are known; usages are shown as numbers. */
+//! Readable dump of HID report descriptors.
+
+/// One line per descriptor item: raw bytes, then the item indented by
+/// collection depth, e.g. `05 01           Usage Page (0x01)`.
+pub fn describe(desc: &[u8]) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut depth = 0usize;
+    let mut pos = 0;
+    while pos < desc.len() {
+        let prefix = desc[pos];
+
+        /* Long item: 0xfe, data size, tag, data. */
+        if prefix == 0xfe {
+            let size = desc.get(pos + 1).copied().map_or(0, usize::from);
+            let end = (pos + 3 + size).min(desc.len());
+            lines.push(line(&desc[pos..end], depth, "Long Item".to_string()));
+            pos = end;
+            continue;
+        }
+
+        let size = match prefix & 0x03 {
+            3 => 4,
+            n => usize::from(n),
+        };
+        let end = pos + 1 + size;
+        if end > desc.len() {
+            lines.push(line(&desc[pos..], 0, "(truncated item)".to_string()));
+            break;
+        }
+        let data = &desc[pos + 1..end];
+        let value = data
+            .iter()
+            .rev()
+            .fold(0u32, |acc, &b| (acc << 8) | u32::from(b));
+        /* Sign-extend from the item's own width. */
+        let signed = match size {
+            1 => i32::from(value as u8 as i8),
+            2 => i32::from(value as u16 as i16),
+            _ => value as i32,
+        };
+
+        let tag = prefix & 0xfc;
+        if tag == 0xc0 {
+            depth = depth.saturating_sub(1);
+        }
+        let text = match tag {
+            0x80 => format!("Input (0x{value:02x})"),
+            0x90 => format!("Output (0x{value:02x})"),
+            0xb0 => format!("Feature (0x{value:02x})"),
+            0xa0 => format!("Collection ({})", collection_name(value)),
+            0xc0 => "End Collection".to_string(),
+            0x04 => format!("Usage Page (0x{value:02x})"),
+            0x14 => format!("Logical Minimum ({signed})"),
+            0x24 => format!("Logical Maximum ({signed})"),
+            0x34 => format!("Physical Minimum ({signed})"),
+            0x44 => format!("Physical Maximum ({signed})"),
+            0x54 => format!("Unit Exponent ({signed})"),
+            0x64 => format!("Unit (0x{value:02x})"),
+            0x74 => format!("Report Size ({value})"),
+            0x84 => format!("Report ID ({value})"),
+            0x94 => format!("Report Count ({value})"),
+            0xa4 => "Push".to_string(),
+            0xb4 => "Pop".to_string(),
+            0x08 => format!("Usage (0x{value:02x})"),
+            0x18 => format!("Usage Minimum (0x{value:02x})"),
+            0x28 => format!("Usage Maximum (0x{value:02x})"),
+            0x38 => format!("Designator Index ({value})"),
+            0x48 => format!("Designator Minimum ({value})"),
+            0x58 => format!("Designator Maximum ({value})"),
+            0x78 => format!("String Index ({value})"),
+            0x88 => format!("String Minimum ({value})"),
+            0x98 => format!("String Maximum ({value})"),
+            0xa8 => format!("Delimiter ({value})"),
+            _ => format!("Unknown item 0x{prefix:02x} ({value})"),
+        };
+        lines.push(line(&desc[pos..end], depth, text));
+        if tag == 0xa0 {
+            depth += 1;
+        }
+        pos = end;
+    }
+    lines
+}
+
+fn collection_name(kind: u32) -> String {
+    match kind {
+        0x00 => "Physical".to_string(),
+        0x01 => "Application".to_string(),
+        0x02 => "Logical".to_string(),
+        0x03 => "Report".to_string(),
+        0x04 => "Named Array".to_string(),
+        0x05 => "Usage Switch".to_string(),
+        0x06 => "Usage Modifier".to_string(),
+        n => format!("0x{n:02x}"),
+    }
+}
+
+fn line(bytes: &[u8], depth: usize, text: String) -> String {
+    let hex: Vec<String> = bytes.iter().map(|b| format!("{b:02x}")).collect();
+    format!("{:<15} {}{}", hex.join(" "), "  ".repeat(depth), text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_describe_mouse_descriptor() {
+        let desc = [
+            0x05, 0x01, /* Usage Page (Generic Desktop) */
+            0x09, 0x02, /* Usage (Mouse) */
+            0xa1, 0x01, /* Collection (Application) */
+            0x85, 0x11, /* Report ID (17) */
+            0x16, 0x01, 0x80, /* Logical Minimum (-32767) */
+            0x81, 0x06, /* Input (Data,Var,Rel) */
+            0xc0, /* End Collection */
+        ];
+        assert_eq!(
+            describe(&desc),
+            [
+                "05 01           Usage Page (0x01)",
+                "09 02           Usage (0x02)",
+                "a1 01           Collection (Application)",
+                "85 11             Report ID (17)",
+                "16 01 80          Logical Minimum (-32767)",
+                "81 06             Input (0x06)",
+                "c0              End Collection",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_describe_truncated_item() {
+        assert_eq!(
+            describe(&[0x09, 0x02, 0x26, 0xff]),
+            ["09 02           Usage (0x02)", "26 ff           (truncated item)"]
+        );
+    }
+}
@@ -1,24 +1,335 @@
 /* ratbagctl CLI: clap-driven client that talks to ratbagd over DBus to list devices, inspect and
  * modify profiles/resolutions/buttons/LEDs, and exercise dev-hook test devices. */
+mod backup;
+mod completion;
 mod dbus_client;
+mod doctor;
+mod hid_descriptor;
+mod monitor;
+mod names;
+mod pending;
+mod repl;
+mod selftest;
+mod watch;
+
+use std::cell::RefCell;
+use std::fmt;
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
 
 use anyhow::{Context, Result};
-use clap::{Parser, Subcommand};
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
+use serde::Deserialize;
+use serde_json::json;
+use tokio_stream::StreamExt;
+
+use dbus_client::{ButtonMapping, RatbagClient};
 
-use dbus_client::RatbagClient;
+/// `println!` into the command's output sink (stdout or `--output`).
+macro_rules! outln {
+    ($ctx:expr, $($arg:tt)*) => {
+        $ctx.out.line(format_args!($($arg)*))?
+    };
+}
 
 /// ratbagctl — configure gaming mice via the ratbagd DBus daemon.
+///
+/// Defaults for the global options can be set in the user config file
+/// (see `ratbagctl config-path`); flags given on the command line win.
 #[derive(Parser)]
 #[command(name = "ratbagctl", version, about)]
 struct Cli {
+    /// DBus bus the daemon listens on (default: system).
+    #[arg(long, global = true, value_enum)]
+    bus: Option<BusKind>,
+
+    /// Stage writes on the daemon without committing them to hardware.
+    #[arg(long, global = true, overrides_with = "commit")]
+    no_commit: bool,
+
+    /// Commit after every write, overriding `no-commit` from the config file.
+    #[arg(long, global = true, overrides_with = "no_commit")]
+    commit: bool,
+
+    /// Stage writes and remember the device; `ratbagctl flush` commits them.
+    #[arg(long, global = true, conflicts_with_all = ["no_commit", "commit"])]
+    batch: bool,
+
+    /// Output format (default: text).
+    #[arg(long, global = true, value_enum)]
+    format: Option<OutputFormat>,
+
+    /// Shorthand for `--format json`.
+    #[arg(long, global = true, conflicts_with = "format")]
+    json: bool,
+
+    /// Write command output to this file instead of stdout.
+    #[arg(long, global = true, value_name = "FILE")]
+    output: Option<PathBuf>,
+
+    /// Colorize status markers such as [active] (default: auto).
+    #[arg(long, global = true, value_enum)]
+    color: Option<ColorChoice>,
+
+    /// Read defaults from this file instead of the user config file.
+    #[arg(long, global = true, value_name = "FILE")]
+    config: Option<PathBuf>,
+
     #[command(subcommand)]
     command: Commands,
 }
 
+/// Which DBus bus to connect to.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum BusKind {
+    #[default]
+    System,
+    Session,
+}
+
+/// How read commands render their results.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum OutputFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+/// When to emit ANSI color codes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum ColorChoice {
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+/// Direction of moving LED effects, as in `Led.EffectDirection`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum LedDirection {
+    /// Clockwise.
+    Cw,
+    /// Counterclockwise.
+    Ccw,
+}
+
+impl LedDirection {
+    fn from_dbus(value: u32) -> Option<Self> {
+        match value {
+            0 => Some(Self::Cw),
+            1 => Some(Self::Ccw),
+            _ => None,
+        }
+    }
+
+    fn to_dbus(self) -> u32 {
+        match self {
+            Self::Cw => 0,
+            Self::Ccw => 1,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Cw => "clockwise",
+            Self::Ccw => "counterclockwise",
+        }
+    }
+}
+
+/// Device classification as reported by `Device.DeviceType`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum DeviceKind {
+    Mouse,
+    Keyboard,
+    Other,
+}
+
+impl DeviceKind {
+    /// Map the DBus `DeviceType` value; 0 (unspecified) matches no kind.
+    fn from_dbus(value: u32) -> Option<Self> {
+        match value {
+            1 => Some(Self::Other),
+            2 => Some(Self::Mouse),
+            3 => Some(Self::Keyboard),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Mouse => "mouse",
+            Self::Keyboard => "keyboard",
+            Self::Other => "other",
+        }
+    }
+}
+
+/// User defaults read from `config.toml`.
+///
+/// Every key is optional; unknown keys are rejected so that typos are
+/// reported instead of silently ignored.
+///
+/// ```toml
+/// bus = "session"
+/// no-commit = true
+/// format = "json"
+/// color = "never"
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+struct Config {
+    bus: Option<BusKind>,
+    no_commit: Option<bool>,
+    format: Option<OutputFormat>,
+    color: Option<ColorChoice>,
+}
+
+/// Effective settings after merging the config file with command-line flags.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Options {
+    bus: BusKind,
+    no_commit: bool,
+    batch: bool,
+    format: OutputFormat,
+    color: ColorChoice,
+    /* Output goes to a file (`--output`), so never colorize. */
+    to_file: bool,
+    /* Running inside `interactive`, which reminds about staged changes
+     * itself when the shell exits. */
+    interactive: bool,
+}
+
+impl Options {
+    /// Merge CLI flags over config-file defaults over built-in defaults.
+    fn merge(cli: &Cli, config: &Config) -> Self {
+        let no_commit = if cli.commit || cli.batch {
+            false
+        } else if cli.no_commit {
+            true
+        } else {
+            config.no_commit.unwrap_or(false)
+        };
+        Self {
+            bus: cli.bus.or(config.bus).unwrap_or_default(),
+            no_commit,
+            batch: cli.batch,
+            format: if cli.json {
+                OutputFormat::Json
+            } else {
+                cli.format.or(config.format).unwrap_or_default()
+            },
+            color: cli.color.or(config.color).unwrap_or_default(),
+            to_file: cli.output.is_some(),
+            interactive: false,
+        }
+    }
+
+    fn use_color(&self) -> bool {
+        match self.color {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => !self.to_file && std::io::stdout().is_terminal(),
+        }
+    }
+
+    /// Render a status marker like `[active]`, bold green when color is on.
+    fn marker(&self, text: &str) -> String {
+        if self.use_color() {
+            format!("\x1b[1;32m{}\x1b[0m", text)
+        } else {
+            text.to_string()
+        }
+    }
+}
+
+/// Resolve the config file path: an explicit `--config`, else
+/// `$XDG_CONFIG_HOME/ratbagctl/config.toml`, else
+/// `$HOME/.config/ratbagctl/config.toml`.
+fn config_path(explicit: Option<&Path>) -> Option<PathBuf> {
+    if let Some(path) = explicit {
+        return Some(path.to_path_buf());
+    }
+    let base = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|v| !v.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(base.join("ratbagctl").join("config.toml"))
+}
+
+/// Load the config file at `path`.  A missing default file is not an
+/// error; a missing file passed explicitly via `--config` is.
+fn load_config(path: &Path, explicit: bool) -> Result<Config> {
+    match std::fs::read_to_string(path) {
+        Ok(text) => toml::from_str(&text)
+            .with_context(|| format!("Invalid config file '{}'", path.display())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound && !explicit => Ok(Config::default()),
+        Err(e) => Err(e).with_context(|| format!("Cannot read config file '{}'", path.display())),
+    }
+}
+
+/// Everything a command needs: the daemon connection and the resolved options.
+struct Ctx {
+    client: RatbagClient,
+    opts: Options,
+    out: Output,
+}
+
+/// Where read commands send their results: stdout, or the `--output` file.
+struct Output {
+    sink: RefCell<Box<dyn Write>>,
+}
+
+impl Output {
+    fn new(sink: Box<dyn Write>) -> Self {
+        Self {
+            sink: RefCell::new(sink),
+        }
+    }
+
+    /// Stdout when `path` is `None`, else a freshly truncated file.
+    fn open(path: Option<&Path>) -> Result<Self> {
+        let sink: Box<dyn Write> = match path {
+            Some(p) => Box::new(std::io::BufWriter::new(
+                std::fs::File::create(p)
+                    .with_context(|| format!("Cannot create output file {}", p.display()))?,
+            )),
+            None => Box::new(std::io::stdout()),
+        };
+        Ok(Self::new(sink))
+    }
+
+    fn line(&self, args: fmt::Arguments<'_>) -> Result<()> {
+        writeln!(self.sink.borrow_mut(), "{}", args)?;
+        Ok(())
+    }
+
+    /// Write a JSON document for `--format json`.
+    fn json(&self, value: &serde_json::Value) -> Result<()> {
+        self.line(format_args!("{}", serde_json::to_string_pretty(value)?))
+    }
+
+    fn flush(&self) -> Result<()> {
+        self.sink.borrow_mut().flush()?;
+        Ok(())
+    }
+}
+
 #[derive(Subcommand)]
 enum Commands {
     /// List connected devices.
-    List,
+    List {
+        /// Only show devices of this type.
+        #[arg(long = "type", value_enum)]
+        device_type: Option<DeviceKind>,
+        /// Also print each device's stable key, usable as `<device>` in
+        /// scripts across reboots.
+        #[arg(long)]
+        show_key: bool,
+    },
 
     /// Show detailed information about a device.
     Info {
@@ -26,10 +337,79 @@ enum Commands {
         device: String,
     },
 
+    /// Dump every profile, resolution, button and LED of a device as JSON.
+    Export {
+        /// Device index or sysname.
+        device: String,
+        /// File to write; stdout (or `--output`) when omitted.
+        #[arg(id = "file", value_name = "FILE")]
+        output: Option<PathBuf>,
+    },
+
+    /// Apply a configuration written by `export`, then commit once.
+    Import {
+        /// Device index or sysname.
+        device: String,
+        /// JSON file from `ratbagctl export`.
+        input: PathBuf,
+        /// List the changes without writing anything.
+        #[arg(long)]
+        dry_run: bool,
+    },
+
     /// Commit pending changes to hardware.
     Commit {
         /// Device index or sysname.
         device: String,
+        /// Write only this profile; the others keep their pending changes.
+        #[arg(long)]
+        profile: Option<u32>,
+    },
+
+    /// Rewrite every profile stored on the device, e.g. after `info` reports
+    /// corrupted profiles.
+    Repair {
+        /// Device index or sysname.
+        device: String,
+    },
+
+    /// Show which profiles of a device have changes that are not committed yet.
+    Status {
+        /// Device index or sysname.
+        device: String,
+    },
+
+    /// Commit the devices written with `--batch` since the last flush.
+    Flush {
+        /// Only flush this device (index or sysname).
+        device: Option<String>,
+    },
+
+    /// Show or set the SmartShift threshold of the scroll wheel.
+    #[command(name = "smart-shift")]
+    SmartShift {
+        /// Device index or sysname.
+        device: String,
+        /// Threshold at which the ratchet releases into free spin, 1-255
+        /// (255 keeps the ratchet engaged). Omit to read the current value.
+        #[arg(value_parser = clap::value_parser!(u32).range(1..=255))]
+        threshold: Option<u32>,
+    },
+
+    /// Show the battery level and whether the device is charging.
+    Battery {
+        /// Device index or sysname.
+        device: String,
+    },
+
+    /// Show or set how many idle minutes pass before the device sleeps.
+    #[command(name = "sleep-timeout")]
+    SleepTimeout {
+        /// Device index or sysname.
+        device: String,
+        /// Minutes of inactivity before sleep, within the range the device
+        /// reports. Omit to read the current value.
+        minutes: Option<u32>,
     },
 
     /// Profile commands.
@@ -51,6 +431,100 @@ enum Commands {
     /// Dev-hooks test commands (requires daemon built with dev-hooks).
     #[command(subcommand)]
     Test(TestCmd),
+
+    /// Print the path of the user config file.
+    #[command(name = "config-path")]
+    ConfigPath,
+
+    /// Show the ratbagctl and daemon versions, for bug reports.
+    Version,
+
+    /// Show which features a driver can write, without any device attached.
+    DriverCaps {
+        /// Driver name as used by `Driver=` in .device files (e.g. asus, hidpp20).
+        driver: String,
+    },
+
+    /// Stop the daemon cleanly (root or the daemon's own user only).
+    Quit,
+
+    /// Report which daemon owns the ratbag bus name: ratbagd-rs or libratbag's C ratbagd.
+    Doctor,
+
+    /// Wait until a device has finished loading its profiles.
+    Wait {
+        /// Give up after this many seconds.
+        #[arg(long, default_value_t = 10)]
+        timeout: u64,
+    },
+
+    /// Print property changes of a device as they happen, until interrupted.
+    ///
+    /// Changes are picked up from the daemon's PropertiesChanged signals.
+    /// Changes made on the mouse itself (e.g. a DPI button) are only
+    /// signalled by drivers that receive hardware notifications; use
+    /// --poll-interval to catch them on other devices as well.
+    Watch {
+        /// Device index or sysname.
+        device: String,
+        /// Also re-read all properties every this many seconds.
+        #[arg(long, value_name = "SECS", value_parser = clap::value_parser!(u64).range(1..))]
+        poll_interval: Option<u64>,
+    },
+
+    /// Print the daemon's signals as they arrive, with a timestamp, until
+    /// interrupted.
+    ///
+    /// Every property in a PropertiesChanged signal is printed with the
+    /// value the signal carries, along with the manager's signals, such as
+    /// its `Devices` list changing when a mouse is plugged in or removed.
+    /// Times are UTC.
+    Monitor {
+        /// Only show signals of this device (index or sysname), plus the
+        /// manager's.
+        device: Option<String>,
+    },
+
+    /// Open a shell for one device that runs commands over a single
+    /// connection.
+    ///
+    /// Commands are typed without the `<device>` argument.  Writes are only
+    /// staged; run `commit` in the shell to write them to the hardware.
+    Interactive {
+        /// Device index or sysname.
+        device: String,
+    },
+
+    /// Write a device's current settings back unchanged and check they round-trip.
+    Selftest {
+        /// Device index or sysname.
+        device: String,
+        /// Confirm that the device may be written to and committed.
+        #[arg(long)]
+        yes: bool,
+    },
+
+    /// Print a shell completion script.
+    ///
+    /// Device and profile arguments are completed from the running daemon,
+    /// e.g. `ratbagctl completions bash > /etc/bash_completion.d/ratbagctl`.
+    Completions {
+        shell: Shell,
+    },
+
+    /// Completion helper called by the completion script.
+    #[command(name = "__complete", hide = true)]
+    Complete {
+        /// The command line after `ratbagctl`, without the word being completed.
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        words: Vec<String>,
+    },
+}
+
+/// Shells `ratbagctl completions` can generate a script for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Shell {
+    Bash,
 }
 
 #[derive(Subcommand)]
@@ -98,6 +572,8 @@ enum ProfileCmd {
         profile: u32,
     },
     /// Set the report rate for a profile.
+    ///
+    /// On devices with a single device-wide rate this changes every profile.
     Rate {
         /// Device index or sysname.
         device: String,
@@ -106,6 +582,19 @@ enum ProfileCmd {
         /// Report rate in Hz.
         rate: u32,
     },
+    /// Copy the resolutions, buttons and LEDs of one profile onto another.
+    ///
+    /// The destination keeps its name, report rate and active/enabled
+    /// state.  When the profiles differ in size only the common items are
+    /// copied.
+    Copy {
+        /// Device index or sysname.
+        device: String,
+        /// Profile to copy from.
+        src: u32,
+        /// Profile to copy onto.
+        dst: u32,
+    },
     /// Get or set angle snapping (on/off).
     #[command(name = "angle-snapping")]
     AngleSnapping {
@@ -125,6 +614,15 @@ enum ProfileCmd {
         /// New debounce time in ms (omit to read current + supported values).
         ms: Option<i32>,
     },
+    /// Get or set the lift-off distance level (1 = closest to the surface).
+    Lod {
+        /// Device index or sysname.
+        device: String,
+        /// Profile index.
+        profile: u32,
+        /// New level (omit to read current + supported levels).
+        level: Option<u32>,
+    },
 }
 
 #[derive(Subcommand)]
@@ -135,6 +633,21 @@ enum ResolutionCmd {
         device: String,
         /// Profile index.
         profile: u32,
+        /// Show DPI as a percentage of the device's highest DPI.
+        #[arg(long)]
+        percent: bool,
+    },
+    /// Show details for a single resolution slot.
+    Info {
+        /// Device index or sysname.
+        device: String,
+        /// Profile index.
+        profile: u32,
+        /// Resolution index.
+        resolution: u32,
+        /// Show DPI as a percentage of the device's highest DPI.
+        #[arg(long)]
+        percent: bool,
     },
     /// Get or set DPI for a resolution.
     Dpi {
@@ -144,8 +657,33 @@ enum ResolutionCmd {
         profile: u32,
         /// Resolution index.
         resolution: u32,
-        /// New DPI value (omit to read current).
+        /// New DPI value (omit to read current); the X axis when a Y
+        /// value follows.
         dpi: Option<u32>,
+        /// Separate Y-axis DPI, for devices with separate X/Y resolution.
+        #[arg(requires = "dpi")]
+        y: Option<u32>,
+        /// When reading, show DPI as a percentage of the device's highest DPI.
+        #[arg(long, conflicts_with = "dpi")]
+        percent: bool,
+    },
+    /// Raise DPI to the next value the device supports.
+    Up {
+        /// Device index or sysname.
+        device: String,
+        /// Profile index.
+        profile: u32,
+        /// Resolution index.
+        resolution: u32,
+    },
+    /// Lower DPI to the previous value the device supports.
+    Down {
+        /// Device index or sysname.
+        device: String,
+        /// Profile index.
+        profile: u32,
+        /// Resolution index.
+        resolution: u32,
     },
     /// Set the active resolution.
     Active {
@@ -193,6 +731,9 @@ enum ButtonCmd {
         device: String,
         /// Profile index.
         profile: u32,
+        /// Show key names, special actions and button names instead of raw values.
+        #[arg(long)]
+        resolved: bool,
     },
     /// Get current button mapping.
     Get {
@@ -252,6 +793,11 @@ enum ButtonCmd {
         profile: u32,
         /// Button index.
         button: u32,
+        /// Play the macro N times, or "hold" to repeat while the button is held.
+        ///
+        /// Devices without repeat control play the macro once.
+        #[arg(long, visible_alias = "count", value_name = "N|hold")]
+        repeat: Option<String>,
         /// Macro events as "KEYCODE:DIR KEYCODE:DIR …".
         events: Vec<String>,
     },
@@ -264,6 +810,15 @@ enum ButtonCmd {
         /// Button index.
         button: u32,
     },
+    /// Restore a button's factory mapping, leaving the rest of the profile alone.
+    Reset {
+        /// Device index or sysname.
+        device: String,
+        /// Profile index.
+        profile: u32,
+        /// Button index.
+        button: u32,
+    },
 }
 
 #[derive(Subcommand)]
@@ -295,6 +850,14 @@ enum LedCmd {
         /// Mode: off, solid, cycle, wave, starlight, breathing, tricolor.
         mode: String,
     },
+    /// Turn off every LED of a profile, committing once.
+    #[command(name = "off-all")]
+    OffAll {
+        /// Device index or sysname.
+        device: String,
+        /// Profile index.
+        profile: u32,
+    },
     /// Set LED primary color (hex RGB, e.g. ff0000).
     Color {
         /// Device index or sysname.
@@ -304,9 +867,10 @@ enum LedCmd {
         /// LED index.
         led: u32,
         /// Hex RGB color (e.g. ff0000 for red).
+        #[arg(id = "rgb", value_name = "COLOR")]
         color: String,
     },
-    /// Set LED secondary color (for multi-color effects like Starlight).
+    /// Set LED secondary color (Starlight stars, or the second breathing color).
     #[command(name = "secondary-color")]
     SecondaryColor {
         /// Device index or sysname.
@@ -316,6 +880,7 @@ enum LedCmd {
         /// LED index.
         led: u32,
         /// Hex RGB color.
+        #[arg(id = "rgb", value_name = "COLOR")]
         color: String,
     },
     /// Set LED tertiary color (for 3-zone effects like TriColor).
@@ -328,6 +893,7 @@ enum LedCmd {
         /// LED index.
         led: u32,
         /// Hex RGB color.
+        #[arg(id = "rgb", value_name = "COLOR")]
         color: String,
     },
     /// Set LED brightness (0-255).
@@ -352,6 +918,29 @@ enum LedCmd {
         /// Duration in milliseconds (0-10000).
         ms: u32,
     },
+    /// Set the direction of moving effects such as wave.
+    Direction {
+        /// Device index or sysname.
+        device: String,
+        /// Profile index.
+        profile: u32,
+        /// LED index.
+        led: u32,
+        /// cw (clockwise) or ccw (counterclockwise).
+        direction: LedDirection,
+    },
+    /// Show a solid color on an LED of the active profile right away,
+    /// without changing the profile.  The next commit restores the LED.
+    #[command(name = "live-color")]
+    LiveColor {
+        /// Device index or sysname.
+        device: String,
+        /// LED index.
+        led: u32,
+        /// Color as RRGGBB hex (e.g. ff0000).
+        #[arg(id = "rgb", value_name = "COLOR")]
+        color: String,
+    },
 }
 
 #[derive(Subcommand)]
@@ -364,172 +953,386 @@ enum TestCmd {
     },
     /// Remove all test devices.
     Reset,
+    /// Print a device's HID report descriptor, one item per line.
+    #[command(name = "hid-descriptor")]
+    HidDescriptor {
+        /// Device index or sysname.
+        device: String,
+    },
+    /// Send a raw HID report and print the reply (dev-hooks daemons only).
+    ///
+    /// The driver does not see the exchange, so a report that changes
+    /// settings leaves the daemon's view of the device stale.
+    Raw {
+        /// Device index or sysname.
+        device: String,
+        /// Report bytes in hex, report ID first (e.g. "10 ff 00 1a").
+        #[arg(required = true, num_args = 1..)]
+        hex_bytes: Vec<String>,
+        /// Set a feature report and read it back, instead of writing an
+        /// output report and waiting for the next input report.
+        #[arg(long)]
+        feature: bool,
+    },
+}
+
+/// Environment variable naming the device for commands typed without
+/// their `<device>` argument.
+const DEVICE_ENV: &str = "RATBAGCTL_DEVICE";
+
+/* The command line with `device` inserted where the command expects its
+ * `<device>` argument, as `interactive` does. */
+fn args_with_device(args: &[String], device: &str) -> Vec<String> {
+    let Some((program, words)) = args.split_first() else {
+        return Vec::new();
+    };
+    std::iter::once(program.clone())
+        .chain(repl::insert_device(Cli::command(), words.to_vec(), device))
+        .collect()
+}
+
+/* Parse `args`.  A command that lacks a required argument is taken to
+ * be missing its `<device>` (the arguments after it shift left) and is
+ * retried with `env_device` inserted, the value of DEVICE_ENV.  Without
+ * the variable it is retried as if `<device>` were 0, and the usage
+ * error is returned alongside, to be reported unless the daemon has
+ * exactly one device. */
+fn parse_args(args: &[String], env_device: Option<&str>) -> Result<(Cli, Option<clap::Error>), clap::Error> {
+    let usage = match Cli::try_parse_from(args) {
+        Ok(cli) => return Ok((cli, None)),
+        Err(e) if e.kind() == clap::error::ErrorKind::MissingRequiredArgument => e,
+        Err(e) => return Err(e),
+    };
+    let retried = Cli::try_parse_from(args_with_device(args, env_device.unwrap_or("0")));
+    match (retried, env_device) {
+        (Ok(cli), Some(_)) => Ok((cli, None)),
+        (Ok(cli), None) => Ok((cli, Some(usage))),
+        (Err(_), _) => Err(usage),
+    }
+}
+
+fn parse_cli() -> (Cli, Option<clap::Error>) {
+    let args: Vec<String> = std::env::args_os().map(|a| a.to_string_lossy().into_owned()).collect();
+    let env_device = std::env::var(DEVICE_ENV).ok().filter(|d| !d.is_empty());
+    parse_args(&args, env_device.as_deref()).unwrap_or_else(|e| e.exit())
 }
 
 #[tokio::main(flavor = "current_thread")]
 async fn main() -> Result<()> {
-    let cli = Cli::parse();
-    let client = RatbagClient::connect()
-        .await
-        .context("Failed to connect to ratbagd on org.freedesktop.ratbag1")?;
+    let (cli, implied_device) = parse_cli();
+    let path = config_path(cli.config.as_deref());
+
+    /* config-path must work without a daemon and with a broken config. */
+    if let Commands::ConfigPath = cli.command {
+        let path = path.context("Cannot determine config path: neither XDG_CONFIG_HOME nor HOME is set")?;
+        println!("{}", path.display());
+        return Ok(());
+    }
+    if let Commands::Completions { shell } = cli.command {
+        match shell {
+            Shell::Bash => print!("{}", completion::BASH_SCRIPT),
+        }
+        return Ok(());
+    }
+    /* Completion must stay silent: no candidates rather than an error. */
+    if let Commands::Complete { words } = &cli.command {
+        let config = path
+            .as_deref()
+            .and_then(|p| load_config(p, false).ok())
+            .unwrap_or_default();
+        let opts = Options::merge(&cli, &config);
+        let candidates = match completion::classify(Cli::command(), words) {
+            /* Subcommand names do not need the daemon. */
+            completion::Slot::Words(words) => words,
+            slot => match RatbagClient::connect(opts.bus == BusKind::Session).await {
+                Ok(client) => completion::candidates(&client, slot).await.unwrap_or_default(),
+                Err(_) => Vec::new(),
+            },
+        };
+        for candidate in candidates {
+            println!("{}", candidate);
+        }
+        return Ok(());
+    }
+
+    let config = match &path {
+        Some(p) => load_config(p, cli.config.is_some())?,
+        None => Config::default(),
+    };
+    let opts = Options::merge(&cli, &config);
+    let client = match RatbagClient::connect(opts.bus == BusKind::Session).await {
+        Ok(client) => client,
+        /* version still reports the client when there is no bus at all. */
+        Err(_) if matches!(cli.command, Commands::Version) => {
+            for line in version_lines(None) {
+                println!("{}", line);
+            }
+            return Ok(());
+        }
+        Err(e) => return Err(e.context("Failed to connect to ratbagd on org.freedesktop.ratbag1")),
+    };
+    if let Some(usage) = implied_device
+        && !client.list_devices().await.is_ok_and(|devices| devices.len() == 1)
+    {
+        usage.exit();
+    }
+    let out = Output::open(cli.output.as_deref())?;
+    let ctx = Ctx { client, opts, out };
 
-    match cli.command {
-        Commands::List => cmd_list(&client).await,
-        Commands::Info { device } => cmd_info(&client, &device).await,
-        Commands::Commit { device } => cmd_commit(&client, &device).await,
+    let result = run(&ctx, cli.command).await;
+    ctx.out.flush()?;
+    result
+}
+
+/// Run one parsed command against the daemon.
+async fn run(ctx: &Ctx, command: Commands) -> Result<()> {
+    match command {
+        Commands::List {
+            device_type,
+            show_key,
+        } => cmd_list(ctx, device_type, show_key).await,
+        Commands::Info { device } => cmd_info(ctx, &device).await,
+        Commands::Export { device, output } => cmd_export(ctx, &device, output.as_deref()).await,
+        Commands::Import {
+            device,
+            input,
+            dry_run,
+        } => cmd_import(ctx, &device, &input, dry_run).await,
+        Commands::Commit { device, profile } => cmd_commit(ctx, &device, profile).await,
+        Commands::Flush { device } => cmd_flush(ctx, device.as_deref()).await,
+        Commands::Repair { device } => cmd_repair(ctx, &device).await,
+        Commands::SmartShift { device, threshold } => {
+            cmd_smart_shift(ctx, &device, threshold).await
+        }
+        Commands::Battery { device } => cmd_battery(ctx, &device).await,
+        Commands::Status { device } => cmd_status(ctx, &device).await,
+        Commands::SleepTimeout { device, minutes } => {
+            cmd_sleep_timeout(ctx, &device, minutes).await
+        }
         Commands::Profile(sub) => match sub {
-            ProfileCmd::List { device } => cmd_profile_list(&client, &device).await,
+            ProfileCmd::List { device } => cmd_profile_list(ctx, &device).await,
             ProfileCmd::Info { device, profile } => {
-                cmd_profile_info(&client, &device, profile).await
+                cmd_profile_info(ctx, &device, profile).await
             }
             ProfileCmd::Active { device, profile } => {
-                cmd_profile_active(&client, &device, profile).await
+                cmd_profile_active(ctx, &device, profile).await
             }
             ProfileCmd::Name {
                 device,
                 profile,
                 name,
-            } => cmd_profile_name(&client, &device, profile, name).await,
+            } => cmd_profile_name(ctx, &device, profile, name).await,
             ProfileCmd::Enable { device, profile } => {
-                cmd_profile_enable_disable(&client, &device, profile, false).await
+                cmd_profile_enable_disable(ctx, &device, profile, false).await
             }
             ProfileCmd::Disable { device, profile } => {
-                cmd_profile_enable_disable(&client, &device, profile, true).await
+                cmd_profile_enable_disable(ctx, &device, profile, true).await
             }
             ProfileCmd::Rate {
                 device,
                 profile,
                 rate,
-            } => cmd_profile_rate(&client, &device, profile, rate).await,
+            } => cmd_profile_rate(ctx, &device, profile, rate).await,
+            ProfileCmd::Copy { device, src, dst } => {
+                cmd_profile_copy(ctx, &device, src, dst).await
+            }
             ProfileCmd::AngleSnapping {
                 device,
                 profile,
                 value,
-            } => cmd_profile_angle_snapping(&client, &device, profile, value).await,
+            } => cmd_profile_angle_snapping(ctx, &device, profile, value).await,
             ProfileCmd::Debounce {
                 device,
                 profile,
                 ms,
-            } => cmd_profile_debounce(&client, &device, profile, ms).await,
+            } => cmd_profile_debounce(ctx, &device, profile, ms).await,
+            ProfileCmd::Lod {
+                device,
+                profile,
+                level,
+            } => cmd_profile_lod(ctx, &device, profile, level).await,
         },
         Commands::Resolution(sub) => match sub {
-            ResolutionCmd::List { device, profile } => {
-                cmd_resolution_list(&client, &device, profile).await
-            }
-            ResolutionCmd::Dpi {
+            ResolutionCmd::List {
                 device,
                 profile,
-                resolution,
-                dpi,
-            } => cmd_resolution_dpi(&client, &device, profile, resolution, dpi).await,
-            ResolutionCmd::Active {
+                percent,
+            } => cmd_resolution_list(ctx, &device, profile, percent).await,
+            ResolutionCmd::Info {
                 device,
                 profile,
                 resolution,
-            } => cmd_resolution_active(&client, &device, profile, resolution).await,
-            ResolutionCmd::Default {
+                percent,
+            } => cmd_resolution_info(ctx, &device, profile, resolution, percent).await,
+            ResolutionCmd::Dpi {
                 device,
                 profile,
                 resolution,
-            } => cmd_resolution_default(&client, &device, profile, resolution).await,
-            ResolutionCmd::Enable {
+                dpi,
+                y,
+                percent,
+            } => cmd_resolution_dpi(ctx, &device, profile, resolution, dpi, y, percent).await,
+            ResolutionCmd::Up {
                 device,
                 profile,
                 resolution,
-            } => cmd_resolution_enable_disable(&client, &device, profile, resolution, false).await,
+            } => cmd_resolution_step(ctx, &device, profile, resolution, true).await,
+            ResolutionCmd::Down {
+                device,
+                profile,
+                resolution,
+            } => cmd_resolution_step(ctx, &device, profile, resolution, false).await,
+            ResolutionCmd::Active {
+                device,
+                profile,
+                resolution,
+            } => cmd_resolution_active(ctx, &device, profile, resolution).await,
+            ResolutionCmd::Default {
+                device,
+                profile,
+                resolution,
+            } => cmd_resolution_default(ctx, &device, profile, resolution).await,
+            ResolutionCmd::Enable {
+                device,
+                profile,
+                resolution,
+            } => cmd_resolution_enable_disable(ctx, &device, profile, resolution, false).await,
             ResolutionCmd::Disable {
                 device,
                 profile,
                 resolution,
-            } => cmd_resolution_enable_disable(&client, &device, profile, resolution, true).await,
+            } => cmd_resolution_enable_disable(ctx, &device, profile, resolution, true).await,
         },
         Commands::Button(sub) => match sub {
-            ButtonCmd::List { device, profile } => {
-                cmd_button_list(&client, &device, profile).await
+            ButtonCmd::List {
+                device,
+                profile,
+                resolved,
+            } => {
+                cmd_button_list(ctx, &device, profile, resolved).await
             }
             ButtonCmd::Get {
                 device,
                 profile,
                 button,
-            } => cmd_button_get(&client, &device, profile, button).await,
+            } => cmd_button_get(ctx, &device, profile, button).await,
             ButtonCmd::SetButton {
                 device,
                 profile,
                 button,
                 value,
-            } => cmd_button_set(&client, &device, profile, button, 1, value).await,
+            } => cmd_button_set(ctx, &device, profile, button, 1, value).await,
             ButtonCmd::SetSpecial {
                 device,
                 profile,
                 button,
                 value,
-            } => cmd_button_set(&client, &device, profile, button, 2, value).await,
+            } => cmd_button_set(ctx, &device, profile, button, 2, value).await,
             ButtonCmd::SetKey {
                 device,
                 profile,
                 button,
                 keycode,
-            } => cmd_button_set(&client, &device, profile, button, 3, keycode).await,
+            } => cmd_button_set(ctx, &device, profile, button, 3, keycode).await,
             ButtonCmd::SetMacro {
                 device,
                 profile,
                 button,
+                repeat,
                 events,
-            } => cmd_button_set_macro(&client, &device, profile, button, &events).await,
+            } => {
+                cmd_button_set_macro(ctx, &device, profile, button, repeat.as_deref(), &events)
+                    .await
+            }
             ButtonCmd::Disable {
                 device,
                 profile,
                 button,
-            } => cmd_button_set(&client, &device, profile, button, 0, 0).await,
+            } => cmd_button_set(ctx, &device, profile, button, 0, 0).await,
+            ButtonCmd::Reset {
+                device,
+                profile,
+                button,
+            } => cmd_button_reset(ctx, &device, profile, button).await,
         },
         Commands::Led(sub) => match sub {
-            LedCmd::List { device, profile } => cmd_led_list(&client, &device, profile).await,
+            LedCmd::List { device, profile } => cmd_led_list(ctx, &device, profile).await,
             LedCmd::Get {
                 device,
                 profile,
                 led,
-            } => cmd_led_get(&client, &device, profile, led).await,
+            } => cmd_led_get(ctx, &device, profile, led).await,
             LedCmd::Mode {
                 device,
                 profile,
                 led,
                 mode,
-            } => cmd_led_mode(&client, &device, profile, led, &mode).await,
+            } => cmd_led_mode(ctx, &device, profile, led, &mode).await,
+            LedCmd::OffAll { device, profile } => cmd_led_off_all(ctx, &device, profile).await,
             LedCmd::Color {
                 device,
                 profile,
                 led,
                 color,
-            } => cmd_led_color(&client, &device, profile, led, &color, "Color").await,
+            } => cmd_led_color(ctx, &device, profile, led, &color, "Color").await,
             LedCmd::SecondaryColor {
                 device,
                 profile,
                 led,
                 color,
-            } => cmd_led_color(&client, &device, profile, led, &color, "SecondaryColor").await,
+            } => cmd_led_color(ctx, &device, profile, led, &color, "SecondaryColor").await,
             LedCmd::TertiaryColor {
                 device,
                 profile,
                 led,
                 color,
-            } => cmd_led_color(&client, &device, profile, led, &color, "TertiaryColor").await,
+            } => cmd_led_color(ctx, &device, profile, led, &color, "TertiaryColor").await,
             LedCmd::Brightness {
                 device,
                 profile,
                 led,
                 value,
-            } => cmd_led_brightness(&client, &device, profile, led, value).await,
+            } => cmd_led_brightness(ctx, &device, profile, led, value).await,
             LedCmd::Duration {
                 device,
                 profile,
                 led,
                 ms,
-            } => cmd_led_duration(&client, &device, profile, led, ms).await,
+            } => cmd_led_duration(ctx, &device, profile, led, ms).await,
+            LedCmd::Direction {
+                device,
+                profile,
+                led,
+                direction,
+            } => cmd_led_direction(ctx, &device, profile, led, direction).await,
+            LedCmd::LiveColor { device, led, color } => {
+                cmd_led_live_color(ctx, &device, led, &color).await
+            }
         },
         Commands::Test(sub) => match sub {
-            TestCmd::LoadDevice { json_file } => cmd_test_load_device(&client, &json_file).await,
-            TestCmd::Reset => cmd_test_reset(&client).await,
+            TestCmd::LoadDevice { json_file } => cmd_test_load_device(ctx, &json_file).await,
+            TestCmd::Reset => cmd_test_reset(ctx).await,
+            TestCmd::HidDescriptor { device } => cmd_test_hid_descriptor(ctx, &device).await,
+            TestCmd::Raw {
+                device,
+                hex_bytes,
+                feature,
+            } => cmd_test_raw(ctx, &device, &hex_bytes.join(" "), feature).await,
         },
+        /* Handled before connecting to the daemon. */
+        Commands::ConfigPath | Commands::Completions { .. } | Commands::Complete { .. } => Ok(()),
+        Commands::Version => cmd_version(ctx).await,
+        Commands::DriverCaps { driver } => cmd_driver_caps(ctx, &driver).await,
+        Commands::Quit => cmd_quit(ctx).await,
+        Commands::Doctor => cmd_doctor(ctx).await,
+        Commands::Wait { timeout } => cmd_wait(ctx, timeout).await,
+        Commands::Interactive { device } => cmd_interactive(ctx, &device).await,
+        Commands::Selftest { device, yes } => cmd_selftest(ctx, &device, yes).await,
+        Commands::Watch {
+            device,
+            poll_interval,
+        } => cmd_watch(ctx, &device, poll_interval).await,
+        Commands::Monitor { device } => cmd_monitor(ctx, device.as_deref()).await,
     }
 }
 
@@ -549,82 +1352,629 @@ fn device_path_from_child(child_path: &str) -> &str {
     }
 }
 
-/// Commit changes to hardware after a write operation.
-async fn auto_commit(client: &RatbagClient, any_path: &str) -> Result<()> {
+/// Last component of a device path, e.g. `hidraw3`.
+fn sysname_of(dev_path: &str) -> &str {
+    dev_path.rsplit('/').next().unwrap_or(dev_path)
+}
+
+/// The reminder printed after a write that was only staged.
+fn staged_reminder(sysname: &str) -> String {
+    format!(
+        "Not committed: the change is lost if {} disconnects; `ratbagctl commit {}` writes it.",
+        sysname, sysname
+    )
+}
+
+/// Indices of the profiles of `dev_path` with staged, uncommitted changes.
+async fn dirty_profiles(client: &RatbagClient, dev_path: &str) -> Result<Vec<u32>> {
+    let mut dirty = Vec::new();
+    for profile in client.get_device_profiles(dev_path).await? {
+        if client.get_profile_is_dirty(&profile).await.unwrap_or(false) {
+            dirty.push(client.get_profile_index(&profile).await?);
+        }
+    }
+    Ok(dirty)
+}
+
+/// Whether `dev_path` has staged changes to device-wide settings or to
+/// any profile.
+async fn has_staged_changes(client: &RatbagClient, dev_path: &str) -> Result<bool> {
+    Ok(client.get_device_is_dirty(dev_path).await.unwrap_or(false)
+        || !dirty_profiles(client, dev_path).await?.is_empty())
+}
+
+/// Commit changes to hardware after a write operation, unless commits are
+/// deferred with `--no-commit` (the change then stays staged on the daemon
+/// until `ratbagctl commit`) or `--batch` (the device is recorded for
+/// `ratbagctl flush`).
+///
+/// Each command calls this once, after all of its writes, so a command
+/// that writes many settings (`import`) still commits once.  Edits spread
+/// over several commands are batched with either flag: the daemon keeps
+/// them staged, shown as `IsDirty`, and one `Commit` writes them all.
+async fn auto_commit(ctx: &Ctx, any_path: &str) -> Result<()> {
     let dev_path = device_path_from_child(any_path);
-    let rc = client.commit_device(dev_path).await?;
-    if rc != 0 {
-        anyhow::bail!("Commit returned error code {}", rc);
+    if ctx.opts.no_commit {
+        if !ctx.opts.interactive {
+            /* On stderr, so that it never ends up in `--output` files. */
+            eprintln!("{}", staged_reminder(sysname_of(dev_path)));
+        }
+        return Ok(());
     }
-    Ok(())
+    if ctx.opts.batch {
+        return pending::PendingStore::open()?.mark(dev_path);
+    }
+    ctx.client.commit_device(dev_path).await?.check(dev_path)
 }
 
 // ---------------------------------------------------------------------------
 // Command implementations
 // ---------------------------------------------------------------------------
 
-async fn cmd_list(client: &RatbagClient) -> Result<()> {
+async fn cmd_list(ctx: &Ctx, filter: Option<DeviceKind>, show_key: bool) -> Result<()> {
+    let client = &ctx.client;
     let api = client.get_api_version().await.unwrap_or(-1);
     let devices = client.list_devices().await?;
-    if devices.is_empty() {
-        println!("No devices found. (API version {})", api);
+
+    /* Keep the unfiltered index: it is what `<device>` arguments resolve. */
+    let mut shown = Vec::with_capacity(devices.len());
+    for (i, path) in devices.iter().enumerate() {
+        let kind = DeviceKind::from_dbus(client.get_device_type(path).await.unwrap_or(0));
+        if filter.is_none() || kind == filter {
+            shown.push((i, path, kind));
+        }
+    }
+
+    if ctx.opts.format == OutputFormat::Json {
+        let mut entries = Vec::with_capacity(shown.len());
+        for (i, path, kind) in &shown {
+            entries.push(json!({
+                "index": i,
+                "path": path,
+                "name": client.get_device_name(path).await.unwrap_or_default(),
+                "model": client.get_device_model(path).await.unwrap_or_default(),
+                "type": kind.map(DeviceKind::name),
+                "key": client.get_device_stable_key(path).await.unwrap_or_default(),
+            }));
+        }
+        return ctx.out.json(&json!({ "api_version": api, "devices": entries }));
+    }
+    if shown.is_empty() {
+        outln!(ctx, "No devices found. (API version {})", api);
         return Ok(());
     }
-    println!("API version: {}", api);
-    for (i, path) in devices.iter().enumerate() {
+    outln!(ctx, "API version: {}", api);
+    for (i, path, _) in &shown {
         let name = client.get_device_name(path).await.unwrap_or_default();
         let model = client.get_device_model(path).await.unwrap_or_default();
-        println!("{}: {} ({})", i, name, model);
+        if show_key {
+            let key = client.get_device_stable_key(path).await.unwrap_or_default();
+            outln!(ctx, "{}: {} ({}) key={}", i, name, model, key);
+        } else {
+            outln!(ctx, "{}: {} ({})", i, name, model);
+        }
+    }
+    Ok(())
+}
+
+/// What `version` reports about a running daemon.
+struct DaemonVersion {
+    version: String,
+    api_version: i32,
+    drivers: Vec<String>,
+}
+
+async fn read_daemon_version(client: &RatbagClient) -> Result<DaemonVersion> {
+    Ok(DaemonVersion {
+        version: client.get_daemon_version().await?,
+        api_version: client.get_api_version().await?,
+        drivers: client.get_drivers().await?,
+    })
+}
+
+fn version_lines(daemon: Option<&DaemonVersion>) -> Vec<String> {
+    let mut lines = vec![format!("ratbagctl {}", env!("CARGO_PKG_VERSION"))];
+    match daemon {
+        Some(d) => {
+            lines.push(format!("ratbagd {}", d.version));
+            lines.push(format!("API version: {}", d.api_version));
+            lines.push(format!("Drivers: {}", d.drivers.join(", ")));
+        }
+        None => lines.push("ratbagd: not running (or too old to report its version)".into()),
+    }
+    lines
+}
+
+async fn cmd_quit(ctx: &Ctx) -> Result<()> {
+    ctx.client.quit().await?;
+    outln!(ctx, "ratbagd is shutting down.");
+    Ok(())
+}
+
+async fn cmd_doctor(ctx: &Ctx) -> Result<()> {
+    let owner = doctor::inspect(&ctx.client).await?;
+    if ctx.opts.format == OutputFormat::Json {
+        return ctx.out.json(&doctor::report_json(owner.as_ref()));
+    }
+    for line in doctor::report_lines(owner.as_ref()) {
+        outln!(ctx, "{}", line);
+    }
+    Ok(())
+}
+
+async fn cmd_version(ctx: &Ctx) -> Result<()> {
+    let daemon = read_daemon_version(&ctx.client).await.ok();
+    if ctx.opts.format == OutputFormat::Json {
+        return ctx.out.json(&json!({
+            "ratbagctl": env!("CARGO_PKG_VERSION"),
+            "daemon": daemon.as_ref().map(|d| json!({
+                "version": d.version,
+                "api_version": d.api_version,
+                "drivers": d.drivers,
+            })),
+        }));
+    }
+    for line in version_lines(daemon.as_ref()) {
+        outln!(ctx, "{}", line);
+    }
+    Ok(())
+}
+
+async fn cmd_driver_caps(ctx: &Ctx, driver: &str) -> Result<()> {
+    let caps = ctx.client.driver_capabilities(driver).await?;
+    if ctx.opts.format == OutputFormat::Json {
+        return ctx.out.json(&json!({ "driver": driver, "capabilities": caps }));
+    }
+    outln!(ctx, "Driver {}:", driver);
+    for (name, supported) in &caps {
+        outln!(ctx, "  {:<12} {}", name, if *supported { "yes" } else { "no" });
+    }
+    Ok(())
+}
+
+async fn cmd_wait(ctx: &Ctx, timeout: u64) -> Result<()> {
+    let path = ctx
+        .client
+        .wait_for_device(std::time::Duration::from_secs(timeout))
+        .await?;
+    outln!(ctx, "{}", path);
+    Ok(())
+}
+
+async fn cmd_interactive(ctx: &Ctx, device: &str) -> Result<()> {
+    let client = &ctx.client;
+    let dev_path = client.resolve_device(device).await?;
+    let sysname = sysname_of(&dev_path);
+    /* Writes stay staged until the user runs `commit`. */
+    let base = Options {
+        no_commit: true,
+        batch: false,
+        interactive: true,
+        ..ctx.opts
+    };
+    let tree = Cli::command();
+    let mut repl = repl::Repl::new(sysname);
+    let terminal = std::io::stdin().is_terminal();
+    if terminal {
+        let name = client.get_device_name(&dev_path).await.unwrap_or_default();
+        outln!(ctx, "{} ({}). Type `help` for commands.", name, sysname);
+    }
+
+    let mut lines = std::io::stdin().lines();
+    loop {
+        if terminal {
+            print!("{}> ", sysname);
+            std::io::stdout().flush()?;
+        }
+        let Some(line) = lines.next() else {
+            break;
+        };
+        match repl.handle(&line?, &tree) {
+            Ok(repl::Action::Run(args)) => {
+                let cli = match Cli::try_parse_from(std::iter::once("ratbagctl".to_string()).chain(args)) {
+                    Ok(cli) => cli,
+                    Err(e) => {
+                        e.print()?;
+                        continue;
+                    }
+                };
+                let shell = Ctx {
+                    client: client.clone(),
+                    opts: Options {
+                        format: cli.format.unwrap_or(base.format),
+                        color: cli.color.unwrap_or(base.color),
+                        ..base
+                    },
+                    out: Output::open(None)?,
+                };
+                if let Err(e) = Box::pin(run(&shell, cli.command)).await {
+                    eprintln!("Error: {:#}", e);
+                }
+                shell.out.flush()?;
+            }
+            Ok(repl::Action::Help) => outln!(ctx, "{}", repl::HELP),
+            Ok(repl::Action::History) => {
+                for (i, entry) in repl.history().iter().enumerate() {
+                    outln!(ctx, "{:>4}  {}", i + 1, entry);
+                }
+            }
+            Ok(repl::Action::Quit) => break,
+            Ok(repl::Action::Nothing) => {}
+            Err(e) => eprintln!("Error: {:#}", e),
+        }
+        ctx.out.flush()?;
+    }
+
+    if has_staged_changes(client, &dev_path).await? {
+        outln!(
+            ctx,
+            "Uncommitted changes are still staged on {}; `ratbagctl commit {}` writes them.",
+            sysname,
+            sysname
+        );
     }
     Ok(())
 }
 
-async fn cmd_info(client: &RatbagClient, device: &str) -> Result<()> {
+async fn cmd_watch(ctx: &Ctx, device: &str, poll_interval: Option<u64>) -> Result<()> {
+    let client = &ctx.client;
     let path = client.resolve_device(device).await?;
-    let name = client.get_device_name(&path).await?;
-    let model = client.get_device_model(&path).await?;
-    let fw = client.get_device_firmware(&path).await?;
-    let profiles = client.get_device_profiles(&path).await?;
-    println!("Device:    {}", name);
-    println!("Model:     {}", model);
-    if !fw.is_empty() {
-        println!("Firmware:  {}", fw);
-    }
-    println!("Profiles:  {}", profiles.len());
-    for profile_path in &profiles {
-        let idx = client.get_profile_index(profile_path).await?;
-        let active = client.get_profile_is_active(profile_path).await?;
-        let rate = client.get_profile_report_rate(profile_path).await?;
-        let pname = client.get_profile_name(profile_path).await.unwrap_or_default();
-        let name_display = if pname.is_empty() {
-            String::new()
+    /* Subscribe before the first snapshot so no change falls in between. */
+    let mut signals = client.receive_property_changes(&path).await?;
+    let mut snapshot = watch::read_snapshot(client, &path).await?;
+    let mut poll = poll_interval.map(|secs| {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(secs));
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        interval
+    });
+    if ctx.opts.format == OutputFormat::Text {
+        outln!(ctx, "Watching {} (Ctrl-C to stop)", path);
+        ctx.out.flush()?;
+    }
+
+    loop {
+        /* Any signal or poll tick re-reads everything and prints the diff,
+         * so both sources report changes the same way. */
+        tokio::select! {
+            msg = signals.next() => {
+                msg.context("PropertiesChanged stream closed")??;
+            }
+            _ = async {
+                match poll.as_mut() {
+                    Some(interval) => interval.tick().await,
+                    None => std::future::pending().await,
+                }
+            } => {}
+        }
+        let now = watch::read_snapshot(client, &path).await?;
+        for change in snapshot.diff(&now) {
+            if ctx.opts.format == OutputFormat::Json {
+                outln!(
+                    ctx,
+                    "{}",
+                    json!({
+                        "object": change.object,
+                        "property": change.property,
+                        "before": change.before,
+                        "after": change.after,
+                    })
+                );
+            } else {
+                outln!(ctx, "{}", change);
+            }
+        }
+        ctx.out.flush()?;
+        snapshot = now;
+    }
+}
+
+async fn cmd_monitor(ctx: &Ctx, device: Option<&str>) -> Result<()> {
+    let client = &ctx.client;
+    let mut signals = client.receive_signals().await?;
+    let device_path = match device {
+        Some(device) => Some(client.resolve_device(device).await?),
+        None => None,
+    };
+    if ctx.opts.format == OutputFormat::Text {
+        outln!(
+            ctx,
+            "Monitoring {} (Ctrl-C to stop)",
+            device_path.as_deref().unwrap_or("all devices")
+        );
+        ctx.out.flush()?;
+    }
+
+    while let Some(msg) = signals.next().await {
+        let msg = msg?;
+        let time = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default();
+        let path = msg.header().path().map(|p| p.to_string()).unwrap_or_default();
+        if !monitor::is_relevant(&path, dbus_client::MANAGER_PATH, device_path.as_deref()) {
+            continue;
+        }
+        for event in monitor::events_from_message(&msg, time)? {
+            if ctx.opts.format == OutputFormat::Json {
+                outln!(ctx, "{}", event.to_json());
+            } else {
+                outln!(ctx, "{}", event);
+            }
+        }
+        ctx.out.flush()?;
+    }
+    anyhow::bail!("Signal stream from the daemon closed")
+}
+
+async fn cmd_selftest(ctx: &Ctx, device: &str, yes: bool) -> Result<()> {
+    anyhow::ensure!(
+        yes,
+        "selftest rewrites and commits every setting on the device; re-run with --yes to proceed"
+    );
+    let client = &ctx.client;
+    let path = client.resolve_device(device).await?;
+    let report = selftest::run(client, &path).await?;
+
+    if ctx.opts.format == OutputFormat::Json {
+        ctx.out.json(&json!({
+            "device": path,
+            "checked": report.checked,
+            "passed": report.mismatches.is_empty(),
+            "mismatches": report.mismatches,
+            "restored": report.restored,
+            "restore_mismatches": report.restore_mismatches,
+        }))?;
+    } else if report.mismatches.is_empty() {
+        outln!(ctx, "{}: {} settings round-tripped", path, report.checked);
+    } else {
+        outln!(
+            ctx,
+            "{}: {} of {} settings did not round-trip",
+            path,
+            report.mismatches.len(),
+            report.checked
+        );
+        for m in &report.mismatches {
+            outln!(ctx, "  {}", m);
+        }
+        if report.restore_mismatches.is_empty() {
+            outln!(ctx, "Original settings restored.");
         } else {
-            format!(" \"{}\"", pname)
-        };
-        println!(
-            "  Profile {}{}: rate={}Hz{}",
-            idx,
-            name_display,
-            rate,
-            if active { " [active]" } else { "" }
+            outln!(ctx, "Original settings could not be restored:");
+            for m in &report.restore_mismatches {
+                outln!(ctx, "  {}", m);
+            }
+        }
+    }
+
+    anyhow::ensure!(report.mismatches.is_empty(), "Self test failed");
+    Ok(())
+}
+
+async fn cmd_info(ctx: &Ctx, device: &str) -> Result<()> {
+    let client = &ctx.client;
+    let path = client.resolve_device(device).await?;
+    let profile_paths = client.get_device_profiles(&path).await?;
+    let mut profiles = Vec::with_capacity(profile_paths.len());
+    for profile_path in &profile_paths {
+        profiles.push(ProfileSummary {
+            index: client.get_profile_index(profile_path).await?,
+            name: client.get_profile_name(profile_path).await.unwrap_or_default(),
+            report_rate: client.get_profile_report_rate(profile_path).await?,
+            is_active: client.get_profile_is_active(profile_path).await?,
+        });
+    }
+    /* Positions are device-wide; read them off the first profile's buttons.
+     * Daemons without the `Position` property leave the layout empty. */
+    let mut button_layout = Vec::new();
+    if let Some(first) = profile_paths.first() {
+        for btn_path in client.get_profile_buttons(first).await? {
+            button_layout.push(client.get_button_position(&btn_path).await.unwrap_or_default());
+        }
+    }
+    /* Older daemons have no battery properties. */
+    let battery = client.get_device_battery(&path).await.unwrap_or(None);
+    let summary = DeviceSummary {
+        name: client.get_device_name(&path).await?,
+        model: client.get_device_model(&path).await?,
+        kind: DeviceKind::from_dbus(client.get_device_type(&path).await.unwrap_or(0)),
+        /* Older daemons have no ID properties. */
+        bus_ids: match (
+            client.get_device_bus_type(&path).await,
+            client.get_device_ids(&path).await,
+        ) {
+            (Ok(bus), Ok((vid, pid))) if !bus.is_empty() => Some((bus, vid, pid)),
+            _ => None,
+        },
+        /* Older daemons have no Serial property. */
+        serial: client.get_device_serial(&path).await.unwrap_or_default(),
+        firmware: client.get_device_firmware(&path).await?,
+        /* Older daemons have no NeedsRepair property. */
+        needs_repair: client.get_device_needs_repair(&path).await.unwrap_or(false),
+        path,
+        profiles,
+        button_layout,
+        battery,
+    };
+
+    if ctx.opts.format == OutputFormat::Json {
+        return ctx.out.json(&summary.to_json());
+    }
+    for line in summary.text_lines(&ctx.opts) {
+        outln!(ctx, "{}", line);
+    }
+    Ok(())
+}
+
+async fn cmd_export(ctx: &Ctx, device: &str, output: Option<&Path>) -> Result<()> {
+    let client = &ctx.client;
+    let dev_path = client.resolve_device(device).await?;
+    let config = backup::read_device(client, &dev_path).await?;
+    let text = serde_json::to_string_pretty(&config)?;
+    match output {
+        Some(file) => std::fs::write(file, text + "\n")
+            .with_context(|| format!("Cannot write {}", file.display()))?,
+        None => outln!(ctx, "{}", text),
+    }
+    Ok(())
+}
+
+async fn cmd_import(ctx: &Ctx, device: &str, input: &Path, dry_run: bool) -> Result<()> {
+    let client = &ctx.client;
+    let dev_path = client.resolve_device(device).await?;
+    let text = std::fs::read_to_string(input)
+        .with_context(|| format!("Cannot read {}", input.display()))?;
+    let wanted: backup::DeviceConfig = serde_json::from_str(&text)
+        .with_context(|| format!("{} is not a ratbagctl export", input.display()))?;
+    let current = backup::read_device(client, &dev_path).await?;
+    if wanted.model != current.model {
+        outln!(
+            ctx,
+            "Note: {} was exported from {} ({}), not {} ({}).",
+            input.display(),
+            wanted.name,
+            wanted.model,
+            current.name,
+            current.model
         );
     }
+
+    let plan = backup::plan(&current, &wanted)?;
+    for note in &plan.skipped {
+        outln!(ctx, "Skipped {}.", note);
+    }
+    if plan.steps.is_empty() {
+        outln!(ctx, "Nothing to change.");
+        return Ok(());
+    }
+    if dry_run {
+        for step in &plan.steps {
+            outln!(ctx, "Would set {}", step);
+        }
+        return Ok(());
+    }
+
+    let mut applied = 0;
+    for step in &plan.steps {
+        match backup::apply(client, &dev_path, step).await {
+            Ok(()) => applied += 1,
+            /* One rejected value should not abandon the rest of the file. */
+            Err(e) if dbus_client::is_unsupported(&e) => {
+                outln!(ctx, "Skipped {}: {}", step, e.root_cause());
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    auto_commit(ctx, &dev_path).await?;
+    outln!(ctx, "Applied {} of {} changes.", applied, plan.steps.len());
+    Ok(())
+}
+
+async fn cmd_repair(ctx: &Ctx, device: &str) -> Result<()> {
+    let client = &ctx.client;
+    let dev_path = client.resolve_device(device).await?;
+    client.repair_device(&dev_path).await?.check(&dev_path)?;
+    outln!(ctx, "Stored profiles rewritten.");
+    Ok(())
+}
+
+async fn cmd_commit(ctx: &Ctx, device: &str, profile: Option<u32>) -> Result<()> {
+    let client = &ctx.client;
+    let dev_path = client.resolve_device(device).await?;
+    let reply = match profile {
+        Some(p) => client.commit_profile(&client.profile_path(&dev_path, p).await?).await?,
+        None => client.commit_device(&dev_path).await?,
+    };
+    reply.check(&dev_path)?;
+    match profile {
+        Some(p) => outln!(ctx, "Profile {} committed to hardware.", p),
+        None => outln!(ctx, "Changes committed to hardware."),
+    }
+    Ok(())
+}
+
+async fn cmd_flush(ctx: &Ctx, device: Option<&str>) -> Result<()> {
+    let client = &ctx.client;
+    let store = pending::PendingStore::open()?;
+    let mut pending = store.list()?;
+    if let Some(spec) = device {
+        let dev_path = client.resolve_device(spec).await?;
+        pending.retain(|p| *p == dev_path);
+    }
+    if pending.is_empty() {
+        outln!(ctx, "No pending changes.");
+        return Ok(());
+    }
+
+    let present = client.list_devices().await?;
+    for dev_path in &pending {
+        let sysname = sysname_of(dev_path);
+        if !present.contains(dev_path) {
+            /* Unplugged since the write; its staged changes are gone with it. */
+            store.clear(dev_path)?;
+            outln!(ctx, "{}: device is gone, dropped.", sysname);
+            continue;
+        }
+        client
+            .commit_device(dev_path)
+            .await?
+            .check(dev_path)
+            .with_context(|| format!("Commit of {}", sysname))?;
+        store.clear(dev_path)?;
+        outln!(ctx, "{}: changes committed to hardware.", sysname);
+    }
     Ok(())
 }
 
-async fn cmd_commit(client: &RatbagClient, device: &str) -> Result<()> {
+async fn cmd_status(ctx: &Ctx, device: &str) -> Result<()> {
+    let client = &ctx.client;
     let dev_path = client.resolve_device(device).await?;
-    let rc = client.commit_device(&dev_path).await?;
-    if rc != 0 {
-        anyhow::bail!("Commit returned error code {}", rc);
+    let device_dirty = client.get_device_is_dirty(&dev_path).await.unwrap_or(false);
+    let dirty = dirty_profiles(client, &dev_path).await?;
+    let sysname = sysname_of(&dev_path);
+    if ctx.opts.format == OutputFormat::Json {
+        return ctx.out.json(&json!({
+            "device": sysname,
+            "device_dirty": device_dirty,
+            "dirty_profiles": dirty,
+        }));
     }
-    println!("Changes committed to hardware.");
+    outln!(ctx, "{}", status_line(sysname, device_dirty, &dirty));
     Ok(())
 }
 
-async fn cmd_profile_list(client: &RatbagClient, device: &str) -> Result<()> {
+/// What `status` prints for a device with staged device-wide settings
+/// (`device_dirty`) and the given dirty profiles.
+fn status_line(sysname: &str, device_dirty: bool, dirty: &[u32]) -> String {
+    let mut staged = Vec::new();
+    if device_dirty {
+        staged.push("device settings".to_string());
+    }
+    match dirty {
+        [] => {}
+        [profile] => staged.push(format!("profile {}", profile)),
+        _ => staged.push(format!(
+            "profiles {}",
+            dirty.iter().map(u32::to_string).collect::<Vec<_>>().join(", ")
+        )),
+    }
+    if staged.is_empty() {
+        return format!("{}: no uncommitted changes.", sysname);
+    }
+    let verb = if staged.len() == 1 && dirty.len() == 1 { "has" } else { "have" };
+    format!(
+        "{}: {} {} uncommitted changes; `ratbagctl commit {}` writes them.",
+        sysname,
+        staged.join(" and "),
+        verb,
+        sysname
+    )
+}
+
+async fn cmd_profile_list(ctx: &Ctx, device: &str) -> Result<()> {
+    let client = &ctx.client;
     let dev_path = client.resolve_device(device).await?;
     let profiles = client.get_device_profiles(&dev_path).await?;
+    let mut entries = Vec::with_capacity(profiles.len());
     for profile_path in &profiles {
         let idx = client.get_profile_index(profile_path).await?;
         let active = client.get_profile_is_active(profile_path).await?;
@@ -632,23 +1982,39 @@ async fn cmd_profile_list(client: &RatbagClient, device: &str) -> Result<()> {
         let rate = client.get_profile_report_rate(profile_path).await?;
         let pname = client.get_profile_name(profile_path).await.unwrap_or_default();
         let dirty = client.get_profile_is_dirty(profile_path).await.unwrap_or(false);
+        if ctx.opts.format == OutputFormat::Json {
+            entries.push(json!({
+                "index": idx,
+                "name": pname,
+                "report_rate": rate,
+                "is_active": active,
+                "is_disabled": !enabled,
+                "is_dirty": dirty,
+            }));
+            continue;
+        }
         let name_display = if pname.is_empty() {
             String::new()
         } else {
             format!(" \"{}\"", pname)
         };
-        println!(
+        outln!(
+            ctx,
             "Profile {}{}: rate={}Hz enabled={} active={}{}",
             idx, name_display, rate, enabled, active,
-            if dirty { " [dirty]" } else { "" }
+            if dirty { format!(" {}", ctx.opts.marker("[dirty]")) } else { String::new() }
         );
     }
+    if ctx.opts.format == OutputFormat::Json {
+        return ctx.out.json(&json!({ "profiles": entries }));
+    }
     Ok(())
 }
 
-async fn cmd_profile_info(client: &RatbagClient, device: &str, profile: u32) -> Result<()> {
+async fn cmd_profile_info(ctx: &Ctx, device: &str, profile: u32) -> Result<()> {
+    let client = &ctx.client;
     let dev_path = client.resolve_device(device).await?;
-    let profile_path = format!("{}/p{}", dev_path, profile);
+    let profile_path = client.profile_path(&dev_path, profile).await?;
     let idx = client.get_profile_index(&profile_path).await?;
     let active = client.get_profile_is_active(&profile_path).await?;
     let disabled = client.get_profile_disabled(&profile_path).await?;
@@ -659,27 +2025,57 @@ async fn cmd_profile_info(client: &RatbagClient, device: &str, profile: u32) ->
     let angle = client.get_profile_angle_snapping(&profile_path).await?;
     let debounce = client.get_profile_debounce(&profile_path).await?;
     let debounces = client.get_profile_debounces(&profile_path).await.unwrap_or_default();
+    /* Older daemons lack the property; treat that as unsupported. */
+    let lod = client
+        .get_profile_lift_off_distance(&profile_path)
+        .await
+        .unwrap_or(0);
+    let shared_rate = has_global_report_rate(client, &profile_path).await;
+
+    if ctx.opts.format == OutputFormat::Json {
+        /* The profile as `export` writes it, plus what only `info` shows. */
+        let mut value = serde_json::to_value(backup::read_profile(client, &profile_path).await?)?;
+        let extra = json!({
+            "is_dirty": dirty,
+            "report_rates": rates,
+            "report_rate_shared": shared_rate,
+            "debounces": debounces,
+            "lift_off_distance": (lod > 0).then_some(lod),
+        });
+        if let (Some(obj), serde_json::Value::Object(extra)) = (value.as_object_mut(), extra) {
+            obj.extend(extra);
+        }
+        return ctx.out.json(&value);
+    }
 
-    println!("Profile {}:", idx);
+    outln!(ctx, "Profile {}:", idx);
     if !pname.is_empty() {
-        println!("  Name:           {}", pname);
+        outln!(ctx, "  Name:           {}", pname);
+    }
+    outln!(ctx, "  Active:         {}", active);
+    outln!(ctx, "  Enabled:        {}", !disabled);
+    outln!(ctx, "  Dirty:          {}", dirty);
+    if shared_rate {
+        outln!(ctx, "  Report rate:    {} Hz (shared by all profiles)", rate);
+    } else {
+        outln!(ctx, "  Report rate:    {} Hz", rate);
     }
-    println!("  Active:         {}", active);
-    println!("  Enabled:        {}", !disabled);
-    println!("  Dirty:          {}", dirty);
-    println!("  Report rate:    {} Hz", rate);
-    println!("  Supported rates: {:?}", rates);
+    outln!(ctx, "  Supported rates: {:?}", rates);
     if angle >= 0 {
-        println!(
+        outln!(
+            ctx,
             "  Angle snapping: {}",
             if angle == 1 { "on" } else { "off" }
         );
     }
     if debounce >= 0 {
-        println!("  Debounce:       {} ms", debounce);
+        outln!(ctx, "  Debounce:       {} ms", debounce);
     }
     if !debounces.is_empty() {
-        println!("  Supported debounces: {:?}", debounces);
+        outln!(ctx, "  Supported debounces: {:?}", debounces);
+    }
+    if lod > 0 {
+        outln!(ctx, "  Lift-off distance: level {}", lod);
     }
 
     let resolutions = client.get_profile_resolutions(&profile_path).await?;
@@ -693,7 +2089,8 @@ async fn cmd_profile_info(client: &RatbagClient, device: &str, profile: u32) ->
         } else {
             format!(" (supported: {:?})", dpi_list)
         };
-        println!(
+        outln!(
+            ctx,
             "  Resolution {}: {}{}{}",
             ri,
             dpi,
@@ -706,7 +2103,8 @@ async fn cmd_profile_info(client: &RatbagClient, device: &str, profile: u32) ->
     for btn_path in &buttons {
         let bi = client.get_button_index(btn_path).await?;
         let (action_type, mapping_val) = client.get_button_mapping(btn_path).await?;
-        println!(
+        outln!(
+            ctx,
             "  Button {}: type={} value={}",
             bi,
             action_type_name(action_type),
@@ -721,7 +2119,8 @@ async fn cmd_profile_info(client: &RatbagClient, device: &str, profile: u32) ->
         let (r, g, b) = client.get_led_color(led_path).await?;
         let bright = client.get_led_brightness(led_path).await?;
         let duration = client.get_led_effect_duration(led_path).await?;
-        println!(
+        outln!(
+            ctx,
             "  LED {}: mode={} color=#{:02x}{:02x}{:02x} brightness={} duration={}ms",
             li,
             led_mode_name(mode),
@@ -735,35 +2134,47 @@ async fn cmd_profile_info(client: &RatbagClient, device: &str, profile: u32) ->
     Ok(())
 }
 
-async fn cmd_profile_active(client: &RatbagClient, device: &str, profile: u32) -> Result<()> {
+async fn cmd_profile_active(ctx: &Ctx, device: &str, profile: u32) -> Result<()> {
+    let client = &ctx.client;
     let dev_path = client.resolve_device(device).await?;
-    let profile_path = format!("{}/p{}", dev_path, profile);
-    client.call_profile_set_active(&profile_path).await?;
-    auto_commit(client, &profile_path).await?;
-    println!("Profile {} set as active.", profile);
+    let profile_path = client.profile_path(&dev_path, profile).await?;
+    /* With deferred commits the switch is staged like any other change.
+     * Otherwise drivers that switch profiles directly leave nothing dirty;
+     * skip the commit so hotkey switching stays fast. */
+    if ctx.opts.no_commit || ctx.opts.batch {
+        client.call_profile_set_active(&profile_path).await?;
+        auto_commit(ctx, &profile_path).await?;
+    } else {
+        client.call_profile_switch(&profile_path).await?;
+        if has_staged_changes(client, &dev_path).await? {
+            auto_commit(ctx, &profile_path).await?;
+        }
+    }
+    outln!(ctx, "Profile {} set as active.", profile);
     Ok(())
 }
 
 async fn cmd_profile_name(
-    client: &RatbagClient,
+    ctx: &Ctx,
     device: &str,
     profile: u32,
     name: Option<String>,
 ) -> Result<()> {
+    let client = &ctx.client;
     let dev_path = client.resolve_device(device).await?;
-    let profile_path = format!("{}/p{}", dev_path, profile);
+    let profile_path = client.profile_path(&dev_path, profile).await?;
     match name {
         Some(n) => {
             client.set_profile_name(&profile_path, &n).await?;
-            auto_commit(client, &profile_path).await?;
-            println!("Profile {} name set to \"{}\".", profile, n);
+            auto_commit(ctx, &profile_path).await?;
+            outln!(ctx, "Profile {} name set to \"{}\".", profile, n);
         }
         None => {
             let n = client.get_profile_name(&profile_path).await?;
             if n.is_empty() {
-                println!("Profile {} has no name set.", profile);
+                outln!(ctx, "Profile {} has no name set.", profile);
             } else {
-                println!("{}", n);
+                outln!(ctx, "{}", n);
             }
         }
     }
@@ -771,16 +2182,18 @@ async fn cmd_profile_name(
 }
 
 async fn cmd_profile_enable_disable(
-    client: &RatbagClient,
+    ctx: &Ctx,
     device: &str,
     profile: u32,
     disable: bool,
 ) -> Result<()> {
+    let client = &ctx.client;
     let dev_path = client.resolve_device(device).await?;
-    let profile_path = format!("{}/p{}", dev_path, profile);
+    let profile_path = client.profile_path(&dev_path, profile).await?;
     client.set_profile_disabled(&profile_path, disable).await?;
-    auto_commit(client, &profile_path).await?;
-    println!(
+    auto_commit(ctx, &profile_path).await?;
+    outln!(
+        ctx,
         "Profile {} {}.",
         profile,
         if disable { "disabled" } else { "enabled" }
@@ -788,30 +2201,165 @@ async fn cmd_profile_enable_disable(
     Ok(())
 }
 
+async fn cmd_smart_shift(ctx: &Ctx, device: &str, threshold: Option<u32>) -> Result<()> {
+    let client = &ctx.client;
+    let dev_path = client.resolve_device(device).await?;
+    let current = client.get_device_smart_shift_threshold(&dev_path).await?;
+    if current == 0 {
+        anyhow::bail!("SmartShift is not supported on this device.");
+    }
+    match threshold {
+        Some(value) => {
+            client
+                .set_device_smart_shift_threshold(&dev_path, value)
+                .await?;
+            auto_commit(ctx, &dev_path).await?;
+            outln!(ctx, "SmartShift threshold set to {}.", value);
+        }
+        None => outln!(ctx, "{}", current),
+    }
+    Ok(())
+}
+
+async fn cmd_battery(ctx: &Ctx, device: &str) -> Result<()> {
+    let client = &ctx.client;
+    let dev_path = client.resolve_device(device).await?;
+    let battery = client.get_device_battery(&dev_path).await?;
+    if ctx.opts.format == OutputFormat::Json {
+        return ctx.out.json(&json!({
+            "level": battery.map(|(level, _)| level),
+            "charging": battery.map(|(_, charging)| charging),
+        }));
+    }
+    match battery {
+        Some((level, charging)) => outln!(
+            ctx,
+            "{}%{}",
+            level,
+            if charging { " (charging)" } else { "" }
+        ),
+        None => outln!(ctx, "unknown"),
+    }
+    Ok(())
+}
+
+async fn cmd_sleep_timeout(ctx: &Ctx, device: &str, minutes: Option<u32>) -> Result<()> {
+    let client = &ctx.client;
+    let dev_path = client.resolve_device(device).await?;
+    let current = client.get_device_sleep_timeout(&dev_path).await?;
+    if current == 0 {
+        anyhow::bail!("Sleep timeout is not supported on this device.");
+    }
+    match minutes {
+        Some(value) => {
+            let (min, max) = client.get_device_sleep_timeout_range(&dev_path).await?;
+            if !(min..=max).contains(&value) {
+                anyhow::bail!(
+                    "Sleep timeout {} is out of range (supported: {}-{} minutes).",
+                    value,
+                    min,
+                    max
+                );
+            }
+            client.set_device_sleep_timeout(&dev_path, value).await?;
+            auto_commit(ctx, &dev_path).await?;
+            outln!(ctx, "Sleep timeout set to {} minutes.", value);
+        }
+        None => outln!(ctx, "{}", current),
+    }
+    Ok(())
+}
+
 async fn cmd_profile_rate(
-    client: &RatbagClient,
+    ctx: &Ctx,
     device: &str,
     profile: u32,
     rate: u32,
 ) -> Result<()> {
+    let client = &ctx.client;
     let dev_path = client.resolve_device(device).await?;
-    let profile_path = format!("{}/p{}", dev_path, profile);
+    let profile_path = client.profile_path(&dev_path, profile).await?;
+    let rates = client
+        .get_profile_report_rates(&profile_path)
+        .await
+        .unwrap_or_default();
+    if !rates.is_empty() && !rates.contains(&rate) {
+        anyhow::bail!("Report rate {} Hz is not supported (supported: {:?})", rate, rates);
+    }
     client.set_profile_report_rate(&profile_path, rate).await?;
-    auto_commit(client, &profile_path).await?;
-    println!("Profile {} report rate set to {} Hz.", profile, rate);
+    auto_commit(ctx, &profile_path).await?;
+    outln!(ctx, "Profile {} report rate set to {} Hz.", profile, rate);
+    if has_global_report_rate(client, &profile_path).await {
+        outln!(
+            ctx,
+            "Note: this device has a single report rate; all profiles now use {} Hz.",
+            rate
+        );
+    }
     Ok(())
 }
 
-async fn cmd_profile_angle_snapping(
-    client: &RatbagClient,
-    device: &str,
-    profile: u32,
-    value: Option<String>,
-) -> Result<()> {
+/// Profile capability advertised by devices whose report rate is device-wide.
+const PROFILE_CAP_GLOBAL_REPORT_RATE: u32 = 104;
+
+async fn has_global_report_rate(client: &RatbagClient, profile_path: &str) -> bool {
+    client
+        .get_profile_capabilities(profile_path)
+        .await
+        .is_ok_and(|caps| caps.contains(&PROFILE_CAP_GLOBAL_REPORT_RATE))
+}
+
+async fn cmd_profile_copy(ctx: &Ctx, device: &str, src: u32, dst: u32) -> Result<()> {
+    if src == dst {
+        anyhow::bail!("Source and destination are both profile {}", src);
+    }
+    let client = &ctx.client;
     let dev_path = client.resolve_device(device).await?;
-    let profile_path = format!("{}/p{}", dev_path, profile);
-    match value {
-        Some(v) => {
+    let from = backup::read_profile(client, &client.profile_path(&dev_path, src).await?).await?;
+    let to = backup::read_profile(client, &client.profile_path(&dev_path, dst).await?).await?;
+
+    let plan = backup::plan_copy(&from, &to)?;
+    for note in &plan.skipped {
+        outln!(ctx, "Warning: {}.", note);
+    }
+    if plan.steps.is_empty() {
+        outln!(ctx, "Profile {} already matches profile {}.", dst, src);
+        return Ok(());
+    }
+
+    let mut applied = 0;
+    for step in &plan.steps {
+        match backup::apply(client, &dev_path, step).await {
+            Ok(()) => applied += 1,
+            Err(e) if dbus_client::is_unsupported(&e) => {
+                outln!(ctx, "Skipped {}: {}", step, e.root_cause());
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    auto_commit(ctx, &dev_path).await?;
+    outln!(
+        ctx,
+        "Copied profile {} to profile {} ({} of {} changes).",
+        src,
+        dst,
+        applied,
+        plan.steps.len()
+    );
+    Ok(())
+}
+
+async fn cmd_profile_angle_snapping(
+    ctx: &Ctx,
+    device: &str,
+    profile: u32,
+    value: Option<String>,
+) -> Result<()> {
+    let client = &ctx.client;
+    let dev_path = client.resolve_device(device).await?;
+    let profile_path = client.profile_path(&dev_path, profile).await?;
+    match value {
+        Some(v) => {
             let val = match v.to_lowercase().as_str() {
                 "on" | "1" | "true" | "yes" => 1,
                 "off" | "0" | "false" | "no" => 0,
@@ -820,8 +2368,9 @@ async fn cmd_profile_angle_snapping(
             client
                 .set_profile_angle_snapping(&profile_path, val)
                 .await?;
-            auto_commit(client, &profile_path).await?;
-            println!(
+            auto_commit(ctx, &profile_path).await?;
+            outln!(
+                ctx,
                 "Profile {} angle snapping set to {}.",
                 profile,
                 if val == 1 { "on" } else { "off" }
@@ -830,9 +2379,9 @@ async fn cmd_profile_angle_snapping(
         None => {
             let angle = client.get_profile_angle_snapping(&profile_path).await?;
             if angle < 0 {
-                println!("Angle snapping is not supported on this device.");
+                outln!(ctx, "Angle snapping is not supported on this device.");
             } else {
-                println!("{}", if angle == 1 { "on" } else { "off" });
+                outln!(ctx, "{}", if angle == 1 { "on" } else { "off" });
             }
         }
     }
@@ -840,18 +2389,19 @@ async fn cmd_profile_angle_snapping(
 }
 
 async fn cmd_profile_debounce(
-    client: &RatbagClient,
+    ctx: &Ctx,
     device: &str,
     profile: u32,
     ms: Option<i32>,
 ) -> Result<()> {
+    let client = &ctx.client;
     let dev_path = client.resolve_device(device).await?;
-    let profile_path = format!("{}/p{}", dev_path, profile);
+    let profile_path = client.profile_path(&dev_path, profile).await?;
     match ms {
         Some(val) => {
             client.set_profile_debounce(&profile_path, val).await?;
-            auto_commit(client, &profile_path).await?;
-            println!("Profile {} debounce set to {} ms.", profile, val);
+            auto_commit(ctx, &profile_path).await?;
+            outln!(ctx, "Profile {} debounce set to {} ms.", profile, val);
         }
         None => {
             let debounce = client.get_profile_debounce(&profile_path).await?;
@@ -860,11 +2410,11 @@ async fn cmd_profile_debounce(
                 .await
                 .unwrap_or_default();
             if debounce < 0 {
-                println!("Debounce is not supported on this device.");
+                outln!(ctx, "Debounce is not supported on this device.");
             } else {
-                println!("Current: {} ms", debounce);
+                outln!(ctx, "Current: {} ms", debounce);
                 if !debounces.is_empty() {
-                    println!("Supported: {:?}", debounces);
+                    outln!(ctx, "Supported: {:?}", debounces);
                 }
             }
         }
@@ -872,126 +2422,259 @@ async fn cmd_profile_debounce(
     Ok(())
 }
 
-async fn cmd_resolution_list(client: &RatbagClient, device: &str, profile: u32) -> Result<()> {
+async fn cmd_profile_lod(
+    ctx: &Ctx,
+    device: &str,
+    profile: u32,
+    level: Option<u32>,
+) -> Result<()> {
+    let client = &ctx.client;
     let dev_path = client.resolve_device(device).await?;
-    let profile_path = format!("{}/p{}", dev_path, profile);
-    let resolutions = client.get_profile_resolutions(&profile_path).await?;
-    for res_path in &resolutions {
-        let idx = client.get_resolution_index(res_path).await?;
-        let dpi = client.get_resolution_dpi(res_path).await?;
-        let active = client.get_resolution_is_active(res_path).await?;
-        let default = client.get_resolution_is_default(res_path).await?;
-        let disabled = client.get_resolution_is_disabled(res_path).await?;
-        let caps = client
+    let profile_path = client.profile_path(&dev_path, profile).await?;
+    let levels = client
+        .get_profile_lift_off_distances(&profile_path)
+        .await
+        .unwrap_or_default();
+    if levels.is_empty() {
+        outln!(ctx, "Lift-off distance is not supported on this device.");
+        return Ok(());
+    }
+    match level {
+        Some(val) => {
+            if !levels.contains(&val) {
+                anyhow::bail!("Lift-off distance level {} is not supported (supported: {:?})", val, levels);
+            }
+            client.set_profile_lift_off_distance(&profile_path, val).await?;
+            auto_commit(ctx, &profile_path).await?;
+            outln!(ctx, "Profile {} lift-off distance set to level {}.", profile, val);
+        }
+        None => {
+            let current = client.get_profile_lift_off_distance(&profile_path).await?;
+            outln!(ctx, "Current: level {}", current);
+            outln!(ctx, "Supported: {:?}", levels);
+        }
+    }
+    Ok(())
+}
+
+/* `dpi` as `--percent` shows it: relative to the highest entry of
+ * `dpi_list`, or absolute when the device reports no list. */
+fn format_dpi_relative(dpi: (u32, u32), dpi_list: &[u32], percent: bool) -> String {
+    match dpi_list.iter().max() {
+        Some(&max) if percent && max > 0 => dbus_client::format_dpi_percent(dpi, max),
+        _ => dbus_client::format_dpi(dpi),
+    }
+}
+
+async fn cmd_resolution_list(ctx: &Ctx, device: &str, profile: u32, percent: bool) -> Result<()> {
+    let client = &ctx.client;
+    let dev_path = client.resolve_device(device).await?;
+    let profile_path = client.profile_path(&dev_path, profile).await?;
+    let mut details = Vec::new();
+    for res_path in client.get_profile_resolutions(&profile_path).await? {
+        details.push(read_resolution_detail(client, &res_path, percent).await?);
+    }
+    if ctx.opts.format == OutputFormat::Json {
+        let resolutions: Vec<_> = details.iter().map(ResolutionDetail::to_json).collect();
+        return ctx.out.json(&json!({ "resolutions": resolutions }));
+    }
+    for detail in &details {
+        outln!(ctx, "{}", detail.list_line(&ctx.opts));
+    }
+    Ok(())
+}
+
+async fn read_resolution_detail(
+    client: &RatbagClient,
+    res_path: &str,
+    percent: bool,
+) -> Result<ResolutionDetail> {
+    Ok(ResolutionDetail {
+        percent,
+        index: client.get_resolution_index(res_path).await?,
+        dpi: client.get_resolution_dpi_xy(res_path).await?,
+        is_active: client.get_resolution_is_active(res_path).await?,
+        is_default: client.get_resolution_is_default(res_path).await?,
+        is_disabled: client.get_resolution_is_disabled(res_path).await?,
+        capabilities: client
             .get_resolution_capabilities(res_path)
             .await
-            .unwrap_or_default();
-        let dpi_list = client
+            .unwrap_or_default(),
+        dpi_list: client
             .get_resolution_dpi_list(res_path)
             .await
-            .unwrap_or_default();
-        let mut flags = Vec::new();
-        if active {
-            flags.push("[active]");
-        }
-        if default {
-            flags.push("[default]");
-        }
-        if disabled {
-            flags.push("[disabled]");
-        }
-        let flags_str = if flags.is_empty() {
-            String::new()
-        } else {
-            format!(" {}", flags.join(" "))
-        };
-        let dpi_info = if dpi_list.is_empty() {
-            String::new()
-        } else {
-            format!(" (supported: {:?})", dpi_list)
-        };
-        let caps_info = if caps.is_empty() {
-            String::new()
-        } else {
-            format!(" caps={:?}", caps)
-        };
-        println!("Resolution {}: {}{}{}{}", idx, dpi, flags_str, dpi_info, caps_info);
+            .unwrap_or_default(),
+    })
+}
+
+async fn cmd_resolution_info(
+    ctx: &Ctx,
+    device: &str,
+    profile: u32,
+    resolution: u32,
+    percent: bool,
+) -> Result<()> {
+    let client = &ctx.client;
+    let dev_path = client.resolve_device(device).await?;
+    let res_path = client.resolution_path(&dev_path, profile, resolution).await?;
+    let detail = read_resolution_detail(client, &res_path, percent).await?;
+    if ctx.opts.format == OutputFormat::Json {
+        return ctx.out.json(&detail.to_json());
+    }
+    for line in detail.text_lines(&ctx.opts) {
+        outln!(ctx, "{}", line);
     }
     Ok(())
 }
 
 async fn cmd_resolution_dpi(
-    client: &RatbagClient,
+    ctx: &Ctx,
     device: &str,
     profile: u32,
     resolution: u32,
     dpi: Option<u32>,
+    y: Option<u32>,
+    percent: bool,
 ) -> Result<()> {
+    let client = &ctx.client;
     let dev_path = client.resolve_device(device).await?;
-    let res_path = format!("{}/p{}/r{}", dev_path, profile, resolution);
-    match dpi {
-        Some(val) => {
+    let res_path = client.resolution_path(&dev_path, profile, resolution).await?;
+    match (dpi, y) {
+        (Some(x), Some(y)) => {
+            client.set_resolution_dpi_xy(&res_path, x, y).await?;
+            auto_commit(ctx, &res_path).await?;
+            outln!(ctx, "Resolution {} DPI set to {}x{}.", resolution, x, y);
+        }
+        (Some(val), None) => {
             client.set_resolution_dpi(&res_path, val).await?;
-            auto_commit(client, &res_path).await?;
-            println!("Resolution {} DPI set to {}.", resolution, val);
+            auto_commit(ctx, &res_path).await?;
+            outln!(ctx, "Resolution {} DPI set to {}.", resolution, val);
         }
-        None => {
-            let current = client.get_resolution_dpi(&res_path).await?;
+        (None, _) => {
+            let current = client.get_resolution_dpi_xy(&res_path).await?;
             let dpi_list = client
                 .get_resolution_dpi_list(&res_path)
                 .await
                 .unwrap_or_default();
-            println!("{}", current);
+            outln!(ctx, "{}", format_dpi_relative(current, &dpi_list, percent));
             if !dpi_list.is_empty() {
-                println!("Supported: {:?}", dpi_list);
+                outln!(ctx, "Supported: {:?}", dpi_list);
             }
         }
     }
     Ok(())
 }
 
+/* The entry of `dpi_list` next to `dpi`: the smallest one above it when
+ * stepping up, the largest one below it otherwise.  `None` at either end
+ * of the list.  `dpi` need not be in the list itself. */
+fn step_dpi(dpi: u32, dpi_list: &[u32], up: bool) -> Option<u32> {
+    let candidates = dpi_list.iter().copied();
+    if up {
+        candidates.filter(|&v| v > dpi).min()
+    } else {
+        candidates.filter(|&v| v < dpi).max()
+    }
+}
+
+async fn cmd_resolution_step(
+    ctx: &Ctx,
+    device: &str,
+    profile: u32,
+    resolution: u32,
+    up: bool,
+) -> Result<()> {
+    let client = &ctx.client;
+    let dev_path = client.resolve_device(device).await?;
+    let res_path = client.resolution_path(&dev_path, profile, resolution).await?;
+    let (x, y) = client.get_resolution_dpi_xy(&res_path).await?;
+    let dpi_list = client
+        .get_resolution_dpi_list(&res_path)
+        .await
+        .unwrap_or_default();
+    if dpi_list.is_empty() {
+        anyhow::bail!(
+            "Resolution {} reports no supported DPI values to step through",
+            resolution
+        );
+    }
+
+    /* Separate axes move one entry each. */
+    let (new_x, new_y) = (step_dpi(x, &dpi_list, up), step_dpi(y, &dpi_list, up));
+    if new_x.is_none() && new_y.is_none() {
+        let limit = if up { "highest" } else { "lowest" };
+        outln!(
+            ctx,
+            "Resolution {} is already at its {} value, {}.",
+            resolution,
+            limit,
+            dbus_client::format_dpi((x, y))
+        );
+        return Ok(());
+    }
+    let dpi = (new_x.unwrap_or(x), new_y.unwrap_or(y));
+    client.set_resolution_dpi_xy(&res_path, dpi.0, dpi.1).await?;
+    auto_commit(ctx, &res_path).await?;
+    outln!(ctx, "Resolution {} set to {}.", resolution, dbus_client::format_dpi(dpi));
+    Ok(())
+}
+
 async fn cmd_resolution_active(
-    client: &RatbagClient,
+    ctx: &Ctx,
     device: &str,
     profile: u32,
     resolution: u32,
 ) -> Result<()> {
+    let client = &ctx.client;
     let dev_path = client.resolve_device(device).await?;
-    let res_path = format!("{}/p{}/r{}", dev_path, profile, resolution);
+    let res_path = client.resolution_path(&dev_path, profile, resolution).await?;
     client.call_resolution_set_active(&res_path).await?;
-    auto_commit(client, &res_path).await?;
-    println!("Resolution {} set as active.", resolution);
+    auto_commit(ctx, &res_path).await?;
+    outln!(ctx, "Resolution {} set as active.", resolution);
     Ok(())
 }
 
 async fn cmd_resolution_default(
-    client: &RatbagClient,
+    ctx: &Ctx,
     device: &str,
     profile: u32,
     resolution: u32,
 ) -> Result<()> {
+    let client = &ctx.client;
     let dev_path = client.resolve_device(device).await?;
-    let res_path = format!("{}/p{}/r{}", dev_path, profile, resolution);
+    let res_path = client.resolution_path(&dev_path, profile, resolution).await?;
     client.call_resolution_set_default(&res_path).await?;
-    auto_commit(client, &res_path).await?;
-    println!("Resolution {} set as default.", resolution);
+    auto_commit(ctx, &res_path).await?;
+    outln!(ctx, "Resolution {} set as default.", resolution);
     Ok(())
 }
 
 async fn cmd_resolution_enable_disable(
-    client: &RatbagClient,
+    ctx: &Ctx,
     device: &str,
     profile: u32,
     resolution: u32,
     disable: bool,
 ) -> Result<()> {
+    let client = &ctx.client;
     let dev_path = client.resolve_device(device).await?;
-    let res_path = format!("{}/p{}/r{}", dev_path, profile, resolution);
+    let res_path = client.resolution_path(&dev_path, profile, resolution).await?;
+    /* The daemon rejects this too; checking first gives a clearer message. */
+    if disable && client.get_resolution_is_active(&res_path).await? {
+        anyhow::bail!(
+            "Resolution {} is the active resolution and cannot be disabled. \
+             Activate another one first with `ratbagctl resolution active {} {} <resolution>`.",
+            resolution,
+            device,
+            profile
+        );
+    }
     client
         .set_resolution_is_disabled(&res_path, disable)
         .await?;
-    auto_commit(client, &res_path).await?;
-    println!(
+    auto_commit(ctx, &res_path).await?;
+    outln!(
+        ctx,
         "Resolution {} {}.",
         resolution,
         if disable { "disabled" } else { "enabled" }
@@ -999,41 +2682,76 @@ async fn cmd_resolution_enable_disable(
     Ok(())
 }
 
-async fn cmd_button_list(client: &RatbagClient, device: &str, profile: u32) -> Result<()> {
+async fn cmd_button_list(ctx: &Ctx, device: &str, profile: u32, resolved: bool) -> Result<()> {
+    let client = &ctx.client;
     let dev_path = client.resolve_device(device).await?;
-    let profile_path = format!("{}/p{}", dev_path, profile);
+    let profile_path = client.profile_path(&dev_path, profile).await?;
     let buttons = client.get_profile_buttons(&profile_path).await?;
+    if ctx.opts.format == OutputFormat::Json {
+        let mut entries = Vec::with_capacity(buttons.len());
+        for btn_path in &buttons {
+            let mut value = serde_json::to_value(backup::read_button(client, btn_path).await?)?;
+            let position = client.get_button_position(btn_path).await.unwrap_or_default();
+            value["position"] = json!((!position.is_empty()).then_some(position));
+            entries.push(value);
+        }
+        return ctx.out.json(&json!({ "buttons": entries }));
+    }
     for btn_path in &buttons {
         let idx = client.get_button_index(btn_path).await?;
-        let (action_type, mapping_val) = client.get_button_mapping(btn_path).await?;
-        println!(
-            "Button {}: type={} value={}",
-            idx,
-            action_type_name(action_type),
-            mapping_val
-        );
+        let (action_type, mapping) = client.get_button_mapping_value(btn_path).await?;
+        let mut line = button_line(idx, action_type, &mapping, resolved);
+        let position = client.get_button_position(btn_path).await.unwrap_or_default();
+        if !position.is_empty() {
+            line.push_str(&format!("  ({})", position_hint(&position)));
+        }
+        outln!(ctx, "{}", line);
     }
     Ok(())
 }
 
+/// One `button list` line; `resolved` decodes the value into names.
+fn button_line(index: u32, action_type: u32, mapping: &ButtonMapping, resolved: bool) -> String {
+    let value = if resolved {
+        names::resolve_mapping(action_type, mapping)
+    } else {
+        mapping.to_string()
+    };
+    format!(
+        "Button {}: type={} value={}",
+        index,
+        action_type_name(action_type),
+        value
+    )
+}
+
 async fn cmd_button_get(
-    client: &RatbagClient,
+    ctx: &Ctx,
     device: &str,
     profile: u32,
     button: u32,
 ) -> Result<()> {
+    let client = &ctx.client;
     let dev_path = client.resolve_device(device).await?;
-    let btn_path = format!("{}/p{}/b{}", dev_path, profile, button);
-    let (action_type, mapping_val) = client.get_button_mapping(&btn_path).await?;
+    let btn_path = client.button_path(&dev_path, profile, button).await?;
     let action_types = client.get_button_action_types(&btn_path).await?;
-    println!("Button {}:", button);
-    println!(
+    if ctx.opts.format == OutputFormat::Json {
+        let mut value = serde_json::to_value(backup::read_button(client, &btn_path).await?)?;
+        value["supported_action_types"] =
+            json!(action_types.iter().map(|t| action_type_name(*t)).collect::<Vec<_>>());
+        return ctx.out.json(&value);
+    }
+    let (action_type, mapping_val) = client.get_button_mapping(&btn_path).await?;
+    outln!(ctx, "Button {}:", button);
+    outln!(
+        ctx,
         "  Action type: {} ({})",
         action_type_name(action_type),
         action_type
     );
-    println!("  Value:       {}", mapping_val);
-    println!(
+    outln!(ctx, "  Value:       {}", mapping_val);
+    outln!(
+        ctx,
         "  Supported:   {:?}",
         action_types
             .iter()
@@ -1044,20 +2762,22 @@ async fn cmd_button_get(
 }
 
 async fn cmd_button_set(
-    client: &RatbagClient,
+    ctx: &Ctx,
     device: &str,
     profile: u32,
     button: u32,
     action_type: u32,
     value: u32,
 ) -> Result<()> {
+    let client = &ctx.client;
     let dev_path = client.resolve_device(device).await?;
-    let btn_path = format!("{}/p{}/b{}", dev_path, profile, button);
+    let btn_path = client.button_path(&dev_path, profile, button).await?;
     client
         .set_button_mapping(&btn_path, action_type, value)
         .await?;
-    auto_commit(client, &btn_path).await?;
-    println!(
+    auto_commit(ctx, &btn_path).await?;
+    outln!(
+        ctx,
         "Button {} set to {}={}.",
         button,
         action_type_name(action_type),
@@ -1066,34 +2786,81 @@ async fn cmd_button_set(
     Ok(())
 }
 
+async fn cmd_button_reset(ctx: &Ctx, device: &str, profile: u32, button: u32) -> Result<()> {
+    let client = &ctx.client;
+    let dev_path = client.resolve_device(device).await?;
+    let btn_path = client.button_path(&dev_path, profile, button).await?;
+    client.call_button_reset(&btn_path).await?;
+    auto_commit(ctx, &btn_path).await?;
+    let (action_type, mapping) = client.get_button_mapping(&btn_path).await?;
+    outln!(
+        ctx,
+        "Button {} reset to {}={}.",
+        button,
+        action_type_name(action_type),
+        mapping
+    );
+    Ok(())
+}
+
 async fn cmd_button_set_macro(
-    client: &RatbagClient,
+    ctx: &Ctx,
     device: &str,
     profile: u32,
     button: u32,
+    repeat: Option<&str>,
     events: &[String],
 ) -> Result<()> {
+    let client = &ctx.client;
     let parsed = parse_macro_events(events)?;
+    let repeat = repeat.map(parse_macro_repeat).transpose()?;
     let dev_path = client.resolve_device(device).await?;
-    let btn_path = format!("{}/p{}/b{}", dev_path, profile, button);
+    let btn_path = client.button_path(&dev_path, profile, button).await?;
     client
         .set_button_macro_mapping(&btn_path, &parsed)
         .await?;
-    auto_commit(client, &btn_path).await?;
-    println!("Button {} set to macro ({} events).", button, parsed.len());
+    if let Some(repeat) = repeat {
+        client.set_button_macro_repeat(&btn_path, repeat).await?;
+    }
+    auto_commit(ctx, &btn_path).await?;
+    match repeat {
+        Some(0) => outln!(
+            ctx,
+            "Button {} set to macro ({} events, repeats while held).",
+            button,
+            parsed.len()
+        ),
+        Some(n) if n > 1 => outln!(
+            ctx,
+            "Button {} set to macro ({} events, plays {} times).",
+            button,
+            parsed.len(),
+            n
+        ),
+        _ => outln!(ctx, "Button {} set to macro ({} events).", button, parsed.len()),
+    }
     Ok(())
 }
 
-async fn cmd_led_list(client: &RatbagClient, device: &str, profile: u32) -> Result<()> {
+async fn cmd_led_list(ctx: &Ctx, device: &str, profile: u32) -> Result<()> {
+    let client = &ctx.client;
     let dev_path = client.resolve_device(device).await?;
-    let profile_path = format!("{}/p{}", dev_path, profile);
+    let profile_path = client.profile_path(&dev_path, profile).await?;
     let leds = client.get_profile_leds(&profile_path).await?;
+    if ctx.opts.format == OutputFormat::Json {
+        let mut entries = Vec::with_capacity(leds.len());
+        for led_path in &leds {
+            entries.push(backup::read_led(client, led_path).await?);
+        }
+        return ctx.out.json(&json!({ "leds": entries }));
+    }
     for led_path in &leds {
         let idx = client.get_led_index(led_path).await?;
         let mode = client.get_led_mode(led_path).await?;
         let (r, g, b) = client.get_led_color(led_path).await?;
         let bright = client.get_led_brightness(led_path).await?;
-        println!(
+        outln!(
+            ctx,
             "LED {}: mode={} color=#{:02x}{:02x}{:02x} brightness={}",
             idx,
             led_mode_name(mode),
@@ -1107,122 +2874,284 @@ async fn cmd_led_list(client: &RatbagClient, device: &str, profile: u32) -> Resu
 }
 
 async fn cmd_led_get(
-    client: &RatbagClient,
+    ctx: &Ctx,
     device: &str,
     profile: u32,
     led: u32,
 ) -> Result<()> {
+    let client = &ctx.client;
     let dev_path = client.resolve_device(device).await?;
-    let led_path = format!("{}/p{}/l{}", dev_path, profile, led);
-    let mode = client.get_led_mode(&led_path).await?;
+    let led_path = client.led_path(&dev_path, profile, led).await?;
     let modes = client.get_led_modes(&led_path).await?;
-    let (r, g, b) = client.get_led_color(&led_path).await?;
-    let (sr, sg, sb) = client.get_led_secondary_color(&led_path).await?;
-    let (tr, tg, tb) = client.get_led_tertiary_color(&led_path).await?;
-    let bright = client.get_led_brightness(&led_path).await?;
-    let duration = client.get_led_effect_duration(&led_path).await?;
     let depth = client.get_led_color_depth(&led_path).await.unwrap_or(0);
-    println!("LED {}:", led);
-    println!("  Mode:            {}", led_mode_name(mode));
-    println!("  Color:           #{:02x}{:02x}{:02x}", r, g, b);
-    println!("  Secondary color: #{:02x}{:02x}{:02x}", sr, sg, sb);
-    println!("  Tertiary color:  #{:02x}{:02x}{:02x}", tr, tg, tb);
-    println!("  Brightness:      {}", bright);
-    println!("  Duration:        {} ms", duration);
-    println!("  Color depth:     {}", color_depth_name(depth));
-    println!(
-        "  Supported modes: {:?}",
-        modes
-            .iter()
-            .map(|m| led_mode_name(*m))
-            .collect::<Vec<_>>()
-    );
+    if ctx.opts.format == OutputFormat::Json {
+        let mut value = serde_json::to_value(backup::read_led(client, &led_path).await?)?;
+        value["color_depth"] = json!(color_depth_name(depth));
+        value["supported_modes"] = json!(modes.iter().map(|m| led_mode_name(*m)).collect::<Vec<_>>());
+        return ctx.out.json(&value);
+    }
+    let detail = LedDetail {
+        index: led,
+        mode: client.get_led_mode(&led_path).await?,
+        colors: [
+            client.get_led_color(&led_path).await?,
+            client.get_led_secondary_color(&led_path).await?,
+            client.get_led_tertiary_color(&led_path).await?,
+        ],
+        brightness: client.get_led_brightness(&led_path).await?,
+        duration: client.get_led_effect_duration(&led_path).await?,
+        direction: client.get_led_effect_direction(&led_path).await.ok(),
+        color_depth: depth,
+        modes,
+    };
+    for line in detail.lines() {
+        outln!(ctx, "{}", line);
+    }
     Ok(())
 }
 
+/// Everything `led get` reports about one LED.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct LedDetail {
+    index: u32,
+    mode: u32,
+    /* Primary, secondary and tertiary color. */
+    colors: [(u32, u32, u32); 3],
+    brightness: u32,
+    duration: u32,
+    direction: Option<u32>,
+    color_depth: u32,
+    modes: Vec<u32>,
+}
+
+impl LedDetail {
+    /// The `led get` text, showing only the settings the current mode
+    /// uses: the rate and direction of animated modes are left out for
+    /// solid and off.  An unknown mode shows everything.
+    fn lines(&self) -> Vec<String> {
+        let name = led_mode_name(self.mode);
+        let known = name != "unknown";
+        let uses = |modes: &[&str]| !known || modes.contains(&name);
+
+        let mut lines = vec![
+            format!("LED {}:", self.index),
+            format!("  Mode:            {name}"),
+        ];
+        let labels = ["Color:          ", "Secondary color:", "Tertiary color: "];
+        let color_modes: [&[&str]; 3] = [
+            &["solid", "breathing", "starlight", "tricolor"],
+            &["breathing", "starlight", "tricolor"],
+            &["tricolor"],
+        ];
+        for ((label, modes), (r, g, b)) in labels.iter().zip(color_modes).zip(self.colors) {
+            if uses(modes) {
+                lines.push(format!("  {label} #{r:02x}{g:02x}{b:02x}"));
+            }
+        }
+        if self.mode != 0 {
+            lines.push(format!("  Brightness:      {}", self.brightness));
+        }
+        if uses(&["cycle", "breathing", "wave", "starlight"]) {
+            lines.push(format!("  Duration:        {} ms", self.duration));
+        }
+        if let Some(direction) = self.direction.filter(|_| uses(&["wave"])) {
+            let name = LedDirection::from_dbus(direction).map_or("unknown", LedDirection::name);
+            lines.push(format!("  Direction:       {name}"));
+        }
+        lines.push(format!("  Color depth:     {}", color_depth_name(self.color_depth)));
+        lines.push(format!(
+            "  Supported modes: {:?}",
+            self.modes.iter().map(|m| led_mode_name(*m)).collect::<Vec<_>>()
+        ));
+        lines
+    }
+}
+
 async fn cmd_led_mode(
-    client: &RatbagClient,
+    ctx: &Ctx,
     device: &str,
     profile: u32,
     led: u32,
     mode: &str,
 ) -> Result<()> {
+    let client = &ctx.client;
     let mode_val = parse_led_mode(mode)?;
     let dev_path = client.resolve_device(device).await?;
-    let led_path = format!("{}/p{}/l{}", dev_path, profile, led);
+    let led_path = client.led_path(&dev_path, profile, led).await?;
+    check_led_mode(led, mode_val, &client.get_led_modes(&led_path).await?)?;
     client.set_led_mode(&led_path, mode_val).await?;
-    auto_commit(client, &led_path).await?;
-    println!("LED {} mode set to {}.", led, mode);
+    auto_commit(ctx, &led_path).await?;
+    outln!(ctx, "LED {} mode set to {}.", led, mode);
+    Ok(())
+}
+
+async fn cmd_led_off_all(ctx: &Ctx, device: &str, profile: u32) -> Result<()> {
+    let client = &ctx.client;
+    let dev_path = client.resolve_device(device).await?;
+    let profile_path = client.profile_path(&dev_path, profile).await?;
+    let leds = client.get_profile_leds(&profile_path).await?;
+    let mut changed = 0;
+    for led_path in &leds {
+        let idx = client.get_led_index(led_path).await?;
+        if client.get_led_mode(led_path).await? == 0 {
+            continue;
+        }
+        if !client.get_led_modes(led_path).await?.contains(&0) {
+            outln!(ctx, "Skipped LED {}: it cannot be turned off", idx);
+            continue;
+        }
+        match client.set_led_mode(led_path, 0).await {
+            Ok(()) => changed += 1,
+            /* Unmanaged LEDs are read-only; leave them and carry on. */
+            Err(e) if dbus_client::is_unsupported(&e) => {
+                outln!(ctx, "Skipped LED {}: {}", idx, e.root_cause());
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    if changed == 0 {
+        outln!(ctx, "All LEDs of profile {} are already off.", profile);
+        return Ok(());
+    }
+    auto_commit(ctx, &dev_path).await?;
+    outln!(ctx, "Turned off {} of {} LEDs.", changed, leds.len());
+    Ok(())
+}
+
+async fn cmd_led_live_color(ctx: &Ctx, device: &str, led: u32, color: &str) -> Result<()> {
+    let client = &ctx.client;
+    let (r, g, b) = parse_hex_color(color)?;
+    let dev_path = client.resolve_device(device).await?;
+    if client.set_led_color_live(&dev_path, led, r, g, b).await? {
+        outln!(ctx, "LED {} showing #{:02x}{:02x}{:02x}.", led, r, g, b);
+    } else {
+        outln!(ctx, "LED {} update dropped: too soon after the last one.", led);
+    }
     Ok(())
 }
 
 async fn cmd_led_color(
-    client: &RatbagClient,
+    ctx: &Ctx,
     device: &str,
     profile: u32,
     led: u32,
     color: &str,
     which: &str,
 ) -> Result<()> {
+    let client = &ctx.client;
     let (r, g, b) = parse_hex_color(color)?;
     let dev_path = client.resolve_device(device).await?;
-    let led_path = format!("{}/p{}/l{}", dev_path, profile, led);
+    let led_path = client.led_path(&dev_path, profile, led).await?;
     match which {
         "SecondaryColor" => client.set_led_secondary_color(&led_path, r, g, b).await?,
         "TertiaryColor" => client.set_led_tertiary_color(&led_path, r, g, b).await?,
         _ => client.set_led_color(&led_path, r, g, b).await?,
     }
-    auto_commit(client, &led_path).await?;
+    auto_commit(ctx, &led_path).await?;
     let label = match which {
         "SecondaryColor" => "secondary color",
         "TertiaryColor" => "tertiary color",
         _ => "color",
     };
-    println!("LED {} {} set to #{:02x}{:02x}{:02x}.", led, label, r, g, b);
+    outln!(ctx, "LED {} {} set to #{:02x}{:02x}{:02x}.", led, label, r, g, b);
     Ok(())
 }
 
 async fn cmd_led_brightness(
-    client: &RatbagClient,
+    ctx: &Ctx,
     device: &str,
     profile: u32,
     led: u32,
     value: u32,
 ) -> Result<()> {
+    let client = &ctx.client;
     let dev_path = client.resolve_device(device).await?;
-    let led_path = format!("{}/p{}/l{}", dev_path, profile, led);
+    let led_path = client.led_path(&dev_path, profile, led).await?;
     client.set_led_brightness(&led_path, value).await?;
-    auto_commit(client, &led_path).await?;
-    println!("LED {} brightness set to {}.", led, value);
+    auto_commit(ctx, &led_path).await?;
+    outln!(ctx, "LED {} brightness set to {}.", led, value);
     Ok(())
 }
 
 async fn cmd_led_duration(
-    client: &RatbagClient,
+    ctx: &Ctx,
     device: &str,
     profile: u32,
     led: u32,
     ms: u32,
 ) -> Result<()> {
+    let client = &ctx.client;
     let dev_path = client.resolve_device(device).await?;
-    let led_path = format!("{}/p{}/l{}", dev_path, profile, led);
+    let led_path = client.led_path(&dev_path, profile, led).await?;
     client.set_led_effect_duration(&led_path, ms).await?;
-    auto_commit(client, &led_path).await?;
-    println!("LED {} effect duration set to {} ms.", led, ms);
+    auto_commit(ctx, &led_path).await?;
+    outln!(ctx, "LED {} effect duration set to {} ms.", led, ms);
+    Ok(())
+}
+
+async fn cmd_led_direction(
+    ctx: &Ctx,
+    device: &str,
+    profile: u32,
+    led: u32,
+    direction: LedDirection,
+) -> Result<()> {
+    let client = &ctx.client;
+    let dev_path = client.resolve_device(device).await?;
+    let led_path = client.led_path(&dev_path, profile, led).await?;
+    client
+        .set_led_effect_direction(&led_path, direction.to_dbus())
+        .await?;
+    auto_commit(ctx, &led_path).await?;
+    outln!(ctx, "LED {} effect direction set to {}.", led, direction.name());
     Ok(())
 }
 
-async fn cmd_test_load_device(client: &RatbagClient, json_file: &str) -> Result<()> {
+async fn cmd_test_load_device(ctx: &Ctx, json_file: &str) -> Result<()> {
+    let client = &ctx.client;
     let json = std::fs::read_to_string(json_file)
         .with_context(|| format!("Cannot read file '{}'", json_file))?;
     let path = client.load_test_device(&json).await?;
-    println!("Test device loaded at {}.", path);
+    outln!(ctx, "Test device loaded at {}.", path);
     Ok(())
 }
 
-async fn cmd_test_reset(client: &RatbagClient) -> Result<()> {
+async fn cmd_test_reset(ctx: &Ctx) -> Result<()> {
+    let client = &ctx.client;
     client.reset_test_device().await?;
-    println!("All test devices removed.");
+    outln!(ctx, "All test devices removed.");
+    Ok(())
+}
+
+async fn cmd_test_hid_descriptor(ctx: &Ctx, device: &str) -> Result<()> {
+    let client = &ctx.client;
+    let dev_path = client.resolve_device(device).await?;
+    let desc = client.get_hid_descriptor(&dev_path).await?;
+    if ctx.opts.format == OutputFormat::Json {
+        let hex: String = desc.iter().map(|b| format!("{:02x}", b)).collect();
+        return ctx.out.json(&json!({ "size": desc.len(), "descriptor": hex }));
+    }
+    outln!(ctx, "Report descriptor: {} bytes", desc.len());
+    for line in hid_descriptor::describe(&desc) {
+        outln!(ctx, "{}", line);
+    }
+    Ok(())
+}
+
+async fn cmd_test_raw(ctx: &Ctx, device: &str, hex: &str, feature: bool) -> Result<()> {
+    let client = &ctx.client;
+    let dev_path = client.resolve_device(device).await?;
+    let data = parse_hex_bytes(hex)?;
+    let reply = client.send_raw_report(&dev_path, feature, &data).await?;
+    let hex: Vec<String> = reply.iter().map(|b| format!("{:02x}", b)).collect();
+    if ctx.opts.format == OutputFormat::Json {
+        return ctx.out.json(&json!({ "reply": hex.concat() }));
+    }
+    if reply.is_empty() {
+        outln!(ctx, "No reply.");
+    } else {
+        outln!(ctx, "{}", hex.join(" "));
+    }
     Ok(())
 }
 
@@ -1230,6 +3159,241 @@ async fn cmd_test_reset(client: &RatbagClient) -> Result<()> {
 // Helpers
 // ---------------------------------------------------------------------------
 
+/// Everything `info` reports about a device.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct DeviceSummary {
+    path: String,
+    name: String,
+    model: String,
+    kind: Option<DeviceKind>,
+    /// Bus type, vendor ID and product ID, if the daemon reports them.
+    bus_ids: Option<(String, u32, u32)>,
+    /// Empty when the device reports none.
+    serial: String,
+    firmware: String,
+    /// Whether the profiles stored on the device failed their checksums.
+    needs_repair: bool,
+    profiles: Vec<ProfileSummary>,
+    /// Physical position of each button by index; empty when the device
+    /// file has no `ButtonLayout`.
+    button_layout: Vec<String>,
+    /// Battery level in percent and charging state, if the device reports one.
+    battery: Option<(u32, bool)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ProfileSummary {
+    index: u32,
+    name: String,
+    report_rate: u32,
+    is_active: bool,
+}
+
+impl DeviceSummary {
+    fn to_json(&self) -> serde_json::Value {
+        let profiles: Vec<_> = self
+            .profiles
+            .iter()
+            .map(|p| {
+                json!({
+                    "index": p.index,
+                    "name": p.name,
+                    "report_rate": p.report_rate,
+                    "is_active": p.is_active,
+                })
+            })
+            .collect();
+        json!({
+            "path": self.path,
+            "name": self.name,
+            "model": self.model,
+            "device_type": self.kind.map(DeviceKind::name),
+            "bus_type": self.bus_ids.as_ref().map(|(bus, _, _)| bus),
+            "vendor_id": self.bus_ids.as_ref().map(|(_, vid, _)| format!("{vid:04x}")),
+            "product_id": self.bus_ids.as_ref().map(|(_, _, pid)| format!("{pid:04x}")),
+            "serial": self.serial,
+            "firmware": self.firmware,
+            "needs_repair": self.needs_repair,
+            "profiles": profiles,
+            "button_layout": self.button_layout,
+            "battery": self.battery.map(|(level, charging)| json!({
+                "level": level,
+                "charging": charging,
+            })),
+        })
+    }
+
+    fn text_lines(&self, opts: &Options) -> Vec<String> {
+        let mut lines = vec![
+            format!("Device:    {}", self.name),
+            format!("Model:     {}", self.model),
+        ];
+        if let Some(kind) = self.kind {
+            lines.push(format!("Type:      {}", kind.name()));
+        }
+        if let Some((bus, vid, pid)) = &self.bus_ids {
+            lines.push(format!("IDs:       {bus} {vid:04x}:{pid:04x}"));
+        }
+        if !self.serial.is_empty() {
+            lines.push(format!("Serial:    {}", self.serial));
+        }
+        if !self.firmware.is_empty() {
+            lines.push(format!("Firmware:  {}", self.firmware));
+        }
+        if self.needs_repair {
+            lines.push(
+                "Warning:   stored profiles are corrupted; `ratbagctl repair` rewrites them"
+                    .to_string(),
+            );
+        }
+        if let Some((level, charging)) = self.battery {
+            lines.push(format!(
+                "Battery:   {}%{}",
+                level,
+                if charging { " (charging)" } else { "" }
+            ));
+        }
+        lines.push(format!("Profiles:  {}", self.profiles.len()));
+        for p in &self.profiles {
+            let name_display = if p.name.is_empty() {
+                String::new()
+            } else {
+                format!(" \"{}\"", p.name)
+            };
+            lines.push(format!(
+                "  Profile {}{}: rate={}Hz{}",
+                p.index,
+                name_display,
+                p.report_rate,
+                if p.is_active { format!(" {}", opts.marker("[active]")) } else { String::new() }
+            ));
+        }
+        if self.button_layout.iter().any(|p| !p.is_empty()) {
+            lines.push("Buttons:".to_string());
+            for (index, position) in self.button_layout.iter().enumerate() {
+                if !position.is_empty() {
+                    lines.push(format!("  Button {}: {}", index, position_hint(position)));
+                }
+            }
+        }
+        lines
+    }
+}
+
+/// Everything `resolution info` reports about one resolution slot.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ResolutionDetail {
+    index: u32,
+    dpi: (u32, u32),
+    is_active: bool,
+    is_default: bool,
+    is_disabled: bool,
+    capabilities: Vec<u32>,
+    dpi_list: Vec<u32>,
+    /* `--percent`: show the DPI relative to the highest supported value. */
+    percent: bool,
+}
+
+impl ResolutionDetail {
+    fn to_json(&self) -> serde_json::Value {
+        let (x, y) = self.dpi;
+        json!({
+            "index": self.index,
+            "dpi": { "x": x, "y": y },
+            "is_active": self.is_active,
+            "is_default": self.is_default,
+            "is_disabled": self.is_disabled,
+            "capabilities": self
+                .capabilities
+                .iter()
+                .map(|c| resolution_capability_name(*c))
+                .collect::<Vec<_>>(),
+            "dpi_list": self.dpi_list,
+        })
+    }
+
+    fn flags(&self, opts: &Options) -> Vec<String> {
+        let mut flags = Vec::new();
+        if self.is_active {
+            flags.push(opts.marker("[active]"));
+        }
+        if self.is_default {
+            flags.push(opts.marker("[default]"));
+        }
+        if self.is_disabled {
+            flags.push(opts.marker("[disabled]"));
+        }
+        flags
+    }
+
+    /// The one-line form used by `resolution list`.
+    fn list_line(&self, opts: &Options) -> String {
+        let mut line = format!(
+            "Resolution {}: {}",
+            self.index,
+            format_dpi_relative(self.dpi, &self.dpi_list, self.percent)
+        );
+        let flags = self.flags(opts);
+        if !flags.is_empty() {
+            line.push_str(&format!(" {}", flags.join(" ")));
+        }
+        if !self.dpi_list.is_empty() {
+            line.push_str(&format!(" (supported: {:?})", self.dpi_list));
+        }
+        if !self.capabilities.is_empty() {
+            line.push_str(&format!(" caps={:?}", self.capabilities));
+        }
+        line
+    }
+
+    fn text_lines(&self, opts: &Options) -> Vec<String> {
+        let flags = self.flags(opts);
+        let mut lines = vec![
+            format!("Resolution {}:", self.index),
+            format!("  DPI:          {}", format_dpi_relative(self.dpi, &self.dpi_list, self.percent)),
+        ];
+        if !flags.is_empty() {
+            lines.push(format!("  Flags:        {}", flags.join(" ")));
+        }
+        if !self.capabilities.is_empty() {
+            let caps: Vec<_> = self
+                .capabilities
+                .iter()
+                .map(|c| resolution_capability_name(*c))
+                .collect();
+            lines.push(format!("  Capabilities: {}", caps.join(", ")));
+        }
+        if let (Some(min), Some(max)) = (self.dpi_list.iter().min(), self.dpi_list.iter().max()) {
+            lines.push(format!(
+                "  Supported:    {}-{} DPI ({} values)",
+                min,
+                max,
+                self.dpi_list.len()
+            ));
+        }
+        lines
+    }
+}
+
+/// One-line top view of the mouse, front up, with `#` (or an arrow) on the
+/// named position, followed by the position name.  Side buttons sit on the
+/// left flank: `'` towards the front, `.` towards the back.  Positions not
+/// in the table are shown by name only.
+fn position_hint(position: &str) -> String {
+    let sketch = match position {
+        "left" => " (#| )",
+        "middle" => " ( # )",
+        "right" => " ( |#)",
+        "wheel-left" => " (<| )",
+        "wheel-right" => " ( |>)",
+        "top" => " ( ^ )",
+        "side-front" => "'(   )",
+        "side-rear" => ".(   )",
+        _ => return position.to_string(),
+    };
+    format!("{} {}", sketch, position)
+}
+
 fn action_type_name(t: u32) -> &'static str {
     match t {
         0 => "none",
@@ -1241,15 +3405,24 @@ fn action_type_name(t: u32) -> &'static str {
     }
 }
 
+fn resolution_capability_name(c: u32) -> &'static str {
+    match c {
+        1 => "individual-report-rate",
+        2 => "separate-xy",
+        3 => "disable",
+        _ => "unknown",
+    }
+}
+
 fn led_mode_name(m: u32) -> &'static str {
     match m {
         0 => "off",
         1 => "solid",
-        3 => "cycle",
+        2 => "cycle",
+        3 => "breathing",
         4 => "wave",
         5 => "starlight",
-        10 => "breathing",
-        32 => "tricolor",
+        6 => "tricolor",
         _ => "unknown",
     }
 }
@@ -1266,11 +3439,11 @@ fn parse_led_mode(s: &str) -> Result<u32> {
     match s.to_lowercase().as_str() {
         "off" => Ok(0),
         "solid" => Ok(1),
-        "cycle" => Ok(3),
+        "cycle" => Ok(2),
+        "breathing" | "breathe" => Ok(3),
         "wave" | "colorwave" | "color-wave" => Ok(4),
         "starlight" => Ok(5),
-        "breathing" | "breathe" => Ok(10),
-        "tricolor" | "tri-color" => Ok(32),
+        "tricolor" | "tri-color" => Ok(6),
         _ => anyhow::bail!(
             "Unknown LED mode '{}'. Use: off, solid, cycle, wave, starlight, breathing, tricolor",
             s
@@ -1278,6 +3451,20 @@ fn parse_led_mode(s: &str) -> Result<u32> {
     }
 }
 
+/// Refuse a mode the LED does not advertise, naming the ones it does.
+fn check_led_mode(led: u32, mode: u32, supported: &[u32]) -> Result<()> {
+    if !supported.contains(&mode) {
+        let names: Vec<_> = supported.iter().map(|m| led_mode_name(*m)).collect();
+        anyhow::bail!(
+            "LED {} does not support mode {} (supported: {})",
+            led,
+            led_mode_name(mode),
+            names.join(", ")
+        );
+    }
+    Ok(())
+}
+
 fn parse_hex_color(s: &str) -> Result<(u32, u32, u32)> {
     let s = s.strip_prefix('#').unwrap_or(s);
     anyhow::ensure!(
@@ -1290,6 +3477,44 @@ fn parse_hex_color(s: &str) -> Result<(u32, u32, u32)> {
     Ok((r, g, b))
 }
 
+/// Parse report bytes written as hex, either run together ("10ff001a") or
+/// separated by spaces or colons ("10 ff 00 1a", "10:ff:00:1a").
+fn parse_hex_bytes(s: &str) -> Result<Vec<u8>> {
+    let digits: Vec<u8> = s
+        .bytes()
+        .filter(|&c| !c.is_ascii_whitespace() && c != b':')
+        .collect();
+    anyhow::ensure!(!digits.is_empty(), "No report bytes given");
+    if let Some(&c) = digits.iter().find(|c| !c.is_ascii_hexdigit()) {
+        anyhow::bail!("Invalid hex digit '{}' in '{}'", char::from(c), s);
+    }
+    anyhow::ensure!(
+        digits.len().is_multiple_of(2),
+        "Report bytes must be whole hex pairs, got '{}'",
+        s
+    );
+    Ok(digits
+        .chunks(2)
+        .map(|pair| {
+            let nibble = |c: u8| char::from(c).to_digit(16).unwrap_or(0) as u8;
+            nibble(pair[0]) << 4 | nibble(pair[1])
+        })
+        .collect())
+}
+
+/// Parse a `--repeat` value into the daemon's `MacroRepeat` encoding:
+/// "hold" is 0 (repeat while held), a number N >= 1 plays the macro N times.
+fn parse_macro_repeat(value: &str) -> Result<u32> {
+    if value.eq_ignore_ascii_case("hold") {
+        return Ok(0);
+    }
+    let count: u32 = value
+        .parse()
+        .with_context(|| format!("Invalid repeat '{}'. Expected a count or \"hold\"", value))?;
+    anyhow::ensure!(count >= 1, "Repeat count must be at least 1, got {}", count);
+    Ok(count)
+}
+
 /// Parse macro events from CLI arguments.
 ///
 /// Each argument is "KEYCODE:DIRECTION" where DIRECTION is 1 (press) or 0 (release).
@@ -1319,3 +3544,487 @@ fn parse_macro_events(events: &[String]) -> Result<Vec<(u32, u32)>> {
     }
     Ok(parsed)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    #[test]
+    fn test_cli_definition_is_consistent() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn test_led_mode_numbers_match_the_daemon() {
+        let names = ["off", "solid", "cycle", "breathing", "wave", "starlight", "tricolor"];
+        for (value, name) in names.iter().enumerate() {
+            assert_eq!(led_mode_name(value as u32), *name);
+            assert_eq!(parse_led_mode(name).unwrap(), value as u32);
+        }
+    }
+
+    #[test]
+    fn test_led_color_does_not_clash_with_global_color() {
+        let cli = Cli::try_parse_from(["ratbagctl", "--color", "never", "led", "color", "0", "0", "1", "ff0000"])
+            .unwrap();
+        assert_eq!(cli.color, Some(ColorChoice::Never));
+        let Commands::Led(LedCmd::Color { led, color, .. }) = cli.command else {
+            panic!("expected led color");
+        };
+        assert_eq!((led, color.as_str()), (1, "ff0000"));
+    }
+
+    fn parse_config(text: &str) -> Config {
+        toml::from_str(text).expect("valid config")
+    }
+
+    #[test]
+    fn test_version_lists_client_and_daemon() {
+        let daemon = DaemonVersion {
+            version: "1.2.3".into(),
+            api_version: 2,
+            drivers: vec!["asus".into(), "hidpp20".into()],
+        };
+        let lines = version_lines(Some(&daemon));
+        assert_eq!(
+            lines,
+            [
+                format!("ratbagctl {}", env!("CARGO_PKG_VERSION")),
+                "ratbagd 1.2.3".to_string(),
+                "API version: 2".to_string(),
+                "Drivers: asus, hidpp20".to_string(),
+            ]
+        );
+
+        let lines = version_lines(None);
+        assert_eq!(lines.len(), 2);
+        assert!(lines[1].starts_with("ratbagd: not running"));
+    }
+
+    #[test]
+    fn test_config_default_is_applied() {
+        let cli = Cli::try_parse_from(["ratbagctl", "list"]).unwrap();
+        let config = parse_config("bus = \"session\"\nno-commit = true\nformat = \"json\"\ncolor = \"never\"\n");
+        let opts = Options::merge(&cli, &config);
+        assert_eq!(opts.bus, BusKind::Session);
+        assert!(opts.no_commit);
+        assert_eq!(opts.format, OutputFormat::Json);
+        assert_eq!(opts.color, ColorChoice::Never);
+    }
+
+    #[test]
+    fn test_cli_flag_overrides_config() {
+        let cli = Cli::try_parse_from([
+            "ratbagctl", "--bus", "system", "--commit", "--format", "text", "--color", "always",
+            "list",
+        ])
+        .unwrap();
+        let config = parse_config("bus = \"session\"\nno-commit = true\nformat = \"json\"\ncolor = \"never\"\n");
+        let opts = Options::merge(&cli, &config);
+        assert_eq!(opts.bus, BusKind::System);
+        assert!(!opts.no_commit);
+        assert_eq!(opts.format, OutputFormat::Text);
+        assert_eq!(opts.color, ColorChoice::Always);
+    }
+
+    #[test]
+    fn test_parse_hex_bytes() {
+        assert_eq!(parse_hex_bytes("10ff001A").unwrap(), [0x10, 0xff, 0x00, 0x1a]);
+        assert_eq!(parse_hex_bytes("10 ff:00  1a").unwrap(), [0x10, 0xff, 0x00, 0x1a]);
+        assert!(parse_hex_bytes("").is_err());
+        assert!(parse_hex_bytes("10f").is_err());
+        assert!(parse_hex_bytes("10 zz").is_err());
+        assert!(parse_hex_bytes("10é").is_err());
+
+        let cli = Cli::try_parse_from(["ratbagctl", "test", "raw", "0", "10", "ff", "--feature"]);
+        let Ok(Commands::Test(TestCmd::Raw { hex_bytes, feature, .. })) = cli.map(|c| c.command)
+        else {
+            panic!("test raw did not parse");
+        };
+        assert_eq!(hex_bytes, ["10", "ff"]);
+        assert!(feature);
+    }
+
+    #[test]
+    fn test_json_flag_selects_json_format() {
+        let cli = Cli::try_parse_from(["ratbagctl", "led", "get", "0", "0", "0", "--json"]).unwrap();
+        let config = parse_config("format = \"text\"\n");
+        assert_eq!(Options::merge(&cli, &config).format, OutputFormat::Json);
+
+        assert!(Cli::try_parse_from(["ratbagctl", "--json", "--format", "text", "list"]).is_err());
+    }
+
+    #[test]
+    fn test_builtin_defaults_without_config() {
+        let cli = Cli::try_parse_from(["ratbagctl", "list"]).unwrap();
+        let opts = Options::merge(&cli, &Config::default());
+        assert_eq!(opts.bus, BusKind::System);
+        assert!(!opts.no_commit);
+        assert_eq!(opts.format, OutputFormat::Text);
+        assert_eq!(opts.color, ColorChoice::Auto);
+    }
+
+    #[test]
+    fn test_parse_macro_repeat() {
+        assert_eq!(parse_macro_repeat("hold").unwrap(), 0);
+        assert_eq!(parse_macro_repeat("3").unwrap(), 3);
+        assert!(parse_macro_repeat("0").is_err());
+        assert!(parse_macro_repeat("twice").is_err());
+
+        let cli = Cli::try_parse_from([
+            "ratbagctl", "button", "set-macro", "0", "0", "4", "--count", "2", "30:1", "30:0",
+        ])
+        .unwrap();
+        let Commands::Button(ButtonCmd::SetMacro { repeat, events, .. }) = cli.command else {
+            panic!("expected button set-macro");
+        };
+        assert_eq!(repeat.as_deref(), Some("2"));
+        assert_eq!(events, vec!["30:1", "30:0"]);
+    }
+
+    #[test]
+    fn test_button_list_resolved_shows_key_name() {
+        let key_a = ButtonMapping::Value(30);
+        assert_eq!(button_line(2, 3, &key_a, false), "Button 2: type=key value=30");
+        assert_eq!(button_line(2, 3, &key_a, true), "Button 2: type=key value=KEY_A");
+        assert_eq!(
+            button_line(0, 1, &ButtonMapping::Value(1), true),
+            "Button 0: type=button value=left"
+        );
+    }
+
+    #[test]
+    fn test_info_shows_button_layout_hint() {
+        let summary = DeviceSummary {
+            path: "/org/freedesktop/ratbag1/device/hidraw0".into(),
+            name: "Test Mouse".into(),
+            model: "usb:046d:c539:0".into(),
+            kind: None,
+            bus_ids: None,
+            serial: String::new(),
+            firmware: String::new(),
+            needs_repair: false,
+            profiles: vec![],
+            button_layout: vec!["left".into(), "right".into(), String::new(), "dpi-shift".into()],
+            battery: None,
+        };
+        let lines = summary.text_lines(&plain_options());
+        let buttons = lines.iter().position(|l| l == "Buttons:").unwrap();
+        assert_eq!(
+            lines[buttons + 1..],
+            ["  Button 0:  (#| ) left", "  Button 1:  ( |#) right", "  Button 3: dpi-shift"]
+        );
+        assert_eq!(summary.to_json()["button_layout"][3], "dpi-shift");
+    }
+
+    #[test]
+    fn test_info_warns_about_corrupted_profiles() {
+        let summary = DeviceSummary {
+            path: "/org/freedesktop/ratbag1/device/hidraw0".into(),
+            name: "Test Mouse".into(),
+            model: "usb:046d:c539:0".into(),
+            kind: None,
+            bus_ids: None,
+            serial: String::new(),
+            firmware: "1.2".into(),
+            needs_repair: true,
+            profiles: vec![],
+            button_layout: vec![],
+            battery: None,
+        };
+        let lines = summary.text_lines(&plain_options());
+        assert!(lines[3].starts_with("Warning:   stored profiles are corrupted"));
+        assert_eq!(summary.to_json()["needs_repair"], true);
+    }
+
+    #[test]
+    fn test_info_shows_type_ids_and_serial() {
+        let summary = DeviceSummary {
+            path: "/org/freedesktop/ratbag1/device/hidraw0".into(),
+            name: "Test Mouse".into(),
+            model: "usb:046d:c539:0".into(),
+            kind: Some(DeviceKind::Mouse),
+            bus_ids: Some(("usb".into(), 0x046d, 0xc539)),
+            serial: "S1234ABCDE".into(),
+            firmware: String::new(),
+            needs_repair: false,
+            profiles: vec![],
+            button_layout: vec![],
+            battery: None,
+        };
+        let lines = summary.text_lines(&plain_options());
+        assert_eq!(
+            lines[2..5],
+            ["Type:      mouse", "IDs:       usb 046d:c539", "Serial:    S1234ABCDE"]
+        );
+        let json = summary.to_json();
+        assert_eq!(json["serial"], "S1234ABCDE");
+        assert_eq!(json["device_type"], "mouse");
+        assert_eq!(json["bus_type"], "usb");
+        assert_eq!(json["vendor_id"], "046d");
+        assert_eq!(json["product_id"], "c539");
+    }
+
+    #[test]
+    fn test_resolution_dpi_takes_optional_y() {
+        let parse = |args: &[&str]| {
+            let args = ["ratbagctl", "resolution", "dpi", "0", "0", "1"].iter().chain(args);
+            match Cli::try_parse_from(args).map(|cli| cli.command) {
+                Ok(Commands::Resolution(ResolutionCmd::Dpi { dpi, y, .. })) => Some((dpi, y)),
+                _ => None,
+            }
+        };
+        assert_eq!(parse(&[]), Some((None, None)));
+        assert_eq!(parse(&["1600"]), Some((Some(1600), None)));
+        assert_eq!(parse(&["1600", "800"]), Some((Some(1600), Some(800))));
+        assert_eq!(parse(&["--percent", "1600"]), None);
+    }
+
+    #[test]
+    fn test_env_device_fills_in_only_a_missing_device() {
+        let parse = |args: &[&str]| {
+            let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+            parse_args(&args, Some("hidraw3")).map(|(cli, usage)| (cli.command, usage.is_some()))
+        };
+        assert!(matches!(
+            parse(&["ratbagctl", "info", "hidraw0"]),
+            Ok((Commands::Info { device }, false)) if device == "hidraw0"
+        ));
+        assert!(matches!(
+            parse(&["ratbagctl", "profile", "active", "hidraw0", "2"]),
+            Ok((Commands::Profile(ProfileCmd::Active { device, profile: 2 }), false)) if device == "hidraw0"
+        ));
+        assert!(matches!(
+            parse(&["ratbagctl", "profile", "active", "2"]),
+            Ok((Commands::Profile(ProfileCmd::Active { device, profile: 2 }), false)) if device == "hidraw3"
+        ));
+        assert!(matches!(
+            parse(&["ratbagctl", "info"]),
+            Ok((Commands::Info { device }, false)) if device == "hidraw3"
+        ));
+        assert!(parse(&["ratbagctl", "profile", "active"]).is_err());
+    }
+
+    #[test]
+    fn test_device_argument_is_inserted_after_options() {
+        let with_device = |args: &[&str]| {
+            let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+            args_with_device(&args, "hidraw3")
+        };
+        assert_eq!(
+            with_device(&["ratbagctl", "resolution", "dpi", "0", "0", "2000"]),
+            ["ratbagctl", "resolution", "dpi", "hidraw3", "0", "0", "2000"]
+        );
+        assert_eq!(
+            with_device(&["ratbagctl", "--format", "json", "--no-commit", "profile", "list"]),
+            ["ratbagctl", "--format", "json", "--no-commit", "profile", "list", "hidraw3"]
+        );
+        let args = with_device(&["ratbagctl", "--output=x", "info", "--json"]);
+        assert_eq!(args, ["ratbagctl", "--output=x", "info", "--json", "hidraw3"]);
+        assert!(Cli::try_parse_from(args).is_ok());
+        assert_eq!(with_device(&["ratbagctl", "list"]), ["ratbagctl", "list"]);
+    }
+
+    #[test]
+    fn test_step_dpi_moves_one_entry_and_stops_at_the_ends() {
+        /* A HID++ 2.0 range of 100..=1000 in steps of 100, as listed. */
+        let list: Vec<u32> = (1..=10).map(|n| n * 100).collect();
+        assert_eq!(step_dpi(400, &list, true), Some(500));
+        assert_eq!(step_dpi(400, &list, false), Some(300));
+        assert_eq!(step_dpi(1000, &list, true), None);
+        assert_eq!(step_dpi(100, &list, false), None);
+        /* A DPI between two entries snaps to the neighbour. */
+        assert_eq!(step_dpi(450, &list, true), Some(500));
+        assert_eq!(step_dpi(450, &list, false), Some(400));
+        /* Lists need not be sorted. */
+        assert_eq!(step_dpi(800, &[1600, 400, 800, 3200], true), Some(1600));
+    }
+
+    #[test]
+    fn test_smart_shift_threshold_range() {
+        assert!(Cli::try_parse_from(["ratbagctl", "smart-shift", "0", "255"]).is_ok());
+        assert!(Cli::try_parse_from(["ratbagctl", "smart-shift", "0"]).is_ok());
+        assert!(Cli::try_parse_from(["ratbagctl", "smart-shift", "0", "0"]).is_err());
+        assert!(Cli::try_parse_from(["ratbagctl", "smart-shift", "0", "256"]).is_err());
+    }
+
+    #[test]
+    fn test_config_rejects_unknown_keys() {
+        assert!(toml::from_str::<Config>("colour = \"never\"").is_err());
+    }
+
+    #[test]
+    fn test_missing_default_config_is_not_an_error() {
+        let path = Path::new("/nonexistent/ratbagctl/config.toml");
+        assert!(load_config(path, false).is_ok());
+        assert!(load_config(path, true).is_err());
+    }
+
+    /* Mirrors the first resolution of the harness's separate-DPI test device. */
+    fn test_device_resolution() -> ResolutionDetail {
+        ResolutionDetail {
+            index: 0,
+            dpi: (800, 1600),
+            is_active: true,
+            is_default: true,
+            is_disabled: false,
+            capabilities: vec![2],
+            dpi_list: vec![100, 200, 400, 800, 1600],
+            percent: false,
+        }
+    }
+
+    fn plain_options() -> Options {
+        Options {
+            bus: BusKind::System,
+            no_commit: false,
+            batch: false,
+            format: OutputFormat::Text,
+            color: ColorChoice::Never,
+            to_file: false,
+            interactive: false,
+        }
+    }
+
+    #[test]
+    fn test_resolution_info_text() {
+        let lines = test_device_resolution().text_lines(&plain_options());
+        assert_eq!(lines[0], "Resolution 0:");
+        assert_eq!(lines[1], "  DPI:          800x1600 DPI");
+        assert_eq!(lines[2], "  Flags:        [active] [default]");
+        assert_eq!(lines[3], "  Capabilities: separate-xy");
+        assert_eq!(lines[4], "  Supported:    100-1600 DPI (5 values)");
+    }
+
+    #[test]
+    fn test_status_reports_dirty_profiles() {
+        assert_eq!(status_line("hidraw3", false, &[]), "hidraw3: no uncommitted changes.");
+        assert_eq!(
+            status_line("hidraw3", false, &[1]),
+            "hidraw3: profile 1 has uncommitted changes; `ratbagctl commit hidraw3` writes them."
+        );
+        assert!(status_line("hidraw3", false, &[0, 2]).starts_with("hidraw3: profiles 0, 2 have"));
+        assert!(status_line("hidraw3", true, &[]).starts_with("hidraw3: device settings have"));
+        assert!(status_line("hidraw3", true, &[1])
+            .starts_with("hidraw3: device settings and profile 1 have"));
+        assert!(staged_reminder("hidraw3").contains("`ratbagctl commit hidraw3`"));
+    }
+
+    #[test]
+    fn test_resolution_list_line() {
+        assert_eq!(
+            test_device_resolution().list_line(&plain_options()),
+            "Resolution 0: 800x1600 DPI [active] [default] (supported: [100, 200, 400, 800, 1600]) caps=[2]"
+        );
+    }
+
+    #[test]
+    fn test_led_mode_must_be_advertised() {
+        assert!(check_led_mode(0, 3, &[1, 3]).is_ok());
+        let err = check_led_mode(0, 6, &[1, 3]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "LED 0 does not support mode tricolor (supported: solid, breathing)"
+        );
+    }
+
+    #[test]
+    fn test_led_get_shows_only_fields_of_the_mode() {
+        let mut led = LedDetail {
+            index: 0,
+            mode: 4,
+            colors: [(0xff, 0, 0), (0, 0xff, 0), (0, 0, 0xff)],
+            brightness: 200,
+            duration: 3000,
+            direction: Some(1),
+            color_depth: 1,
+            modes: vec![0, 1, 4],
+        };
+        let wave = led.lines();
+        assert!(wave.contains(&"  Duration:        3000 ms".to_string()));
+        assert!(wave.contains(&"  Direction:       counterclockwise".to_string()));
+        assert!(!wave.iter().any(|l| l.contains("Color:")));
+
+        led.mode = 1;
+        let solid = led.lines();
+        assert!(solid.contains(&"  Color:           #ff0000".to_string()));
+        assert!(!solid.iter().any(|l| l.contains("Duration") || l.contains("Direction")));
+        assert!(!solid.iter().any(|l| l.contains("Secondary")));
+
+        led.mode = 0;
+        assert_eq!(led.lines().len(), 4);
+    }
+
+    #[test]
+    fn test_resolution_info_json() {
+        let value = test_device_resolution().to_json();
+        assert_eq!(value["dpi"]["x"], 800);
+        assert_eq!(value["dpi"]["y"], 1600);
+        assert_eq!(value["is_active"], true);
+        assert_eq!(value["is_disabled"], false);
+        assert_eq!(value["capabilities"][0], "separate-xy");
+        assert_eq!(value["dpi_list"].as_array().unwrap().len(), 5);
+    }
+
+    /* Stands in for stdout so a test can capture what a command printed. */
+    #[derive(Clone, Default)]
+    struct Captured(std::rc::Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn write_info(out: &Output, summary: &DeviceSummary) -> Result<()> {
+        for line in summary.text_lines(&plain_options()) {
+            out.line(format_args!("{}", line))?;
+        }
+        out.flush()
+    }
+
+    #[test]
+    fn test_info_output_file_matches_stdout() {
+        let summary = DeviceSummary {
+            path: "/org/freedesktop/ratbag1/device/hidraw0".into(),
+            name: "Test Mouse".into(),
+            model: "usb:046d:c539:0".into(),
+            kind: None,
+            bus_ids: None,
+            serial: String::new(),
+            firmware: String::new(),
+            needs_repair: false,
+            profiles: vec![ProfileSummary {
+                index: 0,
+                name: "Default".into(),
+                report_rate: 1000,
+                is_active: true,
+            }],
+            button_layout: vec![],
+            battery: None,
+        };
+
+        let captured = Captured::default();
+        write_info(&Output::new(Box::new(captured.clone())), &summary).unwrap();
+
+        let file = std::env::temp_dir().join(format!("ratbagctl-output-{}.txt", std::process::id()));
+        let cli = Cli::try_parse_from(["ratbagctl", "--output", file.to_str().unwrap(), "info", "0"])
+            .unwrap();
+        assert!(Options::merge(&cli, &Config::default()).to_file);
+        write_info(&Output::open(cli.output.as_deref()).unwrap(), &summary).unwrap();
+        let written = std::fs::read(&file).unwrap();
+        std::fs::remove_file(&file).unwrap();
+
+        assert_eq!(written, *captured.0.borrow());
+        assert_eq!(
+            String::from_utf8(written).unwrap(),
+            "Device:    Test Mouse\nModel:     usb:046d:c539:0\nProfiles:  1\n  Profile 0 \"Default\": rate=1000Hz [active]\n"
+        );
+    }
+}
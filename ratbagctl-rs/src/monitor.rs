@@ -0,0 +1,207 @@
+/* ratbagctl monitor: prints the daemon's signals as they arrive, one line per changed property or
+ * other signal, each with a timestamp. Unlike `watch` nothing is re-read from the daemon; the
+ * values printed are the ones the signals carry, so the output shows exactly what clients see. */
+//! Signal formatting used by `ratbagctl monitor`.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use serde_json::{json, Value as Json};
+use zbus::zvariant::{OwnedValue, Structure};
+
+const PROPERTIES_IFACE: &str = "org.freedesktop.DBus.Properties";
+const RATBAG_IFACE_PREFIX: &str = "org.freedesktop.ratbag1.";
+
+/// One printed line: a changed property, or any other signal.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MonitorEvent {
+    /// Time since the Unix epoch at which the signal arrived.
+    pub time: Duration,
+    pub path: String,
+    /// Interface without the `org.freedesktop.ratbag1.` prefix.
+    pub interface: String,
+    pub kind: EventKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EventKind {
+    /// A property from `PropertiesChanged`; `None` when it was only
+    /// invalidated and the new value has to be read.
+    Property { name: String, value: Option<String> },
+    /// Any other signal with its arguments, e.g. `DeviceReady`.
+    Signal { name: String, args: String },
+}
+
+impl fmt::Display for MonitorEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {}.", format_time(self.time), self.path, self.interface)?;
+        match &self.kind {
+            EventKind::Property { name, value } => {
+                write!(f, "{} = {}", name, value.as_deref().unwrap_or("(invalidated)"))
+            }
+            EventKind::Signal { name, args } => write!(f, "{}{}", name, args),
+        }
+    }
+}
+
+impl MonitorEvent {
+    pub fn to_json(&self) -> Json {
+        let mut value = json!({
+            "time": self.time.as_secs_f64(),
+            "path": self.path,
+            "interface": self.interface,
+        });
+        match &self.kind {
+            EventKind::Property { name, value: v } => {
+                value["property"] = json!(name);
+                value["value"] = json!(v);
+            }
+            EventKind::Signal { name, args } => {
+                value["signal"] = json!(name);
+                value["args"] = json!(args);
+            }
+        }
+        value
+    }
+}
+
+/// Whether a signal from object `path` is shown when monitoring `device`
+/// (a device object path, or every device when `None`).  The manager's
+/// own signals, such as its `Devices` list changing, are always shown.
+pub fn is_relevant(path: &str, manager: &str, device: Option<&str>) -> bool {
+    match device {
+        None => true,
+        Some(device) => {
+            path == manager
+                || path
+                    .strip_prefix(device)
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+        }
+    }
+}
+
+/// The lines to print for one signal received at `time`.
+pub fn events_from_message(msg: &zbus::Message, time: Duration) -> Result<Vec<MonitorEvent>> {
+    let header = msg.header();
+    let path = header.path().map(|p| p.to_string()).unwrap_or_default();
+    let interface = header.interface().map(|i| i.to_string()).unwrap_or_default();
+    let member = header.member().map(|m| m.to_string()).unwrap_or_default();
+    let short = |iface: &str| iface.strip_prefix(RATBAG_IFACE_PREFIX).unwrap_or(iface).to_string();
+
+    if interface == PROPERTIES_IFACE && member == "PropertiesChanged" {
+        let (changed_iface, changed, invalidated): (String, BTreeMap<String, OwnedValue>, Vec<String>) =
+            msg.body()
+                .deserialize()
+                .context("Malformed PropertiesChanged signal")?;
+        let changed = changed
+            .into_iter()
+            .map(|(name, value)| (name, Some(value.to_string())));
+        let invalidated = invalidated.into_iter().map(|name| (name, None));
+        return Ok(changed
+            .chain(invalidated)
+            .map(|(name, value)| MonitorEvent {
+                time,
+                path: path.clone(),
+                interface: short(&changed_iface),
+                kind: EventKind::Property { name, value },
+            })
+            .collect());
+    }
+
+    let body = msg.body();
+    let args = if body.signature().to_string().is_empty() {
+        "()".to_string()
+    } else {
+        body.deserialize::<Structure<'_>>()
+            .map(|args| args.to_string())
+            .unwrap_or_else(|_| format!("({})", body.signature()))
+    };
+    Ok(vec![MonitorEvent {
+        time,
+        path,
+        interface: short(&interface),
+        kind: EventKind::Signal { name: member, args },
+    }])
+}
+
+/// UTC time of day with milliseconds, e.g. `14:03:27.512`.
+fn format_time(time: Duration) -> String {
+    let secs = time.as_secs() % 86_400;
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60,
+        time.subsec_millis()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MANAGER: &str = "/org/freedesktop/ratbag1";
+    const DEVICE: &str = "/org/freedesktop/ratbag1/device/hidraw3";
+
+    #[test]
+    fn test_properties_changed_prints_one_line_per_property() {
+        let changed: BTreeMap<&str, zbus::zvariant::Value<'_>> =
+            [("Resolution", 1600u32.into()), ("IsActive", true.into())].into();
+        let msg = zbus::Message::signal(format!("{DEVICE}/p0/r1"), PROPERTIES_IFACE, "PropertiesChanged")
+            .unwrap()
+            .build(&("org.freedesktop.ratbag1.Resolution", changed, vec!["IsDefault"]))
+            .unwrap();
+        let time = Duration::from_millis(1_700_000_000_250);
+
+        let lines: Vec<String> = events_from_message(&msg, time)
+            .unwrap()
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                format!("22:13:20.250 {DEVICE}/p0/r1 Resolution.IsActive = true"),
+                format!("22:13:20.250 {DEVICE}/p0/r1 Resolution.Resolution = uint32 1600"),
+                format!("22:13:20.250 {DEVICE}/p0/r1 Resolution.IsDefault = (invalidated)"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_other_signals_print_their_arguments() {
+        let device = zbus::zvariant::ObjectPath::try_from(DEVICE).unwrap();
+        let msg = zbus::Message::signal(MANAGER, "org.freedesktop.ratbag1.Manager", "DeviceReady")
+            .unwrap()
+            .build(&(device,))
+            .unwrap();
+        let events = events_from_message(&msg, Duration::ZERO).unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(
+            events[0].kind,
+            EventKind::Signal {
+                name: "DeviceReady".into(),
+                args: format!("(objectpath \"{DEVICE}\",)"),
+            }
+        );
+        assert_eq!(events[0].to_json()["signal"], "DeviceReady");
+
+        let msg = zbus::Message::signal(DEVICE, "org.freedesktop.ratbag1.Device", "Resync")
+            .unwrap()
+            .build(&())
+            .unwrap();
+        let events = events_from_message(&msg, Duration::ZERO).unwrap();
+        assert_eq!(events[0].to_string(), format!("00:00:00.000 {DEVICE} Device.Resync()"));
+    }
+
+    #[test]
+    fn test_device_filter_keeps_manager_signals() {
+        assert!(is_relevant(&format!("{DEVICE}/p0/l0"), MANAGER, Some(DEVICE)));
+        assert!(is_relevant(DEVICE, MANAGER, Some(DEVICE)));
+        assert!(is_relevant(MANAGER, MANAGER, Some(DEVICE)));
+        assert!(!is_relevant(&format!("{DEVICE}0/p0"), MANAGER, Some(DEVICE)));
+        assert!(is_relevant(&format!("{DEVICE}0/p0"), MANAGER, None));
+    }
+}
@@ -0,0 +1,118 @@
+/* ratbagctl --batch: instead of committing after every write, remember which devices have staged
+ * changes in one small file per device under the user's runtime directory. `ratbagctl flush`
+ * commits those devices and removes their files. */
+//! Pending-commit state for batched writes.
+
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+/// Directory holding the pending-commit files: `$XDG_RUNTIME_DIR/ratbagctl/pending`,
+/// else `$XDG_STATE_HOME/ratbagctl/pending`, else `$HOME/.local/state/ratbagctl/pending`.
+pub fn state_dir() -> Option<PathBuf> {
+    let var = |name| std::env::var_os(name).filter(|v| !v.is_empty()).map(PathBuf::from);
+    let base = var("XDG_RUNTIME_DIR")
+        .or_else(|| var("XDG_STATE_HOME"))
+        .or_else(|| var("HOME").map(|home| home.join(".local").join("state")))?;
+    Some(base.join("ratbagctl").join("pending"))
+}
+
+/// Devices with writes that were staged on the daemon but not committed yet.
+///
+/// Each device gets a file named after its sysname that holds its object path.
+pub struct PendingStore {
+    dir: PathBuf,
+}
+
+impl PendingStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// The store in the default [`state_dir`].
+    pub fn open() -> Result<Self> {
+        state_dir()
+            .map(Self::new)
+            .context("Cannot determine state directory: none of XDG_RUNTIME_DIR, XDG_STATE_HOME or HOME is set")
+    }
+
+    fn file(&self, dev_path: &str) -> PathBuf {
+        let sysname = dev_path.rsplit('/').next().unwrap_or(dev_path);
+        self.dir.join(sysname)
+    }
+
+    /// Note that `dev_path` has staged changes.
+    pub fn mark(&self, dev_path: &str) -> Result<()> {
+        std::fs::create_dir_all(&self.dir)
+            .with_context(|| format!("Cannot create '{}'", self.dir.display()))?;
+        let file = self.file(dev_path);
+        std::fs::write(&file, dev_path)
+            .with_context(|| format!("Cannot write '{}'", file.display()))
+    }
+
+    /// Object paths of every device with staged changes, sorted.
+    pub fn list(&self) -> Result<Vec<String>> {
+        let entries = match std::fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e).with_context(|| format!("Cannot read '{}'", self.dir.display())),
+        };
+        let mut paths = Vec::new();
+        for entry in entries {
+            let file = entry?.path();
+            let path = std::fs::read_to_string(&file)
+                .with_context(|| format!("Cannot read '{}'", file.display()))?;
+            paths.push(path.trim().to_string());
+        }
+        paths.sort();
+        Ok(paths)
+    }
+
+    /// Forget `dev_path`, e.g. once it has been committed.
+    pub fn clear(&self, dev_path: &str) -> Result<()> {
+        remove_if_present(&self.file(dev_path))
+    }
+}
+
+fn remove_if_present(file: &Path) -> Result<()> {
+    match std::fs::remove_file(file) {
+        Err(e) if e.kind() != ErrorKind::NotFound => {
+            Err(e).with_context(|| format!("Cannot remove '{}'", file.display()))
+        }
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pending_file_lifecycle() {
+        let dir = std::env::temp_dir().join(format!("ratbagctl-pending-{}", std::process::id()));
+        let store = PendingStore::new(&dir);
+        let mouse = "/org/freedesktop/ratbag1/device/hidraw3";
+        let keyboard = "/org/freedesktop/ratbag1/device/hidraw7";
+
+        assert!(store.list().unwrap().is_empty());
+
+        /* Several writes to one device leave a single entry. */
+        store.mark(keyboard).unwrap();
+        store.mark(mouse).unwrap();
+        store.mark(mouse).unwrap();
+        assert_eq!(store.list().unwrap(), [mouse, keyboard]);
+        assert!(dir.join("hidraw3").is_file());
+
+        store.clear(mouse).unwrap();
+        assert_eq!(store.list().unwrap(), [keyboard]);
+        assert!(!dir.join("hidraw3").exists());
+
+        /* Clearing twice is harmless. */
+        store.clear(mouse).unwrap();
+        store.clear(keyboard).unwrap();
+        assert!(store.list().unwrap().is_empty());
+
+        std::fs::remove_dir(&dir).unwrap();
+    }
+}
@@ -0,0 +1,213 @@
+/* ratbagctl interactive: a line-based shell bound to one device. Each line is split into words,
+ * the device is inserted where the command expects its `<device>` argument, and the result is
+ * parsed and run like a normal command line over the one open connection. Writes are staged
+ * only; `commit` sends them to the hardware. */
+//! Line handling for `ratbagctl interactive`.
+
+use anyhow::{Result, bail};
+use clap::Command;
+
+/// Help text for the shell's own commands.
+pub const HELP: &str = "\
+Commands are those of ratbagctl without the <device> argument, e.g.
+  profile list
+  resolution dpi 0 1 1600
+  led color 0 0 ff0000
+Changes are staged on the daemon; run `commit` to write them.
+Shell commands:
+  help          show this text
+  history       list earlier commands
+  !!            repeat the last command
+  !N            repeat command N from `history`
+  quit, exit    leave the shell (Ctrl-D works too)";
+
+/// What to do with one input line.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Action {
+    /// Parse and run these arguments (without the program name).
+    Run(Vec<String>),
+    /// Print the shell help.
+    Help,
+    /// Print the history.
+    History,
+    /// Leave the shell.
+    Quit,
+    /// Blank line or comment.
+    Nothing,
+}
+
+/// State of one interactive session.
+pub struct Repl {
+    device: String,
+    history: Vec<String>,
+}
+
+impl Repl {
+    /// A session for `device`, a specifier as `<device>` accepts it.
+    pub fn new(device: impl Into<String>) -> Self {
+        Self {
+            device: device.into(),
+            history: Vec::new(),
+        }
+    }
+
+    pub fn history(&self) -> &[String] {
+        &self.history
+    }
+
+    /// Turn `line` into an action.  `tree` is the full ratbagctl command,
+    /// used to find where the device argument goes.
+    pub fn handle(&mut self, line: &str, tree: &Command) -> Result<Action> {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            return Ok(Action::Nothing);
+        }
+        let line = self.expand(line)?;
+        let words = split_words(&line)?;
+        let action = match words.first().map(String::as_str) {
+            Some("quit" | "exit") => return Ok(Action::Quit),
+            Some("help") => Action::Help,
+            Some("history") => return Ok(Action::History),
+            Some("interactive") => bail!("Already in interactive mode"),
+            _ => Action::Run(insert_device(tree.clone(), words, &self.device)),
+        };
+        self.history.push(line);
+        Ok(action)
+    }
+
+    /* Replace a `!!` or `!N` line with the command it refers to. */
+    fn expand(&self, line: &str) -> Result<String> {
+        let Some(reference) = line.strip_prefix('!') else {
+            return Ok(line.to_string());
+        };
+        let entry = if reference == "!" {
+            self.history.last()
+        } else {
+            let n: usize = reference
+                .parse()
+                .map_err(|_| anyhow::anyhow!("Expected !! or !N, got '{}'", line))?;
+            n.checked_sub(1).and_then(|i| self.history.get(i))
+        };
+        match entry {
+            Some(entry) => Ok(entry.clone()),
+            None => bail!("No such history entry: {}", line),
+        }
+    }
+}
+
+/// Split a line into words.  Single and double quotes group words; a
+/// backslash escapes the next character outside single quotes.
+pub fn split_words(line: &str) -> Result<Vec<String>> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' | '"' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some(q) if q == c => break,
+                        Some('\\') if c == '"' => word.extend(chars.next()),
+                        Some(other) => word.push(other),
+                        None => bail!("Unterminated {} quote", c),
+                    }
+                }
+            }
+            '\\' => word.get_or_insert_with(String::new).extend(chars.next()),
+            c if c.is_whitespace() => words.extend(word.take()),
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+/* Insert `device` after the subcommand names when the command's first
+ * positional argument is `<device>`.  Options before or between the
+ * subcommand names, such as `--json`, are skipped along with their
+ * values. */
+pub fn insert_device(mut tree: Command, mut words: Vec<String>, device: &str) -> Vec<String> {
+    tree.build();
+    let mut cmd = &tree;
+    let mut at = 0;
+    while let Some(word) = words.get(at) {
+        if let Some(sub) = cmd.find_subcommand(word) {
+            cmd = sub;
+            at += 1;
+            continue;
+        }
+        let Some(long) = word.strip_prefix("--").filter(|l| !l.is_empty()) else {
+            break;
+        };
+        let takes_value = !long.contains('=')
+            && cmd
+                .get_arguments()
+                .find(|a| a.get_long() == Some(long))
+                .is_some_and(|a| a.get_action().takes_values());
+        at += if takes_value { 2 } else { 1 };
+    }
+    let wants_device = !cmd.has_subcommands()
+        && cmd
+            .get_positionals()
+            .next()
+            .is_some_and(|a| a.get_id().as_str() == "device");
+    if wants_device {
+        words.insert(at, device.to_string());
+    }
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    #[test]
+    fn test_scripted_session() {
+        let tree = crate::Cli::command();
+        let mut repl = Repl::new("hidraw3");
+        let mut run = |line: &str| repl.handle(line, &tree).unwrap();
+        let args = |words: &[&str]| Action::Run(words.iter().map(|w| w.to_string()).collect());
+
+        assert_eq!(run("profile list"), args(&["profile", "list", "hidraw3"]));
+        assert_eq!(
+            run("resolution dpi 0 1 1600 "),
+            args(&["resolution", "dpi", "hidraw3", "0", "1", "1600"])
+        );
+        assert_eq!(
+            run("profile name 0 'My work'"),
+            args(&["profile", "name", "hidraw3", "0", "My work"])
+        );
+        assert_eq!(run("commit"), args(&["commit", "hidraw3"]));
+        /* Commands without a device argument pass through. */
+        assert_eq!(run("list"), args(&["list"]));
+        assert_eq!(run("   # comment"), Action::Nothing);
+        assert_eq!(run("!2"), args(&["resolution", "dpi", "hidraw3", "0", "1", "1600"]));
+        assert_eq!(run("!!"), args(&["resolution", "dpi", "hidraw3", "0", "1", "1600"]));
+        assert_eq!(run("history"), Action::History);
+        assert_eq!(run("quit"), Action::Quit);
+
+        assert_eq!(
+            repl.history(),
+            [
+                "profile list",
+                "resolution dpi 0 1 1600",
+                "profile name 0 'My work'",
+                "commit",
+                "list",
+                "resolution dpi 0 1 1600",
+                "resolution dpi 0 1 1600",
+            ]
+        );
+        assert!(repl.handle("!99", &tree).is_err());
+        assert!(repl.handle("interactive 0", &tree).is_err());
+    }
+
+    #[test]
+    fn test_split_words_quoting() {
+        assert_eq!(split_words(r#"a "b c" d\ e 'f"g'"#).unwrap(), ["a", "b c", "d e", "f\"g"]);
+        assert_eq!(split_words("''").unwrap(), [""]);
+        assert!(split_words("'open").is_err());
+    }
+}
@@ -0,0 +1,277 @@
+/* ratbagctl selftest: reads every writable setting of a device, writes it back unchanged, commits,
+ * reads again and reports anything that did not round-trip. The original state is restored when
+ * the round-trip fails, and read back once more to check that the restore took. */
+//! Settings round-trip check used by `ratbagctl selftest`.
+
+use anyhow::{Context, Result};
+
+use crate::dbus_client::RatbagClient;
+
+/// A single writable setting, tagged with the value read from the daemon.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Setting {
+    ProfileName(String),
+    ReportRate(u32),
+    Dpi(u32, u32),
+    Button { action_type: u32, value: u32 },
+    LedMode(u32),
+    LedColor(u32, u32, u32),
+    LedBrightness(u32),
+    LedDuration(u32),
+}
+
+/// Every setting of one device, keyed by the object path it lives on.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Snapshot {
+    pub entries: Vec<(String, Setting)>,
+}
+
+impl Snapshot {
+    /// Describe every entry of `self` that differs in `after`.
+    pub fn diff(&self, after: &Snapshot) -> Vec<String> {
+        let mut mismatches = Vec::new();
+        for (path, before) in &self.entries {
+            let found = after
+                .entries
+                .iter()
+                .find(|(p, s)| p == path && std::mem::discriminant(s) == std::mem::discriminant(before));
+            match found {
+                Some((_, now)) if now == before => {}
+                Some((_, now)) => mismatches.push(format!("{}: {:?} -> {:?}", path, before, now)),
+                None => mismatches.push(format!("{}: {:?} missing after commit", path, before)),
+            }
+        }
+        mismatches
+    }
+}
+
+/// Where the self test reads, writes and commits settings.
+pub trait SettingsBackend {
+    async fn snapshot(&self, device: &str) -> Result<Snapshot>;
+    async fn apply(&self, snapshot: &Snapshot) -> Result<()>;
+    async fn commit(&self, device: &str) -> Result<()>;
+}
+
+/// Outcome of a self test run.
+#[derive(Debug, Default)]
+pub struct Report {
+    /// Number of settings checked.
+    pub checked: usize,
+    /// Human-readable description of each setting that did not round-trip.
+    pub mismatches: Vec<String>,
+    /// Set when the original state had to be written back.
+    pub restored: bool,
+    /// Settings that still differ from the original state after the
+    /// restore was committed.
+    pub restore_mismatches: Vec<String>,
+}
+
+/// Run the round-trip on `device`.  On a mismatch or error the original
+/// snapshot is written back, committed and read back before returning.
+pub async fn run<B: SettingsBackend>(backend: &B, device: &str) -> Result<Report> {
+    let before = backend
+        .snapshot(device)
+        .await
+        .context("Reading current settings")?;
+
+    let outcome = async {
+        backend.apply(&before).await.context("Writing settings back")?;
+        backend.commit(device).await.context("Committing")?;
+        let after = backend
+            .snapshot(device)
+            .await
+            .context("Re-reading settings")?;
+        Ok::<_, anyhow::Error>(before.diff(&after))
+    }
+    .await;
+
+    let mut report = Report {
+        checked: before.entries.len(),
+        ..Report::default()
+    };
+
+    match outcome {
+        Ok(mismatches) if mismatches.is_empty() => Ok(report),
+        Ok(mismatches) => {
+            report.restore_mismatches = restore(backend, device, &before).await?;
+            report.mismatches = mismatches;
+            report.restored = true;
+            Ok(report)
+        }
+        Err(e) => match restore(backend, device, &before).await {
+            Err(restore_err) => Err(e.context(format!(
+                "Self test failed and restoring original settings also failed: {restore_err:#}"
+            ))),
+            Ok(left) if !left.is_empty() => Err(e.context(format!(
+                "Self test failed and the original settings did not come back: {}",
+                left.join("; ")
+            ))),
+            Ok(_) => Err(e.context("Self test failed; original settings were restored")),
+        },
+    }
+}
+
+/* Write `before` back, commit it and re-read the device.  Returns the
+ * settings that still differ from `before`. */
+async fn restore<B: SettingsBackend>(
+    backend: &B,
+    device: &str,
+    before: &Snapshot,
+) -> Result<Vec<String>> {
+    backend
+        .apply(before)
+        .await
+        .context("Restoring original settings")?;
+    backend
+        .commit(device)
+        .await
+        .context("Committing restored settings")?;
+    let restored = backend
+        .snapshot(device)
+        .await
+        .context("Re-reading restored settings")?;
+    Ok(before.diff(&restored))
+}
+
+impl SettingsBackend for RatbagClient {
+    async fn snapshot(&self, device: &str) -> Result<Snapshot> {
+        let mut entries = Vec::new();
+        for profile in self.get_device_profiles(device).await? {
+            entries.push((
+                profile.clone(),
+                Setting::ProfileName(self.get_profile_name(&profile).await?),
+            ));
+            entries.push((
+                profile.clone(),
+                Setting::ReportRate(self.get_profile_report_rate(&profile).await?),
+            ));
+            for res in self.get_profile_resolutions(&profile).await? {
+                let (x, y) = self.get_resolution_dpi_xy(&res).await?;
+                entries.push((res, Setting::Dpi(x, y)));
+            }
+            for button in self.get_profile_buttons(&profile).await? {
+                /* Macros are left alone: the display string is lossy. */
+                let (action_type, value) = self.get_button_mapping(&button).await?;
+                if let Ok(value) = value.parse::<u32>() {
+                    entries.push((button, Setting::Button { action_type, value }));
+                }
+            }
+            for led in self.get_profile_leds(&profile).await? {
+                let (r, g, b) = self.get_led_color(&led).await?;
+                entries.push((led.clone(), Setting::LedMode(self.get_led_mode(&led).await?)));
+                entries.push((led.clone(), Setting::LedColor(r, g, b)));
+                entries.push((
+                    led.clone(),
+                    Setting::LedBrightness(self.get_led_brightness(&led).await?),
+                ));
+                entries.push((
+                    led.clone(),
+                    Setting::LedDuration(self.get_led_effect_duration(&led).await?),
+                ));
+            }
+        }
+        Ok(Snapshot { entries })
+    }
+
+    async fn apply(&self, snapshot: &Snapshot) -> Result<()> {
+        for (path, setting) in &snapshot.entries {
+            match setting {
+                Setting::ProfileName(name) => self.set_profile_name(path, name).await?,
+                Setting::ReportRate(rate) => self.set_profile_report_rate(path, *rate).await?,
+                Setting::Dpi(x, y) => self.set_resolution_dpi_xy(path, *x, *y).await?,
+                Setting::Button { action_type, value } => {
+                    self.set_button_mapping(path, *action_type, *value).await?
+                }
+                Setting::LedMode(mode) => self.set_led_mode(path, *mode).await?,
+                Setting::LedColor(r, g, b) => self.set_led_color(path, *r, *g, *b).await?,
+                Setting::LedBrightness(v) => self.set_led_brightness(path, *v).await?,
+                Setting::LedDuration(v) => self.set_led_effect_duration(path, *v).await?,
+            }
+        }
+        Ok(())
+    }
+
+    async fn commit(&self, device: &str) -> Result<()> {
+        self.commit_device(device).await?.check(device)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::{Cell, RefCell};
+
+    /* In-memory device.  `lossy_rate` makes every commit round report
+     * rates down to the next multiple of 500, like firmware with coarse
+     * steps. */
+    struct FakeDevice {
+        state: RefCell<Snapshot>,
+        lossy_rate: bool,
+        commits: Cell<u32>,
+    }
+
+    impl FakeDevice {
+        fn new(lossy_rate: bool) -> Self {
+            Self {
+                state: RefCell::new(Snapshot {
+                    entries: vec![
+                        ("/d/p0".into(), Setting::ProfileName("Default".into())),
+                        ("/d/p0".into(), Setting::ReportRate(750)),
+                        ("/d/p0/r0".into(), Setting::Dpi(800, 800)),
+                        ("/d/p0/b0".into(), Setting::Button { action_type: 1, value: 1 }),
+                        ("/d/p0/l0".into(), Setting::LedColor(255, 0, 0)),
+                    ],
+                }),
+                lossy_rate,
+                commits: Cell::new(0),
+            }
+        }
+    }
+
+    impl SettingsBackend for FakeDevice {
+        async fn snapshot(&self, _device: &str) -> Result<Snapshot> {
+            Ok(self.state.borrow().clone())
+        }
+
+        async fn apply(&self, snapshot: &Snapshot) -> Result<()> {
+            *self.state.borrow_mut() = snapshot.clone();
+            Ok(())
+        }
+
+        async fn commit(&self, _device: &str) -> Result<()> {
+            self.commits.set(self.commits.get() + 1);
+            if self.lossy_rate {
+                for (_, setting) in &mut self.state.borrow_mut().entries {
+                    if let Setting::ReportRate(rate) = setting {
+                        *rate -= *rate % 500;
+                    }
+                }
+            }
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_clean_round_trip() {
+        let device = FakeDevice::new(false);
+        let report = run(&device, "/d").await.unwrap();
+        assert_eq!(report.checked, 5);
+        assert!(report.mismatches.is_empty());
+        assert!(!report.restored);
+        assert_eq!(device.commits.get(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_failed_restore_is_reported() {
+        let device = FakeDevice::new(true);
+        let report = run(&device, "/d").await.unwrap();
+        assert_eq!(report.mismatches.len(), 1);
+        assert!(report.mismatches[0].contains("ReportRate(750)"));
+        assert!(report.restored);
+        assert_eq!(device.commits.get(), 2);
+        /* The restore commit is lossy too, so the device never gets its
+         * original rate back. */
+        assert_eq!(report.restore_mismatches.len(), 1);
+        assert!(report.restore_mismatches[0].contains("ReportRate(500)"));
+    }
+}
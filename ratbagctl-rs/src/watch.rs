@@ -0,0 +1,152 @@
+/* ratbagctl watch: snapshots the user-visible properties of one device and reports what changed
+ * between two snapshots. Snapshots are retaken on every PropertiesChanged signal and, for drivers
+ * that cannot report hardware-side changes, on a fixed poll interval. */
+//! Property snapshots and diffs used by `ratbagctl watch`.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use anyhow::Result;
+
+use crate::dbus_client::RatbagClient;
+
+/// Watched property values of one device, keyed by object path relative to
+/// the device (`""`, `"p0"`, `"p0/r1"`, ...) and property name.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PropertySnapshot {
+    values: BTreeMap<(String, &'static str), String>,
+}
+
+/// One property that differs between two snapshots.  `None` means the
+/// object did not exist in that snapshot.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Change {
+    pub object: String,
+    pub property: &'static str,
+    pub before: Option<String>,
+    pub after: Option<String>,
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.object.is_empty() {
+            write!(f, "{} ", self.object)?;
+        }
+        write!(
+            f,
+            "{}: {} -> {}",
+            self.property,
+            self.before.as_deref().unwrap_or("-"),
+            self.after.as_deref().unwrap_or("-")
+        )
+    }
+}
+
+impl PropertySnapshot {
+    pub fn insert(&mut self, object: &str, property: &'static str, value: impl ToString) {
+        self.values
+            .insert((object.to_string(), property), value.to_string());
+    }
+
+    /// Every property whose value in `after` differs from `self`, in
+    /// object then property order.
+    pub fn diff(&self, after: &PropertySnapshot) -> Vec<Change> {
+        let mut keys: Vec<&(String, &'static str)> =
+            self.values.keys().chain(after.values.keys()).collect();
+        keys.sort();
+        keys.dedup();
+        keys.into_iter()
+            .filter_map(|key| {
+                let before = self.values.get(key);
+                let now = after.values.get(key);
+                (before != now).then(|| Change {
+                    object: key.0.clone(),
+                    property: key.1,
+                    before: before.cloned(),
+                    after: now.cloned(),
+                })
+            })
+            .collect()
+    }
+}
+
+/// Read the watched properties of `device` (a device object path).
+pub async fn read_snapshot(client: &RatbagClient, device: &str) -> Result<PropertySnapshot> {
+    let relative = |path: &str| {
+        path.strip_prefix(device)
+            .unwrap_or(path)
+            .trim_start_matches('/')
+            .to_string()
+    };
+    let mut snapshot = PropertySnapshot::default();
+
+    /* Older daemons lack SmartShift; 0 means unsupported either way. */
+    let smart_shift = client
+        .get_device_smart_shift_threshold(device)
+        .await
+        .unwrap_or(0);
+    if smart_shift != 0 {
+        snapshot.insert("", "SmartShiftThreshold", smart_shift);
+    }
+    snapshot.insert("", "IsDirty", client.get_device_is_dirty(device).await.unwrap_or(false));
+
+    for profile in client.get_device_profiles(device).await? {
+        let p = relative(&profile);
+        snapshot.insert(&p, "Name", format!("{:?}", client.get_profile_name(&profile).await?));
+        snapshot.insert(&p, "IsActive", client.get_profile_is_active(&profile).await?);
+        snapshot.insert(&p, "IsDirty", client.get_profile_is_dirty(&profile).await?);
+        snapshot.insert(&p, "Disabled", client.get_profile_disabled(&profile).await?);
+        snapshot.insert(&p, "ReportRate", client.get_profile_report_rate(&profile).await?);
+
+        for res in client.get_profile_resolutions(&profile).await? {
+            let r = relative(&res);
+            snapshot.insert(&r, "Resolution", client.get_resolution_dpi(&res).await?);
+            snapshot.insert(&r, "IsActive", client.get_resolution_is_active(&res).await?);
+            snapshot.insert(&r, "IsDefault", client.get_resolution_is_default(&res).await?);
+            snapshot.insert(&r, "IsDisabled", client.get_resolution_is_disabled(&res).await?);
+        }
+        for button in client.get_profile_buttons(&profile).await? {
+            let (action_type, mapping) = client.get_button_mapping(&button).await?;
+            snapshot.insert(&relative(&button), "Mapping", format!("{}:{}", action_type, mapping));
+        }
+        for led in client.get_profile_leds(&profile).await? {
+            let l = relative(&led);
+            let (r, g, b) = client.get_led_color(&led).await?;
+            snapshot.insert(&l, "Mode", client.get_led_mode(&led).await?);
+            snapshot.insert(&l, "Color", format!("{:02x}{:02x}{:02x}", r, g, b));
+            snapshot.insert(&l, "Brightness", client.get_led_brightness(&led).await?);
+        }
+    }
+    Ok(snapshot)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff_reports_changed_added_and_removed() {
+        let mut before = PropertySnapshot::default();
+        before.insert("p0", "IsActive", true);
+        before.insert("p0/r0", "Resolution", 800);
+        before.insert("p0/r1", "IsActive", false);
+        before.insert("p1", "IsActive", false);
+
+        let mut after = before.clone();
+        assert!(before.diff(&after).is_empty());
+
+        after.insert("p0/r0", "Resolution", 1600);
+        after.insert("", "SmartShiftThreshold", 20);
+        after.values.remove(&("p1".to_string(), "IsActive"));
+
+        let changes: Vec<String> = before.diff(&after).iter().map(Change::to_string).collect();
+        assert_eq!(
+            changes,
+            [
+                "SmartShiftThreshold: - -> 20",
+                "p0/r0 Resolution: 800 -> 1600",
+                "p1 IsActive: false -> -",
+            ]
+        );
+    }
+}
@@ -7,45 +7,272 @@
 
 use std::path::Path;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use anyhow::{Context, Result};
-use tokio::sync::{mpsc, oneshot, RwLock};
+use tokio::sync::{mpsc, oneshot, watch, RwLock};
 use tracing::{debug, info, warn};
 
-use crate::device::DeviceInfo;
-use crate::driver::{DeviceDriver, DeviceIo};
+use crate::audit::{AuditEntry, AuditLog};
+use crate::device::{BatteryInfo, DeviceInfo, RgbColor};
+use crate::device_database::DriverConfig;
+use crate::driver::{CommitTarget, DeviceDriver, DeviceIo, DriverCandidate, PartialCommit};
+use crate::state_cache::{self, CacheKey, StateCache};
 
 /* Commands that DBus interface objects can send to the device actor. */
 #[derive(Debug)]
 pub enum ActorMessage {
-    /* Commit all pending changes to hardware and report success/failure. */
+    /* Commit pending changes to hardware and report success/failure:
+     * every profile, or only `profile` when set. */
     Commit {
-        reply: oneshot::Sender<Result<(), String>>,
+        profile: Option<u32>,
+        reply: oneshot::Sender<Result<(), CommitError>>,
+    },
+    /* Have the next commit rewrite every stored profile; replies with
+     * whether the driver has such storage. */
+    RequestRepair {
+        reply: oneshot::Sender<bool>,
+    },
+    /* Switch the hardware to a profile without a commit; replies with
+     * whether the driver could. */
+    SwitchProfile {
+        index: u32,
+        reply: oneshot::Sender<Result<bool, String>>,
+    },
+    /* Show a solid LED color right away, outside of the profile; replies
+     * with whether the driver could. */
+    SetLedColorLive {
+        led: u32,
+        color: RgbColor,
+        reply: oneshot::Sender<Result<bool, String>>,
+    },
+    /* Read the battery state; replies with `None` when the device has
+     * no battery. */
+    ReadBattery {
+        reply: oneshot::Sender<Result<Option<BatteryInfo>, String>>,
+    },
+    /* Read the device's HID report descriptor. */
+    ReadHidDescriptor {
+        reply: oneshot::Sender<Result<Vec<u8>, String>>,
+    },
+    /* Send `data` as it is, as an output report or (`feature`) a feature
+     * report, and reply with what the device answered.  For protocol
+     * debugging only. */
+    #[cfg(feature = "dev-hooks")]
+    RawReport {
+        feature: bool,
+        data: Vec<u8>,
+        reply: oneshot::Sender<Result<Vec<u8>, String>>,
     },
     /* Gracefully shut down the actor (e.g., on device removal). */
     Shutdown,
 }
 
+/* Why a commit failed: the error, and what could not be written.  When
+ * the driver got some writes through, `failures` names each one that did
+ * not; otherwise it holds the error once, against the whole device. */
+#[derive(Debug)]
+pub struct CommitError {
+    pub message: String,
+    pub failures: Vec<(CommitTarget, String)>,
+}
+
+impl From<String> for CommitError {
+    fn from(message: String) -> Self {
+        Self {
+            failures: vec![(CommitTarget::Device, message.clone())],
+            message,
+        }
+    }
+}
+
+impl From<anyhow::Error> for CommitError {
+    fn from(e: anyhow::Error) -> Self {
+        match e.downcast_ref::<PartialCommit>() {
+            Some(PartialCommit(failures)) => Self {
+                message: format!("{e:#}"),
+                failures: failures.clone(),
+            },
+            None => format!("{e:#}").into(),
+        }
+    }
+}
+
+impl std::fmt::Display for CommitError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
 /* Handle used by DBus objects to send commands to the device actor. */
 #[derive(Clone)]
 pub struct ActorHandle {
     tx: mpsc::Sender<ActorMessage>,
+    /* Out-of-band cancellation flag.  The `Shutdown` message sits behind
+     * any in-flight commit in the queue, so it is paired with this flag,
+     * which the actor watches *while* the driver is doing I/O. */
+    cancel: Arc<watch::Sender<bool>>,
+    /* Marked changed whenever the device reports a state change made
+     * on the hardware side, e.g. a profile button press. */
+    resync: watch::Receiver<()>,
+    /* Whether the driver found stored profiles failing their checksums,
+     * as of the last load or commit. */
+    needs_repair: watch::Receiver<bool>,
+    /* Whether the profiles were loaded from the device; false while a
+     * device that was asleep at plug-in has not answered yet. */
+    ready: watch::Receiver<bool>,
 }
 
 impl ActorHandle {
-    /* Request the actor to shut down gracefully. */
+    /* Request the actor to shut down gracefully.  Any in-flight driver
+     * operation is aborted so that an unplugged device does not hold up
+     * teardown until its read timeouts expire. */
     pub async fn shutdown(&self) {
+        self.cancel.send_replace(true);
         let _ = self.tx.send(ActorMessage::Shutdown).await;
     }
 
+    /* Changes each time the device's state changed outside of the
+     * daemon; DBus objects re-announce their properties when it does.
+     * Closes when the actor exits. */
+    pub fn resyncs(&self) -> watch::Receiver<()> {
+        self.resync.clone()
+    }
+
+    /* Whether the device's stored profiles need a full rewrite. */
+    pub fn needs_repair(&self) -> bool {
+        *self.needs_repair.borrow()
+    }
+
+    /* Whether the device's profiles reflect the hardware. */
+    pub fn is_ready(&self) -> bool {
+        *self.ready.borrow()
+    }
+
+    /* Becomes true once the device's profiles are loaded, which for a
+     * device asleep at plug-in is when it first answers.  Closes when
+     * the actor exits. */
+    pub fn readiness(&self) -> watch::Receiver<bool> {
+        self.ready.clone()
+    }
+
     /* Request the actor to commit pending changes to hardware.
-     * Returns `Ok(())` on success, or an error string on failure. */
-    pub async fn commit(&self) -> Result<(), String> {
+     * Returns `Ok(())` on success, or what could not be written. */
+    pub async fn commit(&self) -> Result<(), CommitError> {
+        self.send_commit(None).await
+    }
+
+    /* Like `commit`, but write only profile `index`.  Other profiles stay
+     * dirty until a later commit. */
+    pub async fn commit_profile(&self, index: u32) -> Result<(), CommitError> {
+        self.send_commit(Some(index)).await
+    }
+
+    async fn send_commit(&self, profile: Option<u32>) -> Result<(), CommitError> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+
+        self.tx
+            .send(ActorMessage::Commit {
+                profile,
+                reply: reply_tx,
+            })
+            .await
+            .map_err(|_| CommitError::from("Device actor is no longer running".to_string()))?;
+
+        reply_rx
+            .await
+            .map_err(|_| CommitError::from("Device actor dropped the reply channel".to_string()))?
+    }
+
+    /* Ask the driver to rewrite every stored profile on the next commit.
+     * `Ok(false)` means the device has nothing to repair. */
+    pub async fn request_repair(&self) -> Result<bool, String> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+
+        self.tx
+            .send(ActorMessage::RequestRepair { reply: reply_tx })
+            .await
+            .map_err(|_| "Device actor is no longer running".to_string())?;
+
+        reply_rx
+            .await
+            .map_err(|_| "Device actor dropped the reply channel".to_string())
+    }
+
+    /* Ask the driver to make profile `index` active on the hardware now.
+     * `Ok(false)` means it cannot and the switch needs a commit. */
+    pub async fn switch_profile(&self, index: u32) -> Result<bool, String> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+
+        self.tx
+            .send(ActorMessage::SwitchProfile { index, reply: reply_tx })
+            .await
+            .map_err(|_| "Device actor is no longer running".to_string())?;
+
+        reply_rx
+            .await
+            .map_err(|_| "Device actor dropped the reply channel".to_string())?
+    }
+
+    /* Ask the driver to show `color` on LED `led` now, without a commit.
+     * `Ok(false)` means the driver cannot write LEDs live. */
+    pub async fn set_led_color_live(&self, led: u32, color: RgbColor) -> Result<bool, String> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+
+        self.tx
+            .send(ActorMessage::SetLedColorLive {
+                led,
+                color,
+                reply: reply_tx,
+            })
+            .await
+            .map_err(|_| "Device actor is no longer running".to_string())?;
+
+        reply_rx
+            .await
+            .map_err(|_| "Device actor dropped the reply channel".to_string())?
+    }
+
+    /* Ask the driver for the current battery state. */
+    pub async fn read_battery(&self) -> Result<Option<BatteryInfo>, String> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+
+        self.tx
+            .send(ActorMessage::ReadBattery { reply: reply_tx })
+            .await
+            .map_err(|_| "Device actor is no longer running".to_string())?;
+
+        reply_rx
+            .await
+            .map_err(|_| "Device actor dropped the reply channel".to_string())?
+    }
+
+    /* Ask for the raw HID report descriptor of the device. */
+    pub async fn read_hid_descriptor(&self) -> Result<Vec<u8>, String> {
         let (reply_tx, reply_rx) = oneshot::channel();
 
         self.tx
-            .send(ActorMessage::Commit { reply: reply_tx })
+            .send(ActorMessage::ReadHidDescriptor { reply: reply_tx })
+            .await
+            .map_err(|_| "Device actor is no longer running".to_string())?;
+
+        reply_rx
+            .await
+            .map_err(|_| "Device actor dropped the reply channel".to_string())?
+    }
+
+    /* Send a raw report, bypassing the driver, and return the answer.
+     * Empty when an output report got no reply in time. */
+    #[cfg(feature = "dev-hooks")]
+    pub async fn send_raw_report(&self, feature: bool, data: Vec<u8>) -> Result<Vec<u8>, String> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+
+        self.tx
+            .send(ActorMessage::RawReport {
+                feature,
+                data,
+                reply: reply_tx,
+            })
             .await
             .map_err(|_| "Device actor is no longer running".to_string())?;
 
@@ -61,9 +288,36 @@ struct DeviceActor {
     io: DeviceIo,
     info: Arc<RwLock<DeviceInfo>>,
     rx: mpsc::Receiver<ActorMessage>,
+    cancel: watch::Receiver<bool>,
+    /* Last battery reading and when it was taken. */
+    battery: Option<(Instant, Option<BatteryInfo>)>,
+    /* Commit audit log, when enabled, and the state the device was last
+     * known to hold, which audit entries are compared against. */
+    audit: Option<Arc<AuditLog>>,
+    committed: DeviceInfo,
+    /* State cache, when enabled, and this device's key in it. */
+    state: Option<(Arc<StateCache>, CacheKey)>,
+    resync: watch::Sender<()>,
+    needs_repair: watch::Sender<bool>,
+    ready: watch::Sender<bool>,
 }
 
 impl DeviceActor {
+    /* Record the last committed state in the state cache, if enabled. */
+    fn store_state(&self) {
+        let Some((cache, key)) = &self.state else { return };
+        let mut state = self.committed.clone();
+        for profile in &mut state.profiles {
+            profile.is_dirty = false;
+            for led in &mut profile.leds {
+                led.is_dirty = false;
+            }
+        }
+        if let Err(e) = cache.store(key, &state) {
+            warn!("Cannot write state cache in {}: {e}", cache.dir().display());
+        }
+    }
+
     /* Main actor loop: process messages until shutdown or channel close. */
     async fn run(mut self) {
         info!(
@@ -72,9 +326,39 @@ impl DeviceActor {
             self.driver.name()
         );
 
-        while let Some(msg) = self.rx.recv().await {
+        /* Drivers whose devices announce hardware-side changes get the
+         * node checked for such reports between messages. */
+        let listening = self.driver.listens_for_events();
+        let mut events = tokio::time::interval(EVENT_POLL_INTERVAL);
+        events.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        /* A device that was asleep when it was plugged in is probed again,
+         * less often each time it stays silent. */
+        let mut wake_backoff = WAKE_RETRY_MIN;
+        let mut next_wake = tokio::time::Instant::now() + wake_backoff;
+
+        loop {
+            let asleep = !self.driver.is_ready();
+            let msg = tokio::select! {
+                msg = self.rx.recv() => msg,
+                _ = tokio::time::sleep_until(next_wake), if asleep => {
+                    if !self.wake().await {
+                        wake_backoff = (wake_backoff * 2).min(WAKE_RETRY_MAX);
+                    }
+                    next_wake = tokio::time::Instant::now() + wake_backoff;
+                    continue;
+                }
+                _ = events.tick(), if listening => {
+                    if let Err(e) = self.io.collect_events().await {
+                        debug!("Reading events from {}: {e:#}", self.io.path().display());
+                    }
+                    self.handle_events().await;
+                    continue;
+                }
+            };
+            let Some(msg) = msg else { break };
             match msg {
-                ActorMessage::Commit { reply } => {
+                ActorMessage::Commit { profile: only, reply } => {
                     /* Clone a snapshot of the device state and release the
                      * lock immediately.  This prevents write-starvation:
                      * if the commit takes a long time (wireless retries,
@@ -82,42 +366,169 @@ impl DeviceActor {
                      * blocked waiting for the read-lock to be released.
                      * The ~1.6 µs clone cost is negligible compared to the
                      * multi-millisecond hardware I/O that follows. */
+                    if !self.driver.is_ready() {
+                        /* What clients edited is the skeleton, not what the
+                         * device holds; reload instead of writing it. */
+                        let reason = if self.wake().await {
+                            "the device was asleep and its settings were just loaded; \
+                             review them and commit again"
+                        } else {
+                            "the device is not answering (asleep?)"
+                        };
+                        let _ = reply.send(Err(format!("Commit aborted: {reason}").into()));
+                        continue;
+                    }
+
                     let snapshot = self.info.read().await.clone();
-                    let result = self.driver.commit(&mut self.io, &snapshot).await;
+
+                    if let Err(e) = snapshot.validate_resolutions() {
+                        warn!("{}: refusing to commit: {e}", snapshot.sysname);
+                        let _ = reply.send(Err(e.into()));
+                        continue;
+                    }
+
+                    if !self.driver.supports_macro_repeat() {
+                        for (profile, button) in snapshot.repeated_macros() {
+                            warn!(
+                                "{}: macro repeat on profile {profile} button {button} is not \
+                                 supported by {}; the macro plays once",
+                                snapshot.sysname,
+                                self.driver.name()
+                            );
+                        }
+                    }
+
+                    /* Race the driver against cancellation: dropping the
+                     * commit future abandons the pending hidraw read. A
+                     * closed cancel channel disables the branch rather
+                     * than firing it. */
+                    let write = async {
+                        match only {
+                            Some(index) => {
+                                self.driver.commit_profile(&mut self.io, &snapshot, index).await
+                            }
+                            None => self.driver.commit(&mut self.io, &snapshot).await,
+                        }
+                    };
+                    let result = tokio::select! {
+                        r = write => r,
+                        Ok(_) = self.cancel.wait_for(|c| *c) => {
+                            info!(
+                                "Commit cancelled for {}: device is shutting down",
+                                snapshot.sysname
+                            );
+                            let _ = reply.send(Err(
+                                "Commit cancelled: device is shutting down".to_string().into(),
+                            ));
+                            break;
+                        }
+                    };
+
+                    if let Some(audit) = &self.audit {
+                        let result = result.as_ref().map(|_| ()).map_err(|e| format!("{e:#}"));
+                        let entry = AuditEntry::new(&self.committed, &snapshot, only, result);
+                        if let Err(e) = audit.append(&entry) {
+                            warn!("Cannot write audit log {}: {e}", audit.path().display());
+                        }
+                    }
 
                     if result.is_ok() {
-                        /* Clear dirty flags under a brief write-lock. */
+                        /* Update the audit baseline with what was written. */
+                        match only {
+                            Some(index) => {
+                                if let (Some(old), Some(new)) = (
+                                    self.committed.find_profile_mut(index),
+                                    snapshot.find_profile(index),
+                                ) {
+                                    *old = new.clone();
+                                }
+                            }
+                            None => self.committed = snapshot.clone(),
+                        }
+                        self.store_state();
+
+                        /* Clear dirty flags under a brief write-lock.  A
+                         * profile commit leaves device-wide settings alone. */
                         let mut info = self.info.write().await;
+                        if only.is_none() {
+                            info.is_dirty = false;
+                        }
                         for profile in &mut info.profiles {
-                            profile.is_dirty = false;
+                            if only.is_none_or(|index| index == profile.index) {
+                                profile.is_dirty = false;
+                                for led in &mut profile.leds {
+                                    led.is_dirty = false;
+                                }
+                            }
                         }
                     }
 
+                    self.needs_repair.send_replace(self.driver.needs_repair());
+
                     /* Process any unsolicited hardware events (e.g. profile
                      * switch notifications) that arrived during the commit's
                      * I/O calls.  These were buffered by DeviceIo::request()
                      * because they didn't match the pending command. */
-                    let events = self.io.drain_events();
-                    if !events.is_empty() {
-                        let mut info = self.info.write().await;
-                        for event in &events {
-                            match self.driver.handle_event(event, &mut info).await {
-                                Ok(true) => {
-                                    debug!(
-                                        "Unsolicited event updated device state: {:02x?}",
-                                        event
-                                    );
-                                }
-                                Ok(false) => { /* event was recognised but no state change */ }
-                                Err(e) => {
-                                    warn!("Error handling unsolicited event: {e}");
-                                }
-                            }
+                    self.handle_events().await;
+
+                    let _ = reply.send(result.map_err(CommitError::from));
+                }
+                ActorMessage::RequestRepair { reply } => {
+                    let _ = reply.send(self.driver.request_repair());
+                }
+                ActorMessage::SwitchProfile { index, reply } => {
+                    let result = tokio::select! {
+                        r = self.driver.switch_profile(&mut self.io, index) => r,
+                        Ok(_) = self.cancel.wait_for(|c| *c) => {
+                            let _ = reply.send(Err(
+                                "Profile switch cancelled: device is shutting down".to_string(),
+                            ));
+                            break;
                         }
+                    };
+                    let _ = reply.send(result.map_err(|e| format!("{e:#}")));
+                }
+                ActorMessage::SetLedColorLive { led, color, reply } => {
+                    let result = tokio::select! {
+                        r = self.driver.set_led_color_live(&mut self.io, led, color) => r,
+                        Ok(_) = self.cancel.wait_for(|c| *c) => {
+                            let _ = reply.send(Err(
+                                "LED update cancelled: device is shutting down".to_string(),
+                            ));
+                            break;
+                        }
+                    };
+                    let _ = reply.send(result.map_err(|e| format!("{e:#}")));
+                }
+                ActorMessage::ReadBattery { reply } => {
+                    if let Some((at, battery)) = self.battery
+                        && at.elapsed() < BATTERY_CACHE_TTL
+                    {
+                        let _ = reply.send(Ok(battery));
+                        continue;
                     }
-
-                    let response = result.map_err(|e| format!("{e:#}"));
-                    let _ = reply.send(response);
+                    let result = tokio::select! {
+                        r = self.driver.read_battery(&mut self.io) => r,
+                        Ok(_) = self.cancel.wait_for(|c| *c) => {
+                            let _ = reply.send(Err(
+                                "Battery read cancelled: device is shutting down".to_string(),
+                            ));
+                            break;
+                        }
+                    };
+                    if let Ok(battery) = result {
+                        self.battery = Some((Instant::now(), battery));
+                    }
+                    let _ = reply.send(result.map_err(|e| format!("{e:#}")));
+                }
+                ActorMessage::ReadHidDescriptor { reply } => {
+                    let result = self.io.get_report_descriptor();
+                    let _ = reply.send(result.map_err(|e| e.to_string()));
+                }
+                #[cfg(feature = "dev-hooks")]
+                ActorMessage::RawReport { feature, data, reply } => {
+                    let result = self.raw_report(feature, &data).await;
+                    let _ = reply.send(result.map_err(|e| format!("{e:#}")));
                 }
                 ActorMessage::Shutdown => {
                     info!(
@@ -131,13 +542,107 @@ impl DeviceActor {
 
         debug!("Device actor loop exited");
     }
+
+    /* A feature report is set and then read back under the same report
+     * ID and length; an output report is written and answered with the
+     * first report the node gives within `RAW_REPLY_TIMEOUT`. */
+    #[cfg(feature = "dev-hooks")]
+    async fn raw_report(&mut self, feature: bool, data: &[u8]) -> Result<Vec<u8>> {
+        if feature {
+            self.io.set_feature_report(data)?;
+            let mut buf = vec![0u8; data.len()];
+            buf[0] = data[0];
+            let n = self.io.get_feature_report(&mut buf)?;
+            buf.truncate(n);
+            return Ok(buf);
+        }
+
+        self.io.write_report(data).await?;
+        let mut buf = [0u8; 256];
+        match tokio::time::timeout(RAW_REPLY_TIMEOUT, self.io.read_report(&mut buf)).await {
+            Ok(n) => Ok(buf[..n?].to_vec()),
+            Err(_) => Ok(Vec::new()),
+        }
+    }
+
+    /* Probe a device that was not ready again and, once it answers,
+     * load its profiles over the skeleton and resync.  Returns whether
+     * the device is ready now. */
+    async fn wake(&mut self) -> bool {
+        let mut cancel = self.cancel.clone();
+        let probed = tokio::select! {
+            r = tokio::time::timeout(PROBE_TIMEOUT, self.driver.probe(&mut self.io)) => r,
+            Ok(_) = cancel.wait_for(|c| *c) => return false,
+        };
+        if let Err(e) = probed.map_err(anyhow::Error::from).and_then(|r| r) {
+            debug!("Wake probe of {}: {e:#}", self.io.path().display());
+            return false;
+        }
+        if !self.driver.is_ready() {
+            return false;
+        }
+
+        let mut info = self.info.write().await;
+        let load = tokio::time::timeout(
+            LOAD_PROFILES_TIMEOUT,
+            self.driver.load_profiles(&mut self.io, &mut info),
+        );
+        let loaded = tokio::select! {
+            r = load => r,
+            Ok(_) = cancel.wait_for(|c| *c) => return false,
+        };
+        if let Err(e) = loaded.map_err(anyhow::Error::from).and_then(|r| r) {
+            debug!("Loading profiles of {} after wake-up: {e:#}", info.sysname);
+            return false;
+        }
+        if !self.driver.is_ready() {
+            return false;
+        }
+        finish_loading(self.driver.as_ref(), &mut info, &self.state);
+        self.committed = info.clone();
+        self.needs_repair.send_replace(self.driver.needs_repair());
+        self.ready.send_replace(true);
+        info!("{}: device woke up, profiles loaded", info.sysname);
+        drop(info);
+        self.battery = None;
+        self.resync.send_replace(());
+        true
+    }
+
+    /* Feed the buffered unsolicited reports to the driver and signal a
+     * resync if any of them changed the device state. */
+    async fn handle_events(&mut self) {
+        let events = self.io.drain_events();
+        if events.is_empty() {
+            return;
+        }
+        let mut changed = false;
+        let mut info = self.info.write().await;
+        for event in &events {
+            match self.driver.handle_event(event, &mut info).await {
+                Ok(true) => {
+                    debug!("Unsolicited event updated device state: {:02x?}", event);
+                    changed = true;
+                }
+                Ok(false) => { /* event was recognised but no state change */ }
+                Err(e) => {
+                    warn!("Error handling unsolicited event: {e}");
+                }
+            }
+        }
+        if changed {
+            info!("{}: state changed on the device, resyncing", info.sysname);
+            self.resync.send_replace(());
+        }
+    }
 }
 
 /* Maximum time allowed for the protocol probe phase (version ping +
  * feature discovery).  HID++ 2.0 probes up to two device indices;
  * a non-responding index burns one READ_TIMEOUT_PER_ATTEMPT (2 s)
- * cycle, so 8 seconds covers the worst case with headroom. */
-const PROBE_TIMEOUT: Duration = Duration::from_secs(8);
+ * cycle.  Feature discovery keeps its retries of unanswered lookups
+ * within a 9 s deadline of its own, so the two fit in 12 seconds. */
+const PROBE_TIMEOUT: Duration = Duration::from_secs(12);
 
 /* Maximum time allowed for loading profiles from hardware.  Complex
  * devices (e.g. G502 with 5 onboard profiles and multiple sector
@@ -145,46 +650,121 @@ const PROBE_TIMEOUT: Duration = Duration::from_secs(8);
  * congested wireless link. */
 const LOAD_PROFILES_TIMEOUT: Duration = Duration::from_secs(15);
 
+/* How long a battery reading is reused.  Clients poll the battery
+ * properties freely; the charge level moves far slower than that, and
+ * on a wireless link every query competes with commits for airtime. */
+const BATTERY_CACHE_TTL: Duration = Duration::from_secs(60);
+
+/* How often an idle actor checks its node for hardware events.  A
+ * profile button press shows up in clients within this time. */
+const EVENT_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/* First and longest wait between wake probes of a device that was
+ * asleep when it was plugged in. */
+const WAKE_RETRY_MIN: Duration = Duration::from_secs(2);
+const WAKE_RETRY_MAX: Duration = Duration::from_secs(60);
+
+/* How long a raw output report waits for the device to answer. */
+#[cfg(feature = "dev-hooks")]
+const RAW_REPLY_TIMEOUT: Duration = Duration::from_millis(500);
+
+/* Probe `drivers` in order and return the first one whose `probe`
+ * succeeds, with its configuration.  Each candidate gets its own
+ * `PROBE_TIMEOUT` budget so that a preferred protocol that never answers
+ * does not starve the fallbacks.  Returns the last probe error if every
+ * candidate fails. */
+async fn probe_drivers(
+    io: &mut DeviceIo,
+    drivers: Vec<DriverCandidate>,
+) -> Result<(Box<dyn DeviceDriver>, DriverConfig)> {
+    let devnode_display = io.path().display().to_string();
+    let mut last_err = anyhow::anyhow!("No driver available for {devnode_display}");
+
+    for (mut driver, config) in drivers {
+        let driver_name = driver.name().to_string();
+        let result = tokio::time::timeout(PROBE_TIMEOUT, async {
+            driver
+                .probe(io)
+                .await
+                .with_context(|| format!("Probing {} with {}", devnode_display, driver_name))
+        })
+        .await
+        .map_err(|_| {
+            anyhow::anyhow!(
+                "Probe timed out after {}s for {} with {}",
+                PROBE_TIMEOUT.as_secs(),
+                devnode_display,
+                driver_name
+            )
+        })
+        .and_then(|r| r);
+
+        match result {
+            Ok(()) => return Ok((driver, config)),
+            Err(e) => {
+                debug!("{e:#}; trying next driver");
+                last_err = e;
+            }
+        }
+    }
+
+    Err(last_err)
+}
+
+/* Fill in what the driver leaves out of a freshly loaded `info`. */
+fn finish_loading(
+    driver: &dyn DeviceDriver,
+    info: &mut DeviceInfo,
+    state: &Option<(Arc<StateCache>, CacheKey)>,
+) {
+    info.fill_default_action_types(driver.action_types());
+    info.fill_default_mappings(|button| driver.default_button_mapping(button));
+    info.mark_unmanaged_leds();
+
+    /* Before the audit baseline is taken, so that a commit of one
+     * profile does not cache the made-up names of the others. */
+    if let Some((cache, key)) = state
+        && !driver.stores_profile_names()
+        && let Some(cached) = cache.load(key)
+    {
+        debug!("{}: restoring profile names from the state cache", info.sysname);
+        state_cache::restore_profile_names(info, &cached);
+    }
+}
+
 /* Spawn a device actor for the given hardware device.
  *
  * This function:
  * 1. Opens the `/dev/hidraw` device node.
- * 2. Probes the device with the protocol driver (with a timeout).
- * 3. Reads the full device state (profiles, DPIs, LEDs).
+ * 2. Probes the device with each candidate driver in priority order
+ *    (with a timeout per driver) and keeps the first that succeeds.
+ * 3. Reads the full device state (profiles, DPIs, LEDs) and restores
+ *    cached profile names the driver cannot read.
  * 4. Spawns the actor task and returns a handle for DBus objects.
  *
- * Returns `Err` if probing or profile loading fails or times out. */
+ * Returns `Err` if every probe fails, or if profile loading fails or
+ * times out. */
 pub async fn spawn_device_actor(
     devnode: &Path,
-    mut driver: Box<dyn DeviceDriver>,
+    drivers: Vec<DriverCandidate>,
     info: Arc<RwLock<DeviceInfo>>,
+    audit: Option<Arc<AuditLog>>,
+    state: Option<(Arc<StateCache>, CacheKey)>,
 ) -> Result<ActorHandle> {
     let mut io = DeviceIo::open(devnode)
         .await
         .with_context(|| format!("Opening {}", devnode.display()))?;
 
-    let driver_name = driver.name().to_string();
-    let devnode_display = devnode.display().to_string();
-
     /* Probe and load_profiles have separate timeout budgets so that a
      * slow probe (e.g. a wired device that first tries the wrong
      * device index) does not eat into the time available for profile
      * loading, which involves many sector reads. */
-    tokio::time::timeout(PROBE_TIMEOUT, async {
-        driver
-            .probe(&mut io)
-            .await
-            .with_context(|| format!("Probing {} with {}", devnode_display, driver_name))
-    })
-    .await
-    .map_err(|_| {
-        anyhow::anyhow!(
-            "Probe timed out after {}s for {} with {}",
-            PROBE_TIMEOUT.as_secs(),
-            devnode.display(),
-            driver.name()
-        )
-    })??;
+    let (mut driver, config) = probe_drivers(&mut io, drivers).await?;
+    /* A fallback driver reads its own section, not the primary's. */
+    info.write().await.driver_config = config;
+
+    let driver_name = driver.name().to_string();
+    let devnode_display = devnode.display().to_string();
 
     tokio::time::timeout(LOAD_PROFILES_TIMEOUT, async {
         let mut device_info = info.write().await;
@@ -208,19 +788,412 @@ pub async fn spawn_device_actor(
         )
     })??;
 
+    finish_loading(driver.as_ref(), &mut *info.write().await, &state);
+
     /* Create the message channel and spawn the actor */
     let (tx, rx) = mpsc::channel(16);
+    let (cancel_tx, cancel_rx) = watch::channel(false);
+    let (resync_tx, resync_rx) = watch::channel(());
+    let (repair_tx, repair_rx) = watch::channel(driver.needs_repair());
+    let (ready_tx, ready_rx) = watch::channel(driver.is_ready());
 
+    let committed = info.read().await.clone();
     let actor = DeviceActor {
         driver,
         io,
         info,
         rx,
+        cancel: cancel_rx,
+        battery: None,
+        audit,
+        committed,
+        state,
+        resync: resync_tx,
+        needs_repair: repair_tx,
+        ready: ready_tx,
     };
 
     tokio::spawn(async move {
         actor.run().await;
     });
 
-    Ok(ActorHandle { tx })
+    Ok(ActorHandle {
+        tx,
+        cancel: Arc::new(cancel_tx),
+        resync: resync_rx,
+        needs_repair: repair_rx,
+        ready: ready_rx,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{MockDriver, ScriptedIo};
+
+    fn fake(name: &'static str, probe_ok: bool) -> DriverCandidate {
+        MockDriver::new(name, probe_ok).candidate()
+    }
+
+    fn empty_info() -> Arc<RwLock<DeviceInfo>> {
+        Arc::new(RwLock::new(DeviceInfo {
+            sysname: "hidraw-test".to_string(),
+            ..Default::default()
+        }))
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_cancels_in_flight_commit() {
+        let driver = MockDriver {
+            commit_delay: Duration::from_secs(60),
+            ..Default::default()
+        };
+        let (handle, state) = driver.spawn(&empty_info()).await;
+
+        let committer = handle.clone();
+        let commit = tokio::spawn(async move { committer.commit().await });
+
+        /* Unplug only once the actor is inside the driver's commit. */
+        state.commit_started.notified().await;
+        handle.shutdown().await;
+
+        let result = tokio::time::timeout(Duration::from_secs(1), commit)
+            .await
+            .expect("shutdown should abort the commit promptly")
+            .unwrap();
+        assert!(result.unwrap_err().message.contains("cancelled"));
+    }
+
+    #[tokio::test]
+    async fn test_commit_appends_audit_entry() {
+        let dir = std::env::temp_dir().join(format!("ratbagd-audit-commit-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let audit = Arc::new(AuditLog::new(dir.join("audit.log")));
+
+        let info = empty_info();
+        info.write().await.profiles = (0..2)
+            .map(|index| crate::device::ProfileInfo {
+                index,
+                report_rate: 500,
+                ..Default::default()
+            })
+            .collect();
+        let handle = spawn_device_actor(
+            Path::new("/dev/null"),
+            vec![fake("plain", true)],
+            Arc::clone(&info),
+            Some(audit),
+            None,
+        )
+        .await
+        .unwrap();
+
+        {
+            let mut info = info.write().await;
+            info.profiles[1].report_rate = 1000;
+            info.profiles[1].is_dirty = true;
+        }
+        handle.commit().await.unwrap();
+
+        let log = std::fs::read_to_string(dir.join("audit.log")).unwrap();
+        let lines: Vec<&str> = log.lines().collect();
+        assert_eq!(lines.len(), 1);
+        let entry: serde_json::Value = serde_json::from_str(lines[0]).unwrap();
+        assert_eq!(entry["device"], "hidraw-test");
+        assert_eq!(entry["ok"], true);
+        assert_eq!(
+            entry["profiles"],
+            serde_json::json!([{ "index": 1, "fields": ["report_rate"] }])
+        );
+        assert!(entry["time"].as_u64().unwrap() > 0);
+
+        /* The written state is the new baseline: an unchanged commit
+         * lists no fields. */
+        info.write().await.profiles[1].is_dirty = true;
+        handle.commit().await.unwrap();
+        let log = std::fs::read_to_string(dir.join("audit.log")).unwrap();
+        let entry: serde_json::Value = serde_json::from_str(log.lines().nth(1).unwrap()).unwrap();
+        assert_eq!(entry["profiles"], serde_json::json!([{ "index": 1, "fields": [] }]));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_state_cache_keeps_names_of_uncommitted_profiles() {
+        let dir = std::env::temp_dir().join(format!("ratbagd-state-commit-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let cache = Arc::new(StateCache::new(&dir));
+        let key = CacheKey {
+            bustype: 0x03,
+            vid: 0x1038,
+            pid: 0x1702,
+            sysname: "hidraw-test".to_string(),
+        };
+        let profiles = |names: [&str; 2]| -> Vec<crate::device::ProfileInfo> {
+            names
+                .iter()
+                .zip(0..)
+                .map(|(name, index)| crate::device::ProfileInfo {
+                    index,
+                    name: name.to_string(),
+                    ..Default::default()
+                })
+                .collect()
+        };
+        let mut cached = empty_info().read().await.clone();
+        cached.profiles = profiles(["Work", "Games"]);
+        cache.store(&key, &cached).unwrap();
+
+        /* The driver's load leaves made-up names, as after a restart. */
+        let info = empty_info();
+        info.write().await.profiles = profiles(["Profile 0", "Profile 1"]);
+        let handle = spawn_device_actor(
+            Path::new("/dev/null"),
+            vec![fake("plain", true)],
+            Arc::clone(&info),
+            None,
+            Some((Arc::clone(&cache), key.clone())),
+        )
+        .await
+        .unwrap();
+        assert_eq!(info.read().await.profiles[0].name, "Work");
+
+        {
+            let mut info = info.write().await;
+            info.profiles[1].name = "Racing".to_string();
+            info.profiles[1].is_dirty = true;
+        }
+        handle.commit_profile(1).await.unwrap();
+
+        let stored = cache.load(&key).unwrap();
+        let names: Vec<&str> = stored.profiles.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["Work", "Racing"]);
+        assert!(!stored.profiles[1].is_dirty);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_partial_commit_names_the_failed_writes() {
+        let info = empty_info();
+        info.write().await.profiles = vec![crate::device::ProfileInfo {
+            index: 1,
+            is_dirty: true,
+            ..Default::default()
+        }];
+        let driver = MockDriver {
+            failing_led: Some((1, 0)),
+            ..Default::default()
+        };
+        let (handle, _) = driver.spawn(&info).await;
+
+        let e = handle.commit().await.unwrap_err();
+        let paths: Vec<(String, &str)> = e
+            .failures
+            .iter()
+            .map(|(target, error)| (target.object_path("/d"), error.as_str()))
+            .collect();
+        assert_eq!(paths, [("/d/p1/l0".to_string(), "zone busy")]);
+        assert!(info.read().await.profiles[0].is_dirty);
+
+        /* Any other error is reported against the whole device. */
+        let e = CommitError::from(anyhow::anyhow!("device gone"));
+        assert_eq!(e.failures, [(CommitTarget::Device, "device gone".to_string())]);
+    }
+
+    /* The DBus objects are built from the shared info as soon as this
+     * returns, so it must not return before the hardware state is in. */
+    #[tokio::test]
+    async fn test_spawn_returns_after_profiles_are_loaded() {
+        /* A profile read that takes a while, as on a wireless device
+         * answering many sector reads. */
+        let driver = MockDriver {
+            load_delay: Duration::from_millis(50),
+            load: Some(|info| {
+                info.firmware_version = "1.2".to_string();
+                info.profiles = vec![crate::device::ProfileInfo {
+                    index: 0,
+                    report_rate: 1000,
+                    ..Default::default()
+                }];
+            }),
+            ..Default::default()
+        };
+        let info = empty_info();
+        driver.spawn(&info).await;
+
+        let info = info.read().await;
+        assert_eq!(info.firmware_version, "1.2");
+        assert_eq!(info.profiles.len(), 1);
+        assert_eq!(info.profiles[0].report_rate, 1000);
+    }
+
+    #[tokio::test]
+    async fn test_commit_to_sleeping_device_reloads_instead_of_writing() {
+        /* A wireless device that sleeps through the probe at plug-in
+         * and answers the next one. */
+        let driver = MockDriver {
+            asleep_probes: 1,
+            load: Some(|info| info.firmware_version = "2.0".to_string()),
+            ..Default::default()
+        };
+        let info = empty_info();
+        let (handle, state) = driver.spawn(&info).await;
+        assert_eq!(info.read().await.firmware_version, "");
+        assert!(!handle.is_ready());
+
+        let resyncs = handle.resyncs();
+        let err = handle.commit().await.unwrap_err();
+        assert!(err.message.contains("asleep"), "{}", err.message);
+        assert_eq!(state.commits(), 0);
+        assert!(resyncs.has_changed().unwrap());
+        assert_eq!(info.read().await.firmware_version, "2.0");
+
+        handle.commit().await.unwrap();
+        assert_eq!(state.commits(), 1);
+        handle.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn test_readiness_follows_the_first_load() {
+        /* An awake device is ready as soon as its actor runs. */
+        let (handle, _) = MockDriver::default().spawn(&empty_info()).await;
+        assert!(handle.is_ready());
+        handle.shutdown().await;
+
+        /* A sleeping one becomes ready, and DeviceReady goes out, only
+         * once its profiles are loaded after it wakes. */
+        let driver = MockDriver {
+            asleep_probes: 1,
+            ..Default::default()
+        };
+        let (handle, _) = driver.spawn(&empty_info()).await;
+        let mut ready = handle.readiness();
+        assert!(!*ready.borrow_and_update());
+
+        let _ = handle.commit().await;
+        assert!(ready.has_changed().unwrap());
+        assert!(*ready.borrow());
+        handle.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn test_repair_flag_follows_the_driver() {
+        let driver = MockDriver {
+            corrupt: true,
+            ..Default::default()
+        };
+        let (handle, _) = driver.spawn(&empty_info()).await;
+        assert!(handle.needs_repair());
+
+        /* A plain commit leaves the corrupted sectors alone. */
+        handle.commit().await.unwrap();
+        assert!(handle.needs_repair());
+
+        assert_eq!(handle.request_repair().await, Ok(true));
+        handle.commit().await.unwrap();
+        assert!(!handle.needs_repair());
+        handle.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn test_idle_actor_resyncs_on_hardware_event() {
+        /* The scripted node polls readable and yields the queued
+         * report, then EOF. */
+        let mut report = vec![0x11, 0x01, 0x0e, 0x40, 0x00, 0x02];
+        report.resize(20, 0);
+        let node = ScriptedIo::new("actor-event", &report).await;
+
+        let info = empty_info();
+        info.write().await.profiles = (0..2)
+            .map(|index| crate::device::ProfileInfo {
+                index,
+                is_active: index == 0,
+                ..Default::default()
+            })
+            .collect();
+        let driver = MockDriver {
+            events: true,
+            ..Default::default()
+        };
+        let handle = spawn_device_actor(node.path(), vec![driver.candidate()], Arc::clone(&info), None, None)
+            .await
+            .unwrap();
+
+        let mut resyncs = handle.resyncs();
+        tokio::time::timeout(Duration::from_secs(2), resyncs.changed())
+            .await
+            .expect("the event should trigger a resync")
+            .unwrap();
+        let active: Vec<bool> = info.read().await.profiles.iter().map(|p| p.is_active).collect();
+        assert_eq!(active, [false, true]);
+
+        handle.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn test_battery_defaults_to_none() {
+        let handle = spawn_device_actor(Path::new("/dev/null"), vec![fake("plain", true)], empty_info(), None, None)
+            .await
+            .unwrap();
+        assert_eq!(handle.read_battery().await, Ok(None));
+    }
+
+    #[tokio::test]
+    async fn test_battery_reading_is_cached() {
+        let driver = MockDriver {
+            battery: Some(BatteryInfo { level: 87, charging: true }),
+            ..Default::default()
+        };
+        let (handle, state) = driver.spawn(&empty_info()).await;
+
+        let expected = Some(BatteryInfo { level: 87, charging: true });
+        assert_eq!(handle.read_battery().await, Ok(expected));
+        assert_eq!(handle.read_battery().await, Ok(expected));
+        assert_eq!(state.battery_reads(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_probe_falls_back_to_second_driver() {
+        let mut io = DeviceIo::open(Path::new("/dev/null")).await.unwrap();
+        let drivers = vec![fake("hidpp20", false), fake("hidpp10", true)];
+        let (driver, _) = probe_drivers(&mut io, drivers).await.unwrap();
+        assert_eq!(driver.name(), "hidpp10");
+    }
+
+    #[tokio::test]
+    async fn test_fallback_driver_loads_with_its_own_config() {
+        let config = |profiles| DriverConfig {
+            profiles: Some(profiles),
+            ..Default::default()
+        };
+        let drivers = vec![
+            (MockDriver::new("hidpp20", false).boxed(), config(1)),
+            (MockDriver::new("hidpp10", true).boxed(), config(5)),
+        ];
+        let info = empty_info();
+        info.write().await.driver_config = config(1);
+        let handle = spawn_device_actor(Path::new("/dev/null"), drivers, Arc::clone(&info), None, None)
+            .await
+            .unwrap();
+        assert_eq!(info.read().await.driver_config.profiles, Some(5));
+        handle.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn test_probe_prefers_first_driver() {
+        let mut io = DeviceIo::open(Path::new("/dev/null")).await.unwrap();
+        let drivers = vec![fake("hidpp20", true), fake("hidpp10", true)];
+        let (driver, _) = probe_drivers(&mut io, drivers).await.unwrap();
+        assert_eq!(driver.name(), "hidpp20");
+    }
+
+    #[tokio::test]
+    async fn test_probe_fails_when_all_drivers_fail() {
+        let mut io = DeviceIo::open(Path::new("/dev/null")).await.unwrap();
+        let drivers = vec![fake("hidpp20", false), fake("hidpp10", false)];
+        let Err(err) = probe_drivers(&mut io, drivers).await else {
+            panic!("probe should fail when no driver matches");
+        };
+        assert!(format!("{err:#}").contains("hidpp10"));
+    }
 }
@@ -0,0 +1,180 @@
+/* Commit audit log: an opt-in JSON-lines file with one entry per commit,
+ * naming the device and which profiles and fields the commit wrote.  It
+ * answers "what did the daemon write to my mouse, and when" after the
+ * fact, without turning on debug logging.
+ *
+ * Enabled by setting RATBAGD_AUDIT_LOG to a file path.  When the file
+ * grows past MAX_SIZE it is renamed to `<path>.1`, replacing the previous
+ * rotation, so the log never takes more than twice that on disk. */
+
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+
+use crate::device::{DeviceInfo, ProfileInfo};
+
+/* Environment variable holding the log path; unset or empty disables it. */
+pub const AUDIT_LOG_ENV: &str = "RATBAGD_AUDIT_LOG";
+
+/* Size at which the log is rotated. */
+const MAX_SIZE: u64 = 1024 * 1024;
+
+/* One line of the log. */
+#[derive(Debug, Serialize)]
+pub struct AuditEntry {
+    /* Seconds since the Unix epoch. */
+    pub time: u64,
+    pub device: String,
+    pub name: String,
+    pub profiles: Vec<ProfileChange>,
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/* A profile the commit wrote and the fields that differ from the state
+ * last read from or written to the device. */
+#[derive(Debug, Serialize, PartialEq, Eq)]
+pub struct ProfileChange {
+    pub index: u32,
+    pub fields: Vec<String>,
+}
+
+impl AuditEntry {
+    /* Entry for a commit of `after` (every dirty profile, or only profile
+     * `only`), compared against `before`. */
+    pub fn new(
+        before: &DeviceInfo,
+        after: &DeviceInfo,
+        only: Option<u32>,
+        result: Result<(), String>,
+    ) -> Self {
+        let profiles = after
+            .profiles
+            .iter()
+            .filter(|p| p.is_dirty && only.is_none_or(|index| index == p.index))
+            .map(|p| ProfileChange {
+                index: p.index,
+                fields: before
+                    .find_profile(p.index)
+                    .map(|old| changed_fields(old, p))
+                    .unwrap_or_default(),
+            })
+            .collect();
+        Self {
+            time: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs()),
+            device: after.sysname.clone(),
+            name: after.name.clone(),
+            profiles,
+            ok: result.is_ok(),
+            error: result.err(),
+        }
+    }
+}
+
+/* Names of the settings that differ between two states of a profile,
+ * e.g. "report_rate", "resolution 1" or "led 0". */
+fn changed_fields(before: &ProfileInfo, after: &ProfileInfo) -> Vec<String> {
+    let mut fields: Vec<String> =
+        after.changed_settings(before).into_iter().map(str::to_string).collect();
+    for new in &after.resolutions {
+        if before.find_resolution(new.index).is_none_or(|old| new.differs_from(old)) {
+            fields.push(format!("resolution {}", new.index));
+        }
+    }
+    for new in &after.buttons {
+        if before.find_button(new.index).is_none_or(|old| new.differs_from(old)) {
+            fields.push(format!("button {}", new.index));
+        }
+    }
+    for new in &after.leds {
+        if before.find_led(new.index).is_none_or(|old| new.differs_from(old)) {
+            fields.push(format!("led {}", new.index));
+        }
+    }
+    fields
+}
+
+/* The log file.  Shared by every device actor; appends are serialized so
+ * that rotation and concurrent commits do not interleave. */
+pub struct AuditLog {
+    path: PathBuf,
+    max_size: u64,
+    lock: Mutex<()>,
+}
+
+impl AuditLog {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            max_size: MAX_SIZE,
+            lock: Mutex::new(()),
+        }
+    }
+
+    /* The log configured through AUDIT_LOG_ENV, if any. */
+    pub fn from_env() -> Option<Self> {
+        std::env::var_os(AUDIT_LOG_ENV)
+            .filter(|path| !path.is_empty())
+            .map(Self::new)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /* Append `entry` as one JSON line, rotating first if the line would
+     * take the file past its size limit.  The writes are small and rare
+     * (one per commit), so plain blocking I/O is fine here. */
+    pub fn append(&self, entry: &AuditEntry) -> io::Result<()> {
+        let mut line = serde_json::to_string(entry)?;
+        line.push('\n');
+
+        let _guard = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        let size = fs::metadata(&self.path).map_or(0, |m| m.len());
+        if size > 0 && size + line.len() as u64 > self.max_size {
+            let mut rotated = self.path.clone().into_os_string();
+            rotated.push(".1");
+            fs::rename(&self.path, rotated)?;
+        }
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?
+            .write_all(line.as_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_audit_log_rotates() {
+        let dir = std::env::temp_dir().join(format!("ratbagd-audit-rotate-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let mut log = AuditLog::new(dir.join("audit.log"));
+        log.max_size = 200;
+
+        let info = DeviceInfo {
+            sysname: "hidraw0".to_string(),
+            ..Default::default()
+        };
+        for _ in 0..3 {
+            log.append(&AuditEntry::new(&info, &info, None, Ok(()))).unwrap();
+        }
+
+        let current = fs::read_to_string(dir.join("audit.log")).unwrap();
+        let rotated = fs::read_to_string(dir.join("audit.log.1")).unwrap();
+        assert_eq!(current.lines().count(), 1);
+        assert_eq!(rotated.lines().count(), 2);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
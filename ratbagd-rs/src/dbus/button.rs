@@ -1,14 +1,15 @@
 /* DBus Button interface: exposes per-button action type/mapping and supported actions, updating the
- * shared DeviceInfo and delegating commits through the device actor when needed. */
+ * shared DeviceInfo; the device's Commit writes them to hardware. */
 use std::sync::Arc;
 
 use tokio::sync::RwLock;
 use zbus::interface;
 use zbus::zvariant::{OwnedValue, Value};
 
-use crate::device::{ActionType, DeviceInfo};
+use crate::device::{ActionType, DeviceInfo, MacroRepeat};
 
 use super::fallback_owned_value;
+use super::profile::profile_is_dirty_changed;
 
 /// The `org.freedesktop.ratbag1.Button` interface.
 ///
@@ -34,65 +35,9 @@ impl RatbagButton {
             button_id,
         }
     }
-}
-
-/// Intermediate representation for parsed button mapping values.
-///
-/// Allows parsing the DBus variant *before* acquiring the write lock,
-/// keeping the critical section as short as possible.
-enum ParsedMapping {
-    None,
-    Macro(Vec<(u32, u32)>),
-    Simple(u32),
-}
-
-#[interface(name = "org.freedesktop.ratbag1.Button")]
-impl RatbagButton {
-    /// Zero-based button index (constant).
-    #[zbus(property)]
-    fn index(&self) -> u32 {
-        self.button_id
-    }
-
-    /// Current button mapping as `(ActionType, Variant)`.
-    ///
-    /// `ActionType` determines the variant format:
-    /// - Button (1): `u32` button number
-    /// - Special (2): `u32` special value
-    /// - Key (3): `u32` keycode
-    /// - Macro (4): `Vec<(u32, u32)>` key events
-    /// - None (0) / Unknown (1000): `u32` with value 0
-    #[zbus(property)]
-    async fn mapping(&self) -> (u32, OwnedValue) {
-        let info = self.device_info.read().await;
-        let Some(profile) = info.find_profile(self.profile_id) else {
-            return (ActionType::None as u32, fallback_owned_value());
-        };
-        let Some(button) = profile.find_button(self.button_id) else {
-            return (ActionType::None as u32, fallback_owned_value());
-        };
-        let action_type = button.action_type as u32;
-
-        let value: OwnedValue = match button.action_type {
-            ActionType::Macro => {
-                OwnedValue::try_from(Value::from(button.macro_entries.clone()))
-                    .unwrap_or_else(|_| fallback_owned_value())
-            }
-            ActionType::Button | ActionType::Special | ActionType::Key => {
-                OwnedValue::try_from(Value::from(button.mapping_value))
-                    .unwrap_or_else(|_| fallback_owned_value())
-            }
-            ActionType::None | ActionType::Unknown => {
-                OwnedValue::try_from(Value::from(0_u32))
-                    .unwrap_or_else(|_| fallback_owned_value())
-            }
-        };
-
-        (action_type, value)
-    }
 
-    #[zbus(property)]
-    async fn set_mapping(&self, mapping: (u32, OwnedValue)) -> zbus::Result<()> {
+    /* Store a mapping written as `Mapping` and mark the profile dirty. */
+    async fn store_mapping(&self, mapping: (u32, OwnedValue)) -> zbus::Result<()> {
         let (action_type_raw, value) = mapping;
         let action_type = match action_type_raw {
             0 => ActionType::None,
@@ -212,6 +157,148 @@ impl RatbagButton {
         profile.is_dirty = true;
         Ok(())
     }
+}
+
+/// Intermediate representation for parsed button mapping values.
+///
+/// Allows parsing the DBus variant *before* acquiring the write lock,
+/// keeping the critical section as short as possible.
+enum ParsedMapping {
+    None,
+    Macro(Vec<(u32, u32)>),
+    Simple(u32),
+}
+
+#[interface(name = "org.freedesktop.ratbag1.Button")]
+impl RatbagButton {
+    /// Zero-based button index (constant).
+    #[zbus(property)]
+    fn index(&self) -> u32 {
+        self.button_id
+    }
+
+    /// Current button mapping as `(ActionType, Variant)`.
+    ///
+    /// `ActionType` determines the variant format:
+    /// - Button (1): `u32` button number
+    /// - Special (2): `u32` special value
+    /// - Key (3): `u32` keycode
+    /// - Macro (4): `Vec<(u32, u32)>` key events
+    /// - None (0) / Unknown (1000): `u32` with value 0
+    #[zbus(property)]
+    async fn mapping(&self) -> (u32, OwnedValue) {
+        let info = self.device_info.read().await;
+        let Some(profile) = info.find_profile(self.profile_id) else {
+            return (ActionType::None as u32, fallback_owned_value());
+        };
+        let Some(button) = profile.find_button(self.button_id) else {
+            return (ActionType::None as u32, fallback_owned_value());
+        };
+        let action_type = button.action_type as u32;
+
+        let value: OwnedValue = match button.action_type {
+            ActionType::Macro => {
+                OwnedValue::try_from(Value::from(button.macro_entries.clone()))
+                    .unwrap_or_else(|_| fallback_owned_value())
+            }
+            ActionType::Button | ActionType::Special | ActionType::Key => {
+                OwnedValue::try_from(Value::from(button.mapping_value))
+                    .unwrap_or_else(|_| fallback_owned_value())
+            }
+            ActionType::None | ActionType::Unknown => {
+                OwnedValue::try_from(Value::from(0_u32))
+                    .unwrap_or_else(|_| fallback_owned_value())
+            }
+        };
+
+        (action_type, value)
+    }
+
+    #[zbus(property)]
+    async fn set_mapping(
+        &self,
+        #[zbus(object_server)] server: &zbus::ObjectServer,
+        #[zbus(signal_emitter)] emitter: zbus::object_server::SignalEmitter<'_>,
+        mapping: (u32, OwnedValue),
+    ) -> zbus::Result<()> {
+        self.store_mapping(mapping).await?;
+        profile_is_dirty_changed(server, emitter.path()).await;
+        Ok(())
+    }
+
+    /// How often a macro mapping plays per press.
+    ///
+    /// `0` repeats the macro while the button is held, `N >= 1` plays it
+    /// `N` times.  Drivers without repeat control log a warning on commit
+    /// and play the macro once.
+    #[zbus(property)]
+    async fn macro_repeat(&self) -> u32 {
+        let info = self.device_info.read().await;
+        info.find_profile(self.profile_id)
+            .and_then(|p| p.find_button(self.button_id))
+            .map_or(1, |b| b.macro_repeat.to_u32())
+    }
+
+    #[zbus(property)]
+    async fn set_macro_repeat(
+        &self,
+        #[zbus(object_server)] server: &zbus::ObjectServer,
+        #[zbus(signal_emitter)] emitter: zbus::object_server::SignalEmitter<'_>,
+        repeat: u32,
+    ) -> zbus::Result<()> {
+        {
+            let mut info = self.device_info.write().await;
+            let profile = info
+                .find_profile_mut(self.profile_id)
+                .ok_or_else(|| zbus::fdo::Error::Failed("Profile not found".into()))?;
+            let button = profile
+                .find_button_mut(self.button_id)
+                .ok_or_else(|| zbus::fdo::Error::Failed("Button not found".into()))?;
+            button.macro_repeat = MacroRepeat::from_u32(repeat);
+            profile.is_dirty = true;
+        }
+        profile_is_dirty_changed(server, emitter.path()).await;
+        Ok(())
+    }
+
+    /// Physical position of the button on the device (constant), e.g.
+    /// `left` or `side-front`, from the database's `ButtonLayout`.
+    /// Empty when the device file does not describe the layout.
+    #[zbus(property)]
+    async fn position(&self) -> String {
+        let info = self.device_info.read().await;
+        info.button_layout
+            .get(self.button_id as usize)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Restore the button's factory mapping.  Like a mapping change, it
+    /// reaches the hardware on the next `Commit`.
+    ///
+    /// Fails with `NotSupported` when the driver does not know the default.
+    async fn reset(
+        &self,
+        #[zbus(object_server)] server: &zbus::ObjectServer,
+        #[zbus(signal_emitter)] emitter: zbus::object_server::SignalEmitter<'_>,
+    ) -> zbus::fdo::Result<()> {
+        {
+            let mut info = self.device_info.write().await;
+            let profile = info
+                .find_profile_mut(self.profile_id)
+                .ok_or_else(|| zbus::fdo::Error::Failed("Profile not found".into()))?;
+            let button = profile
+                .find_button_mut(self.button_id)
+                .ok_or_else(|| zbus::fdo::Error::Failed("Button not found".into()))?;
+            button.reset_to_default().map_err(zbus::fdo::Error::NotSupported)?;
+            profile.is_dirty = true;
+        }
+        /* The reset also drops any macro and its repeat setting. */
+        let _ = self.mapping_changed(&emitter).await;
+        let _ = self.macro_repeat_changed(&emitter).await;
+        profile_is_dirty_changed(server, emitter.path()).await;
+        Ok(())
+    }
 
     /// Supported action types for this button (constant).
     #[zbus(property)]
@@ -223,3 +310,39 @@ impl RatbagButton {
             .unwrap_or_default()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    use crate::device::{ButtonInfo, ProfileInfo};
+    use crate::test_support::MockDriver;
+
+    #[tokio::test]
+    async fn test_set_mapping_waits_for_commit() {
+        let info = Arc::new(RwLock::new(DeviceInfo {
+            sysname: "hidraw-test".to_string(),
+            profiles: vec![ProfileInfo {
+                buttons: vec![ButtonInfo::default()],
+                ..Default::default()
+            }],
+            ..Default::default()
+        }));
+        let (actor, state) = MockDriver::default().spawn(&info).await;
+
+        let button = RatbagButton::new(Arc::clone(&info), 0, 0);
+        button
+            .store_mapping((3, OwnedValue::from(30_u32)))
+            .await
+            .unwrap();
+
+        /* Give the actor a chance to run: nothing may reach the driver. */
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(state.commits(), 0);
+        assert!(info.read().await.profiles[0].is_dirty);
+
+        actor.commit().await.unwrap();
+        assert_eq!(state.commits(), 1);
+    }
+}
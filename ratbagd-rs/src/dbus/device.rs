@@ -1,16 +1,37 @@
 /* DBus Device interface: per-mouse object exposing model/name/firmware and child profile paths,
  * backed by shared DeviceInfo and optional actor handle for commit/shutdown. */
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 use tokio::sync::RwLock;
 use zbus::interface;
 use zbus::zvariant::ObjectPath;
 
-use crate::actor::ActorHandle;
-use crate::device::DeviceInfo;
+use crate::actor::{ActorHandle, CommitError};
+use crate::device::{BatteryInfo, Color, DeviceInfo};
 
 use super::profile::RatbagProfile;
 
+/* Live LED updates closer together than this are dropped, so a color
+ * stream cannot flood the device's HID queue. */
+const LIVE_LED_MIN_INTERVAL: Duration = Duration::from_millis(50);
+
+/* The failures of a commit of the device at `device_path`, as the
+ * (object path, error) pairs `Commit` returns. */
+pub(crate) fn failure_paths(
+    device_path: &str,
+    error: &CommitError,
+) -> Vec<(ObjectPath<'static>, String)> {
+    error
+        .failures
+        .iter()
+        .filter_map(|(target, message)| {
+            let path = ObjectPath::try_from(target.object_path(device_path)).ok()?;
+            Some((path, message.clone()))
+        })
+        .collect()
+}
+
 /// The `org.freedesktop.ratbag1.Device` interface.
 ///
 /// Each connected mouse has one Device object registered on the DBus bus.
@@ -20,11 +41,139 @@ pub struct RatbagDevice {
     info: Arc<RwLock<DeviceInfo>>,
     path: String,
     actor: Option<ActorHandle>,
+    /* When the last live LED update was passed to the actor. */
+    last_live_led: Mutex<Option<Instant>>,
 }
 
 impl RatbagDevice {
     pub fn new(info: Arc<RwLock<DeviceInfo>>, path: String, actor: Option<ActorHandle>) -> Self {
-        Self { info, path, actor }
+        Self {
+            info,
+            path,
+            actor,
+            last_live_led: Mutex::new(None),
+        }
+    }
+
+    /* The device state this object and its children expose. */
+    pub(crate) fn info(&self) -> &Arc<RwLock<DeviceInfo>> {
+        &self.info
+    }
+
+    /* The hardware actor; `None` for test devices. */
+    #[cfg(feature = "dev-hooks")]
+    pub(crate) fn actor(&self) -> Option<&ActorHandle> {
+        self.actor.as_ref()
+    }
+
+    /* Stage a new SmartShift threshold and mark the device dirty. */
+    async fn store_smart_shift_threshold(&self, value: u32) -> zbus::fdo::Result<()> {
+        let mut info = self.info.write().await;
+        let Some(threshold) = info.smart_shift_threshold.as_mut() else {
+            return Err(zbus::fdo::Error::NotSupported(
+                "SmartShift is not supported on this device".into(),
+            ));
+        };
+        *threshold = match u8::try_from(value) {
+            Ok(v) if v > 0 => v,
+            _ => {
+                return Err(zbus::fdo::Error::InvalidArgs(format!(
+                    "Invalid SmartShift threshold {value} (must be 1-255)"
+                )))
+            }
+        };
+        info.is_dirty = true;
+        Ok(())
+    }
+
+    /* Stage a new sleep timeout and mark the device dirty. */
+    async fn store_sleep_timeout(&self, value: u32) -> zbus::fdo::Result<()> {
+        let mut info = self.info.write().await;
+        let Some(timeout) = info.sleep_timeout.as_mut() else {
+            return Err(zbus::fdo::Error::NotSupported(
+                "Sleep timeout is not supported on this device".into(),
+            ));
+        };
+        timeout
+            .validate(value)
+            .map_err(zbus::fdo::Error::InvalidArgs)?;
+        timeout.minutes = value;
+        info.is_dirty = true;
+        Ok(())
+    }
+
+    /* Write everything staged to the device, for `Commit` and its
+     * variants: the status code and the objects that could not be
+     * written. */
+    async fn write_pending(
+        &self,
+        server: &zbus::ObjectServer,
+        emitter: &zbus::object_server::SignalEmitter<'_>,
+    ) -> (u32, Vec<(ObjectPath<'static>, String)>) {
+        let Some(ref actor) = self.actor else {
+            tracing::warn!("Commit requested but no driver actor for {}", self.path);
+            let error = CommitError::from("No driver for this device".to_string());
+            return (1, failure_paths(&self.path, &error));
+        };
+
+        let needed_repair = actor.needs_repair();
+        let result = actor.commit().await;
+        if actor.needs_repair() != needed_repair {
+            let _ = self.needs_repair_changed(emitter).await;
+        }
+        match result {
+            Ok(()) => {
+                tracing::info!("Commit succeeded for {}", self.path);
+
+                /* Notify frontends that dirty flags have been cleared. */
+                let _ = self.is_dirty_changed(emitter).await;
+                let info = self.info.read().await;
+                for prof in &info.profiles {
+                    let path = format!("{}/p{}", self.path, prof.index);
+                    if let Ok(iface_ref) =
+                        server.interface::<_, RatbagProfile>(path.as_str()).await
+                    {
+                        let _ = iface_ref
+                            .get()
+                            .await
+                            .is_dirty_changed(iface_ref.signal_emitter())
+                            .await;
+                    }
+                }
+
+                (0, Vec::new())
+            }
+            Err(e) => {
+                tracing::error!("Commit failed for {}: {e}", self.path);
+                let _ = Self::resync(emitter).await;
+                (1, failure_paths(&self.path, &e))
+            }
+        }
+    }
+
+    /* Claim the next live LED slot, or `false` if the last update was
+     * less than `LIVE_LED_MIN_INTERVAL` ago. */
+    fn take_live_led_slot(&self) -> bool {
+        let mut last = self.last_live_led.lock().unwrap_or_else(|e| e.into_inner());
+        let now = Instant::now();
+        if last.is_some_and(|t| now.duration_since(t) < LIVE_LED_MIN_INTERVAL) {
+            return false;
+        }
+        *last = Some(now);
+        true
+    }
+
+    /* Current battery state from the driver, `None` without a driver or
+     * battery, or when the read fails. */
+    async fn battery(&self) -> Option<BatteryInfo> {
+        let actor = self.actor.as_ref()?;
+        match actor.read_battery().await {
+            Ok(battery) => battery,
+            Err(e) => {
+                tracing::warn!("Battery read failed for {}: {e}", self.path);
+                None
+            }
+        }
     }
 }
 
@@ -36,12 +185,25 @@ impl RatbagDevice {
         self.info.read().await.model.clone()
     }
 
+    /// Stable device key, `bustype:vid:pid[:serial]`, e.g.
+    /// "usb:046d:c539:4a3b2c1d".  Survives reboots, unlike the object path.
+    #[zbus(property)]
+    async fn stable_key(&self) -> String {
+        self.info.read().await.stable_key()
+    }
+
     /// Human-readable device name.
     #[zbus(property)]
     async fn name(&self) -> String {
         self.info.read().await.name.clone()
     }
 
+    /// Serial number as the kernel reports it (HID_UNIQ); may be empty.
+    #[zbus(property)]
+    async fn serial(&self) -> String {
+        self.info.read().await.serial.clone()
+    }
+
     /// Firmware version string, may be empty.
     #[zbus(property)]
     async fn firmware_version(&self) -> String {
@@ -51,7 +213,105 @@ impl RatbagDevice {
     /// Device type: 0=unspecified, 1=other, 2=mouse, 3=keyboard.
     #[zbus(property)]
     async fn device_type(&self) -> u32 {
-        self.info.read().await.device_type
+        self.info.read().await.device_type as u32
+    }
+
+    /// Bus the device is attached through, e.g. "usb" or "bluetooth".
+    #[zbus(property)]
+    async fn bus_type(&self) -> String {
+        self.info.read().await.bus_ids().0.to_string()
+    }
+
+    /// USB/Bluetooth vendor ID, e.g. 0x046d.
+    #[zbus(property)]
+    async fn vendor_id(&self) -> u32 {
+        u32::from(self.info.read().await.bus_ids().1)
+    }
+
+    /// USB/Bluetooth product ID, e.g. 0xc539.
+    #[zbus(property)]
+    async fn product_id(&self) -> u32 {
+        u32::from(self.info.read().await.bus_ids().2)
+    }
+
+    /// SmartShift threshold (1-255) at which the scroll wheel's ratchet
+    /// releases into free spin; 255 keeps the ratchet engaged.  0 when the
+    /// device has no SmartShift.  Written on `Commit`; until then
+    /// `IsDirty` is set.
+    #[zbus(property)]
+    async fn smart_shift_threshold(&self) -> u32 {
+        self.info
+            .read()
+            .await
+            .smart_shift_threshold
+            .map_or(0, u32::from)
+    }
+
+    #[zbus(property)]
+    async fn set_smart_shift_threshold(
+        &self,
+        #[zbus(signal_emitter)] emitter: zbus::object_server::SignalEmitter<'_>,
+        value: u32,
+    ) -> zbus::Result<()> {
+        self.store_smart_shift_threshold(value).await?;
+        let _ = self.is_dirty_changed(&emitter).await;
+        Ok(())
+    }
+
+    /// Minutes of inactivity before the device goes to sleep; 0 when the
+    /// device has no sleep timer.  Must lie within `SleepTimeoutRange`.
+    /// Written on `Commit`; until then `IsDirty` is set.
+    #[zbus(property)]
+    async fn sleep_timeout(&self) -> u32 {
+        self.info
+            .read()
+            .await
+            .sleep_timeout
+            .map_or(0, |t| t.minutes)
+    }
+
+    #[zbus(property)]
+    async fn set_sleep_timeout(
+        &self,
+        #[zbus(signal_emitter)] emitter: zbus::object_server::SignalEmitter<'_>,
+        value: u32,
+    ) -> zbus::Result<()> {
+        self.store_sleep_timeout(value).await?;
+        let _ = self.is_dirty_changed(&emitter).await;
+        Ok(())
+    }
+
+    /// Lowest and highest sleep timeout in minutes the device accepts;
+    /// (0, 0) when the device has no sleep timer.
+    #[zbus(property)]
+    async fn sleep_timeout_range(&self) -> (u32, u32) {
+        self.info
+            .read()
+            .await
+            .sleep_timeout
+            .map_or((0, 0), |t| (t.min, t.max))
+    }
+
+    /// True if a device-wide setting (`SmartShiftThreshold`,
+    /// `SleepTimeout`) has uncommitted changes.  Profiles report their
+    /// own in `Profile.IsDirty`; a full `Commit` clears both.
+    #[zbus(property)]
+    async fn is_dirty(&self) -> bool {
+        self.info.read().await.is_dirty
+    }
+
+    /// Battery charge in percent, read from the device at most once a
+    /// minute; -1 when the device reports no battery.
+    #[zbus(property)]
+    async fn battery_level(&self) -> i32 {
+        self.battery().await.map_or(-1, |b| i32::from(b.level))
+    }
+
+    /// Whether the battery is charging; false when the device reports no
+    /// battery.
+    #[zbus(property)]
+    async fn battery_charging(&self) -> bool {
+        self.battery().await.is_some_and(|b| b.charging)
     }
 
     /// Array of object paths to this device's profiles.
@@ -68,7 +328,12 @@ impl RatbagDevice {
 
     /// Commit pending changes to the device hardware.
     ///
-    /// Returns 0 on success. On failure, the `Resync` signal is emitted.
+    /// Property sets only mark profiles dirty; this writes them.  The
+    /// other calls that write to the device are `CommitWithFailures`,
+    /// `Repair`, `SetLedColorLive`, `Profile.Commit`, `Profile.Switch` and,
+    /// with `dev-hooks`, `Manager.SendRawReport`.  Returns 0 on success.
+    /// On failure, returns 1 and the `Resync` signal is emitted;
+    /// `CommitWithFailures` also says what could not be written.
     /// After a successful commit the actor clears all dirty flags; we then
     /// emit `PropertiesChanged` for `IsDirty` on each profile so that
     /// listening frontends (Piper, ratbagctl) see the updated state
@@ -78,41 +343,217 @@ impl RatbagDevice {
         #[zbus(object_server)] server: &zbus::ObjectServer,
         #[zbus(signal_emitter)] emitter: zbus::object_server::SignalEmitter<'_>,
     ) -> u32 {
+        self.write_pending(server, &emitter).await.0
+    }
+
+    /// Commit like `Commit`, and also return the `(object, error)` pairs
+    /// that could not be written, e.g. one LED.  The list is empty on
+    /// success.
+    #[zbus(out_args("status", "failures"))]
+    async fn commit_with_failures(
+        &self,
+        #[zbus(object_server)] server: &zbus::ObjectServer,
+        #[zbus(signal_emitter)] emitter: zbus::object_server::SignalEmitter<'_>,
+    ) -> (u32, Vec<(ObjectPath<'static>, String)>) {
+        self.write_pending(server, &emitter).await
+    }
+
+    /// Whether the device's objects reflect what the hardware holds.
+    /// False while a device that was asleep when it was plugged in has
+    /// not answered yet; `Manager.DeviceReady` fires when it turns true.
+    #[zbus(property)]
+    async fn is_ready(&self) -> bool {
+        self.actor.as_ref().is_none_or(ActorHandle::is_ready)
+    }
+
+    /// Whether the profiles stored on the device failed their checksums.
+    /// The device then runs on its built-in profiles until `Repair`
+    /// rewrites them.  Updated after every commit.
+    #[zbus(property)]
+    async fn needs_repair(&self) -> bool {
+        self.actor.as_ref().is_some_and(ActorHandle::needs_repair)
+    }
+
+    /// Rewrite every profile stored on the device, whether its checksum
+    /// failed or not, with the settings the profiles show now.
+    ///
+    /// Returns like `CommitWithFailures`; `NeedsRepair` is cleared when it
+    /// succeeds.
+    /// NotSupported when the device keeps no checksummed profiles.
+    #[zbus(out_args("status", "failures"))]
+    async fn repair(
+        &self,
+        #[zbus(object_server)] server: &zbus::ObjectServer,
+        #[zbus(signal_emitter)] emitter: zbus::object_server::SignalEmitter<'_>,
+    ) -> zbus::fdo::Result<(u32, Vec<(ObjectPath<'static>, String)>)> {
         let Some(ref actor) = self.actor else {
-            tracing::warn!("Commit requested but no driver actor for {}", self.path);
-            return 1;
+            return Err(zbus::fdo::Error::NotSupported(
+                "Repairing profiles needs a driver".into(),
+            ));
         };
+        if !actor.request_repair().await.map_err(zbus::fdo::Error::Failed)? {
+            return Err(zbus::fdo::Error::NotSupported(
+                "This device has no stored profiles to repair".into(),
+            ));
+        }
+        tracing::info!("Rewriting all stored profiles of {}", self.path);
+        Ok(self.write_pending(server, &emitter).await)
+    }
 
-        match actor.commit().await {
-            Ok(()) => {
-                tracing::info!("Commit succeeded for {}", self.path);
-
-                /* Notify frontends that dirty flags have been cleared. */
-                let info = self.info.read().await;
-                for prof in &info.profiles {
-                    let path = format!("{}/p{}", self.path, prof.index);
-                    if let Ok(iface_ref) =
-                        server.interface::<_, RatbagProfile>(path.as_str()).await
-                    {
-                        let _ = iface_ref
-                            .get()
-                            .await
-                            .is_dirty_changed(iface_ref.signal_emitter())
-                            .await;
-                    }
-                }
+    /// The device's raw HID report descriptor, for working out the
+    /// report layout of a device that has no driver yet.
+    ///
+    /// Only available when built with `--features dev-hooks`.
+    #[cfg(feature = "dev-hooks")]
+    async fn get_hid_descriptor(&self) -> zbus::fdo::Result<Vec<u8>> {
+        let Some(ref actor) = self.actor else {
+            return Err(zbus::fdo::Error::NotSupported(
+                "Reading the HID descriptor needs a driver".into(),
+            ));
+        };
+        actor
+            .read_hid_descriptor()
+            .await
+            .map_err(zbus::fdo::Error::Failed)
+    }
 
-                0
+    /// Show a solid color on LED `index` right away, for ambient lighting
+    /// that follows an external color source.
+    ///
+    /// Written straight to the device: the profile is neither changed nor
+    /// marked dirty, and the next `Commit` restores the profile's LED.
+    /// Components are clamped to 255.  Updates closer together than 50 ms
+    /// are dropped and return false.  NotSupported when the driver cannot
+    /// write LEDs outside of a commit.
+    async fn set_led_color_live(
+        &self,
+        index: u32,
+        red: u32,
+        green: u32,
+        blue: u32,
+    ) -> zbus::fdo::Result<bool> {
+        let Some(ref actor) = self.actor else {
+            return Err(zbus::fdo::Error::NotSupported(
+                "Live LED colors need a driver".into(),
+            ));
+        };
+        let unmanaged = self
+            .info
+            .read()
+            .await
+            .profiles
+            .iter()
+            .find(|p| p.is_active)
+            .and_then(|p| p.leds.iter().find(|l| l.index == index).map(|l| l.unmanaged));
+        match unmanaged {
+            None => {
+                return Err(zbus::fdo::Error::InvalidArgs(format!(
+                    "No LED {index} on the active profile"
+                )));
             }
-            Err(e) => {
-                tracing::error!("Commit failed for {}: {e}", self.path);
-                let _ = Self::resync(&emitter).await;
-                1
+            Some(true) => {
+                return Err(zbus::fdo::Error::NotSupported(format!(
+                    "Led {index} is not managed by ratbagd"
+                )));
             }
+            Some(false) => {}
+        }
+        if !self.take_live_led_slot() {
+            return Ok(false);
+        }
+
+        let color = Color { red, green, blue }.to_rgb();
+        match actor.set_led_color_live(index, color).await {
+            Ok(true) => Ok(true),
+            Ok(false) => Err(zbus::fdo::Error::NotSupported(
+                "This device cannot set LED colors live".into(),
+            )),
+            Err(e) => Err(zbus::fdo::Error::Failed(e)),
         }
     }
 
     /// Signal emitted when an error occurs during commit.
     #[zbus(signal)]
-    async fn resync(signal_emitter: &zbus::object_server::SignalEmitter<'_>) -> zbus::Result<()>;
+    pub async fn resync(signal_emitter: &zbus::object_server::SignalEmitter<'_>) -> zbus::Result<()>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::{LedInfo, LedMode, ProfileInfo, RgbColor};
+    use crate::test_support::MockDriver;
+
+    fn breathing_led() -> LedInfo {
+        LedInfo {
+            mode: LedMode::Breathing,
+            modes: vec![LedMode::Solid, LedMode::Breathing],
+            color_depth: 3,
+            effect_duration: 1000,
+            brightness: 255,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_smart_shift_marks_device_dirty_until_commit() {
+        let info = Arc::new(RwLock::new(DeviceInfo {
+            sysname: "hidraw-test".to_string(),
+            smart_shift_threshold: Some(30),
+            profiles: vec![ProfileInfo::default()],
+            ..Default::default()
+        }));
+        let (actor, _) = MockDriver::default().spawn(&info).await;
+        let device = RatbagDevice::new(Arc::clone(&info), "/dev".into(), Some(actor.clone()));
+
+        device.store_smart_shift_threshold(40).await.unwrap();
+        assert!(device.is_dirty().await);
+        assert!(!info.read().await.profiles[0].is_dirty);
+
+        /* A profile commit does not write device-wide settings. */
+        actor.commit_profile(0).await.unwrap();
+        assert!(device.is_dirty().await);
+
+        actor.commit().await.unwrap();
+        assert!(!device.is_dirty().await);
+        assert_eq!(device.smart_shift_threshold().await, 40);
+    }
+
+    #[tokio::test]
+    async fn test_live_led_color_leaves_profile_clean() {
+        let info = Arc::new(RwLock::new(DeviceInfo {
+            sysname: "hidraw-test".to_string(),
+            profiles: vec![ProfileInfo {
+                is_active: true,
+                leds: vec![breathing_led()],
+                ..Default::default()
+            }],
+            ..Default::default()
+        }));
+        let driver = MockDriver {
+            live_leds: true,
+            ..Default::default()
+        };
+        let (actor, state) = driver.spawn(&info).await;
+        let device = RatbagDevice::new(Arc::clone(&info), "/dev".into(), Some(actor));
+
+        assert!(device.set_led_color_live(0, 255, 128, 300).await.unwrap());
+        assert_eq!(
+            *state.live_writes.lock().unwrap(),
+            [(0, RgbColor { r: 255, g: 128, b: 255 })]
+        );
+        {
+            let info = info.read().await;
+            assert!(!info.profiles[0].is_dirty);
+            assert_eq!(info.profiles[0].leds[0].mode, LedMode::Breathing);
+        }
+
+        /* Straight away again: dropped by the rate limit. */
+        assert!(!device.set_led_color_live(0, 0, 0, 0).await.unwrap());
+        assert_eq!(state.live_writes.lock().unwrap().len(), 1);
+
+        assert!(matches!(
+            device.set_led_color_live(1, 0, 0, 0).await,
+            Err(zbus::fdo::Error::InvalidArgs(_))
+        ));
+    }
 }
@@ -1,11 +1,15 @@
-/* DBus LED interface: per-LED object managing mode, colors, brightness, and effect duration for a
- * profile LED, writing changes into DeviceInfo and committing via the actor. */
+/* DBus LED interface: per-LED object managing mode, colors, brightness, effect duration and
+ * direction for a profile LED, writing changes into DeviceInfo; the device's Commit writes them
+ * to hardware. */
 use std::sync::Arc;
 
 use tokio::sync::RwLock;
 use zbus::interface;
+use zbus::object_server::SignalEmitter;
 
-use crate::device::{Color, DeviceInfo, LedMode};
+use crate::device::{Color, DeviceInfo, LedDirection, LedInfo, LedMode};
+
+use super::profile::profile_is_dirty_changed;
 
 /// The `org.freedesktop.ratbag1.Led` interface.
 ///
@@ -32,6 +36,45 @@ impl RatbagLed {
             led_id,
         }
     }
+
+    /* Apply `change` to this LED, which must be managed, and mark it and
+     * its profile dirty. */
+    async fn update(&self, change: impl FnOnce(&mut LedInfo) -> zbus::Result<()>) -> zbus::Result<()> {
+        let mut info = self.device_info.write().await;
+        let profile = info.find_profile_mut(self.profile_id).ok_or_else(|| {
+            zbus::fdo::Error::Failed(format!(
+                "Profile {} not found", self.profile_id
+            ))
+        })?;
+        let led = profile.find_led_mut(self.led_id).ok_or_else(|| {
+            zbus::fdo::Error::Failed(format!(
+                "Led {} not found in profile {}", self.led_id, self.profile_id
+            ))
+        })?;
+        ensure_managed(led)?;
+        change(led)?;
+        led.is_dirty = true;
+        profile.is_dirty = true;
+        Ok(())
+    }
+
+    /* Switch to `mode`, which the LED must advertise. */
+    async fn update_mode(&self, mode: u32) -> zbus::Result<()> {
+        let led_mode = LedMode::from_u32(mode).ok_or_else(|| {
+            zbus::fdo::Error::InvalidArgs(format!("Invalid LedMode: {mode}"))
+        })?;
+        self.update(|led| {
+            if !led.modes.contains(&led_mode) {
+                let supported: Vec<u32> = led.modes.iter().map(|m| *m as u32).collect();
+                return Err(zbus::fdo::Error::InvalidArgs(format!(
+                    "LedMode {mode} not supported by this LED (supported: {supported:?})"
+                )).into());
+            }
+            led.mode = led_mode;
+            Ok(())
+        })
+        .await
+    }
 }
 
 /// Convert a DBus RGB tuple `(u32, u32, u32)` into a [`Color`], clamping to 255.
@@ -50,6 +93,17 @@ fn color_to_tuple(c: &Color) -> (u32, u32, u32) {
     (c.red, c.green, c.blue)
 }
 
+/// Refuse writes to a zone the device file marks as unmanaged.
+fn ensure_managed(led: &LedInfo) -> zbus::Result<()> {
+    if led.unmanaged {
+        return Err(zbus::fdo::Error::NotSupported(format!(
+            "Led {} is not managed by ratbagd", led.index
+        ))
+        .into());
+    }
+    Ok(())
+}
+
 #[interface(name = "org.freedesktop.ratbag1.Led")]
 impl RatbagLed {
     /// Zero-based LED index (constant).
@@ -58,6 +112,17 @@ impl RatbagLed {
         self.led_id
     }
 
+    /// Whether ratbagd controls this LED (constant).  False for zones the
+    /// device file lists in `UnmanagedLeds=`: their properties are
+    /// read-only and commits never write them.
+    #[zbus(property)]
+    async fn is_managed(&self) -> bool {
+        let info = self.device_info.read().await;
+        info.find_profile(self.profile_id)
+            .and_then(|p| p.find_led(self.led_id))
+            .is_none_or(|l| !l.unmanaged)
+    }
+
     /// Current LED mode as a u32 discriminant (read-write).
     #[zbus(property)]
     async fn mode(&self) -> u32 {
@@ -69,28 +134,14 @@ impl RatbagLed {
     }
 
     #[zbus(property)]
-    async fn set_mode(&self, mode: u32) -> zbus::Result<()> {
-        let led_mode = LedMode::from_u32(mode).ok_or_else(|| {
-            zbus::fdo::Error::InvalidArgs(format!("Invalid LedMode: {mode}"))
-        })?;
-        let mut info = self.device_info.write().await;
-        let profile = info.find_profile_mut(self.profile_id).ok_or_else(|| {
-            zbus::fdo::Error::Failed(format!(
-                "Profile {} not found", self.profile_id
-            ))
-        })?;
-        let led = profile.find_led_mut(self.led_id).ok_or_else(|| {
-            zbus::fdo::Error::Failed(format!(
-                "Led {} not found in profile {}", self.led_id, self.profile_id
-            ))
-        })?;
-        if !led.modes.contains(&led_mode) {
-            return Err(zbus::fdo::Error::InvalidArgs(format!(
-                "LedMode {} not supported by this LED", mode
-            )).into());
-        }
-        led.mode = led_mode;
-        profile.is_dirty = true;
+    async fn set_mode(
+        &self,
+        #[zbus(object_server)] server: &zbus::ObjectServer,
+        #[zbus(signal_emitter)] emitter: SignalEmitter<'_>,
+        mode: u32,
+    ) -> zbus::Result<()> {
+        self.update_mode(mode).await?;
+        profile_is_dirty_changed(server, emitter.path()).await;
         Ok(())
     }
 
@@ -115,24 +166,25 @@ impl RatbagLed {
     }
 
     #[zbus(property)]
-    async fn set_color(&self, color: (u32, u32, u32)) -> zbus::Result<()> {
-        let mut info = self.device_info.write().await;
-        let profile = info.find_profile_mut(self.profile_id).ok_or_else(|| {
-            zbus::fdo::Error::Failed(format!(
-                "Profile {} not found", self.profile_id
-            ))
-        })?;
-        let led = profile.find_led_mut(self.led_id).ok_or_else(|| {
-            zbus::fdo::Error::Failed(format!(
-                "Led {} not found in profile {}", self.led_id, self.profile_id
-            ))
-        })?;
-        led.color = color_from_tuple(color);
-        profile.is_dirty = true;
+    async fn set_color(
+        &self,
+        #[zbus(object_server)] server: &zbus::ObjectServer,
+        #[zbus(signal_emitter)] emitter: SignalEmitter<'_>,
+        color: (u32, u32, u32),
+    ) -> zbus::Result<()> {
+        self.update(|led| {
+            led.color = color_from_tuple(color);
+            Ok(())
+        })
+        .await?;
+        profile_is_dirty_changed(server, emitter.path()).await;
         Ok(())
     }
 
     /// Secondary LED color for multi-color effects like Starlight (read-write).
+    ///
+    /// In breathing mode, devices that support it fade between the primary
+    /// and secondary color; others ignore the secondary color.
     #[zbus(property)]
     async fn secondary_color(&self) -> (u32, u32, u32) {
         let info = self.device_info.read().await;
@@ -143,20 +195,18 @@ impl RatbagLed {
     }
 
     #[zbus(property)]
-    async fn set_secondary_color(&self, color: (u32, u32, u32)) -> zbus::Result<()> {
-        let mut info = self.device_info.write().await;
-        let profile = info.find_profile_mut(self.profile_id).ok_or_else(|| {
-            zbus::fdo::Error::Failed(format!(
-                "Profile {} not found", self.profile_id
-            ))
-        })?;
-        let led = profile.find_led_mut(self.led_id).ok_or_else(|| {
-            zbus::fdo::Error::Failed(format!(
-                "Led {} not found in profile {}", self.led_id, self.profile_id
-            ))
-        })?;
-        led.secondary_color = color_from_tuple(color);
-        profile.is_dirty = true;
+    async fn set_secondary_color(
+        &self,
+        #[zbus(object_server)] server: &zbus::ObjectServer,
+        #[zbus(signal_emitter)] emitter: SignalEmitter<'_>,
+        color: (u32, u32, u32),
+    ) -> zbus::Result<()> {
+        self.update(|led| {
+            led.secondary_color = color_from_tuple(color);
+            Ok(())
+        })
+        .await?;
+        profile_is_dirty_changed(server, emitter.path()).await;
         Ok(())
     }
 
@@ -171,20 +221,18 @@ impl RatbagLed {
     }
 
     #[zbus(property)]
-    async fn set_tertiary_color(&self, color: (u32, u32, u32)) -> zbus::Result<()> {
-        let mut info = self.device_info.write().await;
-        let profile = info.find_profile_mut(self.profile_id).ok_or_else(|| {
-            zbus::fdo::Error::Failed(format!(
-                "Profile {} not found", self.profile_id
-            ))
-        })?;
-        let led = profile.find_led_mut(self.led_id).ok_or_else(|| {
-            zbus::fdo::Error::Failed(format!(
-                "Led {} not found in profile {}", self.led_id, self.profile_id
-            ))
-        })?;
-        led.tertiary_color = color_from_tuple(color);
-        profile.is_dirty = true;
+    async fn set_tertiary_color(
+        &self,
+        #[zbus(object_server)] server: &zbus::ObjectServer,
+        #[zbus(signal_emitter)] emitter: SignalEmitter<'_>,
+        color: (u32, u32, u32),
+    ) -> zbus::Result<()> {
+        self.update(|led| {
+            led.tertiary_color = color_from_tuple(color);
+            Ok(())
+        })
+        .await?;
+        profile_is_dirty_changed(server, emitter.path()).await;
         Ok(())
     }
 
@@ -209,20 +257,53 @@ impl RatbagLed {
     }
 
     #[zbus(property)]
-    async fn set_effect_duration(&self, duration: u32) -> zbus::Result<()> {
-        let mut info = self.device_info.write().await;
-        let profile = info.find_profile_mut(self.profile_id).ok_or_else(|| {
-            zbus::fdo::Error::Failed(format!(
-                "Profile {} not found", self.profile_id
-            ))
-        })?;
-        let led = profile.find_led_mut(self.led_id).ok_or_else(|| {
-            zbus::fdo::Error::Failed(format!(
-                "Led {} not found in profile {}", self.led_id, self.profile_id
-            ))
+    async fn set_effect_duration(
+        &self,
+        #[zbus(object_server)] server: &zbus::ObjectServer,
+        #[zbus(signal_emitter)] emitter: SignalEmitter<'_>,
+        duration: u32,
+    ) -> zbus::Result<()> {
+        self.update(|led| {
+            led.effect_duration = duration.min(10000);
+            Ok(())
+        })
+        .await?;
+        profile_is_dirty_changed(server, emitter.path()).await;
+        Ok(())
+    }
+
+    /// Direction of moving effects such as ColorWave: 0 clockwise,
+    /// 1 counterclockwise (read-write).
+    #[zbus(property)]
+    async fn effect_direction(&self) -> u32 {
+        let info = self.device_info.read().await;
+        info.find_profile(self.profile_id)
+            .and_then(|p| p.find_led(self.led_id))
+            .map(|l| l.effect_direction as u32)
+            .unwrap_or(LedDirection::Clockwise as u32)
+    }
+
+    #[zbus(property)]
+    async fn set_effect_direction(
+        &self,
+        #[zbus(object_server)] server: &zbus::ObjectServer,
+        #[zbus(signal_emitter)] emitter: SignalEmitter<'_>,
+        direction: u32,
+    ) -> zbus::Result<()> {
+        let direction = LedDirection::from_u32(direction).ok_or_else(|| {
+            zbus::fdo::Error::InvalidArgs(format!("Invalid LedDirection: {direction}"))
         })?;
-        led.effect_duration = duration.min(10000);
-        profile.is_dirty = true;
+        self.update(|led| {
+            if !led.has_direction && direction != LedDirection::Clockwise {
+                return Err(zbus::fdo::Error::NotSupported(
+                    "This LED has no effect direction".to_owned(),
+                ).into());
+            }
+            led.effect_direction = direction;
+            Ok(())
+        })
+        .await?;
+        profile_is_dirty_changed(server, emitter.path()).await;
         Ok(())
     }
 
@@ -237,20 +318,54 @@ impl RatbagLed {
     }
 
     #[zbus(property)]
-    async fn set_brightness(&self, brightness: u32) -> zbus::Result<()> {
-        let mut info = self.device_info.write().await;
-        let profile = info.find_profile_mut(self.profile_id).ok_or_else(|| {
-            zbus::fdo::Error::Failed(format!(
-                "Profile {} not found", self.profile_id
-            ))
-        })?;
-        let led = profile.find_led_mut(self.led_id).ok_or_else(|| {
-            zbus::fdo::Error::Failed(format!(
-                "Led {} not found in profile {}", self.led_id, self.profile_id
-            ))
-        })?;
-        led.brightness = brightness.min(255);
-        profile.is_dirty = true;
+    async fn set_brightness(
+        &self,
+        #[zbus(object_server)] server: &zbus::ObjectServer,
+        #[zbus(signal_emitter)] emitter: SignalEmitter<'_>,
+        brightness: u32,
+    ) -> zbus::Result<()> {
+        self.update(|led| {
+            led.brightness = brightness.min(255);
+            Ok(())
+        })
+        .await?;
+        profile_is_dirty_changed(server, emitter.path()).await;
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::ProfileInfo;
+
+    #[tokio::test]
+    async fn test_unsupported_mode_is_rejected() {
+        let info = Arc::new(RwLock::new(DeviceInfo {
+            sysname: "hidraw-test".to_string(),
+            profiles: vec![ProfileInfo {
+                leds: vec![LedInfo {
+                    mode: LedMode::Solid,
+                    modes: vec![LedMode::Solid, LedMode::Breathing],
+                    color_depth: 1,
+                    brightness: 255,
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }],
+            ..Default::default()
+        }));
+        let led = RatbagLed::new(Arc::clone(&info), 0, 0);
+
+        let err = led.update_mode(LedMode::TriColor as u32).await.unwrap_err();
+        assert!(err.to_string().contains("supported: [1, 3]"), "{err}");
+        {
+            let info = info.read().await;
+            assert_eq!(info.profiles[0].leds[0].mode, LedMode::Solid);
+            assert!(!info.profiles[0].is_dirty);
+        }
+
+        led.update_mode(LedMode::Breathing as u32).await.unwrap();
+        assert_eq!(info.read().await.profiles[0].leds[0].mode, LedMode::Breathing);
+    }
+}
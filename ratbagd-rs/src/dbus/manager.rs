@@ -1,16 +1,22 @@
-/* DBus Manager interface: entry point that tracks device object paths and, under dev-hooks, injects
- * or resets synthetic test devices. */
+/* DBus Manager interface: entry point that tracks device object paths, answers per-driver
+ * capability queries and, under dev-hooks, injects or resets synthetic test devices. */
+use std::collections::HashMap;
+
+use serde::Serialize;
+use tokio::sync::mpsc;
 use zbus::interface;
+use zbus::object_server::ObjectServer;
 use zbus::zvariant::ObjectPath;
 
+use crate::dbus::device::RatbagDevice;
+use crate::device::DeviceInfo;
+
 /// DBus API version. Must match the C daemon's value for client compatibility.
 pub const API_VERSION: i32 = 2;
 
 #[cfg(feature = "dev-hooks")]
 use crate::udev_monitor::DeviceAction;
 #[cfg(feature = "dev-hooks")]
-use tokio::sync::mpsc;
-#[cfg(feature = "dev-hooks")]
 use tracing::{info, warn};
 
 /// The `org.freedesktop.ratbag1.Manager` interface.
@@ -21,6 +27,12 @@ use tracing::{info, warn};
 pub struct RatbagManager {
     devices: Vec<String>,
 
+    /// Channel that asks the main event loop to shut the daemon down.
+    quit_tx: Option<mpsc::Sender<()>>,
+
+    /// Path of the commit audit log; empty when it is disabled.
+    audit_log_path: String,
+
     /// Channel to inject synthetic test devices into the main event loop.
     /// Only present when the `dev-hooks` feature is enabled.
     #[cfg(feature = "dev-hooks")]
@@ -33,10 +45,29 @@ pub struct RatbagManager {
     current_test_sysname: Option<String>,
 }
 
+/* One entry of the `GetAllState` document. */
+#[derive(Serialize)]
+struct DeviceState<'a> {
+    path: &'a str,
+    #[serde(flatten)]
+    info: &'a DeviceInfo,
+}
+
+/* The `GetAllState` document for `devices`, in the order given. */
+fn all_state_json(devices: &[(String, DeviceInfo)]) -> serde_json::Result<String> {
+    let devices: Vec<_> = devices
+        .iter()
+        .map(|(path, info)| DeviceState { path, info })
+        .collect();
+    serde_json::to_string(&serde_json::json!({ "devices": devices }))
+}
+
 impl Default for RatbagManager {
     fn default() -> Self {
         Self {
             devices: Vec::new(),
+            quit_tx: None,
+            audit_log_path: String::new(),
             #[cfg(feature = "dev-hooks")]
             test_device_tx: None,
             #[cfg(feature = "dev-hooks")]
@@ -58,6 +89,16 @@ impl RatbagManager {
         self.devices.retain(|p| p != path);
     }
 
+    /// Wire up the channel `Quit` uses to stop the main event loop.
+    pub fn set_quit_tx(&mut self, tx: mpsc::Sender<()>) {
+        self.quit_tx = Some(tx);
+    }
+
+    /// Record where the commit audit log is written.
+    pub fn set_audit_log_path(&mut self, path: String) {
+        self.audit_log_path = path;
+    }
+
     /// Wire up the test device channel.
     ///
     /// Must be called before `LoadTestDevice` will function.
@@ -75,6 +116,28 @@ impl RatbagManager {
         API_VERSION
     }
 
+    /// The daemon's version, for bug reports.
+    #[zbus(property)]
+    fn daemon_version(&self) -> &str {
+        env!("CARGO_PKG_VERSION")
+    }
+
+    /// `Driver=` names this daemon was built with.
+    #[zbus(property)]
+    fn drivers(&self) -> Vec<String> {
+        crate::driver::DRIVER_NAMES
+            .iter()
+            .map(|name| name.to_string())
+            .collect()
+    }
+
+    /// File the daemon appends a line to for every commit, or an empty
+    /// string when the audit log is disabled (see `RATBAGD_AUDIT_LOG`).
+    #[zbus(property)]
+    fn audit_log_path(&self) -> &str {
+        &self.audit_log_path
+    }
+
     /// Array of object paths to the connected devices.
     #[zbus(property)]
     fn devices(&self) -> Vec<ObjectPath<'static>> {
@@ -84,6 +147,91 @@ impl RatbagManager {
             .collect()
     }
 
+    /// Every connected device's full state as one JSON document, so a
+    /// client can fill its whole UI in one call instead of reading each
+    /// property:
+    /// `{"devices": [{"path", "name", "model", ..., "profiles": [...]}]}`.
+    ///
+    /// The fields mirror the DBus properties, with enum values given by
+    /// name (e.g. `"Solid"`, `{"Unified": 800}`).  A snapshot: watch the
+    /// per-object properties for later changes.
+    async fn get_all_state(
+        &self,
+        #[zbus(object_server)] server: &ObjectServer,
+    ) -> zbus::fdo::Result<String> {
+        let mut devices = Vec::with_capacity(self.devices.len());
+        for path in &self.devices {
+            /* A device being torn down may already be gone. */
+            let Ok(device) = server.interface::<_, RatbagDevice>(path.as_str()).await else {
+                continue;
+            };
+            let info = device.get().await.info().read().await.clone();
+            devices.push((path.clone(), info));
+        }
+        all_state_json(&devices).map_err(|e| zbus::fdo::Error::Failed(e.to_string()))
+    }
+
+    /// Features a driver can write to hardware, keyed by name: "profiles",
+    /// "buttons", "macros", "leds" and "separate-xy".
+    ///
+    /// `driver` is a `Driver=` value from the device database.  Answered
+    /// without hardware; a device may still lack a feature its driver
+    /// supports.
+    fn driver_capabilities(&self, driver: &str) -> zbus::fdo::Result<HashMap<String, bool>> {
+        let driver = crate::driver::create_driver(driver)
+            .ok_or_else(|| zbus::fdo::Error::InvalidArgs(format!("Unknown driver '{driver}'")))?;
+        Ok(driver
+            .capabilities()
+            .entries()
+            .into_iter()
+            .map(|(name, supported)| (name.to_string(), supported))
+            .collect())
+    }
+
+    /// Shut the daemon down cleanly: every device actor is stopped, the
+    /// udev monitor is closed and the process exits.
+    ///
+    /// Only root or the user the daemon runs as may call this.
+    async fn quit(
+        &self,
+        #[zbus(header)] header: zbus::message::Header<'_>,
+        #[zbus(connection)] conn: &zbus::Connection,
+    ) -> zbus::fdo::Result<()> {
+        let sender = header
+            .sender()
+            .ok_or_else(|| zbus::fdo::Error::AccessDenied("Quit needs a sender".into()))?;
+        let uid = zbus::fdo::DBusProxy::new(conn)
+            .await?
+            .get_connection_unix_user(sender.to_owned().into())
+            .await?;
+        if uid != 0 && uid != nix::unistd::geteuid().as_raw() {
+            tracing::warn!("Quit: refused for uid {uid}");
+            return Err(zbus::fdo::Error::AccessDenied(
+                "Only root or the daemon's user may stop ratbagd".into(),
+            ));
+        }
+
+        tracing::info!("Quit requested by uid {uid}");
+        let Some(tx) = &self.quit_tx else {
+            return Err(zbus::fdo::Error::Failed("Quit channel not initialised".into()));
+        };
+        /* A full channel means a quit is already pending. */
+        let _ = tx.try_send(());
+        Ok(())
+    }
+
+    /// Emitted once a device's driver has finished loading its profiles
+    /// and the device's DBus objects reflect real hardware state.
+    ///
+    /// The device is already listed in `Devices` when this fires.  A
+    /// device that was asleep when it was plugged in is listed with
+    /// `IsReady` false, and this fires when it wakes up.
+    #[zbus(signal)]
+    pub async fn device_ready(
+        signal_emitter: &zbus::object_server::SignalEmitter<'_>,
+        device: ObjectPath<'_>,
+    ) -> zbus::Result<()>;
+
     /// Load a synthetic test device from a JSON description.
     ///
     /// The JSON format mirrors the C `ratbagd-json.c` schema.
@@ -95,7 +243,7 @@ impl RatbagManager {
         use crate::test_device::spec::{build_device_info, parse_json};
 
         let spec = parse_json(&json).map_err(|e| {
-            warn!("LoadTestDevice: JSON parse error: {e}");
+            warn!("LoadTestDevice: rejected device JSON: {e}");
             zbus::fdo::Error::InvalidArgs(format!("Invalid device JSON: {e}"))
         })?;
 
@@ -137,6 +285,49 @@ impl RatbagManager {
         Ok(sysname)
     }
 
+    /// Send `data` to `device` as a raw HID report and return the reply,
+    /// for protocol debugging.  `data[0]` is the report ID.
+    ///
+    /// With `feature` the report is set as a feature report and the same
+    /// report ID is read back at the same length.  Otherwise it is written
+    /// as an output report and the reply is the first report the device
+    /// sends within 500 ms, or empty if it sends none.  The driver does
+    /// not see either exchange.
+    ///
+    /// Only available when built with `--features dev-hooks`.
+    #[cfg(feature = "dev-hooks")]
+    async fn send_raw_report(
+        &self,
+        device: ObjectPath<'_>,
+        feature: bool,
+        data: Vec<u8>,
+        #[zbus(object_server)] server: &ObjectServer,
+    ) -> zbus::fdo::Result<Vec<u8>> {
+        if data.is_empty() {
+            return Err(zbus::fdo::Error::InvalidArgs("Empty report".into()));
+        }
+        let device_ref = server
+            .interface::<_, RatbagDevice>(device.as_str())
+            .await
+            .map_err(|_| zbus::fdo::Error::UnknownObject(format!("No device at {device}")))?;
+        let Some(actor) = device_ref.get().await.actor().cloned() else {
+            return Err(zbus::fdo::Error::NotSupported(
+                "Test devices have no hardware to send to".into(),
+            ));
+        };
+
+        info!(
+            "SendRawReport: {} {} bytes to {device}: {:02x?}",
+            if feature { "feature" } else { "output" },
+            data.len(),
+            data
+        );
+        actor
+            .send_raw_report(feature, data)
+            .await
+            .map_err(zbus::fdo::Error::IOError)
+    }
+
     /// Remove the currently-live synthetic test device.
     ///
     /// A no-op if no test device is loaded.
@@ -166,3 +357,38 @@ impl RatbagManager {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::ProfileInfo;
+
+    fn device(sysname: &str, profiles: u32) -> DeviceInfo {
+        DeviceInfo {
+            sysname: sysname.to_string(),
+            name: format!("Mouse {sysname}"),
+            model: "usb:046d:c539:0".to_string(),
+            profiles: (0..profiles)
+                .map(|index| ProfileInfo { index, ..Default::default() })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_all_state_lists_every_device_and_profile() {
+        let devices = [
+            ("/org/freedesktop/ratbag1/device/hidraw0".to_string(), device("hidraw0", 3)),
+            ("/org/freedesktop/ratbag1/device/hidraw1".to_string(), device("hidraw1", 1)),
+        ];
+        let value: serde_json::Value =
+            serde_json::from_str(&all_state_json(&devices).unwrap()).unwrap();
+        let listed = value["devices"].as_array().unwrap();
+        assert_eq!(listed.len(), 2);
+        assert_eq!(listed[0]["path"], "/org/freedesktop/ratbag1/device/hidraw0");
+        assert_eq!(listed[0]["name"], "Mouse hidraw0");
+        assert_eq!(listed[0]["profiles"].as_array().unwrap().len(), 3);
+        assert_eq!(listed[1]["profiles"][0]["index"], 0);
+        assert!(listed[0].get("driver_config").is_none());
+    }
+}
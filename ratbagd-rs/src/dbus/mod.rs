@@ -1,5 +1,11 @@
 /* DBus surface: zbus interface implementations for Manager/Device/Profile/Resolution/Button/LED,
- * plus helpers to register devices and translate device actions from udev. */
+ * plus helpers to register devices and translate device actions from udev.
+ *
+ * Property setters and methods only edit the shared DeviceInfo and mark what they touched dirty;
+ * nothing reaches the hardware until a client calls Commit. Clients that manage commit timing
+ * themselves rely on this. The methods that do write are named for it: `Device.Commit`,
+ * `Device.CommitWithFailures`, `Device.Repair`, `Device.SetLedColorLive`, `Profile.Commit`,
+ * `Profile.Switch` and, with `dev-hooks`, `Manager.SendRawReport`. */
 pub mod button;
 pub mod device;
 pub mod led;
@@ -12,15 +18,18 @@ use std::sync::Arc;
 use std::time::Duration;
 
 use anyhow::Result;
-use tokio::sync::{mpsc, RwLock};
-use tracing::{info, warn};
+use tokio::sync::{mpsc, watch, RwLock};
+use tracing::{error, info, warn};
 use zbus::connection::Builder;
+use zbus::fdo::RequestNameFlags;
 use zbus::zvariant::OwnedValue;
 
 use crate::actor::{self, ActorHandle};
+use crate::audit::AuditLog;
 use crate::device::DeviceInfo;
-use crate::device_database::{BusType, DeviceDb};
+use crate::device_database::{self, BusType, DeviceDb};
 use crate::driver;
+use crate::state_cache::{CacheKey, StateCache};
 use crate::udev_monitor::DeviceAction;
 
 /// Fallback [`OwnedValue`] (`u32` zero) used when zvariant serialization fails.
@@ -46,7 +55,11 @@ enum IfaceKind {
  *
  * Returns a tagged list of all object paths that were registered.  Child
  * objects share the same `Arc<RwLock<DeviceInfo>>` so property mutations
- * propagate to the device-level `commit()` path. */
+ * propagate to the device-level `commit()` path.
+ *
+ * Call this only once `spawn_device_actor` has returned: that is when
+ * `load_profiles` has filled `shared_info`, and the set of child objects
+ * is taken from it here. */
 async fn register_device_on_dbus(
     conn: &zbus::Connection,
     device_path: &str,
@@ -60,7 +73,7 @@ async fn register_device_on_dbus(
     let device_obj = device::RatbagDevice::new(
         Arc::clone(&shared_info),
         device_path.to_owned(),
-        actor_handle,
+        actor_handle.clone(),
     );
 
     if let Err(e) = object_server.at(device_path, device_obj).await {
@@ -80,6 +93,7 @@ async fn register_device_on_dbus(
             Arc::clone(&shared_info),
             device_path.to_owned(),
             prof.index,
+            actor_handle.clone(),
         );
         if let Err(e) = object_server.at(profile_path.as_str(), profile_obj).await {
             warn!("Failed to register profile {profile_path}: {e}");
@@ -130,6 +144,122 @@ async fn register_device_on_dbus(
     object_paths
 }
 
+/* Add a freshly registered device to the manager's device list, then
+ * announce it with `DeviceReady` once its objects reflect real hardware
+ * state, so clients that wait for the signal never see a skeleton device.
+ * That is right away unless `readiness` says the device was asleep when
+ * it was plugged in; then the signal waits until it wakes.
+ *
+ * Shared between the `Add` (udev) and `InjectTest` (dev-hooks) paths. */
+async fn announce_device(
+    conn: &zbus::Connection,
+    device_path: &str,
+    readiness: Option<watch::Receiver<bool>>,
+) -> Result<()> {
+    let object_server = conn.object_server();
+    let iface_ref = object_server
+        .interface::<_, manager::RatbagManager>("/org/freedesktop/ratbag1")
+        .await?;
+    iface_ref.get_mut().await.add_device(device_path.to_string());
+    iface_ref
+        .get()
+        .await
+        .devices_changed(iface_ref.signal_emitter())
+        .await?;
+    match readiness {
+        Some(ready) if !*ready.borrow() => {
+            tokio::spawn(forward_ready(conn.clone(), device_path.to_string(), ready));
+        }
+        _ => emit_device_ready(conn, device_path).await?,
+    }
+    Ok(())
+}
+
+async fn emit_device_ready(conn: &zbus::Connection, device_path: &str) -> Result<()> {
+    let iface_ref = conn
+        .object_server()
+        .interface::<_, manager::RatbagManager>("/org/freedesktop/ratbag1")
+        .await?;
+    manager::RatbagManager::device_ready(
+        iface_ref.signal_emitter(),
+        zbus::zvariant::ObjectPath::try_from(device_path)?,
+    )
+    .await?;
+    Ok(())
+}
+
+/* Announce a device that was asleep when it was plugged in once its
+ * actor has loaded the profiles: `IsReady` changes, then `DeviceReady`
+ * fires.  Returns without a signal if the actor exits first. */
+async fn forward_ready(conn: zbus::Connection, device_path: String, mut ready: watch::Receiver<bool>) {
+    if ready.wait_for(|r| *r).await.is_err() {
+        return;
+    }
+    if let Ok(iface_ref) = conn
+        .object_server()
+        .interface::<_, device::RatbagDevice>(device_path.as_str())
+        .await
+    {
+        let _ = iface_ref
+            .get()
+            .await
+            .is_ready_changed(iface_ref.signal_emitter())
+            .await;
+    }
+    if let Err(e) = emit_device_ready(&conn, &device_path).await {
+        warn!("Failed to announce {device_path} as ready: {e:#}");
+    }
+}
+
+/* Re-announce a device's state each time its actor reports a change made
+ * on the hardware side (a profile or DPI button, another tool): emit
+ * `PropertiesChanged` for `IsActive` on every profile and resolution
+ * object, then the device's `Resync` signal so that clients re-read the
+ * rest.  Returns when the actor exits. */
+async fn forward_resyncs(
+    conn: zbus::Connection,
+    paths: Vec<(String, IfaceKind)>,
+    mut resyncs: watch::Receiver<()>,
+) {
+    while resyncs.changed().await.is_ok() {
+        let object_server = conn.object_server();
+        for (path, kind) in &paths {
+            match kind {
+                IfaceKind::Profile => {
+                    if let Ok(iface_ref) =
+                        object_server.interface::<_, profile::RatbagProfile>(path.as_str()).await
+                    {
+                        let _ = iface_ref
+                            .get()
+                            .await
+                            .is_active_changed(iface_ref.signal_emitter())
+                            .await;
+                    }
+                }
+                IfaceKind::Resolution => {
+                    if let Ok(iface_ref) = object_server
+                        .interface::<_, resolution::RatbagResolution>(path.as_str())
+                        .await
+                    {
+                        let _ = iface_ref
+                            .get()
+                            .await
+                            .is_active_changed(iface_ref.signal_emitter())
+                            .await;
+                    }
+                }
+                _ => {}
+            }
+        }
+        if let Some((device_path, _)) = paths.first()
+            && let Ok(iface_ref) =
+                object_server.interface::<_, device::RatbagDevice>(device_path.as_str()).await
+        {
+            let _ = device::RatbagDevice::resync(iface_ref.signal_emitter()).await;
+        }
+    }
+}
+
 /* Unregister a device and all its children from the D-Bus object server,
  * then remove it from the manager's device list.
  *
@@ -199,17 +329,54 @@ async fn remove_device(
 pub async fn run_server(
     mut device_rx: mpsc::Receiver<DeviceAction>,
     device_db: DeviceDb,
+    audit: Option<AuditLog>,
+    state_cache: Option<StateCache>,
 ) -> Result<()> {
-    let manager = manager::RatbagManager::default();
+    let mut manager = manager::RatbagManager::default();
+    let audit = audit.map(Arc::new);
+    if let Some(audit) = &audit {
+        info!("Recording commits to {}", audit.path().display());
+        manager.set_audit_log_path(audit.path().display().to_string());
+    }
+    let state_cache = state_cache.map(Arc::new);
+    if let Some(cache) = &state_cache {
+        info!("Caching device state in {}", cache.dir().display());
+    }
 
     let conn = Builder::system()?
-        .name("org.freedesktop.ratbag1")?
         .serve_at("/org/freedesktop/ratbag1", manager)?
         .build()
         .await?;
 
+    /* Without DoNotQueue a second daemon would wait silently in the
+     * name's queue instead of failing. */
+    match conn
+        .request_name_with_flags("org.freedesktop.ratbag1", RequestNameFlags::DoNotQueue.into())
+        .await
+    {
+        Ok(_) => {}
+        /* Usually libratbag's C ratbagd, which Piper installs as well. */
+        Err(zbus::Error::NameTaken) => {
+            error!(
+                "org.freedesktop.ratbag1 is already owned by another process, most likely \
+                 libratbag's ratbagd; stop it (e.g. `systemctl stop ratbagd`) and start again. \
+                 `ratbagctl doctor` shows which daemon holds the name."
+            );
+            anyhow::bail!("org.freedesktop.ratbag1 is already owned by another process");
+        }
+        Err(e) => return Err(e.into()),
+    }
+
     info!("DBus server ready on org.freedesktop.ratbag1");
 
+    let (quit_tx, mut quit_rx) = mpsc::channel::<()>(1);
+    conn.object_server()
+        .interface::<_, manager::RatbagManager>("/org/freedesktop/ratbag1")
+        .await?
+        .get_mut()
+        .await
+        .set_quit_tx(quit_tx);
+
     // Under dev-hooks, wire a secondary channel to the manager so that
     // LoadTestDevice / ResetTestDevice can inject synthetic DeviceActions
     // into this same event loop.
@@ -260,11 +427,18 @@ pub async fn run_server(
         let action = tokio::select! {
             a = device_rx.recv() => match a { Some(a) => a, None => break },
             a = test_rx.recv()   => match a { Some(a) => a, None => break },
+            _ = quit_rx.recv()   => {
+                info!("Quit requested, shutting down");
+                break;
+            }
         };
         #[cfg(not(feature = "dev-hooks"))]
-        let action = match device_rx.recv().await {
-            Some(a) => a,
-            None => break,
+        let action = tokio::select! {
+            a = device_rx.recv() => match a { Some(a) => a, None => break },
+            _ = quit_rx.recv()   => {
+                info!("Quit requested, shutting down");
+                break;
+            }
         };
 
         match action {
@@ -277,10 +451,12 @@ pub async fn run_server(
                 pid,
                 phys_path,
                 hid_uniq,
+                interface,
+                usage_pages,
             } => {
                 let db_key = (BusType::from_u16(bustype), vid, pid);
 
-                let entry = match device_db.get(&db_key) {
+                let entry = match device_database::lookup_device(&device_db, &db_key) {
                     Some(e) => e,
                     None => {
                         info!(
@@ -291,6 +467,18 @@ pub async fn run_server(
                     }
                 };
 
+                /* Device files can name the interface the driver speaks
+                 * on; other nodes of the same device would fail to probe,
+                 * or worse, be probed first and block the right one. */
+                if !entry.accepts_interface(interface, &usage_pages) {
+                    info!(
+                        "Skipping {} ({:04x}:{:04x}): interface {:?} with usage pages {:04x?} \
+                         is not the one {} asks for",
+                        sysname, vid, pid, interface, usage_pages, entry.name
+                    );
+                    continue;
+                }
+
                 /* A single physical device (e.g. Logitech G403 HERO) can
                  * expose multiple hidraw nodes — one for the standard HID
                  * mouse interface and one for the vendor-specific HID++
@@ -320,7 +508,7 @@ pub async fn run_server(
                 );
 
                 let device_info =
-                    DeviceInfo::from_entry(&sysname, &name, bustype, vid, pid, entry);
+                    DeviceInfo::from_entry(&sysname, &name, bustype, vid, pid, &hid_uniq, entry);
                 let device_path = format!(
                     "/org/freedesktop/ratbag1/device/{}",
                     sysname.replace('-', "_")
@@ -339,86 +527,76 @@ pub async fn run_server(
                  * failures during USB settle (device not ready yet) and
                  * avoids permanently missing a device that just needed a
                  * moment to initialize. */
-                let actor_handle = match driver::create_driver(&entry.driver) {
-                    Some(drv) => {
+                let state = state_cache.as_ref().map(|cache| {
+                    let key = CacheKey { bustype, vid, pid, sysname: sysname.clone() };
+                    (Arc::clone(cache), key)
+                });
+                let driver_list = entry.drivers.join(", ");
+                let drivers = driver::create_drivers(&entry.drivers, &entry.driver_configs);
+                if drivers.is_empty() {
+                    warn!(
+                        "No driver implementation for '{}', skipping {}",
+                        driver_list, sysname
+                    );
+                    continue;
+                }
+
+                let actor_handle = match actor::spawn_device_actor(
+                    &devnode,
+                    drivers,
+                    Arc::clone(&shared_info),
+                    audit.clone(),
+                    state.clone(),
+                )
+                .await
+                {
+                    Ok(handle) => {
+                        info!("Driver ({}) active for {}", driver_list, sysname);
+                        handle
+                    }
+                    Err(e) => {
+                        info!(
+                            "Driver ({}) probe failed for {} (attempt 1/2): {e:#}, \
+                             retrying after settle delay",
+                            driver_list, sysname
+                        );
+
+                        /* Brief delay for USB settle before retry. */
+                        tokio::time::sleep(Duration::from_millis(500)).await;
+
+                        /* Re-create shared_info for the retry since the
+                         * first attempt may have partially mutated it. */
+                        let retry_info = Arc::new(RwLock::new(DeviceInfo::from_entry(
+                            &sysname, &name, bustype, vid, pid, &hid_uniq, entry,
+                        )));
                         match actor::spawn_device_actor(
                             &devnode,
-                            drv,
-                            Arc::clone(&shared_info),
+                            driver::create_drivers(&entry.drivers, &entry.driver_configs),
+                            Arc::clone(&retry_info),
+                            audit.clone(),
+                            state,
                         )
                         .await
                         {
                             Ok(handle) => {
+                                /* Swap in the fresh info for DBus
+                                 * registration below. */
+                                shared_info = retry_info;
                                 info!(
-                                    "Driver {} active for {}",
-                                    entry.driver, sysname
+                                    "Driver ({}) active for {} (retry succeeded)",
+                                    driver_list, sysname
                                 );
                                 handle
                             }
-                            Err(e) => {
-                                info!(
-                                    "Driver {} probe failed for {} (attempt 1/2): {e:#}, \
-                                     retrying after settle delay",
-                                    entry.driver, sysname
+                            Err(e2) => {
+                                warn!(
+                                    "Driver ({}) probe failed for {} (attempt 2/2): {e2:#}",
+                                    driver_list, sysname
                                 );
-
-                                /* Brief delay for USB settle before retry. */
-                                tokio::time::sleep(Duration::from_millis(500)).await;
-
-                                match driver::create_driver(&entry.driver) {
-                                    Some(drv2) => {
-                                        /* Re-create shared_info for the retry since the
-                                         * first attempt may have partially mutated it. */
-                                        let retry_info = Arc::new(RwLock::new(
-                                            DeviceInfo::from_entry(
-                                                &sysname, &name, bustype, vid, pid, entry,
-                                            ),
-                                        ));
-                                        match actor::spawn_device_actor(
-                                            &devnode,
-                                            drv2,
-                                            Arc::clone(&retry_info),
-                                        )
-                                        .await
-                                        {
-                                            Ok(handle) => {
-                                                /* Swap in the fresh info for DBus
-                                                 * registration below. */
-                                                shared_info = retry_info;
-                                                info!(
-                                                    "Driver {} active for {} (retry succeeded)",
-                                                    entry.driver, sysname
-                                                );
-                                                handle
-                                            }
-                                            Err(e2) => {
-                                                warn!(
-                                                    "Driver {} probe failed for {} (attempt 2/2): \
-                                                     {e2:#}",
-                                                    entry.driver, sysname
-                                                );
-                                                continue;
-                                            }
-                                        }
-                                    }
-                                    None => {
-                                        warn!(
-                                            "No driver implementation for '{}' on retry",
-                                            entry.driver
-                                        );
-                                        continue;
-                                    }
-                                }
+                                continue;
                             }
                         }
                     }
-                    None => {
-                        warn!(
-                            "No driver implementation for '{}', skipping {}",
-                            entry.driver, sysname
-                        );
-                        continue;
-                    }
                 };
 
                 let object_paths = register_device_on_dbus(
@@ -435,26 +613,17 @@ pub async fn run_server(
                  * non-fatal — the device actor is already running and
                  * the D-Bus objects are registered; only the manager's
                  * aggregated Devices list would be stale. */
-                let manager_ok = async {
-                    let object_server = conn.object_server();
-                    let iface_ref = object_server
-                        .interface::<_, manager::RatbagManager>(
-                            "/org/freedesktop/ratbag1",
-                        )
-                        .await?;
-                    iface_ref.get_mut().await.add_device(device_path.clone());
-                    iface_ref
-                        .get()
-                        .await
-                        .devices_changed(iface_ref.signal_emitter())
-                        .await?;
-                    Ok::<(), anyhow::Error>(())
-                }
-                .await;
-                if let Err(e) = manager_ok {
+                if let Err(e) =
+                    announce_device(&conn, &device_path, Some(actor_handle.readiness())).await
+                {
                     warn!("Failed to update manager device list for {}: {e:#}", sysname);
                 }
 
+                tokio::spawn(forward_resyncs(
+                    conn.clone(),
+                    object_paths.clone(),
+                    actor_handle.resyncs(),
+                ));
                 actor_handles.insert(sysname.clone(), actor_handle);
                 registered_devices.insert(sysname.clone(), object_paths);
                 if !phys_path.is_empty() {
@@ -509,23 +678,7 @@ pub async fn run_server(
                 )
                 .await;
 
-                let manager_ok = async {
-                    let object_server = conn.object_server();
-                    let iface_ref = object_server
-                        .interface::<_, manager::RatbagManager>(
-                            "/org/freedesktop/ratbag1",
-                        )
-                        .await?;
-                    iface_ref.get_mut().await.add_device(device_path.clone());
-                    iface_ref
-                        .get()
-                        .await
-                        .devices_changed(iface_ref.signal_emitter())
-                        .await?;
-                    Ok::<(), anyhow::Error>(())
-                }
-                .await;
-                if let Err(e) = manager_ok {
+                if let Err(e) = announce_device(&conn, &device_path, None).await {
                     warn!("Failed to update manager for test device {}: {e:#}", sysname);
                 }
 
@@ -548,6 +701,37 @@ pub async fn run_server(
         }
     }
 
-    info!("udev monitor channel closed, shutting down");
+    shutdown_actors(&mut actor_handles).await;
+    info!("DBus server stopped");
     Ok(())
 }
+
+/* Stop every device actor, aborting any in-flight driver I/O. */
+async fn shutdown_actors(actor_handles: &mut HashMap<String, ActorHandle>) {
+    for (sysname, handle) in actor_handles.drain() {
+        info!("Stopping device actor for {sysname}");
+        handle.shutdown().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::MockDriver;
+
+    #[tokio::test]
+    async fn test_shutdown_actors_stops_every_actor() {
+        let info = Arc::new(RwLock::new(DeviceInfo {
+            sysname: "hidraw-test".to_string(),
+            ..Default::default()
+        }));
+        let (handle, _) = MockDriver::default().spawn(&info).await;
+        assert!(handle.commit().await.is_ok());
+
+        let mut actor_handles = HashMap::from([("hidraw-test".to_string(), handle.clone())]);
+        shutdown_actors(&mut actor_handles).await;
+
+        assert!(actor_handles.is_empty());
+        assert!(handle.commit().await.is_err());
+    }
+}
@@ -1,4 +1,4 @@
-/* DBus Profile interface: exposes per-profile properties (name, rate, angle snapping, debounce,
+/* DBus Profile interface: exposes per-profile properties (name, rate, angle snapping, debounce, LOD,
  * capabilities, resolutions/buttons/leds lists) backed by shared DeviceInfo.
  *
  * Design principles:
@@ -12,8 +12,11 @@ use tokio::sync::RwLock;
 use zbus::interface;
 use zbus::zvariant::ObjectPath;
 
+use crate::actor::{ActorHandle, CommitError};
 use crate::device::{DeviceInfo, ProfileInfo};
 
+use super::device::{failure_paths, RatbagDevice};
+
 /// The `org.freedesktop.ratbag1.Profile` interface.
 ///
 /// Represents one of a device's configurable profiles, containing
@@ -27,6 +30,8 @@ pub struct RatbagProfile {
     device_info: Arc<RwLock<DeviceInfo>>,
     device_path: String,
     profile_id: u32,
+    /* Used by `Switch` for drivers that switch profiles without a commit. */
+    actor: Option<ActorHandle>,
 }
 
 impl RatbagProfile {
@@ -34,11 +39,87 @@ impl RatbagProfile {
         device_info: Arc<RwLock<DeviceInfo>>,
         device_path: String,
         profile_id: u32,
+        actor: Option<ActorHandle>,
     ) -> Self {
         Self {
             device_info,
             device_path,
             profile_id,
+            actor,
+        }
+    }
+
+    /* Make this profile the active one and return the previously active
+     * profile and whether the hardware switched already.  With `direct`,
+     * a driver that can switch on the spot does so and the profile stays
+     * clean; otherwise it is marked dirty so the switch goes out with the
+     * next commit.  Its LEDs are marked too: drivers that keep one
+     * profile on the hardware only write dirty LEDs, and the zones still
+     * show the old profile. */
+    async fn activate(&self, direct: bool) -> zbus::fdo::Result<(Option<u32>, bool)> {
+        let old_active_id;
+        {
+            let mut info = self.device_info.write().await;
+            if info.find_profile(self.profile_id).is_none() {
+                return Err(zbus::fdo::Error::Failed("Profile not found".into()));
+            }
+            old_active_id = info
+                .profiles
+                .iter()
+                .find(|p| p.is_active)
+                .map(|p| p.index);
+            for profile in &mut info.profiles {
+                profile.is_active = profile.index == self.profile_id;
+            }
+        }
+
+        /* The lock is released while the actor talks to the hardware. */
+        let switched = match self.actor.as_ref().filter(|_| direct) {
+            Some(actor) => actor.switch_profile(self.profile_id).await.unwrap_or_else(|e| {
+                tracing::warn!("Direct switch to profile {} failed: {e}", self.profile_id);
+                false
+            }),
+            None => false,
+        };
+        if !switched {
+            let mut info = self.device_info.write().await;
+            if let Some(profile) = info.find_profile_mut(self.profile_id) {
+                profile.is_dirty = true;
+                for led in &mut profile.leds {
+                    led.is_dirty = true;
+                }
+            }
+        }
+        Ok((old_active_id, switched))
+    }
+
+    /* Emit `IsActive` on the previously active profile and `IsActive`
+     * and `IsDirty` on this one.  Each signal goes out through the
+     * emitter of its own object path. */
+    async fn active_changed(&self, server: &zbus::ObjectServer, old_active_id: Option<u32>) {
+        if let Some(old_id) = old_active_id.filter(|&id| id != self.profile_id) {
+            let path = format!("{}/p{}", self.device_path, old_id);
+            if let Ok(iface_ref) = server.interface::<_, RatbagProfile>(path.as_str()).await {
+                let _ = iface_ref
+                    .get()
+                    .await
+                    .is_active_changed(iface_ref.signal_emitter())
+                    .await;
+            }
+        }
+
+        let new_path = format!("{}/p{}", self.device_path, self.profile_id);
+        if let Ok(iface_ref) = server.interface::<_, RatbagProfile>(new_path.as_str()).await {
+            let _ = iface_ref
+                .get()
+                .await
+                .is_active_changed(iface_ref.signal_emitter())
+                .await;
+            let _ = iface_ref
+                .get()
+                .await
+                .is_dirty_changed(iface_ref.signal_emitter())
+                .await;
         }
     }
 }
@@ -58,8 +139,9 @@ impl RatbagProfile {
     /// Profile capabilities (constant).
     ///
     /// Returns the subset of well-known profile capabilities
-    /// (`SET_DEFAULT` = 101, `DISABLE` = 102) that this profile supports,
-    /// matching the C daemon's `ratbagd_profile_get_capabilities`.
+    /// (`SET_DEFAULT` = 101, `DISABLE` = 102, `GLOBAL_REPORT_RATE` = 104)
+    /// that this profile supports, matching the C daemon's
+    /// `ratbagd_profile_get_capabilities` plus the report-rate scope flag.
     #[zbus(property)]
     async fn capabilities(&self) -> Vec<u32> {
         let info = self.device_info.read().await;
@@ -85,13 +167,23 @@ impl RatbagProfile {
     }
 
     #[zbus(property)]
-    async fn set_name(&self, name: String) -> zbus::Result<()> {
-        let mut info = self.device_info.write().await;
-        let profile = info
-            .find_profile_mut(self.profile_id)
-            .ok_or_else(|| zbus::fdo::Error::Failed("Profile not found".into()))?;
-        profile.name = name;
-        profile.is_dirty = true;
+    async fn set_name(
+        &self,
+        #[zbus(signal_emitter)] emitter: zbus::object_server::SignalEmitter<'_>,
+        name: String,
+    ) -> zbus::Result<()> {
+        {
+            let mut info = self.device_info.write().await;
+            let profile = info
+                .find_profile_mut(self.profile_id)
+                .ok_or_else(|| zbus::fdo::Error::Failed("Profile not found".into()))?;
+            profile
+                .validate_name(&name)
+                .map_err(zbus::fdo::Error::InvalidArgs)?;
+            profile.name = name;
+            profile.is_dirty = true;
+        }
+        let _ = self.is_dirty_changed(&emitter).await;
         Ok(())
     }
 
@@ -104,13 +196,20 @@ impl RatbagProfile {
     }
 
     #[zbus(property)]
-    async fn set_disabled(&self, disabled: bool) -> zbus::Result<()> {
-        let mut info = self.device_info.write().await;
-        let profile = info
-            .find_profile_mut(self.profile_id)
-            .ok_or_else(|| zbus::fdo::Error::Failed("Profile not found".into()))?;
-        profile.is_enabled = !disabled;
-        profile.is_dirty = true;
+    async fn set_disabled(
+        &self,
+        #[zbus(signal_emitter)] emitter: zbus::object_server::SignalEmitter<'_>,
+        disabled: bool,
+    ) -> zbus::Result<()> {
+        {
+            let mut info = self.device_info.write().await;
+            let profile = info
+                .find_profile_mut(self.profile_id)
+                .ok_or_else(|| zbus::fdo::Error::Failed("Profile not found".into()))?;
+            profile.is_enabled = !disabled;
+            profile.is_dirty = true;
+        }
+        let _ = self.is_dirty_changed(&emitter).await;
         Ok(())
     }
 
@@ -126,7 +225,11 @@ impl RatbagProfile {
             .is_some_and(|p| p.is_active)
     }
 
-    /// True if this profile has uncommitted changes.
+    /// True if this profile has staged changes that no commit has written
+    /// yet.  `SetActive` or a property set on the profile or one of its
+    /// resolutions, buttons or LEDs marks it; a successful `Device.Commit`
+    /// or `Profile.Commit` of this profile clears it.  A failed commit
+    /// leaves it set, so the changes can be committed again.
     #[zbus(property)]
     async fn is_dirty(&self) -> bool {
         let info = self.device_info.read().await;
@@ -212,17 +315,26 @@ impl RatbagProfile {
     }
 
     #[zbus(property)]
-    async fn set_angle_snapping(&self, value: i32) -> zbus::Result<()> {
-        let mut info = self.device_info.write().await;
-        let profile = info
-            .find_profile_mut(self.profile_id)
-            .ok_or_else(|| zbus::fdo::Error::Failed("Profile not found".into()))?;
-        profile.angle_snapping = value;
-        profile.is_dirty = true;
+    async fn set_angle_snapping(
+        &self,
+        #[zbus(signal_emitter)] emitter: zbus::object_server::SignalEmitter<'_>,
+        value: i32,
+    ) -> zbus::Result<()> {
+        {
+            let mut info = self.device_info.write().await;
+            let profile = info
+                .find_profile_mut(self.profile_id)
+                .ok_or_else(|| zbus::fdo::Error::Failed("Profile not found".into()))?;
+            profile.angle_snapping = value;
+            profile.is_dirty = true;
+        }
+        let _ = self.is_dirty_changed(&emitter).await;
         Ok(())
     }
 
     /// Button debounce time in ms (-1 = unsupported).
+    ///
+    /// Writes must be one of the values listed in `Debounces`.
     #[zbus(property)]
     async fn debounce(&self) -> i32 {
         let info = self.device_info.read().await;
@@ -232,13 +344,22 @@ impl RatbagProfile {
     }
 
     #[zbus(property)]
-    async fn set_debounce(&self, value: i32) -> zbus::Result<()> {
-        let mut info = self.device_info.write().await;
-        let profile = info
-            .find_profile_mut(self.profile_id)
-            .ok_or_else(|| zbus::fdo::Error::Failed("Profile not found".into()))?;
-        profile.debounce = value;
-        profile.is_dirty = true;
+    async fn set_debounce(
+        &self,
+        #[zbus(signal_emitter)] emitter: zbus::object_server::SignalEmitter<'_>,
+        value: i32,
+    ) -> zbus::Result<()> {
+        {
+            let mut info = self.device_info.write().await;
+            let profile = info
+                .find_profile_mut(self.profile_id)
+                .ok_or_else(|| zbus::fdo::Error::Failed("Profile not found".into()))?;
+            ProfileInfo::validate_debounce(value, &profile.debounces)
+                .map_err(zbus::fdo::Error::InvalidArgs)?;
+            profile.debounce = value;
+            profile.is_dirty = true;
+        }
+        let _ = self.is_dirty_changed(&emitter).await;
         Ok(())
     }
 
@@ -251,6 +372,47 @@ impl RatbagProfile {
             .unwrap_or_default()
     }
 
+    /// Lift-off distance level, 1 = closest to the surface (0 = unsupported).
+    ///
+    /// Writes must be one of the values listed in `LiftOffDistances`.
+    #[zbus(property)]
+    async fn lift_off_distance(&self) -> u32 {
+        let info = self.device_info.read().await;
+        info.find_profile(self.profile_id)
+            .map(|p| p.lift_off_distance)
+            .unwrap_or(0)
+    }
+
+    #[zbus(property)]
+    async fn set_lift_off_distance(
+        &self,
+        #[zbus(signal_emitter)] emitter: zbus::object_server::SignalEmitter<'_>,
+        value: u32,
+    ) -> zbus::Result<()> {
+        {
+            let mut info = self.device_info.write().await;
+            let profile = info
+                .find_profile_mut(self.profile_id)
+                .ok_or_else(|| zbus::fdo::Error::Failed("Profile not found".into()))?;
+            ProfileInfo::validate_lift_off_distance(value, &profile.lift_off_distances)
+                .map_err(zbus::fdo::Error::InvalidArgs)?;
+            profile.lift_off_distance = value;
+            profile.is_dirty = true;
+        }
+        let _ = self.is_dirty_changed(&emitter).await;
+        Ok(())
+    }
+
+    /// Permitted lift-off distance levels (constant); empty when the
+    /// device cannot set it.
+    #[zbus(property)]
+    async fn lift_off_distances(&self) -> Vec<u32> {
+        let info = self.device_info.read().await;
+        info.find_profile(self.profile_id)
+            .map(|p| p.lift_off_distances.clone())
+            .unwrap_or_default()
+    }
+
     /// Report rate in Hz.
     #[zbus(property)]
     async fn report_rate(&self) -> u32 {
@@ -262,19 +424,37 @@ impl RatbagProfile {
 
     /// Set report rate in Hz.
     ///
-    /// The value is clamped to [125, 8000] before storage, matching the
-    /// C daemon's sanity check.
+    /// Writes must be one of the values listed in `ReportRates`; a profile
+    /// that lists none has the value clamped to [125, 8000], matching the
+    /// C daemon's sanity check.  On devices advertising the
+    /// `GLOBAL_REPORT_RATE` capability (104) the rate is applied to every
+    /// profile and `ReportRate` changes are emitted on each of them.
     #[zbus(property)]
-    async fn set_report_rate(&self, rate: u32) -> zbus::Result<()> {
-        /* Clamp *before* acquiring the write lock. */
-        let clamped = ProfileInfo::clamp_report_rate(rate);
-
-        let mut info = self.device_info.write().await;
-        let profile = info
-            .find_profile_mut(self.profile_id)
-            .ok_or_else(|| zbus::fdo::Error::Failed("Profile not found".into()))?;
-        profile.report_rate = clamped;
-        profile.is_dirty = true;
+    async fn set_report_rate(
+        &self,
+        #[zbus(object_server)] server: &zbus::ObjectServer,
+        rate: u32,
+    ) -> zbus::Result<()> {
+        let changed = {
+            let mut info = self.device_info.write().await;
+            let profile = info
+                .find_profile(self.profile_id)
+                .ok_or_else(|| zbus::fdo::Error::Failed("Profile not found".into()))?;
+            let rate = ProfileInfo::validate_report_rate(rate, &profile.report_rates)
+                .map_err(zbus::fdo::Error::InvalidArgs)?;
+            info.set_report_rate(self.profile_id, rate)
+                .ok_or_else(|| zbus::fdo::Error::Failed("Profile not found".into()))?
+        };
+
+        /* Lock released — notify every profile whose rate moved. */
+        for id in changed {
+            let path = format!("{}/p{}", self.device_path, id);
+            if let Ok(iface_ref) = server.interface::<_, RatbagProfile>(path.as_str()).await {
+                let iface = iface_ref.get().await;
+                let _ = iface.report_rate_changed(iface_ref.signal_emitter()).await;
+                let _ = iface.is_dirty_changed(iface_ref.signal_emitter()).await;
+            }
+        }
         Ok(())
     }
 
@@ -293,71 +473,187 @@ impl RatbagProfile {
 
     /// Set this profile as the active profile.
     ///
-    /// Deactivates all other profiles on the same device first, then marks
-    /// this profile as active and dirty.  Emits `PropertiesChanged` for
+    /// Deactivates all other profiles on the same device, marks this
+    /// profile as active and dirty, and leaves the write to the next
+    /// `Commit`, like any property set.  Emits `PropertiesChanged` for
     /// `IsActive` on every affected profile so that listening frontends
     /// (e.g. Piper) update immediately without a restart.
     async fn set_active(
         &self,
         #[zbus(object_server)] server: &zbus::ObjectServer,
     ) -> zbus::fdo::Result<()> {
-        let old_active_id;
-        {
-            let mut info = self.device_info.write().await;
-            old_active_id = info
-                .profiles
-                .iter()
-                .find(|p| p.is_active)
-                .map(|p| p.index);
-            for profile in &mut info.profiles {
-                profile.is_active = false;
+        let (old_active_id, _) = self.activate(false).await?;
+        self.active_changed(server, old_active_id).await;
+        tracing::info!("Profile {} set as active", self.profile_id);
+        Ok(())
+    }
+
+    /// Make this profile the active profile on the hardware right away.
+    ///
+    /// Drivers that can switch profiles directly (e.g. ASUS) do so without
+    /// a commit, and the profile stays clean; this is what profile hotkeys
+    /// want.  On all other devices this stages the switch like `SetActive`.
+    /// Returns true when the device switched, false when the switch waits
+    /// for `Commit`.
+    async fn switch(&self, #[zbus(object_server)] server: &zbus::ObjectServer) -> zbus::fdo::Result<bool> {
+        let (old_active_id, switched) = self.activate(true).await?;
+        self.active_changed(server, old_active_id).await;
+        tracing::info!(
+            "Profile {} set as active{}",
+            self.profile_id,
+            if switched { " on the device" } else { "" }
+        );
+        Ok(switched)
+    }
+
+    /// Commit this profile's pending changes only, like `Device.Commit`
+    /// restricted to one profile.
+    ///
+    /// Other profiles stay dirty until a later commit, which saves write
+    /// time and flash wear when only one profile was edited.  Returns 0 and
+    /// an empty list on success; on failure returns 1 and the objects that
+    /// could not be written, as `Device.CommitWithFailures` does, and the
+    /// device emits `Resync`.
+    #[zbus(out_args("status", "failures"))]
+    async fn commit(
+        &self,
+        #[zbus(object_server)] server: &zbus::ObjectServer,
+        #[zbus(signal_emitter)] emitter: zbus::object_server::SignalEmitter<'_>,
+    ) -> (u32, Vec<(ObjectPath<'static>, String)>) {
+        let Some(ref actor) = self.actor else {
+            tracing::warn!(
+                "Commit requested but no driver actor for {}/p{}",
+                self.device_path,
+                self.profile_id
+            );
+            let error = CommitError::from("No driver for this device".to_string());
+            return (1, failure_paths(&self.device_path, &error));
+        };
+
+        match actor.commit_profile(self.profile_id).await {
+            Ok(()) => {
+                tracing::info!("Commit succeeded for {}/p{}", self.device_path, self.profile_id);
+                let _ = self.is_dirty_changed(&emitter).await;
+                (0, Vec::new())
             }
-            let profile = info
-                .find_profile_mut(self.profile_id)
-                .ok_or_else(|| zbus::fdo::Error::Failed("Profile not found".into()))?;
-            profile.is_active = true;
-            profile.is_dirty = true;
-        }
-        /* Lock released — now emit PropertiesChanged signals.         */
-        /*                                                              */
-        /* We notify the previously-active profile (IsActive → false)   */
-        /* and the newly-active profile (IsActive → true, IsDirty →     */
-        /* true).  Each signal must be emitted through the              */
-        /* SignalEmitter of the *correct* object path, obtained from    */
-        /* the ObjectServer's InterfaceRef for that path.               */
-
-        if let Some(old_id) = old_active_id {
-            if old_id != self.profile_id {
-                let path = format!("{}/p{}", self.device_path, old_id);
-                if let Ok(iface_ref) =
-                    server.interface::<_, RatbagProfile>(path.as_str()).await
+            Err(e) => {
+                tracing::error!(
+                    "Commit failed for {}/p{}: {e}",
+                    self.device_path,
+                    self.profile_id
+                );
+                if let Ok(iface_ref) = server
+                    .interface::<_, RatbagDevice>(self.device_path.as_str())
+                    .await
                 {
-                    let _ = iface_ref
-                        .get()
-                        .await
-                        .is_active_changed(iface_ref.signal_emitter())
-                        .await;
+                    let _ = RatbagDevice::resync(iface_ref.signal_emitter()).await;
                 }
+                (1, failure_paths(&self.device_path, &e))
             }
         }
+    }
+}
 
-        let new_path = format!("{}/p{}", self.device_path, self.profile_id);
-        if let Ok(iface_ref) =
-            server.interface::<_, RatbagProfile>(new_path.as_str()).await
-        {
-            let _ = iface_ref
-                .get()
-                .await
-                .is_active_changed(iface_ref.signal_emitter())
-                .await;
-            let _ = iface_ref
-                .get()
-                .await
-                .is_dirty_changed(iface_ref.signal_emitter())
-                .await;
-        }
+/* Emit `IsDirty` on the profile owning the resolution, button or LED
+ * object at `child_path`.  Their setters mark the profile dirty, but the
+ * flag is a property of the profile object. */
+pub(crate) async fn profile_is_dirty_changed(server: &zbus::ObjectServer, child_path: &str) {
+    let Some((profile_path, _)) = child_path.rsplit_once('/') else {
+        return;
+    };
+    if let Ok(iface_ref) = server.interface::<_, RatbagProfile>(profile_path).await {
+        let _ = iface_ref
+            .get()
+            .await
+            .is_dirty_changed(iface_ref.signal_emitter())
+            .await;
+    }
+}
 
-        tracing::info!("Profile {} set as active", self.profile_id);
-        Ok(())
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::MockDriver;
+
+    async fn activate_profile_1(can_switch: bool, direct: bool) -> (DeviceInfo, usize, usize) {
+        let info = Arc::new(RwLock::new(DeviceInfo {
+            sysname: "hidraw-test".to_string(),
+            profiles: (0..2)
+                .map(|index| ProfileInfo {
+                    index,
+                    is_active: index == 0,
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        }));
+        let driver = MockDriver {
+            can_switch,
+            ..Default::default()
+        };
+        let (actor, state) = driver.spawn(&info).await;
+
+        let profile = RatbagProfile::new(Arc::clone(&info), "/dev".into(), 1, Some(actor));
+        let (old_active_id, switched) = profile.activate(direct).await.unwrap();
+        assert_eq!(old_active_id, Some(0));
+        assert_eq!(switched, can_switch && direct);
+
+        let snapshot = info.read().await.clone();
+        (snapshot, state.switches(), state.commits())
+    }
+
+    #[tokio::test]
+    async fn test_profile_commit_leaves_other_profiles_pending() {
+        let info = Arc::new(RwLock::new(DeviceInfo {
+            sysname: "hidraw-test".to_string(),
+            profiles: (0..3)
+                .map(|index| ProfileInfo {
+                    index,
+                    is_active: index == 0,
+                    is_dirty: index != 2,
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        }));
+        let (actor, state) = MockDriver::default().spawn(&info).await;
+
+        actor.commit_profile(1).await.unwrap();
+        assert_eq!(*state.dirty_seen.lock().unwrap(), [vec![1]]);
+        let dirty: Vec<bool> = info.read().await.profiles.iter().map(|p| p.is_dirty).collect();
+        assert_eq!(dirty, [true, false, false]);
+
+        /* A full commit then writes what is left. */
+        actor.commit().await.unwrap();
+        assert_eq!(state.dirty_seen.lock().unwrap()[1], [0]);
+        assert!(info.read().await.profiles.iter().all(|p| !p.is_dirty));
+    }
+
+    #[tokio::test]
+    async fn test_direct_switch_skips_commit() {
+        let (info, switches, commits) = activate_profile_1(true, true).await;
+        assert_eq!(switches, 1);
+        assert_eq!(commits, 0);
+        assert!(info.profiles[1].is_active && !info.profiles[0].is_active);
+        /* Nothing left for a commit to write. */
+        assert!(info.profiles.iter().all(|p| !p.is_dirty));
+    }
+
+    #[tokio::test]
+    async fn test_switch_without_driver_support_waits_for_commit() {
+        let (info, switches, commits) = activate_profile_1(false, true).await;
+        assert_eq!((switches, commits), (0, 0));
+        assert!(info.profiles[1].is_active);
+        assert!(info.profiles[1].is_dirty);
+    }
+
+    /* `SetActive` is a staged change like any other: even a driver that
+     * could switch directly is left alone until `Commit`. */
+    #[tokio::test]
+    async fn test_set_active_never_writes() {
+        let (info, switches, commits) = activate_profile_1(true, false).await;
+        assert_eq!((switches, commits), (0, 0));
+        assert!(info.profiles[1].is_active);
+        assert!(info.profiles[1].is_dirty);
     }
 }
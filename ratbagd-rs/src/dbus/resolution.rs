@@ -1,5 +1,5 @@
 /* DBus Resolution interface: per-resolution object for DPI values, capabilities, active/default
- * flags; mutates DeviceInfo and optionally triggers hardware commit via actor. */
+ * flags; mutates DeviceInfo only, leaving the hardware write to the device's Commit. */
 use std::sync::Arc;
 
 use tokio::sync::RwLock;
@@ -9,6 +9,7 @@ use zbus::zvariant::{OwnedValue, Value};
 use crate::device::{DeviceInfo, Dpi, RATBAG_RESOLUTION_CAP_SEPARATE_XY_RESOLUTION};
 
 use super::fallback_owned_value;
+use super::profile::profile_is_dirty_changed;
 
 /// The `org.freedesktop.ratbag1.Resolution` interface.
 ///
@@ -80,6 +81,45 @@ impl RatbagResolution {
 
         None
     }
+
+    /* Store a DPI written as `Resolution` and mark the profile dirty. */
+    async fn store_dpi(&self, value: OwnedValue) -> zbus::Result<()> {
+        /* Parse the incoming value before taking the write lock to minimize hold time.
+         * Piper and other clients may send the DPI as a plain u32, a (u32, u32)
+         * tuple, or wrapped in an extra variant layer (when the property type is `v`). */
+        let inner: Value<'_> = value.into();
+        let new_dpi = Self::parse_dpi_value(&inner).ok_or_else(|| {
+            zbus::fdo::Error::InvalidArgs(format!(
+                "Invalid resolution value: {inner:?}"
+            ))
+        })?;
+
+        let mut info = self.device_info.write().await;
+        let profile = info.find_profile_mut(self.profile_id).ok_or_else(|| {
+            zbus::fdo::Error::Failed(format!(
+                "Profile {} not found", self.profile_id
+            ))
+        })?;
+        let res = profile.find_resolution_mut(self.resolution_id).ok_or_else(|| {
+            zbus::fdo::Error::Failed(format!(
+                "Resolution {} not found in profile {}",
+                self.resolution_id, self.profile_id
+            ))
+        })?;
+
+        /* Reject (x, y) tuples when the device lacks the SEPARATE_XY capability. */
+        if matches!(new_dpi, Dpi::Separate { .. })
+            && !res.capabilities.contains(&RATBAG_RESOLUTION_CAP_SEPARATE_XY_RESOLUTION)
+        {
+            return Err(zbus::fdo::Error::InvalidArgs(
+                "Device does not support separate X/Y resolution".to_string(),
+            ).into());
+        }
+
+        res.dpi = new_dpi;
+        profile.is_dirty = true;
+        Ok(())
+    }
 }
 
 #[interface(name = "org.freedesktop.ratbag1.Resolution")]
@@ -118,7 +158,8 @@ impl RatbagResolution {
             .is_some_and(|r| r.is_default)
     }
 
-    /// Whether this resolution is disabled (read-write).
+    /// Whether this resolution is disabled (read-write).  Disabling the
+    /// active resolution fails with `InvalidArgs`.
     #[zbus(property)]
     async fn is_disabled(&self) -> bool {
         let info = self.device_info.read().await;
@@ -130,6 +171,7 @@ impl RatbagResolution {
     #[zbus(property)]
     async fn set_is_disabled(
         &self,
+        #[zbus(object_server)] server: &zbus::ObjectServer,
         #[zbus(signal_emitter)] emitter: zbus::object_server::SignalEmitter<'_>,
         disabled: bool,
     ) -> zbus::Result<()> {
@@ -140,16 +182,12 @@ impl RatbagResolution {
                     "Profile {} not found", self.profile_id
                 ))
             })?;
-            let res = profile.find_resolution_mut(self.resolution_id).ok_or_else(|| {
-                zbus::fdo::Error::Failed(format!(
-                    "Resolution {} not found in profile {}",
-                    self.resolution_id, self.profile_id
-                ))
-            })?;
-            res.is_disabled = disabled;
-            profile.is_dirty = true;
+            profile
+                .set_resolution_disabled(self.resolution_id, disabled)
+                .map_err(zbus::fdo::Error::InvalidArgs)?;
         }
         let _ = self.is_disabled_changed(&emitter).await;
+        profile_is_dirty_changed(server, emitter.path()).await;
         Ok(())
     }
 
@@ -175,41 +213,14 @@ impl RatbagResolution {
     }
 
     #[zbus(property)]
-    async fn set_resolution(&self, value: OwnedValue) -> zbus::Result<()> {
-        /* Parse the incoming value before taking the write lock to minimize hold time.
-         * Piper and other clients may send the DPI as a plain u32, a (u32, u32)
-         * tuple, or wrapped in an extra variant layer (when the property type is `v`). */
-        let inner: Value<'_> = value.into();
-        let new_dpi = Self::parse_dpi_value(&inner).ok_or_else(|| {
-            zbus::fdo::Error::InvalidArgs(format!(
-                "Invalid resolution value: {inner:?}"
-            ))
-        })?;
-
-        let mut info = self.device_info.write().await;
-        let profile = info.find_profile_mut(self.profile_id).ok_or_else(|| {
-            zbus::fdo::Error::Failed(format!(
-                "Profile {} not found", self.profile_id
-            ))
-        })?;
-        let res = profile.find_resolution_mut(self.resolution_id).ok_or_else(|| {
-            zbus::fdo::Error::Failed(format!(
-                "Resolution {} not found in profile {}",
-                self.resolution_id, self.profile_id
-            ))
-        })?;
-
-        /* Reject (x, y) tuples when the device lacks the SEPARATE_XY capability. */
-        if matches!(new_dpi, Dpi::Separate { .. })
-            && !res.capabilities.contains(&RATBAG_RESOLUTION_CAP_SEPARATE_XY_RESOLUTION)
-        {
-            return Err(zbus::fdo::Error::InvalidArgs(
-                "Device does not support separate X/Y resolution".to_string(),
-            ).into());
-        }
-
-        res.dpi = new_dpi;
-        profile.is_dirty = true;
+    async fn set_resolution(
+        &self,
+        #[zbus(object_server)] server: &zbus::ObjectServer,
+        #[zbus(signal_emitter)] emitter: zbus::object_server::SignalEmitter<'_>,
+        value: OwnedValue,
+    ) -> zbus::Result<()> {
+        self.store_dpi(value).await?;
+        profile_is_dirty_changed(server, emitter.path()).await;
         Ok(())
     }
 
@@ -232,6 +243,7 @@ impl RatbagResolution {
     async fn set_active(
         &self,
         #[zbus(object_server)] server: &zbus::ObjectServer,
+        #[zbus(signal_emitter)] emitter: zbus::object_server::SignalEmitter<'_>,
     ) -> zbus::fdo::Result<u32> {
         let sibling_count;
         {
@@ -269,6 +281,7 @@ impl RatbagResolution {
                     .await;
             }
         }
+        profile_is_dirty_changed(server, emitter.path()).await;
 
         tracing::info!(
             "Resolution {} in profile {} set as active",
@@ -287,6 +300,7 @@ impl RatbagResolution {
     async fn set_default(
         &self,
         #[zbus(object_server)] server: &zbus::ObjectServer,
+        #[zbus(signal_emitter)] emitter: zbus::object_server::SignalEmitter<'_>,
     ) -> zbus::fdo::Result<u32> {
         let sibling_count;
         {
@@ -324,6 +338,7 @@ impl RatbagResolution {
                     .await;
             }
         }
+        profile_is_dirty_changed(server, emitter.path()).await;
 
         tracing::info!(
             "Resolution {} in profile {} set as default",
@@ -333,3 +348,64 @@ impl RatbagResolution {
         Ok(0)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::{ProfileInfo, ResolutionInfo};
+    use crate::test_support::MockDriver;
+
+    #[test]
+    fn test_parse_dpi_value_accepts_scalar_and_pair() {
+        let parse = |v: Value<'_>| RatbagResolution::parse_dpi_value(&v);
+        assert_eq!(parse(Value::from(1600u32)), Some(Dpi::Unified(1600)));
+        assert_eq!(
+            parse(Value::from((1600u32, 800u32))),
+            Some(Dpi::Separate { x: 1600, y: 800 })
+        );
+        /* Properties.Set wraps the value in one more variant. */
+        assert_eq!(
+            parse(Value::Value(Box::new(Value::from((1600u32, 800u32))))),
+            Some(Dpi::Separate { x: 1600, y: 800 })
+        );
+        assert_eq!(parse(Value::from("1600")), None);
+    }
+
+    async fn dpi_of(resolution: &RatbagResolution) -> u32 {
+        u32::try_from(resolution.resolution().await).unwrap()
+    }
+
+    /* Objects are registered once per device; every read must go through the
+     * shared DeviceInfo rather than a copy taken at registration. */
+    #[tokio::test]
+    async fn test_reads_reflect_state_after_commit() {
+        let info = Arc::new(RwLock::new(DeviceInfo {
+            sysname: "hidraw-test".to_string(),
+            profiles: vec![ProfileInfo {
+                resolutions: vec![ResolutionInfo {
+                    dpi: Dpi::Unified(800),
+                    is_active: true,
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }],
+            ..Default::default()
+        }));
+        let (actor, _) = MockDriver::default().spawn(&info).await;
+        let resolution = RatbagResolution::new(Arc::clone(&info), "/dev".into(), 0, 0);
+
+        resolution
+            .store_dpi(OwnedValue::from(1600_u32))
+            .await
+            .unwrap();
+        assert!(info.read().await.profiles[0].is_dirty);
+
+        actor.commit().await.unwrap();
+        assert_eq!(dpi_of(&resolution).await, 1600);
+        assert!(!info.read().await.profiles[0].is_dirty);
+
+        /* A driver-side update, e.g. an onboard DPI button event. */
+        info.write().await.profiles[0].resolutions[0].dpi = Dpi::Unified(3200);
+        assert_eq!(dpi_of(&resolution).await, 3200);
+    }
+}
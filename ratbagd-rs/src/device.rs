@@ -1,7 +1,11 @@
 /* Canonical device state shared across DBus objects and drivers: device/profile/resolution/button
  * and LED structures plus enums for actions, DPI, and LED modes. */
+use std::collections::BTreeSet;
+
+use serde::{Deserialize, Serialize};
+
 /// Button action types exposed over DBus.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
 #[repr(u32)]
 pub enum ActionType {
     #[default]
@@ -28,6 +32,38 @@ impl ActionType {
     }
 }
 
+/// How often a macro is played per button press.
+///
+/// Exposed over DBus as a `u32`: `0` repeats while the button is held,
+/// `N >= 1` plays the macro `N` times.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum MacroRepeat {
+    #[default]
+    Once,
+    Times(u32),
+    WhileHeld,
+}
+
+impl MacroRepeat {
+    /// Convert the DBus `u32` encoding into a `MacroRepeat`.
+    pub fn from_u32(val: u32) -> Self {
+        match val {
+            0 => Self::WhileHeld,
+            1 => Self::Once,
+            n => Self::Times(n),
+        }
+    }
+
+    /// Convert back to the DBus `u32` encoding.
+    pub fn to_u32(self) -> u32 {
+        match self {
+            Self::WhileHeld => 0,
+            Self::Once => 1,
+            Self::Times(n) => n,
+        }
+    }
+}
+
 /* Canonical special-action values exposed over the DBus `mapping` property
  * when `ActionType::Special` is active.  These mirror the C libratbag enum
  * `ratbag_button_action_special` (starting at 1 << 30 = 0x4000_0000) so
@@ -67,7 +103,7 @@ pub struct RgbColor {
 }
 
 /* Color as an RGB triplet exposed over DBus (u32 fields for compatibility). */
-#[derive(Debug, Clone, Copy, Default)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Color {
     pub red: u32,
     pub green: u32,
@@ -99,9 +135,10 @@ impl Color {
  * (Off=0, On=1, Cycle=2, Breathing=3) so that existing clients like
  * Piper work without translation.  Values 4+ are Rust-only extensions
  * for hardware modes not present in the C codebase. */
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
 #[repr(u32)]
 pub enum LedMode {
+    #[default]
     Off = 0,
     Solid = 1,
     Cycle = 2,
@@ -127,8 +164,29 @@ impl LedMode {
     }
 }
 
+/// Travel direction of moving LED effects such as ColorWave, exposed over
+/// DBus as `Led.EffectDirection`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[repr(u32)]
+pub enum LedDirection {
+    #[default]
+    Clockwise = 0,
+    CounterClockwise = 1,
+}
+
+impl LedDirection {
+    /* Convert a raw DBus u32 value into a LedDirection. */
+    pub fn from_u32(val: u32) -> Option<LedDirection> {
+        match val {
+            0 => Some(LedDirection::Clockwise),
+            1 => Some(LedDirection::CounterClockwise),
+            _ => None,
+        }
+    }
+}
+
 /* Resolution value, either unified or per-axis. */
-#[derive(Debug, Clone, Copy, Default)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Dpi {
     #[default]
     Unknown,
@@ -139,16 +197,87 @@ pub enum Dpi {
     },
 }
 
+/// Device classification exposed over DBus as `Device.DeviceType`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[repr(u32)]
+pub enum DeviceType {
+    #[default]
+    Unspecified = 0,
+    Other = 1,
+    Mouse = 2,
+    Keyboard = 3,
+}
+
+impl DeviceType {
+    /// Parse the `DeviceType=` value of a `.device` file (case-insensitive).
+    /// Unrecognized strings map to [`DeviceType::Unspecified`].
+    pub fn from_db_str(s: &str) -> Self {
+        match s.trim().to_lowercase().as_str() {
+            "mouse" => Self::Mouse,
+            "keyboard" => Self::Keyboard,
+            "other" => Self::Other,
+            _ => Self::Unspecified,
+        }
+    }
+}
+
+/* Auto-sleep timeout: minutes of inactivity before the device sleeps,
+ * with the range the firmware accepts. */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SleepTimeout {
+    pub minutes: u32,
+    pub min: u32,
+    pub max: u32,
+}
+
+impl SleepTimeout {
+    /// Check `minutes` against the range the firmware accepts.
+    pub fn validate(&self, minutes: u32) -> Result<(), String> {
+        if (self.min..=self.max).contains(&minutes) {
+            Ok(())
+        } else {
+            Err(format!(
+                "Sleep timeout {minutes} is out of range (supported: {}-{} minutes)",
+                self.min, self.max
+            ))
+        }
+    }
+}
+
+/* Battery state as reported by the device. */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BatteryInfo {
+    /* Charge level in percent, 0..=100. */
+    pub level: u8,
+    pub charging: bool,
+}
+
 /* Device state synced from hardware. */
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct DeviceInfo {
     pub sysname: String,
     pub name: String,
     pub model: String,
+    /* HID_UNIQ serial number; empty when the device does not report one. */
+    pub serial: String,
     pub firmware_version: String,
-    /* Device type exposed over DBus: 0=unspecified, 1=other, 2=mouse, 3=keyboard */
-    pub device_type: u32,
+    /* Classification from the database entry; "mouse" when unspecified. */
+    pub device_type: DeviceType,
+    /* Physical position hint per button index (e.g. "side-front"); empty
+     * when the database has no `ButtonLayout`. */
+    pub button_layout: Vec<String>,
+    /* SmartShift ratchet release threshold (1..=255); `None` when the
+     * device has no SmartShift feature.  Device-wide, written on commit. */
+    pub smart_shift_threshold: Option<u8>,
+    /* Auto-sleep timeout; `None` when the device has no such setting.
+     * Device-wide, written on commit. */
+    pub sleep_timeout: Option<SleepTimeout>,
+    /* A device-wide setting was changed and not committed yet.  Profile
+     * changes are tracked on the profile itself. */
+    #[serde(skip)]
+    pub is_dirty: bool,
     pub profiles: Vec<ProfileInfo>,
+    #[serde(skip)]
     pub driver_config: crate::device_database::DriverConfig,
 }
 
@@ -160,6 +289,7 @@ impl DeviceInfo {
         bustype: u16,
         vid: u16,
         pid: u16,
+        serial: &str,
         entry: &crate::device_database::DeviceEntry,
     ) -> Self {
         let model = format!(
@@ -211,6 +341,8 @@ impl DeviceInfo {
                 angle_snapping: -1,
                 debounce: -1,
                 debounces: Vec::new(),
+                lift_off_distance: 0,
+                lift_off_distances: Vec::new(),
                 capabilities: Vec::new(),
                 resolutions: (0..num_dpis as u32)
                     .map(|ri| ResolutionInfo {
@@ -227,9 +359,12 @@ impl DeviceInfo {
                     .map(|bi| ButtonInfo {
                         index: bi,
                         action_type: ActionType::Button,
-                        action_types: vec![0, 1, 2, 3, 4],
+                        /* Filled from `DeviceDriver::action_types` once the driver has loaded. */
+                        action_types: Vec::new(),
                         mapping_value: bi,
                         macro_entries: Vec::new(),
+                        macro_repeat: MacroRepeat::Once,
+                        default_mapping: None,
                     })
                     .collect(),
                 leds: (0..num_leds as u32)
@@ -250,26 +385,30 @@ impl DeviceInfo {
                         tertiary_color: Color::default(),
                         color_depth: 1,
                         effect_duration: 0,
+                        effect_direction: LedDirection::Clockwise,
+                        has_direction: false,
                         brightness: 255,
+                        is_dirty: false,
+                        unmanaged: false,
                     })
                     .collect(),
             })
             .collect();
 
-        /* Map the .device file's DeviceType string to the DBus integer enum. */
-        let device_type = match entry.device_type.to_lowercase().as_str() {
-            "mouse" => 2,
-            "keyboard" => 3,
-            "other" => 1,
-            _ => 0, /* unspecified */
-        };
+        /* Map the .device file's DeviceType string to the DBus enum. */
+        let device_type = DeviceType::from_db_str(&entry.device_type);
 
         Self {
             sysname: sysname.to_string(),
             name: name.to_string(),
             model,
+            serial: serial.to_string(),
             firmware_version: String::new(),
             device_type,
+            button_layout: entry.button_layout.clone(),
+            smart_shift_threshold: None,
+            sleep_timeout: None,
+            is_dirty: false,
             profiles,
             driver_config: entry.driver_config.clone().unwrap_or_default(),
         }
@@ -277,6 +416,88 @@ impl DeviceInfo {
 }
 
 impl DeviceInfo {
+    /* Give every button that has no `action_types` yet the driver's list. */
+    pub fn fill_default_action_types(&mut self, types: &[ActionType]) {
+        for button in self.profiles.iter_mut().flat_map(|p| p.buttons.iter_mut()) {
+            if button.action_types.is_empty() {
+                button.action_types = types.iter().map(|t| *t as u32).collect();
+            }
+        }
+    }
+
+    /* Mark the LEDs listed in `UnmanagedLeds=` as unmanaged in every
+     * profile; drivers rebuild their LEDs while loading, so this runs
+     * afterwards. */
+    pub fn mark_unmanaged_leds(&mut self) {
+        let unmanaged = &self.driver_config.unmanaged_leds;
+        for led in self.profiles.iter_mut().flat_map(|p| p.leds.iter_mut()) {
+            led.unmanaged = unmanaged.contains(&led.index);
+        }
+    }
+
+    /* Record each button's factory mapping as reported by the driver. */
+    pub fn fill_default_mappings(&mut self, default: impl Fn(u32) -> Option<(ActionType, u32)>) {
+        for button in self.profiles.iter_mut().flat_map(|p| p.buttons.iter_mut()) {
+            button.default_mapping = default(button.index);
+        }
+    }
+
+    /* (profile, button) pairs of macro buttons asking for a repeat mode
+     * other than a single play. */
+    pub fn repeated_macros(&self) -> Vec<(u32, u32)> {
+        self.profiles
+            .iter()
+            .flat_map(|p| p.buttons.iter().map(move |b| (p.index, b)))
+            .filter(|(_, b)| {
+                b.action_type == ActionType::Macro && b.macro_repeat != MacroRepeat::Once
+            })
+            .map(|(p, b)| (p, b.index))
+            .collect()
+    }
+
+    /// Check every per-axis resolution with `ResolutionInfo::validate_dpi`
+    /// before a commit; the error names the profile and resolution.
+    pub fn validate_resolutions(&self) -> Result<(), String> {
+        for profile in &self.profiles {
+            for res in &profile.resolutions {
+                res.validate_dpi().map_err(|e| {
+                    format!("Profile {} resolution {}: {e}", profile.index, res.index)
+                })?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Key that identifies this device across reboots and hotplugs:
+    /// `bustype:vid:pid`, plus `:serial` when the device reports one.
+    /// Unlike the sysname it does not depend on enumeration order.
+    pub fn stable_key(&self) -> String {
+        /* `model` is "bustype:vid:pid:version"; the version is dropped. */
+        let base = self
+            .model
+            .rsplit_once(':')
+            .map_or(self.model.as_str(), |(base, _)| base);
+        if self.serial.is_empty() {
+            base.to_string()
+        } else {
+            format!("{}:{}", base, self.serial)
+        }
+    }
+
+    /// Bus type, vendor ID and product ID, parsed back out of `model`.
+    /// An empty bus and zero IDs when `model` is not of the form
+    /// `bustype:vid:pid:version`.
+    pub fn bus_ids(&self) -> (&str, u16, u16) {
+        let mut parts = self.model.split(':');
+        let ids = (parts.next(), parts.next(), parts.next());
+        if let (Some(bus), Some(vid), Some(pid)) = ids
+            && let (Ok(vid), Ok(pid)) = (u16::from_str_radix(vid, 16), u16::from_str_radix(pid, 16))
+        {
+            return (bus, vid, pid);
+        }
+        ("", 0, 0)
+    }
+
     /// Find a profile by its `index` field.
     pub fn find_profile(&self, id: u32) -> Option<&ProfileInfo> {
         self.profiles.iter().find(|p| p.index == id)
@@ -286,12 +507,111 @@ impl DeviceInfo {
     pub fn find_profile_mut(&mut self, id: u32) -> Option<&mut ProfileInfo> {
         self.profiles.iter_mut().find(|p| p.index == id)
     }
+
+    /// Set the report rate of profile `id` and mark it dirty.
+    ///
+    /// When the profile carries `GLOBAL_REPORT_RATE` the hardware has a
+    /// single device-wide rate, so every profile is updated to keep them
+    /// consistent.  Returns the indices of the profiles that changed.
+    pub fn set_report_rate(&mut self, id: u32, rate: u32) -> Option<Vec<u32>> {
+        let global = self
+            .find_profile(id)?
+            .capabilities
+            .contains(&RATBAG_PROFILE_CAP_GLOBAL_REPORT_RATE);
+        let mut changed = Vec::new();
+        for profile in &mut self.profiles {
+            if profile.index == id || global {
+                profile.report_rate = rate;
+                profile.is_dirty = true;
+                changed.push(profile.index);
+            }
+        }
+        Some(changed)
+    }
+
+    /// The settings that differ between `self` and `before`, usually the
+    /// state last read from or written to the device.
+    ///
+    /// Only what a commit writes is compared: dirty flags, supported
+    /// values and capabilities are not.  A profile or item that `before`
+    /// lacks counts as changed.
+    pub fn diff(&self, before: &DeviceInfo) -> DiffSet {
+        let mut diff = DiffSet {
+            device: self.smart_shift_threshold != before.smart_shift_threshold
+                || self.sleep_timeout != before.sleep_timeout,
+            ..Default::default()
+        };
+        for profile in &self.profiles {
+            let p = profile.index;
+            let old = before.find_profile(p);
+            if old.is_none_or(|old| !profile.changed_settings(old).is_empty()) {
+                diff.profiles.insert(p);
+            }
+            for res in &profile.resolutions {
+                if old.and_then(|o| o.find_resolution(res.index)).is_none_or(|o| res.differs_from(o)) {
+                    diff.resolutions.insert((p, res.index));
+                }
+            }
+            for button in &profile.buttons {
+                if old.and_then(|o| o.find_button(button.index)).is_none_or(|o| button.differs_from(o)) {
+                    diff.buttons.insert((p, button.index));
+                }
+            }
+            for led in &profile.leds {
+                if old.and_then(|o| o.find_led(led.index)).is_none_or(|o| led.differs_from(o)) {
+                    diff.leds.insert((p, led.index));
+                }
+            }
+        }
+        diff
+    }
+}
+
+/// What [`DeviceInfo::diff`] found changed, by object.  Resolutions,
+/// buttons and LEDs are keyed by `(profile, index)`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DiffSet {
+    /// A device-wide setting (SmartShift, sleep timeout) differs.
+    pub device: bool,
+    /// Profiles whose own settings, such as the report rate, differ.
+    pub profiles: BTreeSet<u32>,
+    pub resolutions: BTreeSet<(u32, u32)>,
+    pub buttons: BTreeSet<(u32, u32)>,
+    pub leds: BTreeSet<(u32, u32)>,
+}
+
+impl DiffSet {
+    /// Whether nothing differs.
+    pub fn is_empty(&self) -> bool {
+        !self.device && !self.touches_any_profile()
+    }
+
+    /// Whether profile `index` or any of its items differs.
+    pub fn touches_profile(&self, index: u32) -> bool {
+        self.profiles.contains(&index)
+            || [&self.resolutions, &self.buttons, &self.leds]
+                .iter()
+                .any(|items| items.iter().any(|&(p, _)| p == index))
+    }
+
+    fn touches_any_profile(&self) -> bool {
+        !self.profiles.is_empty()
+            || !self.resolutions.is_empty()
+            || !self.buttons.is_empty()
+            || !self.leds.is_empty()
+    }
 }
 
 /* Profile capability constants matching libratbag's `ratbag_profile_capability` enum.
- * Only SET_DEFAULT and DISABLE are exposed over DBus (matching the C daemon). */
+ * Only SET_DEFAULT and DISABLE are exposed over DBus (matching the C daemon), plus
+ * two without a C equivalent: GLOBAL_REPORT_RATE marks devices whose report
+ * rate is device-wide rather than stored per profile, and ASCII_NAME marks
+ * devices that can only store ASCII profile names. It skips 103, which is
+ * WRITE_ONLY in libratbag. */
 pub const RATBAG_PROFILE_CAP_SET_DEFAULT: u32 = 101;
 pub const RATBAG_PROFILE_CAP_DISABLE: u32 = 102;
+pub const RATBAG_PROFILE_CAP_GLOBAL_REPORT_RATE: u32 = 104;
+pub const RATBAG_PROFILE_CAP_ASCII_NAME: u32 = 105;
 
 /* Resolution capability constants matching libratbag's `ratbag_resolution_capability` enum.
  * SEPARATE_XY gates whether a (u32,u32) DPI tuple is accepted over DBus;
@@ -305,7 +625,7 @@ pub const REPORT_RATE_MIN: u32 = 125;
 pub const REPORT_RATE_MAX: u32 = 8000;
 
 /// Profile state.
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct ProfileInfo {
     pub index: u32,
     pub name: String,
@@ -317,6 +637,10 @@ pub struct ProfileInfo {
     pub angle_snapping: i32,
     pub debounce: i32,
     pub debounces: Vec<u32>,
+    /* Lift-off distance as a driver-defined level, 1 = closest to the
+     * surface; 0 when the device cannot set it. */
+    pub lift_off_distance: u32,
+    pub lift_off_distances: Vec<u32>,
     pub capabilities: Vec<u32>,
     pub resolutions: Vec<ResolutionInfo>,
     pub buttons: Vec<ButtonInfo>,
@@ -324,6 +648,24 @@ pub struct ProfileInfo {
 }
 
 impl ProfileInfo {
+    /// Names of the profile's own settings that differ from `before`,
+    /// e.g. "report_rate"; its resolutions, buttons and LEDs are not
+    /// included.
+    pub fn changed_settings(&self, before: &ProfileInfo) -> Vec<&'static str> {
+        [
+            ("name", self.name != before.name),
+            ("enabled", self.is_enabled != before.is_enabled),
+            ("active", self.is_active != before.is_active),
+            ("report_rate", self.report_rate != before.report_rate),
+            ("angle_snapping", self.angle_snapping != before.angle_snapping),
+            ("debounce", self.debounce != before.debounce),
+            ("lift_off_distance", self.lift_off_distance != before.lift_off_distance),
+        ]
+        .into_iter()
+        .filter_map(|(field, changed)| changed.then_some(field))
+        .collect()
+    }
+
     /// Find a resolution by its `index` field.
     pub fn find_resolution(&self, id: u32) -> Option<&ResolutionInfo> {
         self.resolutions.iter().find(|r| r.index == id)
@@ -354,16 +696,88 @@ impl ProfileInfo {
         self.leds.iter_mut().find(|l| l.index == id)
     }
 
+    /// Enable or disable a resolution and mark the profile dirty.
+    ///
+    /// The active resolution cannot be disabled: the mouse would be left
+    /// without a DPI stage.  The error string is suitable for DBus.
+    pub fn set_resolution_disabled(&mut self, id: u32, disabled: bool) -> Result<(), String> {
+        let res = self
+            .resolutions
+            .iter_mut()
+            .find(|r| r.index == id)
+            .ok_or_else(|| format!("Resolution {id} not found in profile {}", self.index))?;
+        if disabled && res.is_active {
+            return Err(format!(
+                "Resolution {id} is the active resolution and cannot be disabled; \
+                 activate another resolution first"
+            ));
+        }
+        res.is_disabled = disabled;
+        self.is_dirty = true;
+        Ok(())
+    }
+
     /// Clamp a report rate to the allowed range.
     #[inline]
     pub fn clamp_report_rate(rate: u32) -> u32 {
         rate.clamp(REPORT_RATE_MIN, REPORT_RATE_MAX)
     }
 
-    /// Return only the well-known profile capabilities (SET_DEFAULT, DISABLE)
-    /// that are present in this profile's capability list.
+    /// Check a report rate against a driver's supported list.
+    ///
+    /// Profiles that list no rates fall back to clamping the value to the
+    /// allowed range; the error string is suitable for DBus and log output.
+    pub fn validate_report_rate(rate: u32, supported: &[u32]) -> Result<u32, String> {
+        if supported.is_empty() {
+            return Ok(Self::clamp_report_rate(rate));
+        }
+        if supported.contains(&rate) {
+            Ok(rate)
+        } else {
+            Err(format!("Unsupported report rate {rate} Hz (supported: {supported:?})"))
+        }
+    }
+
+    /// Check a debounce time against a driver's supported list.
+    ///
+    /// Returns the value as milliseconds on success so drivers can encode
+    /// it directly; the error string is suitable for DBus and log output.
+    pub fn validate_debounce(ms: i32, supported: &[u32]) -> Result<u32, String> {
+        if supported.is_empty() {
+            return Err("Debounce is not supported on this profile".to_owned());
+        }
+        match u32::try_from(ms) {
+            Ok(value) if supported.contains(&value) => Ok(value),
+            _ => Err(format!(
+                "Unsupported debounce time {ms} ms (supported: {supported:?})"
+            )),
+        }
+    }
+
+    /// Check a lift-off distance level against a driver's supported list.
+    pub fn validate_lift_off_distance(level: u32, supported: &[u32]) -> Result<u32, String> {
+        if supported.is_empty() {
+            return Err("Lift-off distance is not supported on this profile".to_owned());
+        }
+        if supported.contains(&level) {
+            Ok(level)
+        } else {
+            Err(format!(
+                "Unsupported lift-off distance {level} (supported: {supported:?})"
+            ))
+        }
+    }
+
+    /// Return only the well-known profile capabilities (SET_DEFAULT, DISABLE,
+    /// GLOBAL_REPORT_RATE, ASCII_NAME) that are present in this profile's
+    /// capability list.
     pub fn dbus_capabilities(&self) -> Vec<u32> {
-        const EXPOSED: &[u32] = &[RATBAG_PROFILE_CAP_SET_DEFAULT, RATBAG_PROFILE_CAP_DISABLE];
+        const EXPOSED: &[u32] = &[
+            RATBAG_PROFILE_CAP_SET_DEFAULT,
+            RATBAG_PROFILE_CAP_DISABLE,
+            RATBAG_PROFILE_CAP_GLOBAL_REPORT_RATE,
+            RATBAG_PROFILE_CAP_ASCII_NAME,
+        ];
         self.capabilities
             .iter()
             .copied()
@@ -371,6 +785,30 @@ impl ProfileInfo {
             .collect()
     }
 
+    /// Check that `name` only uses characters the device can store.
+    ///
+    /// Profiles with `RATBAG_PROFILE_CAP_ASCII_NAME` take printable ASCII
+    /// only; the error names the characters that do not fit.
+    pub fn validate_name(&self, name: &str) -> Result<(), String> {
+        if !self.capabilities.contains(&RATBAG_PROFILE_CAP_ASCII_NAME) {
+            return Ok(());
+        }
+        let mut rejected: Vec<char> = Vec::new();
+        for c in name.chars().filter(|c| !(' '..='~').contains(c)) {
+            if !rejected.contains(&c) {
+                rejected.push(c);
+            }
+        }
+        if rejected.is_empty() {
+            return Ok(());
+        }
+        let list: Vec<String> = rejected.iter().map(|c| format!("{:?}", c)).collect();
+        Err(format!(
+            "This device only stores ASCII profile names; {} cannot be stored",
+            list.join(", ")
+        ))
+    }
+
     /// Sanitize a profile name for DBus transport.
     ///
     /// C-compatible policy: if the bytes are valid UTF-8, use them as-is;
@@ -399,7 +837,7 @@ impl ProfileInfo {
 }
 
 /// Resolution state.
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct ResolutionInfo {
     pub index: u32,
     pub dpi: Dpi,
@@ -410,18 +848,75 @@ pub struct ResolutionInfo {
     pub is_disabled: bool,
 }
 
+impl ResolutionInfo {
+    /// Whether a commit would write anything different from `before`.
+    pub fn differs_from(&self, before: &ResolutionInfo) -> bool {
+        self.dpi != before.dpi
+            || self.is_active != before.is_active
+            || self.is_default != before.is_default
+            || self.is_disabled != before.is_disabled
+    }
+
+    /// Check that both axes of a `Dpi::Separate` lie within the range of
+    /// `dpi_list`; firmware rejects or clamps axes it cannot take.  An
+    /// empty list means the driver reported no range.
+    pub fn validate_dpi(&self) -> Result<(), String> {
+        let Dpi::Separate { x, y } = self.dpi else {
+            return Ok(());
+        };
+        let (Some(&min), Some(&max)) = (self.dpi_list.iter().min(), self.dpi_list.iter().max())
+        else {
+            return Ok(());
+        };
+        for (axis, value) in [("X", x), ("Y", y)] {
+            if !(min..=max).contains(&value) {
+                return Err(format!(
+                    "{axis} DPI {value} is out of range (supported: {min}-{max})"
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
 /// Button mapping state.
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct ButtonInfo {
     pub index: u32,
     pub action_type: ActionType,
     pub action_types: Vec<u32>,
     pub mapping_value: u32,
     pub macro_entries: Vec<(u32, u32)>,
+    pub macro_repeat: MacroRepeat,
+    /* Factory mapping as (action type, value); `None` when the driver
+     * does not know it.  Filled in by the actor after load. */
+    pub default_mapping: Option<(ActionType, u32)>,
+}
+
+impl ButtonInfo {
+    /// Whether a commit would write anything different from `before`.
+    pub fn differs_from(&self, before: &ButtonInfo) -> bool {
+        self.action_type != before.action_type
+            || self.mapping_value != before.mapping_value
+            || self.macro_entries != before.macro_entries
+            || self.macro_repeat != before.macro_repeat
+    }
+
+    /// Restore the factory mapping, if the driver reported one.
+    pub fn reset_to_default(&mut self) -> Result<(), String> {
+        let (action_type, value) = self
+            .default_mapping
+            .ok_or_else(|| format!("No default mapping known for button {}", self.index))?;
+        self.action_type = action_type;
+        self.mapping_value = value;
+        self.macro_entries.clear();
+        self.macro_repeat = MacroRepeat::Once;
+        Ok(())
+    }
 }
 
 /// LED state.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct LedInfo {
     pub index: u32,
     pub mode: LedMode,
@@ -431,5 +926,330 @@ pub struct LedInfo {
     pub tertiary_color: Color,
     pub color_depth: u32,
     pub effect_duration: u32,
+    /// Direction of ColorWave-style effects; ignored by static modes.
+    pub effect_direction: LedDirection,
+    /// The device takes `effect_direction`; on others the direction byte
+    /// stays zero and only clockwise is accepted.
+    pub has_direction: bool,
     pub brightness: u32,
+    /// Changed since the last commit; drivers that write LEDs one at a
+    /// time skip clean ones.
+    pub is_dirty: bool,
+    /// Listed in `UnmanagedLeds=`: another controller owns the zone, so it
+    /// is read-only over DBus and drivers never write it.
+    pub unmanaged: bool,
+}
+
+impl LedInfo {
+    /// Whether a commit would write anything different from `before`.
+    pub fn differs_from(&self, before: &LedInfo) -> bool {
+        self.mode != before.mode
+            || self.color != before.color
+            || self.secondary_color != before.secondary_color
+            || self.tertiary_color != before.tertiary_color
+            || self.effect_duration != before.effect_duration
+            || self.effect_direction != before.effect_direction
+            || self.brightness != before.brightness
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device_database::load_device_database;
+
+    /* Write `body` as the only `.device` file of a fresh temp directory,
+     * load it through the database parser and build its `DeviceInfo`. */
+    fn device_info_from_file(tag: &str, body: &str) -> DeviceInfo {
+        let dir = std::env::temp_dir().join(format!(
+            "ratbagd-device-type-{}-{}",
+            tag,
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("test.device"), body).unwrap();
+        let db = load_device_database(&dir);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let entry = db.values().next().expect("device file should parse");
+        DeviceInfo::from_entry("hidraw0", "Test Device", 0x03, 0x046d, 0xc539, "", entry)
+    }
+
+    #[test]
+    fn test_stable_key_includes_serial_when_known() {
+        let mut info = device_info_from_file(
+            "stable-key",
+            "[Device]\nName=Test\nDriver=hidpp20\nDeviceMatch=usb:046d:c539\n",
+        );
+        assert_eq!(info.stable_key(), "usb:046d:c539");
+        info.serial = "4a3b2c1d".to_string();
+        assert_eq!(info.stable_key(), "usb:046d:c539:4a3b2c1d");
+        assert_eq!(info.bus_ids(), ("usb", 0x046d, 0xc539));
+        info.model.clear();
+        assert_eq!(info.bus_ids(), ("", 0, 0));
+    }
+
+    #[test]
+    fn test_fill_default_action_types_keeps_driver_lists() {
+        let mut info = device_info_from_file(
+            "action-types",
+            "[Device]\nName=Test\nDriver=hidpp20\nDeviceMatch=usb:046d:c539\n\n[Driver/hidpp20]\nButtons=2\n",
+        );
+        let buttons = &mut info.profiles[0].buttons;
+        assert!(buttons.iter().all(|b| b.action_types.is_empty()));
+        buttons[1].action_types = vec![ActionType::Button as u32];
+
+        info.fill_default_action_types(&[ActionType::Button, ActionType::Key]);
+        let buttons = &info.profiles[0].buttons;
+        assert_eq!(buttons[0].action_types, vec![1, 3]);
+        assert_eq!(buttons[1].action_types, vec![1]);
+    }
+
+    #[test]
+    fn test_global_report_rate_applies_to_all_profiles() {
+        let body = "[Device]\nName=Test\nDriver=hidpp10\nDeviceMatch=usb:046d:c539\n\n[Driver/hidpp10]\nProfiles=3\n";
+        let mut info = device_info_from_file("global-rate", body);
+        for profile in &mut info.profiles {
+            profile.report_rate = 500;
+            profile.capabilities.push(RATBAG_PROFILE_CAP_GLOBAL_REPORT_RATE);
+        }
+
+        assert_eq!(info.set_report_rate(1, 1000), Some(vec![0, 1, 2]));
+        assert!(info.profiles.iter().all(|p| p.report_rate == 1000 && p.is_dirty));
+
+        let mut info = device_info_from_file("per-profile-rate", body);
+        assert_eq!(info.set_report_rate(1, 250), Some(vec![1]));
+        assert_eq!(info.profiles[0].report_rate, info.profiles[2].report_rate);
+        assert_eq!(info.profiles[1].report_rate, 250);
+        assert!(!info.profiles[0].is_dirty);
+        assert_eq!(info.set_report_rate(7, 250), None);
+    }
+
+    #[test]
+    fn test_diff_names_only_changed_settings() {
+        let body = "[Device]\nName=Test\nDriver=hidpp20\nDeviceMatch=usb:046d:c539\n\n[Driver/hidpp20]\nProfiles=2\nButtons=3\nLeds=1\n";
+        let before = device_info_from_file("diff", body);
+        let mut after = before.clone();
+        assert!(after.diff(&before).is_empty());
+
+        /* Dirty flags and supported lists are not settings. */
+        after.profiles[0].is_dirty = true;
+        after.profiles[0].report_rates = vec![500, 1000];
+        after.profiles[1].leds[0].is_dirty = true;
+        assert!(after.diff(&before).is_empty());
+
+        after.profiles[1].report_rate = 250;
+        after.profiles[1].buttons[2].mapping_value = 9;
+        after.profiles[0].leds[0].brightness = 10;
+        after.smart_shift_threshold = Some(12);
+        let diff = after.diff(&before);
+        assert!(diff.device);
+        assert_eq!(diff.profiles.iter().copied().collect::<Vec<_>>(), [1]);
+        assert!(diff.resolutions.is_empty());
+        assert_eq!(diff.buttons.iter().copied().collect::<Vec<_>>(), [(1, 2)]);
+        assert_eq!(diff.leds.iter().copied().collect::<Vec<_>>(), [(0, 0)]);
+        assert!(diff.touches_profile(0) && diff.touches_profile(1));
+
+        /* A profile the old state lacks is new in every respect. */
+        let mut before = before;
+        before.profiles.pop();
+        let diff = after.diff(&before);
+        assert!(diff.touches_profile(1));
+        assert!(diff.buttons.contains(&(1, 0)));
+    }
+
+    #[test]
+    fn test_unmanaged_leds_come_from_device_file() {
+        let mut info = device_info_from_file(
+            "unmanaged-leds",
+            "[Device]\nName=Test\nDriver=hidpp20\nDeviceMatch=usb:046d:c539\n\n[Driver/hidpp20]\nLeds=3\nUnmanagedLeds=1;2\n",
+        );
+        assert_eq!(info.driver_config.unmanaged_leds, vec![1, 2]);
+        info.mark_unmanaged_leds();
+        let unmanaged: Vec<bool> = info.profiles[0].leds.iter().map(|l| l.unmanaged).collect();
+        assert_eq!(unmanaged, [false, true, true]);
+    }
+
+    #[test]
+    fn test_ascii_only_profile_name() {
+        let mut profile = ProfileInfo::default();
+        assert!(profile.validate_name("Gaming 💀").is_ok());
+
+        profile.capabilities.push(RATBAG_PROFILE_CAP_ASCII_NAME);
+        assert!(profile.validate_name("Gaming ~1").is_ok());
+        assert!(profile.validate_name("").is_ok());
+        assert_eq!(
+            profile.validate_name("💀 Café 💀").unwrap_err(),
+            "This device only stores ASCII profile names; '💀', 'é' cannot be stored"
+        );
+        assert!(profile.validate_name("tab\there").is_err());
+    }
+
+    #[test]
+    fn test_active_resolution_cannot_be_disabled() {
+        let mut info = device_info_from_file(
+            "disable-resolution",
+            "[Device]\nName=Test\nDriver=hidpp20\nDeviceMatch=usb:046d:c539\n\n[Driver/hidpp20]\nProfiles=1\nDpis=3\n",
+        );
+        let profile = &mut info.profiles[0];
+        assert!(profile.resolutions[0].is_active);
+
+        let err = profile.set_resolution_disabled(0, true).unwrap_err();
+        assert!(err.contains("active resolution"));
+        assert!(!profile.resolutions[0].is_disabled);
+        assert!(!profile.is_dirty);
+
+        profile.set_resolution_disabled(2, true).unwrap();
+        assert!(profile.resolutions[2].is_disabled);
+        assert!(profile.is_dirty);
+        /* Re-enabling the active one is always allowed. */
+        profile.set_resolution_disabled(0, false).unwrap();
+        assert!(profile.set_resolution_disabled(9, true).is_err());
+    }
+
+    #[test]
+    fn test_repeated_macros_lists_only_macro_buttons() {
+        let mut info = device_info_from_file(
+            "macro-repeat",
+            "[Device]\nName=Test\nDriver=hidpp20\nDeviceMatch=usb:046d:c539\n\n[Driver/hidpp20]\nProfiles=2\nButtons=3\n",
+        );
+        assert!(info.repeated_macros().is_empty());
+
+        info.profiles[0].buttons[1].macro_repeat = MacroRepeat::Times(3);
+        info.profiles[1].buttons[2].action_type = ActionType::Macro;
+        info.profiles[1].buttons[2].macro_repeat = MacroRepeat::WhileHeld;
+        info.profiles[1].buttons[0].action_type = ActionType::Macro;
+        assert_eq!(info.repeated_macros(), vec![(1, 2)]);
+    }
+
+    #[test]
+    fn test_macro_repeat_dbus_encoding() {
+        assert_eq!(MacroRepeat::from_u32(0), MacroRepeat::WhileHeld);
+        assert_eq!(MacroRepeat::from_u32(1), MacroRepeat::Once);
+        assert_eq!(MacroRepeat::from_u32(3), MacroRepeat::Times(3));
+        for raw in [0, 1, 3] {
+            assert_eq!(MacroRepeat::from_u32(raw).to_u32(), raw);
+        }
+    }
+
+    #[test]
+    fn test_device_type_from_database_entry() {
+        let info = device_info_from_file(
+            "keyboard",
+            "[Device]\nName=Test\nDriver=hidpp20\nDeviceMatch=usb:046d:c539\nDeviceType=keyboard\n",
+        );
+        assert_eq!(info.device_type, DeviceType::Keyboard);
+        assert_eq!(info.device_type as u32, 3);
+    }
+
+    #[test]
+    fn test_device_type_defaults_to_mouse() {
+        let info = device_info_from_file(
+            "default",
+            "[Device]\nName=Test\nDriver=hidpp20\nDeviceMatch=usb:046d:c539\n",
+        );
+        assert_eq!(info.device_type, DeviceType::Mouse);
+    }
+
+    #[test]
+    fn test_device_type_unknown_string() {
+        assert_eq!(DeviceType::from_db_str("tablet"), DeviceType::Unspecified);
+        assert_eq!(DeviceType::from_db_str(" Mouse "), DeviceType::Mouse);
+    }
+
+    #[test]
+    fn test_validate_report_rate_against_supported_rates() {
+        let rates = [125, 250, 500, 1000];
+        assert_eq!(ProfileInfo::validate_report_rate(500, &rates), Ok(500));
+        assert!(ProfileInfo::validate_report_rate(2000, &rates).is_err());
+        assert!(ProfileInfo::validate_report_rate(0, &rates).is_err());
+        /* Without a list only the range is enforced. */
+        assert_eq!(ProfileInfo::validate_report_rate(20_000, &[]), Ok(REPORT_RATE_MAX));
+    }
+
+    #[test]
+    fn test_validate_debounce_accepts_supported_value() {
+        assert_eq!(ProfileInfo::validate_debounce(8, &[4, 8, 12]), Ok(8));
+    }
+
+    #[test]
+    fn test_validate_debounce_rejects_unlisted_value() {
+        assert!(ProfileInfo::validate_debounce(5, &[4, 8, 12]).is_err());
+    }
+
+    #[test]
+    fn test_validate_debounce_rejects_negative_value() {
+        assert!(ProfileInfo::validate_debounce(-1, &[4, 8, 12]).is_err());
+    }
+
+    #[test]
+    fn test_validate_debounce_unsupported_profile() {
+        assert!(ProfileInfo::validate_debounce(8, &[]).is_err());
+    }
+
+    #[test]
+    fn test_validate_lift_off_distance() {
+        assert_eq!(ProfileInfo::validate_lift_off_distance(2, &[1, 2]), Ok(2));
+        assert!(ProfileInfo::validate_lift_off_distance(3, &[1, 2]).is_err());
+        assert!(ProfileInfo::validate_lift_off_distance(1, &[]).is_err());
+    }
+
+    fn separate(x: u32, y: u32) -> ResolutionInfo {
+        ResolutionInfo {
+            index: 1,
+            dpi: Dpi::Separate { x, y },
+            dpi_list: (100..=16000).step_by(50).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_validate_dpi_accepts_axes_in_range() {
+        assert_eq!(separate(400, 16000).validate_dpi(), Ok(()));
+        assert_eq!(separate(100, 100).validate_dpi(), Ok(()));
+        /* No range reported: nothing to check against. */
+        let unlisted = ResolutionInfo {
+            dpi_list: Vec::new(),
+            ..separate(1, 99999)
+        };
+        assert_eq!(unlisted.validate_dpi(), Ok(()));
+    }
+
+    #[test]
+    fn test_sleep_timeout_range() {
+        let timeout = SleepTimeout { minutes: 10, min: 1, max: 300 };
+        assert!(timeout.validate(1).is_ok());
+        assert!(timeout.validate(300).is_ok());
+        assert_eq!(
+            timeout.validate(0),
+            Err("Sleep timeout 0 is out of range (supported: 1-300 minutes)".to_owned())
+        );
+        assert!(timeout.validate(301).is_err());
+    }
+
+    #[test]
+    fn test_validate_dpi_names_the_axis_out_of_range() {
+        assert_eq!(
+            separate(800, 25600).validate_dpi(),
+            Err("Y DPI 25600 is out of range (supported: 100-16000)".to_owned())
+        );
+        assert_eq!(
+            separate(50, 800).validate_dpi(),
+            Err("X DPI 50 is out of range (supported: 100-16000)".to_owned())
+        );
+
+        let info = DeviceInfo {
+            sysname: "hidraw-test".to_string(),
+            profiles: vec![ProfileInfo {
+                index: 2,
+                resolutions: vec![separate(800, 25600)],
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        assert!(info
+            .validate_resolutions()
+            .unwrap_err()
+            .starts_with("Profile 2 resolution 1: Y DPI 25600"));
+    }
 }
@@ -50,10 +50,42 @@ impl fmt::Display for BusType {
 #[allow(dead_code)]
 pub struct DeviceEntry {
     pub name: String,
+    /* Primary driver; always the first element of `drivers`. */
     pub driver: String,
+    /* Drivers to probe in order, from `Driver=hidpp20;hidpp10`. */
+    pub drivers: Vec<String>,
     pub device_type: String,
+    /* Physical position of each button, by index, from `ButtonLayout=`. */
+    pub button_layout: Vec<String>,
+    /* USB interface number of the hidraw node to use, from `Interface=`. */
+    pub interface: Option<u8>,
+    /* Usage page of a top-level collection the hidraw node must declare,
+     * from `UsagePage=` (hex, e.g. `ff00` for a vendor collection). */
+    pub usage_page: Option<u16>,
     pub matches: Vec<DeviceMatch>,
+    /* The primary driver's `[Driver/xxx]` section. */
     pub driver_config: Option<DriverConfig>,
+    /* Each entry of `drivers`' own `[Driver/xxx]` section, in the same
+     * order; empty for a driver the file has no section for. */
+    pub driver_configs: Vec<DriverConfig>,
+}
+
+impl DeviceEntry {
+    /* Whether a hidraw node with USB interface `interface` and top-level
+     * collections on `usage_pages` is the one this entry asks for.  A node
+     * that cannot tell (no USB interface, unreadable descriptor) passes the
+     * corresponding check so Bluetooth and uhid devices keep working. */
+    pub fn accepts_interface(&self, interface: Option<u8>, usage_pages: &[u16]) -> bool {
+        let interface_ok = match (self.interface, interface) {
+            (Some(wanted), Some(actual)) => wanted == actual,
+            _ => true,
+        };
+        let usage_page_ok = match self.usage_page {
+            Some(wanted) if !usage_pages.is_empty() => usage_pages.contains(&wanted),
+            _ => true,
+        };
+        interface_ok && usage_page_ok
+    }
 }
 
 /* A single bus:vid:pid match pattern from the `DeviceMatch=` field. */
@@ -80,11 +112,25 @@ pub struct DriverConfig {
     pub button_mapping: Vec<u8>,
     pub button_mapping_secondary: Vec<u8>,
     pub led_modes: Vec<String>,
+    /// Selectable DPI values from `DpiList=`, an alternative to `DpiRange=`.
+    pub dpi_list: Vec<u32>,
+    /// HID++ 1.0 register and flash layout family from `ProfileType=`
+    /// (`G500`, `G700` or `G9`).
+    pub profile_type: Option<String>,
+    /// LED indices from `UnmanagedLeds=` that another controller owns.
+    pub unmanaged_leds: Vec<u32>,
     /// SinoWealth firmware-versioned device entries from
     /// `[Driver/sinowealth/devices/<fw_version>]` sections.
     pub sinowealth_devices: Vec<SinowealthDeviceConfig>,
 }
 
+impl DriverConfig {
+    /* Whether the device file lists `quirk` in `Quirk=`/`Quirks=`. */
+    pub fn has_quirk(&self, quirk: &str) -> bool {
+        self.quirks.iter().any(|q| q == quirk)
+    }
+}
+
 /* LED channel order for SinoWealth devices. */
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum SinowealthLedType {
@@ -125,7 +171,11 @@ pub struct SinowealthDeviceConfig {
 pub struct DpiRange {
     pub min: u32,
     pub max: u32,
+    /// The step rounded to a whole DPI, at least 1.
     pub step: u32,
+    /// The step as written.  HID++ 1.0 files give a fraction here
+    /// (`@23.53`): the DPI of one raw sensor unit of that model.
+    pub exact_step: f64,
 }
 
 /* Device database: maps `(bustype, vid, pid)` to a `DeviceEntry`. */
@@ -134,6 +184,23 @@ pub struct DpiRange {
 /* patterns share a single allocation instead of being duplicated.   */
 pub type DeviceDb = HashMap<(BusType, u16, u16), Arc<DeviceEntry>>;
 
+/* A `DeviceMatch` pid of `ffff` matches every pid of that bus/vid that has
+ * no exact entry of its own, so one file can cover a family of rebadged
+ * clones sharing a chipset. */
+pub const WILDCARD_PID: u16 = 0xffff;
+
+/* Look up a device by `(bustype, vid, pid)`, falling back to the wildcard
+ * entry for the same bus and vendor when there is no exact match. */
+pub fn lookup_device<'a>(
+    db: &'a DeviceDb,
+    key: &(BusType, u16, u16),
+) -> Option<&'a Arc<DeviceEntry>> {
+    db.get(key).or_else(|| {
+        let (bustype, vid, _) = key;
+        db.get(&(bustype.clone(), *vid, WILDCARD_PID))
+    })
+}
+
 /* Load all `.device` files from the given directory into a lookup table. */
 /*  */
 /* Each `DeviceMatch` pattern (semicolon-separated in the file) becomes */
@@ -187,45 +254,96 @@ pub fn load_device_database(data_dir: &Path) -> DeviceDb {
 /* Parse a single `.device` INI file into a `DeviceEntry`. */
 fn parse_device_file(path: &Path) -> Result<DeviceEntry, String> {
     let mut ini = Ini::new();
+    /* Like GKeyFile, only whole lines are comments: `;` separates list
+     * values (DeviceMatch, DpiList, ButtonLayout, ...). */
+    ini.set_inline_comment_symbols(Some(&[]));
     ini.load(path).map_err(|e| format!("INI parse error: {}", e))?;
 
     /* [Device] section — required fields */
     let name = ini
         .get("device", "name")
         .ok_or("Missing [Device] Name")?;
-    let driver = ini
-        .get("device", "driver")
-        .ok_or("Missing [Device] Driver")?;
+    let drivers = parse_driver_list(
+        &ini.get("device", "driver")
+            .ok_or("Missing [Device] Driver")?,
+    )?;
+    let driver = drivers[0].clone();
     let match_str = ini
         .get("device", "devicematch")
         .ok_or("Missing [Device] DeviceMatch")?;
     let device_type = ini
         .get("device", "devicetype")
         .unwrap_or_else(|| "mouse".to_string());
+    let button_layout = ini
+        .get("device", "buttonlayout")
+        .map(|s| parse_semicolon_strings(&s))
+        .unwrap_or_default();
+
+    let interface = ini
+        .get("device", "interface")
+        .map(|s| {
+            s.trim()
+                .parse::<u8>()
+                .map_err(|e| format!("Invalid Interface '{}': {}", s, e))
+        })
+        .transpose()?;
+    let usage_page = ini
+        .get("device", "usagepage")
+        .map(|s| {
+            let hex = s.trim();
+            let hex = hex.strip_prefix("0x").unwrap_or(hex);
+            u16::from_str_radix(hex, 16).map_err(|e| format!("Invalid UsagePage '{}': {}", s, e))
+        })
+        .transpose()?;
 
     /* Parse semicolon-separated match patterns: "usb:046d:c539;usb:046d:c53a" */
     let matches = parse_device_matches(&match_str)?;
 
-    /* [Driver/xxx] section — optional */
+    let driver_config = parse_driver_section(&ini, &driver);
+    let driver_configs = drivers
+        .iter()
+        .map(|name| parse_driver_section(&ini, name).unwrap_or_default())
+        .collect();
+
+    Ok(DeviceEntry {
+        name,
+        driver,
+        drivers,
+        device_type,
+        button_layout,
+        interface,
+        usage_page,
+        matches,
+        driver_config,
+        driver_configs,
+    })
+}
+
+/* The optional `[Driver/<driver>]` section, with any SinoWealth
+ * firmware-versioned subsections attached. */
+fn parse_driver_section(ini: &Ini, driver: &str) -> Option<DriverConfig> {
     let driver_section = format!("driver/{}", driver);
     let has_driver_section = ini.get(&driver_section, "profiles").is_some()
         || ini.get(&driver_section, "buttons").is_some()
         || ini.get(&driver_section, "leds").is_some()
         || ini.get(&driver_section, "dpis").is_some()
         || ini.get(&driver_section, "dpirange").is_some()
+        || ini.get(&driver_section, "dpilist").is_some()
+        || ini.get(&driver_section, "profiletype").is_some()
         || ini.get(&driver_section, "deviceversion").is_some()
         || ini.get(&driver_section, "macrolength").is_some()
         || ini.get(&driver_section, "quirk").is_some()
         || ini.get(&driver_section, "quirks").is_some()
         || ini.get(&driver_section, "buttonmapping").is_some()
         || ini.get(&driver_section, "buttonmappingsecondary").is_some()
-        || ini.get(&driver_section, "ledmodes").is_some();
+        || ini.get(&driver_section, "ledmodes").is_some()
+        || ini.get(&driver_section, "unmanagedleds").is_some();
 
     /* Parse SinoWealth firmware-versioned subsections if present. */
-    let sinowealth_devices = parse_sinowealth_device_sections(&ini, &driver);
+    let sinowealth_devices = parse_sinowealth_device_sections(ini, driver);
 
     let mut driver_config = if has_driver_section {
-        Some(parse_driver_config(&ini, &driver_section))
+        Some(parse_driver_config(ini, &driver_section))
     } else if !sinowealth_devices.is_empty() {
         /* SinoWealth files may lack a flat [Driver/sinowealth] section but
          * still carry per-firmware subsections.  Create a default config. */
@@ -241,13 +359,18 @@ fn parse_device_file(path: &Path) -> Result<DeviceEntry, String> {
         }
     }
 
-    Ok(DeviceEntry {
-        name,
-        driver,
-        device_type,
-        matches,
-        driver_config,
-    })
+    driver_config
+}
+
+/* Parse a `Driver` string like `"hidpp20;hidpp10"` into an ordered list.
+ * The first entry is the preferred driver; the rest are fallbacks tried
+ * when the preferred driver's probe fails. */
+fn parse_driver_list(s: &str) -> Result<Vec<String>, String> {
+    let drivers = parse_semicolon_strings(s);
+    if drivers.is_empty() {
+        return Err("Driver is empty".to_string());
+    }
+    Ok(drivers)
 }
 
 /* Parse a `DeviceMatch` string like `"usb:046d:c539;usb:046d:c53a"`. */
@@ -309,6 +432,22 @@ fn parse_driver_config(ini: &Ini, section: &str) -> DriverConfig {
         .map(|s| parse_semicolon_strings(&s))
         .unwrap_or_default();
 
+    let dpi_list = ini
+        .get(section, "dpilist")
+        .map(|s| parse_semicolon_strings(&s))
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|v| v.parse().ok())
+        .collect();
+
+    let unmanaged_leds = ini
+        .get(section, "unmanagedleds")
+        .map(|s| parse_semicolon_strings(&s))
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|v| v.parse().ok())
+        .collect();
+
     DriverConfig {
         profiles: ini.get(section, "profiles").and_then(|v| v.parse().ok()),
         buttons: ini.get(section, "buttons").and_then(|v| v.parse().ok()),
@@ -330,6 +469,9 @@ fn parse_driver_config(ini: &Ini, section: &str) -> DriverConfig {
         button_mapping,
         button_mapping_secondary,
         led_modes,
+        dpi_list,
+        profile_type: ini.get(section, "profiletype"),
+        unmanaged_leds,
         sinowealth_devices: Vec::new(),
     }
 }
@@ -411,20 +553,150 @@ fn parse_dpi_range(s: &str) -> Option<DpiRange> {
 
     let min = min_str.parse().ok()?;
     let max = max_str.parse().ok()?;
-    let step: u32 = step_str.parse().ok()?;
+    let exact_step: f64 = step_str.parse().ok()?;
 
     /* Reject degenerate ranges that would cause step_by(0) panics or empty lists. */
-    if step == 0 || min > max {
+    if exact_step.is_nan() || exact_step <= 0.0 || min > max {
         return None;
     }
+    let step = (exact_step.round() as u32).max(1);
 
-    Some(DpiRange { min, max, step })
+    Some(DpiRange { min, max, step, exact_step })
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    /* Load `files` (name, body) from a fresh temp directory. */
+    fn load_db_from(tag: &str, files: &[(&str, &str)]) -> DeviceDb {
+        let dir = std::env::temp_dir().join(format!(
+            "ratbagd-devdb-{}-{}",
+            tag,
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        for (name, body) in files {
+            std::fs::write(dir.join(name), body).unwrap();
+        }
+        let db = load_device_database(&dir);
+        std::fs::remove_dir_all(&dir).unwrap();
+        db
+    }
+
+    #[test]
+    fn test_wildcard_pid_matches_unlisted_pid() {
+        let db = load_db_from(
+            "wildcard",
+            &[
+                (
+                    "clones.device",
+                    "[Device]\nName=Clone Family\nDriver=sinowealth\nDeviceMatch=usb:258a:ffff\n",
+                ),
+                (
+                    "exact.device",
+                    "[Device]\nName=Exact Model\nDriver=sinowealth\nDeviceMatch=usb:258a:0033\n",
+                ),
+            ],
+        );
+
+        let clone = lookup_device(&db, &(BusType::Usb, 0x258a, 0x1007)).unwrap();
+        assert_eq!(clone.name, "Clone Family");
+
+        /* An exact entry still wins over the wildcard. */
+        let exact = lookup_device(&db, &(BusType::Usb, 0x258a, 0x0033)).unwrap();
+        assert_eq!(exact.name, "Exact Model");
+
+        /* The wildcard is scoped to its bus and vendor. */
+        assert!(lookup_device(&db, &(BusType::Usb, 0x258b, 0x1007)).is_none());
+        assert!(lookup_device(&db, &(BusType::Bluetooth, 0x258a, 0x1007)).is_none());
+    }
+
+    #[test]
+    fn test_driver_quirks_from_device_file() {
+        let db = load_db_from(
+            "quirks",
+            &[(
+                "rgb.device",
+                "[Device]\nName=RGB Clusters\nDriver=hidpp20\nDeviceMatch=usb:046d:c099\n\
+                 [Driver/hidpp20]\nQuirk=RGB_EFFECTS_PREFERRED\n",
+            )],
+        );
+
+        let entry = lookup_device(&db, &(BusType::Usb, 0x046d, 0xc099)).unwrap();
+        let config = entry.driver_config.as_ref().unwrap();
+        assert!(config.has_quirk("RGB_EFFECTS_PREFERRED"));
+        assert!(!config.has_quirk("G305"));
+    }
+
+    #[test]
+    fn test_button_layout_is_optional() {
+        let db = load_db_from(
+            "layout",
+            &[
+                (
+                    "layout.device",
+                    "[Device]\nName=With Layout\nDriver=hidpp20\nDeviceMatch=usb:046d:c001\n\
+                     ButtonLayout=left;right;middle;side-rear;side-front\n",
+                ),
+                (
+                    "plain.device",
+                    "[Device]\nName=Plain\nDriver=hidpp20\nDeviceMatch=usb:046d:c002\n",
+                ),
+            ],
+        );
+
+        let with_layout = lookup_device(&db, &(BusType::Usb, 0x046d, 0xc001)).unwrap();
+        assert_eq!(
+            with_layout.button_layout,
+            vec!["left", "right", "middle", "side-rear", "side-front"]
+        );
+        let plain = lookup_device(&db, &(BusType::Usb, 0x046d, 0xc002)).unwrap();
+        assert!(plain.button_layout.is_empty());
+    }
+
+    #[test]
+    fn test_interface_selection_picks_vendor_node() {
+        let db = load_db_from(
+            "interface",
+            &[
+                (
+                    "vendor.device",
+                    "[Device]\nName=Vendor Node\nDriver=sinowealth\nDeviceMatch=usb:258a:0036\n\
+                     Interface=2\nUsagePage=ff00\n",
+                ),
+                (
+                    "any.device",
+                    "[Device]\nName=Any Node\nDriver=hidpp20\nDeviceMatch=usb:046d:c08b\n",
+                ),
+            ],
+        );
+
+        /* (interface, top-level usage pages) of each hidraw node of one mouse. */
+        let candidates: [(Option<u8>, &[u16]); 3] = [
+            (Some(0), &[0x0001]),         /* boot mouse */
+            (Some(1), &[0x0001, 0x000c]), /* keyboard + consumer keys */
+            (Some(2), &[0xff00]),         /* vendor command channel */
+        ];
+        let pick = |entry: &DeviceEntry| {
+            candidates
+                .iter()
+                .position(|(interface, pages)| entry.accepts_interface(*interface, pages))
+        };
+
+        let vendor = lookup_device(&db, &(BusType::Usb, 0x258a, 0x0036)).unwrap();
+        assert_eq!(vendor.interface, Some(2));
+        assert_eq!(vendor.usage_page, Some(0xff00));
+        assert_eq!(pick(vendor), Some(2));
+        /* A node that cannot report either property is not rejected. */
+        assert!(vendor.accepts_interface(None, &[]));
+        assert!(!vendor.accepts_interface(None, &[0x0001]));
+
+        /* Without the fields every node is acceptable, as before. */
+        let any = lookup_device(&db, &(BusType::Usb, 0x046d, 0xc08b)).unwrap();
+        assert_eq!(pick(any), Some(0));
+    }
+
     #[test]
     fn test_parse_device_matches_single() {
         let matches = parse_device_matches("usb:046d:c539").unwrap();
@@ -456,6 +728,46 @@ mod tests {
         assert_eq!(matches[1].bustype, BusType::Bluetooth);
     }
 
+    #[test]
+    fn test_parse_driver_list_single() {
+        assert_eq!(parse_driver_list("hidpp20").unwrap(), vec!["hidpp20"]);
+    }
+
+    #[test]
+    fn test_each_fallback_driver_gets_its_own_section() {
+        let db = load_db_from(
+            "fallback-config",
+            &[(
+                "g700.device",
+                "[Device]\nName=G700\nDriver=hidpp20;hidpp10;roccat\nDeviceMatch=usb:046d:c06b\n\
+                 [Driver/hidpp20]\nQuirk=RGB_EFFECTS_PREFERRED\n\
+                 [Driver/hidpp10]\nProfiles=5\nDpiList=400;800\n",
+            )],
+        );
+
+        let entry = lookup_device(&db, &(BusType::Usb, 0x046d, 0xc06b)).unwrap();
+        let [hidpp20, hidpp10, roccat] = &entry.driver_configs[..] else {
+            panic!("one config per driver expected");
+        };
+        assert!(hidpp20.has_quirk("RGB_EFFECTS_PREFERRED"));
+        assert_eq!(hidpp20.profiles, None);
+        assert!(!hidpp10.has_quirk("RGB_EFFECTS_PREFERRED"));
+        assert_eq!(hidpp10.profiles, Some(5));
+        assert_eq!(roccat.profiles, None);
+        assert!(roccat.quirks.is_empty());
+    }
+
+    #[test]
+    fn test_parse_driver_list_ordered_fallbacks() {
+        let drivers = parse_driver_list("hidpp20; hidpp10").unwrap();
+        assert_eq!(drivers, vec!["hidpp20", "hidpp10"]);
+    }
+
+    #[test]
+    fn test_parse_driver_list_empty() {
+        assert!(parse_driver_list(" ; ").is_err());
+    }
+
     #[test]
     fn test_parse_dpi_range() {
         let range = parse_dpi_range("100:16000@100").unwrap();
@@ -464,6 +776,13 @@ mod tests {
         assert_eq!(range.step, 100);
     }
 
+    #[test]
+    fn test_parse_dpi_range_fractional_step() {
+        let range = parse_dpi_range("0:5700@23.53").unwrap();
+        assert_eq!(range.step, 24);
+        assert!((range.exact_step - 23.53).abs() < 1e-9);
+    }
+
     #[test]
     fn test_parse_dpi_range_invalid() {
         assert!(parse_dpi_range("invalid").is_none());
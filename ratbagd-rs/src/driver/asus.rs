@@ -281,6 +281,15 @@ fn parse_quirks(quirk_strings: &[String]) -> u32 {
     q
 }
 
+/// Convert the angle snapping settings word to ratbag's 0/1.  Any other
+/// value is unknown (-1), and commits leave the setting alone.
+fn snapping_from_wire(raw: u16) -> i32 {
+    match raw {
+        0 | 1 => i32::from(raw),
+        _ => -1,
+    }
+}
+
 /// Convert the stored hardware DPI byte to the user-facing DPI value.
 /// Formula: stored * 50 + 50, then × 2 if DOUBLE_DPI.
 fn dpi_from_stored(stored: u16, quirks: u32) -> u32 {
@@ -301,6 +310,26 @@ fn dpi_to_stored(dpi: u32, quirks: u32) -> u8 {
     (adjusted.saturating_sub(50) / 50).min(255) as u8
 }
 
+/// Refuse separate X/Y presets whose axes differ: a preset write carries
+/// a single DPI, so Y would be dropped without a word.
+fn validate_single_axis(info: &DeviceInfo) -> Result<()> {
+    for profile in &info.profiles {
+        for res in &profile.resolutions {
+            if let Dpi::Separate { x, y } = res.dpi
+                && x != y
+            {
+                bail!(
+                    "ASUS: profile {} resolution {}: the device takes one DPI per preset, \
+                     cannot write X {x} and Y {y}",
+                    profile.index,
+                    res.index
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
 /// Convert the hardware brightness byte to the ratbag 0-255 scale.
 /// Non-raw: hardware uses 0-4, ratbag uses 0-256 (4 × 64 = 256).
 /// RAW_BRIGHTNESS: byte is passed through directly.
@@ -412,12 +441,45 @@ impl AsusRequest {
     }
 
     /* Set a parameter byte at offset `idx` within the params region.
-     * params[idx] = buf[2 + idx]; silently ignored if idx is out of range. */
+     * params[idx] = buf[2 + idx].  Every index is a protocol constant, so an
+     * out-of-range one is a driver bug: debug builds assert, release builds
+     * log it and drop the write. */
     fn set_param(&mut self, idx: usize, val: u8) {
-        if let Some(p) = self.buf.get_mut(2 + idx) {
-            *p = val;
+        match self.buf.get_mut(2 + idx) {
+            Some(p) => *p = val,
+            None => param_out_of_range("set_param", idx),
         }
     }
+
+    /* LED addressing lives here so reads and writes cannot drift apart.
+     * params[0] is the zero-based LED index for both commands: with the
+     * SEPARATE_LEDS quirk a read of LED n sends n and the reply carries
+     * that LED in slot 0; without it a read sends 0 and the reply carries
+     * every LED.  `set_led` always addresses a single LED by its index,
+     * matching the C driver in both modes. */
+    fn get_led_data(led_index: u8) -> Self {
+        let mut req = Self::new(ASUS_CMD_GET_LED_DATA);
+        req.set_param(0, led_index);
+        req
+    }
+
+    fn set_dpi(index: u8, stored: u8) -> Self {
+        let mut req = Self::new(ASUS_CMD_SET_SETTING);
+        req.set_param(0, index); /* DPI preset slot (0-3) */
+        req.set_param(2, stored);
+        req
+    }
+
+    fn set_led(index: u8, mode: u8, brightness: u8, r: u8, g: u8, b: u8) -> Self {
+        let mut req = Self::new(ASUS_CMD_SET_LED);
+        req.set_param(0, index);
+        req.set_param(2, mode);
+        req.set_param(3, brightness);
+        req.set_param(4, r);
+        req.set_param(5, g);
+        req.set_param(6, b);
+        req
+    }
 }
 
 /* All ASUS responses are 64-byte raw HID input reports. */
@@ -436,12 +498,31 @@ impl AsusResponse {
         u16::from_le_bytes([self.buf[0], self.buf[1]])
     }
 
-    /* Access results[idx] = buf[2 + idx].  Returns 0 for out-of-range indices. */
+    /* Access results[idx] = buf[2 + idx].  Out-of-range indices are a
+     * driver bug, handled like in `AsusRequest::set_param`; release builds
+     * read them as 0. */
     fn result(&self, idx: usize) -> u8 {
-        self.buf.get(2 + idx).copied().unwrap_or(0)
+        match self.buf.get(2 + idx) {
+            Some(v) => *v,
+            None => {
+                param_out_of_range("result", idx);
+                0
+            }
+        }
     }
 }
 
+/* Report a params/results index past the end of a packet. */
+fn param_out_of_range(accessor: &str, idx: usize) {
+    warn!(
+        "ASUS: {} index {} out of range (packet has {} parameter bytes)",
+        accessor,
+        idx,
+        ASUS_PACKET_SIZE - 2
+    );
+    debug_assert!(false, "ASUS: {accessor} index {idx} out of range");
+}
+
 /* Parsed button binding for a single button slot. */
 #[derive(Clone, Copy, Default)]
 struct AsusBinding {
@@ -804,12 +885,14 @@ impl AsusDriver {
         }
     }
 
-    async fn set_dpi(&self, io: &mut DeviceIo, index: u8, dpi: u32) -> Result<()> {
-        let stored = dpi_to_stored(dpi, self.quirks);
-        let mut req = AsusRequest::new(ASUS_CMD_SET_SETTING);
-        req.set_param(0, index);   /* DPI preset slot (0-3) */
-        req.set_param(2, stored);  /* stored DPI value */
-        self.query(io, &req).await?;
+    /* The preset write takes a single value, even on SEPARATE_XY_DPI
+     * devices; like the C driver, X is what gets written.  `commit`
+     * refuses presets whose axes differ before anything is written. */
+    async fn set_dpi(&self, io: &mut DeviceIo, index: u8, dpi: Dpi) -> Result<()> {
+        let (Dpi::Separate { x: dpi, .. } | Dpi::Unified(dpi)) = dpi else {
+            return Ok(());
+        };
+        self.query(io, &AsusRequest::set_dpi(index, dpi_to_stored(dpi, self.quirks))).await?;
         Ok(())
     }
 
@@ -824,6 +907,8 @@ impl AsusDriver {
     }
 
     async fn set_button_response(&self, io: &mut DeviceIo, ms: u32, dpi_count: u8) -> Result<()> {
+        let ms = ProfileInfo::validate_debounce(ms as i32, ASUS_DEBOUNCE_TIMES)
+            .map_err(|e| anyhow::anyhow!("ASUS: {e}"))?;
         let idx = debounce_index(ms)
             .ok_or_else(|| anyhow::anyhow!("ASUS: unsupported debounce time {} ms", ms))?;
         let mut req = AsusRequest::new(ASUS_CMD_SET_SETTING);
@@ -847,8 +932,7 @@ impl AsusDriver {
     }
 
     async fn get_led_data(&self, io: &mut DeviceIo, led_index: u8) -> Result<AsusLedData> {
-        let mut req = AsusRequest::new(ASUS_CMD_GET_LED_DATA);
-        req.set_param(0, led_index);
+        let req = AsusRequest::get_led_data(led_index);
         let resp = self.query(io, &req).await?;
         Ok(AsusLedData::from_response(&resp))
     }
@@ -861,13 +945,7 @@ impl AsusDriver {
         brightness: u8,
         r: u8, g: u8, b: u8,
     ) -> Result<()> {
-        let mut req = AsusRequest::new(ASUS_CMD_SET_LED);
-        req.set_param(0, index);
-        req.set_param(2, mode);
-        req.set_param(3, brightness);
-        req.set_param(4, r);
-        req.set_param(5, g);
-        req.set_param(6, b);
+        let req = AsusRequest::set_led(index, mode, brightness, r, g, b);
         self.query(io, &req).await?;
         Ok(())
     }
@@ -993,7 +1071,7 @@ impl AsusDriver {
         if response_idx < ASUS_DEBOUNCE_TIMES.len() as u16 {
             profile.debounce = ASUS_DEBOUNCE_TIMES[response_idx as usize] as i32;
         }
-        profile.angle_snapping = snapping as i32;
+        profile.angle_snapping = snapping_from_wire(snapping);
         for res in &mut profile.resolutions {
             let i = res.index as usize;
             res.dpi = self.build_dpi(
@@ -1180,14 +1258,11 @@ impl AsusDriver {
 
         /* ── DPI presets ─────────────────────────────────────────────────── */
         for res in &profile.resolutions {
-            let dpi_val = match res.dpi {
-                Dpi::Unified(v) => v,
-                /* For separate-XY devices the protocol only accepts a single value;
-                 * use X (matches C driver behaviour). */
-                Dpi::Separate { x, .. } => x,
-                Dpi::Unknown => continue,
-            };
-            self.set_dpi(io, res.index as u8, dpi_val).await?;
+            res.validate_dpi().map_err(|e| anyhow::anyhow!("ASUS: resolution {}: {e}", res.index))?;
+            if crate::driver::dpi_to_commit("ASUS", profile.index, res).is_none() {
+                continue;
+            }
+            self.set_dpi(io, res.index as u8, res.dpi).await?;
         }
 
         /* ── LEDs ────────────────────────────────────────────────────────── */
@@ -1269,6 +1344,36 @@ impl DeviceDriver for AsusDriver {
         "asus"
     }
 
+    /* Commit translates buttons, specials and keys; ASUS macros are not supported. */
+    fn action_types(&self) -> &'static [ActionType] {
+        &[
+            ActionType::None,
+            ActionType::Button,
+            ActionType::Special,
+            ActionType::Key,
+        ]
+    }
+
+    fn capabilities(&self) -> super::DriverCapabilities {
+        super::DriverCapabilities {
+            profiles: true,
+            leds: true,
+            ..super::DriverCapabilities::from_action_types(self.action_types())
+        }
+    }
+
+    /* The code the device file (or the built-in table) assigns to the
+     * button's slot is what the firmware ships with.  Joystick slots have
+     * no ratbag action to restore. */
+    fn default_button_mapping(&self, index: u32) -> Option<(ActionType, u32)> {
+        let flat = (*self.button_indices.get(index as usize)?)?;
+        match find_button_by_code(self.button_mapping[flat]?)?.kind {
+            AsusButtonKind::Button(n) => Some((ActionType::Button, n)),
+            AsusButtonKind::Special(n) => Some((ActionType::Special, n)),
+            AsusButtonKind::None | AsusButtonKind::Joystick => None,
+        }
+    }
+
     async fn probe(&mut self, io: &mut DeviceIo) -> Result<()> {
         /* A successful GET_PROFILE_DATA confirms the device is reachable. */
         let req = AsusRequest::new(ASUS_CMD_GET_PROFILE_DATA);
@@ -1280,7 +1385,8 @@ impl DeviceDriver for AsusDriver {
             }
             Err(e) => {
                 /* ASUS_STATUS_ERROR = sleeping/disconnected wireless mouse.
-                 * Register the device on DBus anyway; commit() will retry. */
+                 * Register the device on DBus anyway; the actor probes
+                 * again until it wakes up. */
                 warn!("ASUS: probe query failed (device may be sleeping): {}", e);
                 self.is_ready = false;
                 Ok(())
@@ -1318,28 +1424,31 @@ impl DeviceDriver for AsusDriver {
     }
 
     async fn commit(&mut self, io: &mut DeviceIo, info: &DeviceInfo) -> Result<()> {
+        /* The actor reloads a sleeping device before it lets a commit
+         * through; writing the skeleton defaults would wipe the mouse. */
         if !self.is_ready {
-            /* Device was sleeping at probe time — attempt recovery using a
-             * scratch clone of info (we do not want to modify info here). */
-            warn!("ASUS: device was not ready, attempting reload before commit");
-            let mut scratch = info.clone();
-            match self.load_all_profiles(io, &mut scratch).await {
-                Ok(()) => {
-                    self.is_ready = true;
-                    debug!("ASUS: device recovery succeeded");
-                }
-                Err(e) => {
-                    warn!("ASUS: device recovery failed: {}", e);
-                    bail!("ASUS: device not ready and recovery failed — commit aborted");
-                }
-            }
-            /* Even after successful recovery, abort this commit as the C driver
-             * does: we rolled back instead of committing. */
-            bail!("ASUS: device was not ready; commit aborted after recovery reload");
+            bail!("ASUS: device is not ready — commit aborted");
         }
+        validate_single_axis(info)?;
 
         self.save_all_profiles(io, info).await
     }
+
+    fn is_ready(&self) -> bool {
+        self.is_ready
+    }
+
+    /* ASUS_CMD_SET_PROFILE takes effect immediately.  `commit` restores the
+     * profile that was active when it started, so without this a profile
+     * switch would never reach the hardware. */
+    async fn switch_profile(&mut self, io: &mut DeviceIo, index: u32) -> Result<bool> {
+        if !self.is_ready {
+            return Ok(false);
+        }
+        self.set_profile(io, index).await?;
+        debug!("ASUS: switched to profile {}", index);
+        Ok(true)
+    }
 }
 
 // ──────────────────────────── Unit tests ───────────────────────────────────
@@ -1348,6 +1457,50 @@ impl DeviceDriver for AsusDriver {
 mod tests {
     use super::*;
 
+    /* ── Packet accessors ────────────────────────────────────────────────── */
+
+    #[test]
+    fn test_param_in_range() {
+        let mut req = AsusRequest::new(ASUS_CMD_SET_LED);
+        req.set_param(ASUS_PACKET_SIZE - 3, 0xab);
+        assert_eq!(req.buf[ASUS_PACKET_SIZE - 1], 0xab);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "set_param index 62 out of range")]
+    fn test_set_param_out_of_range_asserts() {
+        let mut req = AsusRequest::new(ASUS_CMD_SET_LED);
+        req.set_param(ASUS_PACKET_SIZE - 2, 0xab);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "result index 62 out of range")]
+    fn test_result_out_of_range_asserts() {
+        AsusResponse::default().result(ASUS_PACKET_SIZE - 2);
+    }
+
+    /* ── Angle snapping ──────────────────────────────────────────────────── */
+
+    #[test]
+    fn test_snapping_only_reads_zero_or_one() {
+        /* Snapping word of a 2-DPI settings reply. */
+        let mut resp = AsusResponse::default();
+        resp.buf[2 + 10] = 0x01;
+        let data = AsusDpi2Data::from_response(&resp);
+        assert_eq!(snapping_from_wire(data.snapping), 1);
+
+        resp.buf[2 + 10] = 0x00;
+        let data = AsusDpi2Data::from_response(&resp);
+        assert_eq!(snapping_from_wire(data.snapping), 0);
+
+        /* Nothing documents other values; they stay unknown. */
+        for raw in [0x0004, 0x0005, 0x0100, 0xffff] {
+            assert_eq!(snapping_from_wire(raw), -1, "{raw:#06x}");
+        }
+    }
+
     /* ── DPI conversion ──────────────────────────────────────────────────── */
 
     #[test]
@@ -1384,6 +1537,27 @@ mod tests {
 
     /* ── Button table lookups ────────────────────────────────────────────── */
 
+    #[test]
+    fn test_default_button_mapping_restores_left_click() {
+        let mut driver = AsusDriver::new();
+        driver.init_from_config(&crate::device_database::DriverConfig::default());
+        assert_eq!(driver.default_button_mapping(0), Some((ActionType::Button, 1)));
+        assert_eq!(
+            driver.default_button_mapping(3),
+            Some((ActionType::Special, SPECIAL_WHEEL_UP))
+        );
+        assert_eq!(driver.default_button_mapping(40), None);
+
+        let mut button = crate::device::ButtonInfo {
+            action_type: ActionType::Key,
+            mapping_value: 30,
+            default_mapping: driver.default_button_mapping(0),
+            ..Default::default()
+        };
+        button.reset_to_default().unwrap();
+        assert_eq!((button.action_type, button.mapping_value), (ActionType::Button, 1));
+    }
+
     #[test]
     fn test_find_button_by_code_left() {
         let e = find_button_by_code(0xf0).expect("left button must be in table");
@@ -1514,6 +1688,39 @@ mod tests {
 
     /* ── Packet construction ─────────────────────────────────────────────── */
 
+    #[test]
+    fn test_separate_leds_write_addresses_match_reads() {
+        /* Packets the C driver sends for the third LED of a
+         * SEPARATE_LEDS device: asus_get_led_data() puts the LED index in
+         * params[0]; asus_set_led() puts it there too, then mode,
+         * brightness and RGB in params[2..7]. */
+        let mut read = [0u8; ASUS_PACKET_SIZE];
+        read[..3].copy_from_slice(&[0x12, 0x03, 0x02]);
+        let mut write = [0u8; ASUS_PACKET_SIZE];
+        write[..9].copy_from_slice(&[0x51, 0x28, 0x02, 0x00, 0x01, 0x04, 0xff, 0x00, 0x80]);
+
+        assert_eq!(AsusRequest::get_led_data(2).buf, read);
+        assert_eq!(AsusRequest::set_led(2, 1, 4, 0xff, 0x00, 0x80).buf, write);
+    }
+
+    #[test]
+    fn test_separate_xy_dpi_needs_equal_axes() {
+        let req = AsusRequest::set_dpi(1, 15);
+        assert_eq!(&req.buf[0..2], &ASUS_CMD_SET_SETTING.to_le_bytes());
+        assert_eq!(&req.buf[2..8], &[1, 0, 15, 0, 0, 0]);
+
+        let with_dpi = |dpi| DeviceInfo {
+            profiles: vec![ProfileInfo {
+                resolutions: vec![crate::device::ResolutionInfo { dpi, ..Default::default() }],
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        assert!(validate_single_axis(&with_dpi(Dpi::Unified(800))).is_ok());
+        assert!(validate_single_axis(&with_dpi(Dpi::Separate { x: 800, y: 800 })).is_ok());
+        assert!(validate_single_axis(&with_dpi(Dpi::Separate { x: 800, y: 1600 })).is_err());
+    }
+
     #[test]
     fn test_asus_request_cmd_le_encoding() {
         /* GET_PROFILE_DATA = 0x0012 → LE bytes [0x12, 0x00] */
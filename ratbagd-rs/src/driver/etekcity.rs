@@ -168,7 +168,7 @@ impl DeviceDriver for EtekcityDriver {
         /* Query the current profile to confirm the device responds. */
         let mut buf = [0u8; 3];
         buf[0] = ETEKCITY_REPORT_ID_PROFILE;
-        io.get_feature_report(&mut buf)
+        io.get_feature_report_min(&mut buf, 3)
             .map_err(anyhow::Error::from)?;
 
         let num_profiles = (ETEKCITY_PROFILE_MAX + 1) as usize;
@@ -5,15 +5,19 @@
 /// 3 LED zones (logo, wheel, tail) plus a DPI LED, and complex macro support.
 ///
 /// # Status
-/// **Stub** — protocol constants and data layout are complete, but
-/// `probe`/`load_profiles`/`commit` are not yet implemented.
+/// Report rate and DPI levels are read and committed.  Buttons, LEDs and
+/// macros are left as they are in the profile report.
 ///
 /// Reference implementation: `src/driver-gskill.c`.
-use anyhow::Result;
+use anyhow::{Context, Result};
 use async_trait::async_trait;
+use tokio::time::{sleep, Duration};
+use tracing::{debug, info, warn};
 
-use crate::device::DeviceInfo;
-use crate::driver::{DeviceDriver, DeviceIo};
+use crate::device::{
+    DeviceInfo, Dpi, ProfileInfo, ResolutionInfo, RATBAG_RESOLUTION_CAP_SEPARATE_XY_RESOLUTION,
+};
+use crate::driver::{CommitTarget, DeviceDriver, DeviceIo, DriverError, PartialCommit};
 
 /* ------------------------------------------------------------------ */
 /* Protocol constants                                                   */
@@ -44,6 +48,24 @@ const GSKILL_REPORT_SIZE_MACRO: usize = 2052;
 /// Byte offset of the checksum in profile/macro reports.
 const GSKILL_CHECKSUM_OFFSET: usize = 3;
 
+/* Profile report layout.  Byte 4 holds the polling rate in its low
+ * nibble; byte 6 the number of DPI levels (low nibble) and the current
+ * level (high nibble), followed by one X/Y pair per level. */
+const GSKILL_PROFILE_OFFSET_RATE: usize = 4;
+const GSKILL_PROFILE_OFFSET_DPI_INFO: usize = 6;
+const GSKILL_PROFILE_OFFSET_DPI_LEVELS: usize = 7;
+
+/* GSKILL_GENERAL_CMD requests: select a profile for the next profile
+ * report read or write, and make the device reload its profile data
+ * from flash after a write. */
+const GSKILL_CMD_SELECT_PROFILE: [u8; 4] = [GSKILL_GENERAL_CMD, 0xc4, 0x0c, 0x00];
+const GSKILL_CMD_RELOAD_PROFILES: [u8; 4] = [GSKILL_GENERAL_CMD, 0xc4, 0x00, 0x00];
+
+/* Status polls after a general command before giving up, and the pause
+ * between them. */
+const GSKILL_CMD_MAX_POLLS: usize = 10;
+const GSKILL_CMD_POLL_DELAY: Duration = Duration::from_millis(20);
+
 /* Command status codes returned by the device */
 const GSKILL_CMD_SUCCESS: u8 = 0xb0;
 const GSKILL_CMD_IN_PROGRESS: u8 = 0xb1;
@@ -168,6 +190,47 @@ struct GskillData {
     active_profile: u8,
 }
 
+/* ------------------------------------------------------------------ */
+/* Profile report fields                                                */
+/* ------------------------------------------------------------------ */
+
+/// Report rate in Hz stored in a profile report.
+fn report_rate(report: &[u8]) -> u32 {
+    GSKILL_MAX_POLLING_RATE / (u32::from(report[GSKILL_PROFILE_OFFSET_RATE] & 0x0f) + 1)
+}
+
+/// Store `hz` in a profile report.  The device divides 1000 Hz by the
+/// stored value plus one, so only 125, 250, 500 and 1000 are exact.
+fn set_report_rate(report: &mut [u8], hz: u32) {
+    let divider = (GSKILL_MAX_POLLING_RATE / hz.clamp(125, GSKILL_MAX_POLLING_RATE)) - 1;
+    let byte = &mut report[GSKILL_PROFILE_OFFSET_RATE];
+    *byte = (*byte & 0xf0) | divider as u8;
+}
+
+/// The DPI levels of a profile report as (X, Y) and the current level.
+fn dpi_levels(report: &[u8]) -> (Vec<(u32, u32)>, usize) {
+    let info = report[GSKILL_PROFILE_OFFSET_DPI_INFO];
+    let count = usize::from(info & 0x0f).clamp(1, GSKILL_NUM_DPI);
+    let levels = (0..count)
+        .map(|i| {
+            let off = GSKILL_PROFILE_OFFSET_DPI_LEVELS + i * 2;
+            raw_to_dpi(RawDpiLevel { x: report[off], y: report[off + 1] })
+        })
+        .collect();
+    (levels, usize::from(info >> 4).min(count - 1))
+}
+
+/// Store DPI levels and the current level in a profile report.
+fn set_dpi_levels(report: &mut [u8], levels: &[RawDpiLevel], current: usize) {
+    let count = levels.len().min(GSKILL_NUM_DPI);
+    for (i, level) in levels.iter().take(count).enumerate() {
+        let off = GSKILL_PROFILE_OFFSET_DPI_LEVELS + i * 2;
+        report[off] = level.x;
+        report[off + 1] = level.y;
+    }
+    report[GSKILL_PROFILE_OFFSET_DPI_INFO] = ((current.min(count - 1) as u8) << 4) | count as u8;
+}
+
 /* ------------------------------------------------------------------ */
 /* Driver                                                               */
 /* ------------------------------------------------------------------ */
@@ -180,6 +243,133 @@ impl GskillDriver {
     pub fn new() -> Self {
         Self { data: None }
     }
+
+    /* Send a GSKILL_GENERAL_CMD request and wait until the device
+     * reports that it finished. */
+    async fn general_cmd(io: &mut DeviceIo, request: &[u8]) -> Result<()> {
+        let mut cmd = [0u8; GSKILL_REPORT_SIZE_CMD];
+        cmd[..request.len()].copy_from_slice(request);
+        io.set_feature_report(&cmd)?;
+
+        for _ in 0..GSKILL_CMD_MAX_POLLS {
+            sleep(GSKILL_CMD_POLL_DELAY).await;
+            let mut status = [0u8; GSKILL_REPORT_SIZE_CMD];
+            status[0] = GSKILL_GENERAL_CMD;
+            io.get_feature_report_min(&mut status, 2)?;
+            match status[1] {
+                GSKILL_CMD_SUCCESS | GSKILL_CMD_IDLE => return Ok(()),
+                GSKILL_CMD_IN_PROGRESS => continue,
+                GSKILL_CMD_FAILURE => anyhow::bail!("G.Skill: command {:02x?} failed", request),
+                other => anyhow::bail!("G.Skill: unexpected command status {other:#04x}"),
+            }
+        }
+        anyhow::bail!("G.Skill: command {:02x?} did not finish", request)
+    }
+
+    /* Make profile `index` the target of the next profile report read
+     * (`write` false) or write. */
+    async fn select_profile(io: &mut DeviceIo, index: usize, write: bool) -> Result<()> {
+        let mut request = [0u8; 7];
+        request[..4].copy_from_slice(&GSKILL_CMD_SELECT_PROFILE);
+        request[4] = 0x01;
+        request[5] = index as u8;
+        request[6] = u8::from(write);
+        Self::general_cmd(io, &request).await
+    }
+
+    async fn read_profile(
+        io: &mut DeviceIo,
+        index: usize,
+    ) -> Result<Box<[u8; GSKILL_REPORT_SIZE_PROFILE]>> {
+        Self::select_profile(io, index, false).await?;
+        let mut report = Box::new([0u8; GSKILL_REPORT_SIZE_PROFILE]);
+        report[0] = GSKILL_GET_SET_PROFILE;
+        io.request_checked(&mut report[..], GSKILL_REPORT_SIZE_PROFILE, 3, |buf| {
+            let computed = compute_checksum(buf);
+            let received = buf[GSKILL_CHECKSUM_OFFSET];
+            if computed == received {
+                Ok(())
+            } else {
+                Err(DriverError::ChecksumMismatch {
+                    computed: computed.into(),
+                    received: received.into(),
+                })
+            }
+        })
+        .await?;
+        Ok(report)
+    }
+
+    async fn write_profile(io: &mut DeviceIo, index: usize, report: &mut [u8]) -> Result<()> {
+        report[GSKILL_CHECKSUM_OFFSET] = compute_checksum(report);
+        Self::select_profile(io, index, true).await?;
+        io.set_feature_report(report)?;
+        Ok(())
+    }
+}
+
+/// Build the profile `index` from its raw report.
+fn parse_profile(index: usize, report: &[u8], active: bool) -> ProfileInfo {
+    let dpi_list: Vec<u32> = (GSKILL_MIN_DPI..=GSKILL_MAX_DPI)
+        .step_by(GSKILL_DPI_UNIT as usize)
+        .collect();
+    let (levels, current) = dpi_levels(report);
+    ProfileInfo {
+        index: index as u32,
+        name: format!("Profile {index}"),
+        is_active: active,
+        is_enabled: true,
+        report_rate: report_rate(report),
+        report_rates: vec![125, 250, 500, 1000],
+        angle_snapping: -1,
+        debounce: -1,
+        resolutions: levels
+            .into_iter()
+            .enumerate()
+            .map(|(i, (x, y))| ResolutionInfo {
+                index: i as u32,
+                is_active: i == current,
+                is_default: i == current,
+                is_disabled: false,
+                dpi: if x == y { Dpi::Unified(x) } else { Dpi::Separate { x, y } },
+                dpi_list: dpi_list.clone(),
+                capabilities: vec![RATBAG_RESOLUTION_CAP_SEPARATE_XY_RESOLUTION],
+            })
+            .collect(),
+        ..Default::default()
+    }
+}
+
+/// Patch the report rate and DPI levels of `profile` into its raw report.
+fn patch_profile(report: &mut [u8], profile: &ProfileInfo) -> Result<()> {
+    set_report_rate(report, profile.report_rate);
+
+    let (old, _) = dpi_levels(report);
+    let mut levels = Vec::with_capacity(profile.resolutions.len());
+    for res in profile.resolutions.iter().take(GSKILL_NUM_DPI) {
+        let (x, y) = match crate::driver::dpi_to_commit("G.Skill", profile.index, res) {
+            Some(dpi) => dpi,
+            None => old.get(res.index as usize).copied().unwrap_or((800, 800)),
+        };
+        let (Some(raw_x), Some(raw_y)) = (dpi_to_raw(x), dpi_to_raw(y)) else {
+            anyhow::bail!(
+                "resolution {}: {x}x{y} DPI is not a multiple of {GSKILL_DPI_UNIT} in \
+                 {GSKILL_MIN_DPI}..={GSKILL_MAX_DPI}",
+                res.index
+            );
+        };
+        levels.push(RawDpiLevel { x: raw_x, y: raw_y });
+    }
+    if levels.is_empty() {
+        return Ok(());
+    }
+    let current = profile
+        .resolutions
+        .iter()
+        .position(|r| r.is_active)
+        .unwrap_or(0);
+    set_dpi_levels(report, &levels, current);
+    Ok(())
 }
 
 #[async_trait]
@@ -192,7 +382,7 @@ impl DeviceDriver for GskillDriver {
         /* Query current profile number to confirm device presence. */
         let mut cmd = [0u8; GSKILL_REPORT_SIZE_CMD];
         cmd[0] = GSKILL_GET_CURRENT_PROFILE_NUM;
-        io.get_feature_report(&mut cmd)
+        io.get_feature_report_min(&mut cmd, 3)
             .map_err(anyhow::Error::from)?;
 
         let status = cmd[1];
@@ -204,19 +394,74 @@ impl DeviceDriver for GskillDriver {
             profiles: Default::default(),
             active_profile: cmd[2] & 0x0f,
         });
-
-        // TODO: read all profiles using GSKILL_GET_SET_PROFILE.
-        anyhow::bail!("G.Skill driver: load_profiles not yet implemented in the Rust port");
+        Ok(())
     }
 
-    async fn load_profiles(&mut self, _io: &mut DeviceIo, _info: &mut DeviceInfo) -> Result<()> {
-        // TODO: parse cached profile reports and fill info.profiles.
-        anyhow::bail!("G.Skill driver: load_profiles not yet implemented in the Rust port");
+    async fn load_profiles(&mut self, io: &mut DeviceIo, info: &mut DeviceInfo) -> Result<()> {
+        let data = self
+            .data
+            .as_mut()
+            .context("G.Skill: load_profiles called before probe")?;
+
+        info.profiles.clear();
+        for index in 0..GSKILL_PROFILE_MAX {
+            let report = Self::read_profile(io, index)
+                .await
+                .with_context(|| format!("G.Skill: reading profile {index}"))?;
+            let active = index == usize::from(data.active_profile);
+            info.profiles.push(parse_profile(index, &report[..], active));
+            data.profiles[index] = Some(report);
+        }
+
+        info!("G.Skill: loaded {} profiles", info.profiles.len());
+        Ok(())
     }
 
-    async fn commit(&mut self, _io: &mut DeviceIo, _info: &DeviceInfo) -> Result<()> {
-        // TODO: write dirty profiles back using GSKILL_GET_SET_PROFILE.
-        anyhow::bail!("G.Skill driver: commit not yet implemented in the Rust port");
+    async fn commit(&mut self, io: &mut DeviceIo, info: &DeviceInfo) -> Result<()> {
+        let data = self
+            .data
+            .as_mut()
+            .context("G.Skill: commit called before probe")?;
+
+        let mut failures = Vec::new();
+        let mut any_written = false;
+        for profile in info.profiles.iter().filter(|p| p.is_dirty) {
+            let index = profile.index as usize;
+            let Some(cached) = data.profiles.get(index).and_then(Option::as_ref) else {
+                warn!("G.Skill: profile {index} was never read, not writing it");
+                continue;
+            };
+            let mut report = cached.clone();
+            let result = match patch_profile(&mut report[..], profile) {
+                Ok(()) => Self::write_profile(io, index, &mut report[..]).await,
+                Err(e) => Err(e),
+            };
+            match result {
+                Ok(()) => {
+                    debug!("G.Skill: committed profile {index}");
+                    data.profiles[index] = Some(report);
+                    any_written = true;
+                }
+                Err(e) => {
+                    warn!("G.Skill: failed to commit profile {index}: {e:#}");
+                    failures.push((CommitTarget::Profile(profile.index), format!("{e:#}")));
+                }
+            }
+        }
+
+        /* The device keeps using the profiles it loaded from flash until
+         * it is told to reload them. */
+        if any_written
+            && let Err(e) = Self::general_cmd(io, &GSKILL_CMD_RELOAD_PROFILES).await
+        {
+            warn!("G.Skill: failed to reload profiles: {e:#}");
+            failures.push((CommitTarget::Device, format!("{e:#}")));
+        }
+
+        if !failures.is_empty() {
+            return Err(PartialCommit(failures).into());
+        }
+        Ok(())
     }
 }
 
@@ -227,14 +472,12 @@ impl DeviceDriver for GskillDriver {
 /// Convert a raw DPI pair to actual DPI values (X, Y).
 ///
 /// Raw = `dpi / GSKILL_DPI_UNIT - 1`.
-#[allow(dead_code)]
 pub fn raw_to_dpi(raw: RawDpiLevel) -> (u32, u32) {
     let to_dpi = |r: u8| -> u32 { (u32::from(r) + 1) * GSKILL_DPI_UNIT };
     (to_dpi(raw.x), to_dpi(raw.y))
 }
 
 /// Encode a DPI value to the 1-byte hardware representation.
-#[allow(dead_code)]
 pub fn dpi_to_raw(dpi: u32) -> Option<u8> {
     if dpi < GSKILL_MIN_DPI || dpi > GSKILL_MAX_DPI || dpi % GSKILL_DPI_UNIT != 0 {
         return None;
@@ -245,7 +488,57 @@ pub fn dpi_to_raw(dpi: u32) -> Option<u8> {
 /// Compute the one-byte XOR checksum expected at `GSKILL_CHECKSUM_OFFSET`.
 ///
 /// The checksum covers bytes 4..end of the report.
-#[allow(dead_code)]
 pub fn compute_checksum(report: &[u8]) -> u8 {
     report[4..].iter().fold(0u8, |acc, &b| acc ^ b)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dpi_scaling_round_trips() {
+        assert_eq!(dpi_to_raw(100), Some(1));
+        assert_eq!(dpi_to_raw(800), Some(15));
+        assert_eq!(dpi_to_raw(8200), Some(163));
+        assert_eq!(raw_to_dpi(RawDpiLevel { x: 15, y: 163 }), (800, 8200));
+        for dpi in (GSKILL_MIN_DPI..=GSKILL_MAX_DPI).step_by(GSKILL_DPI_UNIT as usize) {
+            let raw = dpi_to_raw(dpi).unwrap();
+            assert_eq!(raw_to_dpi(RawDpiLevel { x: raw, y: raw }), (dpi, dpi));
+        }
+    }
+
+    #[test]
+    fn test_dpi_scaling_rejects_unrepresentable_values() {
+        assert_eq!(dpi_to_raw(50), None);
+        assert_eq!(dpi_to_raw(8250), None);
+        assert_eq!(dpi_to_raw(825), None);
+    }
+
+    #[test]
+    fn test_profile_report_round_trip() {
+        let mut report = [0u8; GSKILL_REPORT_SIZE_PROFILE];
+        report[GSKILL_PROFILE_OFFSET_RATE] = 0x11; /* 500 Hz, high nibble kept */
+        report[GSKILL_PROFILE_OFFSET_DPI_INFO] = 0x12; /* 2 levels, current 1 */
+        report[GSKILL_PROFILE_OFFSET_DPI_LEVELS..GSKILL_PROFILE_OFFSET_DPI_LEVELS + 4]
+            .copy_from_slice(&[15, 15, 31, 63]);
+
+        let mut profile = parse_profile(2, &report, true);
+        assert_eq!(profile.report_rate, 500);
+        let dpis: Vec<Dpi> = profile.resolutions.iter().map(|r| r.dpi).collect();
+        assert_eq!(dpis, [Dpi::Unified(800), Dpi::Separate { x: 1600, y: 3200 }]);
+        assert!(profile.resolutions[1].is_active);
+
+        profile.report_rate = 1000;
+        profile.resolutions[0].dpi = Dpi::Unified(1200);
+        profile.resolutions[0].is_active = true;
+        profile.resolutions[1].is_active = false;
+        patch_profile(&mut report, &profile).unwrap();
+        assert_eq!(report[GSKILL_PROFILE_OFFSET_RATE], 0x10);
+        assert_eq!(report[GSKILL_PROFILE_OFFSET_DPI_INFO], 0x02);
+        assert_eq!(report[GSKILL_PROFILE_OFFSET_DPI_LEVELS], 23);
+
+        profile.resolutions[0].dpi = Dpi::Unified(1225);
+        assert!(patch_profile(&mut report, &profile).is_err());
+    }
+}
@@ -23,9 +23,16 @@ pub const DEVICE_IDX_RECEIVER: u8 = 0x01;
 
 /* HID++ 2.0 feature pages */
 pub const PAGE_DEVICE_NAME: u16 = 0x0005;
+pub const PAGE_BATTERY_STATUS: u16 = 0x1000;
+pub const PAGE_BATTERY_VOLTAGE: u16 = 0x1001;
+pub const PAGE_UNIFIED_BATTERY: u16 = 0x1004;
 pub const PAGE_SPECIAL_KEYS_BUTTONS: u16 = 0x1B04;
+pub const PAGE_SMART_SHIFT: u16 = 0x2110;
+pub const PAGE_SMART_SHIFT_ENHANCED: u16 = 0x2111;
 pub const PAGE_ADJUSTABLE_DPI: u16 = 0x2201;
+pub const PAGE_BRIGHTNESS_CONTROL: u16 = 0x8040;
 pub const PAGE_ADJUSTABLE_REPORT_RATE: u16 = 0x8060;
+pub const PAGE_EXTENDED_REPORT_RATE: u16 = 0x8061;
 pub const PAGE_COLOR_LED_EFFECTS: u16 = 0x8070;
 pub const PAGE_RGB_EFFECTS: u16 = 0x8071;
 pub const PAGE_ONBOARD_PROFILES: u16 = 0x8100;
@@ -67,6 +74,10 @@ pub const LED_HW_MODE_COLOR_WAVE: u8 = 0x04;
 pub const LED_HW_MODE_STARLIGHT: u8 = 0x05;
 pub const LED_HW_MODE_BREATHING: u8 = 0x0A;
 
+/* ColorWave direction byte (payload byte 9). */
+pub const LED_WAVE_DIRECTION_CLOCKWISE: u8 = 0x00;
+pub const LED_WAVE_DIRECTION_COUNTER_CLOCKWISE: u8 = 0x01;
+
 /* Size of the internal LED payload as defined in C struct hidpp20_internal_led. */
 pub const LED_PAYLOAD_SIZE: usize = 11;
 
@@ -76,7 +87,7 @@ pub const LED_PAYLOAD_SIZE: usize = 11;
 /* Off:       [0x00, 0..10 zero]                                              */
 /* Solid:     [0x01, R, G, B, 0x00, 0..6 zero]                               */
 /* Cycle:     [0x03, 0..5 zero, period_hi, period_lo, brightness, 0..2 zero]  */
-/* ColorWave: [0x04, 0..5 zero, period_hi, period_lo, brightness, 0..2 zero]  */
+/* ColorWave: [0x04, 0..5 zero, period_hi, period_lo, brightness, dir, 0]     */
 /* Starlight: [0x05, sky_R, sky_G, sky_B, star_R, star_G, star_B, 0..4 zero]  */
 /* Breathing: [0x0A, R, G, B, period_hi, period_lo, waveform, brightness, 0..3]*/
 pub fn build_led_payload(led: &crate::device::LedInfo) -> [u8; LED_PAYLOAD_SIZE] {
@@ -85,7 +96,7 @@ pub fn build_led_payload(led: &crate::device::LedInfo) -> [u8; LED_PAYLOAD_SIZE]
     let mut payload = [0u8; LED_PAYLOAD_SIZE];
     let rgb = led.color.to_rgb();
     let period = (led.effect_duration as u16).to_be_bytes();
-    let brightness = (led.brightness.min(255) * 100 / 255) as u8;
+    let brightness = brightness_to_percent(led.brightness);
 
     match led.mode {
         LedMode::Off => {
@@ -108,6 +119,11 @@ pub fn build_led_payload(led: &crate::device::LedInfo) -> [u8; LED_PAYLOAD_SIZE]
             payload[6] = period[0];
             payload[7] = period[1];
             payload[8] = brightness;
+            /* Byte 9 is documented as zero; only devices that declare
+             * the direction get it written. */
+            if led.has_direction {
+                payload[9] = wave_direction_byte(led.effect_direction);
+            }
         }
         LedMode::Starlight => {
             let star = led.secondary_color.to_rgb();
@@ -150,6 +166,76 @@ pub fn build_led_payload(led: &crate::device::LedInfo) -> [u8; LED_PAYLOAD_SIZE]
     payload
 }
 
+/* Encode a LedDirection as the ColorWave direction byte. */
+pub fn wave_direction_byte(direction: crate::device::LedDirection) -> u8 {
+    match direction {
+        crate::device::LedDirection::Clockwise => LED_WAVE_DIRECTION_CLOCKWISE,
+        crate::device::LedDirection::CounterClockwise => LED_WAVE_DIRECTION_COUNTER_CLOCKWISE,
+    }
+}
+
+/* Decode the ColorWave direction byte; unknown values read as clockwise. */
+pub fn wave_direction_from_byte(byte: u8) -> crate::device::LedDirection {
+    match byte {
+        LED_WAVE_DIRECTION_COUNTER_CLOCKWISE => crate::device::LedDirection::CounterClockwise,
+        _ => crate::device::LedDirection::Clockwise,
+    }
+}
+
+/* Scale a DBus brightness (0-255) to the percent the effect bytes carry. */
+/* Both directions round to nearest, so a percent read from the device is  */
+/* written back unchanged.                                                 */
+pub fn brightness_to_percent(brightness: u32) -> u8 {
+    ((brightness.min(255) * 100 + 127) / 255) as u8
+}
+
+/* Scale an effect-byte percent back to a DBus brightness (0-255). */
+pub fn brightness_from_percent(percent: u8) -> u32 {
+    (u32::from(percent.min(100)) * 255 + 50) / 100
+}
+
+/* How a pending LED state should reach the hardware, given the state that  */
+/* was last written (or read back) for the same zone.                       */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LedWritePlan {
+    /* Nothing changed since the last write. */
+    Skip,
+    /* Only brightness changed; a brightness-only command avoids restarting */
+    /* the running effect.                                                  */
+    BrightnessOnly,
+    /* The effect itself changed; rewrite the full zone effect. */
+    Full,
+}
+
+/* Decide between a full zone-effect write and a brightness-only write.      */
+/*                                                                           */
+/* `has_brightness_control` reports whether the device exposes a separate    */
+/* brightness command (0x8040); without it every change needs a full write.  */
+pub fn plan_led_write(
+    previous: Option<&crate::device::LedInfo>,
+    led: &crate::device::LedInfo,
+    has_brightness_control: bool,
+) -> LedWritePlan {
+    let Some(previous) = previous else {
+        return LedWritePlan::Full;
+    };
+
+    /* Compare the effects with brightness factored out. */
+    let mut same_brightness = previous.clone();
+    same_brightness.brightness = led.brightness;
+    if previous.mode != led.mode || build_led_payload(&same_brightness) != build_led_payload(led) {
+        return LedWritePlan::Full;
+    }
+
+    if previous.brightness == led.brightness {
+        LedWritePlan::Skip
+    } else if has_brightness_control {
+        LedWritePlan::BrightnessOnly
+    } else {
+        LedWritePlan::Full
+    }
+}
+
 /* Feature 0x8100 Button Data */
 pub const BUTTON_TYPE_MACRO: u8 = 0x00;
 pub const BUTTON_TYPE_HID: u8 = 0x80;
@@ -437,6 +523,26 @@ mod tests {
         }
     }
 
+    #[test]
+    fn brightness_percent_roundtrip() {
+        /* What the device stores survives a read and write-back. */
+        for percent in 0..=100 {
+            assert_eq!(brightness_to_percent(brightness_from_percent(percent)), percent);
+        }
+        /* A DBus value moves by at most one step of the percent scale. */
+        for brightness in 0..=255 {
+            let back = brightness_from_percent(brightness_to_percent(brightness));
+            assert!(
+                back.abs_diff(brightness) <= 1,
+                "brightness {brightness} → {}% → {back}",
+                brightness_to_percent(brightness)
+            );
+        }
+        assert_eq!(brightness_from_percent(100), 255);
+        assert_eq!(brightness_to_percent(255), 100);
+        assert_eq!(brightness_to_percent(1000), 100);
+    }
+
     #[test]
     fn build_hidpp20_request_encoding() {
         let req = build_hidpp20_request(0x00, 0x01, 0x02, 0x0A, &[0x11, 0x22]);
@@ -485,19 +591,15 @@ mod tests {
     /* LED payload serialization tests                                    */
     /* ------------------------------------------------------------------ */
 
-    use crate::device::{Color, LedInfo, LedMode};
+    use crate::device::{Color, LedDirection, LedInfo, LedMode};
 
     fn make_led(mode: LedMode) -> LedInfo {
         LedInfo {
-            index: 0,
             mode,
             modes: vec![LedMode::Off],
-            color: Color::default(),
-            secondary_color: Color::default(),
-            tertiary_color: Color::default(),
             color_depth: 1,
-            effect_duration: 0,
             brightness: 255,
+            ..Default::default()
         }
     }
 
@@ -542,8 +644,25 @@ mod tests {
         assert_eq!(p[0], LED_HW_MODE_COLOR_WAVE);
         assert_eq!(p[6], 0x0B);
         assert_eq!(p[7], 0xB8);
-        /* brightness 127 → 127*100/255 = 49 */
-        assert_eq!(p[8], 49);
+        /* brightness 127 → 49.8%, rounded to 50 */
+        assert_eq!(p[8], 50);
+    }
+
+    #[test]
+    fn led_payload_color_wave_direction() {
+        let mut led = make_led(LedMode::ColorWave);
+        led.effect_direction = LedDirection::CounterClockwise;
+        assert_eq!(build_led_payload(&led)[9], 0);
+
+        led.has_direction = true;
+        let p = build_led_payload(&led);
+        assert_eq!(p[9], LED_WAVE_DIRECTION_COUNTER_CLOCKWISE);
+        assert_eq!(wave_direction_from_byte(p[9]), LedDirection::CounterClockwise);
+
+        /* Static modes never carry a direction. */
+        let mut solid = make_led(LedMode::Solid);
+        solid.effect_direction = LedDirection::CounterClockwise;
+        assert_eq!(build_led_payload(&solid)[9], 0);
     }
 
     #[test]
@@ -567,6 +686,7 @@ mod tests {
     fn led_payload_breathing() {
         let mut led = make_led(LedMode::Breathing);
         led.color = Color { red: 0, green: 255, blue: 0 };
+        led.secondary_color = Color { red: 0, green: 0, blue: 255 };
         led.effect_duration = 2000;
         led.brightness = 200;
         let p = build_led_payload(&led);
@@ -581,6 +701,8 @@ mod tests {
         assert_eq!(p[6], 0x00);
         /* brightness 200 → 200*100/255 = 78 */
         assert_eq!(p[7], 78);
+        /* bytes 8-10 are reserved, even with a secondary color set */
+        assert_eq!(p[8..11], [0, 0, 0]);
     }
 
     #[test]
@@ -624,6 +746,39 @@ mod tests {
         assert_eq!(req.len(), 7);
     }
 
+    #[test]
+    fn led_write_brightness_only_change_is_minimal() {
+        let mut previous = make_led(LedMode::Breathing);
+        previous.effect_duration = 3000;
+        let mut led = previous.clone();
+        led.brightness = 128;
+        assert_eq!(plan_led_write(Some(&previous), &led, true), LedWritePlan::BrightnessOnly);
+    }
+
+    #[test]
+    fn led_write_brightness_without_control_feature_is_full() {
+        let previous = make_led(LedMode::Cycle);
+        let mut led = previous.clone();
+        led.brightness = 128;
+        assert_eq!(plan_led_write(Some(&previous), &led, false), LedWritePlan::Full);
+    }
+
+    #[test]
+    fn led_write_effect_change_is_full() {
+        let previous = make_led(LedMode::Solid);
+        let mut led = previous.clone();
+        led.color = Color { red: 255, green: 0, blue: 0 };
+        led.brightness = 128;
+        assert_eq!(plan_led_write(Some(&previous), &led, true), LedWritePlan::Full);
+        assert_eq!(plan_led_write(None, &led, true), LedWritePlan::Full);
+    }
+
+    #[test]
+    fn led_write_unchanged_is_skipped() {
+        let led = make_led(LedMode::Solid);
+        assert_eq!(plan_led_write(Some(&led), &led, true), LedWritePlan::Skip);
+    }
+
     /* ------------------------------------------------------------------ */
     /* Opcode alignment with C driver (compile-time sanity)               */
     /* ------------------------------------------------------------------ */
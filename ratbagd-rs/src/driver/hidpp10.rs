@@ -10,8 +10,10 @@ use tracing::{debug, info, warn};
 
 use crate::device::{
     ActionType, Color, DeviceInfo, Dpi, LedMode, ProfileInfo, RgbColor,
+    RATBAG_PROFILE_CAP_ASCII_NAME, RATBAG_PROFILE_CAP_GLOBAL_REPORT_RATE,
     special_action,
 };
+use crate::device_database::DriverConfig;
 use crate::driver::DeviceIo;
 
 use super::hidpp::{self, HidppReport, DEVICE_IDX_CORDED, DEVICE_IDX_RECEIVER};
@@ -43,6 +45,17 @@ const SUB_ID_SET_REGISTER: u8 = 0x80;
 const SUB_ID_GET_LONG_REGISTER: u8 = 0x83;
 const SUB_ID_SET_LONG_REGISTER: u8 = 0x82;
 
+/* Error report sub-ID and the error a HID++ 1.0 device answers a HID++ 2.0
+ * request with. */
+const SUB_ID_ERROR: u8 = 0x8F;
+const ERR_INVALID_SUBID: u8 = 0x01;
+
+/* Ping: the HID++ 2.0 root feature's getProtocolVersion, with a software
+ * ID and a marker byte that the reply echoes back. */
+const PING_FUNCTION: u8 = 0x10;
+const PING_SW_ID: u8 = 0x04;
+const PING_DATA: u8 = 0x5A;
+
 /* HOT (Host-Over-Transport) protocol constants */
 const CMD_HOT_CONTROL: u8 = 0xA1;
 const HOT_NOTIFICATION: u8 = 0x50;
@@ -138,7 +151,6 @@ pub enum Hidpp10ProfileType {
 }
 
 impl Hidpp10ProfileType {
-    #[allow(dead_code)]
     pub fn from_str(s: &str) -> Self {
         match s.to_ascii_uppercase().as_str() {
             "G500" => Self::G500,
@@ -478,7 +490,6 @@ fn hidpp10_code_from_special(special: u32) -> u8 {
 
 /* Build a DPI mapping table from a list of DPI values (from .device file).
  * Each entry maps raw_value = (0x80 + index) to the corresponding DPI. */
-#[allow(dead_code)]
 fn build_dpi_table_from_list(entries: &[u32]) -> Vec<DpiMapping> {
     entries.iter().enumerate().map(|(i, &dpi)| DpiMapping {
         raw_value: (i as u8).wrapping_add(0x80),
@@ -487,24 +498,32 @@ fn build_dpi_table_from_list(entries: &[u32]) -> Vec<DpiMapping> {
 }
 
 /* Build a DPI mapping table from a range specification (min, max, step).
- * Raw value 0 is reserved (DPI 0); values 1..=raw_max map linearly with
- * rounding to the nearest multiple of 25. */
-#[allow(dead_code)]
-fn build_dpi_table_from_range(min: u32, max: u32, step: u32) -> Vec<DpiMapping> {
-    if step == 0 || max <= min {
+ * The step is the DPI of one raw sensor unit, which is fractional on most
+ * models (23.53 on the G500).  Raw value 0 is reserved (DPI 0); values
+ * 1..=raw_max map linearly with rounding to the nearest multiple of 25. */
+fn build_dpi_table_from_range(min: u32, max: u32, step: f64) -> Vec<DpiMapping> {
+    if step.is_nan() || step <= 0.0 || max <= min {
         return Vec::new();
     }
-    let raw_max = (max - min) / step;
-    let mut table = Vec::with_capacity(raw_max as usize + 1);
+    let raw_max = (f64::from(max - min) / step).floor().min(f64::from(u8::MAX)) as u8;
+    let mut table = Vec::with_capacity(usize::from(raw_max) + 1);
     table.push(DpiMapping { raw_value: 0, dpi: 0 });
     for i in 1..=raw_max {
-        let dpi_exact = min + step * i;
-        let dpi_rounded = ((dpi_exact + 12) / 25) * 25;
-        table.push(DpiMapping { raw_value: i as u8, dpi: dpi_rounded });
+        let dpi_exact = f64::from(min) + step * f64::from(i);
+        let dpi_rounded = (dpi_exact / 25.0).round() as u32 * 25;
+        table.push(DpiMapping { raw_value: i, dpi: dpi_rounded });
     }
     table
 }
 
+/* The DPI values a table can reach, ascending and without the reserved 0. */
+fn dpi_table_values(table: &[DpiMapping]) -> Vec<u32> {
+    let mut values: Vec<u32> = table.iter().map(|m| m.dpi).filter(|&dpi| dpi > 0).collect();
+    values.sort_unstable();
+    values.dedup();
+    values
+}
+
 /* Get the maximum DPI value from the table. Returns 0 if the table is empty. */
 #[allow(dead_code)]
 fn dpi_table_get_max(table: &[DpiMapping]) -> u32 {
@@ -808,6 +827,9 @@ pub struct Hidpp10Driver {
     device_index: u8,
     version: ProtocolVersion,
     profile_type: Hidpp10ProfileType,
+    /* Register 0x63 is a short register holding one raw byte (G9) rather
+     * than a long one with separate X/Y values. */
+    short_resolution: bool,
     dpi_table: Vec<DpiMapping>,
     #[allow(dead_code)]
     dpi_table_is_range: bool,
@@ -821,6 +843,7 @@ impl Hidpp10Driver {
             device_index: DEVICE_IDX_RECEIVER,
             version: ProtocolVersion::default(),
             profile_type: Hidpp10ProfileType::Unknown,
+            short_resolution: false,
             dpi_table: Vec::new(),
             dpi_table_is_range: false,
             onboard_profiles: Vec::new(),
@@ -828,28 +851,56 @@ impl Hidpp10Driver {
         }
     }
 
+    /* Take the DPI table and the resolution register layout from the
+     * device file.  `ProfileType=` only selects the register layout here:
+     * the onboard flash profiles stay off, because the driver cannot yet
+     * write DeviceInfo changes back into them and switching to a stale
+     * flash profile would undo the register writes. */
+    fn configure(&mut self, config: &DriverConfig) {
+        self.short_resolution = config
+            .profile_type
+            .as_deref()
+            .is_some_and(|t| Hidpp10ProfileType::from_str(t) == Hidpp10ProfileType::G9);
+        if !config.dpi_list.is_empty() {
+            self.dpi_table = build_dpi_table_from_list(&config.dpi_list);
+            self.dpi_table_is_range = false;
+        } else if let Some(range) = &config.dpi_range {
+            self.dpi_table = build_dpi_table_from_range(range.min, range.max, range.exact_step);
+            self.dpi_table_is_range = true;
+        }
+    }
+
     /* ---- Register I/O primitives --------------------------------- */
 
-    async fn try_probe_index(
-        &self,
-        io: &mut DeviceIo,
-        idx: u8,
-    ) -> Option<[u8; 3]> {
-        let request = hidpp::build_short_report(
-            idx, SUB_ID_GET_REGISTER, REG_HIDPP_NOTIFICATIONS,
-            [0x00, 0x00, 0x00],
-        );
-        io.request(&request, 20, 2, move |buf| {
-            let report = HidppReport::parse(buf)?;
-            if report.is_error() { return None; }
-            match report {
-                HidppReport::Short { device_index, sub_id, address, params }
-                    if device_index == idx
-                        && sub_id == SUB_ID_GET_REGISTER
-                        && address == REG_HIDPP_NOTIFICATIONS => Some(params),
-                _ => None,
+    /* Ping the device at `idx` and return its protocol version.  A HID++
+     * 1.0 device rejects the HID++ 2.0 ping with ERR_INVALID_SUBID, which
+     * still proves it is there and speaks HID++; only that reply counts.
+     * A device that answers the ping with a version speaks HID++ 2.0 and
+     * belongs to the HID++ 2.0 driver; its reply is recognised so the
+     * probe does not wait out the timeout, and rejected. */
+    async fn ping(&self, io: &mut DeviceIo, idx: u8) -> Option<ProtocolVersion> {
+        let address = PING_FUNCTION | PING_SW_ID;
+        let request = hidpp::build_short_report(idx, 0x00, address, [0x00, 0x00, PING_DATA]);
+        let answered = io.request(&request, 20, 2, move |buf| match HidppReport::parse(buf)? {
+            HidppReport::Short { device_index, sub_id: SUB_ID_ERROR, address: 0x00, params }
+                if device_index == idx
+                    && params[0] == address
+                    && params[1] == ERR_INVALID_SUBID =>
+            {
+                Some(None)
+            }
+            HidppReport::Long { device_index, sub_id: 0x00, address: a, params }
+                if device_index == idx && a == address && params[2] == PING_DATA =>
+            {
+                Some(Some((params[0], params[1])))
             }
-        }).await.ok()
+            _ => None,
+        }).await.ok()?;
+        if let Some((major, minor)) = answered {
+            debug!("HID++ 1.0 probe at index 0x{idx:02X}: device speaks HID++ {major}.{minor}");
+            return None;
+        }
+        Some(ProtocolVersion { major: 1, minor: 0 })
     }
 
     async fn short_register_request(
@@ -1478,31 +1529,32 @@ impl Hidpp10Driver {
 
     /* ---- Resolution (register 0x63) ------------------------------- */
 
+    /* The register holds the sensor's current DPI, so it lands in the
+     * active resolution (the first one if none is marked).  A raw value
+     * outside the table reads as Unknown rather than 0 DPI. */
     async fn read_resolution(&self, io: &mut DeviceIo, profile: &mut ProfileInfo) -> Result<()> {
-        match self.profile_type {
-            Hidpp10ProfileType::G9 => {
-                /* G9 uses the short register for current resolution. */
-                let params = self.get_register(io, REG_CURRENT_RESOLUTION, [0, 0, 0]).await?;
-                let raw = u16::from_le_bytes([params[0], params[1]]);
-                let dpi_val = dpi_from_raw(&self.dpi_table, raw as u8);
-                if let Some(res) = profile.resolutions.first_mut() {
-                    res.dpi = Dpi::Unified(dpi_val);
-                }
-            }
-            _ => {
-                /* All other devices use the long register with separate X/Y. */
-                let payload = self.get_long_register(io, REG_CURRENT_RESOLUTION).await?;
-                let rp = Hidpp10ResolutionLongPayload::from_bytes(&payload);
-                let x_dpi = dpi_from_raw(&self.dpi_table, rp.xres() as u8);
-                let y_dpi = dpi_from_raw(&self.dpi_table, rp.yres() as u8);
-                if let Some(res) = profile.resolutions.first_mut() {
-                    if x_dpi == y_dpi {
-                        res.dpi = Dpi::Unified(x_dpi);
-                    } else {
-                        res.dpi = Dpi::Separate { x: x_dpi, y: y_dpi };
-                    }
-                }
+        let dpi = if self.short_resolution {
+            /* G9 uses the short register for current resolution. */
+            let params = self.get_register(io, REG_CURRENT_RESOLUTION, [0, 0, 0]).await?;
+            let dpi_val = dpi_from_raw(&self.dpi_table, params[0]);
+            if dpi_val == 0 { Dpi::Unknown } else { Dpi::Unified(dpi_val) }
+        } else {
+            /* All other devices use the long register with separate X/Y. */
+            let payload = self.get_long_register(io, REG_CURRENT_RESOLUTION).await?;
+            let rp = Hidpp10ResolutionLongPayload::from_bytes(&payload);
+            let x_dpi = dpi_from_raw(&self.dpi_table, rp.xres() as u8);
+            let y_dpi = dpi_from_raw(&self.dpi_table, rp.yres() as u8);
+            if x_dpi == 0 || y_dpi == 0 {
+                Dpi::Unknown
+            } else if x_dpi == y_dpi {
+                Dpi::Unified(x_dpi)
+            } else {
+                Dpi::Separate { x: x_dpi, y: y_dpi }
             }
+        };
+        let active = profile.resolutions.iter().position(|r| r.is_active).unwrap_or(0);
+        if let Some(res) = profile.resolutions.get_mut(active) {
+            res.dpi = dpi;
         }
         Ok(())
     }
@@ -1511,24 +1563,20 @@ impl Hidpp10Driver {
         let Some(res) = profile.resolutions.iter().find(|r| r.is_active) else {
             return Ok(());
         };
-        let (x_dpi, y_dpi) = match res.dpi {
-            Dpi::Unified(val) => (val, val),
-            Dpi::Separate { x, y } => (x, y),
-            Dpi::Unknown => return Ok(()),
+        let Some((x_dpi, y_dpi)) = crate::driver::dpi_to_commit("HID++ 1.0", profile.index, res)
+        else {
+            return Ok(());
         };
-        match self.profile_type {
-            Hidpp10ProfileType::G9 => {
-                let raw = raw_from_dpi(&self.dpi_table, x_dpi);
-                self.set_register(io, REG_CURRENT_RESOLUTION, [raw, 0, 0]).await?;
-            }
-            _ => {
-                let x_raw = raw_from_dpi(&self.dpi_table, x_dpi);
-                let y_raw = raw_from_dpi(&self.dpi_table, y_dpi);
-                let mut bytes = [0u8; 16];
-                bytes[0..2].copy_from_slice(&u16::from(x_raw).to_le_bytes());
-                bytes[2..4].copy_from_slice(&u16::from(y_raw).to_le_bytes());
-                self.set_long_register(io, REG_CURRENT_RESOLUTION, bytes).await?;
-            }
+        if self.short_resolution {
+            let raw = raw_from_dpi(&self.dpi_table, x_dpi);
+            self.set_register(io, REG_CURRENT_RESOLUTION, [raw, 0, 0]).await?;
+        } else {
+            let x_raw = raw_from_dpi(&self.dpi_table, x_dpi);
+            let y_raw = raw_from_dpi(&self.dpi_table, y_dpi);
+            let mut bytes = [0u8; 16];
+            bytes[0..2].copy_from_slice(&u16::from(x_raw).to_le_bytes());
+            bytes[2..4].copy_from_slice(&u16::from(y_raw).to_le_bytes());
+            self.set_long_register(io, REG_CURRENT_RESOLUTION, bytes).await?;
         }
         debug!("HID++ 1.0: committed DPI = {x_dpi}×{y_dpi}");
         Ok(())
@@ -2000,15 +2048,25 @@ impl super::DeviceDriver for Hidpp10Driver {
         "Logitech HID++ 1.0"
     }
 
+    fn capabilities(&self) -> super::DriverCapabilities {
+        super::DriverCapabilities {
+            profiles: true,
+            leds: true,
+            separate_xy: true,
+            ..super::DriverCapabilities::from_action_types(self.action_types())
+        }
+    }
+
+    fn stores_profile_names(&self) -> bool {
+        true
+    }
+
     async fn probe(&mut self, io: &mut DeviceIo) -> Result<()> {
         const PROBE_INDICES: &[u8] = &[DEVICE_IDX_RECEIVER, DEVICE_IDX_CORDED];
         for &idx in PROBE_INDICES {
-            if let Some(params) = self.try_probe_index(io, idx).await {
+            if let Some(version) = self.ping(io, idx).await {
                 self.device_index = idx;
-                self.version = ProtocolVersion {
-                    major: params[0],
-                    minor: params[1],
-                };
+                self.version = version;
                 info!(
                     "HID++ 1.0 device detected at index 0x{idx:02X} (protocol {}.{})",
                     self.version.major, self.version.minor
@@ -2018,12 +2076,14 @@ impl super::DeviceDriver for Hidpp10Driver {
             debug!("HID++ 1.0 probe at index 0x{idx:02X}: no response");
         }
         anyhow::bail!(
-            "HID++ 1.0 protocol version probe failed (tried indices: {:02X?})",
+            "HID++ 1.0 ping failed (tried indices: {:02X?})",
             PROBE_INDICES
         );
     }
 
     async fn load_profiles(&mut self, io: &mut DeviceIo, info: &mut DeviceInfo) -> Result<()> {
+        self.configure(&info.driver_config);
+
         /* Read onboard profiles from flash if the device supports them. */
         if self.profile_type != Hidpp10ProfileType::Unknown {
             self.read_profile_directory(io).await?;
@@ -2052,9 +2112,31 @@ impl super::DeviceDriver for Hidpp10Driver {
             self.apply_onboard_profiles_to_device_info(info, active_idx);
         }
 
+        /* Without onboard profiles the refresh-rate register is the only
+         * copy of the rate, so it is shared by every profile. */
+        let global_rate = self.profile_type == Hidpp10ProfileType::Unknown;
+
+        let dpi_list = dpi_table_values(&self.dpi_table);
+
         /* Supplement with live register values. */
         for profile in &mut info.profiles {
             profile.is_active = profile.index == active_idx;
+            if !dpi_list.is_empty() {
+                for res in &mut profile.resolutions {
+                    res.dpi_list.clone_from(&dpi_list);
+                }
+            }
+            if global_rate
+                && !profile.capabilities.contains(&RATBAG_PROFILE_CAP_GLOBAL_REPORT_RATE)
+            {
+                profile.capabilities.push(RATBAG_PROFILE_CAP_GLOBAL_REPORT_RATE);
+            }
+            /* Onboard names are UCS-2, but only the low byte is kept. */
+            if self.profile_type != Hidpp10ProfileType::Unknown
+                && !profile.capabilities.contains(&RATBAG_PROFILE_CAP_ASCII_NAME)
+            {
+                profile.capabilities.push(RATBAG_PROFILE_CAP_ASCII_NAME);
+            }
             if let Err(e) = self.read_resolution(io, profile).await {
                 warn!("Failed to read DPI for profile {}: {}", profile.index, e);
             }
@@ -2105,3 +2187,83 @@ impl super::DeviceDriver for Hidpp10Driver {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::ResolutionInfo;
+    use crate::test_support::{exchanges, ScriptedIo};
+
+    /* Each 7-byte request is answered with a reply read back as 20 bytes. */
+    async fn scripted_io(name: &str, replies: &[[u8; 7]]) -> ScriptedIo {
+        ScriptedIo::new(&format!("hidpp10-{name}"), &exchanges(7, 20, replies)).await
+    }
+
+    #[test]
+    fn test_dpi_table_from_fractional_range() {
+        let table = build_dpi_table_from_range(0, 5700, 23.53);
+        assert_eq!(table.len(), 243);
+        assert_eq!(dpi_from_raw(&table, 0x55), 2000);
+        assert_eq!(dpi_from_raw(&table, 242), 5700);
+        assert_eq!(raw_from_dpi(&table, 2000), 0x55);
+        assert_eq!(dpi_table_values(&table).first(), Some(&25));
+    }
+
+    #[tokio::test]
+    async fn test_probe_accepts_hidpp10_ping_error() {
+        let mut node = scripted_io(
+            "ping",
+            &[[0x10, DEVICE_IDX_RECEIVER, SUB_ID_ERROR, 0x00, PING_FUNCTION | PING_SW_ID, ERR_INVALID_SUBID, 0x00]],
+        ).await;
+        let mut driver = Hidpp10Driver::new();
+        super::super::DeviceDriver::probe(&mut driver, &mut node.io).await.unwrap();
+        assert_eq!(driver.device_index, DEVICE_IDX_RECEIVER);
+        assert_eq!((driver.version.major, driver.version.minor), (1, 0));
+    }
+
+    #[tokio::test]
+    async fn test_probe_rejects_hidpp20_ping_reply() {
+        let mut reply = [0u8; 20];
+        reply[..7].copy_from_slice(&[0x11, DEVICE_IDX_RECEIVER, 0x00, PING_FUNCTION | PING_SW_ID, 4, 2, PING_DATA]);
+        let mut node = ScriptedIo::new("hidpp10-ping-20", &exchanges(7, 20, &[reply])).await;
+        let driver = Hidpp10Driver::new();
+        assert!(driver.ping(&mut node.io, DEVICE_IDX_RECEIVER).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_g9_resolution_uses_short_register() {
+        let mut driver = Hidpp10Driver::new();
+        driver.configure(&DriverConfig {
+            profile_type: Some("G9".to_string()),
+            dpi_list: vec![400, 800, 1600, 3200],
+            ..Default::default()
+        });
+        assert_eq!(driver.profile_type, Hidpp10ProfileType::Unknown, "flash profiles stay off");
+
+        let mut node = scripted_io(
+            "g9",
+            &[
+                [0x10, DEVICE_IDX_RECEIVER, SUB_ID_GET_REGISTER, REG_CURRENT_RESOLUTION, 0x81, 0, 0],
+                [0x10, DEVICE_IDX_RECEIVER, SUB_ID_SET_REGISTER, REG_CURRENT_RESOLUTION, 0, 0, 0],
+            ],
+        ).await;
+        let mut profile = ProfileInfo {
+            resolutions: vec![
+                ResolutionInfo { index: 0, ..Default::default() },
+                ResolutionInfo { index: 1, is_active: true, ..Default::default() },
+            ],
+            ..Default::default()
+        };
+        driver.read_resolution(&mut node.io, &mut profile).await.unwrap();
+        assert!(matches!(profile.resolutions[0].dpi, Dpi::Unknown));
+        assert!(matches!(profile.resolutions[1].dpi, Dpi::Unified(800)));
+
+        profile.resolutions[1].dpi = Dpi::Unified(1600);
+        driver.write_resolution(&mut node.io, &profile).await.unwrap();
+        let written = node.written();
+        assert_eq!(
+            written[27..34],
+            [0x10, DEVICE_IDX_RECEIVER, SUB_ID_SET_REGISTER, REG_CURRENT_RESOLUTION, 0x82, 0, 0]
+        );
+    }
+}
@@ -4,22 +4,26 @@
 /* Logitech gaming mice. Each capability is exposed as a numbered "feature" */
 /* that must be discovered at probe time via the Root feature (0x0000). */
 
+use std::collections::{BTreeSet, HashMap, HashSet};
+
 use anyhow::{Context, Result};
 use async_trait::async_trait;
-use tokio::time::{sleep, Duration};
+use tokio::time::{sleep, timeout_at, Duration, Instant};
 use tracing::{debug, info, trace, warn};
 
-use crate::device::{Color, DeviceInfo, Dpi, LedMode, ProfileInfo, RgbColor};
-use crate::driver::DeviceIo;
+use crate::device::{ActionType, BatteryInfo, Color, DeviceInfo, Dpi, LedInfo, LedMode, ProfileInfo, RgbColor};
+use crate::driver::{CommitTarget, DeviceIo, PartialCommit};
 
 use super::hidpp::{
-    self, HidppReport, DEVICE_IDX_CORDED, DEVICE_IDX_RECEIVER,
+    self, HidppReport, LedWritePlan, DEVICE_IDX_CORDED, DEVICE_IDX_RECEIVER,
     BUTTON_TYPE_DISABLED, BUTTON_TYPE_HID, BUTTON_TYPE_MACRO, BUTTON_TYPE_SPECIAL,
     BUTTON_SUBTYPE_CONSUMER, BUTTON_SUBTYPE_KEYBOARD, BUTTON_SUBTYPE_MOUSE,
     LED_HW_MODE_BREATHING, LED_HW_MODE_COLOR_WAVE,
     LED_HW_MODE_CYCLE, LED_HW_MODE_FIXED, LED_HW_MODE_OFF, LED_HW_MODE_STARLIGHT,
-    PAGE_ADJUSTABLE_DPI, PAGE_ADJUSTABLE_REPORT_RATE,
-    PAGE_COLOR_LED_EFFECTS, PAGE_ONBOARD_PROFILES, PAGE_RGB_EFFECTS,
+    PAGE_ADJUSTABLE_DPI, PAGE_ADJUSTABLE_REPORT_RATE, PAGE_BATTERY_STATUS,
+    PAGE_BATTERY_VOLTAGE, PAGE_BRIGHTNESS_CONTROL, PAGE_COLOR_LED_EFFECTS,
+    PAGE_EXTENDED_REPORT_RATE, PAGE_ONBOARD_PROFILES,
+    PAGE_RGB_EFFECTS, PAGE_SMART_SHIFT, PAGE_SMART_SHIFT_ENHANCED, PAGE_UNIFIED_BATTERY,
     PAGE_SPECIAL_KEYS_BUTTONS, ROOT_FEATURE_INDEX, ROOT_FN_GET_FEATURE,
     ROOT_FN_GET_PROTOCOL_VERSION,
 };
@@ -28,6 +32,7 @@ use super::hidpp::{
 const SW_ID: u8 = 0x04;
 
 /* Adjustable DPI (0x2201) function IDs */
+const DPI_FN_GET_SENSOR_COUNT: u8 = 0x00;
 const DPI_FN_GET_SENSOR_DPI_LIST: u8 = 0x01;
 const DPI_FN_GET_SENSOR_DPI: u8 = 0x02;
 const DPI_FN_SET_SENSOR_DPI: u8 = 0x03;
@@ -35,6 +40,100 @@ const DPI_FN_SET_SENSOR_DPI: u8 = 0x03;
 /* Adjustable Report Rate (0x8060) function IDs */
 const RATE_FN_GET_REPORT_RATE_LIST: u8 = 0x00;
 const RATE_FN_GET_REPORT_RATE: u8 = 0x01;
+const RATE_FN_SET_REPORT_RATE: u8 = 0x02;
+
+/* Extended Adjustable Report Rate (0x8061) function IDs */
+const EXT_RATE_FN_GET_REPORT_RATE_LIST: u8 = 0x01;
+const EXT_RATE_FN_GET_REPORT_RATE: u8 = 0x02;
+const EXT_RATE_FN_SET_REPORT_RATE: u8 = 0x03;
+
+/* 0x8061 rate codes: the rate in Hz of each code, which is also the
+ * code's bit in the feature's 16-bit rate list. */
+const EXTENDED_REPORT_RATES: [u32; 7] = [125, 250, 500, 1000, 2000, 4000, 8000];
+
+/* Bits of the 0x8060 rate list and the rate each stands for, slowest
+ * first.  Bit N means a report interval of N+1 ms; only the intervals
+ * that give one of the standard rates are advertised, the 3, 5, 6 and
+ * 7 ms ones (333, 200, 166 and 142 Hz) are left out. */
+const REPORT_RATE_BITS: [(u8, u32); 4] = [(7, 125), (3, 250), (1, 500), (0, 1000)];
+
+/* Supported report rates in Hz, ascending, from a 0x8060 rate bitmap. */
+fn report_rates_from_bitmap(bitmap: u8) -> Vec<u32> {
+    REPORT_RATE_BITS
+        .iter()
+        .filter(|(bit, _)| bitmap & (1 << bit) != 0)
+        .map(|&(_, hz)| hz)
+        .collect()
+}
+
+/* How a report-rate feature encodes a rate on the wire. */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RateFeature {
+    /* 0x8060: the report interval in whole milliseconds, so nothing
+     * faster than 1000 Hz. */
+    Interval,
+    /* 0x8061: a code from EXTENDED_REPORT_RATES, down to 125 us. */
+    Extended,
+}
+
+impl RateFeature {
+    /* Get-list, get and set function IDs. */
+    fn functions(self) -> (u8, u8, u8) {
+        match self {
+            RateFeature::Interval => {
+                (RATE_FN_GET_REPORT_RATE_LIST, RATE_FN_GET_REPORT_RATE, RATE_FN_SET_REPORT_RATE)
+            }
+            RateFeature::Extended => (
+                EXT_RATE_FN_GET_REPORT_RATE_LIST,
+                EXT_RATE_FN_GET_REPORT_RATE,
+                EXT_RATE_FN_SET_REPORT_RATE,
+            ),
+        }
+    }
+
+    /* Supported rates in Hz, ascending, from a get-list response. */
+    fn rates_from_list(self, data: &[u8]) -> Vec<u32> {
+        match self {
+            RateFeature::Interval => report_rates_from_bitmap(data[0]),
+            RateFeature::Extended => {
+                let bitmap = u16::from_be_bytes([data[0], data[1]]);
+                EXTENDED_REPORT_RATES
+                    .iter()
+                    .enumerate()
+                    .filter(|&(code, _)| bitmap & (1 << code) != 0)
+                    .map(|(_, &hz)| hz)
+                    .collect()
+            }
+        }
+    }
+
+    /* The rate in Hz a get response's value stands for. */
+    fn rate_from_value(self, value: u8) -> Option<u32> {
+        match self {
+            RateFeature::Interval => (value > 0).then(|| 1000 / u32::from(value)),
+            RateFeature::Extended => EXTENDED_REPORT_RATES.get(usize::from(value)).copied(),
+        }
+    }
+
+    /* The value to send for `hz`, or `None` when the feature cannot
+     * express it (e.g. 2000 Hz and up through 0x8060, whose interval
+     * would round down to 0 ms). */
+    fn value_for_rate(self, hz: u32) -> Option<u8> {
+        match self {
+            RateFeature::Interval => {
+                1000u32.checked_div(hz).and_then(|ms| u8::try_from(ms).ok()).filter(|&ms| ms > 0)
+            }
+            RateFeature::Extended => EXTENDED_REPORT_RATES
+                .iter()
+                .position(|&rate| rate == hz)
+                .map(|code| code as u8),
+        }
+    }
+}
+
+/* Brightness Control (0x8040) function IDs */
+const BRIGHTNESS_FN_GET_INFO: u8 = 0x00;
+const BRIGHTNESS_FN_SET_BRIGHTNESS: u8 = 0x02;
 
 /* Color LED Effects (0x8070) function IDs.
  * C defines: GET_INFO=0x00, GET_ZONE_INFO=0x10, GET_ZONE_EFFECT_INFO=0x20,
@@ -44,6 +143,26 @@ const RATE_FN_GET_REPORT_RATE: u8 = 0x01;
 const LED_FN_GET_ZONE_EFFECT: u8 = 0x0E;
 const LED_FN_SET_ZONE_EFFECT: u8 = 0x03;
 
+/* RGB Effects (0x8071) function IDs.  getInfo takes a cluster index and
+ * an effect index; with both 0xFF it describes the device, and byte 2 of
+ * the reply is its number of LED clusters, as Solaar reads it.  The
+ * feature has no call that reads back the running effect. */
+const RGB_EFFECTS_FN_GET_INFO: u8 = 0x00;
+const RGB_EFFECTS_FN_SET_MULTI_LED_RGB_CLUSTER_PATTERN: u8 = 0x02;
+
+/* SmartShift (0x2110) function IDs.  The enhanced variant (0x2111) adds a
+ * capabilities query at fn 0 and shifts get/set up by one. */
+const SMART_SHIFT_FN_GET_STATUS: u8 = 0x00;
+const SMART_SHIFT_FN_SET_STATUS: u8 = 0x01;
+const SMART_SHIFT_ENHANCED_FN_GET_STATUS: u8 = 0x01;
+const SMART_SHIFT_ENHANCED_FN_SET_STATUS: u8 = 0x02;
+
+/* Battery Status (0x1000), Battery Voltage (0x1001) and Unified Battery
+ * (0x1004) function IDs */
+const BATTERY_FN_GET_LEVEL_STATUS: u8 = 0x00;
+const BATTERY_VOLTAGE_FN_GET_INFO: u8 = 0x00;
+const UNIFIED_BATTERY_FN_GET_STATUS: u8 = 0x01;
+
 /* Onboard Profiles (0x8100) function IDs.
  * C defines: GET_PROFILES_DESCR=0x00, SET_ONBOARD_MODE=0x10,
  * GET_ONBOARD_MODE=0x20, SET_CURRENT_PROFILE=0x30,
@@ -82,6 +201,39 @@ const EEPROM_LED_OFFSET: usize = 208;
 const EEPROM_LED_SIZE: usize = 11;
 const EEPROM_LED_COUNT: usize = 2;
 
+/* Onboard macro opcodes (C HIDPP20_MACRO_*).  A macro is a run of these
+ * instructions ending in MACRO_END; MACRO_JUMP carries on at another
+ * (page, offset).  Key events are [op, modifier mask, HID usage], a
+ * delay is [op, BE u16 ms] and a jump is [op, page, 0, offset]. */
+const MACRO_NOOP: u8 = 0x01;
+/* Instructions a DBus macro has no entry for: waiting for or repeating
+ * until the button release, and modifier, media key, mouse button and
+ * consumer control events.  A macro using them is left as it is. */
+const MACRO_WAIT_FOR_BUTTON_RELEASE: u8 = 0x02;
+const MACRO_REPEAT: u8 = 0x04;
+const MACRO_MODIFIERS: u8 = 0x45;
+const MACRO_CONSUMER_CONTROL: u8 = 0x4B;
+const MACRO_DELAY: u8 = 0x40;
+const MACRO_KEY_PRESS: u8 = 0x43;
+const MACRO_KEY_RELEASE: u8 = 0x44;
+const MACRO_JUMP: u8 = 0x60;
+const MACRO_END: u8 = 0xFF;
+
+/* Bounds on reading a macro that never reaches its MACRO_END. */
+const MAX_MACRO_EVENTS: usize = 256;
+const MAX_MACRO_JUMPS: usize = 8;
+
+/* Second half of a DBus macro entry `(value, kind)`, as ratbagctl's
+ * `keycode:direction` events: a key release or press of HID usage
+ * `value`, or a wait of `value` ms. */
+const MACRO_ENTRY_RELEASE: u32 = 0;
+const MACRO_ENTRY_PRESS: u32 = 1;
+const MACRO_ENTRY_WAIT: u32 = 2;
+
+/* HID usage of Left Control, the first of the eight modifier keys; a
+ * key event's modifier mask holds them in this order. */
+const HID_USAGE_LEFT_CONTROL: u32 = 0xE0;
+
 /* A feature page → runtime index mapping for a known set of capabilities. */
 #[derive(Debug, Default)]
 struct FeatureMap {
@@ -91,6 +243,13 @@ struct FeatureMap {
     color_led_effects: Option<u8>,
     rgb_effects: Option<u8>,
     report_rate: Option<u8>,
+    extended_report_rate: Option<u8>,
+    brightness_control: Option<u8>,
+    smart_shift: Option<u8>,
+    smart_shift_enhanced: Option<u8>,
+    battery_status: Option<u8>,
+    battery_voltage: Option<u8>,
+    unified_battery: Option<u8>,
 }
 
 impl FeatureMap {
@@ -103,9 +262,55 @@ impl FeatureMap {
             PAGE_COLOR_LED_EFFECTS => self.color_led_effects = Some(index),
             PAGE_RGB_EFFECTS => self.rgb_effects = Some(index),
             PAGE_ADJUSTABLE_REPORT_RATE => self.report_rate = Some(index),
+            PAGE_EXTENDED_REPORT_RATE => self.extended_report_rate = Some(index),
+            PAGE_BRIGHTNESS_CONTROL => self.brightness_control = Some(index),
+            PAGE_SMART_SHIFT => self.smart_shift = Some(index),
+            PAGE_SMART_SHIFT_ENHANCED => self.smart_shift_enhanced = Some(index),
+            PAGE_BATTERY_STATUS => self.battery_status = Some(index),
+            PAGE_BATTERY_VOLTAGE => self.battery_voltage = Some(index),
+            PAGE_UNIFIED_BATTERY => self.unified_battery = Some(index),
             _ => {}
         }
     }
+
+    /* SmartShift feature index with its get/set function IDs, preferring
+     * the enhanced variant when a device reports both. */
+    fn smart_shift(&self) -> Option<(u8, u8, u8)> {
+        if let Some(idx) = self.smart_shift_enhanced {
+            Some((idx, SMART_SHIFT_ENHANCED_FN_GET_STATUS, SMART_SHIFT_ENHANCED_FN_SET_STATUS))
+        } else {
+            self.smart_shift
+                .map(|idx| (idx, SMART_SHIFT_FN_GET_STATUS, SMART_SHIFT_FN_SET_STATUS))
+        }
+    }
+
+    /* Report-rate feature index and its encoding, preferring the
+     * extended variant when a device reports both: only it can set rates
+     * above 1000 Hz. */
+    fn report_rate(&self) -> Option<(u8, RateFeature)> {
+        if let Some(idx) = self.extended_report_rate {
+            Some((idx, RateFeature::Extended))
+        } else {
+            self.report_rate.map(|idx| (idx, RateFeature::Interval))
+        }
+    }
+
+    /* LED modes the device can be set to.  TriColor is written through
+     * 0x8071 RGB Effects, so it is only offered when that feature exists. */
+    fn led_modes(&self) -> Vec<LedMode> {
+        let mut modes = vec![
+            LedMode::Off,
+            LedMode::Solid,
+            LedMode::Cycle,
+            LedMode::ColorWave,
+            LedMode::Starlight,
+            LedMode::Breathing,
+        ];
+        if self.rgb_effects.is_some() {
+            modes.push(LedMode::TriColor);
+        }
+        modes
+    }
 }
 
 
@@ -150,6 +355,39 @@ impl Hidpp20ButtonBinding {
         }
     }
 
+    /* The DBus mapping value of this binding for the action type that
+     * `to_action` reports. */
+    pub fn mapping_value(self) -> u32 {
+        let raw_id = u16::from_be_bytes(self.control_id_or_macro_id);
+        match (self.button_type, self.subtype) {
+            /* EEPROM mouse buttons are stored as a big-endian bit mask.
+             * ffs(mask) gives the 1-based button ordinal. */
+            (BUTTON_TYPE_HID, BUTTON_SUBTYPE_MOUSE) => {
+                if raw_id > 0 { u32::from(raw_id.trailing_zeros()) + 1 } else { 0 }
+            }
+            /* A special binding keeps its opcode in the second byte, where
+             * the other types keep their subtype. */
+            (BUTTON_TYPE_SPECIAL, special) => hidpp20_raw_to_special(special),
+            _ => u32::from(raw_id),
+        }
+    }
+
+    /* A macro binding pointing at the macro at `offset` of sector `page`. */
+    pub fn macro_at(page: u8, offset: u8) -> Self {
+        Self {
+            button_type: BUTTON_TYPE_MACRO,
+            subtype: page,
+            control_id_or_macro_id: [0, offset],
+        }
+    }
+
+    /* The (page, offset) a macro binding points at. */
+    pub fn macro_address(self) -> Option<(u8, u8)> {
+        (self.button_type == BUTTON_TYPE_MACRO).then_some((self.subtype, self.control_id_or_macro_id[1]))
+    }
+
+    /* The binding of every action but Macro, whose binding points at
+     * where the commit placed the macro (see `macro_at`). */
     pub fn from_action(action: crate::device::ActionType, mapping_value: u32) -> Self {
         use crate::device::ActionType;
         let mut button_type = BUTTON_TYPE_DISABLED;
@@ -157,10 +395,6 @@ impl Hidpp20ButtonBinding {
         let mut control_id = 0u16;
 
         match action {
-            ActionType::Macro => {
-                button_type = BUTTON_TYPE_MACRO;
-                control_id = mapping_value as u16;
-            }
             ActionType::Button => {
                 button_type = BUTTON_TYPE_HID;
                 subtype = BUTTON_SUBTYPE_MOUSE;
@@ -184,7 +418,7 @@ impl Hidpp20ButtonBinding {
             }
             ActionType::Special => {
                 button_type = BUTTON_TYPE_SPECIAL;
-                control_id = hidpp20_special_to_raw(mapping_value) as u16;
+                subtype = hidpp20_special_to_raw(mapping_value);
             }
             _ => {}
         }
@@ -197,53 +431,62 @@ impl Hidpp20ButtonBinding {
     }
 }
 
-/* ---------------------------------------------------------------------- */
-/* HID++ 2.0 special-action translation tables                            */
-/*                                                                        */
-/* The hardware stores small raw opcodes (0x01–0x0b) in the button        */
-/* binding for BUTTON_TYPE_SPECIAL.  DBus clients (e.g. Piper) expect the */
-/* canonical ratbag_button_action_special enum values (base = 1 << 30).   */
-/* These two helpers mirror the C hidpp20_profiles_specials[] table.       */
-/* ---------------------------------------------------------------------- */
-
-/* Convert a raw HID++ 2.0 special opcode (0x00–0x0b) read from the
- * device into the canonical special_action constant for DBus exposure. */
-fn hidpp20_raw_to_special(raw: u8) -> u32 {
+/* HID++ 2.0 special actions: the hardware stores a small opcode in the
+ * binding of a BUTTON_TYPE_SPECIAL button, DBus clients (e.g. Piper) expect
+ * the canonical special_action values.  Mirrors the C
+ * hidpp20_profiles_specials[] table; opcode 0x00 is unassigned. */
+const HIDPP20_SPECIALS: [(u8, u32); 11] = {
     use crate::device::special_action as sa;
-    match raw {
-        0x01 => sa::WHEEL_LEFT,
-        0x02 => sa::WHEEL_RIGHT,
-        0x03 => sa::RESOLUTION_UP,
-        0x04 => sa::RESOLUTION_DOWN,
-        0x05 => sa::RESOLUTION_CYCLE_UP,
-        0x06 => sa::RESOLUTION_DEFAULT,
-        0x07 => sa::RESOLUTION_ALTERNATE,
-        0x08 => sa::PROFILE_UP,
-        0x09 => sa::PROFILE_DOWN,
-        0x0a => sa::PROFILE_CYCLE_UP,
-        0x0b => sa::SECOND_MODE,
-        _    => sa::UNKNOWN,
-    }
+    [
+        (0x01, sa::WHEEL_LEFT),
+        (0x02, sa::WHEEL_RIGHT),
+        (0x03, sa::RESOLUTION_UP),
+        (0x04, sa::RESOLUTION_DOWN),
+        (0x05, sa::RESOLUTION_CYCLE_UP),
+        (0x06, sa::RESOLUTION_DEFAULT),
+        (0x07, sa::RESOLUTION_ALTERNATE),
+        (0x08, sa::PROFILE_UP),
+        (0x09, sa::PROFILE_DOWN),
+        (0x0a, sa::PROFILE_CYCLE_UP),
+        (0x0b, sa::SECOND_MODE),
+    ]
+};
+
+/* Convert a raw special opcode read from the device into the canonical
+ * special_action constant for DBus exposure. */
+fn hidpp20_raw_to_special(raw: u8) -> u32 {
+    HIDPP20_SPECIALS
+        .iter()
+        .find(|&&(code, _)| code == raw)
+        .map_or(crate::device::special_action::UNKNOWN, |&(_, special)| special)
 }
 
-/* Convert a canonical special_action constant back to the raw HID++ 2.0
- * opcode that the hardware expects when writing a button binding. */
+/* Convert a canonical special_action constant back to the raw opcode the
+ * hardware expects, 0x00 for specials the firmware has no opcode for. */
 fn hidpp20_special_to_raw(special: u32) -> u8 {
-    use crate::device::special_action as sa;
-    match special {
-        sa::WHEEL_LEFT            => 0x01,
-        sa::WHEEL_RIGHT           => 0x02,
-        sa::RESOLUTION_UP         => 0x03,
-        sa::RESOLUTION_DOWN       => 0x04,
-        sa::RESOLUTION_CYCLE_UP   => 0x05,
-        sa::RESOLUTION_DEFAULT    => 0x06,
-        sa::RESOLUTION_ALTERNATE  => 0x07,
-        sa::PROFILE_UP            => 0x08,
-        sa::PROFILE_DOWN          => 0x09,
-        sa::PROFILE_CYCLE_UP      => 0x0a,
-        sa::SECOND_MODE           => 0x0b,
-        _                         => 0x00,
-    }
+    HIDPP20_SPECIALS
+        .iter()
+        .find(|&&(_, s)| s == special)
+        .map_or(0x00, |&(code, _)| code)
+}
+
+/* What feature 0x2201 reports for one sensor. */
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct SensorDpi {
+    index: u8,
+    dpi_list: Vec<u32>,
+    current: u32,
+    default: u32,
+}
+
+/* The sensor whose DPI the resolutions show: the first one with a DPI
+ * list.  Like the C driver this is normally sensor 0; a secondary sensor
+ * without adjustable DPI must not hide it. */
+fn primary_sensor(sensors: &[SensorDpi]) -> Option<&SensorDpi> {
+    sensors
+        .iter()
+        .find(|s| !s.dpi_list.is_empty())
+        .or_else(|| sensors.first())
 }
 
 /* Parse HID++ 2.0 DPI sensor list entries (big-endian u16 pairs).
@@ -296,12 +539,114 @@ fn parse_dpi_list(list_bytes: &[u8]) -> Vec<u32> {
     dpi_list
 }
 
+/* Features 0x2110/0x2111: SmartShift ratchet control.
+ * Both variants share the status layout:
+ *   [0] wheel mode       – 1 = free spin, 2 = ratchet
+ *   [1] auto-disengage   – threshold at which the ratchet releases into
+ *                          free spin; 1..=254, 255 = never release
+ *   [2] default / torque – auto-disengage default (0x2110) or tunable
+ *                          torque (0x2111); not managed by ratbag
+ * In a set request a zero byte leaves that field unchanged. */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Hidpp20SmartShift {
+    pub wheel_mode: u8,
+    pub threshold: u8,
+}
+
+impl Hidpp20SmartShift {
+    pub fn from_bytes(buf: &[u8]) -> Self {
+        Self { wheel_mode: buf[0], threshold: buf[1] }
+    }
+
+    /* Parameters for a set request that changes only the threshold. */
+    pub fn threshold_params(threshold: u8) -> [u8; 3] {
+        [0x00, threshold, 0x00]
+    }
+}
+
+/* Feature 0x1000 getBatteryLevelStatus reply:
+ *   [0] discharge level in percent
+ *   [1] next reported level
+ *   [2] status – 0 discharging, 1 recharging, 2 almost full, 3 full,
+ *                4 slow recharge, 5+ battery or charging error */
+pub fn parse_battery_level_status(buf: &[u8]) -> BatteryInfo {
+    BatteryInfo {
+        level: buf[0].min(100),
+        charging: matches!(buf[2], 1 | 2 | 4),
+    }
+}
+
+/* Discharge curve of the single-cell Li-ion batteries in Logitech mice:
+ * (millivolts, percent), highest first.  Values in between are
+ * interpolated linearly. */
+const BATTERY_VOLTAGE_CURVE: &[(u16, u8)] = &[
+    (4186, 100),
+    (4067, 90),
+    (3989, 80),
+    (3922, 70),
+    (3859, 60),
+    (3811, 50),
+    (3778, 40),
+    (3751, 30),
+    (3717, 20),
+    (3671, 10),
+    (3646, 5),
+    (3579, 2),
+    (3500, 0),
+];
+
+/* Feature 0x1001 getBatteryInfo reply:
+ *   [0..2] battery voltage in millivolts, big-endian
+ *   [2]    flags – bit 7 external power connected, bits 0-2 charge
+ *          status: 0 charging, 1 end of charge, 2 charging stopped,
+ *          7 error
+ * The device reports no percentage; it is estimated from the voltage. */
+pub fn parse_battery_voltage(buf: &[u8]) -> BatteryInfo {
+    let millivolts = u16::from_be_bytes([buf[0], buf[1]]);
+    let level = match BATTERY_VOLTAGE_CURVE.iter().position(|&(mv, _)| millivolts >= mv) {
+        Some(0) => 100,
+        None => 0,
+        Some(i) => {
+            let (hi_mv, hi_pct) = BATTERY_VOLTAGE_CURVE[i - 1];
+            let (lo_mv, lo_pct) = BATTERY_VOLTAGE_CURVE[i];
+            let span = u32::from(hi_pct - lo_pct) * u32::from(millivolts - lo_mv);
+            lo_pct + (span / u32::from(hi_mv - lo_mv)) as u8
+        }
+    };
+    BatteryInfo {
+        level,
+        charging: buf[2] & 0x80 != 0 && buf[2] & 0x07 == 0,
+    }
+}
+
+/* Feature 0x1004 get_status reply:
+ *   [0] state of charge in percent, 0 on devices that only report levels
+ *   [1] level flags – bit 0 critical, 1 low, 2 good, 3 full
+ *   [2] charging status – 0 discharging, 1 charging, 2 slow charging,
+ *                         3 complete, 4 error
+ *   [3] external power */
+pub fn parse_unified_battery_status(buf: &[u8]) -> BatteryInfo {
+    /* Without a percentage, approximate one from the coarse level. */
+    let level = match (buf[0], buf[1]) {
+        (0, flags) if flags & 0x08 != 0 => 100,
+        (0, flags) if flags & 0x04 != 0 => 50,
+        (0, flags) if flags & 0x02 != 0 => 20,
+        (0, flags) if flags & 0x01 != 0 => 5,
+        (percent, _) => percent.min(100),
+    };
+    BatteryInfo {
+        level,
+        charging: matches!(buf[2], 1 | 2),
+    }
+}
+
 /* Feature 0x8100: Onboard Profiles */
 #[derive(Debug, Clone, Copy, Default)]
 pub struct Hidpp20OnboardProfilesInfo {
     pub profile_count: u8,
     pub profile_count_oob: u8,
     pub button_count: u8,
+    pub sector_count: u8,
     pub sector_size: [u8; 2],  /* Big Endian u16 */
 }
 
@@ -314,7 +659,7 @@ impl Hidpp20OnboardProfilesInfo {
          *   [3] profile_count
          *   [4] profile_count_oob
          *   [5] button_count
-         *   [6] sector_count      – unused
+         *   [6] sector_count
          *   [7..9] sector_size    (BE u16)
          *   [9] mechanical_layout – unused
          *   [10..16] reserved     – unused
@@ -322,13 +667,28 @@ impl Hidpp20OnboardProfilesInfo {
         let profile_count = buf[3];
         let profile_count_oob = buf[4];
         let button_count = buf[5];
+        let sector_count = buf[6];
         let mut sector_size = [0u8; 2];
         sector_size.copy_from_slice(&buf[7..9]);
-        Self { profile_count, profile_count_oob, button_count, sector_size }
+        Self { profile_count, profile_count_oob, button_count, sector_count, sector_size }
     }
     pub fn sector_size(&self) -> u16 {
         u16::from_be_bytes(self.sector_size)
     }
+
+    /* Sector holding the macros of profile `index`.  Profile `i` lives in
+     * sector `i + 1`; the macro sectors follow the last of them, one per
+     * profile.  `None` when that is past the end of the onboard memory. */
+    pub fn macro_sector(&self, index: u32) -> Option<u8> {
+        let sector = u32::from(self.profile_count.max(1)) + 1 + index;
+        u8::try_from(sector).ok().filter(|&sector| self.can_hold_macros(sector))
+    }
+
+    /* Whether `sector` is past the directory and profile sectors and
+     * within the onboard memory. */
+    pub fn can_hold_macros(&self, sector: u8) -> bool {
+        sector > self.profile_count.max(1) && (self.sector_count == 0 || sector < self.sector_count)
+    }
 }
 
 
@@ -342,6 +702,216 @@ pub struct Hidpp20Driver {
     /* Set when any onboard-profile sector CRC check fails; triggers a full
      * rewrite/rebuild attempt on the next commit. */
     needs_eeprom_repair: bool,
+    /* Last LED state written to (or read from) each zone in host mode,
+     * keyed by zone index.  Lets a brightness-only change skip the full
+     * zone-effect write, which would restart the running animation. */
+    last_written_leds: HashMap<u32, LedInfo>,
+    /* Maximum value accepted by 0x8040 setBrightness, queried lazily. */
+    max_brightness: Option<u16>,
+    /* SmartShift threshold last read from or written to the device. */
+    smart_shift_threshold: Option<u8>,
+    /* 0x2201 sensor the resolutions are read from and written to. */
+    dpi_sensor: u8,
+    /* Set for the duration of `commit_profile`: the one profile the
+     * commit may write. */
+    commit_only: Option<u32>,
+    /* Device state as last read from or committed to the hardware.  A
+     * commit diffs against it and leaves unchanged DPI and profile
+     * sectors alone, sparing the onboard memory needless erase cycles. */
+    last_committed: Option<DeviceInfo>,
+    /* Pages the stored macros of each profile occupy, as last loaded or
+     * written.  Another profile's macros are never written there. */
+    macro_pages: HashMap<u32, BTreeSet<u8>>,
+    /* (profile, button) macros that did not decode up to their
+     * MACRO_END; rewriting them from their DBus entries would lose the
+     * rest, so their profile's macro sector is left alone. */
+    undecoded_macros: HashSet<(u32, u32)>,
+    /* `RGB_EFFECTS_PREFERRED` quirk: zones only take effects written
+     * through 0x8071, so every mode goes there when the device has it. */
+    rgb_effects_preferred: bool,
+    /* `WAVE_DIRECTION` quirk: ColorWave zones take a direction in byte 9
+     * of the effect; elsewhere that byte stays zero. */
+    wave_direction: bool,
+}
+
+/* Whether a commit writes `profile`'s onboard sector: dirty profiles, or
+ * every profile while repairing, but never one outside a single-profile
+ * commit. */
+fn sector_needs_write(profile: &ProfileInfo, force_repair: bool, only: Option<u32>) -> bool {
+    only.is_none_or(|index| index == profile.index) && (profile.is_dirty || force_repair)
+}
+
+/* The onboard profile format has no enabled-DPI mask: a DPI slot holding
+ * 0 (or erased flash, 0xFFFF) is a disabled resolution.  Decodes a slot
+ * as (dpi, disabled). */
+fn parse_dpi_slot(raw: u16) -> (u32, bool) {
+    match raw {
+        0 | 0xFFFF => (0, true),
+        dpi => (u32::from(dpi), false),
+    }
+}
+
+/* The DPI slot value `res` is stored as: 0 when it is disabled.  `None`
+ * leaves the slot as it is on the device. */
+fn dpi_slot_value(profile: u32, res: &crate::device::ResolutionInfo) -> Option<u16> {
+    if res.is_disabled {
+        return Some(0);
+    }
+    let (dpi, _) = crate::driver::dpi_to_commit("HID++ 2.0", profile, res)?;
+    if dpi == 0 {
+        warn!(
+            "HID++ 2.0: profile {profile} resolution {} is enabled but has no DPI; \
+             it stays disabled until a DPI is set",
+            res.index
+        );
+    }
+    Some(dpi.min(u32::from(u16::MAX)) as u16)
+}
+
+/* Where a macro goes on after the instructions `decode_macro` read. */
+#[derive(Debug, PartialEq, Eq)]
+enum MacroNext {
+    End,
+    Jump { page: u8, offset: u8 },
+    /* No MACRO_END before the end of the data, an unknown opcode, or
+     * more than MAX_MACRO_EVENTS events. */
+    Truncated,
+    /* An instruction with no DBus entry. */
+    Unsupported(u8),
+}
+
+/* Decode the instructions from `offset` of a sector's data (without its
+ * CRC) into DBus macro entries appended to `entries`.  A key event with
+ * modifiers becomes one entry per modifier: pressed before the key,
+ * released after it. */
+fn decode_macro(data: &[u8], mut offset: usize, entries: &mut Vec<(u32, u32)>) -> MacroNext {
+    let modifiers = |mask: u8| {
+        (0..8u32)
+            .filter(move |bit| mask & (1 << bit) != 0)
+            .map(|bit| HID_USAGE_LEFT_CONTROL + bit)
+    };
+    while entries.len() < MAX_MACRO_EVENTS {
+        let Some(&op) = data.get(offset) else {
+            return MacroNext::Truncated;
+        };
+        let size = match op {
+            MACRO_END => return MacroNext::End,
+            MACRO_NOOP => 1,
+            MACRO_DELAY | MACRO_KEY_PRESS | MACRO_KEY_RELEASE => 3,
+            MACRO_JUMP => 4,
+            MACRO_WAIT_FOR_BUTTON_RELEASE..=MACRO_REPEAT | MACRO_MODIFIERS..=MACRO_CONSUMER_CONTROL => {
+                return MacroNext::Unsupported(op);
+            }
+            _ => return MacroNext::Truncated,
+        };
+        let Some(args) = data.get(offset + 1..offset + size) else {
+            return MacroNext::Truncated;
+        };
+        match op {
+            MACRO_DELAY => {
+                let ms = u16::from_be_bytes([args[0], args[1]]);
+                entries.push((u32::from(ms), MACRO_ENTRY_WAIT));
+            }
+            MACRO_KEY_PRESS => {
+                entries.extend(modifiers(args[0]).map(|usage| (usage, MACRO_ENTRY_PRESS)));
+                if args[1] != 0 {
+                    entries.push((u32::from(args[1]), MACRO_ENTRY_PRESS));
+                }
+            }
+            MACRO_KEY_RELEASE => {
+                if args[1] != 0 {
+                    entries.push((u32::from(args[1]), MACRO_ENTRY_RELEASE));
+                }
+                entries.extend(modifiers(args[0]).map(|usage| (usage, MACRO_ENTRY_RELEASE)));
+            }
+            MACRO_JUMP => return MacroNext::Jump { page: args[0], offset: args[2] },
+            _ => {}
+        }
+        offset += size;
+    }
+    MacroNext::Truncated
+}
+
+/* Encode DBus macro entries as instructions ending in MACRO_END.  Key
+ * values are HID usages, as this driver's key bindings are; a modifier
+ * key goes in the modifier mask.  Waits are capped at the 16-bit delay,
+ * entries of no known kind or with no one-byte usage are dropped. */
+fn encode_macro(entries: &[(u32, u32)]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(entries.len() * 3 + 1);
+    for &(value, kind) in entries {
+        let op = match kind {
+            MACRO_ENTRY_PRESS => MACRO_KEY_PRESS,
+            MACRO_ENTRY_RELEASE => MACRO_KEY_RELEASE,
+            MACRO_ENTRY_WAIT => {
+                let ms = value.min(u32::from(u16::MAX)) as u16;
+                bytes.push(MACRO_DELAY);
+                bytes.extend_from_slice(&ms.to_be_bytes());
+                continue;
+            }
+            _ => {
+                warn!("HID++ 2.0: dropping macro entry ({value}, {kind}) of unknown kind");
+                continue;
+            }
+        };
+        let event = match value.checked_sub(HID_USAGE_LEFT_CONTROL) {
+            Some(bit @ 0..8) => [op, 1 << bit, 0],
+            _ => match u8::try_from(value) {
+                Ok(usage) => [op, 0, usage],
+                Err(_) => {
+                    warn!("HID++ 2.0: dropping macro key {value}, not a keyboard usage");
+                    continue;
+                }
+            },
+        };
+        bytes.extend_from_slice(&event);
+    }
+    bytes.push(MACRO_END);
+    bytes
+}
+
+/* A macro as read from onboard memory: its DBus entries, the pages it
+ * spans, and whether it decoded all the way to its MACRO_END. */
+struct StoredMacro {
+    entries: Vec<(u32, u32)>,
+    pages: BTreeSet<u8>,
+    complete: bool,
+}
+
+/* A macro button and the offset of its macro, or why it did not fit. */
+type MacroPlacement = (u32, Result<u8, String>);
+
+/* The macro sector of `profile`: its macros packed one after another
+ * from offset 0, padded with 0xFF and closed with the CRC.  Returns the
+ * sector and where each macro button's macro went; a binding can only
+ * point at the first 256 bytes. */
+fn build_macro_sector(profile: &ProfileInfo, sector_size: u16) -> (Vec<u8>, Vec<MacroPlacement>) {
+    let mut sector = vec![0xFFu8; usize::from(sector_size)];
+    let end = sector.len().saturating_sub(2);
+    let mut pos = 0usize;
+    let mut offsets = Vec::new();
+    for button in profile.buttons.iter().filter(|b| b.action_type == ActionType::Macro) {
+        let bytes = encode_macro(&button.macro_entries);
+        let offset = match u8::try_from(pos) {
+            Ok(offset) if pos + bytes.len() <= end => offset,
+            _ => {
+                let error = format!(
+                    "macro of {} bytes does not fit in the {} left of the profile's macro sector",
+                    bytes.len(),
+                    end.saturating_sub(pos)
+                );
+                offsets.push((button.index, Err(error)));
+                continue;
+            }
+        };
+        sector[pos..pos + bytes.len()].copy_from_slice(&bytes);
+        pos += bytes.len();
+        offsets.push((button.index, Ok(offset)));
+    }
+    if sector.len() >= 2 {
+        let crc = hidpp::compute_ccitt_crc(&sector[..end]);
+        sector[end..].copy_from_slice(&crc.to_be_bytes());
+    }
+    (sector, offsets)
 }
 
 impl Hidpp20Driver {
@@ -352,9 +922,61 @@ impl Hidpp20Driver {
             cached_onboard_info: None,
             cached_report_rate_hz: 0,
             needs_eeprom_repair: false,
+            last_written_leds: HashMap::new(),
+            max_brightness: None,
+            smart_shift_threshold: None,
+            dpi_sensor: 0,
+            commit_only: None,
+            last_committed: None,
+            macro_pages: HashMap::new(),
+            undecoded_macros: HashSet::new(),
+            rgb_effects_preferred: false,
+            wave_direction: false,
         }
     }
 
+    /* Feature index and set function a zone effect in `mode` is written
+     * through.  TriColor needs 0x8071; other modes use 0x8070, or 0x8071
+     * when the device prefers it and has it. */
+    fn led_effect_feature(&self, mode: LedMode) -> Option<(u8, u8)> {
+        let rgb_effects = self
+            .features
+            .rgb_effects
+            .map(|idx| (idx, RGB_EFFECTS_FN_SET_MULTI_LED_RGB_CLUSTER_PATTERN));
+        if mode == LedMode::TriColor {
+            return rgb_effects;
+        }
+        let color_led_effects = self
+            .features
+            .color_led_effects
+            .map(|idx| (idx, LED_FN_SET_ZONE_EFFECT));
+        if self.rgb_effects_preferred {
+            rgb_effects.or(color_led_effects)
+        } else {
+            color_led_effects
+        }
+    }
+
+    /* Record `info` as what the hardware now holds: all of it, or only
+     * the profile a single-profile commit wrote. */
+    fn remember_committed(&mut self, info: &DeviceInfo) {
+        match (self.commit_only, self.last_committed.as_mut()) {
+            (Some(index), Some(last)) => {
+                if let (Some(old), Some(new)) = (last.find_profile_mut(index), info.find_profile(index)) {
+                    *old = new.clone();
+                }
+            }
+            _ => self.last_committed = Some(info.clone()),
+        }
+    }
+
+    /* Forget everything learned from a previous connection.  A reconnect  */
+    /* may follow a firmware update that moved or removed features, so the */
+    /* feature map and onboard-profile descriptor must be rediscovered.    */
+    fn reset_session(&mut self) {
+        *self = Self::new();
+    }
+
     /* Attempt a HID++ 2.0 protocol version probe at a specific device index. */
     /* Returns `Some((major, minor))` on success, `None` on timeout or error. */
     /*                                                                         */
@@ -585,7 +1207,26 @@ impl Hidpp20Driver {
         }
     }
 
-    /* Discover all supported features and cache their runtime indices. */
+    /* Look up one feature page and record its index.  `Err` means the
+     * lookup got no answer at all: every `request` attempt timed out. */
+    async fn discover_feature(&mut self, io: &mut DeviceIo, page: u16, name: &str) -> Result<bool> {
+        match self.get_feature_index(io, page).await? {
+            Some(idx) => {
+                info!("  Feature {name} (0x{page:04X}) at index 0x{idx:02X}");
+                self.features.insert(page, idx);
+                Ok(true)
+            }
+            None => {
+                info!("  Feature {name} (0x{page:04X}) not supported");
+                Ok(false)
+            }
+        }
+    }
+
+    /* Discover all supported features and cache their runtime indices.
+     * A device that answers "not supported" is taken at its word; pages
+     * whose lookup went unanswered get one more try afterwards, as long
+     * as the discovery deadline allows. */
     async fn discover_features(&mut self, io: &mut DeviceIo) -> Result<()> {
         const FEATURE_QUERIES: &[(u16, &str)] = &[
             (PAGE_ADJUSTABLE_DPI, "Adjustable DPI"),
@@ -594,30 +1235,63 @@ impl Hidpp20Driver {
             (PAGE_COLOR_LED_EFFECTS, "Color LED Effects"),
             (PAGE_RGB_EFFECTS, "RGB Effects"),
             (PAGE_ADJUSTABLE_REPORT_RATE, "Adjustable Report Rate"),
+            (PAGE_EXTENDED_REPORT_RATE, "Extended Adjustable Report Rate"),
+            (PAGE_BRIGHTNESS_CONTROL, "Brightness Control"),
+            (PAGE_SMART_SHIFT, "SmartShift"),
+            (PAGE_SMART_SHIFT_ENHANCED, "SmartShift Enhanced"),
+            (PAGE_BATTERY_STATUS, "Battery Status"),
+            (PAGE_BATTERY_VOLTAGE, "Battery Voltage"),
+            (PAGE_UNIFIED_BATTERY, "Unified Battery"),
         ];
 
+        /* Features nearly every HID++ 2.0 mouse has.  A lookup lost on a
+         * wireless link is retried for these first. */
+        const CRITICAL_FEATURES: &[u16] = &[PAGE_ADJUSTABLE_DPI, PAGE_ADJUSTABLE_REPORT_RATE];
+
+        /* Time the whole discovery may take, retries included.  The probe
+         * as a whole gets PROBE_TIMEOUT (12 s) in the actor, of which the
+         * version ping can spend 2 s on an index that does not answer. */
+        const DISCOVERY_BUDGET: Duration = Duration::from_secs(9);
+
+        let deadline = Instant::now() + DISCOVERY_BUDGET;
         let mut found_count: usize = 0;
+        let mut unanswered = Vec::new();
         for &(page, name) in FEATURE_QUERIES {
-            match self.get_feature_index(io, page).await {
-                Ok(Some(idx)) => {
-                    info!("  Feature {name} (0x{page:04X}) at index 0x{idx:02X}");
-                    self.features.insert(page, idx);
-                    found_count += 1;
-                }
-                Ok(None) => {
-                    info!("  Feature {name} (0x{page:04X}) not supported");
-                }
+            match self.discover_feature(io, page, name).await {
+                Ok(found) => found_count += usize::from(found),
                 Err(e) => {
                     warn!("  Feature {name} (0x{page:04X}) query failed: {e}");
+                    unanswered.push((page, name));
                 }
             }
         }
+        unanswered.sort_by_key(|&(page, _)| !CRITICAL_FEATURES.contains(&page));
+        found_count += self.requery_features(io, &unanswered, deadline).await;
 
         info!("HID++ 2.0: discovered {found_count} features");
 
         Ok(())
     }
 
+    /* Ask once more for pages whose first lookup went unanswered, in
+     * order, stopping at `deadline`.  Returns how many were found. */
+    async fn requery_features(&mut self, io: &mut DeviceIo, pages: &[(u16, &str)], deadline: Instant) -> usize {
+        let mut found_count = 0;
+        for &(page, name) in pages {
+            if Instant::now() >= deadline {
+                warn!("HID++ 2.0: out of time for feature discovery, not re-querying {name} (0x{page:04X})");
+                break;
+            }
+            debug!("  Re-querying {name} (0x{page:04X})");
+            match timeout_at(deadline, self.discover_feature(io, page, name)).await {
+                Ok(Ok(found)) => found_count += usize::from(found),
+                Ok(Err(e)) => warn!("  Feature {name} (0x{page:04X}) query failed again: {e}"),
+                Err(_) => warn!("HID++ 2.0: out of time for feature discovery while re-querying {name} (0x{page:04X})"),
+            }
+        }
+        found_count
+    }
+
     /* ---------------------------------------------------------------------- */
     /* Sector Memory Operations (PAGE_ONBOARD_PROFILES 0x8100)                */
     /* ---------------------------------------------------------------------- */
@@ -772,9 +1446,186 @@ impl Hidpp20Driver {
         Ok(())
     }
 
+    /* Read the macro at (`page`, `offset`) as DBus entries, following its
+     * jumps.  `sectors` caches the sectors read, as several buttons'
+     * macros usually share one. */
+    async fn read_macro(
+        &self,
+        io: &mut DeviceIo,
+        idx: u8,
+        sector_size: u16,
+        (mut page, mut offset): (u8, u8),
+        sectors: &mut HashMap<u16, Vec<u8>>,
+    ) -> Result<StoredMacro> {
+        let start = (page, offset);
+        let mut stored = StoredMacro { entries: Vec::new(), pages: BTreeSet::new(), complete: false };
+        for _ in 0..=MAX_MACRO_JUMPS {
+            stored.pages.insert(page);
+            let sector = u16::from(page);
+            let data = match sectors.entry(sector) {
+                std::collections::hash_map::Entry::Occupied(entry) => entry.into_mut(),
+                std::collections::hash_map::Entry::Vacant(entry) => {
+                    let data = self.read_sector(io, idx, sector, 0, sector_size).await?;
+                    Self::verify_sector_crc(sector, &data);
+                    entry.insert(data)
+                }
+            };
+            let body = &data[..data.len().saturating_sub(2)];
+            match decode_macro(body, usize::from(offset), &mut stored.entries) {
+                MacroNext::End => {
+                    stored.complete = true;
+                    return Ok(stored);
+                }
+                MacroNext::Jump { page: next_page, offset: next_offset } => {
+                    (page, offset) = (next_page, next_offset);
+                }
+                MacroNext::Truncated => break,
+                MacroNext::Unsupported(op) => {
+                    info!(
+                        "HID++ 2.0: macro at ({:02X},{:02X}) uses instruction 0x{op:02X}, \
+                         which has no DBus entry; kept as stored",
+                        start.0, start.1
+                    );
+                    return Ok(stored);
+                }
+            }
+        }
+        warn!(
+            "HID++ 2.0: macro at ({:02X},{:02X}) has no end within bounds, truncated to {} events",
+            start.0,
+            start.1,
+            stored.entries.len()
+        );
+        Ok(stored)
+    }
+
+    /* Sector to write the macros of profile `index` to: the one sector
+     * its stored macros use, unless another profile's macros are there
+     * too, else its `macro_sector` as long as no stored macro uses it. */
+    fn macro_sector_for(&self, desc: &Hidpp20OnboardProfilesInfo, index: u32) -> Option<u8> {
+        let used_elsewhere = |sector: u8| {
+            self.macro_pages
+                .iter()
+                .any(|(&profile, pages)| profile != index && pages.contains(&sector))
+        };
+        if let Some(pages) = self.macro_pages.get(&index)
+            && let [own] = pages.iter().copied().collect::<Vec<_>>()[..]
+            && desc.can_hold_macros(own)
+            && !used_elsewhere(own)
+        {
+            return Some(own);
+        }
+        desc.macro_sector(index).filter(|&sector| !used_elsewhere(sector))
+    }
+
+    /* Rewrite the macro sector of `profile` when one of its macro
+     * buttons is in `changed`, or all of them when `force` is set.
+     * Returns the binding of each macro button that now points at its
+     * macro; a button left out keeps its stored binding, and when the
+     * sector was due to be written, its failure goes to `failures`. */
+    async fn commit_macros(
+        &mut self,
+        io: &mut DeviceIo,
+        idx: u8,
+        profile: &ProfileInfo,
+        changed: &[u32],
+        force: bool,
+        failures: &mut Vec<(CommitTarget, String)>,
+    ) -> HashMap<u32, Hidpp20ButtonBinding> {
+        let mut bindings = HashMap::new();
+        let buttons: Vec<u32> = profile
+            .buttons
+            .iter()
+            .filter(|b| b.action_type == ActionType::Macro)
+            .map(|b| b.index)
+            .collect();
+        let targets: &[u32] = if force { &buttons } else { changed };
+        let Some(desc) = self.cached_onboard_info.filter(|_| !targets.is_empty()) else {
+            return bindings;
+        };
+        let mut fail_all = |error: String| {
+            for &index in targets {
+                let target = CommitTarget::Button { profile: profile.index, index };
+                failures.push((target, error.clone()));
+            }
+        };
+
+        if let Some(kept) = buttons.iter().find(|&&index| {
+            self.undecoded_macros.contains(&(profile.index, index)) && !changed.contains(&index)
+        }) {
+            fail_all(format!(
+                "the stored macro of button {kept} was not fully read and would be lost \
+                 by rewriting the macros of profile {}",
+                profile.index
+            ));
+            return bindings;
+        }
+        let Some(page) = self.macro_sector_for(&desc, profile.index) else {
+            fail_all(format!("no free onboard sector for the macros of profile {}", profile.index));
+            return bindings;
+        };
+        let (sector, offsets) = build_macro_sector(profile, desc.sector_size());
+        if let Err(e) = self.write_sector(io, idx, u16::from(page), 0, &sector).await {
+            warn!("Failed to write macro sector 0x{page:04X} for profile {}: {e}", profile.index);
+            fail_all(format!("{e:#}"));
+            return bindings;
+        }
+        debug!("HID++ 2.0: committed macros of profile {} → sector 0x{page:04X}", profile.index);
+        self.macro_pages.insert(profile.index, BTreeSet::from([page]));
+        self.undecoded_macros.retain(|&(p, _)| p != profile.index);
+
+        for (index, offset) in offsets {
+            match offset {
+                Ok(offset) => {
+                    bindings.insert(index, Hidpp20ButtonBinding::macro_at(page, offset));
+                }
+                Err(e) => {
+                    warn!("HID++ 2.0: profile {} button {index}: {e}", profile.index);
+                    failures.push((CommitTarget::Button { profile: profile.index, index }, e));
+                }
+            }
+        }
+        bindings
+    }
+
+    /* Read the DPI list and current DPI of every sensor (0x2201). */
+    async fn read_dpi_sensors(&self, io: &mut DeviceIo, idx: u8) -> Result<Vec<SensorDpi>> {
+        let count_data = self
+            .feature_request(io, idx, DPI_FN_GET_SENSOR_COUNT, &[])
+            .await?;
+        /* A device with the feature has at least one sensor. */
+        let count = count_data[0].max(1);
+
+        let mut sensors = Vec::with_capacity(usize::from(count));
+        for sensor in 0..count {
+            let list_data = self
+                .feature_request(io, idx, DPI_FN_GET_SENSOR_DPI_LIST, &[sensor])
+                .await?;
+            let dpi_list = parse_dpi_list(&list_data[1..]); /* skip sensor_index byte */
+            let dpi_data = self
+                .feature_request(io, idx, DPI_FN_GET_SENSOR_DPI, &[sensor])
+                .await?;
+            let current = u16::from_be_bytes([dpi_data[1], dpi_data[2]]);
+            let default = u16::from_be_bytes([dpi_data[3], dpi_data[4]]);
+            debug!(
+                "HID++ 2.0: sensor {sensor} DPI list ({} values): first={}, last={}; current {current} (default {default})",
+                dpi_list.len(),
+                dpi_list.first().unwrap_or(&0),
+                dpi_list.last().unwrap_or(&0),
+            );
+            sensors.push(SensorDpi {
+                index: sensor,
+                dpi_list,
+                current: u32::from(current),
+                default: u32::from(default),
+            });
+        }
+        Ok(sensors)
+    }
+
     /* Read DPI sensor information using feature 0x2201. */
     async fn read_dpi_info(
-        &self,
+        &mut self,
         io: &mut DeviceIo,
         profile: &mut ProfileInfo,
     ) -> Result<()> {
@@ -782,76 +1633,74 @@ impl Hidpp20Driver {
             return Ok(());
         };
 
-        let list_data = self
-            .feature_request(io, idx, DPI_FN_GET_SENSOR_DPI_LIST, &[0])
-            .await?;
-        let dpi_list = parse_dpi_list(&list_data[1..]); /* skip sensor_index byte */
-
-        debug!(
-            "HID++ 2.0: sensor 0 DPI list ({} values): first={}, last={}",
-            dpi_list.len(),
-            dpi_list.first().unwrap_or(&0),
-            dpi_list.last().unwrap_or(&0),
-        );
-
-        /* Read current DPI (fn=2, getSensorDPI). */
-        let dpi_data = self
-            .feature_request(io, idx, DPI_FN_GET_SENSOR_DPI, &[0])
-            .await?;
-        let current_dpi = u16::from_be_bytes([dpi_data[1], dpi_data[2]]);
-        let default_dpi = u16::from_be_bytes([dpi_data[3], dpi_data[4]]);
+        let sensors = self.read_dpi_sensors(io, idx).await?;
+        let Some(primary) = primary_sensor(&sensors) else {
+            return Ok(());
+        };
+        self.dpi_sensor = primary.index;
+        if sensors.len() > 1 {
+            info!(
+                "HID++ 2.0: {} DPI sensors; resolutions follow sensor {}",
+                sensors.len(),
+                primary.index
+            );
+            for other in sensors.iter().filter(|s| s.index != primary.index) {
+                if other.current != primary.current {
+                    debug!(
+                        "HID++ 2.0: sensor {} runs at {} DPI, sensor {} at {} DPI",
+                        other.index, other.current, primary.index, primary.current
+                    );
+                }
+            }
+        }
 
-        /* Apply the queried DPI list and current value to all resolutions. */
+        /* Give the queried DPI list to resolutions that do not carry their
+         * own yet, and the current value to the active one. */
         for res in &mut profile.resolutions {
-            if !dpi_list.is_empty() {
-                res.dpi_list = dpi_list.clone();
+            if res.dpi_list.is_empty() {
+                res.dpi_list = primary.dpi_list.clone();
             }
             if res.is_active {
-                res.dpi = Dpi::Unified(u32::from(current_dpi));
+                res.dpi = Dpi::Unified(primary.current);
             }
         }
-
-        debug!("HID++ 2.0: sensor 0 current DPI = {current_dpi} (default = {default_dpi})");
         Ok(())
     }
 
-    /* Read report rate using feature 0x8060. */
+    /* Read report rate using feature 0x8061 or 0x8060. */
     async fn read_report_rate(
         &mut self,
         io: &mut DeviceIo,
         profile: &mut ProfileInfo,
     ) -> Result<()> {
-        let Some(idx) = self.features.report_rate else {
+        let Some((idx, feature)) = self.features.report_rate() else {
             return Ok(());
         };
+        let (list_fn, get_fn, _) = feature.functions();
 
-        let list_data = self
-            .feature_request(io, idx, RATE_FN_GET_REPORT_RATE_LIST, &[])
-            .await?;
-        let rate_bitmap = list_data[0];
-
-        profile.report_rates = (0..8u32)
-            .filter(|bit| rate_bitmap & (1 << bit) != 0)
-            .map(|bit| 1000 / (bit + 1))
-            .collect();
+        let list_data = self.feature_request(io, idx, list_fn, &[]).await?;
+        profile.report_rates = feature.rates_from_list(&list_data);
 
-        let rate_data = self
-            .feature_request(io, idx, RATE_FN_GET_REPORT_RATE, &[])
-            .await?;
-        let current_rate_ms = u32::from(rate_data[0]);
-        if current_rate_ms > 0 {
-            profile.report_rate = 1000 / current_rate_ms;
-            self.cached_report_rate_hz = profile.report_rate;
+        let rate_data = self.feature_request(io, idx, get_fn, &[]).await?;
+        if let Some(hz) = feature.rate_from_value(rate_data[0]) {
+            profile.report_rate = hz;
+            self.cached_report_rate_hz = hz;
         }
         Ok(())
     }
 
-    /* Read LED zone effect from the device using feature 0x8070. */
+    /* Read LED zone effect from the device using feature 0x8070, or
+     * through 0x8071 on devices that prefer it. */
     async fn read_led_info(
-        &self,
+        &mut self,
         io: &mut DeviceIo,
         profile: &mut ProfileInfo,
     ) -> Result<()> {
+        if self.rgb_effects_preferred
+            && let Some(idx) = self.features.rgb_effects
+        {
+            return self.read_rgb_clusters(io, idx, profile).await;
+        }
         let Some(idx) = self.features.color_led_effects else {
             return Ok(());
         };
@@ -867,54 +1716,129 @@ impl Hidpp20Driver {
                 continue;
             }
 
-            *led = Self::parse_eeprom_led(&response[1..12], led.index as usize);
+            *led = Self::parse_eeprom_led(
+                &response[1..12],
+                led.index as usize,
+                self.wave_direction,
+            );
+            self.last_written_leds.insert(led.index, led.clone());
         }
 
         Ok(())
     }
 
-    /* Write LED zone effect to the device using feature 0x8070. */
-    /* TriColor mode is routed through feature 0x8071 (RGB Effects) instead. */
+    /* 0x8071 cannot report the running effect, so the zones keep their
+     * defaults and stay out of the write cache: the first commit writes
+     * every one of them.  What it does report is the number of clusters;
+     * zones past it do not exist on the device and are left unmanaged. */
+    async fn read_rgb_clusters(
+        &mut self,
+        io: &mut DeviceIo,
+        idx: u8,
+        profile: &mut ProfileInfo,
+    ) -> Result<()> {
+        let info = self
+            .feature_request(io, idx, RGB_EFFECTS_FN_GET_INFO, &[0xFF, 0xFF, 0x00])
+            .await
+            .context("Failed to read RGB Effects info")?;
+        let clusters = u32::from(info[2]);
+        for led in profile.leds.iter_mut().filter(|led| led.index >= clusters) {
+            warn!("LED zone {} is not one of the {clusters} RGB clusters", led.index);
+            led.unmanaged = true;
+        }
+        Ok(())
+    }
+
+    /* Set the device brightness through feature 0x8040, scaling the DBus
+     * 0-255 range onto the device's own maximum.  The setting covers
+     * every zone at once, so it only stands in for a zone write on a
+     * device with a single zone. */
+    async fn write_brightness(&mut self, io: &mut DeviceIo, idx: u8, brightness: u32) -> Result<()> {
+        let max = match self.max_brightness {
+            Some(max) => max,
+            None => {
+                let info = self
+                    .feature_request(io, idx, BRIGHTNESS_FN_GET_INFO, &[])
+                    .await
+                    .context("Failed to read brightness info")?;
+                let max = u16::from_be_bytes([info[0], info[1]]).max(1);
+                self.max_brightness = Some(max);
+                max
+            }
+        };
+        let value = (brightness.min(255) * u32::from(max) / 255) as u16;
+        self.feature_request(io, idx, BRIGHTNESS_FN_SET_BRIGHTNESS, &value.to_be_bytes())
+            .await
+            .context("Failed to write brightness")?;
+        Ok(())
+    }
+
+    /* Write every managed LED zone of `profile`.  A zone that fails does
+     * not stop the others; returns the indices of the failed zones. */
     async fn write_led_info(
-        &self,
+        &mut self,
         io: &mut DeviceIo,
         profile: &ProfileInfo,
-    ) -> Result<()> {
-        for led in &profile.leds {
-            let zone_index = led.index as u8;
+    ) -> Vec<(u32, anyhow::Error)> {
+        let mut failed = Vec::new();
+        let single_zone = profile.leds.len() == 1;
+        for led in profile.leds.iter().filter(|led| !led.unmanaged) {
+            if let Err(e) = self.write_led(io, led, single_zone).await {
+                failed.push((led.index, e));
+            }
+        }
+        failed
+    }
 
+    /* Write LED zone effect to the device using feature 0x8070, or
+     * 0x8071 as `led_effect_feature` picks.  A brightness change on a
+     * device with several zones rewrites the zone's effect with its new
+     * brightness byte rather than going through 0x8040, which would dim
+     * the other zones too. */
+    async fn write_led(&mut self, io: &mut DeviceIo, led: &LedInfo, single_zone: bool) -> Result<()> {
+        let zone_index = led.index as u8;
+
+        /* A clean zone still shows what was last written, unless a
+         * live color write dropped it from the cache. */
+        if !led.is_dirty && self.last_written_leds.contains_key(&led.index) {
+            return Ok(());
+        }
+        let brightness_control = self.features.brightness_control.filter(|_| single_zone);
+        let plan = hidpp::plan_led_write(
+            self.last_written_leds.get(&led.index),
+            led,
+            brightness_control.is_some(),
+        );
+        match (plan, brightness_control) {
+            (LedWritePlan::Skip, _) => return Ok(()),
+            (LedWritePlan::BrightnessOnly, Some(idx)) => {
+                self.write_brightness(io, idx, led.brightness).await?;
+                self.last_written_leds.insert(led.index, led.clone());
+                debug!("HID++ 2.0: LED zone {zone_index} brightness={}", led.brightness);
+                return Ok(());
+            }
+            _ => {}
+        }
+
+        let Some((idx, function)) = self.led_effect_feature(led.mode) else {
             if led.mode == LedMode::TriColor {
-                /* TriColor uses 0x8071 RGB Effects with the multi-LED cluster pattern command. */
-                let Some(idx) = self.features.rgb_effects else {
-                    warn!("TriColor requested but device lacks RGB Effects (0x8071)");
-                    continue;
-                };
-                let led_payload = hidpp::build_led_payload(led);
-                let mut bytes = [0u8; 16];
-                bytes[0] = zone_index;
-                bytes[1..12].copy_from_slice(&led_payload);
-                bytes[12] = 0x01; /* persist */
-                /* Function 0x02 = setMultiLEDRGBClusterPattern on 0x8071. Note: C passes 13 bytes */
-                self.feature_request(io, idx, 0x02, &bytes[0..13])
-                    .await
-                    .context("Failed to write TriColor multi-LED cluster pattern")?;
+                warn!("TriColor requested but device lacks RGB Effects (0x8071)");
             } else {
-                let Some(idx) = self.features.color_led_effects else {
-                    warn!("Device lacks Color LED Effects (0x8070)");
-                    continue;
-                };
-                let led_payload = hidpp::build_led_payload(led);
-                let mut bytes = [0u8; 16];
-                bytes[0] = zone_index;
-                bytes[1..12].copy_from_slice(&led_payload);
-                bytes[12] = 0x01; /* persist */
-                self.feature_request(io, idx, LED_FN_SET_ZONE_EFFECT, &bytes[0..13])
-                    .await
-                    .context("Failed to write LED zone effect")?;
+                warn!("Device lacks Color LED Effects (0x8070)");
             }
+            return Ok(());
+        };
+        let led_payload = hidpp::build_led_payload(led);
+        let mut bytes = [0u8; 16];
+        bytes[0] = zone_index;
+        bytes[1..12].copy_from_slice(&led_payload);
+        bytes[12] = 0x01; /* persist */
+        self.feature_request(io, idx, function, &bytes[0..13])
+            .await
+            .context("Failed to write LED zone effect")?;
 
-            debug!("HID++ 2.0: committed LED zone {zone_index} mode={:?}", led.mode);
-        }
+        self.last_written_leds.insert(led.index, led.clone());
+        debug!("HID++ 2.0: committed LED zone {zone_index} mode={:?}", led.mode);
 
         Ok(())
     }
@@ -930,12 +1854,12 @@ impl Hidpp20Driver {
         };
 
         if let Some(res) = profile.resolutions.iter().find(|r| r.is_active)
-            && let Dpi::Unified(dpi_val) = res.dpi
+            && let Some((dpi_val, _)) = crate::driver::dpi_to_commit("HID++ 2.0", profile.index, res)
         {
             let dpi_u16 = dpi_val.min(u32::from(u16::MAX)) as u16;
             let [hi, lo] = dpi_u16.to_be_bytes();
             /* setSensorDPI is fn=3; only sensor_index + dpi_hi + dpi_lo are needed */
-            let response = self.feature_request(io, idx, DPI_FN_SET_SENSOR_DPI, &[0, hi, lo])
+            let response = self.feature_request(io, idx, DPI_FN_SET_SENSOR_DPI, &[self.dpi_sensor, hi, lo])
                 .await
                 .context("Failed to write DPI")?;
             let actual_dpi = u16::from_be_bytes([response[1], response[2]]);
@@ -944,15 +1868,13 @@ impl Hidpp20Driver {
         Ok(())
     }
 
-    /* Write report rate using feature 0x8060. */
+    /* Write report rate using feature 0x8061 or 0x8060. */
     async fn write_report_rate(
         &self,
         io: &mut DeviceIo,
         profile: &ProfileInfo,
     ) -> Result<()> {
-        const RATE_FN_SET_REPORT_RATE: u8 = 0x02;
-
-        let Some(idx) = self.features.report_rate else {
+        let Some((idx, feature)) = self.features.report_rate() else {
             return Ok(());
         };
 
@@ -963,10 +1885,11 @@ impl Hidpp20Driver {
                 debug!("HID++ 2.0: report rate unchanged at {} Hz, skipping write", profile.report_rate);
                 return Ok(());
             }
-            /* Clamp the ms-interval to u8 range; realistic rates (125–8000 Hz)
-             * always produce values 1–8 so this is purely defensive. */
-            let rate_ms = (1000 / profile.report_rate).min(u32::from(u8::MAX)) as u8;
-            self.feature_request(io, idx, RATE_FN_SET_REPORT_RATE, &[rate_ms])
+            let Some(value) = feature.value_for_rate(profile.report_rate) else {
+                anyhow::bail!("Report rate {} Hz cannot be set on this device", profile.report_rate);
+            };
+            let (_, _, set_fn) = feature.functions();
+            self.feature_request(io, idx, set_fn, &[value])
                 .await
                 .context("Failed to write report rate")?;
             debug!("HID++ 2.0: committed report rate = {} Hz", profile.report_rate);
@@ -974,6 +1897,41 @@ impl Hidpp20Driver {
         Ok(())
     }
 
+    /* Read the SmartShift threshold using feature 0x2110/0x2111.  It is a
+     * live, device-wide setting that onboard profiles do not store. */
+    async fn read_smart_shift(&mut self, io: &mut DeviceIo, info: &mut DeviceInfo) -> Result<()> {
+        let Some((idx, get_fn, _)) = self.features.smart_shift() else {
+            return Ok(());
+        };
+        let data = self.feature_request(io, idx, get_fn, &[]).await?;
+        let status = Hidpp20SmartShift::from_bytes(&data);
+        debug!(
+            "HID++ 2.0: SmartShift wheel mode {} threshold {}",
+            status.wheel_mode, status.threshold
+        );
+        self.smart_shift_threshold = Some(status.threshold);
+        info.smart_shift_threshold = Some(status.threshold);
+        Ok(())
+    }
+
+    /* Write the SmartShift threshold using feature 0x2110/0x2111. */
+    async fn write_smart_shift(&mut self, io: &mut DeviceIo, info: &DeviceInfo) -> Result<()> {
+        let (Some((idx, _, set_fn)), Some(threshold)) =
+            (self.features.smart_shift(), info.smart_shift_threshold)
+        else {
+            return Ok(());
+        };
+        if self.smart_shift_threshold == Some(threshold) {
+            return Ok(());
+        }
+        self.feature_request(io, idx, set_fn, &Hidpp20SmartShift::threshold_params(threshold))
+            .await
+            .context("Failed to write SmartShift threshold")?;
+        self.smart_shift_threshold = Some(threshold);
+        debug!("HID++ 2.0: committed SmartShift threshold = {threshold}");
+        Ok(())
+    }
+
     /* ---------------------------------------------------------------------- */
     /* Helpers: query device-wide capabilities for UI validation               */
     /* ---------------------------------------------------------------------- */
@@ -989,7 +1947,7 @@ impl Hidpp20Driver {
         let idx = self.features.adjustable_dpi?;
 
         let list_data = self
-            .feature_request(io, idx, DPI_FN_GET_SENSOR_DPI_LIST, &[0])
+            .feature_request(io, idx, DPI_FN_GET_SENSOR_DPI_LIST, &[self.dpi_sensor])
             .await
             .ok()?;
         let dpi_list = parse_dpi_list(&list_data[1..]); /* skip sensor_index byte */
@@ -1004,25 +1962,21 @@ impl Hidpp20Driver {
         if dpi_list.is_empty() { None } else { Some(dpi_list) }
     }
 
-    /// Query the supported report rate list via feature 0x8060.
+    /// Query the supported report rate list via feature 0x8061 or 0x8060.
     /// Returns the list of supported rates in Hz, or `None` if absent.
     async fn query_report_rate_list(
         &self,
         io: &mut DeviceIo,
     ) -> Option<Vec<u32>> {
-        let idx = self.features.report_rate?;
+        let (idx, feature) = self.features.report_rate()?;
+        let (list_fn, _, _) = feature.functions();
 
         let list_data = self
-            .feature_request(io, idx, RATE_FN_GET_REPORT_RATE_LIST, &[])
+            .feature_request(io, idx, list_fn, &[])
             .await
             .ok()?;
 
-        let rate_bitmap = list_data[0];
-
-        let rates: Vec<u32> = (0..8u32)
-            .filter(|bit| rate_bitmap & (1 << bit) != 0)
-            .map(|bit| 1000 / (bit + 1))
-            .collect();
+        let rates = feature.rates_from_list(&list_data);
 
         debug!("HID++ 2.0: report rate list query → {:?}", rates);
 
@@ -1037,7 +1991,11 @@ impl Hidpp20Driver {
     /// into a `LedInfo`.  Layout (from hidpp20.h):
     ///   byte 0:    mode (LED_HW_MODE_*)
     ///   bytes 1-10: mode-specific effect union
-    fn parse_eeprom_led(led_bytes: &[u8], led_index: usize) -> crate::device::LedInfo {
+    fn parse_eeprom_led(
+        led_bytes: &[u8],
+        led_index: usize,
+        has_direction: bool,
+    ) -> crate::device::LedInfo {
         let mut led = crate::device::LedInfo {
             index: led_index as u32,
             mode: LedMode::Off,
@@ -1047,7 +2005,11 @@ impl Hidpp20Driver {
             tertiary_color: Color::default(),
             color_depth: 0,
             effect_duration: 0,
+            effect_direction: crate::device::LedDirection::Clockwise,
+            has_direction,
             brightness: 0,
+            is_dirty: false,
+            unmanaged: false,
         };
 
         if led_bytes.len() < 11 {
@@ -1074,13 +2036,16 @@ impl Hidpp20Driver {
                 /* bytes 1-5 unused; period at bytes 6-7 (BE), intensity at byte 8 */
                 led.effect_duration =
                     u32::from(u16::from_be_bytes([led_bytes[6], led_bytes[7]]));
-                led.brightness = u32::from(led_bytes[8]) * 255 / 100;
+                led.brightness = hidpp::brightness_from_percent(led_bytes[8]);
             }
             LED_HW_MODE_COLOR_WAVE => {
                 led.mode = LedMode::ColorWave;
                 led.effect_duration =
                     u32::from(u16::from_be_bytes([led_bytes[6], led_bytes[7]]));
-                led.brightness = u32::from(led_bytes[8]) * 255 / 100;
+                led.brightness = hidpp::brightness_from_percent(led_bytes[8]);
+                if has_direction {
+                    led.effect_direction = hidpp::wave_direction_from_byte(led_bytes[9]);
+                }
             }
             LED_HW_MODE_STARLIGHT => {
                 led.mode = LedMode::Starlight;
@@ -1105,7 +2070,7 @@ impl Hidpp20Driver {
                 led.effect_duration =
                     u32::from(u16::from_be_bytes([led_bytes[4], led_bytes[5]]));
                 /* byte 6 = waveform */
-                led.brightness = u32::from(led_bytes[7]) * 255 / 100;
+                led.brightness = hidpp::brightness_from_percent(led_bytes[7]);
             }
             _ => {
                 debug!("EEPROM LED {led_index}: unknown mode 0x{mode_byte:02X}");
@@ -1136,13 +2101,16 @@ impl Hidpp20Driver {
                 buf[0] = LED_HW_MODE_CYCLE;
                 let period = led.effect_duration as u16;
                 buf[6..8].copy_from_slice(&period.to_be_bytes());
-                buf[8] = (led.brightness * 100 / 255) as u8;
+                buf[8] = hidpp::brightness_to_percent(led.brightness);
             }
             LedMode::ColorWave => {
                 buf[0] = LED_HW_MODE_COLOR_WAVE;
                 let period = led.effect_duration as u16;
                 buf[6..8].copy_from_slice(&period.to_be_bytes());
-                buf[8] = (led.brightness * 100 / 255) as u8;
+                buf[8] = hidpp::brightness_to_percent(led.brightness);
+                if led.has_direction {
+                    buf[9] = hidpp::wave_direction_byte(led.effect_direction);
+                }
             }
             LedMode::Starlight => {
                 buf[0] = LED_HW_MODE_STARLIGHT;
@@ -1164,7 +2132,7 @@ impl Hidpp20Driver {
                 let period = led.effect_duration as u16;
                 buf[4..6].copy_from_slice(&period.to_be_bytes());
                 /* byte 6 = waveform, keep 0 */
-                buf[7] = (led.brightness * 100 / 255) as u8;
+                buf[7] = hidpp::brightness_to_percent(led.brightness);
             }
             _ => {
                 /* TriColor or unknown — leave as OFF */
@@ -1182,6 +2150,44 @@ impl super::DeviceDriver for Hidpp20Driver {
         "Logitech HID++ 2.0"
     }
 
+    fn action_types(&self) -> &'static [ActionType] {
+        &[
+            ActionType::None,
+            ActionType::Button,
+            ActionType::Special,
+            ActionType::Key,
+            ActionType::Macro,
+        ]
+    }
+
+    /* Onboard profile and DPI switches arrive as 0x8100 notifications. */
+    fn listens_for_events(&self) -> bool {
+        self.features.onboard_profiles.is_some()
+    }
+
+    fn needs_repair(&self) -> bool {
+        self.needs_eeprom_repair
+    }
+
+    /* Only the 0x8100 sectors carry checksums; a device without onboard
+     * profiles has nothing to rewrite. */
+    fn request_repair(&mut self) -> bool {
+        if self.features.onboard_profiles.is_none() || self.cached_onboard_info.is_none() {
+            return false;
+        }
+        self.needs_eeprom_repair = true;
+        true
+    }
+
+    fn capabilities(&self) -> super::DriverCapabilities {
+        super::DriverCapabilities {
+            profiles: true,
+            leds: true,
+            live_leds: true,
+            ..super::DriverCapabilities::from_action_types(self.action_types())
+        }
+    }
+
     async fn probe(&mut self, io: &mut DeviceIo) -> Result<()> {
         /* Try the corded device index first, then the wireless receiver index.
          *
@@ -1194,6 +2200,7 @@ impl super::DeviceDriver for Hidpp20Driver {
          * the previous four seconds. */
         const PROBE_INDICES: &[u8] = &[DEVICE_IDX_CORDED, DEVICE_IDX_RECEIVER];
 
+        self.reset_session();
         for &idx in PROBE_INDICES {
             if let Some((major, minor)) = self.try_probe_index(io, idx).await {
                 self.device_index = idx;
@@ -1222,6 +2229,8 @@ impl super::DeviceDriver for Hidpp20Driver {
             .quirks
             .iter()
             .any(|q| q == "G305");
+        self.rgb_effects_preferred = info.driver_config.has_quirk("RGB_EFFECTS_PREFERRED");
+        self.wave_direction = info.driver_config.has_quirk("WAVE_DIRECTION");
 
         /* If the device has PAGE_ONBOARD_PROFILES (0x8100), we initialize based on hardware capacity */
         if let Some(idx) = self.features.onboard_profiles {
@@ -1400,6 +2409,9 @@ impl super::DeviceDriver for Hidpp20Driver {
             }
 
             let num_rom = desc.profile_count_oob as usize;
+            let mut macro_sectors = HashMap::new();
+            self.macro_pages.clear();
+            self.undecoded_macros.clear();
 
             for i in 0..profile_count {
                 let addr = profile_addrs[i];
@@ -1478,11 +2490,7 @@ impl super::DeviceDriver for Hidpp20Driver {
                     let d_off = 3 + d_idx * 2;
                     if d_off + 2 <= profile_data.len() {
                         let raw = u16::from_le_bytes([profile_data[d_off], profile_data[d_off + 1]]);
-                        if raw == 0 || raw == 0xFFFF {
-                            eeprom_dpis.push((0, true));
-                        } else {
-                            eeprom_dpis.push((u32::from(raw), false));
-                        }
+                        eeprom_dpis.push(parse_dpi_slot(raw));
                     }
                 }
 
@@ -1514,6 +2522,7 @@ impl super::DeviceDriver for Hidpp20Driver {
 
                 /* --- Buttons (offset 32, 4 bytes each) --- */
                 let max_buttons = button_count.min(16);
+                let mut macro_addrs = Vec::new();
                 for b_idx in 0..max_buttons {
                     let btn_offset = 32 + (b_idx * 4);
                     if btn_offset + 4 <= profile_data.len() {
@@ -1522,23 +2531,9 @@ impl super::DeviceDriver for Hidpp20Driver {
                         let binding = Hidpp20ButtonBinding::from_bytes(&binding_bytes);
 
                         p.buttons[b_idx].action_type = binding.to_action();
-
-                        /* EEPROM mouse buttons are stored as a big-endian bit mask
-                         * (matching the C hidpp20_buttons_to_cpu / buttons_from_cpu).
-                         * ffs(mask) gives the 1-based button ordinal. */
-                        let raw_id = u16::from_be_bytes(binding.control_id_or_macro_id);
-                        let mapping_value = match (binding.button_type, binding.subtype) {
-                            /* EEPROM mouse buttons are stored as a big-endian bit mask.
-                             * ffs(mask) gives the 1-based button ordinal. */
-                            (BUTTON_TYPE_HID, BUTTON_SUBTYPE_MOUSE) => {
-                                if raw_id > 0 { u32::from(raw_id.trailing_zeros()) + 1 } else { 0 }
-                            }
-                            /* Translate the raw HID++ special opcode to the
-                             * canonical special_action constant for DBus. */
-                            (BUTTON_TYPE_SPECIAL, _) => hidpp20_raw_to_special(raw_id as u8),
-                            _ => u32::from(raw_id),
-                        };
+                        let mapping_value = binding.mapping_value();
                         p.buttons[b_idx].mapping_value = mapping_value;
+                        macro_addrs.extend(binding.macro_address().map(|addr| (b_idx, addr)));
 
                         debug!(
                             "HID++ 2.0: profile {i} button {b_idx}: \
@@ -1553,6 +2548,29 @@ impl super::DeviceDriver for Hidpp20Driver {
                     }
                 }
 
+                /* --- Macros, where the bindings point --- */
+                for (b_idx, addr) in macro_addrs {
+                    let key = (i as u32, b_idx as u32);
+                    match self.read_macro(io, idx, sector_size, addr, &mut macro_sectors).await {
+                        Ok(stored) => {
+                            self.macro_pages.entry(key.0).or_default().extend(&stored.pages);
+                            if !stored.complete {
+                                self.undecoded_macros.insert(key);
+                            }
+                            p.buttons[b_idx].macro_entries = stored.entries;
+                        }
+                        Err(e) => {
+                            warn!(
+                                "HID++ 2.0: profile {i} button {b_idx}: failed to read macro at \
+                                 ({:02X},{:02X}): {e}",
+                                addr.0, addr.1
+                            );
+                            self.macro_pages.entry(key.0).or_default().insert(addr.0);
+                            self.undecoded_macros.insert(key);
+                        }
+                    }
+                }
+
                 /* --- LEDs (offset 208, 2 × 11 bytes) --- *
                  * The C struct places leds[HIDPP20_LED_COUNT] at offset 208
                  * inside the 256-byte packed union.  Each LED is 11 bytes
@@ -1564,6 +2582,7 @@ impl super::DeviceDriver for Hidpp20Driver {
                         let led = Self::parse_eeprom_led(
                             &profile_data[off..off + EEPROM_LED_SIZE],
                             led_idx,
+                            self.wave_direction,
                         );
                         p.leds.push(led);
                     }
@@ -1653,14 +2672,15 @@ impl super::DeviceDriver for Hidpp20Driver {
          * When onboard profiles are absent, we fall back to reading
          * everything from the live features instead. */
         if self.features.onboard_profiles.is_some() {
-            /* Query sensor DPI list/range once and apply to all profiles
-             * (the sensor capabilities are device-wide, not per-profile). */
+            /* Query sensor DPI list/range once and apply it to every
+             * resolution that has no list of its own (the sensor
+             * capabilities are device-wide, not per-profile). */
             let dpi_range = self.query_dpi_sensor_range(io).await;
             let rate_list = self.query_report_rate_list(io).await;
 
             for profile in &mut info.profiles {
                 if let Some(ref range) = dpi_range {
-                    for res in &mut profile.resolutions {
+                    for res in profile.resolutions.iter_mut().filter(|r| r.dpi_list.is_empty()) {
                         res.dpi_list = range.clone();
                     }
                 }
@@ -1686,11 +2706,37 @@ impl super::DeviceDriver for Hidpp20Driver {
             }
         }
 
+        let led_modes = self.features.led_modes();
+        for led in info.profiles.iter_mut().flat_map(|p| p.leds.iter_mut()) {
+            led.modes = led_modes.clone();
+            led.has_direction = self.wave_direction;
+        }
+
+        if let Err(e) = self.read_smart_shift(io, info).await {
+            warn!("Failed to read SmartShift threshold: {e}");
+        }
+
         info!("HID++ 2.0: loaded {} profiles", info.profiles.len());
+        self.last_committed = Some(info.clone());
         Ok(())
     }
 
     async fn commit(&mut self, io: &mut DeviceIo, info: &DeviceInfo) -> Result<()> {
+        /* Writes that failed; the others still go through, and the commit
+         * reports these to the client at the end. */
+        let mut failures: Vec<(CommitTarget, String)> = Vec::new();
+
+        /* What changed since the last load or commit; `None` writes it all. */
+        let diff = self.last_committed.as_ref().map(|last| info.diff(last));
+
+        /* SmartShift is device-wide, not part of any one profile. */
+        if self.commit_only.is_none()
+            && let Err(e) = self.write_smart_shift(io, info).await
+        {
+            warn!("Failed to commit SmartShift threshold: {e:#}");
+            failures.push((CommitTarget::Device, format!("{e:#}")));
+        }
+
         /* When onboard profiles (0x8100) are present the firmware reads all
          * per-profile settings (DPI, report rate, LEDs) from the EEPROM
          * sectors.  We must NOT call the live feature set commands
@@ -1701,15 +2747,37 @@ impl super::DeviceDriver for Hidpp20Driver {
          * When onboard profiles are ABSENT we are in host-managed mode and
          * the live feature calls are the only way to change settings. */
         if self.features.onboard_profiles.is_none() {
-            if let Some(profile) = info.profiles.iter().find(|p| p.is_active) {
-                if let Err(e) = self.write_dpi_info(io, profile).await {
+            if let Some(profile) = info
+                .profiles
+                .iter()
+                .find(|p| p.is_active && self.commit_only.is_none_or(|i| i == p.index))
+            {
+                let dpi_changed = diff.as_ref().is_none_or(|d| {
+                    d.profiles.contains(&profile.index)
+                        || d.resolutions.iter().any(|&(p, _)| p == profile.index)
+                });
+                if !dpi_changed {
+                    debug!("HID++ 2.0: DPI of profile {} unchanged, not writing", profile.index);
+                } else if let Err(e) = self.write_dpi_info(io, profile).await {
                     warn!("Failed to commit DPI for profile {}: {e:#}", profile.index);
+                    /* Only the active resolution is written. */
+                    let target = match profile.resolutions.iter().find(|r| r.is_active) {
+                        Some(res) => CommitTarget::Resolution {
+                            profile: profile.index,
+                            index: res.index,
+                        },
+                        None => CommitTarget::Profile(profile.index),
+                    };
+                    failures.push((target, format!("{e:#}")));
                 }
                 if let Err(e) = self.write_report_rate(io, profile).await {
                     warn!("Failed to commit report rate for profile {}: {e:#}", profile.index);
+                    failures.push((CommitTarget::Profile(profile.index), format!("{e:#}")));
                 }
-                if let Err(e) = self.write_led_info(io, profile).await {
-                    warn!("Failed to commit LEDs for profile {}: {e:#}", profile.index);
+                for (led, e) in self.write_led_info(io, profile).await {
+                    warn!("Failed to commit LED {led} for profile {}: {e:#}", profile.index);
+                    let target = CommitTarget::Led { profile: profile.index, index: led };
+                    failures.push((target, format!("{e:#}")));
                 }
             }
         }
@@ -1736,9 +2804,13 @@ impl super::DeviceDriver for Hidpp20Driver {
                  * being valid before the first write — the G305 may have an
                  * uninitialised directory that throws ERR_INVALID_ARGUMENT. */
                 let mut any_written = false;
-                let mut last_err: Option<anyhow::Error> = None;
+                let failed_before = failures.len();
                 for profile in &info.profiles {
-                    if !profile.is_dirty && !force_repair {
+                    if !sector_needs_write(profile, force_repair, self.commit_only) {
+                        continue;
+                    }
+                    if !force_repair && diff.as_ref().is_some_and(|d| !d.touches_profile(profile.index)) {
+                        debug!("HID++ 2.0: profile {} unchanged, not rewriting its sector", profile.index);
                         continue;
                     }
 
@@ -1754,23 +2826,29 @@ impl super::DeviceDriver for Hidpp20Driver {
                      * corrupted so there is nothing worth preserving — skip
                      * the read entirely and start from a clean 0xFF template.
                      * This saves sector_size/16 USB round-trips per profile. */
-                    let mut profile_data = if force_repair {
-                        vec![0xFFu8; sector_size as usize]
+                    let stored = if force_repair {
+                        None
                     } else {
-                        let mut data = self
-                            .read_sector(io, idx, addr, 0, sector_size)
+                        self.read_sector(io, idx, addr, 0, sector_size)
                             .await
-                            .unwrap_or_else(|_| vec![0xFFu8; sector_size as usize]);
-                        if data.len() < sector_size as usize {
-                            data.resize(sector_size as usize, 0xFF);
-                        }
-                        data
+                            .ok()
+                            .map(|mut data| {
+                                if data.len() < sector_size as usize {
+                                    data.resize(sector_size as usize, 0xFF);
+                                }
+                                data
+                            })
                     };
+                    let mut profile_data =
+                        stored.clone().unwrap_or_else(|| vec![0xFFu8; sector_size as usize]);
 
-                    /* 1. Report rate (byte 0): stored as ms-interval */
+                    /* 1. Report rate (byte 0): stored as ms-interval.  Rates
+                     * above 1000 Hz have no whole-ms interval; store 1 ms
+                     * rather than the 0 the division would give. */
                     if profile.report_rate > 0 {
-                        profile_data[0] =
-                            (1000 / profile.report_rate).min(u32::from(u8::MAX)) as u8;
+                        profile_data[0] = RateFeature::Interval
+                            .value_for_rate(profile.report_rate.min(1000))
+                            .unwrap_or(u8::MAX);
                     }
 
                     /* 2. Default-DPI index (byte 1) */
@@ -1778,34 +2856,55 @@ impl super::DeviceDriver for Hidpp20Driver {
                         profile_data[1] = def_idx as u8;
                     }
 
-                    /* 3. DPI list (bytes 3-12, 5 × LE u16) */
+                    /* 3. DPI list (bytes 3-12, 5 × LE u16); disabled
+                     * resolutions are written as 0. */
                     for (i, res) in profile.resolutions.iter().enumerate().take(5) {
-                        if let Dpi::Unified(val) = res.dpi {
-                            let dpi_bytes =
-                                (val.min(u32::from(u16::MAX)) as u16).to_le_bytes();
+                        if let Some(val) = dpi_slot_value(profile.index, res) {
+                            let dpi_bytes = val.to_le_bytes();
                             profile_data[3 + i * 2] = dpi_bytes[0];
                             profile_data[3 + i * 2 + 1] = dpi_bytes[1];
                         }
                     }
 
-                    /* 4. Buttons (offset 32, 4 bytes each) */
+                    /* 4. Macros, written to their own sector before any
+                     * binding points at them; only rewritten when one of
+                     * them changed. */
+                    let changed_macros: Vec<u32> = profile
+                        .buttons
+                        .iter()
+                        .filter(|b| {
+                            b.action_type == ActionType::Macro
+                                && diff.as_ref().is_some_and(|d| d.buttons.contains(&(profile.index, b.index)))
+                        })
+                        .map(|b| b.index)
+                        .collect();
+                    let macro_bindings = self
+                        .commit_macros(io, idx, profile, &changed_macros, force_repair, &mut failures)
+                        .await;
+
+                    /* 5. Buttons (offset 32, 4 bytes each); a macro that
+                     * could not be placed keeps the stored binding. */
                     let max_buttons = desc.button_count.min(16) as usize;
                     for btn in &profile.buttons {
                         let b_idx = btn.index as usize;
                         if b_idx < max_buttons {
                             let btn_offset = 32 + b_idx * 4;
                             if btn_offset + 4 <= profile_data.len() {
-                                let binding = Hidpp20ButtonBinding::from_action(
-                                    btn.action_type,
-                                    btn.mapping_value,
-                                );
+                                let binding = match macro_bindings.get(&btn.index) {
+                                    Some(&binding) => binding,
+                                    None if btn.action_type == ActionType::Macro => continue,
+                                    None => Hidpp20ButtonBinding::from_action(
+                                        btn.action_type,
+                                        btn.mapping_value,
+                                    ),
+                                };
                                 profile_data[btn_offset..btn_offset + 4]
                                     .copy_from_slice(&binding.into_bytes());
                             }
                         }
                     }
 
-                    /* 5. LEDs (offset 208, 2 × 11 bytes) */
+                    /* 6. LEDs (offset 208, 2 × 11 bytes) */
                     {
                         for led in &profile.leds {
                             let led_idx = led.index as usize;
@@ -1820,14 +2919,22 @@ impl super::DeviceDriver for Hidpp20Driver {
                         }
                     }
 
-                    /* 6. Recompute CRC (last 2 bytes, BE) */
+                    /* 7. Recompute CRC (last 2 bytes, BE) */
                     let crc_offset = profile_data.len() - 2;
                     let crc = hidpp::compute_ccitt_crc(&profile_data[..crc_offset]);
                     let crc_bytes = crc.to_be_bytes();
                     profile_data[crc_offset] = crc_bytes[0];
                     profile_data[crc_offset + 1] = crc_bytes[1];
 
-                    /* 7. Write sector */
+                    /* 8. Write sector, unless the patch left it as it was
+                     * (e.g. a setting was set back to its stored value). */
+                    if stored.as_deref() == Some(profile_data.as_slice()) {
+                        debug!(
+                            "HID++ 2.0: sector 0x{addr:04X} of profile {} unchanged, not rewriting",
+                            profile.index
+                        );
+                        continue;
+                    }
                     match self.write_sector(io, idx, addr, 0, &profile_data).await {
                         Ok(()) => {
                             debug!(
@@ -1838,7 +2945,7 @@ impl super::DeviceDriver for Hidpp20Driver {
                         }
                         Err(e) => {
                             warn!("Failed to write EEPROM sector 0x{addr:04X} for profile {}: {e}", profile.index);
-                            last_err = Some(e);
+                            failures.push((CommitTarget::Profile(profile.index), format!("{e:#}")));
                         }
                     }
                 }
@@ -1848,7 +2955,14 @@ impl super::DeviceDriver for Hidpp20Driver {
                  * Format: 4 bytes per profile [0x00, i+1, enabled, 0x00],
                  * followed by [0xFF, 0xFF, 0x00, 0x00], rest padded 0xFF,
                  * then CRC-CCITT in the last two bytes. */
-                if any_written {
+                /* Enabling or disabling a profile only changes the
+                 * directory, not the profile's own sector. */
+                let enabled_changed = self.last_committed.as_ref().is_none_or(|last| {
+                    info.profiles.iter().any(|p| {
+                        last.find_profile(p.index).is_none_or(|old| old.is_enabled != p.is_enabled)
+                    })
+                });
+                if any_written || enabled_changed {
                     let mut dir = vec![0xFFu8; sector_size as usize];
                     let mut pos = 0usize;
                     for profile in &info.profiles {
@@ -1875,7 +2989,7 @@ impl super::DeviceDriver for Hidpp20Driver {
 
                     if let Err(e) = self.write_sector(io, idx, 0x0000, 0, &dir).await {
                         warn!("HID++ 2.0: failed to write profile directory: {e}");
-                        last_err = Some(e);
+                        failures.push((CommitTarget::Device, format!("{e:#}")));
                     } else {
                         debug!("HID++ 2.0: wrote profile directory (sector 0x0000)");
                     }
@@ -1889,14 +3003,17 @@ impl super::DeviceDriver for Hidpp20Driver {
                     warn!("Failed to switch back to onboard mode: {e:#}");
                 }
 
-                if let Some(e) = last_err {
+                if failures.len() > failed_before {
                     /* Keep the flag set so we retry on the next commit. */
                     self.needs_eeprom_repair = true;
-                    return Err(e);
+                    return Err(PartialCommit(failures).into());
                 }
 
-                /* Successful rewrite clears the repair flag. */
-                self.needs_eeprom_repair = false;
+                /* Successful rewrite clears the repair flag; a single-profile
+                 * commit left the other sectors as they were. */
+                if self.commit_only.is_none() {
+                    self.needs_eeprom_repair = false;
+                }
 
                 /* Tell the hardware which profile is now active.  The C driver
                  * calls hidpp20_onboard_profiles_set_current_profile() which
@@ -1954,9 +3071,79 @@ impl super::DeviceDriver for Hidpp20Driver {
             }
         }
 
+        if !failures.is_empty() {
+            return Err(PartialCommit(failures).into());
+        }
+        self.remember_committed(info);
         Ok(())
     }
 
+    async fn commit_profile(
+        &mut self,
+        io: &mut DeviceIo,
+        info: &DeviceInfo,
+        index: u32,
+    ) -> Result<()> {
+        self.commit_only = Some(index);
+        let result = self.commit(io, info).await;
+        self.commit_only = None;
+        result
+    }
+
+    /* A fixed zone effect with the persist flag clear: the color lives in
+     * RAM only, so onboard memory is not worn by a stream of updates. */
+    async fn set_led_color_live(
+        &mut self,
+        io: &mut DeviceIo,
+        led: u32,
+        color: RgbColor,
+    ) -> Result<bool> {
+        let Some((idx, function)) = self.led_effect_feature(LedMode::Solid) else {
+            return Ok(false);
+        };
+        let zone_index = u8::try_from(led).context("LED index out of range")?;
+        let mut bytes = [0u8; 13];
+        bytes[0] = zone_index;
+        bytes[1] = LED_HW_MODE_FIXED;
+        bytes[2] = color.r;
+        bytes[3] = color.g;
+        bytes[4] = color.b;
+        self.feature_request(io, idx, function, &bytes)
+            .await
+            .context("Failed to write live LED color")?;
+        /* The zone no longer shows the committed effect; rewrite it on
+         * the next commit even if the profile did not change. */
+        self.last_written_leds.remove(&led);
+        Ok(true)
+    }
+
+    /* Battery state from 0x1004 Unified Battery, or from the older 0x1000
+     * Battery Status or 0x1001 Battery Voltage on devices without it. */
+    async fn read_battery(&mut self, io: &mut DeviceIo) -> Result<Option<BatteryInfo>> {
+        if let Some(idx) = self.features.unified_battery {
+            let data = self
+                .feature_request(io, idx, UNIFIED_BATTERY_FN_GET_STATUS, &[])
+                .await
+                .context("Failed to read unified battery status")?;
+            return Ok(Some(parse_unified_battery_status(&data)));
+        }
+        if let Some(idx) = self.features.battery_status {
+            let data = self
+                .feature_request(io, idx, BATTERY_FN_GET_LEVEL_STATUS, &[])
+                .await
+                .context("Failed to read battery level status")?;
+            return Ok(Some(parse_battery_level_status(&data)));
+        }
+        if let Some(idx) = self.features.battery_voltage {
+            let data = self
+                .feature_request(io, idx, BATTERY_VOLTAGE_FN_GET_INFO, &[])
+                .await
+                .context("Failed to read battery voltage")?;
+            return Ok(Some(parse_battery_voltage(&data)));
+        }
+        Ok(None)
+    }
+
     /* Handle unsolicited HID++ 2.0 hardware events.
      *
      * The most important event is a profile-switch notification from feature
@@ -2054,3 +3241,721 @@ impl super::DeviceDriver for Hidpp20Driver {
         Ok(false)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::driver::DeviceDriver;
+    use crate::test_support::{exchanges, ScriptedIo};
+    use std::path::Path;
+
+    #[tokio::test]
+    async fn test_reprobe_clears_cached_onboard_info() {
+        let mut driver = Hidpp20Driver::new();
+        driver.features.insert(PAGE_ONBOARD_PROFILES, 0x0c);
+        driver.cached_onboard_info = Some(Hidpp20OnboardProfilesInfo::from_bytes(&[0u8; 16]));
+        driver.cached_report_rate_hz = 1000;
+        driver.needs_eeprom_repair = true;
+
+        /* A device that no longer answers the probe, e.g. one that came back
+         * with different firmware on another interface.  /dev/full rejects
+         * writes, which cuts the probe short after the first index. */
+        let mut io = DeviceIo::open(Path::new("/dev/full")).await.unwrap();
+        assert!(driver.probe(&mut io).await.is_err());
+
+        assert!(driver.features.onboard_profiles.is_none());
+        assert!(driver.cached_onboard_info.is_none());
+        assert_eq!(driver.cached_report_rate_hz, 0);
+        assert!(!driver.needs_eeprom_repair);
+    }
+
+    #[test]
+    fn test_repair_needs_onboard_profiles() {
+        let mut driver = Hidpp20Driver::new();
+        assert!(!driver.request_repair());
+        assert!(!driver.needs_repair());
+
+        driver.features.insert(PAGE_ONBOARD_PROFILES, 0x0c);
+        driver.cached_onboard_info = Some(Hidpp20OnboardProfilesInfo::from_bytes(&[0u8; 16]));
+        assert!(driver.request_repair());
+        assert!(driver.needs_repair());
+    }
+
+    #[test]
+    fn test_single_profile_commit_skips_other_sectors() {
+        let profiles: Vec<ProfileInfo> = (0..3)
+            .map(|index| ProfileInfo {
+                index,
+                is_dirty: index != 2,
+                ..Default::default()
+            })
+            .collect();
+        let written = |force_repair, only| -> Vec<u32> {
+            profiles
+                .iter()
+                .filter(|p| sector_needs_write(p, force_repair, only))
+                .map(|p| p.index)
+                .collect()
+        };
+
+        assert_eq!(written(false, None), [0, 1]);
+        assert_eq!(written(true, None), [0, 1, 2]);
+        /* Profile 0 is dirty too, but only profile 1 was asked for. */
+        assert_eq!(written(false, Some(1)), [1]);
+        assert_eq!(written(true, Some(2)), [2]);
+    }
+
+    #[test]
+    fn test_disabled_resolution_round_trips_through_dpi_slot() {
+        let mut res = crate::device::ResolutionInfo {
+            index: 2,
+            dpi: Dpi::Unified(1600),
+            ..Default::default()
+        };
+        assert_eq!(dpi_slot_value(0, &res), Some(1600));
+        assert_eq!(parse_dpi_slot(1600), (1600, false));
+
+        /* The DPI is kept in memory but not stored. */
+        res.is_disabled = true;
+        assert_eq!(dpi_slot_value(0, &res), Some(0));
+        assert_eq!(parse_dpi_slot(0), (0, true));
+        assert_eq!(parse_dpi_slot(0xFFFF), (0, true));
+
+        res.is_disabled = false;
+        res.dpi = Dpi::Unknown;
+        assert_eq!(dpi_slot_value(0, &res), None);
+    }
+
+    #[test]
+    fn test_single_profile_commit_remembers_only_that_profile() {
+        let loaded = DeviceInfo {
+            sysname: "hidraw0".to_string(),
+            profiles: (0..2)
+                .map(|index| ProfileInfo { index, report_rate: 500, ..Default::default() })
+                .collect(),
+            ..Default::default()
+        };
+        let mut driver = Hidpp20Driver::new();
+        driver.last_committed = Some(loaded.clone());
+
+        let mut pending = loaded.clone();
+        for profile in &mut pending.profiles {
+            profile.report_rate = 1000;
+        }
+        driver.commit_only = Some(1);
+        driver.remember_committed(&pending);
+        driver.commit_only = None;
+
+        /* Profile 0 still differs from the hardware; profile 1 does not. */
+        let diff = pending.diff(driver.last_committed.as_ref().unwrap());
+        assert!(diff.touches_profile(0));
+        assert!(!diff.touches_profile(1));
+
+        driver.remember_committed(&pending);
+        assert!(pending.diff(driver.last_committed.as_ref().unwrap()).is_empty());
+    }
+
+    #[test]
+    fn test_special_binding_round_trip() {
+        use crate::device::{special_action as sa, ActionType};
+
+        /* DPI cycle and mode switch bindings: the opcode sits in the byte
+         * after the type. */
+        for (bytes, special) in [
+            ([0x90, 0x05, 0x00, 0x00], sa::RESOLUTION_CYCLE_UP),
+            ([0x90, 0x0b, 0x00, 0x00], sa::SECOND_MODE),
+        ] {
+            let binding = Hidpp20ButtonBinding::from_bytes(&bytes);
+            assert_eq!(binding.to_action(), ActionType::Special);
+            assert_eq!(binding.mapping_value(), special);
+            assert_eq!(
+                Hidpp20ButtonBinding::from_action(ActionType::Special, special).into_bytes(),
+                bytes
+            );
+        }
+
+        let unknown = Hidpp20ButtonBinding::from_bytes(&[0x90, 0x42, 0x00, 0x00]);
+        assert_eq!(unknown.mapping_value(), sa::UNKNOWN);
+    }
+
+    #[test]
+    fn test_smart_shift_status_round_trip() {
+        /* getRatchetControlMode reply from an MX Master 3: ratchet mode,
+         * threshold 10, torque 50, rest zero-padded. */
+        let mut reply = [0u8; 16];
+        reply[..3].copy_from_slice(&[0x02, 0x0a, 0x32]);
+        let status = Hidpp20SmartShift::from_bytes(&reply);
+        assert_eq!(status, Hidpp20SmartShift { wheel_mode: 2, threshold: 10 });
+
+        /* Only the threshold changes; mode and torque are left alone. */
+        assert_eq!(Hidpp20SmartShift::threshold_params(30), [0x00, 30, 0x00]);
+    }
+
+    #[test]
+    fn test_smart_shift_prefers_enhanced_feature() {
+        let mut features = FeatureMap::default();
+        assert_eq!(features.smart_shift(), None);
+        features.insert(PAGE_SMART_SHIFT, 0x0a);
+        assert_eq!(features.smart_shift(), Some((0x0a, 0x00, 0x01)));
+        features.insert(PAGE_SMART_SHIFT_ENHANCED, 0x0b);
+        assert_eq!(features.smart_shift(), Some((0x0b, 0x01, 0x02)));
+    }
+
+    #[tokio::test]
+    async fn test_dpi_follows_primary_of_two_sensors() {
+        let mut driver = Hidpp20Driver::new();
+        driver.features.insert(PAGE_ADJUSTABLE_DPI, 0x05);
+        /* A regular file stands in for the device: each 20-byte request
+         * overwrites a blank slot and the canned reply after it is read
+         * back, so the replies must be laid out in request order. */
+        let reply = |function: u8, params: &[u8]| {
+            hidpp::build_hidpp20_request(DEVICE_IDX_RECEIVER, 0x05, function, SW_ID, params)
+        };
+        let replies = [
+            reply(DPI_FN_GET_SENSOR_COUNT, &[2]),
+            /* Sensor 0 is a lift-off sensor without adjustable DPI. */
+            reply(DPI_FN_GET_SENSOR_DPI_LIST, &[0]),
+            reply(DPI_FN_GET_SENSOR_DPI, &[0, 0x00, 0x00, 0x00, 0x00]),
+            /* Sensor 1 tracks: 400 and 800 DPI, currently 800. */
+            reply(DPI_FN_GET_SENSOR_DPI_LIST, &[1, 0x01, 0x90, 0x03, 0x20]),
+            reply(DPI_FN_GET_SENSOR_DPI, &[1, 0x03, 0x20, 0x01, 0x90]),
+        ];
+        let mut node = ScriptedIo::new("hidpp20-dpi", &exchanges(20, 20, &replies)).await;
+
+        let mut profile = ProfileInfo {
+            resolutions: vec![crate::device::ResolutionInfo {
+                is_active: true,
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        driver.read_dpi_info(&mut node.io, &mut profile).await.unwrap();
+
+        assert_eq!(profile.resolutions[0].dpi_list, [400, 800]);
+        assert!(matches!(profile.resolutions[0].dpi, Dpi::Unified(800)));
+        assert_eq!(driver.dpi_sensor, 1, "later writes go to the tracking sensor");
+    }
+
+    #[test]
+    fn test_8000hz_uses_extended_rate_code() {
+        let mut features = FeatureMap::default();
+        features.insert(PAGE_ADJUSTABLE_REPORT_RATE, 0x0a);
+        assert_eq!(features.report_rate(), Some((0x0a, RateFeature::Interval)));
+        features.insert(PAGE_EXTENDED_REPORT_RATE, 0x0b);
+        assert_eq!(features.report_rate(), Some((0x0b, RateFeature::Extended)));
+
+        /* 0x8061 takes a rate code, not an interval that rounds to 0 ms. */
+        assert_eq!(RateFeature::Extended.value_for_rate(8000), Some(6));
+        assert_eq!(RateFeature::Extended.value_for_rate(2000), Some(4));
+        assert_eq!(RateFeature::Extended.value_for_rate(125), Some(0));
+        assert_eq!(RateFeature::Extended.value_for_rate(333), None);
+        assert_eq!(RateFeature::Extended.rate_from_value(6), Some(8000));
+        assert_eq!(RateFeature::Extended.rate_from_value(7), None);
+        assert_eq!(
+            RateFeature::Extended.rates_from_list(&[0x00, 0b0100_1000]),
+            [1000, 8000]
+        );
+
+        assert_eq!(RateFeature::Interval.value_for_rate(8000), None);
+        assert_eq!(RateFeature::Interval.value_for_rate(500), Some(2));
+        assert_eq!(RateFeature::Interval.rate_from_value(0), None);
+    }
+
+    #[test]
+    fn test_report_rate_bitmap_maps_to_standard_rates() {
+        /* 1, 2, 3, 4 and 8 ms intervals. */
+        assert_eq!(report_rates_from_bitmap(0b1000_1111), [125, 250, 500, 1000]);
+        assert_eq!(report_rates_from_bitmap(0xFF), [125, 250, 500, 1000]);
+        assert_eq!(report_rates_from_bitmap(0b0000_0001), [1000]);
+        assert!(report_rates_from_bitmap(0b0111_0100).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_unsupported_feature_is_not_requeried() {
+        /* Every page is answered "not supported": that is final, so no
+         * lookup goes out beyond the thirteen of the first pass. */
+        let reply = hidpp::build_hidpp20_request(DEVICE_IDX_RECEIVER, ROOT_FEATURE_INDEX, ROOT_FN_GET_FEATURE, SW_ID, &[0]);
+        let script = exchanges(20, 20, &vec![reply; 13]);
+        let mut node = ScriptedIo::new("hidpp20-unsupported", &script).await;
+
+        let mut driver = Hidpp20Driver::new();
+        driver.discover_features(&mut node.io).await.unwrap();
+
+        assert_eq!(driver.features.adjustable_dpi, None);
+        assert_eq!(node.written().len(), script.len());
+    }
+
+    #[tokio::test]
+    async fn test_unanswered_feature_requeried_until_deadline() {
+        let reply = hidpp::build_hidpp20_request(DEVICE_IDX_RECEIVER, ROOT_FEATURE_INDEX, ROOT_FN_GET_FEATURE, SW_ID, &[0x05]);
+        let script = exchanges(20, 20, &[reply]);
+        let pages = [(PAGE_ADJUSTABLE_DPI, "Adjustable DPI")];
+
+        /* Out of time: the page is left alone. */
+        let mut node = ScriptedIo::new("hidpp20-requery-late", &script).await;
+        let mut driver = Hidpp20Driver::new();
+        assert_eq!(driver.requery_features(&mut node.io, &pages, Instant::now()).await, 0);
+        assert_eq!(node.written(), script);
+
+        let mut node = ScriptedIo::new("hidpp20-requery", &script).await;
+        let deadline = Instant::now() + Duration::from_secs(5);
+        assert_eq!(driver.requery_features(&mut node.io, &pages, deadline).await, 1);
+        assert_eq!(driver.features.adjustable_dpi, Some(0x05));
+    }
+
+    #[tokio::test]
+    async fn test_dpi_info_keeps_per_resolution_lists() {
+        let mut driver = Hidpp20Driver::new();
+        driver.features.insert(PAGE_ADJUSTABLE_DPI, 0x05);
+        let reply = |function: u8, params: &[u8]| {
+            hidpp::build_hidpp20_request(DEVICE_IDX_RECEIVER, 0x05, function, SW_ID, params)
+        };
+        let replies = [
+            reply(DPI_FN_GET_SENSOR_COUNT, &[1]),
+            reply(DPI_FN_GET_SENSOR_DPI_LIST, &[0, 0x01, 0x90, 0x03, 0x20]),
+            reply(DPI_FN_GET_SENSOR_DPI, &[0, 0x01, 0x90, 0x01, 0x90]),
+        ];
+        let mut node = ScriptedIo::new("hidpp20-dpi-lists", &exchanges(20, 20, &replies)).await;
+
+        let mut profile = ProfileInfo {
+            resolutions: vec![
+                crate::device::ResolutionInfo {
+                    index: 0,
+                    is_active: true,
+                    dpi_list: vec![100, 200, 300],
+                    ..Default::default()
+                },
+                crate::device::ResolutionInfo {
+                    index: 1,
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        driver.read_dpi_info(&mut node.io, &mut profile).await.unwrap();
+
+        assert_eq!(profile.resolutions[0].dpi_list, [100, 200, 300]);
+        assert_eq!(profile.resolutions[1].dpi_list, [400, 800]);
+        assert!(matches!(profile.resolutions[0].dpi, Dpi::Unified(400)));
+    }
+
+    #[test]
+    fn test_wave_direction_byte_needs_the_quirk() {
+        let mut bytes = [0u8; 11];
+        bytes[0] = LED_HW_MODE_COLOR_WAVE;
+        bytes[9] = hidpp::LED_WAVE_DIRECTION_COUNTER_CLOCKWISE;
+
+        let led = Hidpp20Driver::parse_eeprom_led(&bytes, 0, false);
+        assert_eq!(led.effect_direction, crate::device::LedDirection::Clockwise);
+        let mut forced = led.clone();
+        forced.effect_direction = crate::device::LedDirection::CounterClockwise;
+        assert_eq!(Hidpp20Driver::serialize_eeprom_led(&forced)[9], 0);
+
+        let led = Hidpp20Driver::parse_eeprom_led(&bytes, 0, true);
+        assert_eq!(led.effect_direction, crate::device::LedDirection::CounterClockwise);
+        assert_eq!(Hidpp20Driver::serialize_eeprom_led(&led), bytes);
+    }
+
+    #[tokio::test]
+    async fn test_unmanaged_led_is_never_written() {
+        let mut driver = Hidpp20Driver::new();
+        driver.features.insert(PAGE_COLOR_LED_EFFECTS, 0x07);
+        let mut node = ScriptedIo::empty("hidpp20-unmanaged").await;
+
+        /* Not in the write cache yet, so a managed zone would be written
+         * even though it is clean. */
+        let mut led = Hidpp20Driver::parse_eeprom_led(&[0u8; 11], 0, false);
+        led.unmanaged = true;
+        let profile = ProfileInfo {
+            leds: vec![led],
+            ..Default::default()
+        };
+        let failed = driver.write_led_info(&mut node.io, &profile).await;
+        let written = node.written();
+        assert!(failed.is_empty());
+        assert!(written.is_empty());
+    }
+
+    /* Two solid zones whose effects are already on the device, with
+     * zone `changed` dimmed to half brightness. */
+    fn dimmed_profile(driver: &mut Hidpp20Driver, zones: u32, changed: u32) -> ProfileInfo {
+        let mut leds: Vec<LedInfo> = (0..zones)
+            .map(|index| LedInfo {
+                index,
+                mode: LedMode::Solid,
+                color: Color { red: 255, green: 0, blue: 0 },
+                brightness: 255,
+                ..Default::default()
+            })
+            .collect();
+        for led in &leds {
+            driver.last_written_leds.insert(led.index, led.clone());
+        }
+        leds[changed as usize].brightness = 128;
+        leds[changed as usize].is_dirty = true;
+        ProfileInfo {
+            leds,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_brightness_of_one_zone_rewrites_only_that_zone() {
+        let mut driver = Hidpp20Driver::new();
+        driver.features.insert(PAGE_COLOR_LED_EFFECTS, 0x07);
+        driver.features.insert(PAGE_BRIGHTNESS_CONTROL, 0x08);
+        let profile = dimmed_profile(&mut driver, 2, 1);
+        let reply = hidpp::build_hidpp20_request(DEVICE_IDX_RECEIVER, 0x07, LED_FN_SET_ZONE_EFFECT, SW_ID, &[]);
+        let mut node = ScriptedIo::new("hidpp20-zone-brightness", &exchanges(20, 20, &[reply])).await;
+
+        let failed = driver.write_led_info(&mut node.io, &profile).await;
+        assert!(failed.is_empty());
+        let written = node.written();
+        assert_eq!(written[2], 0x07, "0x8070, not the device-wide 0x8040");
+        assert_eq!(written[3] >> 4, LED_FN_SET_ZONE_EFFECT);
+        assert_eq!(written[4], 1, "the dimmed zone");
+        assert_eq!(written[5..16], hidpp::build_led_payload(&profile.leds[1]));
+        assert_eq!(driver.last_written_leds[&0].brightness, 255);
+    }
+
+    #[tokio::test]
+    async fn test_brightness_of_single_zone_uses_brightness_control() {
+        let mut driver = Hidpp20Driver::new();
+        driver.features.insert(PAGE_COLOR_LED_EFFECTS, 0x07);
+        driver.features.insert(PAGE_BRIGHTNESS_CONTROL, 0x08);
+        let profile = dimmed_profile(&mut driver, 1, 0);
+        let reply = |function: u8, params: &[u8]| {
+            hidpp::build_hidpp20_request(DEVICE_IDX_RECEIVER, 0x08, function, SW_ID, params)
+        };
+        let replies = [
+            reply(BRIGHTNESS_FN_GET_INFO, &[0x00, 100]),
+            reply(BRIGHTNESS_FN_SET_BRIGHTNESS, &[]),
+        ];
+        let mut node = ScriptedIo::new("hidpp20-device-brightness", &exchanges(20, 20, &replies)).await;
+
+        let failed = driver.write_led_info(&mut node.io, &profile).await;
+        assert!(failed.is_empty());
+        let written = node.written();
+        assert_eq!(written[40 + 2], 0x08);
+        assert_eq!(written[40 + 3] >> 4, BRIGHTNESS_FN_SET_BRIGHTNESS);
+        assert_eq!(written[44..46], [0, 50], "128 of 255 on a 0-100 scale");
+    }
+
+    #[test]
+    fn test_battery_status_replies() {
+        /* 0x1000: 62 %, recharging. */
+        assert_eq!(
+            parse_battery_level_status(&[62, 50, 1, 0]),
+            BatteryInfo { level: 62, charging: true }
+        );
+        assert!(!parse_battery_level_status(&[100, 0, 3, 0]).charging);
+
+        /* 0x1001: 3.8 V (0x0ed8) on battery, halfway between 40 and 50 %;
+         * then full-scale voltages while charging and a drained cell. */
+        assert_eq!(
+            parse_battery_voltage(&[0x0e, 0xd8, 0x00]),
+            BatteryInfo { level: 46, charging: false }
+        );
+        assert_eq!(
+            parse_battery_voltage(&[0x10, 0x68, 0x80]),
+            BatteryInfo { level: 100, charging: true }
+        );
+        assert_eq!(parse_battery_voltage(&[0x0d, 0x00, 0x81]).level, 0);
+        assert!(!parse_battery_voltage(&[0x10, 0x68, 0x81]).charging);
+
+        /* 0x1004: 87 %, discharging; then a device that only reports "good". */
+        assert_eq!(
+            parse_unified_battery_status(&[87, 0x04, 0, 0]),
+            BatteryInfo { level: 87, charging: false }
+        );
+        assert_eq!(
+            parse_unified_battery_status(&[0, 0x04, 2, 1]),
+            BatteryInfo { level: 50, charging: true }
+        );
+    }
+
+    /* A solid zone written by a driver with the RGB_EFFECTS_PREFERRED
+     * quirk, with the given LED features at index 0x07 (0x8070) and 0x0e
+     * (0x8071), answered from feature `index` function `function`.
+     * Returns what went out. */
+    async fn write_preferred_zone(features: &[(u16, u8)], (index, function): (u8, u8)) -> Vec<u8> {
+        let mut driver = Hidpp20Driver::new();
+        driver.rgb_effects_preferred = true;
+        for &(page, index) in features {
+            driver.features.insert(page, index);
+        }
+        let profile = ProfileInfo {
+            leds: vec![LedInfo {
+                mode: LedMode::Solid,
+                color: Color { red: 0, green: 255, blue: 0 },
+                is_dirty: true,
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let reply = hidpp::build_hidpp20_request(DEVICE_IDX_RECEIVER, index, function, SW_ID, &[]);
+        let mut node = ScriptedIo::new("hidpp20-rgb-preferred", &exchanges(20, 20, &[reply])).await;
+        assert!(driver.write_led_info(&mut node.io, &profile).await.is_empty());
+        node.written()
+    }
+
+    #[tokio::test]
+    async fn test_rgb_effects_preferred_writes_every_mode_through_8071() {
+        let both = [(PAGE_COLOR_LED_EFFECTS, 0x07), (PAGE_RGB_EFFECTS, 0x0e)];
+        let written = write_preferred_zone(&both, (0x0e, RGB_EFFECTS_FN_SET_MULTI_LED_RGB_CLUSTER_PATTERN)).await;
+        assert_eq!(written[2], 0x0e);
+        assert_eq!(written[3] >> 4, RGB_EFFECTS_FN_SET_MULTI_LED_RGB_CLUSTER_PATTERN);
+        assert_eq!(written[5..9], [LED_HW_MODE_FIXED, 0, 255, 0]);
+
+        /* Without 0x8071 the quirk falls back to 0x8070. */
+        let written = write_preferred_zone(&[(PAGE_COLOR_LED_EFFECTS, 0x07)], (0x07, LED_FN_SET_ZONE_EFFECT)).await;
+        assert_eq!(written[2], 0x07);
+        assert_eq!(written[3] >> 4, LED_FN_SET_ZONE_EFFECT);
+    }
+
+    #[tokio::test]
+    async fn test_rgb_effects_preferred_reads_cluster_count() {
+        let mut driver = Hidpp20Driver::new();
+        driver.rgb_effects_preferred = true;
+        driver.features.insert(PAGE_COLOR_LED_EFFECTS, 0x07);
+        driver.features.insert(PAGE_RGB_EFFECTS, 0x0e);
+        let mut profile = ProfileInfo {
+            leds: (0..3).map(|index| LedInfo { index, ..Default::default() }).collect(),
+            ..Default::default()
+        };
+        let reply = hidpp::build_hidpp20_request(
+            DEVICE_IDX_RECEIVER, 0x0e, RGB_EFFECTS_FN_GET_INFO, SW_ID, &[0xFF, 0xFF, 2],
+        );
+        let mut node = ScriptedIo::new("hidpp20-rgb-clusters", &exchanges(20, 20, &[reply])).await;
+
+        driver.read_led_info(&mut node.io, &mut profile).await.unwrap();
+        let written = node.written();
+        assert_eq!(written[2], 0x0e, "read through 0x8071, not 0x8070");
+        assert_eq!(written[3] >> 4, RGB_EFFECTS_FN_GET_INFO);
+        let unmanaged: Vec<bool> = profile.leds.iter().map(|led| led.unmanaged).collect();
+        assert_eq!(unmanaged, [false, false, true]);
+        assert!(driver.last_written_leds.is_empty(), "effects cannot be read back");
+    }
+
+    #[test]
+    fn test_tricolor_needs_rgb_effects() {
+        let mut features = FeatureMap::default();
+        features.insert(PAGE_COLOR_LED_EFFECTS, 0x0d);
+        let modes = features.led_modes();
+        assert!(modes.contains(&LedMode::Solid));
+        assert!(!modes.contains(&LedMode::TriColor));
+
+        features.insert(PAGE_RGB_EFFECTS, 0x0e);
+        assert!(features.led_modes().contains(&LedMode::TriColor));
+    }
+
+    /* Ctrl+A, a 300 ms wait, then B: `(value, kind)` as ratbagctl sends. */
+    const CTRL_A_WAIT_B: [(u32, u32); 7] = [
+        (0xE0, MACRO_ENTRY_PRESS),
+        (0x04, MACRO_ENTRY_PRESS),
+        (0x04, MACRO_ENTRY_RELEASE),
+        (0xE0, MACRO_ENTRY_RELEASE),
+        (300, MACRO_ENTRY_WAIT),
+        (0x05, MACRO_ENTRY_PRESS),
+        (0x05, MACRO_ENTRY_RELEASE),
+    ];
+
+    #[test]
+    fn test_macro_round_trips_through_encoding() {
+        let bytes = encode_macro(&CTRL_A_WAIT_B);
+        assert_eq!(
+            bytes,
+            [
+                MACRO_KEY_PRESS, 0x01, 0, MACRO_KEY_PRESS, 0, 0x04,
+                MACRO_KEY_RELEASE, 0, 0x04, MACRO_KEY_RELEASE, 0x01, 0,
+                MACRO_DELAY, 0x01, 0x2C,
+                MACRO_KEY_PRESS, 0, 0x05, MACRO_KEY_RELEASE, 0, 0x05,
+                MACRO_END,
+            ]
+        );
+        let mut entries = Vec::new();
+        assert_eq!(decode_macro(&bytes, 0, &mut entries), MacroNext::End);
+        assert_eq!(entries, CTRL_A_WAIT_B);
+
+        /* A key event carrying its modifiers, as other software writes
+         * them, and a no-op in between. */
+        let mut entries = Vec::new();
+        let bytes = [MACRO_KEY_PRESS, 0x02, 0x04, MACRO_NOOP, MACRO_KEY_RELEASE, 0x02, 0x04, MACRO_END];
+        assert_eq!(decode_macro(&bytes, 0, &mut entries), MacroNext::End);
+        assert_eq!(entries, [(0xE1, 1), (0x04, 1), (0x04, 0), (0xE1, 0)]);
+    }
+
+    #[test]
+    fn test_macro_decode_is_bounded() {
+        let mut entries = Vec::new();
+        let jump = [MACRO_NOOP, MACRO_JUMP, 0x07, 0, 0x20];
+        assert_eq!(decode_macro(&jump, 0, &mut entries), MacroNext::Jump { page: 0x07, offset: 0x20 });
+
+        /* Erased flash is MACRO_END; a macro cut off by the end of the
+         * sector or an unknown opcode is not. */
+        assert_eq!(decode_macro(&[MACRO_DELAY, 0x00], 0, &mut entries), MacroNext::Truncated);
+        assert_eq!(decode_macro(&[0x42, MACRO_END], 0, &mut entries), MacroNext::Truncated);
+        assert!(entries.is_empty());
+
+        /* Known instructions with no DBus entry stop the decode. */
+        let repeat = [MACRO_KEY_PRESS, 0, 0x04, MACRO_REPEAT, MACRO_END];
+        assert_eq!(decode_macro(&repeat, 0, &mut entries), MacroNext::Unsupported(MACRO_REPEAT));
+        let consumer = [MACRO_CONSUMER_CONTROL, 0x00, 0xE9, MACRO_END];
+        assert_eq!(decode_macro(&consumer, 0, &mut entries), MacroNext::Unsupported(MACRO_CONSUMER_CONTROL));
+        entries.clear();
+
+        let endless = [MACRO_KEY_PRESS, 0, 0x04].repeat(MAX_MACRO_EVENTS + 1);
+        assert_eq!(decode_macro(&endless, 0, &mut entries), MacroNext::Truncated);
+        assert_eq!(entries.len(), MAX_MACRO_EVENTS);
+    }
+
+    #[test]
+    fn test_macro_sector_packs_every_macro_it_fits() {
+        use crate::device::ButtonInfo;
+        let macro_button = |index, entries: &[(u32, u32)]| ButtonInfo {
+            index,
+            action_type: ActionType::Macro,
+            macro_entries: entries.to_vec(),
+            ..Default::default()
+        };
+        let profile = ProfileInfo {
+            index: 1,
+            buttons: vec![
+                macro_button(0, &CTRL_A_WAIT_B[5..]),
+                ButtonInfo { index: 1, action_type: ActionType::Key, mapping_value: 0x04, ..Default::default() },
+                macro_button(2, &CTRL_A_WAIT_B),
+                macro_button(3, &CTRL_A_WAIT_B[4..5]),
+            ],
+            ..Default::default()
+        };
+
+        /* 24 bytes hold 22 of macros: 7, then 22 that do not fit after
+         * them, then 4. */
+        let (sector, offsets) = build_macro_sector(&profile, 24);
+        assert!(Hidpp20Driver::verify_sector_crc(0x0007, &sector));
+        assert_eq!(offsets[0], (0, Ok(0)));
+        assert_eq!(offsets[1].0, 2);
+        assert!(offsets[1].1.is_err());
+        assert_eq!(offsets[2], (3, Ok(7)));
+        for (index, offset) in [(0, 0), (3, 7)] {
+            let mut entries = Vec::new();
+            assert_eq!(decode_macro(&sector[..22], offset, &mut entries), MacroNext::End);
+            assert_eq!(entries, profile.buttons[index].macro_entries);
+        }
+
+        /* Profile 1 of three: after sectors 1-3 and the macros of
+         * profile 0, as long as the memory has the sector. */
+        let mut desc = [0u8; 16];
+        desc[3] = 3;
+        desc[6] = 8;
+        assert_eq!(Hidpp20OnboardProfilesInfo::from_bytes(&desc).macro_sector(1), Some(5));
+        desc[6] = 5;
+        assert_eq!(Hidpp20OnboardProfilesInfo::from_bytes(&desc).macro_sector(1), None);
+
+        let binding = Hidpp20ButtonBinding::macro_at(5, 7);
+        assert_eq!(binding.into_bytes(), [BUTTON_TYPE_MACRO, 5, 0, 7]);
+        assert_eq!(binding.to_action(), ActionType::Macro);
+        assert_eq!(binding.macro_address(), Some((5, 7)));
+    }
+
+    #[tokio::test]
+    async fn test_macro_is_read_across_a_jump() {
+        let driver = Hidpp20Driver::new();
+        let mut first = [0xFFu8; 16];
+        first[4..8].copy_from_slice(&[MACRO_KEY_PRESS, 0, 0x04, MACRO_JUMP]);
+        first[8..11].copy_from_slice(&[0x06, 0, 0x02]);
+        let mut second = [0xFFu8; 16];
+        second[2..6].copy_from_slice(&[MACRO_KEY_RELEASE, 0, 0x04, MACRO_END]);
+        let replies: Vec<_> = [first, second]
+            .iter()
+            .map(|data| {
+                hidpp::build_hidpp20_request(DEVICE_IDX_RECEIVER, 0x0c, PROFILES_FN_MEMORY_READ, SW_ID, data)
+            })
+            .collect();
+        let mut node = ScriptedIo::new("hidpp20-macro-jump", &exchanges(20, 20, &replies)).await;
+
+        let mut sectors = HashMap::new();
+        let stored = driver
+            .read_macro(&mut node.io, 0x0c, 16, (0x05, 4), &mut sectors)
+            .await
+            .unwrap();
+        assert_eq!(stored.entries, [(0x04, MACRO_ENTRY_PRESS), (0x04, MACRO_ENTRY_RELEASE)]);
+        assert_eq!(stored.pages, BTreeSet::from([0x05, 0x06]));
+        assert!(stored.complete);
+        let written = node.written();
+        assert_eq!(written[4..6], [0x00, 0x05]);
+        assert_eq!(written[44..46], [0x00, 0x06]);
+        assert_eq!(sectors.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_macros_rewritten_only_when_changed_and_complete() {
+        use crate::device::ButtonInfo;
+        let mut driver = Hidpp20Driver::new();
+        let mut desc = [0u8; 16];
+        desc[3] = 2;
+        desc[6] = 8;
+        desc[7..9].copy_from_slice(&16u16.to_be_bytes());
+        driver.cached_onboard_info = Some(Hidpp20OnboardProfilesInfo::from_bytes(&desc));
+        let profile = ProfileInfo {
+            index: 0,
+            buttons: (0..2)
+                .map(|index| ButtonInfo {
+                    index,
+                    action_type: ActionType::Macro,
+                    macro_entries: CTRL_A_WAIT_B[5..].to_vec(),
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        };
+        let mut node = ScriptedIo::empty("hidpp20-macro-commit").await;
+        let mut failures = Vec::new();
+
+        /* Nothing changed: the stored bindings stay, nothing is written. */
+        let bindings = driver.commit_macros(&mut node.io, 0x0c, &profile, &[], false, &mut failures).await;
+        assert!(bindings.is_empty());
+        assert!(failures.is_empty());
+
+        /* Button 1's stored macro did not decode to its end: changing
+         * button 0 would lose it, so the sector is not touched. */
+        driver.undecoded_macros.insert((0, 1));
+        let bindings = driver.commit_macros(&mut node.io, 0x0c, &profile, &[0], false, &mut failures).await;
+        assert!(bindings.is_empty());
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].0, CommitTarget::Button { profile: 0, index: 0 });
+        assert!(node.written().is_empty());
+    }
+
+    #[test]
+    fn test_macro_sector_is_not_shared_between_profiles() {
+        let mut driver = Hidpp20Driver::new();
+        let mut raw = [0u8; 16];
+        raw[3] = 2;
+        raw[6] = 8;
+        let desc = Hidpp20OnboardProfilesInfo::from_bytes(&raw);
+
+        /* Nothing stored: each profile gets its own slot after the
+         * profile sectors. */
+        assert_eq!(driver.macro_sector_for(&desc, 0), Some(3));
+        assert_eq!(driver.macro_sector_for(&desc, 1), Some(4));
+
+        /* Profile 0's macros already live alone in sector 6. */
+        driver.macro_pages.insert(0, BTreeSet::from([6]));
+        assert_eq!(driver.macro_sector_for(&desc, 0), Some(6));
+
+        /* Profile 1 shares sector 6 and has macros in its slot too. */
+        driver.macro_pages.insert(1, BTreeSet::from([4, 6]));
+        assert_eq!(driver.macro_sector_for(&desc, 0), Some(3));
+        assert_eq!(driver.macro_sector_for(&desc, 1), Some(4));
+
+        /* Profile 0's slot holds a macro of profile 1: no room left. */
+        driver.macro_pages.insert(1, BTreeSet::from([3, 6]));
+        assert_eq!(driver.macro_sector_for(&desc, 0), None);
+
+        /* A profile sector is never a macro sector. */
+        driver.macro_pages.clear();
+        driver.macro_pages.insert(0, BTreeSet::from([1]));
+        assert_eq!(driver.macro_sector_for(&desc, 0), Some(3));
+    }
+}
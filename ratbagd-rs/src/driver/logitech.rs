@@ -0,0 +1,256 @@
+/* Logitech HID++ auto-detecting driver.
+ *
+ * Selected with `Driver=logitech` in a `.device` file.  At probe time it
+ * tries HID++ 2.0 first and falls back to HID++ 1.0, then delegates every
+ * later call to whichever protocol answered.  Device files for the
+ * Logitech lineup no longer need to know the protocol version up front. */
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use tracing::debug;
+
+use crate::device::DeviceInfo;
+use crate::driver::{hidpp10, hidpp20, DeviceDriver, DeviceIo};
+
+pub struct LogitechDriver {
+    /* Protocols to try, in priority order.  Kept across probes so that a
+     * re-probe starts from HID++ 2.0 again. */
+    candidates: Vec<Box<dyn DeviceDriver>>,
+    /* Index into `candidates` of the protocol that answered the probe. */
+    selected: Option<usize>,
+}
+
+impl LogitechDriver {
+    pub fn new() -> Self {
+        Self::with_candidates(vec![
+            Box::new(hidpp20::Hidpp20Driver::new()),
+            Box::new(hidpp10::Hidpp10Driver::new()),
+        ])
+    }
+
+    fn with_candidates(candidates: Vec<Box<dyn DeviceDriver>>) -> Self {
+        Self {
+            candidates,
+            selected: None,
+        }
+    }
+
+    fn selected(&self) -> Option<&dyn DeviceDriver> {
+        self.selected.map(|i| self.candidates[i].as_ref())
+    }
+
+    fn inner(&mut self) -> Result<&mut Box<dyn DeviceDriver>> {
+        let i = self
+            .selected
+            .ok_or_else(|| anyhow!("Logitech: no HID++ protocol selected; probe first"))?;
+        Ok(&mut self.candidates[i])
+    }
+}
+
+impl Default for LogitechDriver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl DeviceDriver for LogitechDriver {
+    fn name(&self) -> &str {
+        match self.selected() {
+            Some(inner) => inner.name(),
+            None => "Logitech HID++ (auto-detect)",
+        }
+    }
+
+    fn action_types(&self) -> &'static [crate::device::ActionType] {
+        match self.selected() {
+            Some(inner) => inner.action_types(),
+            None => &[],
+        }
+    }
+
+    fn supports_macro_repeat(&self) -> bool {
+        self.selected().is_some_and(|inner| inner.supports_macro_repeat())
+    }
+
+    fn listens_for_events(&self) -> bool {
+        self.selected().is_some_and(|inner| inner.listens_for_events())
+    }
+
+    fn stores_profile_names(&self) -> bool {
+        self.selected().is_some_and(|inner| inner.stores_profile_names())
+    }
+
+    fn is_ready(&self) -> bool {
+        self.selected().is_none_or(|inner| inner.is_ready())
+    }
+
+    fn needs_repair(&self) -> bool {
+        self.selected().is_some_and(|inner| inner.needs_repair())
+    }
+
+    fn request_repair(&mut self) -> bool {
+        self.inner().is_ok_and(|inner| inner.request_repair())
+    }
+
+    fn default_button_mapping(&self, index: u32) -> Option<(crate::device::ActionType, u32)> {
+        self.selected().and_then(|inner| inner.default_button_mapping(index))
+    }
+
+    /* Before the probe, only what every candidate protocol supports. */
+    fn capabilities(&self) -> crate::driver::DriverCapabilities {
+        match self.selected() {
+            Some(inner) => inner.capabilities(),
+            None => self
+                .candidates
+                .iter()
+                .map(|c| c.capabilities())
+                .reduce(crate::driver::DriverCapabilities::intersection)
+                .unwrap_or_default(),
+        }
+    }
+
+    /* Both protocols are probed within the actor's single probe budget; a
+     * failed HID++ 2.0 probe costs at most one read timeout per device
+     * index, which leaves room for the HID++ 1.0 fallback.  Each probe
+     * starts over so a reconnect can land on a different protocol. */
+    async fn probe(&mut self, io: &mut DeviceIo) -> Result<()> {
+        self.selected = None;
+        let mut errors = Vec::new();
+        for (i, candidate) in self.candidates.iter_mut().enumerate() {
+            match candidate.probe(io).await {
+                Ok(()) => {
+                    debug!("Logitech: {} answered the probe", candidate.name());
+                    self.selected = Some(i);
+                    return Ok(());
+                }
+                Err(e) => {
+                    debug!("Logitech: {} probe failed: {e:#}", candidate.name());
+                    errors.push(format!("{}: {e:#}", candidate.name()));
+                }
+            }
+        }
+        Err(anyhow!(
+            "Logitech: no HID++ protocol answered ({})",
+            errors.join("; ")
+        ))
+    }
+
+    async fn load_profiles(&mut self, io: &mut DeviceIo, info: &mut DeviceInfo) -> Result<()> {
+        self.inner()?.load_profiles(io, info).await
+    }
+
+    async fn commit(&mut self, io: &mut DeviceIo, info: &DeviceInfo) -> Result<()> {
+        self.inner()?.commit(io, info).await
+    }
+
+    async fn commit_profile(
+        &mut self,
+        io: &mut DeviceIo,
+        info: &DeviceInfo,
+        index: u32,
+    ) -> Result<()> {
+        self.inner()?.commit_profile(io, info, index).await
+    }
+
+    async fn switch_profile(&mut self, io: &mut DeviceIo, index: u32) -> Result<bool> {
+        self.inner()?.switch_profile(io, index).await
+    }
+
+    async fn set_led_color_live(
+        &mut self,
+        io: &mut DeviceIo,
+        led: u32,
+        color: crate::device::RgbColor,
+    ) -> Result<bool> {
+        self.inner()?.set_led_color_live(io, led, color).await
+    }
+
+    async fn read_battery(
+        &mut self,
+        io: &mut DeviceIo,
+    ) -> Result<Option<crate::device::BatteryInfo>> {
+        self.inner()?.read_battery(io).await
+    }
+
+    async fn handle_event(&mut self, report: &[u8], info: &mut DeviceInfo) -> Result<bool> {
+        match self.selected {
+            Some(i) => self.candidates[i].handle_event(report, info).await,
+            None => Ok(false),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+    use std::sync::atomic::Ordering;
+    use std::sync::Arc;
+
+    use crate::test_support::MockDriver;
+
+    fn logitech(hidpp20_ok: bool, hidpp10_ok: bool) -> LogitechDriver {
+        LogitechDriver::with_candidates(vec![
+            MockDriver::new("hidpp20", hidpp20_ok).boxed(),
+            MockDriver::new("hidpp10", hidpp10_ok).boxed(),
+        ])
+    }
+
+    #[tokio::test]
+    async fn test_falls_back_to_hidpp10() {
+        let mut io = DeviceIo::open(Path::new("/dev/null")).await.unwrap();
+        let mut driver = logitech(false, true);
+        driver.probe(&mut io).await.unwrap();
+        assert_eq!(driver.name(), "hidpp10");
+    }
+
+    #[tokio::test]
+    async fn test_prefers_hidpp20() {
+        let mut io = DeviceIo::open(Path::new("/dev/null")).await.unwrap();
+        let mut driver = logitech(true, true);
+        driver.probe(&mut io).await.unwrap();
+        assert_eq!(driver.name(), "hidpp20");
+    }
+
+    #[tokio::test]
+    async fn test_no_protocol_answers() {
+        let mut io = DeviceIo::open(Path::new("/dev/null")).await.unwrap();
+        let mut driver = logitech(false, false);
+        let err = driver.probe(&mut io).await.unwrap_err().to_string();
+        assert!(err.contains("hidpp20: no response"));
+        assert!(err.contains("hidpp10: no response"));
+        assert_eq!(driver.name(), "Logitech HID++ (auto-detect)");
+    }
+
+    #[tokio::test]
+    async fn test_readiness_comes_from_the_selected_protocol() {
+        let mut io = DeviceIo::open(Path::new("/dev/null")).await.unwrap();
+        let asleep = MockDriver {
+            name: "hidpp20",
+            asleep_probes: 1,
+            ..Default::default()
+        };
+        let mut driver = LogitechDriver::with_candidates(vec![asleep.boxed()]);
+        driver.probe(&mut io).await.unwrap();
+        assert!(!driver.is_ready());
+        driver.probe(&mut io).await.unwrap();
+        assert!(driver.is_ready());
+    }
+
+    #[tokio::test]
+    async fn test_reprobe_starts_from_hidpp20() {
+        let mut io = DeviceIo::open(Path::new("/dev/null")).await.unwrap();
+        let hidpp20 = MockDriver::new("hidpp20", false);
+        let hidpp20_state = Arc::clone(&hidpp20.state);
+        let mut driver =
+            LogitechDriver::with_candidates(vec![hidpp20.boxed(), MockDriver::new("hidpp10", true).boxed()]);
+        driver.probe(&mut io).await.unwrap();
+        assert_eq!(driver.name(), "hidpp10");
+
+        /* Firmware update: the device now speaks HID++ 2.0. */
+        hidpp20_state.probe_fails.store(false, Ordering::SeqCst);
+        driver.probe(&mut io).await.unwrap();
+        assert_eq!(driver.name(), "hidpp20");
+    }
+}
@@ -1,4 +1,4 @@
-use crate::device::DeviceInfo;
+use crate::device::{ActionType, DeviceInfo};
 use crate::driver::{DeviceDriver, DeviceIo};
 use anyhow::Result;
 use async_trait::async_trait;
@@ -103,6 +103,24 @@ impl DeviceDriver for LogitechG300Driver {
         "Logitech G300"
     }
 
+    /* Macros are flattened to a single key on commit, so they are not advertised. */
+    fn action_types(&self) -> &'static [ActionType] {
+        &[
+            ActionType::None,
+            ActionType::Button,
+            ActionType::Special,
+            ActionType::Key,
+        ]
+    }
+
+    fn capabilities(&self) -> super::DriverCapabilities {
+        super::DriverCapabilities {
+            profiles: true,
+            leds: true,
+            ..super::DriverCapabilities::from_action_types(self.action_types())
+        }
+    }
+
     async fn probe(&mut self, _io: &mut DeviceIo) -> Result<()> {
         debug!("Probe called for Logitech G300");
         Ok(())
@@ -145,6 +163,8 @@ impl DeviceDriver for LogitechG300Driver {
                 angle_snapping: -1,
                 debounce: -1,
                 debounces: Vec::new(),
+                lift_off_distance: 0,
+                lift_off_distances: Vec::new(),
                 capabilities: Vec::new(),
             };
 
@@ -164,9 +184,11 @@ impl DeviceDriver for LogitechG300Driver {
                 profile.buttons.push(crate::device::ButtonInfo {
                     index: btn_id,
                     action_type: crate::device::ActionType::Unknown,
-                    action_types: vec![0, 1, 2, 3, 4],
+                    action_types: self.action_types().iter().map(|t| *t as u32).collect(),
                     mapping_value: 0,
                     macro_entries: Vec::new(),
+                    macro_repeat: crate::device::MacroRepeat::Once,
+                    default_mapping: None,
                 });
             }
 
@@ -179,7 +201,11 @@ impl DeviceDriver for LogitechG300Driver {
                 tertiary_color: crate::device::Color::default(),
                 color_depth: 1,
                 effect_duration: 0,
+                effect_direction: crate::device::LedDirection::Clockwise,
+                has_direction: false,
                 brightness: 255,
+                is_dirty: false,
+                unmanaged: false,
             });
 
             info.profiles.push(profile);
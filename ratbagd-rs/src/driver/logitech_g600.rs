@@ -346,12 +346,30 @@ impl DeviceDriver for LG600Driver {
         "Logitech G600"
     }
 
+    /* Matches the C driver's supported action types. */
+    fn action_types(&self) -> &'static [ActionType] {
+        &[
+            ActionType::None,
+            ActionType::Button,
+            ActionType::Special,
+            ActionType::Macro,
+        ]
+    }
+
+    fn capabilities(&self) -> super::DriverCapabilities {
+        super::DriverCapabilities {
+            profiles: true,
+            leds: true,
+            ..super::DriverCapabilities::from_action_types(self.action_types())
+        }
+    }
+
     async fn probe(&mut self, io: &mut DeviceIo) -> Result<()> {
         /* Read the active profile report to confirm the device responds.
          * C: logitech_g600_get_active_profile_and_resolution (line 195). */
         let mut active_buf = [0u8; 4];
         active_buf[0] = REPORT_ID_GET_ACTIVE;
-        io.get_feature_report(&mut active_buf)
+        io.get_feature_report_min(&mut active_buf, 4)
             .map_err(anyhow::Error::from)
             .context("G600: failed to read active profile report")?;
 
@@ -376,7 +394,7 @@ impl DeviceDriver for LG600Driver {
             let mut buf = [0u8; REPORT_SIZE_PROFILE];
             buf[0] = PROFILE_REPORT_IDS[i];
 
-            io.get_feature_report(&mut buf)
+            io.get_feature_report_min(&mut buf, REPORT_SIZE_PROFILE)
                 .map_err(anyhow::Error::from)
                 .with_context(|| format!("G600: failed to read profile {i} report"))?;
 
@@ -429,12 +447,7 @@ impl DeviceDriver for LG600Driver {
 
             /* --- Buttons (41 total) --- */
             /* C lines 296-299: supported action types */
-            let action_types = vec![
-                ActionType::None as u32,
-                ActionType::Button as u32,
-                ActionType::Special as u32,
-                ActionType::Macro as u32,
-            ];
+            let action_types: Vec<u32> = self.action_types().iter().map(|t| *t as u32).collect();
 
             let mut buttons = Vec::with_capacity(NUM_BUTTONS);
             for b in 0..NUM_BUTTONS {
@@ -454,6 +467,8 @@ impl DeviceDriver for LG600Driver {
                     action_types: action_types.clone(),
                     mapping_value,
                     macro_entries: Vec::new(),
+                    macro_repeat: crate::device::MacroRepeat::Once,
+                    default_mapping: None,
                 });
             }
 
@@ -489,7 +504,11 @@ impl DeviceDriver for LG600Driver {
                 tertiary_color: Color::default(),
                 color_depth: 0,
                 effect_duration,
+                effect_direction: crate::device::LedDirection::Clockwise,
+                has_direction: false,
                 brightness: 255,
+                is_dirty: false,
+                unmanaged: false,
             };
 
             let profile = ProfileInfo {
@@ -503,6 +522,8 @@ impl DeviceDriver for LG600Driver {
                 angle_snapping: -1,
                 debounce: -1,
                 debounces: Vec::new(),
+                lift_off_distance: 0,
+                lift_off_distances: Vec::new(),
                 capabilities: Vec::new(),
                 resolutions,
                 buttons,
@@ -556,22 +577,24 @@ impl DeviceDriver for LG600Driver {
                     continue;
                 }
 
-                let dpi_val = match res.dpi {
-                    Dpi::Unified(v) => v,
-                    Dpi::Separate { x, .. } => x,
-                    Dpi::Unknown => 0,
-                };
-
-                report.dpi[r_idx] = if res.is_disabled || dpi_val == 0 {
-                    0
+                /* An unknown DPI keeps the slot as read from the device. */
+                let dpi_val = if res.is_disabled {
+                    Some(0)
                 } else {
-                    dpi_to_raw(dpi_val).unwrap_or_else(|| {
-                        /* Clamp to nearest valid value. */
-                        let clamped = dpi_val.clamp(DPI_MIN, DPI_MAX);
-                        let rounded = (clamped / 50) * 50;
-                        dpi_to_raw(rounded).unwrap_or(0)
-                    })
+                    crate::driver::dpi_to_commit("G600", profile.index, res).map(|(x, _)| x)
                 };
+                if let Some(dpi_val) = dpi_val {
+                    report.dpi[r_idx] = if dpi_val == 0 {
+                        0
+                    } else {
+                        dpi_to_raw(dpi_val).unwrap_or_else(|| {
+                            /* Clamp to nearest valid value. */
+                            let clamped = dpi_val.clamp(DPI_MIN, DPI_MAX);
+                            let rounded = (clamped / 50) * 50;
+                            dpi_to_raw(rounded).unwrap_or(0)
+                        })
+                    };
+                }
 
                 if res.is_default {
                     report.dpi_default = (r_idx + 1) as u8; /* 1-indexed */
@@ -159,7 +159,6 @@ pub struct LedReport {
     pub unknown_6: u8, /* 0xfa */
     pub unknown_7: u8, /* 0xfa */
     pub led: LedState,
-    pub unknown_13: u8,
     pub unknown_14: u8,
     pub unknown_15: u8,
 }
@@ -6,6 +6,7 @@ pub mod gskill;
 pub mod hidpp;
 pub mod hidpp10;
 pub mod hidpp20;
+pub mod logitech;
 pub mod logitech_g300;
 pub mod logitech_g600;
 pub mod marsgaming;
@@ -26,13 +27,15 @@ use thiserror::Error;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tracing::{debug, trace, warn};
 
-use crate::device::DeviceInfo;
+use crate::device::{ActionType, BatteryInfo, DeviceInfo, Dpi, ResolutionInfo, RgbColor};
+use crate::device_database::DriverConfig;
 
 /* Domain-specific error variants for all driver I/O operations. */
 /*                                                                 */
 /* Using explicit variants instead of opaque strings allows the    */
-/* daemon to take structured recovery actions (e.g., retrying on   */
-/* `Timeout` vs. logging and abandoning on `ChecksumMismatch`).   */
+/* daemon to take structured recovery actions (e.g., re-reading a  */
+/* report on `ChecksumMismatch` but failing fast on               */
+/* `ProtocolError`).                                              */
 #[allow(dead_code)]
 #[derive(Debug, Error)]
 pub enum DriverError {
@@ -46,6 +49,9 @@ pub enum DriverError {
     #[error("Feature report ioctl failed: {0}")]
     IoctlFailed(std::io::Error),
 
+    #[error("Report descriptor ioctl failed: {0}")]
+    DescriptorIoctlFailed(std::io::Error),
+
     #[error("Hardware timed out after {attempts} attempt(s)")]
     Timeout { attempts: u8 },
 
@@ -73,6 +79,46 @@ pub enum DriverError {
     Hidpp20ProbeFailure { indices: Vec<u8> },
 }
 
+/* The part of the device a write belongs to, named after the DBus    */
+/* object clients change it through.                                  */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommitTarget {
+    Device,
+    Profile(u32),
+    Resolution { profile: u32, index: u32 },
+    Button { profile: u32, index: u32 },
+    Led { profile: u32, index: u32 },
+}
+
+impl CommitTarget {
+    /* Object path of the target on the device at `device_path`. */
+    pub fn object_path(&self, device_path: &str) -> String {
+        match *self {
+            Self::Device => device_path.to_string(),
+            Self::Profile(profile) => format!("{device_path}/p{profile}"),
+            Self::Resolution { profile, index } => format!("{device_path}/p{profile}/r{index}"),
+            Self::Button { profile, index } => format!("{device_path}/p{profile}/b{index}"),
+            Self::Led { profile, index } => format!("{device_path}/p{profile}/l{index}"),
+        }
+    }
+}
+
+/* Error `commit` returns when some writes failed and others went      */
+/* through, listing what could not be written and why.  The actor      */
+/* passes the list on to the client that asked for the commit; any     */
+/* other error is reported against the whole device.                   */
+#[derive(Debug, Error)]
+#[error("{}", describe_failures(.0))]
+pub struct PartialCommit(pub Vec<(CommitTarget, String)>);
+
+fn describe_failures(failures: &[(CommitTarget, String)]) -> String {
+    failures
+        .iter()
+        .map(|(target, error)| format!("{target:?}: {error}"))
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
 /* Maximum HID report size.                                        */
 /*                                                                 */
 /* Roccat macros are the largest at 2082 bytes. We use 4096 as    */
@@ -100,6 +146,14 @@ const SINGLE_READ_TIMEOUT: Duration = Duration::from_millis(500);
 const HIDPP_SHORT_REPORT_ID: u8 = 0x10;
 const HIDPP_LONG_REPORT_ID: u8 = 0x11;
 
+/* Delay before the first re-read of a report that failed its      */
+/* checksum; it doubles with every further attempt.               */
+const CHECKSUM_RETRY_DELAY: Duration = Duration::from_millis(10);
+
+/* Reports the kernel queues per hidraw reader (HIDRAW_BUFFER_SIZE);  */
+/* anything older has been dropped already.                           */
+const HIDRAW_QUEUE_LEN: usize = 64;
+
 /* Compute the `HIDIOCGFEATURE(len)` ioctl request number.        */
 /*                                                                */
 /* Linux hidraw.h: `_IOC(_IOC_READ|_IOC_WRITE, 'H', 0x07, len)`. */
@@ -121,6 +175,113 @@ fn hid_set_feature_req(len: usize) -> libc::c_ulong {
     (ioc_readwrite << 30) | (ioc_type << 8) | ioc_nr | ((len as libc::c_ulong) << 16)
 }
 
+/* Largest report descriptor the kernel hands out.                */
+/*                                                                */
+/* Linux hid.h: `HID_MAX_DESCRIPTOR_SIZE`.                        */
+const HID_MAX_DESCRIPTOR_SIZE: usize = 4096;
+
+/* Linux hidraw.h: `struct hidraw_report_descriptor`.             */
+#[repr(C)]
+struct HidrawReportDescriptor {
+    size: u32,
+    value: [u8; HID_MAX_DESCRIPTOR_SIZE],
+}
+
+/* Linux hidraw.h: `_IOR('H', 0x01, int)`.                        */
+const HIDIOCGRDESCSIZE: libc::c_ulong = (2 << 30)
+    | ((std::mem::size_of::<libc::c_int>() as libc::c_ulong) << 16)
+    | ((b'H' as libc::c_ulong) << 8)
+    | 0x01;
+
+/* Linux hidraw.h: `_IOR('H', 0x02, struct hidraw_report_descriptor)`. */
+const HIDIOCGRDESC: libc::c_ulong = (2 << 30)
+    | ((std::mem::size_of::<HidrawReportDescriptor>() as libc::c_ulong) << 16)
+    | ((b'H' as libc::c_ulong) << 8)
+    | 0x02;
+
+/* Read a report descriptor with `HIDIOCGRDESCSIZE` then           */
+/* `HIDIOCGRDESC`.  `ioctl` performs one ioctl on the hidraw fd;   */
+/* tests pass a fake in its place.                                */
+fn read_report_descriptor(
+    mut ioctl: impl FnMut(libc::c_ulong, *mut libc::c_void) -> libc::c_int,
+) -> Result<Vec<u8>, DriverError> {
+    let mut size: libc::c_int = 0;
+    if ioctl(HIDIOCGRDESCSIZE, (&mut size as *mut libc::c_int).cast()) < 0 {
+        return Err(DriverError::DescriptorIoctlFailed(std::io::Error::last_os_error()));
+    }
+    let size = usize::try_from(size).unwrap_or(0).min(HID_MAX_DESCRIPTOR_SIZE);
+
+    let mut desc = Box::new(HidrawReportDescriptor {
+        size: size as u32,
+        value: [0; HID_MAX_DESCRIPTOR_SIZE],
+    });
+    if ioctl(HIDIOCGRDESC, (&mut *desc as *mut HidrawReportDescriptor).cast()) < 0 {
+        return Err(DriverError::DescriptorIoctlFailed(std::io::Error::last_os_error()));
+    }
+    Ok(desc.value[..size].to_vec())
+}
+
+/* Validate the byte count returned by `HIDIOCGFEATURE`.              */
+/*                                                                   */
+/* The kernel may fill fewer bytes than requested; the tail of `buf`  */
+/* is zeroed so that nothing left over from the request (the report  */
+/* ID, a reused buffer) is mistaken for data, and a report shorter    */
+/* than `min_len` is rejected.                                        */
+fn accept_feature_report(buf: &mut [u8], n: usize, min_len: usize) -> Result<usize, DriverError> {
+    let n = n.min(buf.len());
+    buf[n..].fill(0);
+    if n < min_len {
+        return Err(DriverError::BufferTooSmall {
+            expected: min_len,
+            actual: n,
+        });
+    }
+    Ok(n)
+}
+
+/* Run `attempt` up to `max_attempts` times (at least once) while it  */
+/* fails with `ChecksumMismatch`, backing off exponentially between    */
+/* tries; the last mismatch is returned.  Any other error,             */
+/* `ProtocolError` included, is returned at once: only a corrupt       */
+/* transfer is worth repeating.                                        */
+async fn retry_on_checksum<T>(
+    max_attempts: u8,
+    mut attempt: impl FnMut() -> Result<T, DriverError>,
+) -> Result<T, DriverError> {
+    let mut delay = CHECKSUM_RETRY_DELAY;
+    let mut n = 1;
+    loop {
+        match attempt() {
+            Err(e @ DriverError::ChecksumMismatch { .. }) if n < max_attempts => {
+                warn!("{e} on attempt {n}/{max_attempts}, retrying in {delay:?}");
+                tokio::time::sleep(delay).await;
+                delay *= 2;
+                n += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/* `DeviceIo::request_checked` with the feature report read passed   */
+/* in as `get`; tests pass a fake in its place.                     */
+async fn request_checked_with<T>(
+    buf: &mut [u8],
+    min_len: usize,
+    max_attempts: u8,
+    mut get: impl FnMut(&mut [u8]) -> Result<usize, DriverError>,
+    mut validate: impl FnMut(&[u8]) -> Result<T, DriverError>,
+) -> Result<T, DriverError> {
+    let report_id = buf.first().copied().unwrap_or(0);
+    retry_on_checksum(max_attempts, || {
+        buf[0] = report_id;
+        let n = get(buf)?;
+        accept_feature_report(buf, n, min_len)?;
+        validate(buf)
+    })
+    .await
+}
+
 /* Async wrapper around a `/dev/hidraw` file descriptor. */
 /*                                                       */
 /* All hardware I/O goes through this struct so that     */
@@ -157,12 +318,19 @@ impl DeviceIo {
         &self.path
     }
 
-    /* Write a raw HID report to the device. */
+    /* Write a raw HID report to the device.  tokio's `File` finishes   */
+    /* writes in the background; flushing waits for this one so that  */
+    /* its error, if any, is reported here rather than by the next     */
+    /* call.                                                           */
     pub async fn write_report(&mut self, buf: &[u8]) -> Result<()> {
         self.file
             .write_all(buf)
             .await
             .with_context(|| format!("Write failed on {}", self.path.display()))?;
+        self.file
+            .flush()
+            .await
+            .with_context(|| format!("Write failed on {}", self.path.display()))?;
         debug!("TX {} bytes: {:02x?}", buf.len(), buf);
         Ok(())
     }
@@ -202,6 +370,42 @@ impl DeviceIo {
         Ok(n)
     }
 
+    /* Get a feature report that the caller will parse up to `min_len`  */
+    /* bytes into. A shorter report fails with `BufferTooSmall` instead */
+    /* of letting the parser read stale or zero tail bytes as data.     */
+    pub fn get_feature_report_min(
+        &self,
+        buf: &mut [u8],
+        min_len: usize,
+    ) -> Result<usize, DriverError> {
+        let n = self.get_feature_report(buf)?;
+        accept_feature_report(buf, n, min_len)
+    }
+
+    /* Get a feature report of at least `min_len` bytes and hand it   */
+    /* to `validate`, re-reading up to `max_attempts` times while the */
+    /* validator reports `ChecksumMismatch`.  For devices whose       */
+    /* reports carry a checksum and occasionally arrive corrupt.      */
+    pub async fn request_checked<T, F>(
+        &self,
+        buf: &mut [u8],
+        min_len: usize,
+        max_attempts: u8,
+        validate: F,
+    ) -> Result<T, DriverError>
+    where
+        F: FnMut(&[u8]) -> Result<T, DriverError>,
+    {
+        request_checked_with(
+            buf,
+            min_len,
+            max_attempts,
+            |buf| self.get_feature_report(buf),
+            validate,
+        )
+        .await
+    }
+
     /* Set a HID feature report using the `HIDIOCSFEATURE` ioctl.  */
     /*                                                             */
     /* `buf[0]` must contain the report ID. Returns the number of  */
@@ -225,6 +429,20 @@ impl DeviceIo {
         Ok(n)
     }
 
+    /* Read the device's HID report descriptor, for inspecting the  */
+    /* report layout of a device that has no driver yet.           */
+    pub fn get_report_descriptor(&self) -> Result<Vec<u8>, DriverError> {
+        let fd = self.file.as_raw_fd();
+
+        /* SAFETY: `fd` is a valid open file descriptor for the     */
+        /* lifetime of this call, and `read_report_descriptor`      */
+        /* passes a pointer to a live value of the type each        */
+        /* request encodes.                                         */
+        let desc = read_report_descriptor(|req, arg| unsafe { libc::ioctl(fd, req, arg) })?;
+        debug!("Report descriptor {} bytes", desc.len());
+        Ok(desc)
+    }
+
     /* Send a report and wait for a matching response.             */
     /*                                                             */
     /* The `matcher` closure receives each incoming HID++ report   */
@@ -321,6 +539,39 @@ impl DeviceIo {
         .into())
     }
 
+    /* Buffer the HID++ reports already queued on the node as events,  */
+    /* without waiting for more.  Readiness is checked with poll(2)    */
+    /* before every read, so the read never blocks and never leaves an  */
+    /* in-flight read that would hold up the next `write_report`.  At   */
+    /* most `HIDRAW_QUEUE_LEN` reports are read per call so that a      */
+    /* receiver streaming mouse input cannot keep the caller here.      */
+    pub async fn collect_events(&mut self) -> Result<()> {
+        let mut buf = [0u8; 64];
+        for _ in 0..HIDRAW_QUEUE_LEN {
+            let fd = self.file.as_raw_fd();
+            let mut pollfd = [nix::poll::PollFd::new(
+                /* SAFETY: `fd` belongs to `self.file`, which outlives  */
+                /* this borrow; `poll` consumes it right away.          */
+                unsafe { std::os::unix::io::BorrowedFd::borrow_raw(fd) },
+                nix::poll::PollFlags::POLLIN,
+            )];
+            if nix::poll::poll(&mut pollfd, nix::poll::PollTimeout::ZERO)
+                .context("poll(2) on hidraw node")?
+                == 0
+            {
+                break;
+            }
+            let n = self.read_report(&mut buf).await?;
+            if n == 0 {
+                break;
+            }
+            if buf[0] == HIDPP_SHORT_REPORT_ID || buf[0] == HIDPP_LONG_REPORT_ID {
+                self.pending_events.push(buf[..n].to_vec());
+            }
+        }
+        Ok(())
+    }
+
     /* Drain all unsolicited HID++ events that were buffered during
      * `request()` calls.  The actor calls this after each I/O batch
      * and forwards the reports to `DeviceDriver::handle_event`. */
@@ -329,6 +580,59 @@ impl DeviceIo {
     }
 }
 
+/* What a driver's `commit` can write, independent of any one device. */
+/*                                                                   */
+/* Answerable without hardware, so the Manager can report it to      */
+/* clients and tests can pin each driver's real feature set.  A      */
+/* device may still lack a feature its driver supports.              */
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DriverCapabilities {
+    /* More than one onboard profile. */
+    pub profiles: bool,
+    pub buttons: bool,
+    pub macros: bool,
+    pub leds: bool,
+    /* Separate X and Y resolutions. */
+    pub separate_xy: bool,
+    /* Solid LED colors pushed outside of a commit (`set_led_color_live`). */
+    pub live_leds: bool,
+}
+
+impl DriverCapabilities {
+    /* Button and macro support as implied by a driver's action types. */
+    pub fn from_action_types(types: &[ActionType]) -> Self {
+        Self {
+            buttons: !types.is_empty(),
+            macros: types.contains(&ActionType::Macro),
+            ..Self::default()
+        }
+    }
+
+    /* Features both `self` and `other` support. */
+    pub fn intersection(self, other: Self) -> Self {
+        Self {
+            profiles: self.profiles && other.profiles,
+            buttons: self.buttons && other.buttons,
+            macros: self.macros && other.macros,
+            leds: self.leds && other.leds,
+            separate_xy: self.separate_xy && other.separate_xy,
+            live_leds: self.live_leds && other.live_leds,
+        }
+    }
+
+    /* (name, supported) pairs in a fixed order, as exposed over DBus. */
+    pub fn entries(&self) -> [(&'static str, bool); 6] {
+        [
+            ("profiles", self.profiles),
+            ("buttons", self.buttons),
+            ("macros", self.macros),
+            ("leds", self.leds),
+            ("separate-xy", self.separate_xy),
+            ("live-leds", self.live_leds),
+        ]
+    }
+}
+
 /* The universal driver interface for all hardware protocols.      */
 /*                                                                 */
 /* Every supported protocol (HID++ 1.0, HID++ 2.0, Roccat, etc.) */
@@ -356,6 +660,114 @@ pub trait DeviceDriver: Send + Sync {
     /* diff the `DeviceInfo` against its internal cached state.    */
     async fn commit(&mut self, io: &mut DeviceIo, info: &DeviceInfo) -> Result<()>;
 
+    /* Write only profile `index`; other profiles keep their pending      */
+    /* changes for a later commit.  The default commits a copy of `info`  */
+    /* in which no other profile is dirty, which is enough for drivers    */
+    /* that only write dirty profiles.                                    */
+    async fn commit_profile(
+        &mut self,
+        io: &mut DeviceIo,
+        info: &DeviceInfo,
+        index: u32,
+    ) -> Result<()> {
+        let mut only = info.clone();
+        for profile in &mut only.profiles {
+            profile.is_dirty &= profile.index == index;
+        }
+        self.commit(io, &only).await
+    }
+
+    /* Button action types this driver's `commit` can write.            */
+    /*                                                                  */
+    /* The actor copies this list into every button the driver left     */
+    /* without its own `action_types` after `load_profiles`. The default */
+    /* is empty: the driver does not remap buttons.                      */
+    fn action_types(&self) -> &'static [ActionType] {
+        &[]
+    }
+
+    /* Whether `commit` honours `ButtonInfo::macro_repeat`. When it does */
+    /* not, the actor warns about every macro with a repeat setting and  */
+    /* the hardware plays it once.                                        */
+    fn supports_macro_repeat(&self) -> bool {
+        false
+    }
+
+    /* Whether the device reports state changes made on the device      */
+    /* itself (profile buttons, DPI buttons) as input reports.  The     */
+    /* actor then watches the node while idle and feeds those reports   */
+    /* to `handle_event`.                                                */
+    fn listens_for_events(&self) -> bool {
+        false
+    }
+
+    /* Whether the device answered when it was last probed or loaded.  */
+    /* A driver that registers a sleeping wireless device with default  */
+    /* settings reports `false`; the actor then probes again on a       */
+    /* backoff and reloads the profiles once the device wakes up.       */
+    fn is_ready(&self) -> bool {
+        true
+    }
+
+    /* Whether profiles stored on the device failed their checksums     */
+    /* when last read, so that only a full rewrite brings them back.    */
+    fn needs_repair(&self) -> bool {
+        false
+    }
+
+    /* Make the next `commit` rewrite every stored profile, checksums   */
+    /* failing or not.  Returns `false` when the driver keeps no such   */
+    /* storage to rewrite.                                              */
+    fn request_repair(&mut self) -> bool {
+        false
+    }
+
+    /* Whether `load_profiles` reads profile names from the device.     */
+    /* Other drivers make names up, so the daemon keeps the names       */
+    /* clients set in its state cache instead.                          */
+    fn stores_profile_names(&self) -> bool {
+        false
+    }
+
+    /* Factory mapping of button `index` as (action type, value), for     */
+    /* `Button.Reset`.  Called after `load_profiles`; `None` when the      */
+    /* driver does not know the default.                                  */
+    fn default_button_mapping(&self, _index: u32) -> Option<(ActionType, u32)> {
+        None
+    }
+
+    /* Features `commit` can write.  The default derives buttons and    */
+    /* macros from `action_types` and claims nothing else.              */
+    fn capabilities(&self) -> DriverCapabilities {
+        DriverCapabilities::from_action_types(self.action_types())
+    }
+
+    /* Make profile `index` active on the hardware right away, outside  */
+    /* of a commit.  Returns `false` when the driver has no such switch; */
+    /* the change then waits for the next `commit`, like any other.      */
+    async fn switch_profile(&mut self, _io: &mut DeviceIo, _index: u32) -> Result<bool> {
+        Ok(false)
+    }
+
+    /* Show `color` as a solid color on LED `led` right away, without   */
+    /* touching the stored profile.  For ambient lighting that follows  */
+    /* an external source; the next commit restores the profile's LED. */
+    /* Returns `false` when the driver cannot write LEDs live.          */
+    async fn set_led_color_live(
+        &mut self,
+        _io: &mut DeviceIo,
+        _led: u32,
+        _color: RgbColor,
+    ) -> Result<bool> {
+        Ok(false)
+    }
+
+    /* Read the battery state now.  Returns `None` when the device     */
+    /* reports no battery.                                              */
+    async fn read_battery(&mut self, _io: &mut DeviceIo) -> Result<Option<BatteryInfo>> {
+        Ok(None)
+    }
+
     /* Handle an unsolicited hardware event (e.g. profile switch,  */
     /* DPI change triggered by a physical button on the device).   */
     /*                                                             */
@@ -372,6 +784,26 @@ pub trait DeviceDriver: Send + Sync {
     }
 }
 
+/* Every `Driver=` name `create_driver` accepts, in alphabetical order. */
+pub const DRIVER_NAMES: &[&str] = &[
+    "asus",
+    "etekcity",
+    "gskill",
+    "hidpp10",
+    "hidpp20",
+    "logitech",
+    "logitech_g300",
+    "logitech_g600",
+    "marsgaming",
+    "openinput",
+    "roccat",
+    "roccat-kone-emp",
+    "roccat-kone-pure",
+    "sinowealth",
+    "sinowealth-nubwo",
+    "steelseries",
+];
+
 /* Instantiate the correct driver based on the driver name from the */
 /* `.device` file database.                                         */
 pub fn create_driver(driver_name: &str) -> Option<Box<dyn DeviceDriver>> {
@@ -381,6 +813,7 @@ pub fn create_driver(driver_name: &str) -> Option<Box<dyn DeviceDriver>> {
         "gskill" => Some(Box::new(gskill::GskillDriver::new())),
         "hidpp10" => Some(Box::new(hidpp10::Hidpp10Driver::new())),
         "hidpp20" => Some(Box::new(hidpp20::Hidpp20Driver::new())),
+        "logitech" => Some(Box::new(logitech::LogitechDriver::new())),
         "logitech_g300" => Some(Box::new(logitech_g300::LogitechG300Driver::new())),
         "logitech_g600" => Some(Box::new(logitech_g600::LG600Driver::new())),
         "marsgaming" => Some(Box::new(marsgaming::MarsGamingDriver::new())),
@@ -397,3 +830,272 @@ pub fn create_driver(driver_name: &str) -> Option<Box<dyn DeviceDriver>> {
         }
     }
 }
+
+/* DPI to write for resolution `res` of `profile`, as (x, y); a unified  */
+/* value fills both axes.  `Dpi::Unknown` was never read from the        */
+/* hardware nor set by the user, so it yields `None` with a warning and  */
+/* the driver leaves that resolution as it is on the device.             */
+pub fn dpi_to_commit(driver: &str, profile: u32, res: &ResolutionInfo) -> Option<(u32, u32)> {
+    match res.dpi {
+        Dpi::Unified(dpi) => Some((dpi, dpi)),
+        Dpi::Separate { x, y } => Some((x, y)),
+        Dpi::Unknown => {
+            warn!(
+                "{driver}: profile {profile} resolution {} has no known DPI, not writing it",
+                res.index
+            );
+            None
+        }
+    }
+}
+
+/* A driver to probe, paired with the device file's `[Driver/xxx]`     */
+/* section for it, which the actor hands over if the probe succeeds.   */
+pub type DriverCandidate = (Box<dyn DeviceDriver>, DriverConfig);
+
+/* Instantiate every known driver from an ordered `Driver=` list, keeping */
+/* the database order so the actor can probe them by priority. Unknown    */
+/* names are logged by `create_driver` and skipped. `configs` holds each  */
+/* name's own section, in the same order.                                 */
+/*                                                                        */
+/* Called for every connection, including reconnects: drivers cache what  */
+/* they learned from the hardware (feature maps, onboard-profile layout), */
+/* and that state may be stale after a firmware update.                   */
+pub fn create_drivers(driver_names: &[String], configs: &[DriverConfig]) -> Vec<DriverCandidate> {
+    driver_names
+        .iter()
+        .enumerate()
+        .filter_map(|(i, name)| {
+            let config = configs.get(i).cloned().unwrap_or_default();
+            create_driver(name).map(|driver| (driver, config))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ActionType::{Button, Key, Macro, None as Disabled, Special};
+
+    fn action_types_of(name: &str) -> Vec<ActionType> {
+        create_driver(name)
+            .unwrap_or_else(|| panic!("{name} should be a known driver"))
+            .action_types()
+            .to_vec()
+    }
+
+    #[test]
+    fn test_driver_names_are_all_known() {
+        for name in DRIVER_NAMES {
+            assert!(create_driver(name).is_some(), "{name} is listed but unknown");
+        }
+    }
+
+    #[test]
+    fn test_short_feature_report_is_rejected() {
+        /* Simulate the kernel filling only 3 of 8 bytes into a buffer that
+         * still holds bytes from a previous report. */
+        let mut buf = [0xAA_u8; 8];
+        let err = accept_feature_report(&mut buf, 3, 8).unwrap_err();
+        assert!(matches!(
+            err,
+            DriverError::BufferTooSmall {
+                expected: 8,
+                actual: 3
+            }
+        ));
+        assert_eq!(buf, [0xAA, 0xAA, 0xAA, 0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_short_feature_report_above_minimum_is_accepted() {
+        let mut buf = [0xAA_u8; 8];
+        assert_eq!(accept_feature_report(&mut buf, 5, 4).unwrap(), 5);
+        assert_eq!(&buf[5..], &[0, 0, 0]);
+        assert_eq!(accept_feature_report(&mut buf, 8, 8).unwrap(), 8);
+    }
+
+    #[tokio::test]
+    async fn test_checksum_mismatch_is_retried() {
+        let mut calls = 0;
+        let result = retry_on_checksum(3, || {
+            calls += 1;
+            if calls < 3 {
+                Err(DriverError::ChecksumMismatch { computed: 1, received: 2 })
+            } else {
+                Ok(calls)
+            }
+        })
+        .await;
+        assert_eq!(result.unwrap(), 3);
+
+        let mut calls = 0;
+        let result: Result<(), _> = retry_on_checksum(3, || {
+            calls += 1;
+            Err(DriverError::ChecksumMismatch { computed: 1, received: 2 })
+        })
+        .await;
+        assert!(matches!(result, Err(DriverError::ChecksumMismatch { .. })));
+        assert_eq!(calls, 3);
+    }
+
+    #[tokio::test]
+    async fn test_request_checked_rereads_after_bad_checksum() {
+        /* First read comes back corrupt, the second one is good.  Each
+         * read must see the report ID again, not the corrupt reply. */
+        let replies = [[0x06, 0xAA, 0x00], [0x06, 0xAA, 0x55]];
+        let mut reads = Vec::new();
+        let mut buf = [0x06_u8, 0, 0];
+        let value = request_checked_with(
+            &mut buf,
+            3,
+            3,
+            |buf| {
+                reads.push(buf[0]);
+                let reply = replies[reads.len() - 1];
+                buf.copy_from_slice(&reply);
+                Ok(reply.len())
+            },
+            |buf| {
+                let computed = u16::from(buf[1] ^ 0xFF);
+                if computed == u16::from(buf[2]) {
+                    Ok(buf[1])
+                } else {
+                    Err(DriverError::ChecksumMismatch { computed, received: u16::from(buf[2]) })
+                }
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(value, 0xAA);
+        assert_eq!(reads, [0x06, 0x06]);
+    }
+
+    #[tokio::test]
+    async fn test_protocol_error_is_not_retried() {
+        let mut calls = 0;
+        let result: Result<(), _> = retry_on_checksum(3, || {
+            calls += 1;
+            Err(DriverError::ProtocolError { sub_id: 0x8F, error: 0x02 })
+        })
+        .await;
+        assert!(matches!(result, Err(DriverError::ProtocolError { .. })));
+        assert_eq!(calls, 1);
+    }
+
+    #[test]
+    fn test_report_descriptor_read_from_fake_fd() {
+        /* Start of a generic mouse descriptor. */
+        const DESCRIPTOR: [u8; 6] = [0x05, 0x01, 0x09, 0x02, 0xa1, 0x01];
+        let mut requests = Vec::new();
+        let desc = read_report_descriptor(|req, arg| {
+            requests.push(req);
+            /* SAFETY: `arg` points at the type each request encodes. */
+            unsafe {
+                match req {
+                    HIDIOCGRDESCSIZE => *arg.cast::<libc::c_int>() = DESCRIPTOR.len() as libc::c_int,
+                    HIDIOCGRDESC => {
+                        let desc = &mut *arg.cast::<HidrawReportDescriptor>();
+                        assert_eq!(desc.size as usize, DESCRIPTOR.len());
+                        desc.value[..DESCRIPTOR.len()].copy_from_slice(&DESCRIPTOR);
+                    }
+                    _ => return -1,
+                }
+            }
+            0
+        })
+        .unwrap();
+        assert_eq!(desc, DESCRIPTOR);
+        assert_eq!(requests, [HIDIOCGRDESCSIZE, HIDIOCGRDESC]);
+        /* The request numbers as spelled out in linux/hidraw.h. */
+        assert_eq!(HIDIOCGRDESCSIZE, 0x8004_4801);
+        assert_eq!(HIDIOCGRDESC, 0x9004_4802);
+
+        assert!(matches!(
+            read_report_descriptor(|_, _| -1),
+            Err(DriverError::DescriptorIoctlFailed(_))
+        ));
+    }
+
+    #[test]
+    fn test_button_capable_drivers_advertise_action_types() {
+        let expected: &[(&str, &[ActionType])] = &[
+            ("asus", &[Disabled, Button, Special, Key]),
+            ("hidpp20", &[Disabled, Button, Special, Key, Macro]),
+            ("logitech_g300", &[Disabled, Button, Special, Key]),
+            ("logitech_g600", &[Disabled, Button, Special, Macro]),
+            ("roccat", &[Disabled, Button, Special, Key, Macro]),
+            ("sinowealth", &[Disabled, Button, Special, Key, Macro]),
+            ("steelseries", &[Disabled, Button, Special, Macro]),
+        ];
+        for (name, types) in expected {
+            assert_eq!(action_types_of(name), *types, "{name}");
+        }
+    }
+
+    #[test]
+    fn test_asus_and_hidpp_capabilities() {
+        let caps = |name| create_driver(name).unwrap().capabilities();
+        assert_eq!(
+            caps("asus"),
+            DriverCapabilities {
+                profiles: true,
+                buttons: true,
+                macros: false,
+                leds: true,
+                separate_xy: false,
+                live_leds: false,
+            }
+        );
+        assert_eq!(
+            caps("hidpp20"),
+            DriverCapabilities {
+                profiles: true,
+                buttons: true,
+                macros: true,
+                leds: true,
+                separate_xy: false,
+                live_leds: true,
+            }
+        );
+        assert_eq!(
+            caps("hidpp10"),
+            DriverCapabilities {
+                profiles: true,
+                buttons: false,
+                macros: false,
+                leds: true,
+                separate_xy: true,
+                live_leds: false,
+            }
+        );
+        /* Unprobed, the auto-detecting wrapper claims only the overlap. */
+        assert_eq!(
+            caps("logitech"),
+            DriverCapabilities {
+                profiles: true,
+                leds: true,
+                ..DriverCapabilities::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_stub_drivers_claim_no_capabilities() {
+        for name in ["etekcity", "gskill", "marsgaming", "openinput", "sinowealth-nubwo"] {
+            let caps = create_driver(name).unwrap().capabilities();
+            assert_eq!(caps, DriverCapabilities::default(), "{name}");
+        }
+    }
+
+    #[test]
+    fn test_drivers_without_button_writes_advertise_nothing() {
+        /* These commits do not write button mappings (yet), so claiming
+         * any action type would let clients stage changes that are lost. */
+        for name in ["etekcity", "gskill", "hidpp10", "marsgaming", "openinput", "sinowealth-nubwo"] {
+            assert!(action_types_of(name).is_empty(), "{name}");
+        }
+        /* The auto-detecting wrapper has nothing to report until probed. */
+        assert!(action_types_of("logitech").is_empty());
+    }
+}
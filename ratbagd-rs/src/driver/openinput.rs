@@ -4,6 +4,11 @@
  * hardware configuration protocol for gaming peripherals.
  *
  * Reference implementation: src/driver-openinput.c.
+ *
+ * Resolutions and LEDs are not read or written: the reference driver
+ * does not use any page for them, and their function numbers and
+ * payloads are left until they can be taken from the protocol
+ * documentation.
  */
 use anyhow::{Context, Result};
 use async_trait::async_trait;
@@ -1,4 +1,4 @@
-use crate::device::DeviceInfo;
+use crate::device::{ActionType, DeviceInfo};
 use crate::driver::{DeviceDriver, DeviceIo, DriverError};
 use anyhow::{Context, Result};
 use async_trait::async_trait;
@@ -23,8 +23,14 @@ const ROCCAT_REPORT_ID_KEY_MAPPING: u8 = 7;
 const ROCCAT_REPORT_ID_MACRO: u8 = 8;
 
 const ROCCAT_MAX_RETRY_READY: usize = 10;
-#[allow(dead_code)]
+
+/* Reads of a report that fails its CRC before giving up. */
+const CHECKSUM_ATTEMPTS: u8 = 3;
+
+/* Key events in one macro report: 80 header bytes + 500 × 4 bytes of */
+/* events + 2 checksum bytes = 2082, the size of the feature report.  */
 const ROCCAT_MAX_MACRO_LENGTH: usize = 500;
+const ROCCAT_MACRO_REPORT_LEN: usize = 2082;
 
 /* Each Roccat button mapping is a 3-byte stride: [action, param1, param2] */
 const ROCCAT_BUTTON_STRIDE: usize = 3;
@@ -154,7 +160,77 @@ pub struct RoccatMacro {
 }
 
 impl RoccatMacro {
-    pub fn from_bytes(buf: &[u8; 2082]) -> Self {
+    /* Build the macro report for `button`, encoding its events.  The
+     * report has no play count; byte 5 is the active flag. */
+    pub fn from_button(profile: u8, button_index: u8, button: &crate::device::ButtonInfo) -> Self {
+        let mut macro_rep = RoccatMacro {
+            report_id: ROCCAT_REPORT_ID_MACRO,
+            report_length: 0x0822,
+            profile,
+            button_index,
+            active: 0x01,
+            padding: [0; 24],
+            group: [0; 24],
+            name: [0; 24],
+            length: 0,
+            keys: [RoccatMacroEvent { keycode: 0, flag: 0, time: 0 }; ROCCAT_MAX_MACRO_LENGTH],
+            checksum: 0,
+        };
+
+        // Initialize group and name with default values as C driver does
+        macro_rep.group[0] = b'g'; macro_rep.group[1] = b'0';
+
+        let mut count = 0;
+        for (ev_type, val) in &button.macro_entries {
+            /* Waits still apply to the last event of a full report. */
+            if count >= ROCCAT_MAX_MACRO_LENGTH && *ev_type != 2 { break; }
+            match *ev_type {
+                0 => {
+                    macro_rep.keys[count].flag = 0x01;
+                    macro_rep.keys[count].keycode = *val as u8;
+                    count += 1;
+                }
+                1 => {
+                    macro_rep.keys[count].flag = 0x02;
+                    macro_rep.keys[count].keycode = *val as u8;
+                    count += 1;
+                }
+                2 => {
+                    if count > 0 {
+                        macro_rep.keys[count - 1].time = (*val).min(u32::from(u16::MAX)) as u16;
+                    }
+                }
+                _ => {}
+            }
+        }
+        macro_rep.length = count as u16;
+        macro_rep
+    }
+
+    /* Number of key events `entries` takes in a macro report; waits
+     * ride along with the preceding key event. */
+    pub fn event_count(entries: &[(u32, u32)]) -> usize {
+        entries.iter().filter(|(ev_type, _)| matches!(ev_type, 0 | 1)).count()
+    }
+
+    /* Decode the report's events back into ratbag macro entries
+     * (0 = press, 1 = release, 2 = wait).  Every key event carries a
+     * wait; a zero time is read as the firmware's 50 ms default. */
+    pub fn to_entries(&self) -> Vec<(u32, u32)> {
+        let mut entries = Vec::new();
+        for ev in self.keys.iter().take((self.length as usize).min(ROCCAT_MAX_MACRO_LENGTH)) {
+            if ev.flag & 0x01 != 0 {
+                entries.push((0, ev.keycode as u32));
+            } else if ev.flag & 0x02 != 0 {
+                entries.push((1, ev.keycode as u32));
+            }
+            let time = if ev.time > 0 { ev.time } else { 50 };
+            entries.push((2, time as u32));
+        }
+        entries
+    }
+
+    pub fn from_bytes(buf: &[u8; ROCCAT_MACRO_REPORT_LEN]) -> Self {
         let mut padding = [0u8; 24];
         padding.copy_from_slice(&buf[6..30]);
         let mut group = [0u8; 24];
@@ -187,8 +263,8 @@ impl RoccatMacro {
         }
     }
     
-    pub fn into_bytes(self) -> [u8; 2082] {
-        let mut buf = [0u8; 2082];
+    pub fn into_bytes(self) -> [u8; ROCCAT_MACRO_REPORT_LEN] {
+        let mut buf = [0u8; ROCCAT_MACRO_REPORT_LEN];
         buf[0] = self.report_id;
         buf[1..3].copy_from_slice(&self.report_length.to_le_bytes());
         buf[3] = self.profile;
@@ -378,6 +454,19 @@ impl RoccatDriver {
         computed == received
     }
 
+    /* `crc_is_valid` as a `DeviceIo::request_checked` validator. */
+    fn check_crc(buf: &[u8]) -> Result<(), DriverError> {
+        if buf.len() < 3 {
+            return Err(DriverError::BufferTooSmall { expected: 3, actual: buf.len() });
+        }
+        if Self::crc_is_valid(buf) {
+            return Ok(());
+        }
+        let computed = Self::compute_crc(buf);
+        let received = u16::from_le_bytes([buf[buf.len() - 2], buf[buf.len() - 1]]);
+        Err(DriverError::ChecksumMismatch { computed, received })
+    }
+
     /* Configure the device to expose the given profile and type on its interface. */
     async fn set_config_profile(&self, io: &mut DeviceIo, profile_idx: u8, config_type: u8) -> Result<()> {
         if profile_idx > ROCCAT_PROFILE_MAX {
@@ -397,20 +486,10 @@ impl RoccatDriver {
 
         let mut buf = [0u8; 43];
         buf[0] = ROCCAT_REPORT_ID_SETTINGS;
-        
-        let len = io.get_feature_report(&mut buf).context("Failed to get settings report")?;
-        if len < 43 {
-            return Err(DriverError::BufferTooSmall {
-                expected: 43,
-                actual: len,
-            }.into());
-        }
 
-        if !Self::crc_is_valid(&buf) {
-            let computed = Self::compute_crc(&buf);
-            let received = u16::from_le_bytes([buf[41], buf[42]]);
-            return Err(DriverError::ChecksumMismatch { computed, received }.into());
-        }
+        io.request_checked(&mut buf, 43, CHECKSUM_ATTEMPTS, Self::check_crc)
+            .await
+            .context("Failed to get settings report")?;
 
         Ok(RoccatSettingsReport::from_bytes(&buf))
     }
@@ -427,19 +506,9 @@ impl RoccatDriver {
         /* Give device time to switch to the profile payload */
         tokio::time::sleep(Duration::from_millis(10)).await;
 
-        let len = io.get_feature_report(&mut buf).context("Failed to get profile mapping report")?;
-        if len < 77 {
-            return Err(DriverError::BufferTooSmall {
-                expected: 77,
-                actual: len,
-            }.into());
-        }
-
-        if !Self::crc_is_valid(&buf) {
-            let computed = Self::compute_crc(&buf);
-            let received = u16::from_le_bytes([buf[75], buf[76]]);
-            return Err(DriverError::ChecksumMismatch { computed, received }.into());
-        }
+        io.request_checked(&mut buf, 77, CHECKSUM_ATTEMPTS, Self::check_crc)
+            .await
+            .context("Failed to get profile mapping report")?;
 
         Ok(RoccatProfileReport::from_bytes(&buf))
     }
@@ -483,21 +552,14 @@ impl RoccatDriver {
         self.set_config_profile(io, profile_idx, 0).await?;
         self.set_config_profile(io, profile_idx, btn_idx).await?;
 
-        let mut buf = [0u8; 2082];
+        let mut buf = [0u8; ROCCAT_MACRO_REPORT_LEN];
         buf[0] = ROCCAT_REPORT_ID_MACRO;
         
         tokio::time::sleep(Duration::from_millis(10)).await;
 
-        let len = io.get_feature_report(&mut buf).context("Failed to get macro report")?;
-        if len < 2082 {
-            return Err(DriverError::BufferTooSmall { expected: 2082, actual: len }.into());
-        }
-
-        if !Self::crc_is_valid(&buf) {
-            let computed = Self::compute_crc(&buf);
-            let received = u16::from_le_bytes([buf[2080], buf[2081]]);
-            return Err(DriverError::ChecksumMismatch { computed, received }.into());
-        }
+        io.request_checked(&mut buf, ROCCAT_MACRO_REPORT_LEN, CHECKSUM_ATTEMPTS, Self::check_crc)
+            .await
+            .context("Failed to get macro report")?;
 
         Ok(RoccatMacro::from_bytes(&buf))
     }
@@ -524,6 +586,25 @@ impl DeviceDriver for RoccatDriver {
         &self.name
     }
 
+    /* Commit encodes every action kind, including uploaded macros. */
+    fn action_types(&self) -> &'static [ActionType] {
+        &[
+            ActionType::None,
+            ActionType::Button,
+            ActionType::Special,
+            ActionType::Key,
+            ActionType::Macro,
+        ]
+    }
+
+    fn capabilities(&self) -> super::DriverCapabilities {
+        super::DriverCapabilities {
+            profiles: true,
+            separate_xy: true,
+            ..super::DriverCapabilities::from_action_types(self.action_types())
+        }
+    }
+
     async fn probe(&mut self, io: &mut DeviceIo) -> Result<()> {
         let mut buf = [0u8; 3];
         buf[0] = ROCCAT_REPORT_ID_PROFILE;
@@ -590,21 +671,7 @@ impl DeviceDriver for RoccatDriver {
                                 if action_type == crate::device::ActionType::Macro {
                                     match self.read_macro(io, profile_idx, btn_idx as u8).await {
                                         Ok(macro_rep) => {
-                                            let mut entries = Vec::new();
-                                            for j in 0..macro_rep.length as usize {
-                                                if j >= ROCCAT_MAX_MACRO_LENGTH { break; }
-                                                let ev = macro_rep.keys[j];
-                                                // Using ratbag conventions: 0=Press, 1=Release, 2=Wait
-                                                if ev.flag & 0x01 != 0 {
-                                                    entries.push((0, ev.keycode as u32));
-                                                } else if ev.flag & 0x02 != 0 {
-                                                    entries.push((1, ev.keycode as u32));
-                                                }
-                                                // Every key event has an associated wait time
-                                                let time = if ev.time > 0 { ev.time } else { 50 };
-                                                entries.push((2, time as u32));
-                                            }
-                                            button_info.macro_entries = entries;
+                                            button_info.macro_entries = macro_rep.to_entries();
                                         }
                                         Err(e) => tracing::warn!("Roccat: failed to read macro for btn {}: {}", btn_idx, e),
                                     }
@@ -622,6 +689,23 @@ impl DeviceDriver for RoccatDriver {
     }
 
     async fn commit(&mut self, io: &mut DeviceIo, info: &DeviceInfo) -> Result<()> {
+        /* A macro that does not fit the report would be cut short; refuse
+         * before anything is written. */
+        for profile in &info.profiles {
+            for button in profile.buttons.iter().filter(|b| b.action_type == ActionType::Macro) {
+                let events = RoccatMacro::event_count(&button.macro_entries);
+                if events > ROCCAT_MAX_MACRO_LENGTH {
+                    anyhow::bail!(
+                        "Roccat: macro on profile {} button {} has {} key events; the device holds at most {}",
+                        profile.index,
+                        button.index,
+                        events,
+                        ROCCAT_MAX_MACRO_LENGTH
+                    );
+                }
+            }
+        }
+
         /* Write profile settings (DPI, polling rate) and key mappings (Buttons) */
         for profile in &info.profiles {
             let p_idx = profile.index as usize;
@@ -634,16 +718,11 @@ impl DeviceDriver for RoccatDriver {
                     let r_idx = res.index as usize;
                     if r_idx >= ROCCAT_NUM_DPI as usize { continue; }
 
-                    match res.dpi {
-                        crate::device::Dpi::Separate { x, y } => {
-                            settings.xres[r_idx] = (x / 50) as u8;
-                            settings.yres[r_idx] = (y / 50) as u8;
-                        }
-                        crate::device::Dpi::Unified(val) => {
-                            settings.xres[r_idx] = (val / 50) as u8;
-                            settings.yres[r_idx] = (val / 50) as u8;
-                        }
-                        crate::device::Dpi::Unknown => {}
+                    if let Some((x, y)) =
+                        crate::driver::dpi_to_commit("Roccat", profile.index, res)
+                    {
+                        settings.xres[r_idx] = (x / 50) as u8;
+                        settings.yres[r_idx] = (y / 50) as u8;
                     }
                     if res.is_active {
                         settings.current_dpi = r_idx as u8;
@@ -671,47 +750,7 @@ impl DeviceDriver for RoccatDriver {
                         profile_report.buttons[btn_idx * ROCCAT_BUTTON_STRIDE] = raw_action;
 
                         if button_info.action_type == crate::device::ActionType::Macro {
-                            let mut macro_rep = RoccatMacro {
-                                report_id: ROCCAT_REPORT_ID_MACRO,
-                                report_length: 0x0822,
-                                profile: profile.index as u8,
-                                button_index: btn_idx as u8,
-                                active: 0x01,
-                                padding: [0; 24],
-                                group: [0; 24],
-                                name: [0; 24],
-                                length: 0,
-                                keys: [RoccatMacroEvent { keycode: 0, flag: 0, time: 0 }; ROCCAT_MAX_MACRO_LENGTH],
-                                checksum: 0,
-                            };
-                            
-                            // Initialize group and name with default values as C driver does
-                            macro_rep.group[0] = b'g'; macro_rep.group[1] = b'0';
-                            
-                            let mut count = 0;
-                            for (ev_type, val) in &button_info.macro_entries {
-                                if count >= ROCCAT_MAX_MACRO_LENGTH { break; }
-                                match *ev_type {
-                                    0 => { 
-                                        macro_rep.keys[count].flag = 0x01;
-                                        macro_rep.keys[count].keycode = *val as u8;
-                                        count += 1;
-                                    }
-                                    1 => {
-                                        macro_rep.keys[count].flag = 0x02;
-                                        macro_rep.keys[count].keycode = *val as u8;
-                                        count += 1;
-                                    }
-                                    2 => {
-                                        if count > 0 {
-                                            macro_rep.keys[count - 1].time = *val as u16;
-                                        }
-                                    }
-                                    _ => {}
-                                }
-                            }
-                            macro_rep.length = count as u16;
-                            
+                            let mut macro_rep = RoccatMacro::from_button(profile.index as u8, btn_idx as u8, button_info);
                             if let Err(e) = self.write_macro(io, &mut macro_rep).await {
                                 tracing::warn!("Roccat: failed to write macro for btn {}: {}", btn_idx, e);
                             }
@@ -773,4 +812,66 @@ mod tests {
         assert_eq!(RoccatDriver::compute_crc(&buf), crc);
         assert!(RoccatDriver::crc_is_valid(&buf));
     }
+
+    fn macro_button() -> crate::device::ButtonInfo {
+        crate::device::ButtonInfo {
+            index: 4,
+            action_type: ActionType::Macro,
+            macro_entries: vec![(0, 30), (2, 20), (1, 30)],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_macro_report_is_marked_active() {
+        let button = crate::device::ButtonInfo {
+            macro_repeat: crate::device::MacroRepeat::Times(3),
+            ..macro_button()
+        };
+        let bytes = RoccatMacro::from_button(1, 4, &button).into_bytes();
+        assert_eq!(bytes[5], 0x01);
+        assert_eq!(u16::from_le_bytes([bytes[78], bytes[79]]), 2);
+        assert_eq!(&bytes[80..84], &[30, 0x01, 20, 0]);
+    }
+
+    /* `n` key presses, each followed by a wait of its index. */
+    fn long_macro(n: u32) -> crate::device::ButtonInfo {
+        crate::device::ButtonInfo {
+            macro_entries: (0..n).flat_map(|i| [(0, 4 + i % 26), (2, i + 1)]).collect(),
+            ..macro_button()
+        }
+    }
+
+    #[test]
+    fn test_macro_fills_report_at_maximum_length() {
+        let button = long_macro(ROCCAT_MAX_MACRO_LENGTH as u32);
+        assert_eq!(RoccatMacro::event_count(&button.macro_entries), ROCCAT_MAX_MACRO_LENGTH);
+
+        let mut bytes = RoccatMacro::from_button(0, 4, &button).into_bytes();
+        let crc = RoccatDriver::compute_crc(&bytes);
+        bytes[2080..].copy_from_slice(&crc.to_le_bytes());
+        assert_eq!(u16::from_le_bytes([bytes[78], bytes[79]]), 500);
+        /* The last event (key 4 + 499 % 26, wait 500) ends right where
+         * the checksum starts. */
+        assert_eq!(&bytes[2076..2080], &[9, 0x01, 0xf4, 0x01]);
+        assert!(RoccatDriver::crc_is_valid(&bytes));
+
+        let decoded = RoccatMacro::from_bytes(&bytes).to_entries();
+        assert_eq!(decoded, button.macro_entries);
+    }
+
+    #[tokio::test]
+    async fn test_commit_rejects_macro_past_maximum() {
+        let info = DeviceInfo {
+            sysname: "hidraw-test".to_string(),
+            profiles: vec![crate::device::ProfileInfo {
+                buttons: vec![long_macro(ROCCAT_MAX_MACRO_LENGTH as u32 + 1)],
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let mut io = DeviceIo::open(std::path::Path::new("/dev/null")).await.unwrap();
+        let err = RoccatDriver::new("roccat").commit(&mut io, &info).await.unwrap_err();
+        assert!(err.to_string().contains("501 key events"), "{err}");
+    }
 }
@@ -9,7 +9,8 @@ use async_trait::async_trait;
 use tracing::{debug, warn};
 
 use crate::device::{
-    ActionType, ButtonInfo, Color, DeviceInfo, Dpi, LedInfo, LedMode, ProfileInfo, RgbColor,
+    ActionType, ButtonInfo, Color, DeviceInfo, Dpi, LedDirection, LedInfo, LedMode, MacroRepeat,
+    ProfileInfo, RgbColor,
 };
 use crate::device_database::SinowealthLedType;
 use crate::driver::{DeviceDriver, DeviceIo};
@@ -402,7 +403,7 @@ impl SinowealthDriver {
             .context("query_read: set_feature failed")?;
         let mut resp = [0u8; SINOWEALTH_CMD_SIZE];
         resp[0] = ReportId::Cmd as u8;
-        io.get_feature_report(&mut resp)
+        io.get_feature_report_min(&mut resp, SINOWEALTH_CMD_SIZE)
             .context("query_read: get_feature failed")?;
         if resp[1] != cmd[1] {
             anyhow::bail!(
@@ -422,11 +423,14 @@ impl SinowealthDriver {
     }
 
     /// Read a full-size report (config or button) after issuing a command.
+    /// The device must return at least `min_len` bytes; the rest of the
+    /// buffer is left zeroed.
     fn query_read_report(
         io: &DeviceIo,
         report_id: ReportId,
         cmd_id: CommandId,
         size: usize,
+        min_len: usize,
     ) -> Result<Vec<u8>> {
         // Step 1: issue command
         let cmd = build_cmd(cmd_id);
@@ -436,7 +440,7 @@ impl SinowealthDriver {
         // Step 2: read the data report
         let mut buf = vec![0u8; size];
         buf[0] = report_id as u8;
-        io.get_feature_report(&mut buf)
+        io.get_feature_report_min(&mut buf, min_len)
             .context("query_read_report: get_feature (data) failed")?;
         Ok(buf)
     }
@@ -712,10 +716,10 @@ impl SinowealthDriver {
             if base + 1 >= cfg.len() {
                 break;
             }
-            let (dpi_x, dpi_y) = match res.dpi {
-                Dpi::Unified(d) => (d, d),
-                Dpi::Separate { x, y } => (x, y),
-                Dpi::Unknown => continue,
+            let Some((dpi_x, dpi_y)) =
+                crate::driver::dpi_to_commit("SinoWealth", profile.index, res)
+            else {
+                continue;
             };
             cfg[base] = data.sensor.dpi_to_raw(dpi_x).unwrap_or(0);
             cfg[base + 1] = data.sensor.dpi_to_raw(dpi_y).unwrap_or(0);
@@ -831,7 +835,8 @@ impl SinowealthDriver {
 
         let mut buf = vec![0u8; SINOWEALTH_MACRO_SIZE];
         buf[0] = report_id as u8;
-        io.get_feature_report(&mut buf)
+        /* Only the header is mandatory; a short macro ends at the zeroed tail. */
+        io.get_feature_report_min(&mut buf, 3)
             .context("read_macro: get_feature")?;
 
         let mut events = Vec::new();
@@ -896,6 +901,26 @@ impl DeviceDriver for SinowealthDriver {
         "SinoWealth"
     }
 
+    /* Commit encodes every action kind and writes macro contents. */
+    fn action_types(&self) -> &'static [ActionType] {
+        &[
+            ActionType::None,
+            ActionType::Button,
+            ActionType::Special,
+            ActionType::Key,
+            ActionType::Macro,
+        ]
+    }
+
+    fn capabilities(&self) -> super::DriverCapabilities {
+        super::DriverCapabilities {
+            profiles: true,
+            leds: true,
+            separate_xy: true,
+            ..super::DriverCapabilities::from_action_types(self.action_types())
+        }
+    }
+
     async fn probe(&mut self, io: &mut DeviceIo) -> Result<()> {
         // 1. Read firmware version
         let cmd = build_cmd(CommandId::FirmwareVersion);
@@ -921,6 +946,7 @@ impl DeviceDriver for SinowealthDriver {
             config_report_id,
             CommandId::GetConfig,
             SINOWEALTH_CONFIG_REPORT_SIZE,
+            1 + SINOWEALTH_CONFIG_SIZE_MIN,
         )
         .context("Failed to read initial config report")?;
         let config_size = Self::detect_config_size(&config0);
@@ -945,6 +971,7 @@ impl DeviceDriver for SinowealthDriver {
     }
 
     async fn load_profiles(&mut self, io: &mut DeviceIo, info: &mut DeviceInfo) -> Result<()> {
+        let action_types: Vec<u32> = self.action_types().iter().map(|t| *t as u32).collect();
         let data = self
             .data
             .as_mut()
@@ -992,6 +1019,7 @@ impl DeviceDriver for SinowealthDriver {
                 config_report_id,
                 cmd_id,
                 SINOWEALTH_CONFIG_REPORT_SIZE,
+                1 + SINOWEALTH_CONFIG_SIZE_MIN,
             )
             .with_context(|| format!("Failed to read config for profile {}", profile_idx))?;
             data.configs.push(config);
@@ -1004,6 +1032,7 @@ impl DeviceDriver for SinowealthDriver {
                 config_report_id,
                 cmd_id,
                 SINOWEALTH_BUTTON_REPORT_SIZE,
+                BUTTON_ENTRY_OFFSET + data.num_buttons.min(SINOWEALTH_NUM_BUTTONS) * BUTTON_ENTRY_SIZE,
             )
             .with_context(|| format!("Failed to read buttons for profile {}", profile_idx))?;
             data.buttons.push(buttons);
@@ -1036,6 +1065,8 @@ impl DeviceDriver for SinowealthDriver {
                 angle_snapping: -1,
                 debounce: -1,
                 debounces: SINOWEALTH_DEBOUNCE_TIMES.to_vec(),
+                lift_off_distance: 0,
+                lift_off_distances: Vec::new(),
                 capabilities: Vec::new(),
                 resolutions: (0..SINOWEALTH_NUM_DPIS as u32)
                     .map(|ri| crate::device::ResolutionInfo {
@@ -1055,9 +1086,11 @@ impl DeviceDriver for SinowealthDriver {
                     .map(|bi| ButtonInfo {
                         index: bi,
                         action_type: ActionType::Button,
-                        action_types: vec![0, 1, 2, 3, 4],
+                        action_types: action_types.clone(),
                         mapping_value: 0x110 + bi, // default: left, right, middle, ...
                         macro_entries: Vec::new(),
+                        macro_repeat: MacroRepeat::Once,
+                        default_mapping: None,
                     })
                     .collect(),
                 leds: (0..num_leds as u32)
@@ -1076,7 +1109,11 @@ impl DeviceDriver for SinowealthDriver {
                         tertiary_color: Color::default(),
                         color_depth: 1,
                         effect_duration: 0,
+                        effect_direction: LedDirection::Clockwise,
+                        has_direction: false,
                         brightness: 255,
+                        is_dirty: false,
+                        unmanaged: false,
                     })
                     .collect(),
             })
@@ -1170,10 +1207,12 @@ impl DeviceDriver for SinowealthDriver {
         // 5. Set debounce if specified
         if let Some(profile) = info.profiles.first() {
             if profile.debounce >= 0 {
+                let ms = ProfileInfo::validate_debounce(profile.debounce, SINOWEALTH_DEBOUNCE_TIMES)
+                    .map_err(|e| anyhow::anyhow!("SinoWealth: {e}"))?;
                 let mut cmd = build_cmd(CommandId::Debounce);
-                cmd[2] = profile.debounce as u8;
+                cmd[2] = ms as u8;
                 Self::query_write(io, &cmd).context("Failed to set debounce")?;
-                debug!("SinoWealth: set debounce to {}ms", profile.debounce);
+                debug!("SinoWealth: set debounce to {}ms", ms);
             }
         }
 
@@ -135,7 +135,7 @@ impl DeviceDriver for SinowealthNubwoDriver {
 
         let mut buf = [0u8; GET_FIRMWARE_MSGSIZE];
         buf[0] = REPORTID_GET_FIRMWARE;
-        io.get_feature_report(&mut buf)
+        io.get_feature_report_min(&mut buf, GET_FIRMWARE_MSGOFFSET)
             .map_err(anyhow::Error::from)?;
 
         let fw_bytes = &buf[GET_FIRMWARE_MSGOFFSET..];
@@ -2,7 +2,7 @@ use anyhow::Result;
 use async_trait::async_trait;
 use tracing::{debug, warn};
 
-use crate::device::DeviceInfo;
+use crate::device::{ActionType, DeviceInfo};
 use crate::driver::{DeviceDriver, DeviceIo};
 
 /* ---------------------------------------------------------------------- */
@@ -45,6 +45,7 @@ const STEELSERIES_ID_SETTINGS_PROTOCOL3: u8 = 0x16;
 /* Opcodes - V4 */
 const STEELSERIES_ID_DPI_PROTOCOL4: u8 = 0x15;
 const STEELSERIES_ID_REPORT_RATE_PROTOCOL4: u8 = 0x17;
+const STEELSERIES_ID_LED_PROTOCOL4: u8 = 0x1c;
 
 /* Buttons */
 const STEELSERIES_BUTTON_OFF: u8 = 0x00;
@@ -122,6 +123,29 @@ impl DeviceDriver for SteelseriesDriver {
         "SteelSeries"
     }
 
+    /* Sensei Raw devices lack macro support; `load_profiles` drops it there. */
+    fn action_types(&self) -> &'static [ActionType] {
+        &[
+            ActionType::None,
+            ActionType::Button,
+            ActionType::Special,
+            ActionType::Macro,
+        ]
+    }
+
+    fn capabilities(&self) -> super::DriverCapabilities {
+        super::DriverCapabilities {
+            leds: true,
+            ..super::DriverCapabilities::from_action_types(self.action_types())
+        }
+    }
+
+    /* Macros go out as one key plus modifiers in the button report, which
+     * has no repeat field; the report's repeat flag only loops LED cycles. */
+    fn supports_macro_repeat(&self) -> bool {
+        false
+    }
+
     async fn probe(&mut self, _io: &mut DeviceIo) -> Result<()> {
         debug!("Probe called for SteelSeries");
         Ok(())
@@ -162,6 +186,8 @@ impl DeviceDriver for SteelseriesDriver {
                 angle_snapping: -1,
                 debounce: -1,
                 debounces: vec![],
+                lift_off_distance: 0,
+                lift_off_distances: Vec::new(),
                 capabilities: vec![],
                 resolutions: vec![],
                 buttons: vec![],
@@ -186,14 +212,12 @@ impl DeviceDriver for SteelseriesDriver {
              * up; for 7 buttons, button 6 gets the cycle; for 8+, button
              * 7 gets it. */
             for btn_id in 0..button_count as u32 {
-                let mut action_types = vec![
-                    crate::device::ActionType::None as u32,
-                    crate::device::ActionType::Button as u32,
-                    crate::device::ActionType::Special as u32,
-                ];
-                if !senseiraw {
-                    action_types.push(crate::device::ActionType::Macro as u32);
-                }
+                let action_types: Vec<u32> = self
+                    .action_types()
+                    .iter()
+                    .filter(|t| !senseiraw || **t != ActionType::Macro)
+                    .map(|t| *t as u32)
+                    .collect();
 
                 let (action_type, mapping_value) =
                     button_defaults_for_layout(btn_id, button_count as u32);
@@ -204,17 +228,20 @@ impl DeviceDriver for SteelseriesDriver {
                     action_types,
                     mapping_value,
                     macro_entries: vec![],
+                    macro_repeat: crate::device::MacroRepeat::Once,
+                    default_mapping: None,
                 });
             }
 
             for led_id in 0..led_count as u32 {
-                /* V1 devices support Off, Solid, Breathing; V2+ add Cycle. */
+                /* V1 and V4 devices support Off, Solid, Breathing; V2 and
+                 * V3 add Cycle. */
                 let mut modes = vec![
                     crate::device::LedMode::Off,
                     crate::device::LedMode::Solid,
                     crate::device::LedMode::Breathing,
                 ];
-                if self.version >= 2 {
+                if matches!(self.version, 2 | 3) {
                     modes.push(crate::device::LedMode::Cycle);
                 }
 
@@ -243,7 +270,11 @@ impl DeviceDriver for SteelseriesDriver {
                     tertiary_color: crate::device::Color::default(),
                     color_depth,
                     effect_duration: 1000,
+                    effect_direction: crate::device::LedDirection::Clockwise,
+                    has_direction: false,
                     brightness,
+                    is_dirty: false,
+                    unmanaged: false,
                 });
             }
 
@@ -277,7 +308,7 @@ impl DeviceDriver for SteelseriesDriver {
         /* Write DPI */
         for res in &profile.resolutions {
             if res.is_active {
-                self.write_dpi(io, res, info).await?;
+                self.write_dpi(io, profile.index, res, info).await?;
                 break;
             }
         }
@@ -286,9 +317,7 @@ impl DeviceDriver for SteelseriesDriver {
         self.write_buttons(io, profile, info).await?;
 
         /* Write LEDs */
-        for led in &profile.leds {
-            self.write_led(io, led, info).await?;
-        }
+        self.write_leds(io, profile, info).await?;
 
         self.write_report_rate(io, profile.report_rate).await?;
 
@@ -347,13 +376,13 @@ impl SteelseriesDriver {
     async fn write_dpi(
         &self,
         io: &mut DeviceIo,
+        profile: u32,
         res: &crate::device::ResolutionInfo,
         info: &DeviceInfo,
     ) -> Result<()> {
-        let dpi_val = match res.dpi {
-            crate::device::Dpi::Unified(d) => d,
-            crate::device::Dpi::Separate { x, .. } => x,
-            crate::device::Dpi::Unknown => 800,
+        /* The firmware has a single axis. */
+        let Some((dpi_val, _)) = crate::driver::dpi_to_commit("SteelSeries", profile, res) else {
+            return Ok(());
         };
         let step = dpi_step(info);
         let res_id = res.index as u8 + 1;
@@ -615,6 +644,23 @@ impl SteelseriesDriver {
         }
     }
 
+    /* ------------------------------------------------------------------ */
+    /* write_leds – only the LEDs changed since the last commit; every     */
+    /* zone write restarts its effect, so clean zones are left alone       */
+    /* ------------------------------------------------------------------ */
+
+    async fn write_leds(
+        &self,
+        io: &mut DeviceIo,
+        profile: &crate::device::ProfileInfo,
+        info: &DeviceInfo,
+    ) -> Result<()> {
+        for led in profile.leds.iter().filter(|led| led.is_dirty && !led.unmanaged) {
+            self.write_led(io, led, info).await?;
+        }
+        Ok(())
+    }
+
     /* ------------------------------------------------------------------ */
     /* write_led (dispatcher)                                              */
     /* ------------------------------------------------------------------ */
@@ -629,6 +675,7 @@ impl SteelseriesDriver {
             1 => self.write_led_v1(io, led, info).await,
             2 => self.write_led_v2(io, led).await,
             3 => self.write_led_v3(io, led).await,
+            4 => self.write_led_v4(io, led).await,
             _ => Ok(()),
         }
     }
@@ -786,6 +833,51 @@ impl SteelseriesDriver {
         Ok(())
     }
 
+    /* ------------------------------------------------------------------ */
+    /* write_led_v4 – cycle-buffer on an output report, like V2            */
+    /* ------------------------------------------------------------------ */
+
+    async fn write_led_v4(&self, io: &mut DeviceIo, led: &crate::device::LedInfo) -> Result<()> {
+        /* V4 gradient spec (Rival 650 family), one report per zone:
+         *   cmd_val  (parameters[0])      → buf index 1
+         *   led_id   (parameters[1])      → buf index 2
+         *   duration (parameters[2..4])   → buf index 3..5  (u16 LE)
+         *   repeat   (parameters[18])     → buf index 19
+         *   trigger  (parameters[22])     → buf index 23
+         *   npoints  (parameters[28])     → buf index 29
+         *   first color data at parameters[29] → buf index 30
+         *
+         * Like the other V4 writes this is an output report, not a
+         * feature report as on V3. */
+        if led.mode == crate::device::LedMode::Cycle {
+            return Err(anyhow::anyhow!(
+                "SteelSeries V4: unsupported LED mode {:?}",
+                led.mode
+            ));
+        }
+
+        let mut buf = [0u8; STEELSERIES_REPORT_SIZE];
+        buf[1] = STEELSERIES_ID_LED_PROTOCOL4;
+        buf[2] = led.index as u8;
+
+        let (repeat, points, duration) = build_cycle_points(led);
+
+        if !repeat {
+            buf[19] = 0x01;
+        }
+        /* buf[23] = trigger_buttons (always 0x00) */
+
+        let header_start = 30usize;
+        let npoints = write_cycle_points(&mut buf, header_start, &points);
+
+        buf[29] = npoints;
+        let d = std::cmp::max(npoints as u16 * 330, duration);
+        buf[3..5].copy_from_slice(&d.to_le_bytes());
+
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        io.write_report(&buf).await
+    }
+
     /* ------------------------------------------------------------------ */
     /* write_save                                                         */
     /* ------------------------------------------------------------------ */
@@ -848,11 +940,7 @@ impl SteelseriesDriver {
         )
         .await
         {
-            if n >= 2 {
-                let major = buf.get(1).copied().unwrap_or(0);
-                let minor = buf.get(0).copied().unwrap_or(0);
-                return Ok(format!("{}.{}", major, minor));
-            }
+            return Ok(parse_firmware_version(&buf[..n]));
         }
 
         Ok(String::new())
@@ -867,6 +955,8 @@ impl SteelseriesDriver {
         io: &mut DeviceIo,
         profile: &mut crate::device::ProfileInfo,
     ) -> Result<()> {
+        /* V4 has no known settings query; its LEDs keep the defaults
+         * until the first commit. */
         let settings_id = match self.version {
             2 => STEELSERIES_ID_SETTINGS,
             3 => STEELSERIES_ID_SETTINGS_PROTOCOL3,
@@ -920,6 +1010,19 @@ impl SteelseriesDriver {
     }
 }
 
+/* ---------------------------------------------------------------------- */
+/* Firmware reply                                                         */
+/* ---------------------------------------------------------------------- */
+
+/* "major.minor" from the first two bytes (minor first); empty for a
+ * reply too short to hold a version. */
+fn parse_firmware_version(reply: &[u8]) -> String {
+    match reply {
+        [minor, major, ..] => format!("{major}.{minor}"),
+        _ => String::new(),
+    }
+}
+
 /* ---------------------------------------------------------------------- */
 /* Cycle-point construction (shared between V2 and V3)                    */
 /* ---------------------------------------------------------------------- */
@@ -986,13 +1089,16 @@ fn build_cycle_points(led: &crate::device::LedInfo) -> (bool, Vec<CyclePoint>, u
             (true, points, led.effect_duration as u16)
         }
         crate::device::LedMode::Breathing => {
-            /* 3-point breathe: black → color → black, matching the C
-             * driver's RATBAG_LED_BREATHING control points. */
+            /* 3-point breathe: secondary → color → secondary.  The secondary
+             * color defaults to black, which gives the C driver's
+             * RATBAG_LED_BREATHING control points; any other secondary makes
+             * the LED fade between the two colors instead. */
+            let base = &led.secondary_color;
             let points = vec![
                 CyclePoint {
-                    r: 0,
-                    g: 0,
-                    b: 0,
+                    r: base.red as u8,
+                    g: base.green as u8,
+                    b: base.blue as u8,
                     pos: 0x00,
                 },
                 CyclePoint {
@@ -1002,9 +1108,9 @@ fn build_cycle_points(led: &crate::device::LedInfo) -> (bool, Vec<CyclePoint>, u
                     pos: 0x7F,
                 },
                 CyclePoint {
-                    r: 0,
-                    g: 0,
-                    b: 0,
+                    r: base.red as u8,
+                    g: base.green as u8,
+                    b: base.blue as u8,
                     pos: 0x7F,
                 },
             ];
@@ -1050,3 +1156,154 @@ fn write_cycle_points(buf: &mut [u8], header_start: usize, points: &[CyclePoint]
 
     points.len() as u8
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::{Color, LedInfo, LedMode};
+    use crate::test_support::ScriptedIo;
+
+    fn breathing_led(color: Color, secondary_color: Color) -> LedInfo {
+        LedInfo {
+            mode: LedMode::Breathing,
+            modes: vec![LedMode::Breathing],
+            color,
+            secondary_color,
+            color_depth: 1,
+            effect_duration: 4000,
+            brightness: 255,
+            ..Default::default()
+        }
+    }
+
+    fn rgb(points: &[CyclePoint]) -> Vec<(u8, u8, u8)> {
+        points.iter().map(|p| (p.r, p.g, p.b)).collect()
+    }
+
+    fn test_info() -> DeviceInfo {
+        DeviceInfo {
+            sysname: "hidraw-test".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_commit_writes_only_dirty_leds() {
+        let mut node = ScriptedIo::empty("steelseries-led").await;
+        let driver = SteelseriesDriver { version: 2 };
+        let mut profile = crate::device::ProfileInfo {
+            leds: vec![
+                breathing_led(Color { red: 255, green: 0, blue: 0 }, Color::default()),
+                breathing_led(Color { red: 0, green: 255, blue: 0 }, Color::default()),
+            ],
+            ..Default::default()
+        };
+        profile.leds[1].index = 1;
+        profile.leds[1].is_dirty = true;
+
+        driver.write_leds(&mut node.io, &profile, &test_info()).await.unwrap();
+        let written = node.written();
+        assert_eq!(written.len(), STEELSERIES_REPORT_SIZE, "one zone written");
+        assert_eq!(written[3], 1, "the dirty zone");
+    }
+
+    #[tokio::test]
+    async fn test_commit_skips_unmanaged_leds() {
+        let mut node = ScriptedIo::empty("steelseries-unmanaged").await;
+        let driver = SteelseriesDriver { version: 2 };
+        let mut profile = crate::device::ProfileInfo {
+            leds: vec![
+                breathing_led(Color { red: 255, green: 0, blue: 0 }, Color::default()),
+                breathing_led(Color { red: 0, green: 255, blue: 0 }, Color::default()),
+            ],
+            ..Default::default()
+        };
+        profile.leds[1].index = 1;
+        for led in &mut profile.leds {
+            led.is_dirty = true;
+        }
+        profile.leds[0].unmanaged = true;
+
+        driver.write_leds(&mut node.io, &profile, &test_info()).await.unwrap();
+        let written = node.written();
+        assert_eq!(written.len(), STEELSERIES_REPORT_SIZE, "one zone written");
+        assert_eq!(written[3], 1, "the managed zone");
+    }
+
+    #[tokio::test]
+    async fn test_unknown_dpi_is_not_written() {
+        let mut node = ScriptedIo::empty("steelseries-dpi").await;
+        let driver = SteelseriesDriver { version: 2 };
+        let info = test_info();
+        let mut res = crate::device::ResolutionInfo {
+            dpi: crate::device::Dpi::Unknown,
+            ..Default::default()
+        };
+
+        driver.write_dpi(&mut node.io, 0, &res, &info).await.unwrap();
+        assert!(node.written().is_empty());
+
+        res.dpi = crate::device::Dpi::Unified(800);
+        driver.write_dpi(&mut node.io, 0, &res, &info).await.unwrap();
+        let written = node.written();
+        assert_eq!(written.len(), STEELSERIES_REPORT_SIZE);
+        assert_eq!(written[4], 7, "800 DPI in steps of 100, minus one");
+    }
+
+    #[tokio::test]
+    async fn test_v4_led_report_layout() {
+        let mut node = ScriptedIo::empty("steelseries-v4").await;
+        let driver = SteelseriesDriver { version: 4 };
+        let mut led = breathing_led(Color { red: 0x12, green: 0x34, blue: 0x56 }, Color::default());
+        led.index = 2;
+        led.mode = LedMode::Solid;
+
+        driver.write_led(&mut node.io, &led, &test_info()).await.unwrap();
+        led.mode = LedMode::Cycle;
+        assert!(driver.write_led(&mut node.io, &led, &test_info()).await.is_err());
+        let written = node.written();
+
+        assert_eq!(written.len(), STEELSERIES_REPORT_SIZE);
+        assert_eq!(&written[..3], &[0x00, STEELSERIES_ID_LED_PROTOCOL4, 2]);
+        assert_eq!(u16::from_le_bytes([written[3], written[4]]), 5000);
+        assert_eq!(written[19], 0x01, "solid does not repeat");
+        assert_eq!(written[29], 1, "one point");
+        assert_eq!(&written[30..33], &[0x12, 0x34, 0x56]);
+        assert_eq!(&written[33..37], &[0x12, 0x34, 0x56, 0x00]);
+    }
+
+    #[test]
+    fn test_breathing_fades_to_black_by_default() {
+        let led = breathing_led(Color { red: 255, green: 0, blue: 0 }, Color::default());
+        let (repeat, points, duration) = build_cycle_points(&led);
+        assert!(repeat);
+        assert_eq!(duration, 4000);
+        assert_eq!(rgb(&points), vec![(0, 0, 0), (255, 0, 0), (0, 0, 0)]);
+    }
+
+    #[test]
+    fn test_two_color_breathing_payload() {
+        let led = breathing_led(
+            Color { red: 255, green: 0, blue: 0 },
+            Color { red: 0, green: 0, blue: 255 },
+        );
+        let (_, points, _) = build_cycle_points(&led);
+        assert_eq!(rgb(&points), vec![(0, 0, 255), (255, 0, 0), (0, 0, 255)]);
+
+        /* The first point doubles as the 3-byte header before the points. */
+        let mut buf = [0u8; 32];
+        let count = write_cycle_points(&mut buf, 4, &points);
+        assert_eq!(count, 3);
+        assert_eq!(&buf[4..7], &[0x00, 0x00, 0xFF]);
+        assert_eq!(&buf[7..11], &[0x00, 0x00, 0xFF, 0x00]);
+        assert_eq!(&buf[11..15], &[0xFF, 0x00, 0x00, 0x7F]);
+        assert_eq!(&buf[15..19], &[0x00, 0x00, 0xFF, 0x7F]);
+    }
+
+    #[test]
+    fn test_firmware_reply() {
+        assert_eq!(parse_firmware_version(&[0x05, 0x01]), "1.5");
+        assert_eq!(parse_firmware_version(&[0x05, 0x01, 0x2c, 0x01]), "1.5");
+        assert_eq!(parse_firmware_version(&[0x05]), "");
+    }
+}
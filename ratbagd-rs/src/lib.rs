@@ -1,8 +1,12 @@
 pub mod actor;
+pub mod audit;
 pub mod dbus;
 pub mod device;
 pub mod device_database;
 pub mod driver;
 pub mod error;
+pub mod state_cache;
 pub mod test_device;
+#[cfg(test)]
+mod test_support;
 pub mod udev_monitor;
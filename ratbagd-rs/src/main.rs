@@ -12,7 +12,7 @@ use tokio::signal;
 use tracing::{info, warn};
 use tracing_subscriber::EnvFilter;
 
-use ratbagd_rs::{dbus, device_database, udev_monitor};
+use ratbagd_rs::{audit, dbus, device_database, state_cache, udev_monitor};
 
 /* Channel capacity for udev hotplug events.  32 is generous for typical
  * hardware — even a full USB hub re-enumeration produces fewer events —
@@ -58,6 +58,12 @@ async fn main() -> Result<()> {
         );
     }
 
+    /* Opt-in record of every commit; see audit.rs. */
+    let audit_log = audit::AuditLog::from_env();
+
+    /* Opt-in cache of profile names and last committed state; see state_cache.rs. */
+    let state_cache = state_cache::StateCache::from_env();
+
     let (device_tx, device_rx) = tokio::sync::mpsc::channel(DEVICE_CHANNEL_CAPACITY);
 
     /* Shared flag that tells the blocking udev thread to exit promptly
@@ -74,7 +80,10 @@ async fn main() -> Result<()> {
     /* Multiplex the DBus server, udev monitor, and shutdown signal.
      * Whichever future completes first determines the exit path. */
     tokio::select! {
-        result = dbus::run_server(device_rx, device_db) => {
+        result = dbus::run_server(device_rx, device_db, audit_log, state_cache) => {
+            /* Manager.Quit stops the server; close the udev monitor too. */
+            shutdown.store(true, Ordering::Relaxed);
+            udev_handle.abort();
             result?;
         }
         result = &mut udev_handle => {
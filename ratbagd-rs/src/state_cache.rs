@@ -0,0 +1,176 @@
+/* Device state cache: an opt-in directory with one JSON file per device,
+ * holding the state the device was left in by its last commit.  Most
+ * drivers cannot read profile names back from the hardware and make them
+ * up on every load; with the cache, the names clients set survive a
+ * daemon restart.
+ *
+ * Enabled by setting RATBAGD_STATE_CACHE to 1.  Files live under
+ * `$XDG_STATE_HOME/ratbagd` (`~/.local/state/ratbagd` when unset) and are
+ * keyed by bus type, vendor and product id and the hidraw node name, so a
+ * mouse that comes back on another node starts over from the hardware. */
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use tracing::warn;
+
+use crate::device::DeviceInfo;
+
+/* Environment variable enabling the cache; unset, empty or 0 disables it. */
+pub const STATE_CACHE_ENV: &str = "RATBAGD_STATE_CACHE";
+
+/* What identifies a device's cache file. */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CacheKey {
+    pub bustype: u16,
+    pub vid: u16,
+    pub pid: u16,
+    pub sysname: String,
+}
+
+impl CacheKey {
+    /* e.g. `0003-046d-c539-hidraw3.json` */
+    fn file_name(&self) -> String {
+        format!(
+            "{:04x}-{:04x}-{:04x}-{}.json",
+            self.bustype, self.vid, self.pid, self.sysname
+        )
+    }
+}
+
+/* The cache directory.  Writes come from one task per device and never
+ * touch another device's file, so no locking is needed. */
+pub struct StateCache {
+    dir: PathBuf,
+}
+
+impl StateCache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /* The cache when STATE_CACHE_ENV enables it and a state directory
+     * can be found. */
+    pub fn from_env() -> Option<Self> {
+        let enabled = std::env::var_os(STATE_CACHE_ENV)
+            .is_some_and(|value| !value.is_empty() && value != "0");
+        if !enabled {
+            return None;
+        }
+        let state_home = std::env::var_os("XDG_STATE_HOME")
+            .map(PathBuf::from)
+            .filter(|dir| dir.is_absolute())
+            .or_else(|| {
+                std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/state"))
+            });
+        match state_home {
+            Some(dir) => Some(Self::new(dir.join("ratbagd"))),
+            None => {
+                warn!("{STATE_CACHE_ENV} is set but neither XDG_STATE_HOME nor HOME is");
+                None
+            }
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn path(&self, key: &CacheKey) -> PathBuf {
+        self.dir.join(key.file_name())
+    }
+
+    /* The state last stored for `key`, if any.  A file that cannot be
+     * parsed, e.g. one written by an older daemon, is ignored. */
+    pub fn load(&self, key: &CacheKey) -> Option<DeviceInfo> {
+        let path = self.path(key);
+        let data = match fs::read(&path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return None,
+            Err(e) => {
+                warn!("Cannot read state cache {}: {e}", path.display());
+                return None;
+            }
+        };
+        serde_json::from_slice(&data)
+            .inspect_err(|e| warn!("Ignoring state cache {}: {e}", path.display()))
+            .ok()
+    }
+
+    /* Replace the state stored for `key`.  The file is written next to
+     * its final name and renamed into place, so a crash mid-write leaves
+     * the previous state rather than a truncated file. */
+    pub fn store(&self, key: &CacheKey, info: &DeviceInfo) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let path = self.path(key);
+        let mut tmp = path.clone().into_os_string();
+        tmp.push(".tmp");
+        fs::write(&tmp, serde_json::to_vec(info)?)?;
+        fs::rename(&tmp, &path)
+    }
+}
+
+/* Give the profiles of a freshly loaded device the names stored in the
+ * cache.  Profiles the cache does not know, or knows without a name,
+ * keep what the driver reported. */
+pub fn restore_profile_names(info: &mut DeviceInfo, cached: &DeviceInfo) {
+    for profile in &mut info.profiles {
+        if let Some(old) = cached.find_profile(profile.index)
+            && !old.name.is_empty()
+        {
+            profile.name.clone_from(&old.name);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::ProfileInfo;
+
+    fn device(names: &[&str]) -> DeviceInfo {
+        DeviceInfo {
+            sysname: "hidraw3".to_string(),
+            profiles: names
+                .iter()
+                .zip(0..)
+                .map(|(name, index)| ProfileInfo {
+                    index,
+                    name: name.to_string(),
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_stored_names_survive_a_reload() {
+        let dir = std::env::temp_dir().join(format!("ratbagd-state-cache-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let cache = StateCache::new(&dir);
+        let key = CacheKey {
+            bustype: 0x03,
+            vid: 0x1038,
+            pid: 0x1702,
+            sysname: "hidraw3".to_string(),
+        };
+        assert!(cache.load(&key).is_none());
+
+        cache.store(&key, &device(&["Work", "", "Games"])).unwrap();
+        assert!(dir.join("0003-1038-1702-hidraw3.json").is_file());
+        let cached = cache.load(&key).unwrap();
+
+        /* The driver made up names again; profile 1 had none stored and
+         * profile 3 is new. */
+        let mut info = device(&["Profile 0", "Profile 1", "Profile 2", "Profile 3"]);
+        restore_profile_names(&mut info, &cached);
+        let names: Vec<&str> = info.profiles.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["Work", "Profile 1", "Games", "Profile 3"]);
+
+        let other = CacheKey { sysname: "hidraw4".to_string(), ..key };
+        assert!(cache.load(&other).is_none());
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
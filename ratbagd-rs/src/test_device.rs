@@ -11,8 +11,8 @@ pub mod spec {
     use serde::Deserialize;
 
     use crate::device::{
-        ActionType, ButtonInfo, Color, DeviceInfo, Dpi, LedInfo, LedMode, ProfileInfo,
-        ResolutionInfo,
+        ActionType, ButtonInfo, Color, DeviceInfo, DeviceType, Dpi, LedDirection, LedInfo,
+        LedMode, MacroRepeat, ProfileInfo, ResolutionInfo,
     };
     use crate::device_database::DriverConfig;
 
@@ -21,12 +21,23 @@ pub mod spec {
     /* ------------------------------------------------------------------ */
 
     #[derive(Debug, Default, Deserialize)]
+    #[serde(deny_unknown_fields)]
     pub struct TestDeviceSpec {
         #[serde(default)]
         pub profiles: Vec<TestProfileSpec>,
+        /// Position hint per button index, as `ButtonLayout=` in a `.device` file.
+        #[serde(default)]
+        pub button_layout: Vec<String>,
+        /// SmartShift threshold; omit for a device without SmartShift.
+        #[serde(default)]
+        pub smart_shift_threshold: Option<u8>,
+        /// Serial number, as HID_UNIQ would report it.
+        #[serde(default)]
+        pub serial: String,
     }
 
     #[derive(Debug, Default, Deserialize)]
+    #[serde(deny_unknown_fields)]
     pub struct TestProfileSpec {
         #[serde(default)]
         pub is_active: bool,
@@ -39,6 +50,12 @@ pub mod spec {
         pub rate: u32,
         #[serde(default = "default_report_rates")]
         pub report_rates: Vec<u32>,
+        /// Lift-off distance levels; the first one starts out selected.
+        #[serde(default)]
+        pub lift_off_distances: Vec<u32>,
+        /// Profile capabilities, e.g. 105 for an ASCII-only name.
+        #[serde(default)]
+        pub capabilities: Vec<u32>,
         #[serde(default)]
         pub resolutions: Vec<TestResolutionSpec>,
         #[serde(default)]
@@ -48,6 +65,7 @@ pub mod spec {
     }
 
     #[derive(Debug, Default, Deserialize)]
+    #[serde(deny_unknown_fields)]
     pub struct TestResolutionSpec {
         #[serde(default = "default_dpi")]
         pub xres: u32,
@@ -66,6 +84,7 @@ pub mod spec {
     }
 
     #[derive(Debug, Default, Deserialize)]
+    #[serde(deny_unknown_fields)]
     pub struct TestButtonSpec {
         #[serde(default = "default_action_type")]
         pub action_type: String,
@@ -76,15 +95,22 @@ pub mod spec {
     }
 
     #[derive(Debug, Default, Deserialize)]
+    #[serde(deny_unknown_fields)]
     pub struct TestLedSpec {
         #[serde(default)]
         pub mode: u32,
         #[serde(default)]
         pub duration: u32,
+        /// `LedDirection` discriminant; 0 is clockwise.
+        #[serde(default)]
+        pub direction: u32,
         #[serde(default = "default_brightness")]
         pub brightness: u32,
         /// `[r, g, b]` array.
         pub color: Option<Vec<u8>>,
+        /// Expose the LED read-only, as `UnmanagedLeds=` does.
+        #[serde(default)]
+        pub unmanaged: bool,
     }
 
     /* ------------------------------------------------------------------ */
@@ -142,6 +168,8 @@ pub mod spec {
             is_disabled: false,
             rate: 1000,
             report_rates: vec![125, 250, 500, 1000],
+            lift_off_distances: Vec::new(),
+            capabilities: Vec::new(),
             resolutions: vec![default_resolution()],
             buttons: vec![default_button()],
             leds: Vec::new(),
@@ -224,6 +252,11 @@ pub mod spec {
                             action_types: vec![0, 1, 2, 3, 4],
                             mapping_value: b.button,
                             macro_entries: Vec::new(),
+                            macro_repeat: MacroRepeat::Once,
+                            /* The mapping the spec starts with is what
+                             * Button.Reset restores. */
+                            default_mapping: (action_type != ActionType::Macro)
+                                .then_some((action_type, b.button)),
                         }
                     })
                     .collect();
@@ -264,7 +297,11 @@ pub mod spec {
                             tertiary_color: Color::default(),
                             color_depth: 1,
                             effect_duration: l.duration,
+                            effect_direction: LedDirection::from_u32(l.direction).unwrap_or_default(),
+                            has_direction: true,
                             brightness: l.brightness,
+                            is_dirty: false,
+                            unmanaged: l.unmanaged,
                         }
                     })
                     .collect();
@@ -280,7 +317,9 @@ pub mod spec {
                     angle_snapping: -1,
                     debounce: -1,
                     debounces: Vec::new(),
-                    capabilities: Vec::new(),
+                    lift_off_distance: p.lift_off_distances.first().copied().unwrap_or(0),
+                    lift_off_distances: p.lift_off_distances,
+                    capabilities: p.capabilities,
                     resolutions,
                     buttons,
                     leds,
@@ -292,21 +331,177 @@ pub mod spec {
             sysname: sysname.to_string(),
             name: format!("Test Device ({})", sysname),
             model: "test:0000:0000:0".to_string(),
+            serial: spec.serial,
             firmware_version: String::new(),
-            device_type: 2, /* mouse */
+            device_type: DeviceType::Mouse,
+            button_layout: spec.button_layout,
+            smart_shift_threshold: spec.smart_shift_threshold,
+            sleep_timeout: None,
+            is_dirty: false,
             profiles,
             driver_config: DriverConfig::default(),
         }
     }
 
-    /// Parse a JSON string into a [`TestDeviceSpec`].
+    /// Parse a JSON string into a [`TestDeviceSpec`] and validate it.
     ///
     /// An empty or `"{}"` JSON object produces the minimum sane defaults.
-    pub fn parse_json(json: &str) -> Result<TestDeviceSpec, serde_json::Error> {
+    /// Unknown fields are rejected; the error names the offending field.
+    pub fn parse_json(json: &str) -> Result<TestDeviceSpec, String> {
         /* Empty string → minimum device */
         if json.trim().is_empty() {
             return Ok(TestDeviceSpec::default());
         }
-        serde_json::from_str(json)
+        let spec: TestDeviceSpec = serde_json::from_str(json).map_err(|e| e.to_string())?;
+        spec.validate()?;
+        Ok(spec)
+    }
+
+    /* ------------------------------------------------------------------ */
+    /* Validation                                                           */
+    /* ------------------------------------------------------------------ */
+
+    impl TestDeviceSpec {
+        /// Check that the spec describes a device the daemon can expose
+        /// consistently.  Errors are prefixed with the JSON path of the
+        /// offending value, e.g. `profiles[0].resolutions[1].dpi_min`.
+        pub fn validate(&self) -> Result<(), String> {
+            let active = self.profiles.iter().filter(|p| p.is_active).count();
+            if active > 1 {
+                return Err(format!("profiles: {active} profiles are active, at most one may be"));
+            }
+            if self.smart_shift_threshold == Some(0) {
+                return Err("smart_shift_threshold: must be 1-255 (omit it for no SmartShift)".into());
+            }
+            for (pi, p) in self.profiles.iter().enumerate() {
+                p.validate(&format!("profiles[{pi}]"))?;
+            }
+            Ok(())
+        }
+    }
+
+    impl TestProfileSpec {
+        fn validate(&self, at: &str) -> Result<(), String> {
+            if !self.report_rates.is_empty() && !self.report_rates.contains(&self.rate) {
+                return Err(format!(
+                    "{at}.rate: {} Hz is not in report_rates {:?}",
+                    self.rate, self.report_rates
+                ));
+            }
+            if self.lift_off_distances.contains(&0) {
+                return Err(format!("{at}.lift_off_distances: levels start at 1"));
+            }
+            let active = self.resolutions.iter().filter(|r| r.is_active).count();
+            if active > 1 {
+                return Err(format!(
+                    "{at}.resolutions: {active} resolutions are active, at most one may be"
+                ));
+            }
+            for (ri, r) in self.resolutions.iter().enumerate() {
+                let at = format!("{at}.resolutions[{ri}]");
+                if let (Some(lo), Some(hi)) = (r.dpi_min, r.dpi_max) {
+                    if lo > hi {
+                        return Err(format!("{at}.dpi_min: {lo} is above dpi_max {hi}"));
+                    }
+                    if !(lo..=hi).contains(&r.xres) {
+                        return Err(format!("{at}.xres: {} is outside {lo}-{hi}", r.xres));
+                    }
+                }
+            }
+            for (bi, b) in self.buttons.iter().enumerate() {
+                if !matches!(
+                    b.action_type.as_str(),
+                    "none" | "button" | "special" | "key" | "macro"
+                ) {
+                    return Err(format!(
+                        "{at}.buttons[{bi}].action_type: unknown type {:?} \
+                         (expected none, button, special, key or macro)",
+                        b.action_type
+                    ));
+                }
+            }
+            for (li, l) in self.leds.iter().enumerate() {
+                if LedMode::from_u32(l.mode).is_none() {
+                    return Err(format!("{at}.leds[{li}].mode: unknown LED mode {}", l.mode));
+                }
+                if let Some(color) = l.color.as_ref().filter(|c| c.len() != 3) {
+                    return Err(format!(
+                        "{at}.leds[{li}].color: expected [r, g, b], got {} component(s)",
+                        color.len()
+                    ));
+                }
+            }
+            Ok(())
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn rejection(json: &str) -> String {
+            parse_json(json).expect_err("spec should be rejected")
+        }
+
+        #[test]
+        fn test_minimal_specs_parse() {
+            assert!(parse_json("").is_ok());
+            assert!(parse_json("{}").is_ok());
+            assert!(parse_json(r#"{"profiles": [{"is_active": true, "rate": 500}]}"#).is_ok());
+        }
+
+        #[test]
+        fn test_unknown_field_is_named() {
+            let err = rejection(r#"{"profiles": [{"resolutions": [{"xress": 800}]}]}"#);
+            assert!(err.contains("unknown field `xress`"), "{err}");
+        }
+
+        #[test]
+        fn test_wrong_type_is_reported() {
+            let err = rejection(r#"{"profiles": [{"rate": "fast"}]}"#);
+            assert!(err.contains("invalid type"), "{err}");
+        }
+
+        #[test]
+        fn test_inconsistent_values_report_their_path() {
+            let cases = [
+                (
+                    r#"{"profiles": [{"is_active": true}, {"is_active": true}]}"#,
+                    "profiles: 2 profiles are active",
+                ),
+                (
+                    r#"{"profiles": [{"rate": 333}]}"#,
+                    "profiles[0].rate: 333 Hz is not in report_rates",
+                ),
+                (
+                    r#"{"profiles": [{}, {"resolutions": [{}, {"dpi_min": 1600, "dpi_max": 800}]}]}"#,
+                    "profiles[1].resolutions[1].dpi_min: 1600 is above dpi_max 800",
+                ),
+                (
+                    r#"{"profiles": [{"resolutions": [{"xres": 50, "dpi_min": 100, "dpi_max": 800}]}]}"#,
+                    "profiles[0].resolutions[0].xres: 50 is outside 100-800",
+                ),
+                (
+                    r#"{"profiles": [{"resolutions": [{"is_active": true}, {"is_active": true}]}]}"#,
+                    "profiles[0].resolutions: 2 resolutions are active",
+                ),
+                (
+                    r#"{"profiles": [{"buttons": [{}, {"action_type": "keyboard"}]}]}"#,
+                    "profiles[0].buttons[1].action_type: unknown type \"keyboard\"",
+                ),
+                (
+                    r#"{"profiles": [{"leds": [{"color": [255, 0]}]}]}"#,
+                    "profiles[0].leds[0].color: expected [r, g, b], got 2 component(s)",
+                ),
+                (
+                    r#"{"smart_shift_threshold": 0}"#,
+                    "smart_shift_threshold: must be 1-255",
+                ),
+            ];
+            for (json, expected) in cases {
+                let err = rejection(json);
+                assert!(err.starts_with(expected), "{json}: {err}");
+            }
+        }
     }
 }
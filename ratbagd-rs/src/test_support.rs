@@ -0,0 +1,275 @@
+/* Fixtures shared by the unit tests: a driver that talks to no hardware
+ * and records what it was asked to do, and a file-backed DeviceIo that
+ * replays a scripted device.
+ *
+ * `MockDriver` does nothing beyond probing and committing by default;
+ * each field turns on one extra behaviour a test needs, so a test spells
+ * out only what it relies on.  Everything the driver observes goes to the
+ * shared `MockState`, which the test keeps a handle to after the driver
+ * has moved into an actor. */
+
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use tokio::sync::RwLock;
+
+use crate::actor::{spawn_device_actor, ActorHandle};
+use crate::device::{BatteryInfo, DeviceInfo, RgbColor};
+use crate::device_database::DriverConfig;
+use crate::driver::{CommitTarget, DeviceDriver, DeviceIo, DriverCandidate, PartialCommit};
+
+/* What a `MockDriver` saw, plus the switches a test flips while the
+ * driver is running. */
+#[derive(Default)]
+pub struct MockState {
+    /* Make every further probe fail, as a device that stopped speaking
+     * the protocol. */
+    pub probe_fails: AtomicBool,
+    pub probes: AtomicU32,
+    pub commits: AtomicUsize,
+    /* Signalled as each commit reaches the driver, before its delay. */
+    pub commit_started: tokio::sync::Notify,
+    pub switches: AtomicUsize,
+    pub battery_reads: AtomicUsize,
+    /* Indices of the profiles each commit saw as dirty. */
+    pub dirty_seen: Mutex<Vec<Vec<u32>>>,
+    pub live_writes: Mutex<Vec<(u32, RgbColor)>>,
+    corrupt: AtomicBool,
+    repair_requested: AtomicBool,
+}
+
+impl MockState {
+    pub fn commits(&self) -> usize {
+        self.commits.load(Ordering::SeqCst)
+    }
+
+    pub fn switches(&self) -> usize {
+        self.switches.load(Ordering::SeqCst)
+    }
+
+    pub fn battery_reads(&self) -> usize {
+        self.battery_reads.load(Ordering::SeqCst)
+    }
+}
+
+pub struct MockDriver {
+    pub name: &'static str,
+    pub state: Arc<MockState>,
+    /* How long `commit` takes; a long delay is a device that stopped
+     * answering. */
+    pub commit_delay: Duration,
+    /* How long `load_profiles` takes, as a wireless device answering
+     * many sector reads does. */
+    pub load_delay: Duration,
+    /* Fills in what the hardware reports, once the device is ready. */
+    pub load: Option<fn(&mut DeviceInfo)>,
+    /* LED `(profile, index)` every commit fails to write. */
+    pub failing_led: Option<(u32, u32)>,
+    /* `switch_profile` switches directly instead of declining. */
+    pub can_switch: bool,
+    /* `set_led_color_live` is supported and recorded. */
+    pub live_leds: bool,
+    pub battery: Option<BatteryInfo>,
+    /* Number of probes the device sleeps through before it is ready. */
+    pub asleep_probes: u32,
+    /* Stored profiles fail their checksums until a commit following
+     * `request_repair` rewrites them. */
+    pub corrupt: bool,
+    /* Any long HID++ report makes profile 1 active, as a profile button
+     * press does. */
+    pub events: bool,
+}
+
+impl Default for MockDriver {
+    fn default() -> Self {
+        Self {
+            name: "mock",
+            state: Arc::default(),
+            commit_delay: Duration::ZERO,
+            load_delay: Duration::ZERO,
+            load: None,
+            failing_led: None,
+            can_switch: false,
+            live_leds: false,
+            battery: None,
+            asleep_probes: 0,
+            corrupt: false,
+            events: false,
+        }
+    }
+}
+
+impl MockDriver {
+    /* A plain driver named `name` whose probe succeeds or fails. */
+    pub fn new(name: &'static str, probe_ok: bool) -> Self {
+        let driver = Self {
+            name,
+            ..Default::default()
+        };
+        driver.state.probe_fails.store(!probe_ok, Ordering::SeqCst);
+        driver
+    }
+
+    pub fn boxed(self) -> Box<dyn DeviceDriver> {
+        Box::new(self)
+    }
+
+    /* This driver as the only probe candidate, with an empty config. */
+    pub fn candidate(self) -> DriverCandidate {
+        (self.boxed(), DriverConfig::default())
+    }
+
+    /* Spawn an actor for this driver on /dev/null around `info`. */
+    pub async fn spawn(self, info: &Arc<RwLock<DeviceInfo>>) -> (ActorHandle, Arc<MockState>) {
+        let state = Arc::clone(&self.state);
+        let handle = spawn_device_actor(Path::new("/dev/null"), vec![self.candidate()], Arc::clone(info), None, None)
+            .await
+            .unwrap();
+        (handle, state)
+    }
+}
+
+#[async_trait]
+impl DeviceDriver for MockDriver {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    async fn probe(&mut self, _io: &mut DeviceIo) -> Result<()> {
+        if self.state.probe_fails.load(Ordering::SeqCst) {
+            anyhow::bail!("no response");
+        }
+        self.state.probes.fetch_add(1, Ordering::SeqCst);
+        Ok(())
+    }
+
+    async fn load_profiles(&mut self, _io: &mut DeviceIo, info: &mut DeviceInfo) -> Result<()> {
+        tokio::time::sleep(self.load_delay).await;
+        self.state.corrupt.store(self.corrupt, Ordering::SeqCst);
+        if let Some(load) = self.load.filter(|_| self.is_ready()) {
+            load(info);
+        }
+        Ok(())
+    }
+
+    async fn commit(&mut self, _io: &mut DeviceIo, info: &DeviceInfo) -> Result<()> {
+        self.state.commit_started.notify_one();
+        tokio::time::sleep(self.commit_delay).await;
+        self.state.commits.fetch_add(1, Ordering::SeqCst);
+        let dirty = info.profiles.iter().filter(|p| p.is_dirty).map(|p| p.index).collect();
+        self.state.dirty_seen.lock().unwrap().push(dirty);
+        if self.state.repair_requested.load(Ordering::SeqCst) {
+            self.state.corrupt.store(false, Ordering::SeqCst);
+        }
+        if let Some((profile, index)) = self.failing_led {
+            let target = CommitTarget::Led { profile, index };
+            return Err(PartialCommit(vec![(target, "zone busy".to_string())])).context("mock commit");
+        }
+        Ok(())
+    }
+
+    fn listens_for_events(&self) -> bool {
+        self.events
+    }
+
+    fn is_ready(&self) -> bool {
+        self.state.probes.load(Ordering::SeqCst) > self.asleep_probes
+    }
+
+    fn needs_repair(&self) -> bool {
+        self.state.corrupt.load(Ordering::SeqCst)
+    }
+
+    fn request_repair(&mut self) -> bool {
+        self.state.repair_requested.store(true, Ordering::SeqCst);
+        true
+    }
+
+    async fn switch_profile(&mut self, _io: &mut DeviceIo, _index: u32) -> Result<bool> {
+        if self.can_switch {
+            self.state.switches.fetch_add(1, Ordering::SeqCst);
+        }
+        Ok(self.can_switch)
+    }
+
+    async fn set_led_color_live(&mut self, _io: &mut DeviceIo, led: u32, color: RgbColor) -> Result<bool> {
+        if self.live_leds {
+            self.state.live_writes.lock().unwrap().push((led, color));
+        }
+        Ok(self.live_leds)
+    }
+
+    async fn read_battery(&mut self, _io: &mut DeviceIo) -> Result<Option<BatteryInfo>> {
+        self.state.battery_reads.fetch_add(1, Ordering::SeqCst);
+        Ok(self.battery)
+    }
+
+    async fn handle_event(&mut self, report: &[u8], info: &mut DeviceInfo) -> Result<bool> {
+        if !self.events || report[0] != 0x11 {
+            return Ok(false);
+        }
+        for profile in &mut info.profiles {
+            profile.is_active = profile.index == 1;
+        }
+        Ok(true)
+    }
+}
+
+/* A regular file standing in for a hidraw node.  Reads consume the
+ * script from the start and writes overwrite it in place, so a request
+ * of N bytes skips the N bytes reserved for it and the read that follows
+ * gets the scripted reply.  The file is removed on drop. */
+pub struct ScriptedIo {
+    pub io: DeviceIo,
+    path: PathBuf,
+}
+
+impl ScriptedIo {
+    pub async fn new(name: &str, script: &[u8]) -> Self {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let unique = NEXT.fetch_add(1, Ordering::Relaxed);
+        let path = std::env::temp_dir().join(format!("ratbagd-{name}-{}-{unique}", std::process::id()));
+        std::fs::write(&path, script).unwrap();
+        let io = DeviceIo::open(&path).await.unwrap();
+        Self { io, path }
+    }
+
+    /* A device that takes writes and never answers. */
+    pub async fn empty(name: &str) -> Self {
+        Self::new(name, b"").await
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /* Everything in the file: the requests written so far, over
+     * whatever of the script they did not replace. */
+    pub fn written(&self) -> Vec<u8> {
+        std::fs::read(&self.path).unwrap()
+    }
+}
+
+impl Drop for ScriptedIo {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/* Lay out a script for request/reply exchanges: a blank slot of
+ * `request_len` bytes for each request, followed by its reply padded to
+ * the `read_len` bytes read back. */
+pub fn exchanges<R: AsRef<[u8]>>(request_len: usize, read_len: usize, replies: &[R]) -> Vec<u8> {
+    let mut script = Vec::new();
+    for reply in replies {
+        script.resize(script.len() + request_len, 0);
+        let start = script.len();
+        script.extend_from_slice(reply.as_ref());
+        script.resize(start + read_len, 0);
+    }
+    script
+}
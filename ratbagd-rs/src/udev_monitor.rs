@@ -40,6 +40,12 @@ pub enum DeviceAction {
          * same USB topology path.  Empty for devices that don't
          * report a serial. */
         hid_uniq: String,
+        /* USB interface number (`bInterfaceNumber`) of the node; `None`
+         * for devices that are not on USB. */
+        interface: Option<u8>,
+        /* Usage pages of the top-level collections in the node's report
+         * descriptor.  Empty when sysfs has no descriptor to read. */
+        usage_pages: Vec<u16>,
     },
     Remove {
         sysname: String,
@@ -244,6 +250,20 @@ fn build_add_action(device: &udev::Device) -> Option<DeviceAction> {
         .map(|v| v.to_string_lossy().to_string())
         .unwrap_or_default();
 
+    /* Lets the device file pick one node of a multi-interface device,
+     * see `DeviceEntry::accepts_interface`. */
+    let interface = device
+        .parent_with_subsystem_devtype("usb", "usb_interface")
+        .ok()
+        .flatten()
+        .and_then(|usb| {
+            let number = usb.attribute_value("bInterfaceNumber")?.to_string_lossy().to_string();
+            u8::from_str_radix(number.trim(), 16).ok()
+        });
+    let usage_pages = std::fs::read(hid_parent.syspath().join("report_descriptor"))
+        .map(|desc| top_level_usage_pages(&desc))
+        .unwrap_or_default();
+
     Some(DeviceAction::Add {
         sysname,
         devnode,
@@ -253,9 +273,55 @@ fn build_add_action(device: &udev::Device) -> Option<DeviceAction> {
         pid,
         phys_path,
         hid_uniq,
+        interface,
+        usage_pages,
     })
 }
 
+/* Usage page of every top-level collection in a HID report descriptor,
+ * in order.  Only short items matter here; long items are skipped. */
+pub fn top_level_usage_pages(desc: &[u8]) -> Vec<u16> {
+    let mut pages = Vec::new();
+    let mut usage_page = 0u16;
+    let mut depth = 0usize;
+    let mut pos = 0;
+    while pos < desc.len() {
+        let prefix = desc[pos];
+        if prefix == 0xfe {
+            let size = desc.get(pos + 1).copied().map_or(0, usize::from);
+            pos += 3 + size;
+            continue;
+        }
+        let size = match prefix & 0x03 {
+            3 => 4,
+            n => usize::from(n),
+        };
+        let Some(data) = desc.get(pos + 1..pos + 1 + size) else {
+            break;
+        };
+        let value = data
+            .iter()
+            .rev()
+            .fold(0u32, |acc, &b| (acc << 8) | u32::from(b));
+        match prefix & 0xfc {
+            /* Usage Page (global) */
+            0x04 => usage_page = value as u16,
+            /* Collection */
+            0xa0 => {
+                if depth == 0 {
+                    pages.push(usage_page);
+                }
+                depth += 1;
+            }
+            /* End Collection */
+            0xc0 => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+        pos += 1 + size;
+    }
+    pages
+}
+
 /* Walk up the device tree to find the parent with subsystem "hid". */
 fn find_hid_parent(device: &udev::Device) -> Option<udev::Device> {
     let mut current = device.parent()?;
@@ -296,3 +362,27 @@ fn action_sysname(action: &DeviceAction) -> &str {
         DeviceAction::RemoveTest { sysname } => sysname,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_top_level_usage_pages() {
+        let desc = [
+            0x05, 0x01, /* Usage Page (Generic Desktop) */
+            0x09, 0x02, /* Usage (Mouse) */
+            0xa1, 0x01, /* Collection (Application) */
+            0x05, 0x09, /*   Usage Page (Button): nested, not top-level */
+            0xa1, 0x00, /*   Collection (Physical) */
+            0xc0, /*   End Collection */
+            0xc0, /* End Collection */
+            0x06, 0x00, 0xff, /* Usage Page (Vendor 0xff00) */
+            0x09, 0x01, /* Usage (1) */
+            0xa1, 0x01, /* Collection (Application) */
+            0xc0, /* End Collection */
+        ];
+        assert_eq!(top_level_usage_pages(&desc), [0x0001, 0xff00]);
+        assert!(top_level_usage_pages(&[0x06, 0x00]).is_empty());
+    }
+}
@@ -208,3 +208,36 @@ SEPARATE_DPI_DEVICE_JSON = json.dumps(
         ]
     }
 )
+
+# ASUS-style device: every resolution carries the separate-XY capability and
+# the X and Y axes differ, as on ROG mice with per-axis DPI.
+ASUS_SEPARATE_DPI_DEVICE_JSON = json.dumps(
+    {
+        "profiles": [
+            {
+                "is_active": True,
+                "rate": 1000,
+                "resolutions": [
+                    {
+                        "xres": 800,
+                        "yres": 1200,
+                        "dpi_min": 100,
+                        "dpi_max": 16000,
+                        "is_active": True,
+                        "is_default": True,
+                        "capabilities": [2],
+                    },
+                    {
+                        "xres": 3200,
+                        "yres": 1600,
+                        "dpi_min": 100,
+                        "dpi_max": 16000,
+                        "capabilities": [2],
+                    },
+                ],
+                "buttons": [{"action_type": "button", "button": 0x110}],
+                "leds": [],
+            }
+        ]
+    }
+)
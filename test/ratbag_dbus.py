@@ -32,6 +32,7 @@ class RatbagDBusClient:
     """Thin wrapper around the org.freedesktop.ratbag1 DBus API."""
 
     def __init__(self, bus_type: str = "system"):
+        self._bus_type = bus_type
         if bus_type == "session":
             self._bus = dbus.SessionBus()
         else:
@@ -78,6 +79,12 @@ class RatbagDBusClient:
     def manager_api_version(self) -> int:
         return int(self._get_property(MANAGER_PATH, MANAGER_IFACE, "APIVersion"))
 
+    def manager_daemon_version(self) -> str:
+        return str(self._get_property(MANAGER_PATH, MANAGER_IFACE, "DaemonVersion"))
+
+    def manager_drivers(self) -> list[str]:
+        return [str(d) for d in self._get_property(MANAGER_PATH, MANAGER_IFACE, "Drivers")]
+
     def manager_devices(self) -> list[str]:
         paths = self._get_property(MANAGER_PATH, MANAGER_IFACE, "Devices")
         return [str(p) for p in paths]
@@ -86,6 +93,42 @@ class RatbagDBusClient:
         """Inject a synthetic test device (requires dev-hooks)."""
         self._call_method(MANAGER_PATH, MANAGER_IFACE, "LoadTestDevice", json_str)
 
+    def load_test_device_and_wait_ready(
+        self, json_str: str, timeout: float = 3.0
+    ) -> str | None:
+        """Inject a test device and return the path from its DeviceReady signal.
+
+        Signals need a main loop, so this uses a private GLib-backed
+        connection instead of the shared blocking one.  Returns ``None``
+        if no DeviceReady arrives within *timeout* seconds.
+        """
+        from dbus.mainloop.glib import DBusGMainLoop
+        from gi.repository import GLib
+
+        loop = GLib.MainLoop()
+        bus_cls = dbus.SessionBus if self._bus_type == "session" else dbus.SystemBus
+        bus = bus_cls(mainloop=DBusGMainLoop(), private=True)
+        ready: list[str] = []
+
+        def on_ready(path):
+            ready.append(str(path))
+            loop.quit()
+
+        match = bus.add_signal_receiver(
+            on_ready,
+            signal_name="DeviceReady",
+            dbus_interface=MANAGER_IFACE,
+            path=MANAGER_PATH,
+        )
+        try:
+            self.load_test_device(json_str)
+            GLib.timeout_add(int(timeout * 1000), loop.quit)
+            loop.run()
+        finally:
+            match.remove()
+            bus.close()
+        return ready[0] if ready else None
+
     def load_test_device_with_driver(
         self, driver_name: str, config_json: str, io_script_json: str
     ) -> str:
@@ -151,6 +194,9 @@ class RatbagDBusClient:
     def device_firmware_version(self, path: str) -> str:
         return str(self._get_property(path, DEVICE_IFACE, "FirmwareVersion"))
 
+    def device_is_ready(self, path: str) -> bool:
+        return bool(self._get_property(path, DEVICE_IFACE, "IsReady"))
+
     def device_profiles(self, path: str) -> list[str]:
         paths = self._get_property(path, DEVICE_IFACE, "Profiles")
         return [str(p) for p in paths]
@@ -366,3 +412,11 @@ class RatbagDBusClient:
         self._set_property(
             path, LED_IFACE, "EffectDuration", dbus.UInt32(duration)
         )
+
+    def led_effect_direction(self, path: str) -> int:
+        return int(self._get_property(path, LED_IFACE, "EffectDirection"))
+
+    def set_led_effect_direction(self, path: str, direction: int):
+        self._set_property(
+            path, LED_IFACE, "EffectDirection", dbus.UInt32(direction)
+        )
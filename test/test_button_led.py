@@ -346,6 +346,15 @@ class TestLed:
         dbus_client.set_led_effect_duration(leds[0], 99999)
         assert dbus_client.led_effect_duration(leds[0]) == 10000
 
+    def test_set_led_effect_direction(self, dbus_client: RatbagDBusClient):
+        """Effect direction defaults to clockwise and can be reversed."""
+        path = _load_and_get_device(dbus_client, SIMPLE_DEVICE_JSON)
+        profile = _first_profile(dbus_client, path)
+        leds = dbus_client.profile_leds(profile)
+        assert dbus_client.led_effect_direction(leds[0]) == 0
+        dbus_client.set_led_effect_direction(leds[0], 1)
+        assert dbus_client.led_effect_direction(leds[0]) == 1
+
     def test_led_color_depth(self, dbus_client: RatbagDBusClient):
         """Color depth should be a non-negative integer."""
         path = _load_and_get_device(dbus_client, SIMPLE_DEVICE_JSON)
@@ -47,6 +47,16 @@ class TestManager:
         """APIVersion must be 2 for the current protocol."""
         assert dbus_client.manager_api_version() == 2
 
+    def test_daemon_version(self, dbus_client: RatbagDBusClient):
+        """DaemonVersion is a non-empty version string."""
+        assert dbus_client.manager_daemon_version()
+
+    def test_drivers(self, dbus_client: RatbagDBusClient):
+        """Drivers lists the compiled-in Driver= names."""
+        drivers = dbus_client.manager_drivers()
+        assert "hidpp20" in drivers
+        assert drivers == sorted(drivers)
+
     def test_devices_initially_present(self, dbus_client: RatbagDBusClient):
         """Devices list should be accessible (may be empty without hardware)."""
         devices = dbus_client.manager_devices()
@@ -77,6 +87,20 @@ class TestManager:
         devices = dbus_client.manager_devices()
         assert path not in devices
 
+    @pytest.mark.requires_dev_hooks
+    def test_profiles_loaded_after_device_ready(
+        self, dbus_client: RatbagDBusClient
+    ):
+        """Profiles read right after DeviceReady must be the loaded data."""
+        path = dbus_client.load_test_device_and_wait_ready(SIMPLE_DEVICE_JSON)
+        assert path is not None, "DeviceReady was not emitted"
+        assert path in dbus_client.manager_devices()
+        assert dbus_client.device_is_ready(path)
+        profiles = dbus_client.device_profiles(path)
+        assert len(profiles) == 1
+        assert dbus_client.profile_report_rate(profiles[0]) == 1000
+        assert len(dbus_client.profile_resolutions(profiles[0])) == 2
+
     @pytest.mark.requires_dev_hooks
     def test_load_replaces_previous(self, dbus_client: RatbagDBusClient):
         """Loading a new test device should replace the previous one."""
@@ -7,12 +7,14 @@ child object enumeration, and resolution read/write (unified & separate DPI).
 
 import time
 
+import dbus
 import pytest
 
 from .conftest import (
     SIMPLE_DEVICE_JSON,
     MULTI_PROFILE_DEVICE_JSON,
     SEPARATE_DPI_DEVICE_JSON,
+    ASUS_SEPARATE_DPI_DEVICE_JSON,
 )
 from .ratbag_dbus import RatbagDBusClient
 
@@ -142,19 +144,21 @@ class TestProfile:
         caps = dbus_client.profile_capabilities(profile)
         assert isinstance(caps, list)
 
-    def test_report_rate_clamped_low(self, dbus_client: RatbagDBusClient):
-        """Report rate below 125 should be clamped to 125."""
+    def test_report_rate_rejected_low(self, dbus_client: RatbagDBusClient):
+        """Report rate below every supported rate should be rejected."""
         path = _load_and_get_device(dbus_client, SIMPLE_DEVICE_JSON)
         profile = _first_profile(dbus_client, path)
-        dbus_client.set_profile_report_rate(profile, 50)
-        assert dbus_client.profile_report_rate(profile) == 125
+        with pytest.raises(dbus.exceptions.DBusException):
+            dbus_client.set_profile_report_rate(profile, 50)
+        assert dbus_client.profile_report_rate(profile) == 1000
 
-    def test_report_rate_clamped_high(self, dbus_client: RatbagDBusClient):
-        """Report rate above 8000 should be clamped to 8000."""
+    def test_report_rate_rejected_high(self, dbus_client: RatbagDBusClient):
+        """Report rate missing from ReportRates should be rejected."""
         path = _load_and_get_device(dbus_client, SIMPLE_DEVICE_JSON)
         profile = _first_profile(dbus_client, path)
-        dbus_client.set_profile_report_rate(profile, 99999)
-        assert dbus_client.profile_report_rate(profile) == 8000
+        with pytest.raises(dbus.exceptions.DBusException):
+            dbus_client.set_profile_report_rate(profile, 2000)
+        assert dbus_client.profile_report_rate(profile) == 1000
 
     def test_set_active_profile(self, dbus_client: RatbagDBusClient):
         """SetActive should switch the active profile."""
@@ -240,6 +244,16 @@ class TestResolution:
         assert int(val[0]) == 800
         assert int(val[1]) == 1600
 
+    def test_resolution_separate_dpi_asus(self, dbus_client: RatbagDBusClient):
+        """Each separate-XY resolution keeps its own X and Y values."""
+        path = _load_and_get_device(dbus_client, ASUS_SEPARATE_DPI_DEVICE_JSON)
+        profile = _first_profile(dbus_client, path)
+        resolutions = dbus_client.profile_resolutions(profile)
+        values = [
+            tuple(int(v) for v in dbus_client.resolution_value(r)) for r in resolutions
+        ]
+        assert values == [(800, 1200), (3200, 1600)]
+
     def test_set_resolution_unified(self, dbus_client: RatbagDBusClient):
         """Setting a unified DPI should persist."""
         path = _load_and_get_device(dbus_client, SIMPLE_DEVICE_JSON)